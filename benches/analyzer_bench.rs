@@ -1,6 +1,7 @@
 use chrono::Utc;
 use criterion::{criterion_group, criterion_main, Criterion};
 use ferriscope::analyzer;
+use ferriscope::fields::Fields;
 use ferriscope::ui::PacketInfo;
 
 pub fn analyzer_benchmark(c: &mut Criterion) {
@@ -19,6 +20,9 @@ pub fn analyzer_benchmark(c: &mut Criterion) {
             0x01, // Source IP
             0xc0, 0xa8, 0x01, 0x01, // Dest IP
         ],
+        fields: Fields::new(),
+        expert: Vec::new(),
+        objects: Vec::new(),
     };
 
     // Create sample UDP packet info
@@ -36,6 +40,9 @@ pub fn analyzer_benchmark(c: &mut Criterion) {
             0x01, // Source IP
             0xc0, 0xa8, 0x01, 0x01, // Dest IP
         ],
+        fields: Fields::new(),
+        expert: Vec::new(),
+        objects: Vec::new(),
     };
 
     c.bench_function("analyze_tcp_packet", |b| {
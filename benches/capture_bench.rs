@@ -1,6 +1,7 @@
 use chrono::Utc;
 use criterion::{criterion_group, criterion_main, Criterion};
 use ferriscope::capture;
+use ferriscope::fields::Fields;
 use ferriscope::ui::PacketInfo;
 use tokio::sync::mpsc;
 
@@ -21,6 +22,9 @@ pub fn capture_benchmark(c: &mut Criterion) {
                     length: 64,
                     info: "Test packet".to_string(),
                     raw_data: vec![0; 64],
+                    fields: Fields::new(),
+                    expert: Vec::new(),
+                    objects: Vec::new(),
                 };
 
                 packet_tx.send(test_packet).await.unwrap();
@@ -47,6 +51,9 @@ pub fn capture_benchmark(c: &mut Criterion) {
                         length: 64,
                         info: "Test packet".to_string(),
                         raw_data: vec![0; 64],
+                        fields: Fields::new(),
+                        expert: Vec::new(),
+                        objects: Vec::new(),
                     };
 
                     // Simulate filter processing
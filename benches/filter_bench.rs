@@ -4,6 +4,7 @@ extern crate test;
 
 use chrono::Utc;
 use criterion::{criterion_group, criterion_main, Criterion};
+use ferriscope::fields::Fields;
 use ferriscope::filters;
 use ferriscope::ui::PacketInfo;
 
@@ -37,6 +38,9 @@ pub fn filter_benchmark(c: &mut Criterion) {
             0x45, 0x00, 0x00, 0x28, 0x00, 0x00, 0x40, 0x00, 0x40, 0x06, 0x00, 0x00, 0x7f, 0x00,
             0x00, 0x01, 0xc0, 0xa8, 0x01, 0x01,
         ],
+        fields: Fields::new(),
+        expert: Vec::new(),
+        objects: Vec::new(),
     };
 
     // Test filter parsing
@@ -0,0 +1,481 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionTable, Exchange};
+use crate::reassembly::{MessageBuffer, StreamData};
+use std::net::IpAddr;
+
+pub const PROTOCOL_HEADER: &[u8] = b"AMQP";
+const PROTOCOL_HEADER_LEN: usize = 8;
+// Type, channel and payload size
+const FRAME_HEADER_LEN: usize = 7;
+const FRAME_END: u8 = 0xce;
+const REPLY_SUCCESS: u16 = 200;
+
+pub const FRAME_METHOD: u8 = 1;
+pub const FRAME_HEADER: u8 = 2;
+pub const FRAME_BODY: u8 = 3;
+pub const FRAME_HEARTBEAT: u8 = 8;
+
+const CONNECTION: u16 = 10;
+const CHANNEL: u16 = 20;
+const EXCHANGE: u16 = 40;
+const QUEUE: u16 = 50;
+const BASIC: u16 = 60;
+
+pub fn class_name(class: u16) -> &'static str {
+    match class {
+        CONNECTION => "Connection",
+        CHANNEL => "Channel",
+        EXCHANGE => "Exchange",
+        QUEUE => "Queue",
+        BASIC => "Basic",
+        85 => "Confirm",
+        90 => "Tx",
+        _ => "Unknown",
+    }
+}
+
+pub fn method_name(class: u16, method: u16) -> &'static str {
+    match (class, method) {
+        (CONNECTION, 10) => "Start",
+        (CONNECTION, 11) => "Start-Ok",
+        (CONNECTION, 20) => "Secure",
+        (CONNECTION, 21) => "Secure-Ok",
+        (CONNECTION, 30) => "Tune",
+        (CONNECTION, 31) => "Tune-Ok",
+        (CONNECTION, 40) => "Open",
+        (CONNECTION, 41) => "Open-Ok",
+        (CONNECTION, 50) | (CHANNEL, 40) => "Close",
+        (CONNECTION, 51) | (CHANNEL, 41) => "Close-Ok",
+        (CONNECTION, 60) => "Blocked",
+        (CONNECTION, 61) => "Unblocked",
+        (CHANNEL, 10) => "Open",
+        (CHANNEL, 11) => "Open-Ok",
+        (CHANNEL, 20) => "Flow",
+        (CHANNEL, 21) => "Flow-Ok",
+        (EXCHANGE | QUEUE, 10) => "Declare",
+        (EXCHANGE | QUEUE, 11) => "Declare-Ok",
+        (EXCHANGE, 20) | (QUEUE, 40) => "Delete",
+        (EXCHANGE, 21) | (QUEUE, 41) => "Delete-Ok",
+        (EXCHANGE, 30) | (QUEUE, 20) => "Bind",
+        (EXCHANGE, 31) | (QUEUE, 21) => "Bind-Ok",
+        (EXCHANGE, 40) | (QUEUE, 50) => "Unbind",
+        (EXCHANGE, 51) | (QUEUE, 51) => "Unbind-Ok",
+        (QUEUE, 30) => "Purge",
+        (QUEUE, 31) => "Purge-Ok",
+        (BASIC, 10) => "Qos",
+        (BASIC, 11) => "Qos-Ok",
+        (BASIC, 20) => "Consume",
+        (BASIC, 21) => "Consume-Ok",
+        (BASIC, 30) => "Cancel",
+        (BASIC, 31) => "Cancel-Ok",
+        (BASIC, 40) => "Publish",
+        (BASIC, 50) => "Return",
+        (BASIC, 60) => "Deliver",
+        (BASIC, 70) => "Get",
+        (BASIC, 71) => "Get-Ok",
+        (BASIC, 72) => "Get-Empty",
+        (BASIC, 80) => "Ack",
+        (BASIC, 90) => "Reject",
+        (BASIC, 100) => "Recover-Async",
+        (BASIC, 110) => "Recover",
+        (BASIC, 111) => "Recover-Ok",
+        (BASIC, 120) => "Nack",
+        (85, 10) | (90, 10) => "Select",
+        (85, 11) | (90, 11) => "Select-Ok",
+        (90, 20) => "Commit",
+        (90, 21) => "Commit-Ok",
+        (90, 30) => "Rollback",
+        (90, 31) => "Rollback-Ok",
+        _ => "Unknown",
+    }
+}
+
+/// Arguments of a method frame naming exchanges, queues and routing keys,
+/// or the reason a connection or channel was closed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Arguments {
+    pub exchange: Option<String>,
+    pub routing_key: Option<String>,
+    pub queue: Option<String>,
+    pub reply: Option<(u16, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    ProtocolHeader {
+        major: u8,
+        minor: u8,
+        revision: u8,
+    },
+    Method {
+        class: u16,
+        method: u16,
+        arguments: Arguments,
+    },
+    ContentHeader {
+        class: u16,
+        body_size: u64,
+    },
+    ContentBody(usize),
+    Heartbeat,
+    Other,
+}
+
+/// An AMQP 0-9-1 frame, or the protocol header opening a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmqpFrame {
+    pub frame_type: u8,
+    pub channel: u16,
+    pub length: usize,
+    pub payload: Payload,
+}
+
+impl AppMessage for AmqpFrame {
+    const TIME_FIELD: &'static str = "amqp.response_time";
+
+    fn info(&self) -> String {
+        match &self.payload {
+            Payload::ProtocolHeader {
+                major,
+                minor,
+                revision,
+            } => format!("Protocol-Header {}-{}-{}", major, minor, revision),
+            Payload::Method {
+                class,
+                method,
+                arguments,
+            } => {
+                let mut info = format!("{}.{}", class_name(*class), method_name(*class, *method));
+                if let Some(exchange) = &arguments.exchange {
+                    info.push_str(&format!(" x={}", exchange));
+                }
+                if let Some(routing_key) = &arguments.routing_key {
+                    info.push_str(&format!(" rk={}", routing_key));
+                }
+                if let Some(queue) = &arguments.queue {
+                    info.push_str(&format!(" q={}", queue));
+                }
+                if let Some((code, text)) = &arguments.reply {
+                    info.push_str(&format!(" reply={} {}", code, text));
+                }
+                info
+            }
+            Payload::ContentHeader { .. } => "Content-Header".to_string(),
+            Payload::ContentBody(_) => "Content-Body".to_string(),
+            Payload::Heartbeat => "Heartbeat".to_string(),
+            Payload::Other => "Unknown frame".to_string(),
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        if let Payload::ProtocolHeader { .. } = self.payload {
+            return;
+        }
+        fields.insert("amqp.type", self.frame_type);
+        fields.insert("amqp.channel", self.channel);
+        fields.insert("amqp.length", self.length);
+        match &self.payload {
+            Payload::Method {
+                class,
+                method,
+                arguments,
+            } => {
+                fields.insert("amqp.method.class", *class);
+                fields.insert("amqp.method.method", *method);
+                if let Some(exchange) = &arguments.exchange {
+                    fields.insert("amqp.method.arguments.exchange", exchange.as_str());
+                }
+                if let Some(routing_key) = &arguments.routing_key {
+                    fields.insert("amqp.method.arguments.routing_key", routing_key.as_str());
+                }
+                if let Some(queue) = &arguments.queue {
+                    fields.insert("amqp.method.arguments.queue", queue.as_str());
+                }
+                if let Some((code, text)) = &arguments.reply {
+                    fields.insert("amqp.method.arguments.reply_code", *code);
+                    fields.insert("amqp.method.arguments.reply_text", text.as_str());
+                }
+            }
+            Payload::ContentHeader { class, body_size } => {
+                fields.insert("amqp.header.class", *class);
+                fields.insert("amqp.header.body-size", *body_size);
+            }
+            Payload::ContentBody(len) => fields.insert("amqp.payload_length", *len),
+            _ => {}
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match &self.payload {
+            Payload::Method {
+                class,
+                arguments:
+                    Arguments {
+                        reply: Some((code, text)),
+                        ..
+                    },
+                ..
+            } if *code != REPLY_SUCCESS && matches!(*class, CONNECTION | CHANNEL) => Some(format!(
+                "{} closed: {} {}",
+                class_name(*class).to_lowercase(),
+                code,
+                text
+            )),
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn short_string(&mut self) -> Option<String> {
+        let len = *self.bytes(1)?.first()?;
+        Some(String::from_utf8_lossy(self.bytes(usize::from(len))?).into_owned())
+    }
+}
+
+/// Decodes the arguments of the methods that route messages.
+fn parse_arguments(class: u16, method: u16, data: &[u8]) -> Option<Arguments> {
+    let mut reader = Reader { data };
+    let mut arguments = Arguments::default();
+    match (class, method) {
+        (CONNECTION, 50) | (CHANNEL, 40) => {
+            let code = reader.u16()?;
+            arguments.reply = Some((code, reader.short_string()?));
+        }
+        (EXCHANGE, 10) | (EXCHANGE, 20) => {
+            reader.u16()?;
+            arguments.exchange = Some(reader.short_string()?);
+        }
+        (QUEUE, 10) | (QUEUE, 30) | (QUEUE, 40) | (BASIC, 20) | (BASIC, 70) => {
+            reader.u16()?;
+            arguments.queue = Some(reader.short_string()?);
+        }
+        (QUEUE, 20) | (QUEUE, 50) => {
+            reader.u16()?;
+            arguments.queue = Some(reader.short_string()?);
+            arguments.exchange = Some(reader.short_string()?);
+            arguments.routing_key = Some(reader.short_string()?);
+        }
+        (QUEUE, 11) => arguments.queue = Some(reader.short_string()?),
+        (BASIC, 40) => {
+            reader.u16()?;
+            arguments.exchange = Some(reader.short_string()?);
+            arguments.routing_key = Some(reader.short_string()?);
+        }
+        (BASIC, 50) => {
+            let code = reader.u16()?;
+            arguments.reply = Some((code, reader.short_string()?));
+            arguments.exchange = Some(reader.short_string()?);
+            arguments.routing_key = Some(reader.short_string()?);
+        }
+        (BASIC, 60) | (BASIC, 71) => {
+            if method == 60 {
+                // Consumer tag
+                reader.short_string()?;
+            }
+            // Delivery tag and redelivered flag
+            reader.u64()?;
+            reader.bytes(1)?;
+            arguments.exchange = Some(reader.short_string()?);
+            arguments.routing_key = Some(reader.short_string()?);
+        }
+        _ => {}
+    }
+    Some(arguments)
+}
+
+fn parse_payload(frame_type: u8, data: &[u8]) -> Option<Payload> {
+    let mut reader = Reader { data };
+    Some(match frame_type {
+        FRAME_METHOD => {
+            let class = reader.u16()?;
+            let method = reader.u16()?;
+            Payload::Method {
+                class,
+                method,
+                arguments: parse_arguments(class, method, reader.data).unwrap_or_default(),
+            }
+        }
+        FRAME_HEADER => {
+            let class = reader.u16()?;
+            // Weight
+            reader.u16()?;
+            Payload::ContentHeader {
+                class,
+                body_size: reader.u64()?,
+            }
+        }
+        FRAME_BODY => Payload::ContentBody(data.len()),
+        FRAME_HEARTBEAT => Payload::Heartbeat,
+        _ => Payload::Other,
+    })
+}
+
+type Endpoint = (IpAddr, u16);
+
+/// Splits reassembled AMQP 0-9-1 connections into frames.
+#[derive(Debug, Default)]
+pub struct AmqpTracker {
+    connections: ConnectionTable<()>,
+}
+
+impl AmqpTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the frames `stream` completes.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+    ) -> Exchange<AmqpFrame> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let buffer = connection.direction(from_client);
+        buffer.push(&stream.data);
+        while let Some(frame) = next_frame(buffer) {
+            exchange.messages.push(frame);
+        }
+        exchange
+    }
+}
+
+fn next_frame(buffer: &mut MessageBuffer) -> Option<AmqpFrame> {
+    let data = buffer.data();
+    if data.starts_with(PROTOCOL_HEADER) {
+        let header = buffer.take(PROTOCOL_HEADER_LEN)?;
+        return Some(AmqpFrame {
+            frame_type: 0,
+            channel: 0,
+            length: 0,
+            payload: Payload::ProtocolHeader {
+                major: header[5],
+                minor: header[6],
+                revision: header[7],
+            },
+        });
+    }
+
+    let header = data.get(..FRAME_HEADER_LEN)?;
+    let frame_type = header[0];
+    let channel = u16::from_be_bytes([header[1], header[2]]);
+    let len = u32::from_be_bytes([header[3], header[4], header[5], header[6]]) as usize;
+    if !matches!(
+        frame_type,
+        FRAME_METHOD | FRAME_HEADER | FRAME_BODY | FRAME_HEARTBEAT
+    ) {
+        // Not at a frame boundary, e.g. after missing data
+        buffer.clear();
+        return None;
+    }
+    let frame = buffer.take(FRAME_HEADER_LEN + len + 1)?;
+    let end = (FRAME_HEADER_LEN + len).min(frame.len());
+    let payload = match frame.get(FRAME_HEADER_LEN + len) {
+        Some(&FRAME_END) | None => parse_payload(frame_type, &frame[FRAME_HEADER_LEN..end]),
+        Some(_) => None,
+    };
+    Some(AmqpFrame {
+        frame_type,
+        channel,
+        length: len,
+        payload: payload.unwrap_or(Payload::Other),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    fn frame(frame_type: u8, channel: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![frame_type];
+        frame.extend_from_slice(&channel.to_be_bytes());
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame.push(FRAME_END);
+        frame
+    }
+
+    fn short_string(text: &str) -> Vec<u8> {
+        let mut data = vec![text.len() as u8];
+        data.extend_from_slice(text.as_bytes());
+        data
+    }
+
+    #[test]
+    fn test_publish_with_content() {
+        let mut tracker = AmqpTracker::new();
+
+        let mut data = b"AMQP\x00\x00\x09\x01".to_vec();
+        let mut publish = vec![0, 60, 0, 40, 0, 0];
+        publish.extend(short_string("orders"));
+        publish.extend(short_string("order.created"));
+        publish.push(0);
+        data.extend(frame(FRAME_METHOD, 1, &publish));
+        let mut header = vec![0, 60, 0, 0];
+        header.extend_from_slice(&5u64.to_be_bytes());
+        header.extend_from_slice(&[0, 0]);
+        data.extend(frame(FRAME_HEADER, 1, &header));
+        data.extend(frame(FRAME_BODY, 1, b"hello"));
+
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&data));
+        let infos: Vec<_> = exchange.messages.iter().map(|m| m.info()).collect();
+        assert_eq!(
+            infos,
+            [
+                "Protocol-Header 0-9-1",
+                "Basic.Publish x=orders rk=order.created",
+                "Content-Header",
+                "Content-Body"
+            ]
+        );
+
+        let mut fields = Fields::new();
+        exchange.messages[1].insert_fields(&mut fields);
+        assert_eq!(
+            fields.get("amqp.method.arguments.routing_key"),
+            Some(&"order.created".into())
+        );
+    }
+
+    #[test]
+    fn test_channel_close_is_an_error() {
+        let mut tracker = AmqpTracker::new();
+        let mut close = vec![0, 20, 0, 40, 1, 148];
+        close.extend(short_string("NOT_FOUND - no exchange 'missing'"));
+        close.extend_from_slice(&[0, 60, 0, 40]);
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(&frame(FRAME_METHOD, 1, &close)),
+        );
+        assert_eq!(
+            exchange.messages[0].error().unwrap(),
+            "channel closed: 404 NOT_FOUND - no exchange 'missing'"
+        );
+    }
+}
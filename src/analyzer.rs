@@ -1,48 +1,1006 @@
+use crate::amqp::AmqpTracker;
+use crate::arp::ArpPacket;
+use crate::bacnet::{self, BacnetPacket};
+use crate::bgp::BgpTracker;
+use crate::bindings::BindingTracker;
+use crate::cdp::{self, CdpPacket};
+use crate::checksum::{self, ChecksumCheck, ChecksumStatus};
+use crate::defrag::{DefragResult, Defragmenter, FragmentInfo};
+use crate::dhcp::{self, DhcpPacket, DhcpTracker, Lease, TransactionInfo, ACK};
+use crate::dhcpv6::{self, Dhcpv6Packet, REPLY};
+use crate::dnp3::{self, Dnp3Tracker};
+use crate::expert::{ExpertInfo, Severity};
+use crate::export::{ExportedObject, ObjectExtractor};
+use crate::fields::format_mac;
+use crate::ftp::FtpTracker;
+use crate::grpc;
+use crate::hsrp::{self, HsrpPacket};
+use crate::http1::Head;
+use crate::http2::Http2Tracker;
+use crate::icmp::{self, Echo, EchoTracker, QuotedPacket};
+use crate::imap::ImapTracker;
+use crate::kafka::KafkaTracker;
+use crate::link::{self, ether_type, LinkType};
+use crate::lldp::LldpPacket;
+use crate::modbus::ModbusTracker;
+use crate::mqtt::MqttTracker;
+use crate::mysql::MysqlTracker;
+use crate::ndp::NdpPacket;
+use crate::netflow::{self, FlowCollector, FlowPacket};
+use crate::ntp::{self, NtpPacket};
+use crate::ospf::{self, OspfPacket};
+use crate::pgsql::PgsqlTracker;
+use crate::pop3::Pop3Tracker;
+use crate::ports::{AppProtocol, PortMap, PortOverride};
+use crate::protobuf::DescriptorPool;
+use crate::query::{self, AppMessage, Exchange};
+use crate::quic::{self, QuicTracker};
+use crate::reassembly::{StreamData, TcpReassembler};
+use crate::redis::RedisTracker;
+use crate::rtp::{RtcpPacket, RtpAnalyzer, RtpHeader};
+use crate::sip::{self, SipMessage, SipTracker};
+use crate::smtp::SmtpTracker;
+use crate::snmp::{self, SnmpMessage};
+use crate::ssh::SshTracker;
+use crate::stp::{self, Bpdu};
+use crate::syslog::{self, SyslogMessage};
+use crate::tcp_analysis::{TcpSegment, TcpTracker};
+use crate::tunnel::{self, Decapsulated, Inner};
 use crate::ui::PacketInfo;
+use crate::vrrp::{self, VrrpPacket};
+use crate::websocket::{WebSocketMessage, WebSocketTracker};
+use crate::wlan::{self, EapolKey, Management, Radiotap, WlanFrame};
 use dns_parser::Packet as DnsPacket;
-use etherparse::{InternetSlice, SlicedPacket, TransportSlice};
+use etherparse::{InternetSlice, SlicedPacket, TransportSlice, UdpHeaderSlice};
+use std::net::IpAddr;
 
+/// Tunnels inside tunnels are decoded up to this depth.
+const MAX_TUNNEL_DEPTH: usize = 4;
+
+/// Analyzes a single packet without any knowledge of earlier packets.
 pub fn analyze_packet(packet_info: &mut PacketInfo) {
-    // Clone the raw data so we can drop the borrow immediately
-    let raw_data = packet_info.raw_data.clone();
-
-    // Parse the packet first and store the result
-    let sliced = match SlicedPacket::from_ethernet(&raw_data) {
-        Ok(s) => s,
-        Err(_) => {
-            packet_info.info = "Failed to parse packet".to_string();
+    Analyzer::new().analyze(packet_info);
+}
+
+/// Packet analyzer that keeps per-connection state across packets.
+#[derive(Default)]
+pub struct Analyzer {
+    tcp: TcpTracker,
+    defrag: Defragmenter,
+    bindings: BindingTracker,
+    echoes: EchoTracker,
+    dhcp: DhcpTracker,
+    quic: QuicTracker,
+    reassembler: TcpReassembler,
+    http2: Http2Tracker,
+    websocket: WebSocketTracker,
+    ports: PortMap,
+    pgsql: PgsqlTracker,
+    mysql: MysqlTracker,
+    redis: RedisTracker,
+    mqtt: MqttTracker,
+    amqp: AmqpTracker,
+    kafka: KafkaTracker,
+    smtp: SmtpTracker,
+    imap: ImapTracker,
+    pop3: Pop3Tracker,
+    ftp: FtpTracker,
+    ssh: SshTracker,
+    modbus: ModbusTracker,
+    dnp3: Dnp3Tracker,
+    bgp: BgpTracker,
+    netflow: FlowCollector,
+    sip: SipTracker,
+    rtp: RtpAnalyzer,
+    /// Follows HTTP/1.x, keeping the files sent when exporting objects.
+    objects: ObjectExtractor,
+    link_type: LinkType,
+    validate_checksums: bool,
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes captured frames as `link_type` rather than Ethernet.
+    pub fn with_link_type(mut self, link_type: LinkType) -> Self {
+        self.link_type = link_type;
+        self
+    }
+
+    /// Enables verification of IP, TCP, UDP and ICMP checksums.
+    pub fn with_checksum_validation(mut self, enabled: bool) -> Self {
+        self.validate_checksums = enabled;
+        self
+    }
+
+    /// Flags DHCP answers from any server but these. Without trusted
+    /// servers, the first server seen answering is trusted.
+    pub fn with_trusted_dhcp_servers(mut self, servers: Vec<IpAddr>) -> Self {
+        self.dhcp = DhcpTracker::new().with_trusted_servers(servers);
+        self
+    }
+
+    /// Decodes the protobuf messages of gRPC calls to the services in
+    /// `descriptors`.
+    pub fn with_protobuf_descriptors(mut self, descriptors: DescriptorPool) -> Self {
+        self.http2 = Http2Tracker::new().with_descriptors(descriptors);
+        self
+    }
+
+    /// Decodes connections to the given ports as the given protocols, in
+    /// addition to their default ports.
+    pub fn with_port_overrides(mut self, overrides: Vec<PortOverride>) -> Self {
+        for port_override in overrides {
+            self.ports.set(port_override.port, port_override.protocol);
+        }
+        self
+    }
+
+    /// Collects the files sent over HTTP and FTP into the `objects` of the
+    /// packets completing them.
+    pub fn with_object_export(mut self, enabled: bool) -> Self {
+        self.objects = ObjectExtractor::default().with_export(enabled);
+        self
+    }
+
+    pub fn analyze(&mut self, packet_info: &mut PacketInfo) {
+        // Clone the raw data so we can drop the borrow immediately
+        let raw_data = packet_info.raw_data.clone();
+
+        match self.link_type {
+            LinkType::Ethernet => {
+                // Set default protocol to link layer
+                packet_info.protocol = "Ethernet".to_string();
+                self.analyze_ethernet(packet_info, &raw_data, 0);
+            }
+            LinkType::Ieee80211 => self.analyze_wlan(packet_info, &raw_data),
+            LinkType::Radiotap => self.analyze_radiotap(packet_info, &raw_data),
+        }
+    }
+
+    /// Returns the objects still being received, at the end of a capture
+    /// file.
+    pub fn finish_objects(&mut self) -> Vec<ExportedObject> {
+        self.objects.finish()
+    }
+
+    fn analyze_radiotap(&mut self, packet_info: &mut PacketInfo, data: &[u8]) {
+        packet_info.protocol = "Radiotap".to_string();
+        let radiotap = match Radiotap::parse(data) {
+            Some(radiotap) => radiotap,
+            None => {
+                packet_info.info = "Malformed radiotap header".to_string();
+                return;
+            }
+        };
+        radiotap.insert_fields(&mut packet_info.fields);
+        let end = data.len().saturating_sub(radiotap.trailer_len());
+        let frame = data.get(radiotap.length..end).unwrap_or_default();
+        self.analyze_wlan(packet_info, frame);
+
+        let summary = radiotap.summary();
+        if !summary.is_empty() && packet_info.fields.contains("wlan.fc.type") {
+            packet_info.info = format!("{} [{}]", packet_info.info, summary);
+        }
+    }
+
+    /// Decodes an 802.11 frame, and the IP, ARP or EAPOL packet of an
+    /// unprotected data frame.
+    fn analyze_wlan(&mut self, packet_info: &mut PacketInfo, frame: &[u8]) {
+        packet_info.protocol = "802.11".to_string();
+        let wlan = match WlanFrame::parse(frame) {
+            Some(wlan) => wlan,
+            None => {
+                packet_info.info = "Malformed 802.11 frame".to_string();
+                return;
+            }
+        };
+        wlan.insert_fields(&mut packet_info.fields);
+        packet_info.source = wlan
+            .source()
+            .map(|mac| format_mac(&mac))
+            .unwrap_or_default();
+        packet_info.destination = format_mac(&wlan.destination());
+        packet_info.info = wlan.name().to_string();
+
+        if wlan.is_management() {
+            let management = Management::parse(wlan.subtype, wlan.body);
+            management.insert_fields(&mut packet_info.fields);
+            let details = management.info();
+            if !details.is_empty() {
+                packet_info.info = format!("{}, {}", wlan.name(), details);
+            }
+            if let Some(reason) = management.reason {
+                packet_info.expert.push(ExpertInfo::new(
+                    Severity::Note,
+                    format!("{} with reason {}", wlan.name(), reason),
+                ));
+            }
+            if let Some(status) = management.status.filter(|&status| status != 0) {
+                packet_info.expert.push(ExpertInfo::new(
+                    Severity::Warning,
+                    format!("{} refused with status {}", wlan.name(), status),
+                ));
+            }
+        } else if wlan.is_protected() {
+            packet_info.info.push_str(", protected");
+        }
+
+        let Some((ether_type, payload)) = wlan.llc_payload() else {
             return;
+        };
+        match ether_type {
+            ether_type::IPV4 | ether_type::IPV6 => self.analyze_ip(packet_info, payload, 0),
+            ether_type::ARP | ether_type::RARP => self.analyze_arp(packet_info, payload),
+            wlan::EAPOL_ETHER_TYPE => {
+                packet_info.protocol = "EAPOL".to_string();
+                packet_info.info = match EapolKey::parse(payload) {
+                    Some(key) => {
+                        key.insert_fields(&mut packet_info.fields);
+                        key.info()
+                    }
+                    None => wlan::eapol_type_name(payload.get(1).copied().unwrap_or(0)).to_string(),
+                };
+                if let Some(&packet_type) = payload.get(1) {
+                    packet_info.fields.insert("eapol.type", packet_type);
+                }
+            }
+            _ => {}
         }
-    };
+    }
 
-    // Set default protocol to link layer
-    packet_info.protocol = "Ethernet".to_string();
+    /// Decodes an Ethernet frame, either the captured one or one carried by
+    /// a tunnel `depth` levels deep.
+    fn analyze_ethernet(&mut self, packet_info: &mut PacketInfo, frame: &[u8], depth: usize) {
+        // Decode the link layer first, including VLAN tags and MPLS labels
+        let link = match link::decode_ethernet(frame) {
+            Some(link) => link,
+            None => {
+                packet_info.info = "Failed to parse packet".to_string();
+                return;
+            }
+        };
+        link.insert_fields(&mut packet_info.fields);
 
-    // Extract IP information first
-    let (ip_proto, src, dst) = match &sliced.ip {
-        Some(InternetSlice::Ipv4(ref header, _)) => (
-            "IPv4",
-            header.source_addr().to_string(),
-            header.destination_addr().to_string(),
-        ),
-        Some(InternetSlice::Ipv6(ref header, _)) => (
-            "IPv6",
-            header.source_addr().to_string(),
-            header.destination_addr().to_string(),
-        ),
-        None => {
-            packet_info.info = "Non-IP packet".to_string();
+        // Link layer addresses, replaced by IP addresses for IP packets
+        packet_info.source = format_mac(&link.source);
+        packet_info.destination = format_mac(&link.destination);
+
+        let payload = &frame[link.payload_offset..];
+        match link.ether_type {
+            ether_type::IPV4 | ether_type::IPV6 => self.analyze_ip(packet_info, payload, depth),
+            ether_type::ARP | ether_type::RARP => self.analyze_arp(packet_info, payload),
+            ether_type::LLDP => analyze_lldp(packet_info, payload),
+            ether_type::TEB if depth < MAX_TUNNEL_DEPTH => {
+                self.analyze_ethernet(packet_info, payload, depth + 1)
+            }
+            _ if link.is_llc() => {
+                // The type field is the length of the LLC PDU; drop padding
+                let len = usize::from(link.ether_type).min(payload.len());
+                analyze_llc(packet_info, &payload[..len]);
+            }
+            _ => packet_info.info = "Non-IP packet".to_string(),
+        }
+    }
+
+    fn analyze_arp(&mut self, packet_info: &mut PacketInfo, payload: &[u8]) {
+        packet_info.protocol = "ARP".to_string();
+        let arp = match ArpPacket::parse(payload) {
+            Some(arp) => arp,
+            None => {
+                packet_info.info = "Malformed or unsupported ARP packet".to_string();
+                return;
+            }
+        };
+
+        packet_info.protocol = arp.protocol_name().to_string();
+        packet_info.info = arp.info();
+        arp.insert_fields(&mut packet_info.fields);
+
+        if !arp.is_reverse() {
+            self.track_binding(packet_info, arp.sender_ip.into(), arp.sender_mac);
+        }
+    }
+
+    /// Pairs echo replies with their requests to measure the round trip time.
+    fn track_echo(
+        &mut self,
+        packet_info: &mut PacketInfo,
+        v6: bool,
+        message: &[u8],
+        src: IpAddr,
+        dst: IpAddr,
+    ) {
+        let echo = match Echo::parse(v6, message) {
+            Some(echo) => echo,
+            None => return,
+        };
+        if let Some(rtt) = self.echoes.process(src, dst, &echo, packet_info.timestamp) {
+            let millis = rtt * 1000.0;
+            packet_info
+                .fields
+                .insert(icmp::field_names(v6).response_time, millis);
+            packet_info
+                .info
+                .push_str(&format!(", time={:.3} ms", millis));
+        }
+    }
+
+    /// Decodes application protocols carried over UDP that need state
+    /// across packets.
+    fn analyze_udp_payload(
+        &mut self,
+        packet_info: &mut PacketInfo,
+        src: (IpAddr, u16),
+        dst: (IpAddr, u16),
+        payload: &[u8],
+    ) {
+        let uses_ports = |ports: [u16; 2]| ports.contains(&src.1) && ports.contains(&dst.1);
+        if uses_ports([dhcp::SERVER_PORT, dhcp::CLIENT_PORT]) {
+            if let Some(dhcp) = DhcpPacket::parse(payload) {
+                self.analyze_dhcp(packet_info, src.0, &dhcp);
+            }
+        } else if uses_ports([dhcpv6::SERVER_PORT, dhcpv6::CLIENT_PORT]) {
+            if let Some(dhcp) = Dhcpv6Packet::parse(payload) {
+                self.analyze_dhcpv6(packet_info, src.0, &dhcp);
+            }
+        } else if src.1 == sip::SIP_PORT || dst.1 == sip::SIP_PORT {
+            if let Some(message) = SipMessage::parse(payload) {
+                self.analyze_sip(packet_info, &message);
+            }
+        } else if src.1 == dnp3::DNP3_PORT || dst.1 == dnp3::DNP3_PORT {
+            // The side on the DNP3 port is the outstation
+            let from_client = dst.1 == dnp3::DNP3_PORT;
+            let (client, server) = if from_client { (src, dst) } else { (dst, src) };
+            let datagram = StreamData::complete(payload);
+            let exchange = self.dnp3.process(
+                client,
+                server,
+                from_client,
+                &datagram,
+                packet_info.timestamp,
+            );
+            report_exchange(packet_info, "DNP 3.0", exchange);
+        } else if src.1 == bacnet::BACNET_PORT || dst.1 == bacnet::BACNET_PORT {
+            if let Some(bacnet) = BacnetPacket::parse(payload) {
+                analyze_bacnet(packet_info, &bacnet);
+            }
+        } else if [src.1, dst.1]
+            .iter()
+            .any(|port| [snmp::SNMP_PORT, snmp::SNMP_TRAP_PORT].contains(port))
+        {
+            if let Some(message) = SnmpMessage::parse(payload) {
+                analyze_snmp(packet_info, &message);
+            }
+        } else if dst.1 == syslog::SYSLOG_PORT {
+            if let Some(message) = SyslogMessage::parse(payload) {
+                analyze_syslog(packet_info, &message);
+            }
+        } else if src.1 == ntp::NTP_PORT || dst.1 == ntp::NTP_PORT {
+            if let Some(ntp) = NtpPacket::parse(payload) {
+                analyze_ntp(packet_info, &ntp);
+            }
+        } else if netflow::NETFLOW_PORTS.contains(&dst.1) || dst.1 == netflow::IPFIX_PORT {
+            if let Some(flows) = self.netflow.process(src.0, payload) {
+                analyze_flows(packet_info, &flows);
+            }
+        } else if [hsrp::HSRP_PORT, hsrp::HSRP_V6_PORT].contains(&dst.1) {
+            if let Some(hsrp) = HsrpPacket::parse(payload) {
+                analyze_hsrp(packet_info, &hsrp);
+            }
+        } else if src.1 == quic::QUIC_PORT || dst.1 == quic::QUIC_PORT {
+            self.analyze_quic(packet_info, src, dst, payload);
+        } else {
+            self.analyze_media(packet_info, src, dst, payload);
+        }
+    }
+
+    fn analyze_sip(&mut self, packet_info: &mut PacketInfo, message: &SipMessage) {
+        packet_info.protocol = "SIP".to_string();
+        packet_info.info = message.info();
+        message.insert_fields(&mut packet_info.fields);
+        if let Some(error) = message.error() {
+            packet_info
+                .expert
+                .push(ExpertInfo::new(Severity::Warning, error));
+        }
+        if let Some(state) = self.sip.process(message) {
+            packet_info
+                .fields
+                .insert("sip.call.state", state.to_string());
+        }
+    }
+
+    /// Decodes RTP and RTCP sent to or from the media endpoints of a call.
+    fn analyze_media(
+        &mut self,
+        packet_info: &mut PacketInfo,
+        src: (IpAddr, u16),
+        dst: (IpAddr, u16),
+        payload: &[u8],
+    ) {
+        let Some(media) = self.sip.media(dst).or_else(|| self.sip.media(src)) else {
+            return;
+        };
+
+        if media.rtcp {
+            let Some(packets) = RtcpPacket::parse_compound(payload) else {
+                return;
+            };
+            packet_info.protocol = "RTCP".to_string();
+            let names: Vec<_> = packets.iter().map(|packet| packet.name()).collect();
+            packet_info.info = names.join("; ");
+            for packet in &packets {
+                packet.insert_fields(&mut packet_info.fields);
+            }
+            packet_info
+                .fields
+                .insert("rtcp.call_id", media.call_id.as_str());
             return;
         }
-    };
 
-    // Set IP information
-    packet_info.protocol = ip_proto.to_string();
-    packet_info.source = src;
-    packet_info.destination = dst;
+        let Some(header) = RtpHeader::parse(payload) else {
+            return;
+        };
+        let encoding = media.encoding(header.payload_type);
+        packet_info.protocol = "RTP".to_string();
+        packet_info.info = header.info(encoding.map(|(name, _)| name));
+        header.insert_fields(&mut packet_info.fields);
+        packet_info
+            .fields
+            .insert("rtp.call_id", media.call_id.as_str());
 
-    // Now analyze transport layer
-    analyze_transport(packet_info, &sliced);
+        let clock_rate = encoding.map_or(8000, |(_, clock_rate)| clock_rate);
+        let arrival = packet_info.timestamp.timestamp_micros() as f64 / 1e6;
+        let stats = self.rtp.process(src, dst, &header, arrival, clock_rate);
+        stats.insert_fields(&mut packet_info.fields);
+        if let Some((expected, received)) = stats.gap {
+            packet_info.expert.push(ExpertInfo::new(
+                Severity::Warning,
+                format!(
+                    "Wrong sequence number: expected {}, got {} ({} packets lost)",
+                    expected,
+                    received,
+                    received.wrapping_sub(expected)
+                ),
+            ));
+        }
+    }
+
+    fn analyze_quic(
+        &mut self,
+        packet_info: &mut PacketInfo,
+        src: (IpAddr, u16),
+        dst: (IpAddr, u16),
+        payload: &[u8],
+    ) {
+        let packets = quic::parse_datagram(payload, |packet| self.quic.dcid_len(packet));
+        if packets.is_empty() {
+            return;
+        }
+        packet_info.protocol = "QUIC".to_string();
+        let infos: Vec<_> = packets.iter().map(|p| p.info()).collect();
+        packet_info.info = infos.join("; ");
+        for packet in &packets {
+            packet.insert_fields(&mut packet_info.fields);
+        }
+
+        let flow = self.quic.process(src, dst, &packets);
+        packet_info
+            .fields
+            .insert("quic.connection.number", flow.connection);
+        if let Some(hello) = flow.client_hello {
+            hello.insert_fields(&mut packet_info.fields);
+            if let Some(name) = &hello.server_name {
+                packet_info.info.push_str(&format!(", SNI={}", name));
+            }
+            if !hello.alpn.is_empty() {
+                packet_info
+                    .info
+                    .push_str(&format!(", ALPN={}", hello.alpn.join(",")));
+            }
+        }
+    }
+
+    fn analyze_dhcp(&mut self, packet_info: &mut PacketInfo, src: IpAddr, dhcp: &DhcpPacket) {
+        packet_info.protocol = "DHCP".to_string();
+        packet_info.info = dhcp.info();
+        dhcp.insert_fields(&mut packet_info.fields);
+
+        let transaction = self.dhcp.message(
+            false,
+            dhcp.xid,
+            packet_info.timestamp,
+            dhcp.hostname.as_deref(),
+        );
+        insert_transaction_fields(packet_info, transaction);
+
+        if !dhcp.is_server_message() {
+            return;
+        }
+        let server = dhcp.server_id.map(IpAddr::V4).unwrap_or(src);
+        self.check_dhcp_server(packet_info, server);
+
+        if let (Some(ACK), Some(address), Some(mac)) =
+            (dhcp.message_type, dhcp.your_ip, dhcp.client_mac)
+        {
+            let lease = Lease {
+                client: format_mac(&mac),
+                address: address.into(),
+                server,
+                hostname: dhcp
+                    .hostname
+                    .clone()
+                    .or_else(|| self.dhcp.hostname(false, dhcp.xid).map(String::from)),
+                lease_time: dhcp.lease_time,
+                granted: packet_info.timestamp,
+            };
+            lease.insert_fields(&mut packet_info.fields);
+        }
+    }
+
+    fn analyze_dhcpv6(&mut self, packet_info: &mut PacketInfo, src: IpAddr, dhcp: &Dhcpv6Packet) {
+        packet_info.protocol = "DHCPv6".to_string();
+        packet_info.info = dhcp.info();
+        dhcp.insert_fields(&mut packet_info.fields);
+
+        let message = dhcp.innermost();
+        let transaction = self
+            .dhcp
+            .message(true, message.xid, packet_info.timestamp, None);
+        insert_transaction_fields(packet_info, transaction);
+
+        if !message.is_server_message() {
+            return;
+        }
+        self.check_dhcp_server(packet_info, src);
+
+        if let (REPLY, Some(duid)) = (message.message_type, &message.client_duid) {
+            for (address, valid) in &message.addresses {
+                let lease = Lease {
+                    client: dhcpv6::client_name(duid),
+                    address: (*address).into(),
+                    server: src,
+                    hostname: None,
+                    lease_time: Some(*valid),
+                    granted: packet_info.timestamp,
+                };
+                lease.insert_fields(&mut packet_info.fields);
+            }
+        }
+    }
+
+    fn check_dhcp_server(&mut self, packet_info: &mut PacketInfo, server: IpAddr) {
+        if let Some(expected) = self.dhcp.check_server(server) {
+            let expected: Vec<_> = expected.iter().map(|ip| ip.to_string()).collect();
+            packet_info.fields.insert("dhcp.rogue_server", true);
+            packet_info.expert.push(ExpertInfo::new(
+                Severity::Warning,
+                format!(
+                    "Possible rogue DHCP server {} (expected {})",
+                    server,
+                    expected.join(", ")
+                ),
+            ));
+        }
+    }
+
+    /// Raises an alert when an IP address shows up with a different MAC
+    /// address than before, e.g. because of ARP spoofing.
+    fn track_binding(&mut self, packet_info: &mut PacketInfo, ip: IpAddr, mac: [u8; 6]) {
+        if let Some(previous) = self.bindings.observe(ip, mac) {
+            packet_info.fields.insert("binding.changed", true);
+            packet_info.fields.insert("binding.previous_mac", previous);
+            packet_info.expert.push(ExpertInfo::new(
+                Severity::Warning,
+                format!(
+                    "{} moved from {} to {} (possible spoofing)",
+                    ip,
+                    format_mac(&previous),
+                    format_mac(&mac)
+                ),
+            ));
+        }
+    }
+
+    fn analyze_ip(&mut self, packet_info: &mut PacketInfo, network: &[u8], depth: usize) {
+        let sliced = match SlicedPacket::from_ip(network) {
+            Ok(s) => s,
+            Err(_) => {
+                packet_info.info = "Failed to parse packet".to_string();
+                return;
+            }
+        };
+
+        // Extract IP information first
+        let (ip_proto, src, dst): (&str, IpAddr, IpAddr) = match &sliced.ip {
+            Some(InternetSlice::Ipv4(ref header, _)) => (
+                "IPv4",
+                header.source_addr().into(),
+                header.destination_addr().into(),
+            ),
+            Some(InternetSlice::Ipv6(ref header, _)) => (
+                "IPv6",
+                header.source_addr().into(),
+                header.destination_addr().into(),
+            ),
+            None => {
+                packet_info.info = "Non-IP packet".to_string();
+                return;
+            }
+        };
+
+        // Set IP information
+        packet_info.protocol = ip_proto.to_string();
+        packet_info.source = src.to_string();
+        packet_info.destination = dst.to_string();
+        packet_info.fields.insert("ip.src", src);
+        packet_info.fields.insert("ip.dst", dst);
+        packet_info.fields.insert("ip.addr", src);
+        packet_info.fields.insert("ip.addr", dst);
+
+        if self.validate_checksums {
+            apply_checksums(packet_info, &checksum::verify(&sliced));
+        }
+
+        // Reassemble fragmented datagrams before looking at the transport layer
+        let defrag = self.defrag.process(network, packet_info.timestamp);
+        let reassembled;
+        let sliced = match defrag {
+            DefragResult::NotFragmented => sliced,
+            DefragResult::Fragment(info) => {
+                describe_fragment(packet_info, &info);
+                return;
+            }
+            DefragResult::Dropped(info) => {
+                describe_fragment(packet_info, &info);
+                packet_info.expert.push(ExpertInfo::new(
+                    Severity::Warning,
+                    "Fragment dropped, datagram discarded",
+                ));
+                return;
+            }
+            DefragResult::Reassembled {
+                info,
+                datagram,
+                fragments,
+            } => {
+                if info.overlap_conflict {
+                    packet_info.expert.push(ExpertInfo::new(
+                        Severity::Warning,
+                        "Overlapping fragment with conflicting data",
+                    ));
+                }
+                packet_info.fields.insert("ip.fragment.count", fragments);
+                packet_info
+                    .fields
+                    .insert("ip.reassembled.length", datagram.len());
+                reassembled = datagram;
+                let sliced = match SlicedPacket::from_ip(&reassembled) {
+                    Ok(s) => s,
+                    Err(_) => {
+                        packet_info.info = "Failed to parse reassembled datagram".to_string();
+                        return;
+                    }
+                };
+                if self.validate_checksums {
+                    // The IP header of the reassembled datagram is synthesized
+                    let checks: Vec<_> = checksum::verify(&sliced)
+                        .into_iter()
+                        .filter(|check| check.layer != "ip")
+                        .collect();
+                    apply_checksums(packet_info, &checks);
+                }
+                sliced
+            }
+        };
+
+        // Now analyze transport layer
+        analyze_transport(packet_info, &sliced);
+
+        match &sliced.transport {
+            Some(TransportSlice::Icmpv4(icmp)) => {
+                self.track_echo(packet_info, false, icmp.slice(), src, dst);
+            }
+            Some(TransportSlice::Icmpv6(icmp)) => {
+                self.track_echo(packet_info, true, icmp.slice(), src, dst);
+                if let Some(ndp) = NdpPacket::parse(icmp.slice()) {
+                    for (ip, mac) in ndp.bindings(src) {
+                        self.track_binding(packet_info, ip, mac);
+                    }
+                }
+            }
+            Some(TransportSlice::Udp(udp)) => self.analyze_udp_payload(
+                packet_info,
+                (src, udp.source_port()),
+                (dst, udp.destination_port()),
+                udp_payload(&sliced, udp),
+            ),
+            _ => {}
+        }
+
+        if depth < MAX_TUNNEL_DEPTH {
+            if let Some(decap) = decapsulate(&sliced) {
+                self.analyze_tunnel(packet_info, &decap, depth);
+                return;
+            }
+        }
+
+        if let Some(TransportSlice::Tcp(tcp)) = &sliced.transport {
+            let src = (src, tcp.source_port());
+            let dst = (dst, tcp.destination_port());
+            let segment = TcpSegment::from_header(tcp, sliced.payload.len(), packet_info.timestamp);
+            self.analyze_tcp_payload(packet_info, src, dst, &segment, sliced.payload);
+            if segment.fin || segment.rst {
+                packet_info
+                    .objects
+                    .extend(self.objects.close(src, dst, segment.rst));
+            }
+            self.track_tcp(packet_info, src, dst, &segment);
+        }
+    }
+
+    /// Adds the tunnel header and decodes the packet it carries, which then
+    /// determines the summary columns.
+    fn analyze_tunnel(&mut self, packet_info: &mut PacketInfo, decap: &Decapsulated, depth: usize) {
+        let tunnel = &decap.tunnel;
+        tunnel.insert_fields(&mut packet_info.fields);
+        packet_info.protocol = tunnel.protocol_name().to_string();
+        packet_info.info = tunnel.info();
+
+        match decap.inner {
+            Some(Inner::Ethernet(frame)) => self.analyze_ethernet(packet_info, frame, depth + 1),
+            Some(Inner::Ip(packet)) => self.analyze_ip(packet_info, packet, depth + 1),
+            None => {}
+        }
+    }
+
+    /// Reassembles the TCP stream and decodes the application protocol it
+    /// carries.
+    fn analyze_tcp_payload(
+        &mut self,
+        packet_info: &mut PacketInfo,
+        src: (IpAddr, u16),
+        dst: (IpAddr, u16),
+        segment: &TcpSegment,
+        payload: &[u8],
+    ) {
+        let stream = self.reassembler.process(src, dst, segment, payload);
+        let http = self.objects.process(src, dst, &stream);
+        packet_info.objects.extend(http.objects);
+        let frames = self.http2.process(src, dst, &stream);
+        if frames.is_empty() {
+            match self.websocket.process(src, dst, &stream) {
+                Some(messages) => analyze_websocket(packet_info, &messages),
+                None if !http.heads.is_empty() => analyze_http(packet_info, &http.heads),
+                None => self.analyze_by_port(packet_info, src, dst, &stream),
+            }
+            return;
+        }
+
+        let is_grpc = frames.iter().any(|frame| frame.grpc_method.is_some());
+        packet_info.protocol = if is_grpc { "gRPC" } else { "HTTP2" }.to_string();
+        let infos: Vec<_> = frames.iter().map(|frame| frame.info()).collect();
+        packet_info.info = infos.join(", ");
+        for frame in &frames {
+            frame.insert_fields(&mut packet_info.fields);
+            if let Some(status) = frame.grpc_status().filter(|status| *status != 0) {
+                let call = match &frame.grpc_method {
+                    Some(method) => format!("gRPC call {}", method),
+                    None => "gRPC call".to_string(),
+                };
+                packet_info.expert.push(ExpertInfo::new(
+                    Severity::Warning,
+                    format!("{} failed with {}", call, grpc::status_name(status)),
+                ));
+            }
+        }
+    }
+
+    /// Decodes stream data of protocols recognized by the server's port.
+    fn analyze_by_port(
+        &mut self,
+        packet_info: &mut PacketInfo,
+        src: (IpAddr, u16),
+        dst: (IpAddr, u16),
+        stream: &StreamData,
+    ) {
+        if let Some(exchange) = self.ftp.data(src, dst, stream) {
+            if let Some(data) = exchange.messages.first() {
+                self.objects
+                    .ftp_data(src, dst, data.command.as_deref(), stream);
+            }
+            report_exchange(packet_info, "FTP-DATA", exchange);
+            return;
+        }
+        let Some((protocol, from_client)) = self.ports.lookup(src.1, dst.1) else {
+            if let Some(exchange) = self.ssh.detect(src, dst, stream) {
+                report_exchange(packet_info, "SSH", exchange);
+            }
+            return;
+        };
+        let (client, server) = if from_client { (src, dst) } else { (dst, src) };
+        let timestamp = packet_info.timestamp;
+        match protocol {
+            AppProtocol::PostgreSql => {
+                let exchange = self
+                    .pgsql
+                    .process(client, server, from_client, stream, timestamp);
+                report_exchange(packet_info, "PGSQL", exchange);
+            }
+            AppProtocol::MySql => {
+                let exchange = self
+                    .mysql
+                    .process(client, server, from_client, stream, timestamp);
+                report_exchange(packet_info, "MySQL", exchange);
+            }
+            AppProtocol::Redis => {
+                let exchange = self
+                    .redis
+                    .process(client, server, from_client, stream, timestamp);
+                report_exchange(packet_info, "Redis", exchange);
+            }
+            AppProtocol::Mqtt => {
+                let exchange = self.mqtt.process(client, server, from_client, stream);
+                report_exchange(packet_info, "MQTT", exchange);
+            }
+            AppProtocol::Amqp => {
+                let exchange = self.amqp.process(client, server, from_client, stream);
+                report_exchange(packet_info, "AMQP", exchange);
+            }
+            AppProtocol::Kafka => {
+                let exchange = self
+                    .kafka
+                    .process(client, server, from_client, stream, timestamp);
+                report_exchange(packet_info, "Kafka", exchange);
+            }
+            AppProtocol::Smtp => {
+                let exchange = self.smtp.process(client, server, from_client, stream);
+                report_exchange(packet_info, "SMTP", exchange);
+            }
+            AppProtocol::Imap => {
+                let exchange = self.imap.process(client, server, from_client, stream);
+                report_exchange(packet_info, "IMAP", exchange);
+            }
+            AppProtocol::Pop3 => {
+                let exchange = self.pop3.process(client, server, from_client, stream);
+                report_exchange(packet_info, "POP", exchange);
+            }
+            AppProtocol::Ftp => {
+                let exchange = self.ftp.process(client, server, from_client, stream);
+                report_exchange(packet_info, "FTP", exchange);
+            }
+            AppProtocol::Ssh => {
+                let exchange = self.ssh.process(client, server, from_client, stream);
+                report_exchange(packet_info, "SSH", exchange);
+            }
+            AppProtocol::Modbus => {
+                let exchange = self
+                    .modbus
+                    .process(client, server, from_client, stream, timestamp);
+                report_exchange(packet_info, "Modbus/TCP", exchange);
+            }
+            AppProtocol::Dnp3 => {
+                let exchange = self
+                    .dnp3
+                    .process(client, server, from_client, stream, timestamp);
+                report_exchange(packet_info, "DNP 3.0", exchange);
+            }
+            AppProtocol::Bgp => {
+                let exchange = self.bgp.process(client, server, from_client, stream);
+                report_exchange(packet_info, "BGP", exchange);
+            }
+        }
+    }
+
+    fn track_tcp(
+        &mut self,
+        packet_info: &mut PacketInfo,
+        src: (IpAddr, u16),
+        dst: (IpAddr, u16),
+        segment: &TcpSegment,
+    ) {
+        let analysis = self.tcp.process(src, dst, segment);
+
+        if let Some(rtt) = analysis.ack_rtt {
+            packet_info.fields.insert("tcp.analysis.ack_rtt", rtt);
+        }
+        if analysis.flags.is_empty() {
+            return;
+        }
+
+        packet_info.fields.insert("tcp.analysis.flags", true);
+        if let Some(count) = analysis.duplicate_ack_num {
+            packet_info
+                .fields
+                .insert("tcp.analysis.duplicate_ack_num", count);
+        }
+
+        let mut labels = Vec::new();
+        for flag in &analysis.flags {
+            packet_info.fields.insert(flag.field_name(), true);
+            packet_info
+                .expert
+                .push(ExpertInfo::new(flag.severity(), flag.label()));
+            labels.push(format!("[{}]", flag.label()));
+        }
+        packet_info.info = format!("{} {}", labels.join(" "), packet_info.info);
+    }
+}
+
+/// The UDP payload without any link layer padding.
+fn udp_payload<'a>(sliced: &SlicedPacket<'a>, udp: &UdpHeaderSlice) -> &'a [u8] {
+    let len = usize::from(udp.length()).saturating_sub(8);
+    &sliced.payload[..len.min(sliced.payload.len())]
+}
+
+/// Finds a tunnel header right after the IP or UDP header.
+fn decapsulate<'a>(sliced: &SlicedPacket<'a>) -> Option<Decapsulated<'a>> {
+    match &sliced.transport {
+        Some(TransportSlice::Udp(udp)) => tunnel::from_udp(
+            udp.source_port(),
+            udp.destination_port(),
+            udp_payload(sliced, udp),
+        ),
+        Some(TransportSlice::Unknown(protocol)) => {
+            tunnel::from_ip_protocol(*protocol, sliced.payload)
+        }
+        _ => None,
+    }
+}
+
+fn insert_transaction_fields(packet_info: &mut PacketInfo, transaction: TransactionInfo) {
+    packet_info
+        .fields
+        .insert("dhcp.transaction.messages", transaction.index);
+    packet_info
+        .fields
+        .insert("dhcp.transaction.elapsed", transaction.elapsed);
+}
+
+fn apply_checksums(packet_info: &mut PacketInfo, checks: &[ChecksumCheck]) {
+    for check in checks {
+        let field = match check.layer {
+            "ip" => "ip.checksum.status",
+            "tcp" => "tcp.checksum.status",
+            "udp" => "udp.checksum.status",
+            "icmp" => "icmp.checksum.status",
+            _ => "icmpv6.checksum.status",
+        };
+        packet_info.fields.insert(field, check.status.as_str());
+
+        if check.status == ChecksumStatus::Bad {
+            if !packet_info.fields.contains("bad_checksum") {
+                packet_info.fields.insert("bad_checksum", true);
+            }
+            packet_info.expert.push(ExpertInfo::new(
+                Severity::Error,
+                format!("Bad {} checksum", check.layer.to_uppercase()),
+            ));
+        }
+    }
+}
+
+fn describe_fragment(packet_info: &mut PacketInfo, fragment: &FragmentInfo) {
+    packet_info.info = format!(
+        "Fragmented IP protocol (proto={}, off={}, ID={:x})",
+        fragment.protocol, fragment.offset, fragment.id
+    );
+    packet_info.fields.insert("ip.id", fragment.id);
+    packet_info.fields.insert("ip.frag_offset", fragment.offset);
+    packet_info
+        .fields
+        .insert("ip.flags.mf", fragment.more_fragments);
+    if fragment.overlap {
+        packet_info.fields.insert("ip.fragment.overlap", true);
+    }
+    if fragment.overlap_conflict {
+        packet_info
+            .fields
+            .insert("ip.fragment.overlap.conflict", true);
+    }
 }
 
 fn analyze_transport(packet_info: &mut PacketInfo, packet: &SlicedPacket) {
@@ -64,6 +1022,21 @@ fn analyze_transport(packet_info: &mut PacketInfo, packet: &SlicedPacket) {
                 if tcp.urg() { "URG" } else { "" }
             );
             packet_info.info = flags;
+
+            let fields = &mut packet_info.fields;
+            fields.insert("tcp.srcport", tcp.source_port());
+            fields.insert("tcp.dstport", tcp.destination_port());
+            fields.insert("tcp.port", tcp.source_port());
+            fields.insert("tcp.port", tcp.destination_port());
+            fields.insert("tcp.seq", tcp.sequence_number());
+            fields.insert("tcp.ack", tcp.acknowledgment_number());
+            fields.insert("tcp.len", packet.payload.len());
+            fields.insert("tcp.window_size", tcp.window_size());
+            fields.insert("tcp.flags.syn", tcp.syn());
+            fields.insert("tcp.flags.ack", tcp.ack());
+            fields.insert("tcp.flags.fin", tcp.fin());
+            fields.insert("tcp.flags.reset", tcp.rst());
+            fields.insert("tcp.flags.push", tcp.psh());
         }
         Some(TransportSlice::Udp(udp)) => {
             packet_info.protocol = "UDP".to_string();
@@ -71,6 +1044,13 @@ fn analyze_transport(packet_info: &mut PacketInfo, packet: &SlicedPacket) {
             packet_info.destination =
                 format!("{}:{}", packet_info.destination, udp.destination_port());
 
+            let fields = &mut packet_info.fields;
+            fields.insert("udp.srcport", udp.source_port());
+            fields.insert("udp.dstport", udp.destination_port());
+            fields.insert("udp.port", udp.source_port());
+            fields.insert("udp.port", udp.destination_port());
+            fields.insert("udp.length", udp.length());
+
             // Check for DNS
             if udp.destination_port() == 53 || udp.source_port() == 53 {
                 packet_info.protocol = "DNS".to_string();
@@ -81,13 +1061,32 @@ fn analyze_transport(packet_info: &mut PacketInfo, packet: &SlicedPacket) {
         }
         Some(TransportSlice::Icmpv4(icmp)) => {
             packet_info.protocol = "ICMPv4".to_string();
-            packet_info.info = format!("Type: {}, Code: {}", icmp.type_u8(), icmp.code_u8());
+            packet_info.fields.insert("icmp.type", icmp.type_u8());
+            packet_info.fields.insert("icmp.code", icmp.code_u8());
+            analyze_icmp(packet_info, false, icmp.slice());
         }
         Some(TransportSlice::Icmpv6(icmp)) => {
             packet_info.protocol = "ICMPv6".to_string();
-            packet_info.info = format!("Type: {}, Code: {}", icmp.type_u8(), icmp.code_u8());
+            packet_info.fields.insert("icmpv6.type", icmp.type_u8());
+            packet_info.fields.insert("icmpv6.code", icmp.code_u8());
+            analyze_icmp(packet_info, true, icmp.slice());
+
+            if let Some(ndp) = NdpPacket::parse(icmp.slice()) {
+                packet_info.info = ndp.info();
+                ndp.insert_fields(&mut packet_info.fields);
+            }
         }
-        Some(TransportSlice::Unknown(_)) => {
+        Some(TransportSlice::Unknown(protocol)) => {
+            let ipv6 = matches!(packet.ip, Some(InternetSlice::Ipv6(..)));
+            if *protocol == ospf::IP_PROTOCOL {
+                if let Some(ospf) = OspfPacket::parse(packet.payload) {
+                    return analyze_ospf(packet_info, &ospf);
+                }
+            } else if *protocol == vrrp::IP_PROTOCOL {
+                if let Some(vrrp) = VrrpPacket::parse(packet.payload, ipv6) {
+                    return analyze_vrrp(packet_info, &vrrp);
+                }
+            }
             packet_info.protocol = "Unknown Transport".to_string();
             packet_info.info = "Unknown transport protocol".to_string();
         }
@@ -97,6 +1096,286 @@ fn analyze_transport(packet_info: &mut PacketInfo, packet: &SlicedPacket) {
     }
 }
 
+/// Names the ICMP message and decodes echo identifiers, announced MTUs and
+/// the packet quoted by error messages.
+fn analyze_icmp(packet_info: &mut PacketInfo, v6: bool, message: &[u8]) {
+    let names = icmp::field_names(v6);
+    let (icmp_type, code) = (message[0], message[1]);
+    let mut info = icmp::describe(v6, icmp_type, code);
+
+    if let Some(echo) = Echo::parse(v6, message) {
+        packet_info.fields.insert(names.identifier, echo.identifier);
+        packet_info.fields.insert(names.sequence, echo.sequence);
+        info.push_str(&format!(
+            " id=0x{:04x}, seq={}",
+            echo.identifier, echo.sequence
+        ));
+    }
+    if let Some(mtu) = icmp::mtu(v6, message) {
+        packet_info.fields.insert(names.mtu, mtu);
+        info.push_str(&format!(", MTU {}", mtu));
+    }
+    if icmp::is_error(v6, icmp_type) {
+        if let Some(quoted) = QuotedPacket::parse(message) {
+            let fields = &mut packet_info.fields;
+            fields.insert(names.quoted_src, quoted.source);
+            fields.insert(names.quoted_dst, quoted.destination);
+            fields.insert(names.quoted_proto, quoted.protocol);
+            if let Some((src_port, dst_port)) = quoted.ports {
+                fields.insert(names.quoted_srcport, src_port);
+                fields.insert(names.quoted_dstport, dst_port);
+            }
+            info.push_str(&format!(" for {}", quoted));
+        }
+    }
+
+    packet_info.info = info;
+}
+
+/// Shows the messages of a request/response protocol with the response
+/// times of the requests they complete.
+fn report_exchange<M: AppMessage>(
+    packet_info: &mut PacketInfo,
+    protocol: &str,
+    exchange: Exchange<M>,
+) {
+    if exchange.messages.is_empty() {
+        return;
+    }
+    packet_info.protocol = protocol.to_string();
+    let mut info = query::summarize(exchange.messages.iter().map(|message| message.info()));
+    for message in &exchange.messages {
+        message.insert_fields(&mut packet_info.fields);
+        if let Some(error) = message.error() {
+            packet_info.expert.push(ExpertInfo::new(
+                Severity::Warning,
+                format!("{} error: {}", protocol, error),
+            ));
+        }
+        if let Some(warning) = message.warning() {
+            packet_info
+                .expert
+                .push(ExpertInfo::new(Severity::Warning, warning));
+        }
+        if let Some(note) = message.note() {
+            packet_info
+                .expert
+                .push(ExpertInfo::new(Severity::Note, note));
+        }
+    }
+    for timing in &exchange.timings {
+        packet_info.fields.insert(M::TIME_FIELD, timing.latency);
+        info.push_str(&format!(", time={:.3} ms", timing.latency * 1000.0));
+    }
+    packet_info.info = info;
+}
+
+fn analyze_bacnet(packet_info: &mut PacketInfo, bacnet: &BacnetPacket) {
+    packet_info.protocol = "BACnet".to_string();
+    packet_info.info = bacnet.info();
+    bacnet.insert_fields(&mut packet_info.fields);
+    if let Some(error) = bacnet.error() {
+        packet_info.expert.push(ExpertInfo::new(
+            Severity::Warning,
+            format!("BACnet {}", error),
+        ));
+    }
+    if let Some(warning) = bacnet.warning() {
+        packet_info
+            .expert
+            .push(ExpertInfo::new(Severity::Warning, warning));
+    }
+}
+
+fn analyze_snmp(packet_info: &mut PacketInfo, message: &SnmpMessage) {
+    packet_info.protocol = "SNMP".to_string();
+    packet_info.info = message.info();
+    message.insert_fields(&mut packet_info.fields);
+    if let Some(error) = message.error() {
+        packet_info.expert.push(ExpertInfo::new(
+            Severity::Warning,
+            format!("SNMP error: {}", error),
+        ));
+    }
+}
+
+fn analyze_syslog(packet_info: &mut PacketInfo, message: &SyslogMessage) {
+    packet_info.protocol = "Syslog".to_string();
+    packet_info.info = message.info();
+    message.insert_fields(&mut packet_info.fields);
+    if message.is_error() {
+        packet_info.expert.push(ExpertInfo::new(
+            Severity::Warning,
+            format!("Syslog {} message", message.severity_name()),
+        ));
+    }
+}
+
+fn analyze_ntp(packet_info: &mut PacketInfo, ntp: &NtpPacket) {
+    packet_info.protocol = "NTP".to_string();
+    packet_info.info = ntp.info();
+    ntp.insert_fields(&mut packet_info.fields);
+    let arrival = packet_info.timestamp.timestamp_micros() as f64 / 1e6;
+    if let Some((offset, delay)) = ntp.offset_and_delay(arrival) {
+        packet_info.fields.insert("ntp.offset", offset);
+        packet_info.fields.insert("ntp.delay", delay);
+        packet_info.info.push_str(&format!(
+            ", offset={:+.3} ms, delay={:.3} ms",
+            offset * 1000.0,
+            delay * 1000.0
+        ));
+    }
+    if let Some(code) = ntp.kiss_code() {
+        packet_info.expert.push(ExpertInfo::new(
+            Severity::Warning,
+            format!("NTP kiss-o'-death {}", code),
+        ));
+    } else if ntp.unsynchronized() {
+        packet_info.expert.push(ExpertInfo::new(
+            Severity::Note,
+            "NTP server clock is unsynchronized",
+        ));
+    }
+}
+
+fn analyze_flows(packet_info: &mut PacketInfo, flows: &FlowPacket) {
+    packet_info.protocol = flows.protocol().to_string();
+    packet_info.info = flows.info();
+    flows.insert_fields(&mut packet_info.fields);
+    if let Some(error) = flows.error() {
+        packet_info.expert.push(ExpertInfo::new(
+            Severity::Warning,
+            format!("{} {}", flows.protocol(), error),
+        ));
+    }
+    if !flows.unknown_templates.is_empty() {
+        packet_info.expert.push(ExpertInfo::new(
+            Severity::Note,
+            "Data sets can't be decoded until their template is captured",
+        ));
+    }
+}
+
+/// Reports the HTTP/1.x requests and responses whose heads a segment
+/// completed.
+fn analyze_http(packet_info: &mut PacketInfo, heads: &[Head]) {
+    packet_info.protocol = "HTTP".to_string();
+    let infos: Vec<_> = heads.iter().map(Head::info).collect();
+    packet_info.info = infos.join(", ");
+    for head in heads {
+        head.insert_fields(&mut packet_info.fields);
+    }
+}
+
+fn analyze_websocket(packet_info: &mut PacketInfo, messages: &[WebSocketMessage]) {
+    if messages.is_empty() {
+        return;
+    }
+    packet_info.protocol = "WebSocket".to_string();
+    let mut infos = Vec::new();
+    for message in messages {
+        match message {
+            WebSocketMessage::Handshake(handshake) => {
+                infos.push(handshake.info());
+                if let Some(head) = &handshake.head {
+                    head.insert_fields(&mut packet_info.fields);
+                }
+                handshake.insert_fields(&mut packet_info.fields);
+                if handshake.status.is_some() && !handshake.accepted() {
+                    packet_info.expert.push(ExpertInfo::new(
+                        Severity::Warning,
+                        format!("WebSocket upgrade refused: {}", handshake.info()),
+                    ));
+                }
+            }
+            WebSocketMessage::Frame(frame) => {
+                infos.push(frame.info());
+                frame.insert_fields(&mut packet_info.fields);
+                if let Some(warning) = frame.warning() {
+                    packet_info
+                        .expert
+                        .push(ExpertInfo::new(Severity::Warning, warning));
+                }
+            }
+        }
+    }
+    packet_info.info = infos.join(", ");
+}
+
+fn analyze_hsrp(packet_info: &mut PacketInfo, hsrp: &HsrpPacket) {
+    packet_info.protocol = hsrp.protocol().to_string();
+    packet_info.info = hsrp.info();
+    hsrp.insert_fields(&mut packet_info.fields);
+    if let Some(change) = hsrp.role_change() {
+        packet_info
+            .expert
+            .push(ExpertInfo::new(Severity::Note, change));
+    }
+}
+
+fn analyze_ospf(packet_info: &mut PacketInfo, ospf: &OspfPacket) {
+    packet_info.protocol = ospf.protocol().to_string();
+    packet_info.info = ospf.info();
+    ospf.insert_fields(&mut packet_info.fields);
+    if let Some(note) = ospf.flush_note() {
+        packet_info
+            .expert
+            .push(ExpertInfo::new(Severity::Note, note));
+    }
+}
+
+fn analyze_vrrp(packet_info: &mut PacketInfo, vrrp: &VrrpPacket) {
+    packet_info.protocol = "VRRP".to_string();
+    packet_info.info = vrrp.info();
+    vrrp.insert_fields(&mut packet_info.fields);
+    if vrrp.releasing() {
+        packet_info.expert.push(ExpertInfo::new(
+            Severity::Note,
+            format!("VRRP master is releasing VRID {}", vrrp.virtual_router_id),
+        ));
+    }
+}
+
+fn analyze_lldp(packet_info: &mut PacketInfo, payload: &[u8]) {
+    packet_info.protocol = "LLDP".to_string();
+    match LldpPacket::parse(payload) {
+        Some(lldp) => {
+            packet_info.info = lldp.info();
+            lldp.insert_fields(&mut packet_info.fields);
+        }
+        None => packet_info.info = "Malformed LLDP packet".to_string(),
+    }
+}
+
+/// Decodes 802.3 frames: STP BPDUs and CDP in a SNAP header.
+fn analyze_llc(packet_info: &mut PacketInfo, pdu: &[u8]) {
+    packet_info.protocol = "LLC".to_string();
+    let llc = match link::decode_llc(pdu) {
+        Some(llc) => llc,
+        None => {
+            packet_info.info = "Malformed LLC header".to_string();
+            return;
+        }
+    };
+    llc.insert_fields(&mut packet_info.fields);
+    packet_info.info = format!("DSAP 0x{:02x}, SSAP 0x{:02x}", llc.dsap, llc.ssap);
+
+    let payload = &pdu[llc.payload_offset..];
+    if llc.dsap == stp::LLC_SAP_STP {
+        if let Some(bpdu) = Bpdu::parse(payload) {
+            packet_info.protocol = bpdu.protocol_name().to_string();
+            packet_info.info = bpdu.info();
+            bpdu.insert_fields(&mut packet_info.fields);
+        }
+    } else if llc.snap == Some((cdp::OUI_CISCO, cdp::PID_CDP)) {
+        if let Some(cdp) = CdpPacket::parse(payload) {
+            packet_info.protocol = "CDP".to_string();
+            packet_info.info = cdp.info();
+            cdp.insert_fields(&mut packet_info.fields);
+        }
+    }
+}
+
 fn analyze_dns(packet_info: &mut PacketInfo, dns: &DnsPacket) {
     let query_type = if dns.header.query {
         "Query"
@@ -104,9 +1383,15 @@ fn analyze_dns(packet_info: &mut PacketInfo, dns: &DnsPacket) {
         "Response"
     };
 
+    packet_info.fields.insert("dns.id", dns.header.id);
+    packet_info
+        .fields
+        .insert("dns.flags.response", !dns.header.query);
     let mut queries = Vec::new();
     for question in &dns.questions {
-        queries.push(format!("{}", question.qname));
+        let name = question.qname.to_string();
+        packet_info.fields.insert("dns.qry.name", name.clone());
+        queries.push(name);
     }
 
     packet_info.info = format!("{}: {}", query_type, queries.join(", "));
@@ -115,6 +1400,7 @@ fn analyze_dns(packet_info: &mut PacketInfo, dns: &DnsPacket) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fields::Fields;
     use chrono::Utc;
 
     fn create_basic_packet_info() -> PacketInfo {
@@ -126,6 +1412,9 @@ mod tests {
             info: String::new(),
             length: 0,
             timestamp: Utc::now(),
+            fields: Fields::new(),
+            expert: Vec::new(),
+            objects: Vec::new(),
         }
     }
 
@@ -240,8 +1529,7 @@ mod tests {
         analyze_transport(&mut packet_info, &icmp_packet);
 
         assert_eq!(packet_info.protocol, "ICMPv4");
-        assert!(packet_info.info.contains("Type: 8"));
-        assert!(packet_info.info.contains("Code: 0"));
+        assert_eq!(packet_info.info, "Echo (ping) request id=0x0000, seq=0");
     }
 
     #[test]
@@ -279,4 +1567,873 @@ mod tests {
         assert!(packet_info.destination.contains("443"));
         assert!(packet_info.info.contains("SYN"));
     }
+
+    fn tcp_frame_between(
+        src: ([u8; 4], u16),
+        dst: ([u8; 4], u16),
+        seq: u32,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let builder = etherparse::PacketBuilder::ethernet2([0; 6], [0; 6])
+            .ipv4(src.0, dst.0, 64)
+            .tcp(src.1, dst.1, seq, 1024)
+            .ack(1);
+        let mut frame = Vec::with_capacity(builder.size(payload.len()));
+        builder.write(&mut frame, payload).unwrap();
+        frame
+    }
+
+    fn tcp_frame(seq: u32, payload: &[u8]) -> Vec<u8> {
+        tcp_frame_between(([10, 0, 0, 1], 40000), ([10, 0, 0, 2], 80), seq, payload)
+    }
+
+    fn udp_frame_between(src: ([u8; 4], u16), dst: ([u8; 4], u16), payload: &[u8]) -> Vec<u8> {
+        let builder = etherparse::PacketBuilder::ethernet2([0; 6], [0; 6])
+            .ipv4(src.0, dst.0, 64)
+            .udp(src.1, dst.1);
+        let mut frame = Vec::with_capacity(builder.size(payload.len()));
+        builder.write(&mut frame, payload).unwrap();
+        frame
+    }
+
+    #[test]
+    fn test_analyzer_flags_retransmission() {
+        let mut analyzer = Analyzer::new();
+
+        let mut first = create_basic_packet_info();
+        first.raw_data = tcp_frame(1000, b"hello");
+        analyzer.analyze(&mut first);
+        assert!(first.expert.is_empty());
+        assert_eq!(first.fields.get("tcp.dstport"), Some(&80u16.into()));
+
+        let mut again = create_basic_packet_info();
+        again.raw_data = tcp_frame(1000, b"hello");
+        analyzer.analyze(&mut again);
+        assert!(again.fields.contains("tcp.analysis.retransmission"));
+        assert!(again.info.starts_with("[TCP Retransmission]"));
+        assert_eq!(again.expert.len(), 1);
+    }
+
+    #[test]
+    fn test_analyzer_reassembles_fragments() {
+        let mut analyzer = Analyzer::new();
+
+        // UDP header (port 53 -> 12345, length 24) and 16 bytes of payload
+        let mut datagram = vec![0x00, 0x35, 0x30, 0x39, 0x00, 0x18, 0x00, 0x00];
+        datagram.extend(b"0123456789abcdef");
+
+        let fragment = |flags: [u8; 2], data: &[u8]| {
+            let mut frame = vec![0; 12];
+            frame.extend_from_slice(&[0x08, 0x00]);
+            frame.extend_from_slice(&[0x45, 0x00, 0x00, 20 + data.len() as u8]);
+            frame.extend_from_slice(&[0x12, 0x34, flags[0], flags[1]]);
+            frame.extend_from_slice(&[0x40, 0x11, 0x00, 0x00, 10, 0, 0, 1, 10, 0, 0, 2]);
+            frame.extend_from_slice(data);
+            frame
+        };
+
+        let mut first = create_basic_packet_info();
+        first.raw_data = fragment([0x20, 0x00], &datagram[..16]);
+        analyzer.analyze(&mut first);
+        assert!(first.info.starts_with("Fragmented IP protocol"));
+        assert_eq!(first.fields.get("ip.flags.mf"), Some(&true.into()));
+
+        let mut last = create_basic_packet_info();
+        last.raw_data = fragment([0x00, 0x02], &datagram[16..]);
+        analyzer.analyze(&mut last);
+        assert_eq!(last.protocol, "DNS");
+        assert_eq!(last.source, "10.0.0.1:53");
+        assert_eq!(last.fields.get("ip.fragment.count"), Some(&2usize.into()));
+    }
+
+    #[test]
+    fn test_analyzer_flags_bad_checksum() {
+        let mut frame = tcp_frame(1000, b"hello");
+        *frame.last_mut().unwrap() ^= 0xff;
+
+        let mut unchecked = create_basic_packet_info();
+        unchecked.raw_data = frame.clone();
+        Analyzer::new().analyze(&mut unchecked);
+        assert!(!unchecked.fields.contains("bad_checksum"));
+
+        let mut checked = create_basic_packet_info();
+        checked.raw_data = frame;
+        Analyzer::new()
+            .with_checksum_validation(true)
+            .analyze(&mut checked);
+        assert_eq!(checked.fields.get("bad_checksum"), Some(&true.into()));
+        assert_eq!(
+            checked.fields.get("ip.checksum.status"),
+            Some(&"Good".into())
+        );
+        assert_eq!(
+            checked.fields.get("tcp.checksum.status"),
+            Some(&"Bad".into())
+        );
+        assert_eq!(checked.expert[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_truncated_pseudowire_frame() {
+        let mut packet = create_basic_packet_info();
+        let mut frame = vec![0x01, 0x80, 0xc2, 0, 0, 0, 0, 1, 2, 3, 4, 5, 0x88, 0x47];
+        frame.extend_from_slice(&[0x2e, 0xac, 0x1f, 0x6b]); // MPLS label, BoS
+        frame.push(0x09); // First byte of a control word
+        packet.raw_data = frame;
+
+        analyze_packet(&mut packet);
+
+        assert_eq!(packet.info, "Failed to parse packet");
+    }
+
+    #[test]
+    fn test_ethernet_over_mpls() {
+        let mut frame = vec![0x01, 0x80, 0xc2, 0, 0, 0, 0, 1, 2, 3, 4, 5, 0x88, 0x47];
+        frame.extend_from_slice(&[0x00, 0x01, 0x11, 0x40]); // MPLS label 17, BoS
+        frame.extend_from_slice(&[0; 4]); // Control word
+        frame.extend_from_slice(&tcp_frame(1, b"hello"));
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = frame;
+
+        analyze_packet(&mut packet_info);
+
+        assert_eq!(packet_info.protocol, "TCP");
+        assert_eq!(packet_info.source, "10.0.0.1:40000");
+        assert_eq!(packet_info.fields.get("mpls.label"), Some(&17u32.into()));
+        assert_eq!(
+            packet_info.fields.get("ip.dst"),
+            Some(&IpAddr::from([10, 0, 0, 2]).into())
+        );
+        assert_eq!(packet_info.fields.get("tcp.dstport"), Some(&80u16.into()));
+        // Outer and inner Ethernet headers
+        assert_eq!(packet_info.fields.get_all("eth.src").count(), 2);
+    }
+
+    #[test]
+    fn test_vlan_and_mpls_encapsulation() {
+        let mut vlan = create_basic_packet_info();
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&[0x81, 0x00, 0x20, 0x0a, 0x88, 0x47]); // VLAN 10, PCP 1
+        frame.extend_from_slice(&[0x00, 0x01, 0x01, 0x40]); // MPLS label 16, BoS
+        frame.extend_from_slice(&tcp_frame(1, b"")[14..]);
+        vlan.raw_data = frame;
+
+        analyze_packet(&mut vlan);
+
+        assert_eq!(vlan.protocol, "TCP");
+        assert_eq!(vlan.fields.get("vlan.id"), Some(&10u16.into()));
+        assert_eq!(vlan.fields.get("vlan.priority"), Some(&1u8.into()));
+        assert_eq!(vlan.fields.get("mpls.label"), Some(&16u32.into()));
+        assert_eq!(vlan.source, "10.0.0.1:40000");
+    }
+
+    #[test]
+    fn test_vxlan_inner_packet() {
+        let inner = tcp_frame(1, b"hello");
+        let mut payload = vec![0x08, 0, 0, 0, 0, 0, 0x64, 0]; // VNI 100
+        payload.extend_from_slice(&inner);
+
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = udp_frame_between(
+            ([192, 168, 0, 1], 50000),
+            ([192, 168, 0, 2], tunnel::VXLAN_PORT),
+            &payload,
+        );
+        analyze_packet(&mut packet_info);
+
+        assert_eq!(packet_info.protocol, "TCP");
+        assert_eq!(packet_info.source, "10.0.0.1:40000");
+        assert_eq!(packet_info.fields.get("vxlan.vni"), Some(&100u32.into()));
+        assert_eq!(packet_info.fields.get("udp.dstport"), Some(&4789u16.into()));
+        // Outer and inner addresses
+        let addrs: Vec<_> = packet_info.fields.get_all("ip.addr").collect();
+        assert_eq!(addrs.len(), 4);
+        assert!(addrs.contains(&&IpAddr::from([192, 168, 0, 1]).into()));
+        assert!(addrs.contains(&&IpAddr::from([10, 0, 0, 2]).into()));
+    }
+
+    fn arp_frame(sender_mac: [u8; 6], sender_ip: [u8; 4]) -> Vec<u8> {
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&sender_mac);
+        frame.extend_from_slice(&[0x08, 0x06, 0x00, 0x01, 0x08, 0x00, 6, 4, 0x00, 0x02]);
+        frame.extend_from_slice(&sender_mac);
+        frame.extend_from_slice(&sender_ip);
+        frame.extend_from_slice(&[0; 6]);
+        frame.extend_from_slice(&[10, 0, 0, 9]);
+        frame
+    }
+
+    #[test]
+    fn test_arp_binding_change() {
+        let mut analyzer = Analyzer::new();
+
+        let mut first = create_basic_packet_info();
+        first.raw_data = arp_frame([0x02, 0, 0, 0, 0, 1], [10, 0, 0, 1]);
+        analyzer.analyze(&mut first);
+        assert_eq!(first.protocol, "ARP");
+        assert_eq!(first.info, "10.0.0.1 is at 02:00:00:00:00:01");
+        assert_eq!(first.source, "02:00:00:00:00:01");
+        assert!(first.expert.is_empty());
+
+        let mut spoofed = create_basic_packet_info();
+        spoofed.raw_data = arp_frame([0x02, 0, 0, 0, 0, 2], [10, 0, 0, 1]);
+        analyzer.analyze(&mut spoofed);
+        assert_eq!(spoofed.fields.get("binding.changed"), Some(&true.into()));
+        assert_eq!(
+            spoofed.fields.get("binding.previous_mac"),
+            Some(&[0x02, 0, 0, 0, 0, 1].into())
+        );
+        assert_eq!(spoofed.expert[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_stp_over_llc() {
+        let mut frame = vec![0x01, 0x80, 0xc2, 0, 0, 0, 0x02, 0, 0, 0, 0, 1];
+        frame.extend_from_slice(&7u16.to_be_bytes()); // 802.3 length
+        frame.extend_from_slice(&[0x42, 0x42, 0x03, 0, 0, 0, 0x80]);
+        frame.resize(60, 0); // padding
+
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = frame;
+        analyze_packet(&mut packet_info);
+
+        assert_eq!(packet_info.protocol, "STP");
+        assert_eq!(packet_info.info, "Topology Change Notification");
+    }
+
+    fn icmp_frame(src: [u8; 4], dst: [u8; 4], icmp_type: u8, payload: &[u8]) -> Vec<u8> {
+        let builder = etherparse::PacketBuilder::ethernet2([0; 6], [0; 6])
+            .ipv4(src, dst, 64)
+            .icmpv4(etherparse::Icmpv4Type::Unknown {
+                type_u8: icmp_type,
+                code_u8: 0,
+                bytes5to8: [0, 1, 0, 7],
+            });
+        let mut frame = Vec::with_capacity(builder.size(payload.len()));
+        builder.write(&mut frame, payload).unwrap();
+        frame
+    }
+
+    #[test]
+    fn test_echo_reply_rtt() {
+        let mut analyzer = Analyzer::new();
+
+        let mut request = create_basic_packet_info();
+        request.raw_data = icmp_frame([10, 0, 0, 1], [10, 0, 0, 2], 8, b"ping");
+        analyzer.analyze(&mut request);
+
+        let mut reply = create_basic_packet_info();
+        reply.raw_data = icmp_frame([10, 0, 0, 2], [10, 0, 0, 1], 0, b"ping");
+        reply.timestamp = request.timestamp + chrono::Duration::milliseconds(12);
+        analyzer.analyze(&mut reply);
+
+        assert_eq!(
+            reply.info,
+            "Echo (ping) reply id=0x0001, seq=7, time=12.000 ms"
+        );
+        assert_eq!(reply.fields.get("icmp.resptime"), Some(&12.0.into()));
+    }
+
+    #[test]
+    fn test_time_exceeded_quotes_original_packet() {
+        let quoted = &tcp_frame(1, b"")[14..42];
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = icmp_frame([192, 168, 0, 1], [10, 0, 0, 1], 11, quoted);
+        analyze_packet(&mut packet_info);
+
+        assert_eq!(
+            packet_info.info,
+            "Time exceeded (TTL exceeded in transit) for TCP 10.0.0.1:40000 -> 10.0.0.2:80"
+        );
+        assert_eq!(
+            packet_info.fields.get("icmp.quoted.dstport"),
+            Some(&80u16.into())
+        );
+    }
+
+    fn dhcp_ack_frame(server: [u8; 4]) -> Vec<u8> {
+        let mut payload = vec![0; 236];
+        payload[..3].copy_from_slice(&[2, 1, 6]);
+        payload[4..8].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        payload[16..20].copy_from_slice(&[192, 168, 0, 10]); // yiaddr
+        payload[28..34].copy_from_slice(&[0x02, 0, 0, 0, 0, 1]);
+        payload.extend_from_slice(&[0x63, 0x82, 0x53, 0x63, 53, 1, 5, 255]);
+
+        udp_frame_between(
+            (server, dhcp::SERVER_PORT),
+            ([255, 255, 255, 255], dhcp::CLIENT_PORT),
+            &payload,
+        )
+    }
+
+    #[test]
+    fn test_dhcp_lease_and_rogue_server() {
+        let mut analyzer = Analyzer::new();
+
+        let mut ack = create_basic_packet_info();
+        ack.raw_data = dhcp_ack_frame([192, 168, 0, 1]);
+        analyzer.analyze(&mut ack);
+        assert_eq!(ack.protocol, "DHCP");
+        assert!(ack.expert.is_empty());
+        let leases = Lease::from_fields(&ack.fields, ack.timestamp);
+        assert_eq!(leases.len(), 1);
+        assert_eq!(leases[0].client, "02:00:00:00:00:01");
+        assert_eq!(leases[0].address, IpAddr::from([192, 168, 0, 10]));
+
+        let mut rogue = create_basic_packet_info();
+        rogue.raw_data = dhcp_ack_frame([192, 168, 0, 66]);
+        analyzer.analyze(&mut rogue);
+        assert_eq!(rogue.fields.get("dhcp.rogue_server"), Some(&true.into()));
+        assert_eq!(
+            rogue.fields.get("dhcp.transaction.messages"),
+            Some(&2u32.into())
+        );
+        assert_eq!(
+            rogue.expert[0].message,
+            "Possible rogue DHCP server 192.168.0.66 (expected 192.168.0.1)"
+        );
+    }
+
+    #[test]
+    fn test_quic_initial_sni() {
+        let hello = crate::tls::tests::client_hello("example.com", &["h3"]);
+        let payload = crate::quic::tests::client_initial(&quic::tests::DCID, &[0xaa], 0, &hello);
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = udp_frame_between(
+            ([10, 0, 0, 1], 50000),
+            ([10, 0, 0, 2], quic::QUIC_PORT),
+            &payload,
+        );
+        analyze_packet(&mut packet_info);
+
+        assert_eq!(packet_info.protocol, "QUIC");
+        assert!(packet_info
+            .info
+            .ends_with("CRYPTO, PADDING, SNI=example.com, ALPN=h3"));
+        assert_eq!(
+            packet_info
+                .fields
+                .get("tls.handshake.extensions_server_name"),
+            Some(&"example.com".into())
+        );
+        assert_eq!(
+            packet_info.fields.get("quic.connection.number"),
+            Some(&0usize.into())
+        );
+    }
+
+    #[test]
+    fn test_grpc_request_over_tcp() {
+        let descriptors =
+            DescriptorPool::decode(&crate::protobuf::tests::greeter_descriptor_set()).unwrap();
+        let mut analyzer = Analyzer::new().with_protobuf_descriptors(descriptors);
+
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = tcp_frame(1000, &crate::http2::tests::grpc_request());
+        analyzer.analyze(&mut packet_info);
+
+        assert_eq!(packet_info.protocol, "gRPC");
+        assert!(packet_info
+            .info
+            .starts_with("SETTINGS[0], HEADERS[1]: POST /helloworld.Greeter/SayHello, DATA[1]"));
+        assert_eq!(
+            packet_info.fields.get("grpc.method"),
+            Some(&"/helloworld.Greeter/SayHello".into())
+        );
+        assert_eq!(
+            packet_info.fields.get("protobuf.message.name"),
+            Some(&"helloworld.HelloRequest".into())
+        );
+    }
+
+    fn pgsql_message(type_code: u8, body: &[u8]) -> Vec<u8> {
+        let mut message = vec![type_code];
+        message.extend_from_slice(&(body.len() as u32 + 4).to_be_bytes());
+        message.extend_from_slice(body);
+        message
+    }
+
+    #[test]
+    fn test_postgresql_query_on_overridden_port() {
+        let overrides = vec!["postgresql=15432".parse().unwrap()];
+        let mut analyzer = Analyzer::new().with_port_overrides(overrides);
+
+        let mut query = create_basic_packet_info();
+        let (client, server) = (([10, 0, 0, 1], 40000), ([10, 0, 0, 2], 15432));
+        let message = pgsql_message(b'Q', b"SELECT 1\0");
+        query.raw_data = tcp_frame_between(client, server, 1000, &message);
+        analyzer.analyze(&mut query);
+        assert_eq!(query.protocol, "PGSQL");
+        assert_eq!(query.info, "Simple query: SELECT 1");
+        assert_eq!(query.fields.get("pgsql.query"), Some(&"SELECT 1".into()));
+
+        let mut error = pgsql_message(b'E', b"SERROR\0C42601\0Msyntax error\0\0");
+        error.extend(pgsql_message(b'Z', b"I"));
+        let mut response = create_basic_packet_info();
+        response.timestamp = query.timestamp + chrono::Duration::milliseconds(2);
+        response.raw_data = tcp_frame_between(server, client, 5000, &error);
+        analyzer.analyze(&mut response);
+        assert_eq!(
+            response.info,
+            "Error: ERROR 42601 syntax error, Ready for query, time=2.000 ms"
+        );
+        assert_eq!(
+            response.fields.get("pgsql.response_time"),
+            Some(&0.002.into())
+        );
+        assert_eq!(response.expert[0].severity, Severity::Warning);
+        assert_eq!(
+            response.expert[0].message,
+            "PGSQL error: syntax error (42601)"
+        );
+    }
+
+    #[test]
+    fn test_mqtt_topic_is_filterable() {
+        use crate::filters::PacketFilter;
+        use crate::mqtt::tests::{packet, string};
+
+        let mut publish = string("plant/line1/temp");
+        publish.extend_from_slice(b"21.5");
+        let client = ([10, 0, 0, 1], 40000);
+        let broker = ([10, 0, 0, 2], 1883);
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = tcp_frame_between(client, broker, 1000, &packet(0x30, &publish));
+        Analyzer::new().analyze(&mut packet_info);
+
+        assert_eq!(packet_info.protocol, "MQTT");
+        assert_eq!(packet_info.info, "Publish Message [plant/line1/temp]");
+        let filter = |text: &str| PacketFilter::parse(text).unwrap();
+        assert!(filter("mqtt.topic == \"plant/line1/temp\"").matches(&packet_info));
+        assert!(!filter("mqtt.topic == plant/line2/temp").matches(&packet_info));
+    }
+
+    #[test]
+    fn test_ftp_data_connection_is_labelled() {
+        let mut analyzer = Analyzer::new();
+        let client = ([10, 0, 0, 1], 40000);
+        let server = ([10, 0, 0, 2], 21);
+        let mut analyze = |src, dst, seq, payload: &[u8]| {
+            let mut packet_info = create_basic_packet_info();
+            packet_info.raw_data = tcp_frame_between(src, dst, seq, payload);
+            analyzer.analyze(&mut packet_info);
+            packet_info
+        };
+
+        analyze(client, server, 1000, b"PASV\r\n");
+        let reply = analyze(
+            server,
+            client,
+            5000,
+            b"227 Entering Passive Mode (10,0,0,2,195,80).\r\n",
+        );
+        assert_eq!(reply.protocol, "FTP");
+        analyze(client, server, 1006, b"RETR report.csv\r\n");
+
+        let data = analyze(
+            ([10, 0, 0, 2], 50000),
+            ([10, 0, 0, 1], 40001),
+            7000,
+            b"a,b\n",
+        );
+        assert_eq!(data.protocol, "FTP-DATA");
+        assert_eq!(data.info, "FTP Data: 4 bytes (PASV) (RETR report.csv)");
+    }
+
+    #[test]
+    fn test_ssh_detected_on_non_standard_port() {
+        let mut packet_info = create_basic_packet_info();
+        let server = ([10, 0, 0, 2], 2222);
+        let client = ([10, 0, 0, 1], 40000);
+        packet_info.raw_data = tcp_frame_between(server, client, 1000, b"SSH-2.0-OpenSSH_9.6\r\n");
+        Analyzer::new().analyze(&mut packet_info);
+
+        assert_eq!(packet_info.protocol, "SSH");
+        assert_eq!(packet_info.info, "Server: Protocol (SSH-2.0-OpenSSH_9.6)");
+        assert!(packet_info.fields.contains("ssh.softwareversion"));
+    }
+
+    #[test]
+    fn test_rtp_stream_found_through_sdp() {
+        let mut analyzer = Analyzer::new();
+        let mut invite = create_basic_packet_info();
+        invite.raw_data = udp_frame_between(
+            ([10, 0, 0, 1], sip::SIP_PORT),
+            ([10, 0, 0, 2], sip::SIP_PORT),
+            crate::sip::tests::INVITE.as_bytes(),
+        );
+        analyzer.analyze(&mut invite);
+        assert_eq!(invite.protocol, "SIP");
+        assert_eq!(invite.fields.get("sip.call.state"), Some(&"Calling".into()));
+
+        let mut rtp = |sequence: u16| {
+            let mut payload = vec![0x80, 0x00];
+            payload.extend_from_slice(&sequence.to_be_bytes());
+            payload.extend_from_slice(&(u32::from(sequence) * 160).to_be_bytes());
+            payload.extend_from_slice(&[0, 0, 0, 1]);
+            payload.extend_from_slice(&[0xff; 160]);
+            let mut packet_info = create_basic_packet_info();
+            packet_info.raw_data =
+                udp_frame_between(([10, 0, 0, 2], 30000), ([10, 0, 0, 1], 49170), &payload);
+            analyzer.analyze(&mut packet_info);
+            packet_info
+        };
+        let first = rtp(1);
+        assert_eq!(first.protocol, "RTP");
+        assert_eq!(first.info, "PT=PCMU (0), SSRC=0x00000001, Seq=1, Time=160");
+        assert_eq!(
+            first.fields.get("rtp.call_id"),
+            Some(&"a84b4c76e66710@pc33.example.com".into())
+        );
+
+        let after_gap = rtp(4);
+        assert_eq!(
+            after_gap.fields.get("rtp.analysis.lost"),
+            Some(&2u64.into())
+        );
+        assert_eq!(
+            after_gap.expert[0].message,
+            "Wrong sequence number: expected 2, got 4 (2 packets lost)"
+        );
+    }
+
+    #[test]
+    fn test_industrial_writes_are_flagged() {
+        let mut packet_info = create_basic_packet_info();
+        let write = crate::modbus::tests::adu(1, 1, &[6, 0, 40, 0x04, 0xd2]);
+        packet_info.raw_data =
+            tcp_frame_between(([10, 0, 0, 1], 40000), ([10, 0, 0, 2], 502), 1000, &write);
+        Analyzer::new().analyze(&mut packet_info);
+        assert_eq!(packet_info.protocol, "Modbus/TCP");
+        assert_eq!(
+            packet_info.info,
+            "Query: Write Single Register, unit 1, register 40 = 1234"
+        );
+        assert_eq!(packet_info.expert[0].severity, Severity::Warning);
+
+        let mut packet_info = create_basic_packet_info();
+        let write = crate::bacnet::tests::unicast(&[
+            0x00, 0x05, 1, 15, 0x0c, 0x01, 0x00, 0x00, 0x02, 0x19, 85, 0x3e, 0x91, 1, 0x3f,
+        ]);
+        packet_info.raw_data = udp_frame_between(
+            ([10, 0, 0, 1], bacnet::BACNET_PORT),
+            ([10, 0, 0, 2], bacnet::BACNET_PORT),
+            &write,
+        );
+        Analyzer::new().analyze(&mut packet_info);
+        assert_eq!(packet_info.protocol, "BACnet");
+        assert_eq!(
+            packet_info.info,
+            "Confirmed-REQ writeProperty[1] binary-output,2 present-value"
+        );
+        assert_eq!(packet_info.expert[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_syslog_and_netflow_over_udp() {
+        let mut analyzer = Analyzer::new();
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = udp_frame_between(
+            ([10, 0, 0, 1], 40000),
+            ([10, 0, 0, 2], syslog::SYSLOG_PORT),
+            b"<11>sshd[42]: fatal: no hostkey",
+        );
+        analyzer.analyze(&mut packet_info);
+        assert_eq!(packet_info.protocol, "Syslog");
+        assert_eq!(packet_info.info, "USER.ERR: sshd[42]: fatal: no hostkey");
+        assert_eq!(packet_info.expert[0].message, "Syslog ERR message");
+
+        let export = |sequence: u32| {
+            let mut data = vec![0, 5, 0, 1];
+            data.extend_from_slice(&[0; 12]);
+            data.extend_from_slice(&sequence.to_be_bytes());
+            data.extend_from_slice(&[0; 4]);
+            let mut record = vec![0; 48];
+            record[..8].copy_from_slice(&[192, 168, 1, 1, 192, 168, 1, 2]);
+            record[38] = 17;
+            data.extend(record);
+            let mut packet_info = create_basic_packet_info();
+            packet_info.raw_data = udp_frame_between(
+                ([10, 0, 0, 254], 50000),
+                ([10, 0, 0, 2], netflow::NETFLOW_PORTS[0]),
+                &data,
+            );
+            packet_info
+        };
+        let mut first = export(10);
+        analyzer.analyze(&mut first);
+        assert_eq!(first.protocol, "NetFlow");
+        assert_eq!(first.info, "NetFlow v5, 1 flow");
+        assert!(first.expert.is_empty());
+
+        let mut after_loss = export(20);
+        analyzer.analyze(&mut after_loss);
+        assert_eq!(
+            after_loss.expert[0].message,
+            "NetFlow sequence number 20 where 11 was expected"
+        );
+    }
+
+    fn ip_frame(protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let builder = etherparse::PacketBuilder::ethernet2([0; 6], [0; 6]).ipv4(
+            [10, 0, 0, 1],
+            [224, 0, 0, 5],
+            1,
+        );
+        let mut frame = Vec::with_capacity(builder.size(payload.len()));
+        builder.write(&mut frame, protocol, payload).unwrap();
+        frame
+    }
+
+    #[test]
+    fn test_routing_protocols() {
+        let mut packet_info = create_basic_packet_info();
+        let notification = crate::bgp::tests::message(3, &[6, 2]);
+        packet_info.raw_data = tcp_frame_between(
+            ([10, 0, 0, 2], 179),
+            ([10, 0, 0, 1], 40000),
+            1000,
+            &notification,
+        );
+        Analyzer::new().analyze(&mut packet_info);
+        assert_eq!(packet_info.protocol, "BGP");
+        assert_eq!(
+            packet_info.info,
+            "NOTIFICATION Message, Cease (Administrative Shutdown)"
+        );
+        assert_eq!(
+            packet_info.expert[0].message,
+            "BGP error: Cease: Administrative Shutdown"
+        );
+
+        let mut hello = vec![2, 1, 0, 48, 1, 1, 1, 1, 0, 0, 0, 0];
+        hello.resize(24, 0);
+        hello.extend_from_slice(&[255, 255, 255, 0, 0, 10, 0x02, 1, 0, 0, 0, 40]);
+        hello.extend_from_slice(&[10, 0, 0, 1, 0, 0, 0, 0, 2, 2, 2, 2]);
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = ip_frame(ospf::IP_PROTOCOL, &hello);
+        Analyzer::new().analyze(&mut packet_info);
+        assert_eq!(packet_info.protocol, "OSPF");
+        assert_eq!(
+            packet_info.info,
+            "Hello Packet, router 1.1.1.1, area 0.0.0.0, 1 neighbor"
+        );
+
+        let release = [
+            0x21, 1, 0, 1, 0, 1, 0, 0, 10, 0, 0, 254, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = ip_frame(vrrp::IP_PROTOCOL, &release);
+        Analyzer::new().analyze(&mut packet_info);
+        assert_eq!(packet_info.protocol, "VRRP");
+        assert_eq!(
+            packet_info.expert[0].message,
+            "VRRP master is releasing VRID 1"
+        );
+
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data = ip_frame(253, &[0; 8]);
+        Analyzer::new().analyze(&mut packet_info);
+        assert_eq!(packet_info.protocol, "Unknown Transport");
+    }
+
+    #[test]
+    fn test_radiotap_and_80211_frames() {
+        use crate::wlan::tests::{beacon, eapol_key, radiotap, AP, STATION};
+
+        let mut analyzer = Analyzer::new().with_link_type(LinkType::Radiotap);
+        let with_radio = |frame: Vec<u8>| {
+            let mut data = radiotap(2437, -42);
+            data.extend(frame);
+            // The frame check sequence
+            data.extend_from_slice(&[0; 4]);
+            let mut packet_info = create_basic_packet_info();
+            packet_info.raw_data = data;
+            packet_info
+        };
+
+        let mut packet_info = with_radio(beacon("HomeNet"));
+        analyzer.analyze(&mut packet_info);
+        assert_eq!(packet_info.protocol, "802.11");
+        assert_eq!(
+            packet_info.info,
+            "Beacon frame, SSID \"HomeNet\", channel 6, WPA2 [-42 dBm, ch 6, 6 Mb/s]"
+        );
+        assert_eq!(packet_info.fields.get("wlan.bssid"), Some(&AP.into()));
+
+        let mut packet_info = with_radio(eapol_key(1));
+        analyzer.analyze(&mut packet_info);
+        assert_eq!(packet_info.protocol, "EAPOL");
+        assert!(packet_info.info.starts_with("Key (Message 1 of 4)"));
+        assert_eq!(packet_info.fields.get("wlan.staa"), Some(&STATION.into()));
+
+        // An IPv4 packet in an unprotected data frame from the station
+        let mut frame = vec![0x08, 0x01, 0, 0];
+        frame.extend_from_slice(&AP);
+        frame.extend_from_slice(&STATION);
+        frame.extend_from_slice(&[0xff; 6]);
+        frame.extend_from_slice(&[0, 0, 0xaa, 0xaa, 0x03, 0, 0, 0, 0x08, 0x00]);
+        let udp = udp_frame_between(
+            ([10, 0, 0, 5], 40000),
+            ([10, 0, 0, 255], syslog::SYSLOG_PORT),
+            b"<14>hello",
+        );
+        frame.extend_from_slice(&udp[14..]);
+        let mut packet_info = with_radio(frame);
+        analyzer.analyze(&mut packet_info);
+        assert_eq!(packet_info.protocol, "Syslog");
+        assert_eq!(packet_info.source, "10.0.0.5:40000");
+    }
+
+    #[test]
+    fn test_websocket_upgrade_and_frames() {
+        use crate::websocket::tests::{frame, SWITCH, UPGRADE};
+
+        let mut analyzer = Analyzer::new();
+        let (client, server) = (([10, 0, 0, 1], 40000), ([10, 0, 0, 2], 8080));
+        let mut request = create_basic_packet_info();
+        request.raw_data = tcp_frame_between(client, server, 1000, UPGRADE.as_bytes());
+        analyzer.analyze(&mut request);
+        assert_eq!(request.protocol, "WebSocket");
+        assert_eq!(request.info, "Upgrade request GET /chat, protocol chat");
+        assert_eq!(request.fields.get("http.host"), Some(&"example.com".into()));
+        assert_eq!(
+            request.fields.get("http.request.version"),
+            Some(&"HTTP/1.1".into())
+        );
+        assert_eq!(
+            request.fields.get("http.upgrade"),
+            Some(&"websocket".into())
+        );
+
+        let mut response = create_basic_packet_info();
+        response.raw_data = tcp_frame_between(server, client, 5000, SWITCH.as_bytes());
+        analyzer.analyze(&mut response);
+        assert_eq!(response.info, "Switching Protocols (101), protocol chat");
+        assert_eq!(
+            response.fields.get("http.response.phrase"),
+            Some(&"Switching Protocols".into())
+        );
+        assert_eq!(response.fields.get_all("http.response.code").count(), 1);
+
+        let mut text = create_basic_packet_info();
+        let seq = 1000 + UPGRADE.len() as u32;
+        text.raw_data = tcp_frame_between(client, server, seq, &frame(0x81, b"subscribe", true));
+        analyzer.analyze(&mut text);
+        assert_eq!(text.info, "Text [FIN, MASKED]: subscribe");
+        assert_eq!(
+            text.fields.get("websocket.payload.text"),
+            Some(&"subscribe".into())
+        );
+    }
+
+    #[test]
+    fn test_object_export() {
+        let mut analyzer = Analyzer::new().with_object_export(true);
+        let mut analyze = |src, dst, seq, payload: &[u8]| {
+            let mut packet_info = create_basic_packet_info();
+            packet_info.raw_data = tcp_frame_between(src, dst, seq, payload);
+            analyzer.analyze(&mut packet_info);
+            packet_info
+        };
+
+        let (client, server) = (([10, 0, 0, 1], 40000), ([10, 0, 0, 2], 8000));
+        analyze(
+            client,
+            server,
+            1000,
+            b"GET /a.txt HTTP/1.1\r\nHost: files\r\n\r\n",
+        );
+        let response = analyze(
+            server,
+            client,
+            5000,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi",
+        );
+        assert_eq!(response.objects.len(), 1);
+        assert_eq!(response.objects[0].name, "http://files/a.txt");
+        assert_eq!(response.objects[0].data, b"hi");
+
+        // An FTP download is complete when the data connection closes
+        let control = ([10, 0, 0, 2], 21);
+        analyze(client, control, 1000, b"PASV\r\n");
+        let reply = b"227 Entering Passive Mode (10,0,0,2,195,80).\r\n";
+        analyze(control, client, 5000, reply);
+        analyze(client, control, 1006, b"RETR report.csv\r\n");
+        let (data_server, data_client) = (([10, 0, 0, 2], 50000), ([10, 0, 0, 1], 40001));
+        let data = analyze(data_server, data_client, 7000, b"a,b\n");
+        assert!(data.objects.is_empty());
+
+        let mut fin = create_basic_packet_info();
+        let builder = etherparse::PacketBuilder::ethernet2([0; 6], [0; 6])
+            .ipv4(data_server.0, data_client.0, 64)
+            .tcp(data_server.1, data_client.1, 7004, 1024)
+            .fin();
+        builder.write(&mut fin.raw_data, &[]).unwrap();
+        analyzer.analyze(&mut fin);
+        assert_eq!(fin.objects.len(), 1);
+        assert_eq!(fin.objects[0].protocol, "FTP-DATA");
+        assert_eq!(fin.objects[0].name, "report.csv");
+        assert_eq!(fin.objects[0].data, b"a,b\n");
+        assert!(analyzer.finish_objects().is_empty());
+    }
+
+    #[test]
+    fn test_http_heads() {
+        use crate::filters::PacketFilter;
+
+        let mut analyzer = Analyzer::new();
+        let (client, server) = (([10, 0, 0, 1], 40000), ([10, 0, 0, 2], 80));
+        let mut request = create_basic_packet_info();
+        request.raw_data = tcp_frame_between(
+            client,
+            server,
+            1000,
+            b"GET /intranet HTTP/1.1\r\nHost: wiki.corp.example\r\n\r\n",
+        );
+        analyzer.analyze(&mut request);
+        let mut response = create_basic_packet_info();
+        response.raw_data = tcp_frame_between(
+            server,
+            client,
+            5000,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi",
+        );
+        analyzer.analyze(&mut response);
+
+        assert_eq!(request.protocol, "HTTP");
+        assert_eq!(request.info, "GET /intranet HTTP/1.1");
+        assert!(PacketFilter::parse("http.host contains \"corp\"")
+            .unwrap()
+            .matches(&request));
+        assert_eq!(response.info, "HTTP/1.1 200 OK");
+        assert_eq!(
+            response.fields.get("http.response.code"),
+            Some(&200u16.into())
+        );
+        // Without object export the body isn't kept
+        assert!(response.objects.is_empty());
+    }
+
+    #[test]
+    fn test_display_filter_on_dns_query() {
+        use crate::filters::PacketFilter;
+
+        let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        query.extend_from_slice(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        let mut packet_info = create_basic_packet_info();
+        packet_info.raw_data =
+            udp_frame_between(([10, 0, 0, 1], 40000), ([8, 8, 8, 8], 53), &query);
+        Analyzer::new().analyze(&mut packet_info);
+
+        assert_eq!(packet_info.info, "Query: example.com");
+        let filter = |text: &str| PacketFilter::parse(text).unwrap();
+        assert!(filter("dns && dns.qry.name == example.com").matches(&packet_info));
+        assert!(
+            filter("dns.qry.name matches \"EXAMPLE\\.com$\" and not dns.flags.response")
+                .matches(&packet_info)
+        );
+        assert!(filter("ip.dst == 8.8.8.8 && udp.dstport in {53 5353}").matches(&packet_info));
+        assert!(!filter("dns.id != 0x1234").matches(&packet_info));
+    }
 }
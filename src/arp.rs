@@ -0,0 +1,128 @@
+use crate::fields::{format_mac, Fields};
+use crate::link::ether_type;
+use std::net::Ipv4Addr;
+
+pub const REQUEST: u16 = 1;
+pub const REPLY: u16 = 2;
+pub const REVERSE_REQUEST: u16 = 3;
+pub const REVERSE_REPLY: u16 = 4;
+
+const HARDWARE_ETHERNET: u16 = 1;
+
+/// An ARP or RARP packet for IPv4 over Ethernet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpPacket {
+    pub opcode: u16,
+    pub sender_mac: [u8; 6],
+    pub sender_ip: Ipv4Addr,
+    pub target_mac: [u8; 6],
+    pub target_ip: Ipv4Addr,
+}
+
+impl ArpPacket {
+    pub fn parse(payload: &[u8]) -> Option<ArpPacket> {
+        let header = payload.get(..28)?;
+        let hardware = u16::from_be_bytes([header[0], header[1]]);
+        let protocol = u16::from_be_bytes([header[2], header[3]]);
+        if hardware != HARDWARE_ETHERNET
+            || protocol != ether_type::IPV4
+            || header[4] != 6
+            || header[5] != 4
+        {
+            return None;
+        }
+
+        Some(ArpPacket {
+            opcode: u16::from_be_bytes([header[6], header[7]]),
+            sender_mac: header[8..14].try_into().ok()?,
+            sender_ip: Ipv4Addr::new(header[14], header[15], header[16], header[17]),
+            target_mac: header[18..24].try_into().ok()?,
+            target_ip: Ipv4Addr::new(header[24], header[25], header[26], header[27]),
+        })
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        matches!(self.opcode, REVERSE_REQUEST | REVERSE_REPLY)
+    }
+
+    /// An announcement of the sender's own address rather than a lookup.
+    pub fn is_gratuitous(&self) -> bool {
+        matches!(self.opcode, REQUEST | REPLY) && self.sender_ip == self.target_ip
+    }
+
+    pub fn protocol_name(&self) -> &'static str {
+        if self.is_reverse() {
+            "RARP"
+        } else {
+            "ARP"
+        }
+    }
+
+    pub fn info(&self) -> String {
+        match self.opcode {
+            _ if self.is_gratuitous() => format!("Gratuitous ARP for {}", self.sender_ip),
+            REQUEST if self.sender_ip.is_unspecified() => {
+                format!("Who has {}? (ARP Probe)", self.target_ip)
+            }
+            REQUEST => format!("Who has {}? Tell {}", self.target_ip, self.sender_ip),
+            REPLY => format!("{} is at {}", self.sender_ip, format_mac(&self.sender_mac)),
+            REVERSE_REQUEST => format!(
+                "Who is {}? Tell {}",
+                format_mac(&self.target_mac),
+                format_mac(&self.sender_mac)
+            ),
+            REVERSE_REPLY => format!("{} is at {}", format_mac(&self.target_mac), self.target_ip),
+            opcode => format!("Unknown ARP opcode {}", opcode),
+        }
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("arp.opcode", self.opcode);
+        fields.insert("arp.src.hw_mac", self.sender_mac);
+        fields.insert("arp.src.proto_ipv4", self.sender_ip);
+        fields.insert("arp.dst.hw_mac", self.target_mac);
+        fields.insert("arp.dst.proto_ipv4", self.target_ip);
+        if self.is_gratuitous() {
+            fields.insert("arp.isgratuitous", true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arp(opcode: u16, sender_ip: [u8; 4], target_ip: [u8; 4]) -> Vec<u8> {
+        let mut payload = vec![0x00, 0x01, 0x08, 0x00, 6, 4];
+        payload.extend_from_slice(&opcode.to_be_bytes());
+        payload.extend_from_slice(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        payload.extend_from_slice(&sender_ip);
+        payload.extend_from_slice(&[0; 6]);
+        payload.extend_from_slice(&target_ip);
+        payload
+    }
+
+    #[test]
+    fn test_request_and_reply() {
+        let request = ArpPacket::parse(&arp(REQUEST, [10, 0, 0, 1], [10, 0, 0, 2])).unwrap();
+        assert_eq!(request.info(), "Who has 10.0.0.2? Tell 10.0.0.1");
+        assert_eq!(request.protocol_name(), "ARP");
+
+        let reply = ArpPacket::parse(&arp(REPLY, [10, 0, 0, 2], [10, 0, 0, 1])).unwrap();
+        assert_eq!(reply.info(), "10.0.0.2 is at 00:11:22:33:44:55");
+    }
+
+    #[test]
+    fn test_gratuitous_and_reverse() {
+        let gratuitous = ArpPacket::parse(&arp(REQUEST, [10, 0, 0, 1], [10, 0, 0, 1])).unwrap();
+        assert!(gratuitous.is_gratuitous());
+
+        let mut fields = Fields::new();
+        gratuitous.insert_fields(&mut fields);
+        assert!(fields.contains("arp.isgratuitous"));
+
+        let rarp = ArpPacket::parse(&arp(REVERSE_REQUEST, [0; 4], [0; 4])).unwrap();
+        assert_eq!(rarp.protocol_name(), "RARP");
+        assert!(!rarp.is_gratuitous());
+    }
+}
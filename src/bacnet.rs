@@ -0,0 +1,611 @@
+use crate::fields::Fields;
+
+pub const BACNET_PORT: u16 = 47808;
+
+const BVLC_TYPE: u8 = 0x81;
+const ORIGINAL_UNICAST_NPDU: u8 = 0x0a;
+const ORIGINAL_BROADCAST_NPDU: u8 = 0x0b;
+const FORWARDED_NPDU: u8 = 0x04;
+const DISTRIBUTE_BROADCAST: u8 = 0x09;
+const ORIGINAL_SECURE_NPDU: u8 = 0x0c;
+
+// NPDU control bits
+const NETWORK_MESSAGE: u8 = 0x80;
+const DNET_PRESENT: u8 = 0x20;
+const SNET_PRESENT: u8 = 0x08;
+
+// Segmented message bit of requests and complex ACKs
+const SEGMENTED: u8 = 0x08;
+
+const CONFIRMED_REQUEST: u8 = 0;
+const UNCONFIRMED_REQUEST: u8 = 1;
+const SIMPLE_ACK: u8 = 2;
+const COMPLEX_ACK: u8 = 3;
+const SEGMENT_ACK: u8 = 4;
+const ERROR: u8 = 5;
+const REJECT: u8 = 6;
+const ABORT: u8 = 7;
+
+const I_AM: u8 = 0;
+const WRITE_GROUP: u8 = 10;
+
+fn bvlc_function_name(function: u8) -> &'static str {
+    match function {
+        0x00 => "BVLC-Result",
+        0x01 => "Write-Broadcast-Distribution-Table",
+        0x02 => "Read-Broadcast-Distribution-Table",
+        0x03 => "Read-Broadcast-Distribution-Table-Ack",
+        FORWARDED_NPDU => "Forwarded-NPDU",
+        0x05 => "Register-Foreign-Device",
+        0x06 => "Read-Foreign-Device-Table",
+        0x07 => "Read-Foreign-Device-Table-Ack",
+        0x08 => "Delete-Foreign-Device-Table-Entry",
+        DISTRIBUTE_BROADCAST => "Distribute-Broadcast-To-Network",
+        ORIGINAL_UNICAST_NPDU => "Original-Unicast-NPDU",
+        ORIGINAL_BROADCAST_NPDU => "Original-Broadcast-NPDU",
+        ORIGINAL_SECURE_NPDU => "Secure-BVLL",
+        _ => "Unknown",
+    }
+}
+
+fn pdu_type_name(pdu_type: u8) -> &'static str {
+    match pdu_type {
+        CONFIRMED_REQUEST => "Confirmed-REQ",
+        UNCONFIRMED_REQUEST => "Unconfirmed-REQ",
+        SIMPLE_ACK => "SimpleACK",
+        COMPLEX_ACK => "ComplexACK",
+        SEGMENT_ACK => "SegmentACK",
+        ERROR => "Error",
+        REJECT => "Reject",
+        ABORT => "Abort",
+        _ => "Unknown",
+    }
+}
+
+pub fn confirmed_service_name(service: u8) -> &'static str {
+    match service {
+        0 => "acknowledgeAlarm",
+        1 => "confirmedCOVNotification",
+        2 => "confirmedEventNotification",
+        3 => "getAlarmSummary",
+        4 => "getEnrollmentSummary",
+        5 => "subscribeCOV",
+        6 => "atomicReadFile",
+        7 => "atomicWriteFile",
+        8 => "addListElement",
+        9 => "removeListElement",
+        10 => "createObject",
+        11 => "deleteObject",
+        12 => "readProperty",
+        14 => "readPropertyMultiple",
+        15 => "writeProperty",
+        16 => "writePropertyMultiple",
+        17 => "deviceCommunicationControl",
+        18 => "confirmedPrivateTransfer",
+        19 => "confirmedTextMessage",
+        20 => "reinitializeDevice",
+        26 => "readRange",
+        28 => "subscribeCOVProperty",
+        29 => "getEventInformation",
+        _ => "unknown",
+    }
+}
+
+pub fn unconfirmed_service_name(service: u8) -> &'static str {
+    match service {
+        I_AM => "i-Am",
+        1 => "i-Have",
+        2 => "unconfirmedCOVNotification",
+        3 => "unconfirmedEventNotification",
+        4 => "unconfirmedPrivateTransfer",
+        5 => "unconfirmedTextMessage",
+        6 => "timeSynchronization",
+        7 => "who-Has",
+        8 => "who-Is",
+        9 => "utcTimeSynchronization",
+        WRITE_GROUP => "writeGroup",
+        _ => "unknown",
+    }
+}
+
+/// Whether the confirmed service changes objects, files or the state of
+/// the device.
+pub fn is_write(service: u8) -> bool {
+    matches!(service, 7..=11 | 15 | 16 | 17 | 20)
+}
+
+fn object_type_name(object_type: u16) -> Option<&'static str> {
+    Some(match object_type {
+        0 => "analog-input",
+        1 => "analog-output",
+        2 => "analog-value",
+        3 => "binary-input",
+        4 => "binary-output",
+        5 => "binary-value",
+        6 => "calendar",
+        7 => "command",
+        8 => "device",
+        9 => "event-enrollment",
+        10 => "file",
+        11 => "group",
+        12 => "loop",
+        13 => "multi-state-input",
+        14 => "multi-state-output",
+        15 => "notification-class",
+        16 => "program",
+        17 => "schedule",
+        18 => "averaging",
+        19 => "multi-state-value",
+        20 => "trend-log",
+        _ => return None,
+    })
+}
+
+fn property_name(property: u32) -> Option<&'static str> {
+    Some(match property {
+        8 => "all",
+        28 => "description",
+        36 => "event-state",
+        75 => "object-identifier",
+        76 => "object-list",
+        77 => "object-name",
+        79 => "object-type",
+        81 => "out-of-service",
+        85 => "present-value",
+        87 => "priority-array",
+        103 => "reliability",
+        104 => "relinquish-default",
+        111 => "status-flags",
+        117 => "units",
+        _ => return None,
+    })
+}
+
+fn error_class_name(class: u32) -> &'static str {
+    match class {
+        0 => "device",
+        1 => "object",
+        2 => "property",
+        3 => "resources",
+        4 => "security",
+        5 => "services",
+        6 => "vt",
+        7 => "communication",
+        _ => "unknown",
+    }
+}
+
+fn error_code_name(code: u32) -> Option<&'static str> {
+    Some(match code {
+        0 => "other",
+        3 => "device-busy",
+        9 => "invalid-data-type",
+        27 => "read-access-denied",
+        31 => "unknown-object",
+        32 => "unknown-property",
+        37 => "value-out-of-range",
+        40 => "write-access-denied",
+        _ => return None,
+    })
+}
+
+/// An object identifier: a 10-bit type and a 22-bit instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectId {
+    pub object_type: u16,
+    pub instance: u32,
+}
+
+impl ObjectId {
+    fn from_u32(value: u32) -> Self {
+        ObjectId {
+            object_type: (value >> 22) as u16,
+            instance: value & 0x3f_ffff,
+        }
+    }
+}
+
+impl std::fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match object_type_name(self.object_type) {
+            Some(name) => write!(f, "{},{}", name, self.instance),
+            None => write!(f, "object-type-{},{}", self.object_type, self.instance),
+        }
+    }
+}
+
+/// The application layer of a BACnet packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Apdu {
+    pub pdu_type: u8,
+    pub invoke_id: Option<u8>,
+    pub service: Option<u8>,
+    pub object: Option<ObjectId>,
+    pub property: Option<u32>,
+    /// Error class and code of an Error PDU.
+    pub error: Option<(u32, u32)>,
+    /// Reason of a Reject or Abort PDU.
+    pub reason: Option<u8>,
+}
+
+/// A BACnet/IP packet: the BVLC header, the network layer and, unless it
+/// carries a network message, the application layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacnetPacket {
+    pub bvlc_function: u8,
+    pub npdu_control: Option<u8>,
+    pub network_message: Option<u8>,
+    pub destination_network: Option<u16>,
+    pub source_network: Option<u16>,
+    pub apdu: Option<Apdu>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.data.get(..2)?;
+        self.data = &self.data[2..];
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.data = self.data.get(len..)?;
+        Some(())
+    }
+
+    /// An unsigned value of a tag holding up to four bytes.
+    fn unsigned(&mut self, len: usize) -> Option<u32> {
+        let bytes = self.data.get(..len).filter(|_| (1..=4).contains(&len))?;
+        self.data = &self.data[len..];
+        Some(bytes.iter().fold(0, |value, &b| value << 8 | u32::from(b)))
+    }
+
+    /// The number, whether it's context specific, and the length of the
+    /// next tag. Opening and closing tags aren't decoded.
+    fn tag(&mut self) -> Option<(u8, bool, usize)> {
+        let tag = self.u8()?;
+        let len = match tag & 0x07 {
+            5 => usize::from(self.u8()?),
+            6 | 7 => return None,
+            len => usize::from(len),
+        };
+        Some((tag >> 4, tag & 0x08 != 0, len))
+    }
+
+    /// A context tag with the given number and an unsigned value.
+    fn context(&mut self, number: u8) -> Option<u32> {
+        match self.tag()? {
+            (n, true, len) if n == number => self.unsigned(len),
+            _ => None,
+        }
+    }
+
+    /// An application tag with an unsigned value, such as an enumeration.
+    fn application(&mut self) -> Option<u32> {
+        match self.tag()? {
+            (_, false, len) => self.unsigned(len),
+            _ => None,
+        }
+    }
+}
+
+impl Apdu {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let mut reader = Reader { data };
+        let first = reader.u8()?;
+        let mut apdu = Apdu {
+            pdu_type: first >> 4,
+            invoke_id: None,
+            service: None,
+            object: None,
+            property: None,
+            error: None,
+            reason: None,
+        };
+        match apdu.pdu_type {
+            CONFIRMED_REQUEST => {
+                // Maximum segments and APDU size
+                reader.u8()?;
+                apdu.invoke_id = Some(reader.u8()?);
+                if first & SEGMENTED != 0 {
+                    reader.skip(2)?;
+                }
+                apdu.service = Some(reader.u8()?);
+                apdu.parameters(&mut reader);
+            }
+            UNCONFIRMED_REQUEST => {
+                apdu.service = Some(reader.u8()?);
+                if apdu.service == Some(I_AM) {
+                    apdu.object = reader.application().map(ObjectId::from_u32);
+                }
+            }
+            SIMPLE_ACK => {
+                apdu.invoke_id = Some(reader.u8()?);
+                apdu.service = Some(reader.u8()?);
+            }
+            COMPLEX_ACK => {
+                apdu.invoke_id = Some(reader.u8()?);
+                if first & SEGMENTED != 0 {
+                    reader.skip(2)?;
+                }
+                apdu.service = Some(reader.u8()?);
+                apdu.parameters(&mut reader);
+            }
+            SEGMENT_ACK => apdu.invoke_id = Some(reader.u8()?),
+            ERROR => {
+                apdu.invoke_id = Some(reader.u8()?);
+                apdu.service = Some(reader.u8()?);
+                apdu.error = reader.application().zip(reader.application());
+            }
+            REJECT | ABORT => {
+                apdu.invoke_id = Some(reader.u8()?);
+                apdu.reason = Some(reader.u8()?);
+            }
+            _ => return None,
+        }
+        Some(apdu)
+    }
+
+    /// Decodes the object and property a confirmed service or its ACK
+    /// refers to.
+    fn parameters(&mut self, reader: &mut Reader) {
+        match self.service {
+            Some(12 | 15 | 14 | 16 | 5 | 28) => {
+                self.object = reader.context(0).map(ObjectId::from_u32);
+            }
+            _ => return,
+        }
+        if matches!(self.service, Some(12 | 15)) {
+            self.property = reader.context(1);
+        }
+    }
+
+    fn service_name(&self) -> Option<&'static str> {
+        let service = self.service?;
+        Some(match self.pdu_type {
+            UNCONFIRMED_REQUEST => unconfirmed_service_name(service),
+            _ => confirmed_service_name(service),
+        })
+    }
+
+    /// A request that changes the device it's sent to.
+    pub fn is_write(&self) -> bool {
+        match (self.pdu_type, self.service) {
+            (CONFIRMED_REQUEST, Some(service)) => is_write(service),
+            (UNCONFIRMED_REQUEST, Some(service)) => service == WRITE_GROUP,
+            _ => false,
+        }
+    }
+
+    fn info(&self) -> String {
+        let mut info = pdu_type_name(self.pdu_type).to_string();
+        if let Some(service) = self.service_name() {
+            info.push(' ');
+            info.push_str(service);
+        }
+        if let Some(invoke_id) = self.invoke_id {
+            info.push_str(&format!("[{}]", invoke_id));
+        }
+        if let Some(object) = self.object {
+            info.push_str(&format!(" {}", object));
+        }
+        if let Some(property) = self.property {
+            match property_name(property) {
+                Some(name) => info.push_str(&format!(" {}", name)),
+                None => info.push_str(&format!(" property-{}", property)),
+            }
+        }
+        if let Some(error) = self.error_description() {
+            info.push_str(&format!(": {}", error));
+        }
+        info
+    }
+
+    fn error_description(&self) -> Option<String> {
+        if let Some((class, code)) = self.error {
+            let code = error_code_name(code)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("error-code-{}", code));
+            return Some(format!("{}: {}", error_class_name(class), code));
+        }
+        self.reason.map(|reason| format!("reason {}", reason))
+    }
+}
+
+impl BacnetPacket {
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let mut reader = Reader { data: payload };
+        if reader.u8()? != BVLC_TYPE {
+            return None;
+        }
+        let bvlc_function = reader.u8()?;
+        let length = usize::from(reader.u16()?);
+        if length < 4 || length > payload.len() {
+            return None;
+        }
+        reader.data = &payload[4..length];
+        let mut packet = BacnetPacket {
+            bvlc_function,
+            npdu_control: None,
+            network_message: None,
+            destination_network: None,
+            source_network: None,
+            apdu: None,
+        };
+        match bvlc_function {
+            ORIGINAL_UNICAST_NPDU | ORIGINAL_BROADCAST_NPDU | DISTRIBUTE_BROADCAST => {}
+            // The address of the original sender comes first
+            FORWARDED_NPDU => reader.skip(6)?,
+            _ => return Some(packet),
+        }
+
+        // NPDU version
+        if reader.u8()? != 1 {
+            return None;
+        }
+        let control = reader.u8()?;
+        packet.npdu_control = Some(control);
+        if control & DNET_PRESENT != 0 {
+            packet.destination_network = Some(reader.u16()?);
+            let address_len = usize::from(reader.u8()?);
+            reader.skip(address_len)?;
+        }
+        if control & SNET_PRESENT != 0 {
+            packet.source_network = Some(reader.u16()?);
+            let address_len = usize::from(reader.u8()?);
+            reader.skip(address_len)?;
+        }
+        if control & DNET_PRESENT != 0 {
+            // Hop count
+            reader.u8()?;
+        }
+        if control & NETWORK_MESSAGE != 0 {
+            packet.network_message = Some(reader.u8()?);
+        } else {
+            packet.apdu = Apdu::parse(reader.data);
+        }
+        Some(packet)
+    }
+
+    pub fn info(&self) -> String {
+        match (&self.apdu, self.network_message) {
+            (Some(apdu), _) => apdu.info(),
+            (None, Some(message)) => format!("Network message 0x{:02x}", message),
+            (None, None) => bvlc_function_name(self.bvlc_function).to_string(),
+        }
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("bvlc.function", self.bvlc_function);
+        if let Some(control) = self.npdu_control {
+            fields.insert("bacnet.control", control);
+        }
+        if let Some(network) = self.destination_network {
+            fields.insert("bacnet.dnet", network);
+        }
+        if let Some(network) = self.source_network {
+            fields.insert("bacnet.snet", network);
+        }
+        if let Some(message) = self.network_message {
+            fields.insert("bacnet.mesgtyp", message);
+        }
+        let Some(apdu) = &self.apdu else {
+            return;
+        };
+        fields.insert("bacapp.type", apdu.pdu_type);
+        if let Some(invoke_id) = apdu.invoke_id {
+            fields.insert("bacapp.invoke_id", invoke_id);
+        }
+        if let Some(service) = apdu.service {
+            let name = match apdu.pdu_type {
+                UNCONFIRMED_REQUEST => "bacapp.unconfirmed_service",
+                _ => "bacapp.confirmed_service",
+            };
+            fields.insert(name, service);
+        }
+        if let Some(object) = apdu.object {
+            fields.insert("bacapp.objectType", object.object_type);
+            fields.insert("bacapp.instance_number", object.instance);
+        }
+        if let Some(property) = apdu.property {
+            fields.insert("bacapp.property_identifier", property);
+        }
+        if let Some((class, code)) = apdu.error {
+            fields.insert("bacapp.error_class", class);
+            fields.insert("bacapp.error_code", code);
+        }
+        if let Some(reason) = apdu.reason {
+            fields.insert("bacapp.reason", reason);
+        }
+        if apdu.is_write() {
+            fields.insert("bacnet.write", true);
+        }
+    }
+
+    /// The error, reject or abort the packet reports.
+    pub fn error(&self) -> Option<String> {
+        let apdu = self.apdu.as_ref()?;
+        matches!(apdu.pdu_type, ERROR | REJECT | ABORT).then(|| {
+            format!(
+                "{}: {}",
+                pdu_type_name(apdu.pdu_type),
+                apdu.error_description().unwrap_or_default()
+            )
+        })
+    }
+
+    /// A warning about a request that changes the device.
+    pub fn warning(&self) -> Option<String> {
+        let apdu = self.apdu.as_ref()?;
+        apdu.is_write()
+            .then(|| format!("BACnet write: {}", apdu.info()))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// An Original-Unicast-NPDU without network addresses.
+    pub(crate) fn unicast(apdu: &[u8]) -> Vec<u8> {
+        let mut packet = vec![BVLC_TYPE, ORIGINAL_UNICAST_NPDU];
+        packet.extend_from_slice(&(apdu.len() as u16 + 6).to_be_bytes());
+        packet.extend_from_slice(&[1, 0x04]);
+        packet.extend_from_slice(apdu);
+        packet
+    }
+
+    #[test]
+    fn test_write_property() {
+        // writeProperty analog-output,1 present-value 42.0
+        let data = unicast(&[
+            0x00, 0x05, 7, 15, 0x0c, 0x00, 0x40, 0x00, 0x01, 0x19, 85, 0x3e, 0x44, 0x42, 0x28,
+            0x00, 0x00, 0x3f,
+        ]);
+        let packet = BacnetPacket::parse(&data).unwrap();
+        assert_eq!(
+            packet.info(),
+            "Confirmed-REQ writeProperty[7] analog-output,1 present-value"
+        );
+        assert_eq!(
+            packet.warning().unwrap(),
+            "BACnet write: Confirmed-REQ writeProperty[7] analog-output,1 present-value"
+        );
+        let mut fields = Fields::new();
+        packet.insert_fields(&mut fields);
+        assert!(fields.contains("bacnet.write"));
+
+        let ack = BacnetPacket::parse(&unicast(&[0x20, 7, 15])).unwrap();
+        assert_eq!(ack.info(), "SimpleACK writeProperty[7]");
+        assert!(ack.warning().is_none());
+    }
+
+    #[test]
+    fn test_i_am_and_errors() {
+        // Forwarded by a BBMD with the original sender's address
+        let mut data = vec![BVLC_TYPE, FORWARDED_NPDU, 0, 21];
+        data.extend_from_slice(&[192, 168, 1, 5, 0xba, 0xc0, 1, 0x20, 0xff, 0xff, 0, 0xff]);
+        data.extend_from_slice(&[0x10, I_AM, 0xc4, 0x02, 0x00, 0x04, 0xd2]);
+        data[3] = data.len() as u8;
+        let packet = BacnetPacket::parse(&data).unwrap();
+        assert_eq!(packet.info(), "Unconfirmed-REQ i-Am device,1234");
+        assert_eq!(packet.destination_network, Some(0xffff));
+
+        let data = unicast(&[0x50, 3, 12, 0x91, 2, 0x91, 32]);
+        let packet = BacnetPacket::parse(&data).unwrap();
+        assert_eq!(
+            packet.info(),
+            "Error readProperty[3]: property: unknown-property"
+        );
+        assert_eq!(packet.error().unwrap(), "Error: property: unknown-property");
+    }
+}
@@ -0,0 +1,681 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionState, ConnectionTable, Exchange};
+use crate::reassembly::{MessageBuffer, StreamData};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const MARKER: [u8; 16] = [0xff; 16];
+// Marker, length and type
+const HEADER_LEN: usize = 19;
+// RFC 8654 extended messages
+const MAX_MESSAGE_LEN: usize = 65535;
+// Prefixes listed in the info column
+const MAX_INFO_PREFIXES: usize = 4;
+
+const OPEN: u8 = 1;
+const UPDATE: u8 = 2;
+const NOTIFICATION: u8 = 3;
+const KEEPALIVE: u8 = 4;
+const ROUTE_REFRESH: u8 = 5;
+
+const CAPABILITIES_PARAMETER: u8 = 2;
+const FOUR_OCTET_AS: u8 = 65;
+// Placeholder for four-octet AS numbers in two-octet fields
+const AS_TRANS: u16 = 23456;
+
+// Path attributes
+const EXTENDED_LENGTH: u8 = 0x10;
+const ORIGIN: u8 = 1;
+const AS_PATH: u8 = 2;
+const NEXT_HOP: u8 = 3;
+const MULTI_EXIT_DISC: u8 = 4;
+const LOCAL_PREF: u8 = 5;
+const COMMUNITIES: u8 = 8;
+const MP_REACH_NLRI: u8 = 14;
+const MP_UNREACH_NLRI: u8 = 15;
+
+const AS_SET: u8 = 1;
+const AFI_IPV4: u16 = 1;
+const AFI_IPV6: u16 = 2;
+
+fn message_type_name(message_type: u8) -> &'static str {
+    match message_type {
+        OPEN => "OPEN",
+        UPDATE => "UPDATE",
+        NOTIFICATION => "NOTIFICATION",
+        KEEPALIVE => "KEEPALIVE",
+        ROUTE_REFRESH => "ROUTE-REFRESH",
+        _ => "Unknown",
+    }
+}
+
+fn capability_name(code: u8) -> &'static str {
+    match code {
+        1 => "Multiprotocol extensions",
+        2 => "Route refresh",
+        6 => "Extended message",
+        64 => "Graceful restart",
+        FOUR_OCTET_AS => "4-octet AS number",
+        69 => "ADD-PATH",
+        70 => "Enhanced route refresh",
+        71 => "Long-lived graceful restart",
+        73 => "FQDN",
+        128 => "Route refresh (Cisco)",
+        _ => "Unknown",
+    }
+}
+
+fn error_name(code: u8) -> &'static str {
+    match code {
+        1 => "Message Header Error",
+        2 => "OPEN Message Error",
+        3 => "UPDATE Message Error",
+        4 => "Hold Timer Expired",
+        5 => "Finite State Machine Error",
+        6 => "Cease",
+        7 => "ROUTE-REFRESH Message Error",
+        _ => "Unknown",
+    }
+}
+
+fn error_subcode_name(code: u8, subcode: u8) -> Option<&'static str> {
+    Some(match (code, subcode) {
+        (1, 1) => "Connection Not Synchronized",
+        (1, 2) => "Bad Message Length",
+        (1, 3) => "Bad Message Type",
+        (2, 1) => "Unsupported Version Number",
+        (2, 2) => "Bad Peer AS",
+        (2, 3) => "Bad BGP Identifier",
+        (2, 4) => "Unsupported Optional Parameter",
+        (2, 6) => "Unacceptable Hold Time",
+        (2, 7) => "Unsupported Capability",
+        (3, 1) => "Malformed Attribute List",
+        (3, 2) => "Unrecognized Well-known Attribute",
+        (3, 3) => "Missing Well-known Attribute",
+        (3, 4) => "Attribute Flags Error",
+        (3, 5) => "Attribute Length Error",
+        (3, 6) => "Invalid ORIGIN Attribute",
+        (3, 8) => "Invalid NEXT_HOP Attribute",
+        (3, 9) => "Optional Attribute Error",
+        (3, 10) => "Invalid Network Field",
+        (3, 11) => "Malformed AS_PATH",
+        (6, 1) => "Maximum Number of Prefixes Reached",
+        (6, 2) => "Administrative Shutdown",
+        (6, 3) => "Peer De-configured",
+        (6, 4) => "Administrative Reset",
+        (6, 5) => "Connection Rejected",
+        (6, 6) => "Other Configuration Change",
+        (6, 7) => "Connection Collision Resolution",
+        (6, 8) => "Out of Resources",
+        (6, 9) => "Hard Reset",
+        _ => return None,
+    })
+}
+
+fn origin_name(origin: u8) -> &'static str {
+    match origin {
+        0 => "IGP",
+        1 => "EGP",
+        _ => "INCOMPLETE",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Open {
+    pub version: u8,
+    /// The four-octet AS number when the speaker advertises one.
+    pub asn: u32,
+    pub hold_time: u16,
+    pub identifier: Ipv4Addr,
+    pub capabilities: Vec<u8>,
+}
+
+/// Routes withdrawn and announced by an UPDATE, with the path attributes
+/// of the announced ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Update {
+    pub withdrawn: Vec<String>,
+    pub nlri: Vec<String>,
+    pub origin: Option<u8>,
+    /// AS numbers in order, with sets in braces.
+    pub as_path: Option<String>,
+    pub next_hop: Option<IpAddr>,
+    pub med: Option<u32>,
+    pub local_pref: Option<u32>,
+    pub communities: Vec<String>,
+    /// Attribute type codes in order.
+    pub attributes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Open(Open),
+    Update(Update),
+    Notification { code: u8, subcode: u8 },
+    Keepalive,
+    RouteRefresh { afi: u16, safi: u8 },
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BgpMessage {
+    pub message_type: u8,
+    pub length: usize,
+    pub kind: Kind,
+}
+
+fn list_prefixes(prefixes: &[String]) -> String {
+    let mut list = prefixes
+        .iter()
+        .take(MAX_INFO_PREFIXES)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if prefixes.len() > MAX_INFO_PREFIXES {
+        list.push_str(&format!(" (+{} more)", prefixes.len() - MAX_INFO_PREFIXES));
+    }
+    list
+}
+
+impl AppMessage for BgpMessage {
+    const TIME_FIELD: &'static str = "bgp.response_time";
+
+    fn info(&self) -> String {
+        let name = format!("{} Message", message_type_name(self.message_type));
+        match &self.kind {
+            Kind::Open(open) => format!(
+                "{}, AS {}, hold time {}, ID {}",
+                name, open.asn, open.hold_time, open.identifier
+            ),
+            Kind::Update(update) if update.nlri.is_empty() && update.withdrawn.is_empty() => {
+                if update.attributes.is_empty() {
+                    format!("{}, End-of-RIB", name)
+                } else {
+                    name
+                }
+            }
+            Kind::Update(update) => {
+                let mut info = name;
+                if !update.nlri.is_empty() {
+                    info.push_str(&format!(", NLRI {}", list_prefixes(&update.nlri)));
+                    if let Some(next_hop) = update.next_hop {
+                        info.push_str(&format!(" via {}", next_hop));
+                    }
+                    if let Some(path) = update.as_path.as_ref().filter(|path| !path.is_empty()) {
+                        info.push_str(&format!(", AS path {}", path));
+                    }
+                }
+                if !update.withdrawn.is_empty() {
+                    info.push_str(&format!(", withdrawn {}", list_prefixes(&update.withdrawn)));
+                }
+                info
+            }
+            Kind::Notification { code, subcode } => match error_subcode_name(*code, *subcode) {
+                Some(subcode) => format!("{}, {} ({})", name, error_name(*code), subcode),
+                None => format!("{}, {}", name, error_name(*code)),
+            },
+            Kind::RouteRefresh { afi, safi } => format!("{}, AFI {} SAFI {}", name, afi, safi),
+            Kind::Keepalive | Kind::Unknown(_) => name,
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("bgp.type", self.message_type);
+        fields.insert("bgp.length", self.length);
+        match &self.kind {
+            Kind::Open(open) => {
+                fields.insert("bgp.open.version", open.version);
+                fields.insert("bgp.open.myas", open.asn);
+                fields.insert("bgp.open.holdtime", open.hold_time);
+                fields.insert("bgp.open.identifier", open.identifier);
+                for code in &open.capabilities {
+                    fields.insert("bgp.cap", capability_name(*code));
+                }
+            }
+            Kind::Update(update) => {
+                for prefix in &update.withdrawn {
+                    fields.insert("bgp.withdrawn_prefix", prefix.as_str());
+                }
+                for prefix in &update.nlri {
+                    fields.insert("bgp.nlri_prefix", prefix.as_str());
+                }
+                if let Some(origin) = update.origin {
+                    fields.insert("bgp.origin", origin_name(origin));
+                }
+                if let Some(path) = &update.as_path {
+                    fields.insert("bgp.as_path", path.as_str());
+                }
+                if let Some(next_hop) = update.next_hop {
+                    fields.insert("bgp.next_hop", next_hop);
+                }
+                if let Some(med) = update.med {
+                    fields.insert("bgp.multi_exit_disc", med);
+                }
+                if let Some(local_pref) = update.local_pref {
+                    fields.insert("bgp.local_pref", local_pref);
+                }
+                for community in &update.communities {
+                    fields.insert("bgp.community", community.as_str());
+                }
+            }
+            Kind::Notification { code, subcode } => {
+                fields.insert("bgp.notify.major_error", *code);
+                fields.insert("bgp.notify.minor_error", *subcode);
+            }
+            Kind::RouteRefresh { afi, safi } => {
+                fields.insert("bgp.route_refresh.afi", *afi);
+                fields.insert("bgp.route_refresh.safi", *safi);
+            }
+            Kind::Keepalive | Kind::Unknown(_) => {}
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match self.kind {
+            Kind::Notification { code, subcode } => Some(match error_subcode_name(code, subcode) {
+                Some(subcode) => format!("{}: {}", error_name(code), subcode),
+                None => error_name(code).to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(bytes)
+    }
+}
+
+/// Decodes prefixes encoded as a length in bits and the significant
+/// bytes of the address.
+fn prefixes(data: &[u8], v6: bool) -> Option<Vec<String>> {
+    let mut reader = Reader { data };
+    let mut prefixes = Vec::new();
+    let max_len = if v6 { 128 } else { 32 };
+    while let Some(bits) = reader.u8() {
+        if bits > max_len {
+            return None;
+        }
+        let bytes = reader.bytes(usize::from(bits).div_ceil(8))?;
+        let address = if v6 {
+            let mut octets = [0; 16];
+            octets[..bytes.len()].copy_from_slice(bytes);
+            IpAddr::V6(Ipv6Addr::from(octets))
+        } else {
+            let mut octets = [0; 4];
+            octets[..bytes.len()].copy_from_slice(bytes);
+            IpAddr::V4(Ipv4Addr::from(octets))
+        };
+        prefixes.push(format!("{}/{}", address, bits));
+    }
+    Some(prefixes)
+}
+
+/// Decodes AS_PATH segments with AS numbers of `width` bytes.
+fn as_path(data: &[u8], width: usize) -> Option<String> {
+    let mut reader = Reader { data };
+    let mut segments = Vec::new();
+    while let Some(segment_type) = reader.u8() {
+        let count = usize::from(reader.u8()?);
+        let numbers = (0..count)
+            .map(|_| match width {
+                4 => reader.u32(),
+                _ => reader.u16().map(u32::from),
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let numbers: Vec<_> = numbers.iter().map(|asn| asn.to_string()).collect();
+        if segment_type == AS_SET {
+            segments.push(format!("{{{}}}", numbers.join(",")));
+        } else {
+            segments.push(numbers.join(" "));
+        }
+    }
+    Some(segments.join(" "))
+}
+
+fn next_hop(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::new(
+            bytes[0], bytes[1], bytes[2], bytes[3],
+        ))),
+        // A global address, optionally followed by a link-local one
+        16 | 32 => Some(IpAddr::V6(Ipv6Addr::from(
+            <[u8; 16]>::try_from(&bytes[..16]).ok()?,
+        ))),
+        _ => None,
+    }
+}
+
+/// Decodes the routes of MP_REACH_NLRI or MP_UNREACH_NLRI for the
+/// unicast and multicast address families; others are left out.
+fn mp_prefixes(afi: u16, safi: u8, data: &[u8]) -> Vec<String> {
+    match (afi, safi) {
+        (AFI_IPV4, 1 | 2) => prefixes(data, false).unwrap_or_default(),
+        (AFI_IPV6, 1 | 2) => prefixes(data, true).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+impl Update {
+    /// Decodes an UPDATE body. Four-octet AS numbers in AS_PATH are used
+    /// when both speakers advertised them, or when unknown and the
+    /// attribute only parses that way.
+    fn parse(data: &[u8], four_octet: Option<bool>) -> Option<Self> {
+        let mut reader = Reader { data };
+        let withdrawn_len = usize::from(reader.u16()?);
+        let mut update = Update {
+            withdrawn: prefixes(reader.bytes(withdrawn_len)?, false)?,
+            ..Update::default()
+        };
+        let attributes_len = usize::from(reader.u16()?);
+        let mut attributes = Reader {
+            data: reader.bytes(attributes_len)?,
+        };
+        update.nlri = prefixes(reader.data, false)?;
+
+        while let Some(flags) = attributes.u8() {
+            let code = attributes.u8()?;
+            let len = if flags & EXTENDED_LENGTH != 0 {
+                usize::from(attributes.u16()?)
+            } else {
+                usize::from(attributes.u8()?)
+            };
+            let value = attributes.bytes(len)?;
+            update.attributes.push(code);
+            let mut value_reader = Reader { data: value };
+            match code {
+                ORIGIN => update.origin = value.first().copied(),
+                AS_PATH => {
+                    update.as_path = match four_octet {
+                        Some(true) => as_path(value, 4),
+                        Some(false) => as_path(value, 2),
+                        None => as_path(value, 4).or_else(|| as_path(value, 2)),
+                    }
+                }
+                NEXT_HOP => update.next_hop = next_hop(value),
+                MULTI_EXIT_DISC => update.med = value_reader.u32(),
+                LOCAL_PREF => update.local_pref = value_reader.u32(),
+                COMMUNITIES => {
+                    while let (Some(high), Some(low)) = (value_reader.u16(), value_reader.u16()) {
+                        update.communities.push(format!("{}:{}", high, low));
+                    }
+                }
+                MP_REACH_NLRI => {
+                    let afi = value_reader.u16()?;
+                    let safi = value_reader.u8()?;
+                    let next_hop_len = usize::from(value_reader.u8()?);
+                    update.next_hop = next_hop(value_reader.bytes(next_hop_len)?);
+                    // Reserved
+                    value_reader.u8()?;
+                    update
+                        .nlri
+                        .extend(mp_prefixes(afi, safi, value_reader.data));
+                }
+                MP_UNREACH_NLRI => {
+                    let afi = value_reader.u16()?;
+                    let safi = value_reader.u8()?;
+                    update
+                        .withdrawn
+                        .extend(mp_prefixes(afi, safi, value_reader.data));
+                }
+                _ => {}
+            }
+        }
+        Some(update)
+    }
+}
+
+impl Open {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let mut reader = Reader { data };
+        let version = reader.u8()?;
+        let my_as = reader.u16()?;
+        let hold_time = reader.u16()?;
+        let identifier = Ipv4Addr::from(reader.u32()?);
+        let params_len = usize::from(reader.u8()?);
+        let mut params = Reader {
+            data: reader.bytes(params_len)?,
+        };
+        let mut open = Open {
+            version,
+            asn: u32::from(my_as),
+            hold_time,
+            identifier,
+            capabilities: Vec::new(),
+        };
+        while let (Some(param_type), Some(len)) = (params.u8(), params.u8()) {
+            let value = params.bytes(usize::from(len))?;
+            if param_type != CAPABILITIES_PARAMETER {
+                continue;
+            }
+            let mut capabilities = Reader { data: value };
+            while let (Some(code), Some(len)) = (capabilities.u8(), capabilities.u8()) {
+                let value = capabilities.bytes(usize::from(len))?;
+                open.capabilities.push(code);
+                if code == FOUR_OCTET_AS && my_as == AS_TRANS {
+                    open.asn = Reader { data: value }.u32()?;
+                }
+            }
+        }
+        Some(open)
+    }
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// Whether each side's OPEN advertised four-octet AS numbers.
+    four_octet: [Option<bool>; 2],
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+}
+
+/// Splits reassembled BGP sessions into messages, remembering the
+/// capabilities each speaker advertised.
+#[derive(Debug, Default)]
+pub struct BgpTracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl BgpTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the messages `stream` completes.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+    ) -> Exchange<BgpMessage> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let side = usize::from(!from_client);
+        let (buffer, connection) = connection.split(from_client);
+        buffer.push(&stream.data);
+        while let Some(header) = buffer.data().get(..HEADER_LEN) {
+            let length = usize::from(u16::from_be_bytes([header[16], header[17]]));
+            if header[..16] != MARKER || !(HEADER_LEN..=MAX_MESSAGE_LEN).contains(&length) {
+                // Not at a message boundary, e.g. after missing data
+                buffer.clear();
+                break;
+            }
+            let Some(message) = buffer.take(length) else {
+                break;
+            };
+            let message_type = message[18];
+            let body = &message[HEADER_LEN..];
+            let four_octet = match connection.four_octet {
+                [Some(client), Some(server)] => Some(client && server),
+                _ => None,
+            };
+            let kind = match message_type {
+                OPEN => Open::parse(body).map(|open| {
+                    connection.four_octet[side] = Some(open.capabilities.contains(&FOUR_OCTET_AS));
+                    Kind::Open(open)
+                }),
+                UPDATE => Update::parse(body, four_octet).map(Kind::Update),
+                NOTIFICATION => match body {
+                    [code, subcode, ..] => Some(Kind::Notification {
+                        code: *code,
+                        subcode: *subcode,
+                    }),
+                    _ => None,
+                },
+                KEEPALIVE => Some(Kind::Keepalive),
+                ROUTE_REFRESH => match body {
+                    [afi_high, afi_low, _, safi, ..] => Some(Kind::RouteRefresh {
+                        afi: u16::from_be_bytes([*afi_high, *afi_low]),
+                        safi: *safi,
+                    }),
+                    _ => None,
+                },
+                _ => Some(Kind::Unknown(message_type)),
+            };
+            exchange.messages.push(BgpMessage {
+                message_type,
+                length,
+                kind: kind.unwrap_or(Kind::Unknown(message_type)),
+            });
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    pub(crate) fn message(message_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut data = MARKER.to_vec();
+        data.extend_from_slice(&((HEADER_LEN + body.len()) as u16).to_be_bytes());
+        data.push(message_type);
+        data.extend_from_slice(body);
+        data
+    }
+
+    /// An OPEN from AS 4200000000 advertising four-octet AS numbers.
+    fn open() -> Vec<u8> {
+        let mut body = vec![4];
+        body.extend_from_slice(&AS_TRANS.to_be_bytes());
+        body.extend_from_slice(&[0, 90, 10, 0, 0, 1]);
+        let capabilities = [1, 4, 0, 1, 0, 1, FOUR_OCTET_AS, 4, 0xfa, 0x56, 0xea, 0x00];
+        body.push(capabilities.len() as u8 + 2);
+        body.extend_from_slice(&[CAPABILITIES_PARAMETER, capabilities.len() as u8]);
+        body.extend_from_slice(&capabilities);
+        message(OPEN, &body)
+    }
+
+    #[test]
+    fn test_open_and_update() {
+        let mut tracker = BgpTracker::new();
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&open()));
+        assert_eq!(
+            exchange.messages[0].info(),
+            "OPEN Message, AS 4200000000, hold time 90, ID 10.0.0.1"
+        );
+        let mut data = open();
+        data.extend(message(KEEPALIVE, &[]));
+        let exchange = tracker.process(CLIENT, SERVER, false, &StreamData::complete(&data));
+        assert_eq!(exchange.messages[1].info(), "KEEPALIVE Message");
+
+        let mut attributes = vec![0x40, ORIGIN, 1, 0];
+        attributes
+            .extend_from_slice(&[0x40, AS_PATH, 10, 2, 2, 0, 0, 0xfd, 0xe9, 0, 0, 0xfd, 0xea]);
+        attributes.extend_from_slice(&[0x40, NEXT_HOP, 4, 192, 0, 2, 1]);
+        attributes.extend_from_slice(&[0xc0, COMMUNITIES, 4, 0xfd, 0xe9, 0, 100]);
+        let mut body = vec![0, 3, 16, 192, 168];
+        body.extend_from_slice(&(attributes.len() as u16).to_be_bytes());
+        body.extend_from_slice(&attributes);
+        body.extend_from_slice(&[24, 10, 1, 2, 8, 10]);
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(&message(UPDATE, &body)),
+        );
+        let update = &exchange.messages[0];
+        assert_eq!(
+            update.info(),
+            "UPDATE Message, NLRI 10.1.2.0/24 10.0.0.0/8 via 192.0.2.1, AS path 65001 65002, \
+             withdrawn 192.168.0.0/16"
+        );
+        let mut fields = Fields::new();
+        update.insert_fields(&mut fields);
+        assert_eq!(fields.get("bgp.community"), Some(&"65001:100".into()));
+        assert_eq!(fields.get("bgp.origin"), Some(&"IGP".into()));
+
+        let end_of_rib = message(UPDATE, &[0, 0, 0, 0]);
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&end_of_rib));
+        assert_eq!(exchange.messages[0].info(), "UPDATE Message, End-of-RIB");
+    }
+
+    #[test]
+    fn test_notification_and_ipv6_routes() {
+        let mut tracker = BgpTracker::new();
+        let mut reach = vec![0x80, MP_REACH_NLRI, 0];
+        let mut value = vec![0, 2, 1, 16];
+        value.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        value.extend_from_slice(&[0, 32, 0x20, 0x01, 0x0d, 0xb8]);
+        reach[2] = value.len() as u8;
+        reach.extend(value);
+        let mut body = vec![0, 0];
+        body.extend_from_slice(&(reach.len() as u16).to_be_bytes());
+        body.extend(reach);
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(&message(UPDATE, &body)),
+        );
+        assert_eq!(
+            exchange.messages[0].info(),
+            "UPDATE Message, NLRI 2001:db8::/32 via 2001:db8::1"
+        );
+
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(&message(NOTIFICATION, &[6, 2])),
+        );
+        assert_eq!(
+            exchange.messages[0].info(),
+            "NOTIFICATION Message, Cease (Administrative Shutdown)"
+        );
+        assert_eq!(
+            exchange.messages[0].error().unwrap(),
+            "Cease: Administrative Shutdown"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+// Upper bound on tracked addresses so a long capture can't grow without limit
+const MAX_BINDINGS: usize = 65536;
+
+/// Remembers which MAC address announced each IP address in ARP and
+/// Neighbor Discovery traffic, to spot spoofing and address conflicts.
+#[derive(Debug, Default)]
+pub struct BindingTracker {
+    bindings: HashMap<IpAddr, [u8; 6]>,
+}
+
+impl BindingTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `ip` is at `mac`. Returns the previous MAC address if
+    /// the binding changed.
+    pub fn observe(&mut self, ip: IpAddr, mac: [u8; 6]) -> Option<[u8; 6]> {
+        if ip.is_unspecified() || mac == [0; 6] || mac == [0xff; 6] {
+            return None;
+        }
+        if self.bindings.len() >= MAX_BINDINGS && !self.bindings.contains_key(&ip) {
+            self.bindings.clear();
+        }
+
+        match self.bindings.insert(ip, mac) {
+            Some(previous) if previous != mac => Some(previous),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, ip: &IpAddr) -> Option<&[u8; 6]> {
+        self.bindings.get(ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binding_change() {
+        let mut tracker = BindingTracker::new();
+        let ip = IpAddr::from([10, 0, 0, 1]);
+
+        assert_eq!(tracker.observe(ip, [1; 6]), None);
+        assert_eq!(tracker.observe(ip, [1; 6]), None);
+        assert_eq!(tracker.observe(ip, [2; 6]), Some([1; 6]));
+        assert_eq!(tracker.get(&ip), Some(&[2; 6]));
+
+        // Probes without a sender address are not bindings
+        assert_eq!(tracker.observe(IpAddr::from([0, 0, 0, 0]), [3; 6]), None);
+    }
+}
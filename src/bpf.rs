@@ -0,0 +1,297 @@
+use crate::filters::FilterError;
+use pcap::{Capture, Linktype};
+use std::fmt::Write;
+
+// Continuations tried after a prefix of a filter to tell whether it could
+// still become a valid one
+const CONTINUATIONS: &[&str] = &["", " 1", " tcp", " 10.0.0.1"];
+
+/// One instruction of a compiled capture filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl Instruction {
+    /// Reads libpcap's `code jt jf k` rendering of an instruction.
+    fn from_pcap(instruction: &pcap::BpfInstruction) -> Option<Self> {
+        let text = instruction.to_string();
+        let mut parts = text.split_whitespace();
+        Some(Instruction {
+            code: parts.next()?.parse().ok()?,
+            jt: parts.next()?.parse().ok()?,
+            jf: parts.next()?.parse().ok()?,
+            k: parts.next()?.parse().ok()?,
+        })
+    }
+
+    /// The mnemonic and operand of the instruction at `index`, as
+    /// `tcpdump -d` prints them.
+    fn image(&self, index: usize) -> (&'static str, String) {
+        let k = self.k;
+        let signed = k as i32;
+        match self.code {
+            0x06 => ("ret", format!("#{}", signed)),
+            0x16 => ("ret", String::new()),
+            0x0e => ("ret", "x".to_string()),
+            0x20 => ("ld", format!("[{}]", signed)),
+            0x28 => ("ldh", format!("[{}]", signed)),
+            0x30 => ("ldb", format!("[{}]", signed)),
+            0x80 => ("ld", "#pktlen".to_string()),
+            0x40 => ("ld", format!("[x + {}]", signed)),
+            0x48 => ("ldh", format!("[x + {}]", signed)),
+            0x50 => ("ldb", format!("[x + {}]", signed)),
+            0x00 => ("ld", format!("#0x{:x}", k)),
+            0x01 => ("ldx", format!("#0x{:x}", k)),
+            0x81 => ("ldx", "#pktlen".to_string()),
+            0xb1 => ("ldxb", format!("4*([{}]&0xf)", signed)),
+            0x60 => ("ld", format!("M[{}]", signed)),
+            0x61 => ("ldx", format!("M[{}]", signed)),
+            0x02 => ("st", format!("M[{}]", signed)),
+            0x03 => ("stx", format!("M[{}]", signed)),
+            0x05 => ("ja", (index + 1 + k as usize).to_string()),
+            0x25 => ("jgt", format!("#0x{:x}", k)),
+            0x35 => ("jge", format!("#0x{:x}", k)),
+            0x15 => ("jeq", format!("#0x{:x}", k)),
+            0x45 => ("jset", format!("#0x{:x}", k)),
+            0x2d => ("jgt", "x".to_string()),
+            0x3d => ("jge", "x".to_string()),
+            0x1d => ("jeq", "x".to_string()),
+            0x4d => ("jset", "x".to_string()),
+            0x84 => ("neg", String::new()),
+            0x07 => ("tax", String::new()),
+            0x87 => ("txa", String::new()),
+            code if code & 0x07 == 0x04 => {
+                let op = match code & 0xf0 {
+                    0x00 => "add",
+                    0x10 => "sub",
+                    0x20 => "mul",
+                    0x30 => "div",
+                    0x40 => "or",
+                    0x50 => "and",
+                    0x60 => "lsh",
+                    0x70 => "rsh",
+                    0x90 => "mod",
+                    0xa0 => "xor",
+                    _ => return ("unimp", format!("0x{:x}", code)),
+                };
+                let operand = match (code & 0x08, op) {
+                    (0x08, _) => "x".to_string(),
+                    (_, "or" | "and" | "xor") => format!("#0x{:x}", k),
+                    _ => format!("#{}", signed),
+                };
+                (op, operand)
+            }
+            code => ("unimp", format!("0x{:x}", code)),
+        }
+    }
+
+    /// Conditional jumps, whose targets are given by `jt` and `jf`.
+    fn is_branch(&self) -> bool {
+        self.code & 0x07 == 0x05 && self.code & 0xf0 != 0x00
+    }
+}
+
+/// Reads a link type given by name, e.g. `EN10MB` or `LINUX_SLL`, or by
+/// number.
+pub fn parse_linktype(text: &str) -> Result<Linktype, String> {
+    if let Ok(number) = text.parse() {
+        return Ok(Linktype(number));
+    }
+    Linktype::from_name(&text.to_ascii_uppercase())
+        .map_err(|_| format!("unknown link type '{}'", text))
+}
+
+/// Compiles a capture filter for packets of `linktype` without opening a
+/// device, so no capture permissions are needed.
+pub fn compile(expression: &str, linktype: Linktype) -> Result<Vec<Instruction>, FilterError> {
+    let capture = Capture::dead(linktype).map_err(|e| FilterError {
+        column: 1,
+        message: e.to_string(),
+    })?;
+    match capture.compile(expression, true) {
+        Ok(program) => Ok(program
+            .get_instructions()
+            .iter()
+            .filter_map(Instruction::from_pcap)
+            .collect()),
+        Err(e) => {
+            let message = match e {
+                pcap::Error::PcapError(message) => message
+                    .trim_start_matches("can't parse filter expression: ")
+                    .to_string(),
+                e => e.to_string(),
+            };
+            let column = locate_error(expression, &message, |text| {
+                capture.compile(text, true).is_ok()
+            });
+            Err(FilterError { column, message })
+        }
+    }
+}
+
+/// Prints a compiled filter the way `tcpdump -d` does.
+pub fn dump(program: &[Instruction]) -> String {
+    let mut output = String::new();
+    for (index, instruction) in program.iter().enumerate() {
+        let (op, operand) = instruction.image(index);
+        let line = if instruction.is_branch() {
+            format!(
+                "({:03}) {:<8} {:<16} jt {}\tjf {}",
+                index,
+                op,
+                operand,
+                index + 1 + usize::from(instruction.jt),
+                index + 1 + usize::from(instruction.jf)
+            )
+        } else {
+            format!("({:03}) {:<8} {}", index, op, operand)
+        };
+        let _ = writeln!(output, "{}", line.trim_end());
+    }
+    output
+}
+
+/// The words of a capture filter with the byte offsets they start and end
+/// at. Parentheses are words of their own.
+fn words(expression: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (offset, c) in expression.char_indices() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if let Some(start) = start.take() {
+                words.push((start, offset));
+            }
+            if c == '(' || c == ')' {
+                words.push((offset, offset + 1));
+            }
+        } else if start.is_none() {
+            start = Some(offset);
+        }
+    }
+    if let Some(start) = start {
+        words.push((start, expression.len()));
+    }
+    words
+}
+
+/// Finds the column (counting from 1) of the word a filter went wrong at.
+/// libpcap quotes the word it couldn't resolve, e.g. `unknown host 'foo'`;
+/// for syntax errors this is the first word that no continuation can turn
+/// into a valid filter. A filter that is only missing its end is reported
+/// just past it.
+fn locate_error(expression: &str, message: &str, compiles: impl Fn(&str) -> bool) -> usize {
+    let column = |offset: usize| expression[..offset].chars().count() + 1;
+    let words = words(expression);
+
+    let quoted = message.split('\'').nth(1).filter(|word| !word.is_empty());
+    if let Some(quoted) = quoted {
+        if let Some((start, _)) = words
+            .iter()
+            .find(|(start, end)| expression[*start..*end].contains(quoted))
+        {
+            return column(*start);
+        }
+    }
+
+    for (start, end) in &words {
+        let prefix = &expression[..*end];
+        let open = prefix.matches('(').count();
+        let closed = prefix.matches(')').count();
+        let viable = open >= closed
+            && CONTINUATIONS.iter().any(|continuation| {
+                compiles(&format!(
+                    "{}{}{}",
+                    prefix,
+                    continuation,
+                    ")".repeat(open - closed)
+                ))
+            });
+        if !viable {
+            return column(*start);
+        }
+    }
+    expression.chars().count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(code: u16, jt: u8, jf: u8, k: u32) -> Instruction {
+        Instruction { code, jt, jf, k }
+    }
+
+    #[test]
+    fn test_dump_matches_tcpdump() {
+        // tcpdump -d "ip"
+        let program = [
+            instruction(0x28, 0, 0, 12),
+            instruction(0x15, 0, 1, 0x800),
+            instruction(0x06, 0, 0, 262144),
+            instruction(0x06, 0, 0, 0),
+        ];
+        assert_eq!(
+            dump(&program),
+            "(000) ldh      [12]\n\
+             (001) jeq      #0x800           jt 2\tjf 3\n\
+             (002) ret      #262144\n\
+             (003) ret      #0\n"
+        );
+
+        let program = [
+            instruction(0xb1, 0, 0, 14),
+            instruction(0x50, 0, 0, 27),
+            instruction(0x54, 0, 0, 0x12),
+            instruction(0x05, 0, 0, 1),
+            instruction(0x16, 0, 0, 0),
+        ];
+        assert_eq!(
+            dump(&program),
+            "(000) ldxb     4*([14]&0xf)\n\
+             (001) ldb      [x + 27]\n\
+             (002) and      #0x12\n\
+             (003) ja       5\n\
+             (004) ret\n"
+        );
+    }
+
+    #[test]
+    fn test_locate_error() {
+        // Primitives joined by `and`, standing in for libpcap
+        let compiles = |text: &str| {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            words.len() % 2 == 1
+                && words
+                    .iter()
+                    .enumerate()
+                    .all(|(i, word)| (i % 2 == 1) == (*word == "and"))
+        };
+        assert_eq!(locate_error("tcp and and udp", "syntax error", compiles), 9);
+        assert_eq!(locate_error("tcp and", "syntax error", compiles), 8);
+        assert_eq!(locate_error("tcp udp", "syntax error", compiles), 5);
+        assert_eq!(
+            locate_error("tcp and host foo", "unknown host 'foo'", compiles),
+            14
+        );
+    }
+
+    #[test]
+    fn test_compile_error_column() {
+        let err = compile("tcp port 80 and and udp", Linktype::ETHERNET).unwrap_err();
+        assert_eq!(err.column, 17);
+    }
+
+    #[test]
+    fn test_words() {
+        let text = "(tcp port 80)\tor udp";
+        let words: Vec<&str> = words(text)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect();
+        assert_eq!(words, vec!["(", "tcp", "port", "80", ")", "or", "udp"]);
+    }
+}
@@ -1,43 +1,47 @@
+use crate::analyzer::Analyzer;
+use crate::bpf;
+use crate::export::ExportedObject;
+use crate::fields::Fields;
+use crate::link::LinkType;
 use crate::ui::PacketInfo;
-use chrono::Utc;
-use etherparse::{InternetSlice, SlicedPacket, TransportSlice};
-use pcap::{Capture, Device};
+use chrono::{DateTime, Utc};
+use pcap::{Activated, Capture, Device};
 use std::error::Error;
 use tokio::sync::mpsc;
 
-fn get_protocol_name(packet: &SlicedPacket) -> String {
-    match &packet.transport {
-        Some(TransportSlice::Tcp(_)) => "TCP".to_string(),
-        Some(TransportSlice::Udp(_)) => "UDP".to_string(),
-        Some(TransportSlice::Icmpv4(_)) => "ICMPv4".to_string(),
-        Some(TransportSlice::Icmpv6(_)) => "ICMPv6".to_string(),
-        Some(TransportSlice::Unknown(_)) => "Unknown".to_string(),
-        None => "Other".to_string(),
-    }
+/// Converts the pcap header timestamp, falling back to the current time.
+#[allow(clippy::unnecessary_cast)] // time_t is not i64 on every platform
+fn packet_timestamp(header: &pcap::PacketHeader) -> DateTime<Utc> {
+    DateTime::from_timestamp(header.ts.tv_sec as i64, header.ts.tv_usec as u32 * 1000)
+        .unwrap_or_else(Utc::now)
 }
 
-fn get_packet_info(packet: &SlicedPacket) -> String {
-    match &packet.transport {
-        Some(TransportSlice::Tcp(tcp)) => {
-            let flags = vec![
-                if tcp.syn() { "SYN" } else { "" },
-                if tcp.ack() { "ACK" } else { "" },
-                if tcp.fin() { "FIN" } else { "" },
-                if tcp.rst() { "RST" } else { "" },
-                if tcp.psh() { "PSH" } else { "" },
-                if tcp.urg() { "URG" } else { "" },
-            ];
-            flags
-                .into_iter()
-                .filter(|s| !s.is_empty())
-                .collect::<Vec<_>>()
-                .join(" ")
-        }
-        Some(TransportSlice::Udp(_)) => "UDP Datagram".to_string(),
-        Some(TransportSlice::Icmpv4(_)) => "ICMP Message".to_string(),
-        Some(TransportSlice::Icmpv6(_)) => "ICMPv6 Message".to_string(),
-        Some(TransportSlice::Unknown(_)) => "Unknown Protocol".to_string(),
-        None => "".to_string(),
+/// Applies a capture filter. A bad one is compiled again on its own to
+/// report the column it goes wrong at.
+fn apply_filter<T: Activated + ?Sized>(
+    cap: &mut Capture<T>,
+    filter: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let Err(e) = cap.filter(filter, true) else {
+        return Ok(());
+    };
+    bpf::compile(filter, cap.get_datalink())?;
+    Err(e.into())
+}
+
+/// A captured packet, before analysis.
+fn packet_info(packet: &pcap::Packet) -> PacketInfo {
+    PacketInfo {
+        timestamp: packet_timestamp(packet.header),
+        source: String::new(),
+        destination: String::new(),
+        protocol: String::new(),
+        length: packet.len(),
+        info: String::new(),
+        raw_data: packet.to_vec(),
+        fields: Fields::new(),
+        expert: Vec::new(),
+        objects: Vec::new(),
     }
 }
 
@@ -45,6 +49,7 @@ pub async fn start_capture(
     interface: Option<String>,
     filter: Option<String>,
     output: Option<String>,
+    analyzer: Analyzer,
     mut shutdown_rx: mpsc::Receiver<()>,
     packet_tx: mpsc::Sender<PacketInfo>,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
@@ -67,8 +72,9 @@ pub async fn start_capture(
 
     // Apply filter if specified
     if let Some(filter) = filter {
-        cap.filter(&filter, true)?;
+        apply_filter(&mut cap, &filter)?;
     }
+    let mut analyzer = analyzer.with_link_type(LinkType::from_dlt(cap.get_datalink().0));
 
     // Create pcap writer if output specified
     let mut pcap_writer = if let Some(path) = output {
@@ -101,66 +107,15 @@ pub async fn start_capture(
                         println!("Wrote packet: {} bytes", packet.len());
                     }
 
-                    // Parse packet for UI
-                    if let Ok(parsed) = SlicedPacket::from_ethernet(packet.data) {
-                        let protocol = get_protocol_name(&parsed);
-                        let info = get_packet_info(&parsed);
-                        let (source, destination) = match &parsed.ip {
-                            Some(InternetSlice::Ipv4(ref header, _)) => match &parsed.transport {
-                                Some(TransportSlice::Tcp(tcp)) => (
-                                    format!("{}:{}", header.source_addr(), tcp.source_port()),
-                                    format!("{}:{}", header.destination_addr(), tcp.destination_port()),
-                                ),
-                                Some(TransportSlice::Udp(udp)) => (
-                                    format!("{}:{}", header.source_addr(), udp.source_port()),
-                                    format!("{}:{}", header.destination_addr(), udp.destination_port()),
-                                ),
-                                Some(TransportSlice::Unknown(_)) => (
-                                    header.source_addr().to_string(),
-                                    header.destination_addr().to_string(),
-                                ),
-                                _ => (
-                                    header.source_addr().to_string(),
-                                    header.destination_addr().to_string(),
-                                ),
-                            },
-                            Some(InternetSlice::Ipv6(ref header, _)) => match &parsed.transport {
-                                Some(TransportSlice::Tcp(tcp)) => (
-                                    format!("{}:{}", header.source_addr(), tcp.source_port()),
-                                    format!("{}:{}", header.destination_addr(), tcp.destination_port()),
-                                ),
-                                Some(TransportSlice::Udp(udp)) => (
-                                    format!("{}:{}", header.source_addr(), udp.source_port()),
-                                    format!("{}:{}", header.destination_addr(), udp.destination_port()),
-                                ),
-                                Some(TransportSlice::Unknown(_)) => (
-                                    header.source_addr().to_string(),
-                                    header.destination_addr().to_string(),
-                                ),
-                                _ => (
-                                    header.source_addr().to_string(),
-                                    header.destination_addr().to_string(),
-                                ),
-                            },
-                            None => ("Unknown".to_string(), "Unknown".to_string()),
-                        };
-
-                        let packet_info = PacketInfo {
-                            timestamp: Utc::now(),
-                            source,
-                            destination,
-                            protocol,
-                            length: packet.len(),
-                            info,
-                            raw_data: packet.to_vec(),
-                        };
-
-                        if packet_tx.send(packet_info).await.is_err() {
-                            if let Some(writer) = pcap_writer.as_mut() {
-                                writer.flush()?;
-                            }
-                            break;
+                    // Analyze packet for UI
+                    let mut packet_info = packet_info(&packet);
+                    analyzer.analyze(&mut packet_info);
+
+                    if packet_tx.send(packet_info).await.is_err() {
+                        if let Some(writer) = pcap_writer.as_mut() {
+                            writer.flush()?;
                         }
+                        break;
                     }
                 }
                 Ok::<_, Box<dyn Error + Send + Sync>>(())
@@ -177,6 +132,65 @@ pub async fn start_capture(
     Ok(())
 }
 
+/// Analyzes the packets of a capture file, decoding them after the file's
+/// link type.
+pub async fn read_capture_file(
+    path: String,
+    filter: Option<String>,
+    analyzer: Analyzer,
+    packet_tx: mpsc::Sender<PacketInfo>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut cap = Capture::from_file(&path)?;
+    if let Some(filter) = filter {
+        apply_filter(&mut cap, &filter)?;
+    }
+    let mut analyzer = analyzer.with_link_type(LinkType::from_dlt(cap.get_datalink().0));
+
+    loop {
+        let packet = match cap.next_packet() {
+            Ok(packet) => packet,
+            Err(pcap::Error::NoMorePackets) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let mut packet_info = packet_info(&packet);
+        analyzer.analyze(&mut packet_info);
+        if packet_tx.send(packet_info).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Analyzes a capture file and returns the files sent over HTTP and FTP in
+/// it.
+pub fn extract_objects(
+    path: &str,
+    filter: Option<String>,
+    analyzer: Analyzer,
+) -> Result<Vec<ExportedObject>, Box<dyn Error + Send + Sync>> {
+    let mut cap = Capture::from_file(path)?;
+    if let Some(filter) = filter {
+        apply_filter(&mut cap, &filter)?;
+    }
+    let mut analyzer = analyzer
+        .with_link_type(LinkType::from_dlt(cap.get_datalink().0))
+        .with_object_export(true);
+
+    let mut objects = Vec::new();
+    loop {
+        let packet = match cap.next_packet() {
+            Ok(packet) => packet,
+            Err(pcap::Error::NoMorePackets) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let mut packet_info = packet_info(&packet);
+        analyzer.analyze(&mut packet_info);
+        objects.append(&mut packet_info.objects);
+    }
+    objects.extend(analyzer.finish_objects());
+    Ok(objects)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -203,10 +217,9 @@ mod tests {
         let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>(1);
         let (packet_tx, _packet_rx) = mpsc::channel::<PacketInfo>(1000);
 
-        let capture_handle =
-            tokio::spawn(
-                async move { start_capture(None, None, None, shutdown_rx, packet_tx).await },
-            );
+        let capture_handle = tokio::spawn(async move {
+            start_capture(None, None, None, Analyzer::new(), shutdown_rx, packet_tx).await
+        });
 
         shutdown_tx
             .send(())
@@ -236,7 +249,15 @@ mod tests {
                 let (packet_tx, _packet_rx) = mpsc::channel::<PacketInfo>(1000);
 
                 let capture_handle = tokio::spawn(async move {
-                    start_capture(Some(dev_name), None, None, shutdown_rx, packet_tx).await
+                    start_capture(
+                        Some(dev_name),
+                        None,
+                        None,
+                        Analyzer::new(),
+                        shutdown_rx,
+                        packet_tx,
+                    )
+                    .await
                 });
 
                 shutdown_tx
@@ -266,7 +287,15 @@ mod tests {
         let (packet_tx, _packet_rx) = mpsc::channel::<PacketInfo>(1000);
 
         let capture_handle = tokio::spawn(async move {
-            start_capture(None, Some("tcp".to_string()), None, shutdown_rx, packet_tx).await
+            start_capture(
+                None,
+                Some("tcp".to_string()),
+                None,
+                Analyzer::new(),
+                shutdown_rx,
+                packet_tx,
+            )
+            .await
         });
 
         shutdown_tx
@@ -292,6 +321,7 @@ mod tests {
             Some("invalid_device".to_string()),
             None,
             None,
+            Analyzer::new(),
             shutdown_rx,
             packet_tx,
         )
@@ -309,6 +339,7 @@ mod tests {
             None,
             Some("invalid filter syntax".to_string()),
             None,
+            Analyzer::new(),
             shutdown_rx,
             packet_tx,
         )
@@ -316,4 +347,19 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_missing_capture_file() {
+        let (packet_tx, _packet_rx) = mpsc::channel::<PacketInfo>(1000);
+
+        let result = read_capture_file(
+            "/nonexistent/capture.pcap".to_string(),
+            None,
+            Analyzer::new(),
+            packet_tx,
+        )
+        .await;
+
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,124 @@
+use crate::fields::Fields;
+
+/// SNAP organization and protocol ID of CDP.
+pub const OUI_CISCO: u32 = 0x00000c;
+pub const PID_CDP: u16 = 0x2000;
+
+const TLV_DEVICE_ID: u16 = 0x0001;
+const TLV_PORT_ID: u16 = 0x0003;
+const TLV_SOFTWARE_VERSION: u16 = 0x0005;
+const TLV_PLATFORM: u16 = 0x0006;
+const TLV_NATIVE_VLAN: u16 = 0x000a;
+
+/// A Cisco Discovery Protocol packet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CdpPacket {
+    pub version: u8,
+    pub ttl: u8,
+    pub device_id: Option<String>,
+    pub port_id: Option<String>,
+    pub software_version: Option<String>,
+    pub platform: Option<String>,
+    pub native_vlan: Option<u16>,
+}
+
+impl CdpPacket {
+    /// Parses the CDP header and TLVs following the SNAP header.
+    pub fn parse(payload: &[u8]) -> Option<CdpPacket> {
+        let header = payload.get(..4)?;
+        if !matches!(header[0], 1 | 2) {
+            return None;
+        }
+        let mut packet = CdpPacket {
+            version: header[0],
+            ttl: header[1],
+            ..CdpPacket::default()
+        };
+
+        // TLVs with a 16 bit type and a 16 bit length including the header
+        let mut rest = &payload[4..];
+        while rest.len() >= 4 {
+            let tlv_type = u16::from_be_bytes([rest[0], rest[1]]);
+            let len = usize::from(u16::from_be_bytes([rest[2], rest[3]]));
+            if len < 4 || len > rest.len() {
+                break;
+            }
+            let value = &rest[4..len];
+            rest = &rest[len..];
+
+            let text = || {
+                String::from_utf8_lossy(value)
+                    .trim_end_matches(|c: char| c == '\0' || c.is_whitespace())
+                    .to_string()
+            };
+            match tlv_type {
+                TLV_DEVICE_ID => packet.device_id = Some(text()),
+                TLV_PORT_ID => packet.port_id = Some(text()),
+                TLV_SOFTWARE_VERSION => packet.software_version = Some(text()),
+                TLV_PLATFORM => packet.platform = Some(text()),
+                TLV_NATIVE_VLAN if value.len() == 2 => {
+                    packet.native_vlan = Some(u16::from_be_bytes([value[0], value[1]]));
+                }
+                _ => {}
+            }
+        }
+
+        Some(packet)
+    }
+
+    pub fn info(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(device) = &self.device_id {
+            parts.push(format!("Device ID: {}", device));
+        }
+        if let Some(port) = &self.port_id {
+            parts.push(format!("Port ID: {}", port));
+        }
+        if parts.is_empty() {
+            format!("Cisco Discovery Protocol version {}", self.version)
+        } else {
+            parts.join("  ")
+        }
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("cdp.version", self.version);
+        fields.insert("cdp.ttl", self.ttl);
+        if let Some(device) = &self.device_id {
+            fields.insert("cdp.deviceid", device.as_str());
+        }
+        if let Some(port) = &self.port_id {
+            fields.insert("cdp.portid", port.as_str());
+        }
+        if let Some(version) = &self.software_version {
+            fields.insert("cdp.software_version", version.as_str());
+        }
+        if let Some(platform) = &self.platform {
+            fields.insert("cdp.platform", platform.as_str());
+        }
+        if let Some(vlan) = self.native_vlan {
+            fields.insert("cdp.native_vlan", vlan);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cdp_tlvs() {
+        let mut payload = vec![2, 180, 0, 0];
+        payload.extend_from_slice(&[0x00, 0x01, 0x00, 0x07]);
+        payload.extend_from_slice(b"sw1");
+        payload.extend_from_slice(&[0x00, 0x03, 0x00, 0x0a]);
+        payload.extend_from_slice(b"Gi0/1\0");
+        payload.extend_from_slice(&[0x00, 0x0a, 0x00, 0x06, 0x00, 0x64]);
+
+        let cdp = CdpPacket::parse(&payload).unwrap();
+        assert_eq!(cdp.ttl, 180);
+        assert_eq!(cdp.device_id.as_deref(), Some("sw1"));
+        assert_eq!(cdp.port_id.as_deref(), Some("Gi0/1"));
+        assert_eq!(cdp.native_vlan, Some(100));
+    }
+}
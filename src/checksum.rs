@@ -0,0 +1,203 @@
+use etherparse::{ip_number, InternetSlice, SlicedPacket, TransportSlice};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    Good,
+    Bad,
+    /// The checksum was not filled in: a zero UDP checksum, or a partial
+    /// checksum left by checksum offloading on locally sent packets.
+    Unverified,
+}
+
+impl ChecksumStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChecksumStatus::Good => "Good",
+            ChecksumStatus::Bad => "Bad",
+            ChecksumStatus::Unverified => "Unverified",
+        }
+    }
+}
+
+/// Checksum verdict for one protocol layer of a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumCheck {
+    /// Field prefix of the layer, e.g. `ip` or `tcp`.
+    pub layer: &'static str,
+    pub status: ChecksumStatus,
+}
+
+/// Verifies the IPv4 header checksum and the TCP, UDP, ICMP or ICMPv6
+/// checksum of a sliced packet.
+pub fn verify(sliced: &SlicedPacket) -> Vec<ChecksumCheck> {
+    let mut checks = Vec::new();
+
+    let ip = match &sliced.ip {
+        Some(ip) => ip,
+        None => return checks,
+    };
+
+    // The pseudo header sum without the length, and the transport length
+    // declared by the IP header (anything behind it is link layer padding)
+    let (pseudo, ip_payload_len) = match ip {
+        InternetSlice::Ipv4(header, extensions) => {
+            let status = match header.header_checksum() {
+                0 => ChecksumStatus::Unverified,
+                _ if fold(sum(header.slice(), 0)) == 0xffff => ChecksumStatus::Good,
+                _ => ChecksumStatus::Bad,
+            };
+            checks.push(ChecksumCheck {
+                layer: "ip",
+                status,
+            });
+
+            let pseudo = sum(&header.source(), sum(&header.destination(), 0));
+            let len = usize::from(header.payload_len())
+                .saturating_sub(extensions.to_header().header_len());
+            (pseudo, len)
+        }
+        InternetSlice::Ipv6(header, extensions) => {
+            let pseudo = sum(&header.source(), sum(&header.destination(), 0));
+            let len = usize::from(header.payload_length()).saturating_sub(extensions.slice().len());
+            (pseudo, len)
+        }
+    };
+
+    let (layer, protocol, offset, header, payload) = match &sliced.transport {
+        Some(TransportSlice::Tcp(tcp)) => ("tcp", ip_number::TCP, 16, tcp.slice(), sliced.payload),
+        Some(TransportSlice::Udp(udp)) => ("udp", ip_number::UDP, 6, udp.slice(), sliced.payload),
+        // ICMP slices cover the whole message
+        Some(TransportSlice::Icmpv4(icmp)) => ("icmp", ip_number::ICMP, 2, icmp.slice(), &[][..]),
+        Some(TransportSlice::Icmpv6(icmp)) => {
+            ("icmpv6", ip_number::IPV6_ICMP, 2, icmp.slice(), &[][..])
+        }
+        _ => return checks,
+    };
+
+    // Cut off link layer padding; give up on truncated captures
+    let (header, payload) = if header.len() >= ip_payload_len {
+        (&header[..ip_payload_len], &[][..])
+    } else if header.len() + payload.len() >= ip_payload_len {
+        (header, &payload[..ip_payload_len - header.len()])
+    } else {
+        return checks;
+    };
+    if header.len() < offset + 2 {
+        return checks;
+    }
+
+    let stored = u16::from_be_bytes([header[offset], header[offset + 1]]);
+    let pseudo = if layer == "icmp" {
+        0
+    } else {
+        pseudo + u32::from(protocol) + ip_payload_len as u32
+    };
+
+    let status = if stored == 0 && layer != "icmp" && layer != "icmpv6" {
+        ChecksumStatus::Unverified
+    } else if fold(sum(payload, sum(header, pseudo))) == 0xffff {
+        ChecksumStatus::Good
+    } else if layer != "icmp" && stored == fold(pseudo) {
+        ChecksumStatus::Unverified
+    } else {
+        ChecksumStatus::Bad
+    };
+    checks.push(ChecksumCheck { layer, status });
+
+    checks
+}
+
+/// Adds `data` as big endian 16 bit words to `initial` without folding.
+/// An odd trailing byte is padded with zero.
+fn sum(data: &[u8], initial: u32) -> u32 {
+    data.chunks(2).fold(initial, |acc, pair| {
+        acc + u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+    })
+}
+
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// The 16 bit one's complement sum of `data`, as used by IP checksums.
+pub fn ones_complement_sum(data: &[u8]) -> u16 {
+    fold(sum(data, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::PacketBuilder;
+
+    fn udp_frame(payload: &[u8]) -> Vec<u8> {
+        let builder = PacketBuilder::ethernet2([0; 6], [0; 6])
+            .ipv4([192, 168, 1, 1], [192, 168, 1, 2], 64)
+            .udp(5353, 53);
+        let mut frame = Vec::with_capacity(builder.size(payload.len()));
+        builder.write(&mut frame, payload).unwrap();
+        frame
+    }
+
+    fn status(frame: &[u8], layer: &str) -> Option<ChecksumStatus> {
+        let sliced = SlicedPacket::from_ethernet(frame).unwrap();
+        verify(&sliced)
+            .into_iter()
+            .find(|check| check.layer == layer)
+            .map(|check| check.status)
+    }
+
+    #[test]
+    fn test_valid_checksums() {
+        let frame = udp_frame(b"query");
+        assert_eq!(status(&frame, "ip"), Some(ChecksumStatus::Good));
+        assert_eq!(status(&frame, "udp"), Some(ChecksumStatus::Good));
+    }
+
+    #[test]
+    fn test_bad_checksums() {
+        let mut frame = udp_frame(b"query");
+        *frame.last_mut().unwrap() ^= 0xff;
+        frame[14 + 8] ^= 0x01; // TTL
+        assert_eq!(status(&frame, "ip"), Some(ChecksumStatus::Bad));
+        assert_eq!(status(&frame, "udp"), Some(ChecksumStatus::Bad));
+    }
+
+    #[test]
+    fn test_link_padding_is_ignored() {
+        let mut frame = udp_frame(b"q");
+        frame.extend_from_slice(&[0xaa; 16]);
+        assert_eq!(status(&frame, "udp"), Some(ChecksumStatus::Good));
+    }
+
+    #[test]
+    fn test_offloaded_checksums() {
+        let mut frame = udp_frame(b"query");
+        // Partial checksum as left by the kernel for the NIC to complete:
+        // the folded pseudo header sum
+        let udp_len = 8 + 5u32;
+        let pseudo = sum(&[192, 168, 1, 1], sum(&[192, 168, 1, 2], 0)) + 17 + udp_len;
+        frame[40..42].copy_from_slice(&fold(pseudo).to_be_bytes());
+        assert_eq!(status(&frame, "udp"), Some(ChecksumStatus::Unverified));
+
+        // No IPv4 header checksum filled in yet
+        frame[24..26].copy_from_slice(&[0, 0]);
+        assert_eq!(status(&frame, "ip"), Some(ChecksumStatus::Unverified));
+    }
+
+    #[test]
+    fn test_icmpv6_uses_pseudo_header() {
+        let builder = PacketBuilder::ethernet2([0; 6], [0; 6])
+            .ipv6([0xfe; 16], [0xff; 16], 64)
+            .icmpv6_echo_request(1, 1);
+        let mut frame = Vec::with_capacity(builder.size(4));
+        builder.write(&mut frame, b"ping").unwrap();
+        assert_eq!(status(&frame, "icmpv6"), Some(ChecksumStatus::Good));
+
+        // Changing the destination address invalidates the checksum
+        frame[14 + 39] ^= 0x01;
+        assert_eq!(status(&frame, "icmpv6"), Some(ChecksumStatus::Bad));
+    }
+}
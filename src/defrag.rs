@@ -0,0 +1,498 @@
+use crate::checksum::ones_complement_sum;
+use chrono::{DateTime, Duration, Utc};
+use etherparse::{ip_number, Ipv4HeaderSlice, Ipv6HeaderSlice};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+// Incomplete datagrams are dropped after this long, like the Linux default
+const FRAGMENT_TIMEOUT_SECS: i64 = 30;
+const MAX_PENDING_DATAGRAMS: usize = 1024;
+const MAX_DATAGRAM_LEN: usize = 65535;
+const IPV6_HEADER_LEN: usize = 40;
+
+/// Outcome of feeding one fragment to the [`Defragmenter`].
+#[derive(Debug, PartialEq)]
+pub enum DefragResult {
+    /// Not a fragment, the packet can be dissected as is.
+    NotFragmented,
+    /// A fragment was stored; the datagram is still incomplete.
+    Fragment(FragmentInfo),
+    /// The last missing fragment arrived. `datagram` is a complete IP packet
+    /// starting with the IP header.
+    Reassembled {
+        info: FragmentInfo,
+        datagram: Vec<u8>,
+        fragments: usize,
+    },
+    /// The fragment was rejected, e.g. an overlapping IPv6 fragment.
+    Dropped(FragmentInfo),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FragmentInfo {
+    pub id: u32,
+    pub protocol: u8,
+    /// Offset of the fragment data within the datagram, in bytes.
+    pub offset: usize,
+    pub more_fragments: bool,
+    /// Set if the fragment overlapped data received earlier.
+    pub overlap: bool,
+    /// Set if the overlapping bytes differed from the earlier data.
+    pub overlap_conflict: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct DatagramKey {
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    id: u32,
+}
+
+struct PendingDatagram {
+    first_seen: DateTime<Utc>,
+    /// IP header taken from the fragment at offset zero.
+    header: Option<Vec<u8>>,
+    data: Vec<u8>,
+    /// Sorted, non-overlapping byte ranges of `data` received so far.
+    received: Vec<(usize, usize)>,
+    total_len: Option<usize>,
+    fragments: usize,
+}
+
+impl PendingDatagram {
+    fn new(first_seen: DateTime<Utc>) -> Self {
+        Self {
+            first_seen,
+            header: None,
+            data: Vec::new(),
+            received: Vec::new(),
+            total_len: None,
+            fragments: 0,
+        }
+    }
+
+    /// Copies `data` in at `offset`, keeping bytes that were received first.
+    /// Returns (overlap, conflict).
+    fn insert(&mut self, offset: usize, data: &[u8]) -> (bool, bool) {
+        let end = offset + data.len();
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+
+        let mut overlap = false;
+        let mut conflict = false;
+        for &(start, stop) in &self.received {
+            let (lo, hi) = (start.max(offset), stop.min(end));
+            if lo < hi {
+                overlap = true;
+                conflict |= self.data[lo..hi] != data[lo - offset..hi - offset];
+            }
+        }
+
+        let mut pos = offset;
+        while pos < end {
+            let covered = self
+                .received
+                .iter()
+                .find(|&&(start, stop)| start <= pos && pos < stop)
+                .map(|&(_, stop)| stop);
+            match covered {
+                Some(stop) => pos = stop,
+                None => {
+                    let next = self
+                        .received
+                        .iter()
+                        .map(|&(start, _)| start)
+                        .filter(|&start| start > pos)
+                        .min()
+                        .unwrap_or(end)
+                        .min(end);
+                    self.data[pos..next].copy_from_slice(&data[pos - offset..next - offset]);
+                    pos = next;
+                }
+            }
+        }
+
+        self.received.push((offset, end));
+        self.received.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(self.received.len());
+        for &(start, stop) in &self.received {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(stop),
+                _ => merged.push((start, stop)),
+            }
+        }
+        self.received = merged;
+
+        (overlap, conflict)
+    }
+
+    fn is_complete(&self) -> bool {
+        match self.total_len {
+            Some(total) => self.header.is_some() && self.received == [(0, total)],
+            None => false,
+        }
+    }
+}
+
+/// Reassembles fragmented IPv4 datagrams and IPv6 datagrams carrying a
+/// Fragment extension header.
+///
+/// Overlapping IPv4 fragments keep the bytes that arrived first. Overlapping
+/// IPv6 fragments discard the whole datagram as required by RFC 5722.
+#[derive(Default)]
+pub struct Defragmenter {
+    pending: HashMap<DatagramKey, PendingDatagram>,
+}
+
+impl Defragmenter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of datagrams waiting for more fragments.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Feeds an IP packet (starting at the IP header) to the reassembler.
+    pub fn process(&mut self, packet: &[u8], timestamp: DateTime<Utc>) -> DefragResult {
+        let fragment = match parse_fragment(packet) {
+            Some(fragment) => fragment,
+            None => return DefragResult::NotFragmented,
+        };
+
+        self.expire(timestamp);
+
+        let ParsedFragment {
+            key,
+            offset,
+            more_fragments,
+            header,
+            payload,
+            ipv6,
+        } = fragment;
+
+        let mut info = FragmentInfo {
+            id: key.id,
+            protocol: key.protocol,
+            offset,
+            more_fragments,
+            overlap: false,
+            overlap_conflict: false,
+        };
+
+        if offset + payload.len() > MAX_DATAGRAM_LEN {
+            self.pending.remove(&key);
+            return DefragResult::Dropped(info);
+        }
+
+        if !self.pending.contains_key(&key) && self.pending.len() >= MAX_PENDING_DATAGRAMS {
+            return DefragResult::Dropped(info);
+        }
+
+        let datagram = self
+            .pending
+            .entry(key)
+            .or_insert_with(|| PendingDatagram::new(timestamp));
+        datagram.fragments += 1;
+        if offset == 0 {
+            datagram.header = Some(header);
+        }
+        if !more_fragments {
+            datagram.total_len = Some(offset + payload.len());
+        }
+
+        let (overlap, conflict) = datagram.insert(offset, payload);
+        info.overlap = overlap;
+        info.overlap_conflict = conflict;
+
+        if overlap && ipv6 {
+            self.pending.remove(&key);
+            return DefragResult::Dropped(info);
+        }
+
+        if !datagram.is_complete() {
+            return DefragResult::Fragment(info);
+        }
+
+        let datagram = self.pending.remove(&key).expect("datagram is pending");
+        let total_len = datagram.total_len.unwrap_or(0);
+        let mut bytes = datagram.header.unwrap_or_default();
+        bytes.extend_from_slice(&datagram.data[..total_len]);
+        fix_lengths(&mut bytes);
+
+        DefragResult::Reassembled {
+            info,
+            datagram: bytes,
+            fragments: datagram.fragments,
+        }
+    }
+
+    fn expire(&mut self, now: DateTime<Utc>) {
+        let timeout = Duration::seconds(FRAGMENT_TIMEOUT_SECS);
+        self.pending
+            .retain(|_, datagram| now - datagram.first_seen < timeout);
+    }
+}
+
+struct ParsedFragment<'a> {
+    key: DatagramKey,
+    offset: usize,
+    more_fragments: bool,
+    /// Header for the reassembled datagram, with the fragmentation removed.
+    header: Vec<u8>,
+    payload: &'a [u8],
+    ipv6: bool,
+}
+
+/// Decodes the fragmentation fields of an IP packet, returning `None` if the
+/// packet is not a fragment.
+///
+/// The IPv6 Fragment header is decoded by hand because etherparse 0.13 reads
+/// the offset and M flag from the wrong bits.
+fn parse_fragment(packet: &[u8]) -> Option<ParsedFragment<'_>> {
+    match packet.first()? >> 4 {
+        4 => {
+            let header = Ipv4HeaderSlice::from_slice(packet).ok()?;
+            if !header.is_fragmenting_payload() {
+                return None;
+            }
+            let end = usize::from(header.total_len()).min(packet.len());
+            let mut reassembled = header.slice().to_vec();
+            reassembled[6] &= 0x40; // keep DF, clear MF and the offset
+            reassembled[7] = 0;
+
+            Some(ParsedFragment {
+                key: DatagramKey {
+                    src: header.source_addr().into(),
+                    dst: header.destination_addr().into(),
+                    protocol: header.protocol(),
+                    id: header.identification().into(),
+                },
+                offset: usize::from(header.fragments_offset()) * 8,
+                more_fragments: header.more_fragments(),
+                header: reassembled,
+                payload: packet.get(header.slice().len()..end)?,
+                ipv6: false,
+            })
+        }
+        6 => {
+            let header = Ipv6HeaderSlice::from_slice(packet).ok()?;
+            let end = (IPV6_HEADER_LEN + usize::from(header.payload_length())).min(packet.len());
+
+            // Walk the extension headers up to the Fragment header, remembering
+            // where the "next header" byte pointing at it lives
+            let mut next_header = header.next_header();
+            let mut next_header_pos = 6;
+            let mut pos = IPV6_HEADER_LEN;
+            while next_header != ip_number::IPV6_FRAG {
+                let len = match next_header {
+                    ip_number::IPV6_HOP_BY_HOP
+                    | ip_number::IPV6_ROUTE
+                    | ip_number::IPV6_DEST_OPTIONS => (usize::from(*packet.get(pos + 1)?) + 1) * 8,
+                    ip_number::AUTH => (usize::from(*packet.get(pos + 1)?) + 2) * 4,
+                    _ => return None,
+                };
+                next_header = *packet.get(pos)?;
+                next_header_pos = pos;
+                pos += len;
+            }
+
+            let fragment = packet.get(pos..pos + 8)?;
+            let offset_flags = u16::from_be_bytes([fragment[2], fragment[3]]);
+            let offset = usize::from(offset_flags >> 3) * 8;
+            let more_fragments = offset_flags & 1 == 1;
+            if offset == 0 && !more_fragments {
+                return None;
+            }
+
+            let mut reassembled = packet[..pos].to_vec();
+            reassembled[next_header_pos] = fragment[0];
+
+            Some(ParsedFragment {
+                key: DatagramKey {
+                    src: header.source_addr().into(),
+                    dst: header.destination_addr().into(),
+                    protocol: fragment[0],
+                    id: u32::from_be_bytes([fragment[4], fragment[5], fragment[6], fragment[7]]),
+                },
+                offset,
+                more_fragments,
+                header: reassembled,
+                payload: packet.get(pos + 8..end)?,
+                ipv6: true,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Fixes up the length fields (and for IPv4 the header checksum) of a
+/// reassembled datagram.
+fn fix_lengths(datagram: &mut [u8]) {
+    if datagram[0] >> 4 == 4 {
+        let header_len = usize::from(datagram[0] & 0x0f) * 4;
+        let total = datagram.len() as u16;
+        datagram[2..4].copy_from_slice(&total.to_be_bytes());
+        datagram[10..12].copy_from_slice(&[0, 0]);
+        let checksum = !ones_complement_sum(&datagram[..header_len]);
+        datagram[10..12].copy_from_slice(&checksum.to_be_bytes());
+    } else {
+        let payload_len = (datagram.len() - IPV6_HEADER_LEN) as u16;
+        datagram[4..6].copy_from_slice(&payload_len.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::SlicedPacket;
+
+    /// Builds an IPv4 fragment of a UDP datagram with the given id.
+    fn ipv4_fragment(id: u16, offset: usize, more: bool, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![
+            0x45, 0x00, 0x00, 0x00, // version, ihl, tos, total length
+            0x00, 0x00, 0x00, 0x00, // id, flags, fragment offset
+            0x40, 0x11, 0x00, 0x00, // ttl, protocol (UDP), checksum
+            10, 0, 0, 1, // source
+            10, 0, 0, 2, // destination
+        ];
+        packet[2..4].copy_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+        packet[4..6].copy_from_slice(&id.to_be_bytes());
+        let flags = if more { 0x2000 } else { 0 } | (offset / 8) as u16;
+        packet[6..8].copy_from_slice(&flags.to_be_bytes());
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    fn feed(defrag: &mut Defragmenter, packet: &[u8]) -> DefragResult {
+        defrag.process(packet, Utc::now())
+    }
+
+    fn udp_datagram() -> Vec<u8> {
+        // UDP header (port 53 -> 12345, length 24) and 16 bytes of payload
+        let mut datagram = vec![0x00, 0x35, 0x30, 0x39, 0x00, 0x18, 0x00, 0x00];
+        datagram.extend(b"0123456789abcdef");
+        datagram
+    }
+
+    #[test]
+    fn test_ipv4_reassembly_out_of_order() {
+        let mut defrag = Defragmenter::new();
+        let datagram = udp_datagram();
+
+        let second = ipv4_fragment(7, 16, false, &datagram[16..]);
+        let first = ipv4_fragment(7, 0, true, &datagram[..16]);
+
+        assert!(matches!(
+            feed(&mut defrag, &second),
+            DefragResult::Fragment(_)
+        ));
+        assert_eq!(defrag.pending(), 1);
+
+        match feed(&mut defrag, &first) {
+            DefragResult::Reassembled {
+                datagram: bytes,
+                fragments,
+                ..
+            } => {
+                assert_eq!(fragments, 2);
+                let sliced = SlicedPacket::from_ip(&bytes).unwrap();
+                assert!(matches!(
+                    sliced.transport,
+                    Some(etherparse::TransportSlice::Udp(_))
+                ));
+                assert_eq!(sliced.payload, b"0123456789abcdef");
+            }
+            other => panic!("expected reassembly, got {:?}", other),
+        }
+        assert_eq!(defrag.pending(), 0);
+    }
+
+    #[test]
+    fn test_ipv4_overlap_keeps_first_data() {
+        let mut defrag = Defragmenter::new();
+        let datagram = udp_datagram();
+
+        feed(&mut defrag, &ipv4_fragment(9, 0, true, &datagram[..16]));
+        let mut forged = datagram[8..].to_vec();
+        forged[0] = b'X';
+        match feed(&mut defrag, &ipv4_fragment(9, 8, false, &forged)) {
+            DefragResult::Reassembled {
+                info,
+                datagram: bytes,
+                ..
+            } => {
+                assert!(info.overlap);
+                assert!(info.overlap_conflict);
+                assert_eq!(&bytes[20..], &datagram[..]);
+            }
+            other => panic!("expected reassembly, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ipv6_fragment_header() {
+        let mut defrag = Defragmenter::new();
+        let datagram = udp_datagram();
+
+        let fragment = |offset: usize, more: bool, data: &[u8]| {
+            let mut packet = vec![0x60, 0, 0, 0, 0, 0, 44, 64];
+            packet[4..6].copy_from_slice(&((8 + data.len()) as u16).to_be_bytes());
+            packet.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+            packet.extend_from_slice(&[0; 11]);
+            packet.push(1);
+            packet.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+            packet.extend_from_slice(&[0; 11]);
+            packet.push(2);
+            let flags = ((offset / 8) as u16) << 3 | u16::from(more);
+            packet.extend_from_slice(&[17, 0]);
+            packet.extend_from_slice(&flags.to_be_bytes());
+            packet.extend_from_slice(&0xdead_beef_u32.to_be_bytes());
+            packet.extend_from_slice(data);
+            packet
+        };
+
+        assert!(matches!(
+            feed(&mut defrag, &fragment(0, true, &datagram[..16])),
+            DefragResult::Fragment(_)
+        ));
+        match feed(&mut defrag, &fragment(16, false, &datagram[16..])) {
+            DefragResult::Reassembled {
+                info,
+                datagram: bytes,
+                ..
+            } => {
+                assert_eq!(info.id, 0xdead_beef);
+                let sliced = SlicedPacket::from_ip(&bytes).unwrap();
+                assert_eq!(sliced.payload, b"0123456789abcdef");
+            }
+            other => panic!("expected reassembly, got {:?}", other),
+        }
+
+        // Overlapping IPv6 fragments discard the datagram
+        feed(&mut defrag, &fragment(0, true, &datagram[..16]));
+        assert!(matches!(
+            feed(&mut defrag, &fragment(8, false, &datagram[8..])),
+            DefragResult::Dropped(_)
+        ));
+        assert_eq!(defrag.pending(), 0);
+    }
+
+    #[test]
+    fn test_timeout_discards_incomplete_datagrams() {
+        let mut defrag = Defragmenter::new();
+        let datagram = udp_datagram();
+        let start = Utc::now();
+
+        defrag.process(&ipv4_fragment(3, 0, true, &datagram[..16]), start);
+        defrag.process(
+            &ipv4_fragment(4, 0, true, &datagram[..16]),
+            start + Duration::seconds(FRAGMENT_TIMEOUT_SECS + 1),
+        );
+
+        assert_eq!(defrag.pending(), 1);
+    }
+}
@@ -0,0 +1,481 @@
+use crate::fields::{format_mac, FieldValue, Fields};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+
+pub const SERVER_PORT: u16 = 67;
+pub const CLIENT_PORT: u16 = 68;
+
+pub const DISCOVER: u8 = 1;
+pub const OFFER: u8 = 2;
+pub const REQUEST: u8 = 3;
+pub const DECLINE: u8 = 4;
+pub const ACK: u8 = 5;
+pub const NAK: u8 = 6;
+pub const RELEASE: u8 = 7;
+pub const INFORM: u8 = 8;
+
+const MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+const FIXED_HEADER_LEN: usize = 236;
+
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS_SERVER: u8 = 6;
+const OPT_HOSTNAME: u8 = 12;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_CLIENT_ID: u8 = 61;
+const OPT_END: u8 = 255;
+
+// Upper bound on tracked transactions so a long capture can't grow without
+// limit
+const MAX_TRANSACTIONS: usize = 65536;
+
+pub fn message_type_name(message_type: u8) -> &'static str {
+    match message_type {
+        DISCOVER => "Discover",
+        OFFER => "Offer",
+        REQUEST => "Request",
+        DECLINE => "Decline",
+        ACK => "ACK",
+        NAK => "NAK",
+        RELEASE => "Release",
+        INFORM => "Inform",
+        _ => "Unknown",
+    }
+}
+
+/// A DHCPv4 message (RFC 2131) with the commonly used options.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DhcpPacket {
+    pub op: u8,
+    pub xid: u32,
+    pub client_ip: Option<Ipv4Addr>,
+    pub your_ip: Option<Ipv4Addr>,
+    pub relay_ip: Option<Ipv4Addr>,
+    /// Client hardware address if it is an Ethernet MAC address.
+    pub client_mac: Option<[u8; 6]>,
+    pub message_type: Option<u8>,
+    pub hostname: Option<String>,
+    pub lease_time: Option<u32>,
+    pub subnet_mask: Option<Ipv4Addr>,
+    pub routers: Vec<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr>,
+    pub client_id: Option<Vec<u8>>,
+    pub server_id: Option<Ipv4Addr>,
+    pub requested_ip: Option<Ipv4Addr>,
+}
+
+impl DhcpPacket {
+    pub fn parse(payload: &[u8]) -> Option<DhcpPacket> {
+        let header = payload.get(..FIXED_HEADER_LEN)?;
+        if !matches!(header[0], 1 | 2)
+            || payload.get(FIXED_HEADER_LEN..FIXED_HEADER_LEN + 4)? != MAGIC_COOKIE
+        {
+            return None;
+        }
+
+        let address = |offset: usize| {
+            let ip = Ipv4Addr::new(
+                header[offset],
+                header[offset + 1],
+                header[offset + 2],
+                header[offset + 3],
+            );
+            Some(ip).filter(|ip| !ip.is_unspecified())
+        };
+        let mut packet = DhcpPacket {
+            op: header[0],
+            xid: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+            client_ip: address(12),
+            your_ip: address(16),
+            relay_ip: address(24),
+            client_mac: if header[1] == 1 && header[2] == 6 {
+                header[28..34].try_into().ok()
+            } else {
+                None
+            },
+            ..DhcpPacket::default()
+        };
+
+        let mut options = &payload[FIXED_HEADER_LEN + 4..];
+        while let Some(&code) = options.first() {
+            match code {
+                OPT_PAD => {
+                    options = &options[1..];
+                    continue;
+                }
+                OPT_END => break,
+                _ => {}
+            }
+            let len = usize::from(*options.get(1)?);
+            let value = options.get(2..2 + len)?;
+            options = &options[2 + len..];
+
+            match code {
+                OPT_MESSAGE_TYPE if len == 1 => packet.message_type = Some(value[0]),
+                OPT_HOSTNAME => packet.hostname = Some(String::from_utf8_lossy(value).into()),
+                OPT_LEASE_TIME if len == 4 => {
+                    packet.lease_time =
+                        Some(u32::from_be_bytes([value[0], value[1], value[2], value[3]]));
+                }
+                OPT_SUBNET_MASK => packet.subnet_mask = addresses(value).first().copied(),
+                OPT_ROUTER => packet.routers = addresses(value),
+                OPT_DNS_SERVER => packet.dns_servers = addresses(value),
+                OPT_CLIENT_ID => packet.client_id = Some(value.to_vec()),
+                OPT_SERVER_ID => packet.server_id = addresses(value).first().copied(),
+                OPT_REQUESTED_IP => packet.requested_ip = addresses(value).first().copied(),
+                _ => {}
+            }
+        }
+
+        Some(packet)
+    }
+
+    /// Whether the message is sent by a server.
+    pub fn is_server_message(&self) -> bool {
+        matches!(self.message_type, Some(OFFER | ACK | NAK))
+    }
+
+    pub fn info(&self) -> String {
+        let name = match self.message_type {
+            Some(message_type) => message_type_name(message_type),
+            None => "BOOTP",
+        };
+        let mut info = format!("DHCP {} - Transaction ID 0x{:08x}", name, self.xid);
+        if let (Some(OFFER | ACK), Some(ip)) = (self.message_type, self.your_ip) {
+            info.push_str(&format!(", {}", ip));
+            if let Some(mac) = self.client_mac {
+                info.push_str(&format!(" for {}", format_mac(&mac)));
+            }
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("dhcp.type", self.op);
+        fields.insert("dhcp.id", self.xid);
+        if let Some(mac) = self.client_mac {
+            fields.insert("dhcp.hw.mac_addr", mac);
+        }
+        if let Some(ip) = self.client_ip {
+            fields.insert("dhcp.ip.client", ip);
+        }
+        if let Some(ip) = self.your_ip {
+            fields.insert("dhcp.ip.your", ip);
+        }
+        if let Some(ip) = self.relay_ip {
+            fields.insert("dhcp.ip.relay", ip);
+        }
+        if let Some(message_type) = self.message_type {
+            fields.insert("dhcp.option.dhcp", message_type);
+        }
+        if let Some(hostname) = &self.hostname {
+            fields.insert("dhcp.option.hostname", hostname.as_str());
+        }
+        if let Some(lease_time) = self.lease_time {
+            fields.insert("dhcp.option.ip_address_lease_time", lease_time);
+        }
+        if let Some(mask) = self.subnet_mask {
+            fields.insert("dhcp.option.subnet_mask", mask);
+        }
+        for router in &self.routers {
+            fields.insert("dhcp.option.router", *router);
+        }
+        for server in &self.dns_servers {
+            fields.insert("dhcp.option.domain_name_server", *server);
+        }
+        if let Some(client_id) = &self.client_id {
+            fields.insert("dhcp.option.client_id", client_id.clone());
+        }
+        if let Some(server) = self.server_id {
+            fields.insert("dhcp.option.dhcp_server_id", server);
+        }
+        if let Some(ip) = self.requested_ip {
+            fields.insert("dhcp.option.requested_ip_address", ip);
+        }
+    }
+}
+
+fn addresses(value: &[u8]) -> Vec<Ipv4Addr> {
+    value
+        .chunks_exact(4)
+        .map(|b| Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+        .collect()
+}
+
+/// An address handed out by a DHCP or DHCPv6 server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lease {
+    /// Client MAC address, or the DUID of DHCPv6 clients without one.
+    pub client: String,
+    pub address: IpAddr,
+    pub server: IpAddr,
+    pub hostname: Option<String>,
+    /// Lease time or valid lifetime in seconds.
+    pub lease_time: Option<u32>,
+    pub granted: DateTime<Utc>,
+}
+
+impl Lease {
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("dhcp.lease.client", self.client.as_str());
+        fields.insert("dhcp.lease.address", self.address);
+        fields.insert("dhcp.lease.server", self.server);
+        if let Some(hostname) = &self.hostname {
+            fields.insert("dhcp.lease.hostname", hostname.as_str());
+        }
+        if let Some(lease_time) = self.lease_time {
+            fields.insert("dhcp.lease.time", lease_time);
+        }
+    }
+
+    /// Rebuilds the leases granted by a packet from its fields.
+    pub fn from_fields(fields: &Fields, granted: DateTime<Utc>) -> Vec<Lease> {
+        let client = match fields.get("dhcp.lease.client") {
+            Some(client) => client.to_string(),
+            None => return Vec::new(),
+        };
+        let server = match fields.get("dhcp.lease.server") {
+            Some(FieldValue::Ip(server)) => *server,
+            _ => return Vec::new(),
+        };
+        let hostname = fields.get("dhcp.lease.hostname").map(|h| h.to_string());
+        let lease_time = match fields.get("dhcp.lease.time") {
+            Some(FieldValue::UInt(time)) => u32::try_from(*time).ok(),
+            _ => None,
+        };
+
+        fields
+            .get_all("dhcp.lease.address")
+            .filter_map(|address| match address {
+                FieldValue::Ip(address) => Some(Lease {
+                    client: client.clone(),
+                    address: *address,
+                    server,
+                    hostname: hostname.clone(),
+                    lease_time,
+                    granted,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The current lease of every address, most recently granted last.
+#[derive(Debug, Default)]
+pub struct LeaseTable {
+    leases: Vec<Lease>,
+}
+
+impl LeaseTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, lease: Lease) {
+        self.leases.retain(|l| l.address != lease.address);
+        self.leases.push(lease);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Lease> {
+        self.leases.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.leases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leases.is_empty()
+    }
+}
+
+/// Position of a message within its transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransactionInfo {
+    /// 1 for the first message with this transaction ID.
+    pub index: u32,
+    /// Seconds since the first message.
+    pub elapsed: f64,
+}
+
+#[derive(Debug)]
+struct Transaction {
+    first_seen: DateTime<Utc>,
+    messages: u32,
+    hostname: Option<String>,
+}
+
+/// Groups DHCP and DHCPv6 messages into transactions and remembers which
+/// servers answer, to spot rogue servers.
+#[derive(Debug, Default)]
+pub struct DhcpTracker {
+    /// Keyed by DHCPv6 flag and transaction ID.
+    transactions: HashMap<(bool, u32), Transaction>,
+    servers: Vec<IpAddr>,
+    trusted: Vec<IpAddr>,
+}
+
+impl DhcpTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only the given servers are legitimate. Without trusted servers, the
+    /// first server seen answering is.
+    pub fn with_trusted_servers(mut self, servers: Vec<IpAddr>) -> Self {
+        self.trusted = servers;
+        self
+    }
+
+    /// Records a message of transaction `xid`, and the client hostname if
+    /// it names one.
+    pub fn message(
+        &mut self,
+        v6: bool,
+        xid: u32,
+        timestamp: DateTime<Utc>,
+        hostname: Option<&str>,
+    ) -> TransactionInfo {
+        if self.transactions.len() >= MAX_TRANSACTIONS {
+            self.transactions.clear();
+        }
+        let transaction = self
+            .transactions
+            .entry((v6, xid))
+            .or_insert_with(|| Transaction {
+                first_seen: timestamp,
+                messages: 0,
+                hostname: None,
+            });
+        transaction.messages += 1;
+        if let Some(hostname) = hostname {
+            transaction.hostname = Some(hostname.to_string());
+        }
+
+        let elapsed = timestamp.signed_duration_since(transaction.first_seen);
+        TransactionInfo {
+            index: transaction.messages,
+            elapsed: elapsed.num_microseconds().unwrap_or(0) as f64 / 1_000_000.0,
+        }
+    }
+
+    /// The hostname a client named earlier in the transaction.
+    pub fn hostname(&self, v6: bool, xid: u32) -> Option<&str> {
+        self.transactions.get(&(v6, xid))?.hostname.as_deref()
+    }
+
+    /// Records an answer from `server`. Returns the legitimate servers if
+    /// `server` is not one of them.
+    pub fn check_server(&mut self, server: IpAddr) -> Option<Vec<IpAddr>> {
+        if !self.trusted.is_empty() {
+            return if self.trusted.contains(&server) {
+                None
+            } else {
+                Some(self.trusted.clone())
+            };
+        }
+
+        if !self.servers.contains(&server) {
+            self.servers.push(server);
+        }
+        if self.servers[0] == server {
+            None
+        } else {
+            Some(vec![self.servers[0]])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dhcp_message(message_type: u8, your_ip: [u8; 4], options: &[u8]) -> Vec<u8> {
+        let mut payload = vec![0; FIXED_HEADER_LEN];
+        payload[0] = if message_type == OFFER || message_type == ACK {
+            2
+        } else {
+            1
+        };
+        payload[1] = 1;
+        payload[2] = 6;
+        payload[4..8].copy_from_slice(&0x3903f326u32.to_be_bytes());
+        payload[16..20].copy_from_slice(&your_ip);
+        payload[28..34].copy_from_slice(&[0x00, 0x0b, 0x82, 0x01, 0xfc, 0x42]);
+        payload.extend_from_slice(&MAGIC_COOKIE);
+        payload.extend_from_slice(&[OPT_MESSAGE_TYPE, 1, message_type]);
+        payload.extend_from_slice(options);
+        payload.push(OPT_END);
+        payload
+    }
+
+    #[test]
+    fn test_dhcp_ack_options() {
+        let mut options = vec![OPT_LEASE_TIME, 4, 0, 0, 0x0e, 0x10]; // 3600 s
+        options.extend_from_slice(&[OPT_ROUTER, 4, 192, 168, 0, 1]);
+        options.extend_from_slice(&[OPT_DNS_SERVER, 8, 8, 8, 8, 8, 1, 1, 1, 1]);
+        options.extend_from_slice(&[OPT_SERVER_ID, 4, 192, 168, 0, 1]);
+        let dhcp = DhcpPacket::parse(&dhcp_message(ACK, [192, 168, 0, 10], &options)).unwrap();
+
+        assert_eq!(dhcp.message_type, Some(ACK));
+        assert_eq!(dhcp.lease_time, Some(3600));
+        assert_eq!(dhcp.routers, vec![Ipv4Addr::new(192, 168, 0, 1)]);
+        assert_eq!(dhcp.dns_servers.len(), 2);
+        assert_eq!(dhcp.server_id, Some(Ipv4Addr::new(192, 168, 0, 1)));
+        assert_eq!(
+            dhcp.info(),
+            "DHCP ACK - Transaction ID 0x3903f326, 192.168.0.10 for 00:0b:82:01:fc:42"
+        );
+    }
+
+    #[test]
+    fn test_transactions_and_rogue_servers() {
+        let mut tracker = DhcpTracker::new();
+        let now = Utc::now();
+
+        let first = tracker.message(false, 7, now, Some("laptop"));
+        let second = tracker.message(false, 7, now + chrono::Duration::seconds(1), None);
+        assert_eq!(first.index, 1);
+        assert_eq!(second.index, 2);
+        assert_eq!(second.elapsed, 1.0);
+        assert_eq!(tracker.hostname(false, 7), Some("laptop"));
+
+        let legit = IpAddr::from([192, 168, 0, 1]);
+        let rogue = IpAddr::from([192, 168, 0, 66]);
+        assert_eq!(tracker.check_server(legit), None);
+        assert_eq!(tracker.check_server(rogue), Some(vec![legit]));
+
+        let mut trusted = DhcpTracker::new().with_trusted_servers(vec![rogue]);
+        assert_eq!(trusted.check_server(legit), Some(vec![rogue]));
+    }
+
+    #[test]
+    fn test_lease_fields_round_trip() {
+        let lease = Lease {
+            client: "00:0b:82:01:fc:42".to_string(),
+            address: IpAddr::from([192, 168, 0, 10]),
+            server: IpAddr::from([192, 168, 0, 1]),
+            hostname: Some("laptop".to_string()),
+            lease_time: Some(3600),
+            granted: Utc::now(),
+        };
+        let mut fields = Fields::new();
+        lease.insert_fields(&mut fields);
+
+        assert_eq!(
+            Lease::from_fields(&fields, lease.granted),
+            vec![lease.clone()]
+        );
+
+        let mut table = LeaseTable::new();
+        table.update(lease.clone());
+        table.update(lease);
+        assert_eq!(table.len(), 1);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::fields::{format_mac, Fields};
+use std::net::Ipv6Addr;
+
+pub const CLIENT_PORT: u16 = 546;
+pub const SERVER_PORT: u16 = 547;
+
+pub const SOLICIT: u8 = 1;
+pub const ADVERTISE: u8 = 2;
+pub const REQUEST: u8 = 3;
+pub const CONFIRM: u8 = 4;
+pub const RENEW: u8 = 5;
+pub const REBIND: u8 = 6;
+pub const REPLY: u8 = 7;
+pub const RELEASE: u8 = 8;
+pub const DECLINE: u8 = 9;
+pub const RECONFIGURE: u8 = 10;
+pub const INFORMATION_REQUEST: u8 = 11;
+pub const RELAY_FORW: u8 = 12;
+pub const RELAY_REPL: u8 = 13;
+
+const OPT_CLIENTID: u16 = 1;
+const OPT_SERVERID: u16 = 2;
+const OPT_IA_NA: u16 = 3;
+const OPT_IAADDR: u16 = 5;
+const OPT_RELAY_MSG: u16 = 9;
+const OPT_DNS_SERVERS: u16 = 23;
+
+// Relay agents can be chained, but not endlessly
+const MAX_RELAY_DEPTH: usize = 8;
+
+pub fn message_type_name(message_type: u8) -> &'static str {
+    match message_type {
+        SOLICIT => "Solicit",
+        ADVERTISE => "Advertise",
+        REQUEST => "Request",
+        CONFIRM => "Confirm",
+        RENEW => "Renew",
+        REBIND => "Rebind",
+        REPLY => "Reply",
+        RELEASE => "Release",
+        DECLINE => "Decline",
+        RECONFIGURE => "Reconfigure",
+        INFORMATION_REQUEST => "Information-request",
+        RELAY_FORW => "Relay-forward",
+        RELAY_REPL => "Relay-reply",
+        _ => "Unknown",
+    }
+}
+
+/// A DHCPv6 message (RFC 8415). Relay messages carry the relayed message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dhcpv6Packet {
+    pub message_type: u8,
+    /// 24 bit transaction ID, 0 for relay messages.
+    pub xid: u32,
+    pub client_duid: Option<Vec<u8>>,
+    pub server_duid: Option<Vec<u8>>,
+    /// Assigned addresses with their valid lifetime.
+    pub addresses: Vec<(Ipv6Addr, u32)>,
+    pub dns_servers: Vec<Ipv6Addr>,
+    pub relayed: Option<Box<Dhcpv6Packet>>,
+}
+
+impl Dhcpv6Packet {
+    pub fn parse(payload: &[u8]) -> Option<Dhcpv6Packet> {
+        Self::parse_nested(payload, 0)
+    }
+
+    fn parse_nested(payload: &[u8], depth: usize) -> Option<Dhcpv6Packet> {
+        let message_type = *payload.first()?;
+        if !(SOLICIT..=RELAY_REPL).contains(&message_type) {
+            return None;
+        }
+
+        let mut packet = Dhcpv6Packet {
+            message_type,
+            ..Dhcpv6Packet::default()
+        };
+        // Relay messages have a hop count and link and peer address instead
+        // of the transaction ID
+        let options = if packet.is_relay() {
+            payload.get(34..)?
+        } else {
+            let header = payload.get(..4)?;
+            packet.xid = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+            &payload[4..]
+        };
+
+        for (code, value) in options_iter(options) {
+            match code {
+                OPT_CLIENTID => packet.client_duid = Some(value.to_vec()),
+                OPT_SERVERID => packet.server_duid = Some(value.to_vec()),
+                OPT_IA_NA if value.len() >= 12 => {
+                    // IAID, T1 and T2, then the IA options
+                    for (code, address) in options_iter(&value[12..]) {
+                        if code == OPT_IAADDR && address.len() >= 24 {
+                            let ip: [u8; 16] = address[..16].try_into().ok()?;
+                            let valid = u32::from_be_bytes([
+                                address[20],
+                                address[21],
+                                address[22],
+                                address[23],
+                            ]);
+                            packet.addresses.push((Ipv6Addr::from(ip), valid));
+                        }
+                    }
+                }
+                OPT_DNS_SERVERS => {
+                    packet.dns_servers = value
+                        .chunks_exact(16)
+                        .filter_map(|b| <[u8; 16]>::try_from(b).ok())
+                        .map(Ipv6Addr::from)
+                        .collect();
+                }
+                OPT_RELAY_MSG if packet.is_relay() && depth < MAX_RELAY_DEPTH => {
+                    packet.relayed = Self::parse_nested(value, depth + 1).map(Box::new);
+                }
+                _ => {}
+            }
+        }
+
+        Some(packet)
+    }
+
+    pub fn is_relay(&self) -> bool {
+        matches!(self.message_type, RELAY_FORW | RELAY_REPL)
+    }
+
+    /// The client or server message, looking through relay messages.
+    pub fn innermost(&self) -> &Dhcpv6Packet {
+        match &self.relayed {
+            Some(relayed) => relayed.innermost(),
+            None => self,
+        }
+    }
+
+    /// Whether the message is sent by a server.
+    pub fn is_server_message(&self) -> bool {
+        matches!(self.message_type, ADVERTISE | REPLY | RECONFIGURE)
+    }
+
+    pub fn info(&self) -> String {
+        if let Some(relayed) = &self.relayed {
+            return format!(
+                "{} ({})",
+                message_type_name(self.message_type),
+                relayed.info()
+            );
+        }
+        let mut info = format!(
+            "{} XID: 0x{:06x}",
+            message_type_name(self.message_type),
+            self.xid
+        );
+        if let Some(duid) = &self.client_duid {
+            info.push_str(&format!(" CID: {}", client_name(duid)));
+        }
+        for (address, _) in &self.addresses {
+            info.push_str(&format!(" IAA: {}", address));
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("dhcpv6.msgtype", self.message_type);
+        if !self.is_relay() {
+            fields.insert("dhcpv6.xid", self.xid);
+        }
+        if let Some(duid) = &self.client_duid {
+            fields.insert("dhcpv6.clientid", duid.clone());
+            if let Some(mac) = duid_link_layer_address(duid) {
+                fields.insert("dhcpv6.duid.link_layer_addr", mac);
+            }
+        }
+        if let Some(duid) = &self.server_duid {
+            fields.insert("dhcpv6.serverid", duid.clone());
+        }
+        for (address, valid) in &self.addresses {
+            fields.insert("dhcpv6.iaaddr.ip", *address);
+            fields.insert("dhcpv6.iaaddr.valid_lft", *valid);
+        }
+        for server in &self.dns_servers {
+            fields.insert("dhcpv6.dns_server", *server);
+        }
+        if let Some(relayed) = &self.relayed {
+            relayed.insert_fields(fields);
+        }
+    }
+}
+
+/// Options with a 16 bit code and a 16 bit length.
+fn options_iter(mut options: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let header = options.get(..4)?;
+        let code = u16::from_be_bytes([header[0], header[1]]);
+        let len = usize::from(u16::from_be_bytes([header[2], header[3]]));
+        let value = options.get(4..4 + len)?;
+        options = &options[4 + len..];
+        Some((code, value))
+    })
+}
+
+/// The MAC address in a DUID-LLT or DUID-LL of an Ethernet interface.
+pub fn duid_link_layer_address(duid: &[u8]) -> Option<[u8; 6]> {
+    let header = duid.get(..4)?;
+    let duid_type = u16::from_be_bytes([header[0], header[1]]);
+    let hardware_type = u16::from_be_bytes([header[2], header[3]]);
+    let address = match duid_type {
+        1 => duid.get(8..)?,
+        3 => duid.get(4..)?,
+        _ => return None,
+    };
+    if hardware_type != 1 {
+        return None;
+    }
+    address.try_into().ok()
+}
+
+/// The client's MAC address if the DUID contains it, otherwise the DUID in hex.
+pub fn client_name(duid: &[u8]) -> String {
+    match duid_link_layer_address(duid) {
+        Some(mac) => format_mac(&mac),
+        None => duid.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option(code: u16, value: &[u8]) -> Vec<u8> {
+        let mut bytes = code.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    fn reply() -> Vec<u8> {
+        let address: Ipv6Addr = "2001:db8::10".parse().unwrap();
+        let mut iaaddr = address.octets().to_vec();
+        iaaddr.extend_from_slice(&3600u32.to_be_bytes());
+        iaaddr.extend_from_slice(&7200u32.to_be_bytes());
+        let mut ia_na = vec![0; 12];
+        ia_na.extend(option(OPT_IAADDR, &iaaddr));
+
+        let mut payload = vec![REPLY, 0x12, 0x34, 0x56];
+        payload.extend(option(OPT_CLIENTID, &[0, 3, 0, 1, 2, 0, 0, 0, 0, 1]));
+        payload.extend(option(OPT_SERVERID, &[0, 3, 0, 1, 2, 0, 0, 0, 0, 0xfe]));
+        payload.extend(option(OPT_IA_NA, &ia_na));
+        payload
+    }
+
+    #[test]
+    fn test_reply_with_address() {
+        let dhcp = Dhcpv6Packet::parse(&reply()).unwrap();
+        assert_eq!(dhcp.xid, 0x123456);
+        assert_eq!(
+            dhcp.addresses,
+            vec![("2001:db8::10".parse().unwrap(), 7200)]
+        );
+        assert_eq!(
+            dhcp.info(),
+            "Reply XID: 0x123456 CID: 02:00:00:00:00:01 IAA: 2001:db8::10"
+        );
+    }
+
+    #[test]
+    fn test_relayed_message() {
+        let mut relay = vec![RELAY_REPL, 0];
+        relay.extend_from_slice(&[0; 32]);
+        relay.extend(option(OPT_RELAY_MSG, &reply()));
+
+        let dhcp = Dhcpv6Packet::parse(&relay).unwrap();
+        assert!(dhcp.is_relay());
+        assert_eq!(dhcp.innermost().message_type, REPLY);
+        assert!(dhcp.info().starts_with("Relay-reply (Reply"));
+    }
+}
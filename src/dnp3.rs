@@ -0,0 +1,622 @@
+use crate::fields::Fields;
+use crate::query::{self, AppMessage, ConnectionState, ConnectionTable, Exchange, QueryTiming};
+use crate::reassembly::{MessageBuffer, StreamData};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+pub const DNP3_PORT: u16 = 20000;
+
+const MAX_PENDING_REQUESTS: usize = 16;
+const MAX_FRAGMENT_LEN: usize = 65536;
+const START: [u8; 2] = [0x05, 0x64];
+// Start bytes, length, control, destination, source and CRC
+const HEADER_LEN: usize = 10;
+const BLOCK_LEN: usize = 16;
+const CRC_LEN: usize = 2;
+
+// Link control bits
+const DIR: u8 = 0x80;
+const PRM: u8 = 0x40;
+
+// Transport header bits
+const FIN: u8 = 0x80;
+const FIR: u8 = 0x40;
+
+const RESPONSE: u8 = 129;
+const UNSOLICITED_RESPONSE: u8 = 130;
+
+/// CRC-16/DNP of link headers and data blocks.
+pub fn crc(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= u16::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xa6bc
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn link_function_name(primary: bool, function: u8) -> &'static str {
+    match (primary, function) {
+        (true, 0) => "Reset of Remote Link",
+        (true, 1) => "Reset of User Process",
+        (true, 2) => "Test Function for Link",
+        (true, 3) => "User Data",
+        (true, 4) => "Unconfirmed User Data",
+        (true, 9) => "Request Link Status",
+        (false, 0) => "ACK",
+        (false, 1) => "NACK",
+        (false, 11) => "Status of Link",
+        (false, 14) => "Link Service Not Functioning",
+        (false, 15) => "Link Service Not Used or Implemented",
+        _ => "Unknown",
+    }
+}
+
+pub fn function_name(function: u8) -> &'static str {
+    match function {
+        0 => "Confirm",
+        1 => "Read",
+        2 => "Write",
+        3 => "Select",
+        4 => "Operate",
+        5 => "Direct Operate",
+        6 => "Direct Operate No Ack",
+        7 => "Immediate Freeze",
+        8 => "Immediate Freeze No Ack",
+        9 => "Freeze and Clear",
+        10 => "Freeze and Clear No Ack",
+        11 => "Freeze with Time",
+        12 => "Freeze with Time No Ack",
+        13 => "Cold Restart",
+        14 => "Warm Restart",
+        15 => "Initialize Data",
+        16 => "Initialize Application",
+        17 => "Start Application",
+        18 => "Stop Application",
+        19 => "Save Configuration",
+        20 => "Enable Spontaneous Messages",
+        21 => "Disable Spontaneous Messages",
+        22 => "Assign Classes",
+        23 => "Delay Measurement",
+        24 => "Record Current Time",
+        25 => "Open File",
+        26 => "Close File",
+        27 => "Delete File",
+        28 => "Get File Info",
+        29 => "Authenticate File",
+        30 => "Abort File",
+        31 => "Activate Config",
+        RESPONSE => "Response",
+        UNSOLICITED_RESPONSE => "Unsolicited Response",
+        131 => "Authentication Response",
+        _ => "Unknown",
+    }
+}
+
+/// Whether the function changes outputs, data or the state of the
+/// outstation.
+pub fn is_write(function: u8) -> bool {
+    matches!(
+        function,
+        2..=6 | 9 | 10 | 13..=22 | 24 | 27
+    )
+}
+
+/// Functions the outstation doesn't answer.
+fn expects_response(function: u8) -> bool {
+    !matches!(function, 0 | 6 | 8 | 10 | 12) && function < RESPONSE
+}
+
+const IIN_NAMES: [(u16, &str); 14] = [
+    (0x0100, "Broadcast Msg Rx"),
+    (0x0200, "Class 1 Data Available"),
+    (0x0400, "Class 2 Data Available"),
+    (0x0800, "Class 3 Data Available"),
+    (0x1000, "Time Sync Required"),
+    (0x2000, "Digital Outputs in Local"),
+    (0x4000, "Device Trouble"),
+    (0x8000, "Device Restart"),
+    (0x0001, "Function Code not implemented"),
+    (0x0002, "Requested Objects Unknown"),
+    (0x0004, "Parameters Invalid or Out of Range"),
+    (0x0008, "Event Buffer Overflow"),
+    (0x0010, "Operation Already Executing"),
+    (0x0020, "Device Configuration Corrupt"),
+];
+
+// IIN2 bits of a request the outstation couldn't carry out
+const IIN_ERRORS: u16 = 0x0001 | 0x0002 | 0x0004 | 0x0020;
+const IIN_NOTES: u16 = 0x4000 | 0x8000;
+
+fn iin_names(iin: u16, mask: u16) -> Vec<&'static str> {
+    IIN_NAMES
+        .iter()
+        .filter(|(bit, _)| iin & mask & bit != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// An application layer fragment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Application {
+    pub control: u8,
+    pub function: u8,
+    /// Internal indications of responses: IIN1 in the high byte.
+    pub iin: Option<u16>,
+    /// Group and variation of the object headers. Only requests that
+    /// carry no object data list more than the first.
+    pub objects: Vec<(u8, u8)>,
+}
+
+impl Application {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let (&control, rest) = data.split_first()?;
+        let (&function, mut rest) = rest.split_first()?;
+        let mut iin = None;
+        if matches!(function, RESPONSE..=131) {
+            let bytes = rest.get(..2)?;
+            iin = Some(u16::from_be_bytes([bytes[0], bytes[1]]));
+            rest = &rest[2..];
+        }
+        Some(Application {
+            control,
+            function,
+            iin,
+            objects: object_headers(rest, function == 1),
+        })
+    }
+
+    pub fn seq(&self) -> u8 {
+        self.control & 0x0f
+    }
+
+    fn describe(&self) -> String {
+        let mut description = function_name(self.function).to_string();
+        if !self.objects.is_empty() {
+            let objects: Vec<_> = self
+                .objects
+                .iter()
+                .map(|(group, variation)| format!("g{}v{}", group, variation))
+                .collect();
+            description.push_str(&format!(", {}", objects.join(" ")));
+        }
+        if let Some(iin) = self.iin {
+            let names = iin_names(iin, u16::MAX);
+            if !names.is_empty() {
+                description.push_str(&format!(", IIN: {}", names.join(", ")));
+            }
+        }
+        description
+    }
+}
+
+/// Reads object headers up to the first one followed by object data,
+/// or all of them when `all` is set.
+fn object_headers(mut data: &[u8], all: bool) -> Vec<(u8, u8)> {
+    let mut objects = Vec::new();
+    while let [group, variation, qualifier, rest @ ..] = data {
+        objects.push((*group, *variation));
+        let range_len = match qualifier & 0x0f {
+            0 | 3 => 2,
+            1 | 4 => 4,
+            2 | 5 => 8,
+            6 => 0,
+            7 | 0x0b => 1,
+            8 => 2,
+            9 => 4,
+            _ => break,
+        };
+        if !all && qualifier & 0x0f != 6 {
+            break;
+        }
+        let Some(rest) = rest.get(range_len..) else {
+            break;
+        };
+        data = rest;
+    }
+    objects
+}
+
+/// The transport header of a frame carrying user data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transport {
+    pub fir: bool,
+    pub fin: bool,
+    pub seq: u8,
+}
+
+/// A DNP3 link layer frame and the application fragment it completes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dnp3Message {
+    /// The frame was sent by a master.
+    pub from_master: bool,
+    pub primary: bool,
+    pub link_function: u8,
+    pub destination: u16,
+    pub source: u16,
+    pub transport: Option<Transport>,
+    pub application: Option<Application>,
+    pub crc_error: bool,
+}
+
+impl Dnp3Message {
+    fn parse(frame: &[u8]) -> Self {
+        let control = frame[3];
+        Dnp3Message {
+            from_master: control & DIR != 0,
+            primary: control & PRM != 0,
+            link_function: control & 0x0f,
+            destination: u16::from_le_bytes([frame[4], frame[5]]),
+            source: u16::from_le_bytes([frame[6], frame[7]]),
+            transport: None,
+            application: None,
+            crc_error: crc(&frame[..8]).to_le_bytes() != frame[8..HEADER_LEN],
+        }
+    }
+
+    /// User data of the frame without the block CRCs.
+    fn user_data(&mut self, frame: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for block in frame[HEADER_LEN..].chunks(BLOCK_LEN + CRC_LEN) {
+            let Some(split) = block.len().checked_sub(CRC_LEN) else {
+                self.crc_error = true;
+                break;
+            };
+            let (block, check) = block.split_at(split);
+            if crc(block).to_le_bytes() != check {
+                self.crc_error = true;
+            }
+            data.extend_from_slice(block);
+        }
+        data
+    }
+
+    fn endpoints(&self) -> String {
+        format!("from {} to {}", self.source, self.destination)
+    }
+
+    fn description(&self) -> String {
+        match (&self.application, self.transport) {
+            (Some(application), _) => application.describe(),
+            (None, Some(transport)) => format!("Transport segment {}", transport.seq),
+            (None, None) => link_function_name(self.primary, self.link_function).to_string(),
+        }
+    }
+}
+
+impl AppMessage for Dnp3Message {
+    const TIME_FIELD: &'static str = "dnp3.al.response_time";
+
+    fn info(&self) -> String {
+        format!("{}, {}", self.endpoints(), self.description())
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("dnp3.src", self.source);
+        fields.insert("dnp3.dst", self.destination);
+        fields.insert("dnp3.ctl.dir", self.from_master);
+        fields.insert("dnp3.ctl.prm", self.primary);
+        let function = if self.primary {
+            "dnp3.ctl.prifunc"
+        } else {
+            "dnp3.ctl.secfunc"
+        };
+        fields.insert(function, self.link_function);
+        if let Some(transport) = self.transport {
+            fields.insert("dnp3.tr.fin", transport.fin);
+            fields.insert("dnp3.tr.fir", transport.fir);
+            fields.insert("dnp3.tr.seq", transport.seq);
+        }
+        if let Some(application) = &self.application {
+            fields.insert("dnp3.al.func", application.function);
+            fields.insert("dnp3.al.seq", application.seq());
+            if let Some(iin) = application.iin {
+                fields.insert("dnp3.al.iin", iin);
+            }
+            for (group, variation) in &application.objects {
+                fields.insert("dnp3.al.obj", u16::from_be_bytes([*group, *variation]));
+            }
+            if is_write(application.function) {
+                fields.insert("dnp3.write", true);
+            }
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        if self.crc_error {
+            return Some("bad CRC".to_string());
+        }
+        let iin = self.application.as_ref()?.iin?;
+        let names = iin_names(iin, IIN_ERRORS);
+        (!names.is_empty()).then(|| names.join(", "))
+    }
+
+    fn note(&self) -> Option<String> {
+        let iin = self.application.as_ref()?.iin?;
+        let names = iin_names(iin, IIN_NOTES);
+        (!names.is_empty()).then(|| format!("Outstation {}: {}", self.source, names.join(", ")))
+    }
+
+    fn warning(&self) -> Option<String> {
+        let application = self.application.as_ref()?;
+        is_write(application.function).then(|| {
+            format!(
+                "DNP3 {} {}",
+                function_name(application.function),
+                self.endpoints()
+            )
+        })
+    }
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Direction {
+    buffer: MessageBuffer,
+    /// Transport segments of the fragment being reassembled.
+    fragment: Vec<u8>,
+    /// Sequence number of the next segment, while reassembling.
+    next_seq: Option<u8>,
+}
+
+impl AsMut<MessageBuffer> for Direction {
+    fn as_mut(&mut self) -> &mut MessageBuffer {
+        &mut self.buffer
+    }
+}
+
+impl Direction {
+    /// Adds a transport segment, returning the fragment it completes.
+    fn segment(&mut self, transport: Transport, data: &[u8]) -> Option<Vec<u8>> {
+        if transport.fir {
+            self.fragment.clear();
+        } else if self.next_seq != Some(transport.seq) {
+            // A segment is missing, drop the fragment
+            self.fragment.clear();
+            self.next_seq = None;
+            return None;
+        }
+        if self.fragment.len() + data.len() > MAX_FRAGMENT_LEN {
+            self.fragment.clear();
+            self.next_seq = None;
+            return None;
+        }
+        self.fragment.extend_from_slice(data);
+        if transport.fin {
+            self.next_seq = None;
+            return Some(std::mem::take(&mut self.fragment));
+        }
+        self.next_seq = Some((transport.seq + 1) & 0x3f);
+        None
+    }
+}
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// Request time and description of requests by application sequence
+    /// number.
+    pending: HashMap<u8, (DateTime<Utc>, String)>,
+}
+
+impl ConnectionState for Connection {
+    type Direction = Direction;
+
+    fn gap(&mut self, direction: &mut Direction, _: bool) {
+        direction.fragment.clear();
+        direction.next_seq = None;
+    }
+}
+
+/// Splits DNP3 over TCP or UDP into link frames, reassembles transport
+/// segments into application fragments and matches responses to requests.
+#[derive(Debug, Default)]
+pub struct Dnp3Tracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl Dnp3Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the link frames in stream data or a datagram, with the
+    /// application fragments they complete.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+        timestamp: DateTime<Utc>,
+    ) -> Exchange<Dnp3Message> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (direction, connection) = connection.split(from_client);
+
+        direction.buffer.push(&stream.data);
+        loop {
+            let data = direction.buffer.data();
+            if !data.starts_with(&START) {
+                // Skip to the next frame after missing or garbled data
+                let skip = data
+                    .windows(2)
+                    .position(|window| window == START)
+                    .unwrap_or(data.len().saturating_sub(1));
+                if skip == 0 {
+                    break;
+                }
+                direction.buffer.take(skip);
+                continue;
+            }
+            let Some(&length) = data.get(2) else {
+                break;
+            };
+            if length < 5 {
+                direction.buffer.take(START.len());
+                continue;
+            }
+            let data_len = usize::from(length) - 5;
+            let frame_len = HEADER_LEN + data_len + CRC_LEN * data_len.div_ceil(BLOCK_LEN);
+            let Some(frame) = direction.buffer.take(frame_len) else {
+                break;
+            };
+
+            let mut message = Dnp3Message::parse(&frame);
+            let user_data = message.user_data(&frame);
+            if let (true, 3 | 4, Some((&header, segment))) = (
+                message.primary,
+                message.link_function,
+                user_data.split_first(),
+            ) {
+                let transport = Transport {
+                    fir: header & FIR != 0,
+                    fin: header & FIN != 0,
+                    seq: header & 0x3f,
+                };
+                message.transport = Some(transport);
+                if !message.crc_error {
+                    message.application = direction
+                        .segment(transport, segment)
+                        .and_then(|fragment| Application::parse(&fragment));
+                }
+            }
+
+            if let Some(application) = &message.application {
+                if expects_response(application.function) {
+                    if connection.pending.len() >= MAX_PENDING_REQUESTS {
+                        connection.pending.clear();
+                    }
+                    connection
+                        .pending
+                        .insert(application.seq(), (timestamp, message.info()));
+                } else if application.function == RESPONSE {
+                    if let Some((sent, query)) = connection.pending.remove(&application.seq()) {
+                        exchange.timings.extend(
+                            query::latency(sent, timestamp)
+                                .map(|latency| QueryTiming { query, latency }),
+                        );
+                    }
+                }
+            }
+            exchange.messages.push(message);
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT as MASTER, SERVER as OUTSTATION};
+
+    /// Builds a frame with the given link control and user data.
+    pub(crate) fn frame(control: u8, destination: u16, source: u16, data: &[u8]) -> Vec<u8> {
+        let mut frame = START.to_vec();
+        frame.push(data.len() as u8 + 5);
+        frame.push(control);
+        frame.extend_from_slice(&destination.to_le_bytes());
+        frame.extend_from_slice(&source.to_le_bytes());
+        frame.extend_from_slice(&crc(&frame).to_le_bytes());
+        for block in data.chunks(BLOCK_LEN) {
+            frame.extend_from_slice(block);
+            frame.extend_from_slice(&crc(block).to_le_bytes());
+        }
+        frame
+    }
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(crc(b"123456789"), 0xea82);
+        let reset = [0x05, 0x64, 0x05, 0xc0, 0x01, 0x00, 0x00, 0x04];
+        assert_eq!(crc(&reset).to_le_bytes(), [0xe9, 0x21]);
+    }
+
+    #[test]
+    fn test_read_class_data() {
+        let mut tracker = Dnp3Tracker::new();
+        let start = Utc::now();
+        // Read class 1, 2, 3 and 0 data
+        let request = frame(
+            0xc4,
+            10,
+            1,
+            &[0xc0, 0xc3, 1, 60, 2, 6, 60, 3, 6, 60, 4, 6, 60, 1, 6],
+        );
+        let exchange = tracker.process(
+            MASTER,
+            OUTSTATION,
+            true,
+            &StreamData::complete(&request),
+            start,
+        );
+        assert_eq!(
+            exchange.messages[0].info(),
+            "from 1 to 10, Read, g60v2 g60v3 g60v4 g60v1"
+        );
+        assert!(exchange.messages[0].warning().is_none());
+
+        // A response split over two transport segments, reporting a restart
+        let mut response = frame(0x44, 1, 10, &[0x40, 0xc3, RESPONSE, 0x80, 0x00]);
+        response.extend(frame(0x44, 1, 10, &[0x81, 1, 2, 0x00, 0, 0, 0x81]));
+        let exchange = tracker.process(
+            MASTER,
+            OUTSTATION,
+            false,
+            &StreamData::complete(&response),
+            start + chrono::Duration::milliseconds(30),
+        );
+        assert_eq!(
+            exchange.messages[0].info(),
+            "from 10 to 1, Transport segment 0"
+        );
+        let message = &exchange.messages[1];
+        assert_eq!(
+            message.info(),
+            "from 10 to 1, Response, g1v2, IIN: Device Restart"
+        );
+        assert_eq!(message.note().unwrap(), "Outstation 10: Device Restart");
+        assert_eq!(exchange.timings[0].latency, 0.03);
+    }
+
+    #[test]
+    fn test_operate_and_errors() {
+        let mut tracker = Dnp3Tracker::new();
+        let now = Utc::now();
+        let mut data = frame(0xc4, 10, 1, &[0xc0, 0xc1, 5, 12, 1, 0x28, 1, 0, 0, 0]);
+        // Trailing garbage before the next frame is skipped
+        data.extend_from_slice(&[0xff, 0x05]);
+        let response = frame(0x44, 1, 10, &[0xc1, 0xc1, RESPONSE, 0x00, 0x02]);
+        data.extend_from_slice(&response);
+        let exchange = tracker.process(MASTER, OUTSTATION, true, &StreamData::complete(&data), now);
+        assert_eq!(
+            exchange.messages[0].warning().unwrap(),
+            "DNP3 Direct Operate from 1 to 10"
+        );
+        assert_eq!(
+            exchange.messages[1].error().unwrap(),
+            "Requested Objects Unknown"
+        );
+
+        let mut corrupt = frame(0x44, 1, 10, &[0xc2, 0xc2, RESPONSE, 0, 0]);
+        corrupt[12] ^= 0xff;
+        let exchange = tracker.process(
+            MASTER,
+            OUTSTATION,
+            false,
+            &StreamData::complete(&corrupt),
+            now,
+        );
+        assert_eq!(exchange.messages[0].error().unwrap(), "bad CRC");
+        assert!(exchange.messages[0].application.is_none());
+    }
+}
@@ -0,0 +1,29 @@
+/// How much attention an expert item deserves, in increasing order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Chat,
+    Note,
+    Warning,
+    Error,
+}
+
+/// A diagnostic raised by the analyzer about a single packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpertInfo {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl ExpertInfo {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+/// Returns the highest severity in `items`, if any.
+pub fn max_severity(items: &[ExpertInfo]) -> Option<Severity> {
+    items.iter().map(|e| e.severity).max()
+}
@@ -0,0 +1,809 @@
+use crate::http1::{self, find, starts_message, Head, StartLine, HEADER_END};
+use crate::inflate;
+use crate::reassembly::StreamData;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+
+// Upper bounds on buffered state so a long capture can't grow without limit
+const MAX_CONNECTIONS: usize = 65536;
+const MAX_OBJECT_LEN: usize = 64 << 20;
+const MAX_HEADER_LEN: usize = 1 << 16;
+
+type Endpoint = (IpAddr, u16);
+
+/// A file carried by the capture, as it was sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedObject {
+    pub protocol: &'static str,
+    /// The endpoint that sent the object.
+    pub source: Endpoint,
+    pub destination: Endpoint,
+    /// URL of an HTTP object, or the path of an FTP transfer.
+    pub name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+    /// Data was lost, or the transfer ended before the object did.
+    pub truncated: bool,
+}
+
+impl ExportedObject {
+    pub fn sha256(&self) -> String {
+        Sha256::digest(&self.data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// A name to store the object under that can't leave the export
+    /// directory, numbered to tell objects with the same name apart.
+    pub fn file_name(&self, index: usize) -> String {
+        let path = self.name.split(['?', '#']).next().unwrap_or_default();
+        let base: String = path
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let base = base.trim_start_matches('.');
+        let base = if base.is_empty() { "object" } else { base };
+        match extension(&self.mime_type).filter(|_| !base.contains('.')) {
+            Some(extension) => format!("{:04}-{}.{}", index, base, extension),
+            None => format!("{:04}-{}", index, base),
+        }
+    }
+}
+
+/// A line of the manifest written next to the exported objects.
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    file: String,
+    protocol: &'static str,
+    source: String,
+    destination: String,
+    url: String,
+    mime_type: String,
+    size: usize,
+    sha256: String,
+    truncated: bool,
+}
+
+/// Writes `objects` to `dir`, with a `manifest.json` listing where each
+/// came from. Files of an earlier export to `dir` are overwritten.
+pub fn write_objects(dir: &Path, objects: &[ExportedObject]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut manifest = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        let file = object.file_name(index + 1);
+        fs::write(dir.join(&file), &object.data)?;
+        manifest.push(ManifestEntry {
+            file,
+            protocol: object.protocol,
+            source: SocketAddr::from(object.source).to_string(),
+            destination: SocketAddr::from(object.destination).to_string(),
+            url: object.name.clone(),
+            mime_type: object.mime_type.clone(),
+            size: object.data.len(),
+            sha256: object.sha256(),
+            truncated: object.truncated,
+        });
+    }
+    let manifest = serde_json::to_string_pretty(&manifest).map_err(io::Error::other)?;
+    fs::write(dir.join("manifest.json"), manifest + "\n")
+}
+
+/// Guesses the MIME type of data sent without one from its first bytes.
+pub fn sniff_mime_type(data: &[u8]) -> &'static str {
+    const SIGNATURES: [(&[u8], &str); 8] = [
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF8", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\x7fELF", "application/x-executable"),
+        (b"MZ", "application/vnd.microsoft.portable-executable"),
+    ];
+    if let Some((_, mime_type)) = SIGNATURES.iter().find(|(magic, _)| data.starts_with(magic)) {
+        return mime_type;
+    }
+    match std::str::from_utf8(data) {
+        Ok(text) => {
+            let start = text.trim_start().get(..14).unwrap_or_default();
+            if start.eq_ignore_ascii_case("<!doctype html") || start.starts_with("<html") {
+                "text/html"
+            } else {
+                "text/plain"
+            }
+        }
+        Err(_) => "application/octet-stream",
+    }
+}
+
+/// File name extension for the common MIME types.
+fn extension(mime_type: &str) -> Option<&'static str> {
+    Some(match mime_type {
+        "text/html" => "html",
+        "text/plain" => "txt",
+        "text/css" => "css",
+        "application/javascript" | "text/javascript" => "js",
+        "application/json" => "json",
+        "application/xml" | "text/xml" => "xml",
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "application/pdf" => "pdf",
+        "application/zip" => "zip",
+        "application/gzip" => "gz",
+        _ => return None,
+    })
+}
+
+/// Follows HTTP/1.x connections and FTP data connections in their
+/// reassembled streams, collecting the files sent over them. The default
+/// one only reports HTTP heads.
+#[derive(Debug, Default)]
+pub struct ObjectExtractor {
+    http: HashMap<(Endpoint, Endpoint), HttpConnection>,
+    ftp: HashMap<(Endpoint, Endpoint), ExportedObject>,
+    /// Without it only the HTTP heads are reported.
+    export: bool,
+}
+
+/// The HTTP requests and responses whose heads a segment completed, and the
+/// objects whose bodies it completed.
+#[derive(Debug, Default)]
+pub struct HttpUpdate {
+    pub heads: Vec<Head>,
+    pub objects: Vec<ExportedObject>,
+}
+
+#[derive(Debug, Default)]
+struct HttpConnection {
+    /// Directions by whether they're sent from the lower endpoint.
+    directions: [HttpDirection; 2],
+    /// Methods and URLs of the requests still waiting for a response.
+    requests: VecDeque<(String, String)>,
+    /// The connection switched to another protocol.
+    upgraded: bool,
+    /// Bodies are kept to be exported.
+    export: bool,
+}
+
+#[derive(Debug, Default)]
+struct HttpDirection {
+    buffer: Vec<u8>,
+    message: Option<Message>,
+    /// The data isn't HTTP, or lost where the next message starts.
+    lost: bool,
+    closed: bool,
+}
+
+/// An HTTP message whose body is being received.
+#[derive(Debug)]
+struct Message {
+    url: String,
+    content_type: Option<String>,
+    content_encoding: Option<String>,
+    body: Body,
+    data: Vec<u8>,
+    /// The body is only skipped over.
+    discard: bool,
+    truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Body {
+    /// This many bytes are left.
+    Length(usize),
+    Chunked(Chunk),
+    UntilClose,
+    Complete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Chunk {
+    Size,
+    Data(usize),
+    /// The line break after the data.
+    DataEnd,
+    Trailer,
+}
+
+impl ObjectExtractor {
+    /// An extractor keeping the files sent.
+    pub fn new() -> Self {
+        Self::default().with_export(true)
+    }
+
+    /// Whether to keep the files sent, or only report the HTTP heads.
+    pub fn with_export(mut self, enabled: bool) -> Self {
+        self.export = enabled;
+        self
+    }
+
+    /// Adds stream data sent from `src` to `dst` and returns the HTTP heads
+    /// and objects it completes.
+    pub fn process(&mut self, src: Endpoint, dst: Endpoint, stream: &StreamData) -> HttpUpdate {
+        let mut update = HttpUpdate::default();
+        let key = if src <= dst { (src, dst) } else { (dst, src) };
+        if !self.http.contains_key(&key) {
+            if !starts_message(&stream.data) {
+                return update;
+            }
+            if self.http.len() >= MAX_CONNECTIONS {
+                self.http.clear();
+            }
+        }
+
+        let export = self.export;
+        let connection = self.http.entry(key).or_insert_with(|| HttpConnection {
+            export,
+            ..Default::default()
+        });
+        if connection.upgraded {
+            return update;
+        }
+        let direction = &mut connection.directions[usize::from(src != key.0)];
+        if stream.gap {
+            // Whatever was being received is missing data now
+            if let Some(mut message) = direction.message.take() {
+                message.truncated = true;
+                update.objects.extend(message.into_object(src, dst));
+            }
+            direction.buffer.clear();
+            direction.lost = true;
+        }
+        if direction.lost && starts_message(&stream.data) {
+            direction.lost = false;
+        }
+        if direction.lost {
+            return update;
+        }
+
+        direction.buffer.extend_from_slice(&stream.data);
+        while let Some(message) = direction.receive(
+            dst,
+            connection.export,
+            &mut connection.requests,
+            &mut update.heads,
+        ) {
+            match message {
+                Received::Message(message) => update.objects.extend(message.into_object(src, dst)),
+                Received::Upgrade => {
+                    connection.upgraded = true;
+                    break;
+                }
+            }
+        }
+        update
+    }
+
+    /// Adds data of an FTP data connection. The file is complete once the
+    /// connection closes.
+    pub fn ftp_data(
+        &mut self,
+        src: Endpoint,
+        dst: Endpoint,
+        command: Option<&str>,
+        stream: &StreamData,
+    ) {
+        if !self.export {
+            return;
+        }
+        let key = if src <= dst { (src, dst) } else { (dst, src) };
+        if !self.ftp.contains_key(&key) && self.ftp.len() >= MAX_CONNECTIONS {
+            self.ftp.clear();
+        }
+        let object = self.ftp.entry(key).or_insert_with(|| {
+            // "RETR path", or a listing
+            let command = command.unwrap_or_default();
+            let name = match command.split_once(' ') {
+                Some((_, path)) => path.trim().to_string(),
+                None => command.to_string(),
+            };
+            ExportedObject {
+                protocol: "FTP-DATA",
+                source: src,
+                destination: dst,
+                name,
+                mime_type: String::new(),
+                data: Vec::new(),
+                truncated: false,
+            }
+        });
+        object.truncated |= stream.gap;
+        let room = MAX_OBJECT_LEN.saturating_sub(object.data.len());
+        object.truncated |= stream.data.len() > room;
+        object
+            .data
+            .extend_from_slice(&stream.data[..stream.data.len().min(room)]);
+    }
+
+    /// Ends the stream from `src` to `dst`, or the whole connection when it
+    /// was reset, and returns the objects that ended with it.
+    pub fn close(&mut self, src: Endpoint, dst: Endpoint, reset: bool) -> Vec<ExportedObject> {
+        let key = if src <= dst { (src, dst) } else { (dst, src) };
+        let mut objects = Vec::new();
+        if let Some(object) = self.ftp.remove(&key) {
+            objects.extend(finish_ftp(object));
+        }
+
+        let Some(connection) = self.http.get_mut(&key) else {
+            return objects;
+        };
+        let sending = usize::from(src != key.0);
+        let ends = if reset {
+            vec![(sending, src, dst), (1 - sending, dst, src)]
+        } else {
+            vec![(sending, src, dst)]
+        };
+        for (index, from, to) in ends {
+            let direction = &mut connection.directions[index];
+            direction.closed = true;
+            if let Some(message) = direction.message.take() {
+                objects.extend(message.end().into_object(from, to));
+            }
+        }
+        if connection
+            .directions
+            .iter()
+            .all(|direction| direction.closed)
+        {
+            self.http.remove(&key);
+        }
+        objects
+    }
+
+    /// Returns the objects still being received, at the end of a capture.
+    pub fn finish(&mut self) -> Vec<ExportedObject> {
+        let mut objects: Vec<_> = self
+            .ftp
+            .drain()
+            .filter_map(|(_, o)| finish_ftp(o))
+            .collect();
+        for ((low, high), connection) in self.http.drain() {
+            let [from_low, from_high] = connection.directions;
+            for (direction, src, dst) in [(from_low, low, high), (from_high, high, low)] {
+                if let Some(message) = direction.message {
+                    objects.extend(message.end().into_object(src, dst));
+                }
+            }
+        }
+        objects
+    }
+}
+
+fn finish_ftp(mut object: ExportedObject) -> Option<ExportedObject> {
+    if object.data.is_empty() {
+        return None;
+    }
+    object.mime_type = sniff_mime_type(&object.data).to_string();
+    Some(object)
+}
+
+/// What the data of a direction completed.
+enum Received {
+    Message(Message),
+    /// The connection switched protocols, e.g. to WebSocket.
+    Upgrade,
+}
+
+impl HttpDirection {
+    /// Takes the next message whose body is complete off the buffer, adding
+    /// the heads it reads to `heads`. Bodies are only kept for `export`.
+    fn receive(
+        &mut self,
+        dst: Endpoint,
+        export: bool,
+        requests: &mut VecDeque<(String, String)>,
+        heads: &mut Vec<Head>,
+    ) -> Option<Received> {
+        loop {
+            let Some(message) = &mut self.message else {
+                let start = self.buffer.iter().position(|b| !b"\r\n".contains(b))?;
+                self.buffer.drain(..start);
+                let Some(end) = find(&self.buffer, HEADER_END) else {
+                    if self.buffer.len() > MAX_HEADER_LEN || !starts_message(&self.buffer) {
+                        self.buffer.clear();
+                        self.lost = true;
+                    }
+                    return None;
+                };
+                let head = Head::parse(&self.buffer[..end]);
+                self.buffer.drain(..end + HEADER_END.len());
+                let header = head.and_then(|head| {
+                    let header = parse_header(&head, dst, requests);
+                    heads.push(head);
+                    header
+                });
+                match header {
+                    Some(Header::Message(mut message)) => {
+                        message.discard = !export;
+                        self.message = Some(message);
+                    }
+                    Some(Header::Upgrade) => return Some(Received::Upgrade),
+                    Some(Header::NoBody) => {}
+                    None => {
+                        self.buffer.clear();
+                        self.lost = true;
+                        return None;
+                    }
+                }
+                continue;
+            };
+
+            if !message.receive(&mut self.buffer) {
+                return None;
+            }
+            return self.message.take().map(Received::Message);
+        }
+    }
+}
+
+impl Message {
+    /// Moves body bytes out of `buffer`, returning whether the body is
+    /// complete.
+    fn receive(&mut self, buffer: &mut Vec<u8>) -> bool {
+        loop {
+            match self.body {
+                Body::Complete => return true,
+                Body::Length(left) => {
+                    let len = left.min(buffer.len());
+                    self.append(buffer.drain(..len).as_slice());
+                    self.body = if len == left {
+                        Body::Complete
+                    } else {
+                        Body::Length(left - len)
+                    };
+                    if len < left {
+                        return false;
+                    }
+                }
+                Body::UntilClose => {
+                    self.append(buffer.drain(..).as_slice());
+                    return false;
+                }
+                Body::Chunked(Chunk::Size) => {
+                    let Some(end) = find(buffer, b"\r\n") else {
+                        return false;
+                    };
+                    let line = String::from_utf8_lossy(&buffer[..end]).into_owned();
+                    buffer.drain(..end + 2);
+                    // Extensions follow the size after a semicolon
+                    let size = line.split(';').next().unwrap_or_default().trim();
+                    self.body = match usize::from_str_radix(size, 16) {
+                        Ok(0) => Body::Chunked(Chunk::Trailer),
+                        Ok(size) => Body::Chunked(Chunk::Data(size)),
+                        Err(_) => {
+                            self.truncated = true;
+                            buffer.clear();
+                            Body::Complete
+                        }
+                    };
+                }
+                Body::Chunked(Chunk::Data(left)) => {
+                    let len = left.min(buffer.len());
+                    self.append(buffer.drain(..len).as_slice());
+                    if len < left {
+                        self.body = Body::Chunked(Chunk::Data(left - len));
+                        return false;
+                    }
+                    self.body = Body::Chunked(Chunk::DataEnd);
+                }
+                Body::Chunked(Chunk::DataEnd) => {
+                    if buffer.len() < 2 {
+                        return false;
+                    }
+                    buffer.drain(..2);
+                    self.body = Body::Chunked(Chunk::Size);
+                }
+                Body::Chunked(Chunk::Trailer) => {
+                    let Some(end) = find(buffer, b"\r\n") else {
+                        return false;
+                    };
+                    buffer.drain(..end + 2);
+                    if end == 0 {
+                        self.body = Body::Complete;
+                    }
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, data: &[u8]) {
+        if self.discard {
+            return;
+        }
+        let room = MAX_OBJECT_LEN.saturating_sub(self.data.len());
+        self.truncated |= data.len() > room;
+        self.data.extend_from_slice(&data[..data.len().min(room)]);
+    }
+
+    /// The connection closed, which only completes bodies sent until then.
+    fn end(mut self) -> Self {
+        self.truncated |= self.body != Body::UntilClose;
+        self
+    }
+
+    /// The object with its content encoding removed, unless there's no
+    /// body.
+    fn into_object(self, source: Endpoint, destination: Endpoint) -> Option<ExportedObject> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let decoded = match self.content_encoding.as_deref() {
+            Some("gzip" | "x-gzip") => inflate::gunzip(&self.data),
+            // Meant to be zlib, but some servers send raw DEFLATE
+            Some("deflate") => {
+                inflate::zlib_decompress(&self.data).or_else(|| inflate::inflate(&self.data))
+            }
+            _ => None,
+        };
+        let data = decoded.unwrap_or(self.data);
+        let mime_type = self
+            .content_type
+            .unwrap_or_else(|| sniff_mime_type(&data).to_string());
+        Some(ExportedObject {
+            protocol: "HTTP",
+            source,
+            destination,
+            name: self.url,
+            mime_type,
+            data,
+            truncated: self.truncated,
+        })
+    }
+}
+
+enum Header {
+    Message(Message),
+    NoBody,
+    Upgrade,
+}
+
+/// Reads how the body of a request or response sent to `dst` is framed.
+fn parse_header(
+    head: &Head,
+    dst: Endpoint,
+    requests: &mut VecDeque<(String, String)>,
+) -> Option<Header> {
+    let chunked = head
+        .header("transfer-encoding")
+        .is_some_and(|value| value.to_ascii_lowercase().contains("chunked"));
+    let length = head
+        .header("content-length")
+        .and_then(|value| value.parse::<usize>().ok());
+
+    let (url, body) = match &head.start_line {
+        StartLine::Response { status, .. } => {
+            let status = *status;
+            if status == http1::SWITCHING_PROTOCOLS {
+                return Some(Header::Upgrade);
+            }
+            if status < 200 {
+                // Interim responses come before the final one
+                return Some(Header::NoBody);
+            }
+            let (method, url) = requests.pop_front().unwrap_or_default();
+            if method == "CONNECT" && status < 300 {
+                return Some(Header::Upgrade);
+            }
+            if method == "HEAD" || status == 204 || status == 304 {
+                return Some(Header::NoBody);
+            }
+            let body = match (chunked, length) {
+                (true, _) => Body::Chunked(Chunk::Size),
+                (false, Some(length)) => Body::Length(length),
+                (false, None) => Body::UntilClose,
+            };
+            (url, body)
+        }
+        StartLine::Request { method, target } => {
+            let url = if target.contains("://") {
+                target.clone()
+            } else {
+                let host = head
+                    .header("host")
+                    .map(str::to_string)
+                    .unwrap_or_else(|| SocketAddr::from(dst).to_string());
+                format!("http://{}{}", host, target)
+            };
+            requests.push_back((method.clone(), url.clone()));
+            let body = match (chunked, length) {
+                (true, _) => Body::Chunked(Chunk::Size),
+                (false, Some(length)) => Body::Length(length),
+                (false, None) => return Some(Header::NoBody),
+            };
+            (url, body)
+        }
+    };
+
+    let mut message = Message {
+        url,
+        content_type: head.header("content-type").map(|value| {
+            let mime_type = value.split(';').next().unwrap_or_default();
+            mime_type.trim().to_ascii_lowercase()
+        }),
+        content_encoding: head
+            .header("content-encoding")
+            .map(|value| value.to_ascii_lowercase()),
+        body,
+        data: Vec::new(),
+        discard: false,
+        truncated: false,
+    };
+    if message.body == Body::Length(0) {
+        message.body = Body::Complete;
+    }
+    Some(Header::Message(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_http_objects() {
+        let mut extractor = ObjectExtractor::new();
+        let request = b"GET /files/report.pdf HTTP/1.1\r\nHost: example.com\r\n\r\n\
+                        HEAD /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n\
+                        GET /data HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let update = extractor.process(CLIENT, SERVER, &StreamData::complete(request));
+        assert!(update.objects.is_empty());
+        assert_eq!(update.heads.len(), 3);
+        assert_eq!(update.heads[1].method(), Some("HEAD"));
+
+        // The first body arrives in two segments
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\n\
+                         Content-Length: 9\r\n\r\n%PDF-";
+        assert!(extractor
+            .process(SERVER, CLIENT, &StreamData::complete(response))
+            .objects
+            .is_empty());
+        let mut rest = b"1.7\nHTTP/1.1 200 OK\r\nContent-Length: 120\r\n\r\n".to_vec();
+        rest.extend_from_slice(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n");
+        rest.extend_from_slice(b"6;ext=1\r\nhello,\r\n7\r\n chunks\r\n0\r\n\r\n");
+        let objects = extractor
+            .process(SERVER, CLIENT, &StreamData::complete(&rest))
+            .objects;
+
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].name, "http://example.com/files/report.pdf");
+        assert_eq!(objects[0].mime_type, "application/pdf");
+        assert_eq!(objects[0].data, b"%PDF-1.7\n");
+        assert_eq!(
+            (objects[0].source, objects[0].destination),
+            (SERVER, CLIENT)
+        );
+        assert!(!objects[0].truncated);
+        assert_eq!(objects[0].file_name(1), "0001-report.pdf");
+        assert_eq!(
+            objects[0].sha256(),
+            "0716f9264c9fe19f5d7455276107f3ddcc1d3497f63d60689a73558ae8a1bf5e"
+        );
+
+        // The HEAD response had no body despite its length
+        assert_eq!(objects[1].name, "http://example.com/data");
+        assert_eq!(objects[1].data, b"hello, chunks");
+        assert_eq!(objects[1].mime_type, "text/plain");
+        assert_eq!(objects[1].file_name(2), "0002-data.txt");
+    }
+
+    #[test]
+    fn test_content_encoding_and_close() {
+        let mut extractor = ObjectExtractor::new();
+        let upload = b"POST /upload HTTP/1.1\r\nHost: h\r\nContent-Length: 4\r\n\r\nabcd";
+        let objects = extractor
+            .process(CLIENT, SERVER, &StreamData::complete(upload))
+            .objects;
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].name, "http://h/upload");
+        assert_eq!(
+            (objects[0].source, objects[0].destination),
+            (CLIENT, SERVER)
+        );
+
+        let gzip = hex("1f8b0800000000000203cb48cdc9c9d75148afca2ce00200861f82a40c000000");
+        let mut response =
+            b"HTTP/1.0 200 OK\r\nContent-Encoding: gzip\r\nContent-Type: text/plain\r\n\r\n"
+                .to_vec();
+        response.extend_from_slice(&gzip);
+        assert!(extractor
+            .process(SERVER, CLIENT, &StreamData::complete(&response))
+            .objects
+            .is_empty());
+
+        // Without a length, the body ends with the connection
+        let objects = extractor.close(SERVER, CLIENT, false);
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].data, b"hello, gzip\n");
+        assert!(!objects[0].truncated);
+
+        // A body cut short by the end of the capture is truncated
+        let request = b"GET /big HTTP/1.1\r\nHost: h\r\n\r\n";
+        extractor.process(CLIENT, SERVER, &StreamData::complete(request));
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\npartial";
+        extractor.process(SERVER, CLIENT, &StreamData::complete(response));
+        let objects = extractor.finish();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].data, b"partial");
+        assert!(objects[0].truncated);
+
+        // Other protocols aren't tracked
+        let tls = StreamData::complete(&[0x16, 0x03, 0x01, 0x00, 0x05]);
+        assert!(extractor.process(CLIENT, SERVER, &tls).heads.is_empty());
+        assert!(extractor.http.is_empty());
+
+        // Without export, heads are still read but bodies aren't kept
+        let mut extractor = ObjectExtractor::new().with_export(false);
+        let update = extractor.process(CLIENT, SERVER, &StreamData::complete(upload));
+        assert!(update.objects.is_empty());
+        assert_eq!(update.heads[0].header("content-length"), Some("4"));
+        assert_eq!(
+            extractor
+                .process(CLIENT, SERVER, &StreamData::complete(request))
+                .heads
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_ftp_objects_and_manifest() {
+        let mut extractor = ObjectExtractor::new();
+        let data_port = (SERVER.0, 20);
+        extractor.ftp_data(
+            data_port,
+            CLIENT,
+            Some("RETR docs/../secret.png"),
+            &StreamData::complete(b"\x89PNG\r\n\x1a\nIHDR"),
+        );
+        extractor.ftp_data(
+            data_port,
+            CLIENT,
+            Some("RETR docs/../secret.png"),
+            &StreamData::complete(b"more"),
+        );
+        let objects = extractor.close(data_port, CLIENT, false);
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].protocol, "FTP-DATA");
+        assert_eq!(objects[0].name, "docs/../secret.png");
+        assert_eq!(objects[0].mime_type, "image/png");
+        assert_eq!(objects[0].data.len(), 16);
+        assert_eq!(objects[0].file_name(3), "0003-secret.png");
+
+        let dir = std::env::temp_dir().join(format!("ferriscope-export-{}", std::process::id()));
+        write_objects(&dir, &objects).unwrap();
+        assert_eq!(
+            fs::read(dir.join("0001-secret.png")).unwrap(),
+            objects[0].data
+        );
+        let manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("manifest.json")).unwrap()).unwrap();
+        assert_eq!(manifest[0]["file"], "0001-secret.png");
+        assert_eq!(manifest[0]["source"], "10.0.0.2:20");
+        assert_eq!(manifest[0]["url"], "docs/../secret.png");
+        assert_eq!(manifest[0]["sha256"], objects[0].sha256());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
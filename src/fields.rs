@@ -0,0 +1,202 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// A typed value extracted by one of the dissectors.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Bool(bool),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Ip(IpAddr),
+    Mac([u8; 6]),
+    Bytes(Vec<u8>),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Bool(b) => write!(f, "{}", b),
+            FieldValue::UInt(n) => write!(f, "{}", n),
+            FieldValue::Float(x) => write!(f, "{:.6}", x),
+            FieldValue::Str(s) => write!(f, "{}", s),
+            FieldValue::Ip(ip) => write!(f, "{}", ip),
+            FieldValue::Mac(mac) => write!(f, "{}", format_mac(mac)),
+            FieldValue::Bytes(bytes) => {
+                for byte in bytes {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        FieldValue::Bool(value)
+    }
+}
+
+impl From<u8> for FieldValue {
+    fn from(value: u8) -> Self {
+        FieldValue::UInt(value.into())
+    }
+}
+
+impl From<u16> for FieldValue {
+    fn from(value: u16) -> Self {
+        FieldValue::UInt(value.into())
+    }
+}
+
+impl From<u32> for FieldValue {
+    fn from(value: u32) -> Self {
+        FieldValue::UInt(value.into())
+    }
+}
+
+impl From<u64> for FieldValue {
+    fn from(value: u64) -> Self {
+        FieldValue::UInt(value)
+    }
+}
+
+impl From<usize> for FieldValue {
+    fn from(value: usize) -> Self {
+        FieldValue::UInt(value as u64)
+    }
+}
+
+impl From<f64> for FieldValue {
+    fn from(value: f64) -> Self {
+        FieldValue::Float(value)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::Str(value.to_string())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        FieldValue::Str(value)
+    }
+}
+
+impl From<IpAddr> for FieldValue {
+    fn from(value: IpAddr) -> Self {
+        FieldValue::Ip(value)
+    }
+}
+
+impl From<Ipv4Addr> for FieldValue {
+    fn from(value: Ipv4Addr) -> Self {
+        FieldValue::Ip(IpAddr::V4(value))
+    }
+}
+
+impl From<Ipv6Addr> for FieldValue {
+    fn from(value: Ipv6Addr) -> Self {
+        FieldValue::Ip(IpAddr::V6(value))
+    }
+}
+
+impl From<[u8; 6]> for FieldValue {
+    fn from(value: [u8; 6]) -> Self {
+        FieldValue::Mac(value)
+    }
+}
+
+impl From<Vec<u8>> for FieldValue {
+    fn from(value: Vec<u8>) -> Self {
+        FieldValue::Bytes(value)
+    }
+}
+
+/// Named fields of a dissected packet, in the order the dissectors added them.
+///
+/// A name may occur more than once, e.g. `ip.addr` holds both the source and
+/// the destination address.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fields {
+    entries: Vec<(&'static str, FieldValue)>,
+}
+
+impl Fields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &'static str, value: impl Into<FieldValue>) {
+        self.entries.push((name, value.into()));
+    }
+
+    /// Returns the first value stored under `name`.
+    pub fn get(&self, name: &str) -> Option<&FieldValue> {
+        self.entries
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a FieldValue> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| *n == name)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.iter().any(|(n, _)| *n == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &FieldValue)> {
+        self.entries.iter().map(|(n, v)| (*n, v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_valued_fields() {
+        let mut fields = Fields::new();
+        fields.insert("ip.addr", Ipv4Addr::new(10, 0, 0, 1));
+        fields.insert("ip.addr", Ipv4Addr::new(10, 0, 0, 2));
+        fields.insert("tcp.port", 443u16);
+
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields.get_all("ip.addr").count(), 2);
+        assert_eq!(fields.get("tcp.port"), Some(&FieldValue::UInt(443)));
+        assert!(!fields.contains("udp.port"));
+    }
+
+    #[test]
+    fn test_field_value_display() {
+        assert_eq!(
+            FieldValue::Mac([0xaa, 0xbb, 0xcc, 0x00, 0x01, 0x02]).to_string(),
+            "aa:bb:cc:00:01:02"
+        );
+        assert_eq!(FieldValue::Bytes(vec![0xde, 0xad]).to_string(), "dead");
+        assert_eq!(FieldValue::from("GET").to_string(), "GET");
+    }
+}
@@ -1,117 +1,697 @@
+use crate::fields::FieldValue;
 use crate::ui::PacketInfo;
+use regex::{Regex, RegexBuilder};
+use std::cmp::Ordering;
+use std::fmt;
+use std::net::IpAddr;
 use std::str::FromStr;
 
-#[derive(Debug)]
+/// A display filter, e.g. `tcp.port == 80 && !(ip.src in {10.0.0.1 10.0.0.2})`.
+///
+/// Names refer to the fields of the dissected packet. A name on its own
+/// tests that a protocol or field is present, comparisons match when any
+/// value of the field does.
+#[derive(Debug, Clone, Default)]
 pub struct PacketFilter {
-    protocol: Option<Protocol>,
-    port: Option<u16>,
-    host: Option<String>,
+    /// None matches every packet.
+    expr: Option<Expr>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Protocol {
-    Tcp,
-    Udp,
-    Icmp,
-    Dns,
+#[derive(Debug, Clone)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// A protocol or field is present. Boolean fields only count when set.
+    Exists(String),
+    Compare(String, CompareOp, Literal),
+    Contains(String, Literal),
+    Matches(String, Regex),
+    In(String, Vec<Literal>),
 }
 
-impl FromStr for Protocol {
-    type Err = String;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "tcp" => Ok(Protocol::Tcp),
-            "udp" => Ok(Protocol::Udp),
-            "icmp" => Ok(Protocol::Icmp),
-            "dns" => Ok(Protocol::Dns),
-            _ => Err("Invalid protocol".to_string()),
-        }
+/// A value written in a filter, with the types it can be read as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Literal {
+    pub text: String,
+    number: Option<f64>,
+    boolean: Option<bool>,
+    ip: Option<IpAddr>,
+    /// A network address in CIDR notation, e.g. `10.0.0.0/8`, with the host
+    /// bits cleared.
+    network: Option<(IpAddr, u8)>,
+    /// An inclusive range of numbers, e.g. `8000..8100`.
+    range: Option<(f64, f64)>,
+    mac: Option<[u8; 6]>,
+    bytes: Option<Vec<u8>>,
+}
+
+/// Shorthands for the fields of either transport or direction.
+const ALIASES: &[(&str, &[&str])] = &[
+    ("port", &["tcp.port", "udp.port"]),
+    ("src.port", &["tcp.srcport", "udp.srcport"]),
+    ("dst.port", &["tcp.dstport", "udp.dstport"]),
+    ("host", &["ip.addr"]),
+    ("src.host", &["ip.src"]),
+    ("dst.host", &["ip.dst"]),
+];
+
+/// A filter that couldn't be parsed, with the column (counting from 1) of
+/// the mistake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
     }
 }
 
+impl std::error::Error for FilterError {}
+
 impl PacketFilter {
-    pub fn new() -> Self {
-        Self {
-            protocol: None,
-            port: None,
-            host: None,
+    /// Parses a display filter. An empty filter matches every packet.
+    pub fn parse(text: &str) -> Result<Self, FilterError> {
+        let tokens = tokenize(text)?;
+        if tokens.is_empty() {
+            return Ok(Self::default());
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            end: text.chars().count() + 1,
+        };
+        let expr = parser.or()?;
+        if let Some((token, column)) = parser.tokens.get(parser.position) {
+            return Err(error(*column, format!("unexpected {}", token)));
         }
+        Ok(Self { expr: Some(expr) })
     }
 
     pub fn matches(&self, packet: &PacketInfo) -> bool {
-        // Check protocol match if filter is set
-        if let Some(proto) = &self.protocol {
-            let packet_proto = match packet.protocol.to_uppercase().as_str() {
-                "TCP" => Protocol::Tcp,
-                "UDP" => Protocol::Udp,
-                "ICMP" | "ICMPV4" | "ICMPV6" => Protocol::Icmp,
-                "DNS" => Protocol::Dns,
-                _ => return false,
-            };
-            if packet_proto != *proto {
-                return false;
+        self.expr.as_ref().is_none_or(|expr| expr.matches(packet))
+    }
+}
+
+impl FromStr for PacketFilter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Expr {
+    pub fn matches(&self, packet: &PacketInfo) -> bool {
+        fn values<'a>(packet: &'a PacketInfo, name: &'a str) -> Vec<&'a FieldValue> {
+            match ALIASES.iter().find(|(alias, _)| *alias == name) {
+                Some((_, fields)) => fields
+                    .iter()
+                    .flat_map(|field| packet.fields.get_all(field))
+                    .collect(),
+                None => packet.fields.get_all(name).collect(),
+            }
+        }
+        match self {
+            Expr::And(left, right) => left.matches(packet) && right.matches(packet),
+            Expr::Or(left, right) => left.matches(packet) || right.matches(packet),
+            Expr::Not(expr) => !expr.matches(packet),
+            Expr::Exists(name) if ALIASES.iter().any(|(alias, _)| alias == name) => {
+                !values(packet, name).is_empty()
+            }
+            Expr::Exists(name) => {
+                let prefix = format!("{}.", name);
+                packet.protocol.eq_ignore_ascii_case(name)
+                    || packet.fields.iter().any(|(field, value)| {
+                        (field == name && *value != FieldValue::Bool(false))
+                            || field.starts_with(&prefix)
+                    })
+            }
+            Expr::Compare(name, op, literal) => {
+                let values = values(packet, name);
+                let any = |test: fn(Ordering) -> bool| {
+                    values
+                        .iter()
+                        .any(|value| compare(value, literal).is_some_and(test))
+                };
+                match op {
+                    // Unlike the other comparisons, no value may be equal
+                    CompareOp::Ne => !values.is_empty() && !any(Ordering::is_eq),
+                    CompareOp::Eq => any(Ordering::is_eq),
+                    CompareOp::Lt => any(Ordering::is_lt),
+                    CompareOp::Le => any(Ordering::is_le),
+                    CompareOp::Gt => any(Ordering::is_gt),
+                    CompareOp::Ge => any(Ordering::is_ge),
+                }
             }
+            Expr::Contains(name, literal) => values(packet, name).iter().any(|value| match value {
+                FieldValue::Bytes(bytes) => {
+                    let needle = literal.bytes.as_deref().unwrap_or(literal.text.as_bytes());
+                    needle.is_empty() || bytes.windows(needle.len()).any(|w| w == needle)
+                }
+                value => value.to_string().contains(&literal.text),
+            }),
+            Expr::Matches(name, regex) => values(packet, name)
+                .iter()
+                .any(|value| regex.is_match(&value.to_string())),
+            Expr::In(name, literals) => values(packet, name).iter().any(|value| {
+                literals
+                    .iter()
+                    .any(|literal| compare(value, literal) == Some(Ordering::Equal))
+            }),
+        }
+    }
+}
+
+/// Orders a field value against a literal of a type it can be compared to.
+/// Values inside a network or range count as equal to it.
+fn compare(value: &FieldValue, literal: &Literal) -> Option<Ordering> {
+    match value {
+        FieldValue::Bool(b) => Some(b.cmp(&literal.boolean?)),
+        FieldValue::UInt(n) => compare_number(*n as f64, literal),
+        FieldValue::Float(x) => compare_number(*x, literal),
+        FieldValue::Str(s) => Some(s.as_str().cmp(&literal.text)),
+        FieldValue::Ip(ip) => match literal.network {
+            Some((network, prefix)) if mask(*ip, prefix) == Some(network) => Some(Ordering::Equal),
+            Some((network, _)) => Some(ip.cmp(&network)),
+            None => Some(ip.cmp(&literal.ip?)),
+        },
+        FieldValue::Mac(mac) => Some(mac.cmp(&literal.mac?)),
+        FieldValue::Bytes(bytes) => Some(bytes.as_slice().cmp(literal.bytes.as_deref()?)),
+    }
+}
+
+fn compare_number(n: f64, literal: &Literal) -> Option<Ordering> {
+    match literal.range {
+        Some((low, _)) if n < low => Some(Ordering::Less),
+        Some((_, high)) if n > high => Some(Ordering::Greater),
+        Some(_) => Some(Ordering::Equal),
+        None => n.partial_cmp(&literal.number?),
+    }
+}
+
+/// Clears all but the first `prefix` bits of an address, or None when the
+/// prefix is longer than the address.
+fn mask(ip: IpAddr, prefix: u8) -> Option<IpAddr> {
+    match ip {
+        IpAddr::V4(ip) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            Some(IpAddr::from((u32::from(ip) & mask).to_be_bytes()))
+        }
+        IpAddr::V6(ip) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            Some(IpAddr::from((u128::from(ip) & mask).to_be_bytes()))
+        }
+        _ => None,
+    }
+}
+
+impl Literal {
+    /// Reads a value, failing on networks and ranges that can't hold
+    /// anything.
+    fn new(text: String) -> Result<Self, &'static str> {
+        let number = match text.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok().map(|n| n as f64),
+            None => text.parse().ok().filter(|n: &f64| n.is_finite()),
+        };
+        let boolean = match text.as_str() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        };
+        // Byte strings and MAC addresses are hex pairs separated by colons,
+        // dashes or dots
+        let bytes: Option<Vec<u8>> = text
+            .split([':', '-', '.'])
+            .map(|pair| match pair.len() {
+                2 => u8::from_str_radix(pair, 16).ok(),
+                _ => None,
+            })
+            .collect();
+        // MAC addresses may also be written as three groups of four digits,
+        // e.g. aabb.cc00.0102
+        let mac = bytes
+            .as_deref()
+            .and_then(|bytes| <[u8; 6]>::try_from(bytes).ok())
+            .or_else(|| {
+                let digits = text.replace('.', "");
+                let groups = text.split('.').all(|group| group.len() == 4);
+                if !groups || digits.len() != 12 {
+                    return None;
+                }
+                let mut mac = [0; 6];
+                for (i, byte) in mac.iter_mut().enumerate() {
+                    *byte = u8::from_str_radix(digits.get(i * 2..i * 2 + 2)?, 16).ok()?;
+                }
+                Some(mac)
+            });
+        let network = match text.split_once('/') {
+            Some((ip, prefix)) => match (ip.parse(), prefix.parse()) {
+                (Ok(ip), Ok(prefix)) => Some((
+                    mask(ip, prefix).ok_or("prefix longer than the address")?,
+                    prefix,
+                )),
+                _ => None,
+            },
+            None => None,
+        };
+        let range = match text.split_once("..") {
+            Some((low, high)) => match (low.parse::<f64>(), high.parse::<f64>()) {
+                (Ok(low), Ok(high)) if low > high => return Err("range ends before it starts"),
+                (Ok(low), Ok(high)) => Some((low, high)),
+                _ => None,
+            },
+            None => None,
+        };
+        Ok(Literal {
+            number,
+            boolean,
+            ip: text.parse().ok(),
+            network,
+            range,
+            mac,
+            bytes,
+            text,
+        })
+    }
+}
+
+fn error(column: usize, message: impl Into<String>) -> FilterError {
+    FilterError {
+        column,
+        message: message.into(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    And,
+    Or,
+    Not,
+    Op(CompareOp),
+    Contains,
+    Matches,
+    In,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Quoted(text) => write!(f, "string \"{}\"", text),
+            Token::And => write!(f, "'and'"),
+            Token::Or => write!(f, "'or'"),
+            Token::Not => write!(f, "'not'"),
+            Token::Op(op) => write!(f, "'{}'", op_symbol(*op)),
+            Token::Contains => write!(f, "'contains'"),
+            Token::Matches => write!(f, "'matches'"),
+            Token::In => write!(f, "'in'"),
+            Token::LeftParen => write!(f, "'('"),
+            Token::RightParen => write!(f, "')'"),
+            Token::LeftBrace => write!(f, "'{{'"),
+            Token::RightBrace => write!(f, "'}}'"),
+            Token::Comma => write!(f, "','"),
         }
+    }
+}
+
+fn op_symbol(op: CompareOp) -> &'static str {
+    match op {
+        CompareOp::Eq => "==",
+        CompareOp::Ne => "!=",
+        CompareOp::Lt => "<",
+        CompareOp::Le => "<=",
+        CompareOp::Gt => ">",
+        CompareOp::Ge => ">=",
+    }
+}
 
-        // Check port match if filter is set
-        if let Some(port) = self.port {
-            let has_port = packet.source.contains(&format!(":{}", port))
-                || packet.destination.contains(&format!(":{}", port));
-            if !has_port {
-                return false;
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | ':' | '_' | '-' | '/')
+}
+
+/// Splits a filter into tokens and the columns they start at.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, FilterError> {
+    match scan(text) {
+        (_, Some(error)) => Err(error),
+        (tokens, None) => Ok(tokens
+            .into_iter()
+            .map(|(token, column, _)| (token, column))
+            .collect()),
+    }
+}
+
+/// Splits a filter into tokens with their columns and lengths, up to the
+/// first character that doesn't start a token.
+fn scan(text: &str) -> (Vec<(Token, usize, usize)>, Option<FilterError>) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        match read_token(&chars, i) {
+            Ok((token, len)) => {
+                tokens.push((token, i + 1, len));
+                i += len;
             }
+            Err(error) => return (tokens, Some(error)),
         }
+    }
+    (tokens, None)
+}
 
-        // Check host match if filter is set
-        if let Some(host) = &self.host {
-            let source_host = packet.source.split(':').next().unwrap_or("");
-            let dest_host = packet.destination.split(':').next().unwrap_or("");
-            if source_host != host && dest_host != host {
-                return false;
+/// Reads the token starting at `chars[i]` and returns it with its length.
+fn read_token(chars: &[char], i: usize) -> Result<(Token, usize), FilterError> {
+    let column = i + 1;
+    let next = chars.get(i + 1).copied();
+    Ok(match (chars[i], next) {
+        ('=', Some('=')) => (Token::Op(CompareOp::Eq), 2),
+        ('!', Some('=')) => (Token::Op(CompareOp::Ne), 2),
+        ('<', Some('=')) => (Token::Op(CompareOp::Le), 2),
+        ('>', Some('=')) => (Token::Op(CompareOp::Ge), 2),
+        ('&', Some('&')) => (Token::And, 2),
+        ('|', Some('|')) => (Token::Or, 2),
+        ('<', _) => (Token::Op(CompareOp::Lt), 1),
+        ('>', _) => (Token::Op(CompareOp::Gt), 1),
+        ('!', _) => (Token::Not, 1),
+        ('(', _) => (Token::LeftParen, 1),
+        (')', _) => (Token::RightParen, 1),
+        ('{', _) => (Token::LeftBrace, 1),
+        ('}', _) => (Token::RightBrace, 1),
+        (',', _) => (Token::Comma, 1),
+        ('=', _) => return Err(error(column, "expected '==' for comparison")),
+        ('&', _) => return Err(error(column, "expected '&&'")),
+        ('|', _) => return Err(error(column, "expected '||'")),
+        ('"', _) => {
+            let mut value = String::new();
+            let mut end = i + 1;
+            loop {
+                match chars.get(end) {
+                    None => return Err(error(column, "unterminated string")),
+                    Some('"') => break,
+                    Some('\\') => {
+                        let escaped = chars
+                            .get(end + 1)
+                            .ok_or_else(|| error(column, "unterminated string"))?;
+                        value.push(*escaped);
+                        end += 2;
+                    }
+                    Some(c) => {
+                        value.push(*c);
+                        end += 1;
+                    }
+                }
             }
+            (Token::Quoted(value), end + 1 - i)
         }
+        (c, _) if is_word_char(c) => {
+            let len = chars[i..].iter().take_while(|c| is_word_char(**c)).count();
+            let word: String = chars[i..i + len].iter().collect();
+            let token = match word.as_str() {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                "eq" => Token::Op(CompareOp::Eq),
+                "ne" => Token::Op(CompareOp::Ne),
+                "lt" => Token::Op(CompareOp::Lt),
+                "le" => Token::Op(CompareOp::Le),
+                "gt" => Token::Op(CompareOp::Gt),
+                "ge" => Token::Op(CompareOp::Ge),
+                "contains" => Token::Contains,
+                "matches" => Token::Matches,
+                "in" => Token::In,
+                _ => Token::Word(word),
+            };
+            (token, len)
+        }
+        (c, _) => return Err(error(column, format!("unexpected character '{}'", c))),
+    })
+}
 
-        // If all filters pass (or none were set), return true
-        true
-    }
+/// How a part of a filter is shown while it's typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    Field,
+    /// Comparisons, `contains`, `matches` and `in`.
+    Operator,
+    /// `and`, `or` and `not`.
+    Keyword,
+    Value,
+    Punctuation,
+    /// Where the filter stops making sense.
+    Invalid,
 }
 
-impl Default for PacketFilter {
-    fn default() -> Self {
-        Self::new()
+/// Splits a filter into the character ranges (start and length) to
+/// highlight. Text from a parse error on is marked invalid: the token the
+/// parser stumbled over, or the rest of the filter when no token starts
+/// there.
+pub fn highlight(text: &str) -> Vec<(usize, usize, Highlight)> {
+    let (tokens, _) = scan(text);
+    let error_column = PacketFilter::parse(text).err().map(|error| error.column);
+    let mut spans = Vec::new();
+    let mut expects_value = false;
+    let mut in_set = false;
+    for (token, column, len) in tokens {
+        if error_column.is_some_and(|error| error < column) {
+            break;
+        }
+        let highlight = match &token {
+            _ if error_column == Some(column) => Highlight::Invalid,
+            Token::Word(_) if expects_value || in_set => Highlight::Value,
+            Token::Word(_) => Highlight::Field,
+            Token::Quoted(_) => Highlight::Value,
+            Token::And | Token::Or | Token::Not => Highlight::Keyword,
+            Token::Op(_) | Token::Contains | Token::Matches | Token::In => Highlight::Operator,
+            _ => Highlight::Punctuation,
+        };
+        expects_value = matches!(
+            token,
+            Token::Op(_) | Token::Contains | Token::Matches | Token::In
+        );
+        match token {
+            Token::LeftBrace => in_set = true,
+            Token::RightBrace => in_set = false,
+            _ => {}
+        }
+        spans.push((column - 1, len, highlight));
     }
+
+    let chars = text.chars().count();
+    if let Some(column) = error_column.filter(|column| *column <= chars) {
+        if spans.last().is_none_or(|(start, _, _)| *start < column - 1) {
+            spans.push((column - 1, chars + 1 - column, Highlight::Invalid));
+        }
+    }
+    spans
 }
 
-pub fn parse_filter(expression: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let device = if cfg!(target_os = "macos") {
-        "lo0"
-    } else {
-        "any"
-    };
+/// Recursive descent over the tokens; `not` binds tightest, then `and`,
+/// then `or`.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    /// The column just past the filter, where missing tokens are reported.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.end, |(_, column)| *column)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
 
-    // Create a temporary capture handle to validate the filter
-    let cap = pcap::Capture::<pcap::Inactive>::from_device(device)?;
+    /// An error at the current token, naming it unless the filter ended.
+    fn expected(&self, what: &str) -> FilterError {
+        let message = match self.peek() {
+            Some(token) => format!("expected {}, found {}", what, token),
+            None => format!("expected {}", what),
+        };
+        error(self.column(), message)
+    }
 
-    #[cfg(target_os = "macos")]
-    let mut cap = cap.snaplen(65535).timeout(1000).open()?;
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
 
-    #[cfg(not(target_os = "macos"))]
-    let mut cap = cap.snaplen(65535).timeout(1000).promisc(true).open()?;
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
 
-    // Try to set the filter with optimization enabled
-    match cap.filter(expression, true) {
-        Ok(_) => Ok(expression.to_string()),
-        Err(e) => Err(Box::new(e)),
+    fn unary(&mut self) -> Result<Expr, FilterError> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.position += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::LeftParen) => {
+                let column = self.column();
+                self.position += 1;
+                let expr = self.or()?;
+                match self.peek() {
+                    Some(Token::RightParen) => {
+                        self.position += 1;
+                        Ok(expr)
+                    }
+                    Some(_) => Err(self.expected("')'")),
+                    None => Err(error(column, "unclosed '('")),
+                }
+            }
+            _ => self.relation(),
+        }
     }
+
+    /// A field on its own, or compared to values.
+    fn relation(&mut self) -> Result<Expr, FilterError> {
+        let name = match self.peek() {
+            Some(Token::Word(word)) if is_field_name(word) => word.clone(),
+            _ => return Err(self.expected("a field name")),
+        };
+        self.position += 1;
+
+        match self.peek() {
+            Some(Token::Op(op)) => {
+                let op = *op;
+                self.position += 1;
+                let literal = self.value(&format!("a value after '{}'", op_symbol(op)))?;
+                Ok(Expr::Compare(name, op, literal))
+            }
+            Some(Token::Contains) => {
+                self.position += 1;
+                Ok(Expr::Contains(
+                    name,
+                    self.value("a value after 'contains'")?,
+                ))
+            }
+            Some(Token::Matches) => {
+                self.position += 1;
+                let column = self.column();
+                let pattern = self.value("a regular expression after 'matches'")?;
+                let regex = RegexBuilder::new(&pattern.text)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|_| error(column, "invalid regular expression"))?;
+                Ok(Expr::Matches(name, regex))
+            }
+            Some(Token::In) => {
+                self.position += 1;
+                // A single network or range needs no braces
+                if self.peek() != Some(&Token::LeftBrace) {
+                    let literal = self.value("'{' or a value after 'in'")?;
+                    return Ok(Expr::In(name, vec![literal]));
+                }
+                let column = self.column();
+                self.position += 1;
+                let mut literals = Vec::new();
+                loop {
+                    match self.peek() {
+                        Some(Token::RightBrace) if !literals.is_empty() => break,
+                        Some(Token::Comma) if !literals.is_empty() => self.position += 1,
+                        None => return Err(error(column, "unclosed '{'")),
+                        _ => {}
+                    }
+                    literals.push(self.value("a value")?);
+                }
+                self.position += 1;
+                Ok(Expr::In(name, literals))
+            }
+            _ => Ok(Expr::Exists(name)),
+        }
+    }
+
+    fn value(&mut self, what: &str) -> Result<Literal, FilterError> {
+        let column = self.column();
+        match self.peek() {
+            Some(Token::Word(_) | Token::Quoted(_)) => match self.next() {
+                Some(Token::Word(text) | Token::Quoted(text)) => {
+                    Literal::new(text).map_err(|message| error(column, message))
+                }
+                _ => unreachable!(),
+            },
+            _ => Err(self.expected(what)),
+        }
+    }
+}
+
+/// Field names start with a letter and are made of letters, digits,
+/// underscores, dashes and dots, e.g. `tcp.analysis.zero_window`.
+fn is_field_name(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_alphabetic())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Checks a capture filter by compiling it for Ethernet, without opening
+/// a device.
+pub fn parse_filter(expression: &str) -> Result<String, Box<dyn std::error::Error>> {
+    crate::bpf::compile(expression, pcap::Linktype::ETHERNET)?;
+    Ok(expression.to_string())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fields::Fields;
     use chrono::Utc;
+    use std::net::IpAddr;
 
     fn create_test_packet(protocol: &str, port: Option<u16>, host: &str) -> PacketInfo {
+        let mut fields = Fields::new();
+        let ip: IpAddr = host.parse().unwrap();
+        fields.insert("ip.src", ip);
+        fields.insert("ip.addr", ip);
+        if let Some(port) = port {
+            match protocol {
+                "TCP" => fields.insert("tcp.port", port),
+                "UDP" => fields.insert("udp.port", port),
+                _ => {}
+            }
+        }
         PacketInfo {
             raw_data: Vec::new(),
             protocol: protocol.to_string(),
@@ -124,131 +704,261 @@ mod tests {
             info: String::new(),
             length: 0,
             timestamp: Utc::now(),
+            fields,
+            expert: Vec::new(),
+            objects: Vec::new(),
         }
     }
 
-    fn get_test_interface() -> String {
-        // Try to find a suitable interface for testing
-        if cfg!(target_os = "macos") {
-            "lo0".to_string()
-        } else if cfg!(target_os = "linux") {
-            "lo".to_string()
-        } else {
-            // Get the first available interface as fallback
-            pcap::Device::list()
-                .unwrap()
-                .first()
-                .map(|dev| dev.name.clone())
-                .unwrap_or_else(|| "any".to_string())
-        }
+    fn filter(text: &str) -> PacketFilter {
+        PacketFilter::parse(text).unwrap()
     }
 
     #[test]
     fn test_protocol_filter() {
-        let mut filter = PacketFilter::new();
-        filter.protocol = Some(Protocol::Tcp);
-
         let tcp_packet = create_test_packet("TCP", Some(80), "192.168.1.1");
         let udp_packet = create_test_packet("UDP", Some(53), "192.168.1.1");
-
-        assert!(filter.matches(&tcp_packet));
-        assert!(!filter.matches(&udp_packet));
+        let mut dns_packet = create_test_packet("DNS", Some(53), "192.168.1.1");
+        dns_packet.fields.insert("udp.port", 53u16);
+
+        assert!(filter("tcp").matches(&tcp_packet));
+        assert!(!filter("tcp").matches(&udp_packet));
+        // Protocols are present by name or by their fields
+        assert!(filter("dns").matches(&dns_packet));
+        assert!(filter("udp").matches(&dns_packet));
     }
 
     #[test]
     fn test_port_filter() {
-        let mut filter = PacketFilter::new();
-        filter.port = Some(80);
-
         let http_packet = create_test_packet("TCP", Some(80), "192.168.1.1");
         let https_packet = create_test_packet("TCP", Some(443), "192.168.1.1");
 
-        assert!(filter.matches(&http_packet));
-        assert!(!filter.matches(&https_packet));
+        assert!(filter("tcp.port == 80").matches(&http_packet));
+        assert!(!filter("tcp.port == 80").matches(&https_packet));
+        assert!(filter("tcp.port >= 443").matches(&https_packet));
+        assert!(filter("tcp.port < 0x100").matches(&http_packet));
+        assert!(!filter("tcp.port gt 443").matches(&https_packet));
     }
 
     #[test]
     fn test_host_filter() {
-        let mut filter = PacketFilter::new();
-        filter.host = Some("192.168.1.1".to_string());
-
         let matching_packet = create_test_packet("TCP", Some(80), "192.168.1.1");
         let non_matching_packet = create_test_packet("TCP", Some(80), "192.168.1.2");
 
-        assert!(filter.matches(&matching_packet));
-        assert!(!filter.matches(&non_matching_packet));
+        let host = filter("ip.addr == 192.168.1.1");
+        assert!(host.matches(&matching_packet));
+        assert!(!host.matches(&non_matching_packet));
+
+        let ipv6 = create_test_packet("TCP", Some(80), "fe80::1");
+        assert!(filter("ip.src == fe80::1").matches(&ipv6));
     }
 
     #[test]
-    fn test_combined_filters() {
-        let mut filter = PacketFilter::new();
-        filter.protocol = Some(Protocol::Tcp);
-        filter.port = Some(80);
-        filter.host = Some("192.168.1.1".to_string());
+    fn test_boolean_operators() {
+        let combined = filter("tcp && tcp.port == 80 and ip.addr == 192.168.1.1");
 
         let matching_packet = create_test_packet("TCP", Some(80), "192.168.1.1");
         let wrong_protocol = create_test_packet("UDP", Some(80), "192.168.1.1");
         let wrong_port = create_test_packet("TCP", Some(443), "192.168.1.1");
         let wrong_host = create_test_packet("TCP", Some(80), "192.168.1.2");
 
-        assert!(filter.matches(&matching_packet));
-        assert!(!filter.matches(&wrong_protocol));
-        assert!(!filter.matches(&wrong_port));
-        assert!(!filter.matches(&wrong_host));
+        assert!(combined.matches(&matching_packet));
+        assert!(!combined.matches(&wrong_protocol));
+        assert!(!combined.matches(&wrong_port));
+        assert!(!combined.matches(&wrong_host));
+
+        // `and` binds tighter than `or`, `not` tighter than both
+        let either = filter("udp || tcp.port == 443 && not ip.addr == 192.168.1.2");
+        assert!(either.matches(&wrong_protocol));
+        assert!(either.matches(&wrong_port));
+        assert!(!either.matches(&matching_packet));
+        let grouped = filter("!(udp or tcp.port == 443)");
+        assert!(grouped.matches(&matching_packet));
+        assert!(!grouped.matches(&wrong_port));
     }
 
     #[test]
-    fn test_protocol_from_str() {
-        assert_eq!(Protocol::from_str("tcp").unwrap(), Protocol::Tcp);
-        assert_eq!(Protocol::from_str("TCP").unwrap(), Protocol::Tcp);
-        assert_eq!(Protocol::from_str("udp").unwrap(), Protocol::Udp);
-        assert_eq!(Protocol::from_str("UDP").unwrap(), Protocol::Udp);
-        assert_eq!(Protocol::from_str("icmp").unwrap(), Protocol::Icmp);
-        assert_eq!(Protocol::from_str("ICMP").unwrap(), Protocol::Icmp);
-        assert_eq!(Protocol::from_str("dns").unwrap(), Protocol::Dns);
-        assert_eq!(Protocol::from_str("DNS").unwrap(), Protocol::Dns);
+    fn test_empty_filter() {
+        let packet = create_test_packet("TCP", Some(80), "192.168.1.1");
 
-        assert!(Protocol::from_str("invalid").is_err());
+        assert!(
+            filter("  ").matches(&packet),
+            "Empty filter should match all packets"
+        );
+        assert!(PacketFilter::default().matches(&packet));
     }
 
     #[test]
-    fn test_empty_filter() {
-        let filter = PacketFilter::new();
-        let packet = create_test_packet("TCP", Some(80), "192.168.1.1");
+    fn test_not_equal_and_sets() {
+        let mut packet = create_test_packet("TCP", Some(80), "192.168.1.1");
+        packet.fields.insert("ip.addr", IpAddr::from([10, 0, 0, 1]));
+
+        // No value may be equal, and a missing field is never unequal
+        assert!(!filter("ip.addr != 10.0.0.1").matches(&packet));
+        assert!(filter("ip.addr != 10.0.0.2").matches(&packet));
+        assert!(!filter("udp.port != 53").matches(&packet));
+
+        assert!(filter("ip.addr in {10.0.0.1 10.0.0.2}").matches(&packet));
+        assert!(filter("tcp.port in {22, 80, 443}").matches(&packet));
+        assert!(!filter("tcp.port in {22,443}").matches(&packet));
+    }
 
+    #[test]
+    fn test_string_operators() {
+        let mut packet = create_test_packet("DNS", Some(53), "192.168.1.1");
+        packet.fields.insert("dns.qry.name", "www.Example.com");
+        packet.fields.insert("http.host", "example.com");
+        packet
+            .fields
+            .insert("data.data", vec![0xde, 0xad, 0xbe, 0xef]);
+
+        assert!(filter("http.host == example.com").matches(&packet));
+        assert!(filter("http.host == \"example.com\"").matches(&packet));
+        assert!(filter("dns.qry.name contains \"Example\"").matches(&packet));
+        assert!(!filter("dns.qry.name contains example").matches(&packet));
+        // Regular expressions ignore case
         assert!(
-            filter.matches(&packet),
-            "Empty filter should match all packets"
+            filter("dns.qry.name matches \"^www\\\\.example\\\\.(com|org)$\"").matches(&packet)
         );
+        assert!(!filter("dns.qry.name matches \"^example\"").matches(&packet));
+        assert!(filter("data.data contains ad:be").matches(&packet));
+        assert!(filter("data.data == de:ad:be:ef").matches(&packet));
+    }
+
+    #[test]
+    fn test_field_filter() {
+        let mut retransmission = create_test_packet("TCP", Some(80), "192.168.1.1");
+        retransmission
+            .fields
+            .insert("tcp.analysis.retransmission", true);
+        let mut unset = create_test_packet("TCP", Some(80), "192.168.1.1");
+        unset.fields.insert("tcp.analysis.retransmission", false);
+        let plain = create_test_packet("TCP", Some(80), "192.168.1.1");
+
+        let filter = filter("tcp.analysis.retransmission");
+        assert!(filter.matches(&retransmission));
+        assert!(!filter.matches(&unset));
+        assert!(!filter.matches(&plain));
     }
 
     #[test]
-    fn test_partial_filters() {
-        let mut filter = PacketFilter::new();
-        filter.protocol = Some(Protocol::Tcp);
-        // port and host are None
+    fn test_field_value_filter() {
+        let mut outer = create_test_packet("TCP", Some(80), "192.168.1.1");
+        outer.fields.insert("vlan.id", 100u16);
+        outer.fields.insert("vlan.id", 20u16);
+        let mut other = create_test_packet("TCP", Some(80), "192.168.1.1");
+        other.fields.insert("vlan.id", 200u16);
+        other
+            .fields
+            .insert("eth.src", [0xaa, 0xbb, 0xcc, 0x00, 0x01, 0x02]);
+        other.fields.insert("tcp.analysis.ack_rtt", 0.25);
+
+        assert!(filter("vlan.id == 100").matches(&outer));
+        assert!(!filter("vlan.id == 100").matches(&other));
+        assert!(filter("eth.src == aa:bb:cc:00:01:02").matches(&other));
+        assert!(filter("eth.src == aa-bb-cc-00-01-02").matches(&other));
+        assert!(filter("tcp.analysis.ack_rtt > 0.1").matches(&other));
+    }
 
-        let tcp_packet = create_test_packet("TCP", Some(80), "192.168.1.1");
-        let udp_packet = create_test_packet("UDP", Some(53), "192.168.1.1");
+    #[test]
+    fn test_networks_ranges_and_shorthands() {
+        let mut packet = create_test_packet("TCP", Some(8080), "10.1.2.3");
+        packet
+            .fields
+            .insert("ip.dst", IpAddr::from([192, 168, 1, 1]));
+        packet
+            .fields
+            .insert("ip.addr", IpAddr::from([192, 168, 1, 1]));
+        packet.fields.insert("tcp.srcport", 8080u16);
+        packet.fields.insert("tcp.dstport", 443u16);
+        packet
+            .fields
+            .insert("eth.addr", [0xaa, 0xbb, 0xcc, 0x00, 0x01, 0x02]);
+        let ipv6 = create_test_packet("UDP", Some(53), "2001:db8::42");
+
+        assert!(filter("ip.addr in 10.0.0.0/8").matches(&packet));
+        assert!(filter("ip.src == 10.1.2.3/32").matches(&packet));
+        assert!(!filter("ip.src in {172.16.0.0/12 192.168.0.0/16}").matches(&packet));
+        assert!(filter("ip.addr != 172.16.0.0/12").matches(&packet));
+        assert!(!filter("ip.addr != 192.168.0.0/16").matches(&packet));
+        assert!(filter("ip.addr in 2001:db8::/32").matches(&ipv6));
+        assert!(!filter("ip.addr in 10.0.0.0/8").matches(&ipv6));
+
+        // Ports are compared as numbers, never as text
+        assert!(!filter("tcp.port == 80").matches(&packet));
+        assert!(filter("port in 8000..8100").matches(&packet));
+        assert!(filter("tcp.port in {22 8000..8100}").matches(&packet));
+        assert!(filter("port in 1..1024").matches(&ipv6));
+        assert!(filter("src.port == 8080 && dst.port == 443").matches(&packet));
+        assert!(!filter("dst.port == 8080").matches(&packet));
+        assert!(filter("src.host in 10.0.0.0/8 and dst.host == 192.168.1.1").matches(&packet));
+        assert!(filter("port").matches(&packet));
+
+        assert!(filter("eth.addr == aa:bb:cc:00:01:02").matches(&packet));
+        assert!(filter("eth.addr == aabb.cc00.0102").matches(&packet));
+        assert!(!filter("eth.addr == aa:bb:cc:00:01:03").matches(&packet));
+
+        let column = |text: &str| PacketFilter::parse(text).unwrap_err().column;
+        assert_eq!(column("ip.addr in 10.0.0.0/33"), 12);
+        assert_eq!(column("port in 100..10"), 9);
+    }
 
-        assert!(filter.matches(&tcp_packet));
-        assert!(!filter.matches(&udp_packet));
+    #[test]
+    fn test_parse_errors_report_column() {
+        let column = |text: &str| PacketFilter::parse(text).unwrap_err().column;
+        assert_eq!(column("tcp.port = 80"), 10);
+        assert_eq!(column("tcp.port == "), 13);
+        assert_eq!(column("(tcp or udp"), 1);
+        assert_eq!(column("tcp and"), 8);
+        assert_eq!(column("tcp udp"), 5);
+        assert_eq!(column("ip.addr == \"10.0.0.1"), 12);
+        assert_eq!(column("http.host matches \"(\""), 19);
+        assert_eq!(column("tcp.port in {80"), 13);
+        assert_eq!(column("80 == tcp.port"), 1);
+        assert_eq!(column("tcp.port == 80 # comment"), 16);
+
+        let err = PacketFilter::parse("tcp.port == == 80").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "column 13: expected a value after '==', found '=='"
+        );
+        assert!("tcp ||".parse::<PacketFilter>().is_err());
     }
 
     #[test]
-    fn test_parse_valid_filter() {
-        let interface = get_test_interface();
-
-        // Initialize pcap with the appropriate interface
-        let _ = pcap::Capture::<pcap::Inactive>::from_device(interface.as_str())
-            .unwrap()
-            .promisc(false)
-            .snaplen(65535)
-            .timeout(1000)
-            .immediate_mode(true)
-            .open()
-            .unwrap();
+    fn test_highlight() {
+        use Highlight::*;
+
+        assert_eq!(
+            highlight("ip.src in {10.0.0.1} && !dns.qry.name contains \"x\""),
+            vec![
+                (0, 6, Field),
+                (7, 2, Operator),
+                (10, 1, Punctuation),
+                (11, 8, Value),
+                (19, 1, Punctuation),
+                (21, 2, Keyword),
+                (24, 1, Keyword),
+                (25, 12, Field),
+                (38, 8, Operator),
+                (47, 3, Value),
+            ]
+        );
+        // The token the parser stopped at, or the rest after a bad character
+        assert_eq!(highlight("tcp udp"), vec![(0, 3, Field), (4, 3, Invalid)]);
+        assert_eq!(
+            highlight("tcp.port = 80"),
+            vec![(0, 8, Field), (9, 4, Invalid)]
+        );
+        // Nothing is wrong yet with a filter that's still being typed
+        assert_eq!(
+            highlight("tcp.port =="),
+            vec![(0, 8, Field), (9, 2, Operator)]
+        );
+    }
 
+    #[test]
+    fn test_parse_valid_filter() {
         let result = parse_filter("tcp port 80");
         assert!(result.is_ok(), "Filter parsing failed: {:?}", result.err());
         assert_eq!(result.unwrap(), "tcp port 80");
@@ -256,18 +966,6 @@ mod tests {
 
     #[test]
     fn test_parse_invalid_filter() {
-        let interface = get_test_interface();
-
-        // Initialize pcap with the appropriate interface
-        let _ = pcap::Capture::<pcap::Inactive>::from_device(interface.as_str())
-            .unwrap()
-            .promisc(false)
-            .snaplen(65535)
-            .timeout(1000)
-            .immediate_mode(true)
-            .open()
-            .unwrap();
-
         let result = parse_filter("invalid filter");
         assert!(result.is_err());
     }
@@ -0,0 +1,451 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionState, ConnectionTable, Exchange};
+use crate::reassembly::{MessageBuffer, StreamData};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+
+const MAX_CONNECTIONS: usize = 65536;
+const MAX_REPLY_LINES: usize = 64;
+const ENTERING_PASSIVE_MODE: u16 = 227;
+const ENTERING_EXTENDED_PASSIVE_MODE: u16 = 229;
+const AUTH_ACCEPTED: u16 = 234;
+
+/// Commands whose data is sent over a data connection.
+const TRANSFER_COMMANDS: [&str; 7] = ["RETR", "STOR", "STOU", "APPE", "LIST", "NLST", "MLSD"];
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Command {
+        command: String,
+        argument: String,
+    },
+    Reply {
+        code: u16,
+        lines: Vec<String>,
+    },
+    /// Data after a successful AUTH TLS.
+    Encrypted(usize),
+}
+
+/// An FTP command or reply on a control connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FtpMessage {
+    pub from_client: bool,
+    pub kind: Kind,
+    /// The data connection endpoint announced by PORT, EPRT, PASV or EPSV.
+    pub data_endpoint: Option<Endpoint>,
+    pub starts_tls: bool,
+}
+
+impl AppMessage for FtpMessage {
+    const TIME_FIELD: &'static str = "ftp.response_time";
+
+    fn info(&self) -> String {
+        match &self.kind {
+            Kind::Command { command, argument } if argument.is_empty() => {
+                format!("Request: {}", command)
+            }
+            Kind::Command { command, argument } => format!("Request: {} {}", command, argument),
+            Kind::Reply { code, lines } => format!("Response: {} {}", code, lines.join(" | ")),
+            Kind::Encrypted(bytes) => format!("TLS encrypted data, {} bytes", bytes),
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        match &self.kind {
+            Kind::Command { command, argument } => {
+                fields.insert("ftp.request.command", command.as_str());
+                if !argument.is_empty() {
+                    fields.insert("ftp.request.arg", argument.as_str());
+                }
+            }
+            Kind::Reply { code, lines } => {
+                fields.insert("ftp.response.code", *code);
+                for line in lines {
+                    fields.insert("ftp.response.arg", line.as_str());
+                }
+            }
+            Kind::Encrypted(_) => fields.insert("ftp.tls", true),
+        }
+        if let Some((ip, port)) = self.data_endpoint {
+            let (ip_field, port_field) = match self.from_client {
+                true => ("ftp.active.cip", "ftp.active.port"),
+                false => ("ftp.passive.ip", "ftp.passive.port"),
+            };
+            fields.insert(ip_field, ip);
+            fields.insert(port_field, port);
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match &self.kind {
+            Kind::Reply { code, lines } if *code >= 400 => {
+                Some(format!("{} {}", code, lines.join(" ")))
+            }
+            _ => None,
+        }
+    }
+
+    fn note(&self) -> Option<String> {
+        self.starts_tls
+            .then(|| "Connection switched to TLS by AUTH TLS".to_string())
+    }
+}
+
+/// Data sent over a data connection negotiated on a control connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FtpData {
+    pub setup_method: &'static str,
+    /// The transfer command last sent on the control connection.
+    pub command: Option<String>,
+    pub bytes: usize,
+}
+
+impl AppMessage for FtpData {
+    const TIME_FIELD: &'static str = "ftp-data.response_time";
+
+    fn info(&self) -> String {
+        let mut info = format!("FTP Data: {} bytes ({})", self.bytes, self.setup_method);
+        if let Some(command) = &self.command {
+            info.push_str(&format!(" ({})", command));
+        }
+        info
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("ftp-data.setup-method", self.setup_method);
+        if let Some(command) = &self.command {
+            fields.insert("ftp-data.command", command.as_str());
+        }
+        fields.insert("ftp-data.length", self.bytes);
+    }
+
+    fn error(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Parses the `h1,h2,h3,h4,p1,p2` address of PORT and 227 replies.
+fn parse_host_port(text: &str) -> Option<Endpoint> {
+    let numbers = text
+        .split(|c: char| !c.is_ascii_digit() && c != ',')
+        .find(|token| token.matches(',').count() == 5)?;
+    let bytes: Vec<u8> = numbers
+        .split(',')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    let ip = Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+    Some((ip.into(), u16::from(bytes[4]) << 8 | u16::from(bytes[5])))
+}
+
+/// Parses the `|proto|address|port|` argument of EPRT, or the `|||port|`
+/// of 229 replies, whose address is `default_ip`.
+fn parse_extended(text: &str, default_ip: IpAddr) -> Option<Endpoint> {
+    let start = text.find('(').map_or(0, |start| start + 1);
+    let text = text[start..].trim_end_matches(')');
+    let delimiter = text.chars().next()?;
+    let parts: Vec<_> = text.split(delimiter).collect();
+    let ip = match parts.get(2)? {
+        &"" => default_ip,
+        ip => ip.parse().ok()?,
+    };
+    Some((ip, parts.get(3)?.parse().ok()?))
+}
+
+#[derive(Debug, Default)]
+struct Connection {
+    reply: Option<(u16, Vec<String>)>,
+    transfer: Option<String>,
+    auth_sent: bool,
+    encrypted: bool,
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+
+    fn gap(&mut self, _: &mut MessageBuffer, _: bool) {
+        self.reply = None;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DataChannel {
+    control: (Endpoint, Endpoint),
+    setup_method: &'static str,
+}
+
+/// Splits reassembled FTP control connections into commands and replies,
+/// and recognizes the data connections they negotiate.
+#[derive(Debug, Default)]
+pub struct FtpTracker {
+    connections: ConnectionTable<Connection>,
+    /// Announced data endpoints nobody connected to yet.
+    expected: HashMap<Endpoint, DataChannel>,
+    data_connections: HashMap<(Endpoint, Endpoint), DataChannel>,
+}
+
+impl FtpTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds stream data of the control connection from `client` to
+    /// `server` and returns the commands or replies it completes.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+    ) -> Exchange<FtpMessage> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (buffer, connection) = connection.split(from_client);
+        if connection.encrypted {
+            if !stream.data.is_empty() {
+                exchange.messages.push(FtpMessage {
+                    from_client,
+                    kind: Kind::Encrypted(stream.data.len()),
+                    data_endpoint: None,
+                    starts_tls: false,
+                });
+            }
+            return exchange;
+        }
+
+        buffer.push(&stream.data);
+        while let Some(line) = buffer.take_line() {
+            let message = match from_client {
+                true => connection.command(&line),
+                false => match connection.reply_line(&line, server.0) {
+                    Some(message) => message,
+                    None => continue,
+                },
+            };
+            if let Some(endpoint) = message.data_endpoint {
+                let setup_method = match &message.kind {
+                    Kind::Command { command, .. } if command == "EPRT" => "EPRT",
+                    Kind::Command { .. } => "PORT",
+                    Kind::Reply { code, .. } if *code == ENTERING_PASSIVE_MODE => "PASV",
+                    _ => "EPSV",
+                };
+                let channel = DataChannel {
+                    control: (client, server),
+                    setup_method,
+                };
+                if self.expected.len() >= MAX_CONNECTIONS {
+                    self.expected.clear();
+                }
+                // Clients behind NAT often ignore the announced address
+                // and connect to the server they already talk to
+                if !from_client && endpoint.0 != server.0 {
+                    self.expected
+                        .insert((server.0, endpoint.1), channel.clone());
+                }
+                self.expected.insert(endpoint, channel);
+            }
+            let starts_tls = message.starts_tls;
+            exchange.messages.push(message);
+            if starts_tls {
+                connection.encrypted = true;
+                buffer.clear();
+                break;
+            }
+        }
+        exchange
+    }
+
+    /// Returns the data sent between `src` and `dst` if they form a data
+    /// connection of a known control connection.
+    pub fn data(
+        &mut self,
+        src: Endpoint,
+        dst: Endpoint,
+        stream: &StreamData,
+    ) -> Option<Exchange<FtpData>> {
+        let key = if src <= dst { (src, dst) } else { (dst, src) };
+        if !self.data_connections.contains_key(&key) {
+            // Whoever connects, the other side announced its endpoint
+            let channel = self
+                .expected
+                .remove(&dst)
+                .or_else(|| self.expected.remove(&src))?;
+            if self.data_connections.len() >= MAX_CONNECTIONS {
+                self.data_connections.clear();
+            }
+            self.data_connections.insert(key, channel);
+        }
+
+        let channel = &self.data_connections[&key];
+        let mut exchange = Exchange::default();
+        if !stream.data.is_empty() {
+            let (client, server) = channel.control;
+            let command = self
+                .connections
+                .get(client, server)
+                .and_then(|connection| connection.state.transfer.clone());
+            exchange.messages.push(FtpData {
+                setup_method: channel.setup_method,
+                command,
+                bytes: stream.data.len(),
+            });
+        }
+        Some(exchange)
+    }
+}
+
+impl Connection {
+    fn command(&mut self, line: &str) -> FtpMessage {
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let command = command.to_ascii_uppercase();
+        let data_endpoint = match command.as_str() {
+            "PORT" => parse_host_port(argument),
+            "EPRT" => parse_extended(argument, Ipv4Addr::UNSPECIFIED.into()),
+            _ => None,
+        };
+        if TRANSFER_COMMANDS.contains(&command.as_str()) {
+            self.transfer = Some(line.to_string());
+        }
+        if command == "AUTH" {
+            self.auth_sent = true;
+        }
+        FtpMessage {
+            from_client: true,
+            kind: Kind::Command {
+                command,
+                argument: argument.to_string(),
+            },
+            data_endpoint,
+            starts_tls: false,
+        }
+    }
+
+    /// Adds a reply line, returning the reply once its last line is in.
+    fn reply_line(&mut self, line: &str, server_ip: IpAddr) -> Option<FtpMessage> {
+        let code: Option<u16> = line.get(..3).and_then(|code| code.parse().ok());
+        let (code, lines) = self
+            .reply
+            .get_or_insert_with(|| (code.unwrap_or(0), Vec::new()));
+        // Lines of a multi-line reply may be arbitrary text, up to a line
+        // starting with the code and a space
+        let text = match line.get(..4) {
+            Some(prefix) if prefix == format!("{}-", code) || prefix == format!("{} ", code) => {
+                &line[4..]
+            }
+            _ => line,
+        };
+        if lines.len() < MAX_REPLY_LINES {
+            lines.push(text.to_string());
+        }
+        let last = line.len() == 3 || line.get(..4) == Some(&format!("{} ", code)[..]);
+        if !last {
+            return None;
+        }
+
+        let (code, lines) = self.reply.take().expect("set above");
+        let data_endpoint = match code {
+            ENTERING_PASSIVE_MODE => parse_host_port(&lines.join(" ")),
+            ENTERING_EXTENDED_PASSIVE_MODE => parse_extended(&lines.join(" "), server_ip),
+            _ => None,
+        };
+        let starts_tls = self.auth_sent && code == AUTH_ACCEPTED;
+        self.auth_sent = false;
+        Some(FtpMessage {
+            from_client: false,
+            kind: Kind::Reply { code, lines },
+            data_endpoint,
+            starts_tls,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    #[test]
+    fn test_parse_data_endpoints() {
+        let ip: IpAddr = Ipv4Addr::new(192, 168, 1, 2).into();
+        assert_eq!(
+            parse_host_port("Entering Passive Mode (192,168,1,2,19,137)."),
+            Some((ip, 5001))
+        );
+        assert_eq!(parse_host_port("192,168,1,2,19,137"), Some((ip, 5001)));
+        assert_eq!(
+            parse_extended("Entering Extended Passive Mode (|||6446|)", ip),
+            Some((ip, 6446))
+        );
+        assert_eq!(
+            parse_extended("|2|2001:db8::1|5282|", ip),
+            Some(("2001:db8::1".parse().unwrap(), 5282))
+        );
+    }
+
+    #[test]
+    fn test_passive_data_connection() {
+        let mut tracker = FtpTracker::new();
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(b"220-Welcome\r\n  to the server\r\n220 Ready\r\n"),
+        );
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Response: 220 Welcome |   to the server | Ready"
+        );
+
+        tracker.process(CLIENT, SERVER, true, &StreamData::complete(b"PASV\r\n"));
+        let reply = b"227 Entering Passive Mode (10,0,0,2,195,80).\r\n";
+        let exchange = tracker.process(CLIENT, SERVER, false, &StreamData::complete(reply));
+        let data_endpoint = exchange.messages[0].data_endpoint.unwrap();
+        assert_eq!(data_endpoint, (SERVER.0, 50000));
+        tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(b"RETR report.csv\r\n"),
+        );
+
+        // The client connects to the announced endpoint
+        let data_client = (CLIENT.0, 40001);
+        let exchange = tracker
+            .data(data_client, data_endpoint, &StreamData::complete(b""))
+            .unwrap();
+        assert!(exchange.messages.is_empty());
+        let exchange = tracker
+            .data(
+                data_endpoint,
+                data_client,
+                &StreamData::complete(b"a,b\n1,2\n"),
+            )
+            .unwrap();
+        assert_eq!(
+            exchange.messages[0].info(),
+            "FTP Data: 8 bytes (PASV) (RETR report.csv)"
+        );
+        assert!(tracker
+            .data(data_client, (SERVER.0, 50001), &StreamData::complete(b"x"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_error_reply() {
+        let mut tracker = FtpTracker::new();
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(b"550 report.csv: No such file\r\n"),
+        );
+        assert_eq!(
+            exchange.messages[0].error().unwrap(),
+            "550 report.csv: No such file"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::fields::Fields;
+use crate::protobuf::{self, DecodedField};
+
+// Compressed flag and message length
+const MESSAGE_HEADER_LEN: usize = 5;
+
+pub fn is_grpc(content_type: &str) -> bool {
+    content_type.starts_with("application/grpc")
+}
+
+pub fn status_name(code: u32) -> &'static str {
+    match code {
+        0 => "OK",
+        1 => "CANCELLED",
+        2 => "UNKNOWN",
+        3 => "INVALID_ARGUMENT",
+        4 => "DEADLINE_EXCEEDED",
+        5 => "NOT_FOUND",
+        6 => "ALREADY_EXISTS",
+        7 => "PERMISSION_DENIED",
+        8 => "RESOURCE_EXHAUSTED",
+        9 => "FAILED_PRECONDITION",
+        10 => "ABORTED",
+        11 => "OUT_OF_RANGE",
+        12 => "UNIMPLEMENTED",
+        13 => "INTERNAL",
+        14 => "UNAVAILABLE",
+        15 => "DATA_LOSS",
+        16 => "UNAUTHENTICATED",
+        _ => "Unknown",
+    }
+}
+
+/// A length-prefixed gRPC message, decoded if its type is known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrpcMessage {
+    pub compressed: bool,
+    pub data: Vec<u8>,
+    pub message_type: Option<String>,
+    pub decoded: Option<Vec<DecodedField>>,
+}
+
+impl GrpcMessage {
+    /// Removes the complete messages from the start of `buffer`, which holds
+    /// the DATA frame payloads of one direction of a stream.
+    pub fn take_all(buffer: &mut Vec<u8>) -> Vec<GrpcMessage> {
+        let mut messages = Vec::new();
+        let mut consumed = 0;
+        while let Some(header) = buffer.get(consumed..consumed + MESSAGE_HEADER_LEN) {
+            let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+            let start = consumed + MESSAGE_HEADER_LEN;
+            let Some(data) = buffer.get(start..start + len) else {
+                break;
+            };
+            messages.push(GrpcMessage {
+                compressed: header[0] & 0x01 != 0,
+                data: data.to_vec(),
+                message_type: None,
+                decoded: None,
+            });
+            consumed = start + len;
+        }
+        buffer.drain(..consumed);
+        messages
+    }
+
+    pub fn info(&self) -> String {
+        match (&self.message_type, &self.decoded) {
+            (Some(message_type), Some(decoded)) => {
+                format!("{} {{{}}}", message_type, protobuf::format_fields(decoded))
+            }
+            _ if self.compressed => format!("compressed message ({} bytes)", self.data.len()),
+            _ => format!("message ({} bytes)", self.data.len()),
+        }
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("grpc.compressed_flag", self.compressed);
+        fields.insert("grpc.message_length", self.data.len());
+        if let Some(message_type) = &self.message_type {
+            fields.insert("protobuf.message.name", message_type.as_str());
+        }
+        for field in self.decoded.iter().flatten() {
+            fields.insert("protobuf.field.name", field.name.clone());
+            fields.insert("protobuf.field.value", field.value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_across_frames() {
+        let mut buffer = vec![0, 0, 0, 0, 2, 0x08, 0x01, 1, 0, 0];
+        let messages = GrpcMessage::take_all(&mut buffer);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].data, vec![0x08, 0x01]);
+        assert_eq!(messages[0].info(), "message (2 bytes)");
+        assert_eq!(buffer, vec![1, 0, 0]);
+
+        buffer.extend_from_slice(&[0, 1, 0xff]);
+        let messages = GrpcMessage::take_all(&mut buffer);
+        assert!(messages[0].compressed);
+        assert!(buffer.is_empty());
+    }
+}
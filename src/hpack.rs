@@ -0,0 +1,351 @@
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+
+/// Dynamic table size until the encoder announces another one.
+const DEFAULT_TABLE_SIZE: usize = 4096;
+// Entries count 32 bytes on top of their name and value (RFC 7541 section
+// 4.1)
+const ENTRY_OVERHEAD: usize = 32;
+const EOS: u16 = 256;
+
+/// RFC 7541 appendix A.
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// Code lengths of the Huffman code in RFC 7541 appendix B, by symbol. The
+/// code is canonical, so the lengths determine the codes.
+const HUFFMAN_CODE_LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 30, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6, 5, 5,
+    5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10, 13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6, 15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6,
+    6, 5, 6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28, 20, 22, 20, 20, 22, 22, 22, 23, 22,
+    23, 23, 23, 23, 23, 24, 23, 24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24, 22,
+    21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23, 21, 21, 22, 21, 23, 22, 23, 23, 20,
+    22, 22, 22, 23, 22, 22, 23, 26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25, 19,
+    21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27, 20, 24, 20, 21, 22, 21, 21, 23, 22,
+    22, 25, 25, 24, 24, 26, 23, 26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26, 30,
+];
+
+/// A decoded header field.
+pub type Header = (String, String);
+
+/// HPACK decoder for one direction of an HTTP/2 connection. The dynamic
+/// table makes header blocks depend on all earlier ones.
+#[derive(Debug)]
+pub struct Decoder {
+    dynamic: VecDeque<Header>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder {
+            dynamic: VecDeque::new(),
+            size: 0,
+            max_size: DEFAULT_TABLE_SIZE,
+        }
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes a complete header block. None means the block is malformed
+    /// and the dynamic table can no longer be trusted.
+    pub fn decode(&mut self, block: &[u8]) -> Option<Vec<Header>> {
+        let mut headers = Vec::new();
+        let mut rest = block;
+        while let Some(&first) = rest.first() {
+            if first & 0x80 != 0 {
+                // Indexed header field
+                let (index, next) = integer(rest, 7)?;
+                headers.push(self.entry(index)?);
+                rest = next;
+            } else if first & 0x40 != 0 {
+                // Literal with incremental indexing
+                let (header, next) = self.literal(rest, 6)?;
+                self.insert(header.clone());
+                headers.push(header);
+                rest = next;
+            } else if first & 0x20 != 0 {
+                let (size, next) = integer(rest, 5)?;
+                self.max_size = usize::try_from(size).ok()?;
+                self.evict(0);
+                rest = next;
+            } else {
+                // Literal without indexing or never indexed
+                let (header, next) = self.literal(rest, 4)?;
+                headers.push(header);
+                rest = next;
+            }
+        }
+        Some(headers)
+    }
+
+    fn entry(&self, index: u64) -> Option<Header> {
+        let index = usize::try_from(index).ok()?.checked_sub(1)?;
+        match STATIC_TABLE.get(index) {
+            Some((name, value)) => Some((name.to_string(), value.to_string())),
+            None => self.dynamic.get(index - STATIC_TABLE.len()).cloned(),
+        }
+    }
+
+    fn literal<'a>(&self, data: &'a [u8], prefix: u8) -> Option<(Header, &'a [u8])> {
+        let (index, rest) = integer(data, prefix)?;
+        let (name, rest) = if index == 0 {
+            string(rest)?
+        } else {
+            (self.entry(index)?.0, rest)
+        };
+        let (value, rest) = string(rest)?;
+        Some(((name, value), rest))
+    }
+
+    fn insert(&mut self, header: Header) {
+        let size = header.0.len() + header.1.len() + ENTRY_OVERHEAD;
+        self.evict(size);
+        // An entry larger than the table empties it and isn't added
+        if size <= self.max_size {
+            self.size += size;
+            self.dynamic.push_front(header);
+        }
+    }
+
+    /// Evicts entries until `incoming` more bytes fit.
+    fn evict(&mut self, incoming: usize) {
+        while self.size + incoming > self.max_size {
+            match self.dynamic.pop_back() {
+                Some((name, value)) => self.size -= name.len() + value.len() + ENTRY_OVERHEAD,
+                None => {
+                    self.size = 0;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Decodes an integer with an N bit prefix (RFC 7541 section 5.1).
+fn integer(data: &[u8], prefix: u8) -> Option<(u64, &[u8])> {
+    let max = (1u64 << prefix) - 1;
+    let mut value = u64::from(*data.first()?) & max;
+    let mut rest = &data[1..];
+    if value < max {
+        return Some((value, rest));
+    }
+    let mut shift = 0;
+    loop {
+        let (&byte, next) = rest.split_first()?;
+        rest = next;
+        value = value.checked_add(u64::from(byte & 0x7f).checked_shl(shift)?)?;
+        shift += 7;
+        if byte & 0x80 == 0 || shift > 56 {
+            break;
+        }
+    }
+    Some((value, rest))
+}
+
+/// Decodes a string literal, Huffman coded or not (RFC 7541 section 5.2).
+fn string(data: &[u8]) -> Option<(String, &[u8])> {
+    let huffman = *data.first()? & 0x80 != 0;
+    let (len, rest) = integer(data, 7)?;
+    let len = usize::try_from(len).ok()?;
+    let bytes = rest.get(..len)?;
+    let decoded = if huffman {
+        huffman_decode(bytes)?
+    } else {
+        bytes.to_vec()
+    };
+    Some((String::from_utf8_lossy(&decoded).into_owned(), &rest[len..]))
+}
+
+/// Canonical decoding tables: for every code length, the first code, the
+/// number of codes and where their symbols start in `symbols`.
+struct HuffmanTable {
+    first_code: [u32; 31],
+    count: [u32; 31],
+    offset: [usize; 31],
+    symbols: Vec<u16>,
+}
+
+fn huffman_table() -> &'static HuffmanTable {
+    static TABLE: OnceLock<HuffmanTable> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut symbols: Vec<u16> = (0..=EOS).collect();
+        symbols.sort_by_key(|&symbol| HUFFMAN_CODE_LENGTHS[usize::from(symbol)]);
+        let mut table = HuffmanTable {
+            first_code: [0; 31],
+            count: [0; 31],
+            offset: [0; 31],
+            symbols,
+        };
+        for &len in &HUFFMAN_CODE_LENGTHS {
+            table.count[usize::from(len)] += 1;
+        }
+        let mut code = 0;
+        let mut offset = 0;
+        for len in 1..31 {
+            code = (code + table.count[len - 1]) << 1;
+            table.first_code[len] = code;
+            table.offset[len] = offset;
+            offset += table.count[len] as usize;
+        }
+        table
+    })
+}
+
+fn huffman_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let table = huffman_table();
+    let mut decoded = Vec::with_capacity(data.len() * 8 / 5);
+    let mut code = 0u32;
+    let mut len = 0;
+    for byte in data {
+        for bit in (0..8).rev() {
+            code = code << 1 | u32::from(byte >> bit & 1);
+            len += 1;
+            let index = code.wrapping_sub(table.first_code[len]);
+            if index < table.count[len] {
+                let symbol = table.symbols[table.offset[len] + index as usize];
+                if symbol == EOS {
+                    return None;
+                }
+                decoded.push(symbol as u8);
+                code = 0;
+                len = 0;
+            } else if len == 30 {
+                return None;
+            }
+        }
+    }
+    // Up to 7 bits of the EOS code pad the string
+    if len > 7 || code != (1 << len) - 1 {
+        return None;
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn header(name: &str, value: &str) -> Header {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_requests_with_huffman_coding() {
+        // RFC 7541 appendix C.4
+        let mut decoder = Decoder::new();
+        let first = decoder
+            .decode(&unhex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff"))
+            .unwrap();
+        assert_eq!(
+            first,
+            vec![
+                header(":method", "GET"),
+                header(":scheme", "http"),
+                header(":path", "/"),
+                header(":authority", "www.example.com"),
+            ]
+        );
+        assert_eq!(decoder.size, 57);
+
+        // Refers to the dynamic table entry added by the first request
+        let second = decoder
+            .decode(&unhex("8286 84be 5886 a8eb 1064 9cbf"))
+            .unwrap();
+        assert_eq!(second[3], header(":authority", "www.example.com"));
+        assert_eq!(second[4], header("cache-control", "no-cache"));
+        assert_eq!(decoder.size, 110);
+    }
+
+    #[test]
+    fn test_literal_without_indexing() {
+        // RFC 7541 appendix C.2.2
+        let mut decoder = Decoder::new();
+        let headers = decoder
+            .decode(&unhex("040c 2f73 616d 706c 652f 7061 7468"))
+            .unwrap();
+        assert_eq!(headers, vec![header(":path", "/sample/path")]);
+        assert!(decoder.dynamic.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_index() {
+        assert!(Decoder::new().decode(&[0xbe]).is_none());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::fields::Fields;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub const HSRP_PORT: u16 = 1985;
+pub const HSRP_V6_PORT: u16 = 2029;
+
+const V1_LEN: usize = 20;
+const GROUP_STATE_TLV: u8 = 1;
+const GROUP_STATE_LEN: usize = 40;
+
+const HELLO: u8 = 0;
+const COUP: u8 = 1;
+const RESIGN: u8 = 2;
+
+fn opcode_name(opcode: u8) -> &'static str {
+    match opcode {
+        HELLO => "Hello",
+        COUP => "Coup",
+        RESIGN => "Resign",
+        3 => "Advertise",
+        _ => "Unknown",
+    }
+}
+
+fn state_name(state: u8) -> &'static str {
+    match state {
+        0 => "Initial",
+        1 => "Learn",
+        2 => "Listen",
+        4 => "Speak",
+        8 => "Standby",
+        16 => "Active",
+        _ => "Unknown",
+    }
+}
+
+/// An HSRP message about a standby group. Version 1 messages are fixed
+/// length; version 2 carries the same in a group state TLV.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HsrpPacket {
+    pub version: u8,
+    pub opcode: u8,
+    pub state: u8,
+    pub group: u16,
+    pub priority: u32,
+    /// Milliseconds.
+    pub hello_time: u32,
+    pub hold_time: u32,
+    pub virtual_ip: IpAddr,
+    /// The plain text password of version 1, "cisco" by default.
+    pub auth_data: Option<String>,
+}
+
+impl HsrpPacket {
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        match payload.first()? {
+            0 => Self::parse_v1(payload.get(..V1_LEN)?),
+            &GROUP_STATE_TLV if usize::from(*payload.get(1)?) == GROUP_STATE_LEN => {
+                Self::parse_v2(payload.get(2..2 + GROUP_STATE_LEN)?)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_v1(data: &[u8]) -> Option<Self> {
+        let auth = data[8..16].iter().take_while(|&&b| b != 0);
+        Some(HsrpPacket {
+            version: 1,
+            opcode: data[1],
+            state: data[2],
+            hello_time: u32::from(data[3]) * 1000,
+            hold_time: u32::from(data[4]) * 1000,
+            priority: u32::from(data[5]),
+            group: u16::from(data[6]),
+            auth_data: Some(auth.map(|&b| char::from(b)).collect()),
+            virtual_ip: IpAddr::V4(Ipv4Addr::new(data[16], data[17], data[18], data[19])),
+        })
+    }
+
+    fn parse_v2(data: &[u8]) -> Option<Self> {
+        if data[0] != 2 {
+            return None;
+        }
+        let u32_at = |i: usize| u32::from_be_bytes(data[i..i + 4].try_into().unwrap());
+        let address = &data[24..40];
+        let virtual_ip = match data[3] {
+            4 => IpAddr::V4(Ipv4Addr::new(
+                address[0], address[1], address[2], address[3],
+            )),
+            6 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(address).ok()?)),
+            _ => return None,
+        };
+        Some(HsrpPacket {
+            version: 2,
+            opcode: data[1],
+            state: data[2],
+            group: u16::from_be_bytes([data[4], data[5]]),
+            priority: u32_at(12),
+            hello_time: u32_at(16),
+            hold_time: u32_at(20),
+            virtual_ip,
+            auth_data: None,
+        })
+    }
+
+    pub fn protocol(&self) -> &'static str {
+        if self.version == 2 {
+            "HSRPv2"
+        } else {
+            "HSRP"
+        }
+    }
+
+    pub fn info(&self) -> String {
+        format!(
+            "{} (state {}), group {}, priority {}, {}",
+            opcode_name(self.opcode),
+            state_name(self.state),
+            self.group,
+            self.priority,
+            self.virtual_ip
+        )
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("hsrp.version", self.version);
+        fields.insert("hsrp.opcode", self.opcode);
+        fields.insert("hsrp.state", self.state);
+        fields.insert("hsrp.group", self.group);
+        fields.insert("hsrp.priority", self.priority);
+        fields.insert("hsrp.hellotime", self.hello_time);
+        fields.insert("hsrp.holdtime", self.hold_time);
+        fields.insert("hsrp.virt_ip", self.virtual_ip);
+        if let Some(auth_data) = &self.auth_data {
+            fields.insert("hsrp.auth_data", auth_data.as_str());
+        }
+    }
+
+    /// Coups and resigns, where a router takes over or gives up the
+    /// active role.
+    pub fn role_change(&self) -> Option<String> {
+        let action = match self.opcode {
+            COUP => "is taking over as active router",
+            RESIGN => "is resigning as active router",
+            _ => return None,
+        };
+        Some(format!("HSRP group {}: router {}", self.group, action))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v1_hello() {
+        let mut data = vec![0, HELLO, 16, 3, 10, 110, 1, 0];
+        data.extend_from_slice(b"cisco\0\0\0");
+        data.extend_from_slice(&[10, 0, 0, 1]);
+        let hsrp = HsrpPacket::parse(&data).unwrap();
+        assert_eq!(
+            hsrp.info(),
+            "Hello (state Active), group 1, priority 110, 10.0.0.1"
+        );
+        assert_eq!(hsrp.hold_time, 10_000);
+        assert_eq!(hsrp.auth_data.as_deref(), Some("cisco"));
+        assert!(hsrp.role_change().is_none());
+    }
+
+    #[test]
+    fn test_v2_coup() {
+        let mut data = vec![GROUP_STATE_TLV, 40, 2, COUP, 4, 6, 0, 20];
+        data.extend_from_slice(&[0x00, 0x00, 0x0c, 0x9f, 0xf0, 0x14]);
+        data.extend_from_slice(&200u32.to_be_bytes());
+        data.extend_from_slice(&3000u32.to_be_bytes());
+        data.extend_from_slice(&10000u32.to_be_bytes());
+        data.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        let hsrp = HsrpPacket::parse(&data).unwrap();
+        assert_eq!(hsrp.protocol(), "HSRPv2");
+        assert_eq!(
+            hsrp.info(),
+            "Coup (state Speak), group 20, priority 200, 2001:db8::1"
+        );
+        assert_eq!(
+            hsrp.role_change().unwrap(),
+            "HSRP group 20: router is taking over as active router"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use crate::fields::Fields;
+
+pub const HEADER_END: &[u8] = b"\r\n\r\n";
+pub const SWITCHING_PROTOCOLS: u16 = 101;
+
+// Header fields kept as fields of the packet, by their field names
+const HEADER_FIELDS: [(&str, &str); 9] = [
+    ("host", "http.host"),
+    ("user-agent", "http.user_agent"),
+    ("content-type", "http.content_type"),
+    ("server", "http.server"),
+    ("location", "http.location"),
+    ("referer", "http.referer"),
+    ("cookie", "http.cookie"),
+    ("set-cookie", "http.set_cookie"),
+    ("upgrade", "http.upgrade"),
+];
+
+const METHODS: [&str; 9] = [
+    "GET", "POST", "PUT", "DELETE", "HEAD", "OPTIONS", "PATCH", "TRACE", "CONNECT",
+];
+
+/// The position of `needle` in `data`.
+pub fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Whether `data` starts with the start line of a request or response.
+pub fn starts_message(data: &[u8]) -> bool {
+    data.starts_with(b"HTTP/1.")
+        || METHODS.iter().any(|method| {
+            data.starts_with(method.as_bytes()) && data.get(method.len()) == Some(&b' ')
+        })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartLine {
+    Request { method: String, target: String },
+    Response { status: u16, reason: String },
+}
+
+/// The start line and header fields of an HTTP/1.x request or response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Head {
+    pub start_line: StartLine,
+    pub version: String,
+    /// Names in lower case, values trimmed.
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    /// Parses the head up to the blank line ending it.
+    pub fn parse(head: &[u8]) -> Option<Self> {
+        let text = String::from_utf8_lossy(head);
+        let mut lines = text.trim_end().split("\r\n");
+        let mut parts = lines.next()?.splitn(3, ' ');
+        let first = parts.next()?;
+        let (start_line, version) = if first.starts_with("HTTP/1.") {
+            let status = parts.next()?.parse().ok()?;
+            let reason = parts.next().unwrap_or_default().to_string();
+            (StartLine::Response { status, reason }, first)
+        } else {
+            let target = parts.next()?.to_string();
+            let version = parts.next()?;
+            if !METHODS.contains(&first) || !version.starts_with("HTTP/1.") {
+                return None;
+            }
+            let method = first.to_string();
+            (StartLine::Request { method, target }, version)
+        };
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        Some(Head {
+            start_line,
+            version: version.to_string(),
+            headers,
+        })
+    }
+
+    /// The value of the first header field called `name`, in lower case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn method(&self) -> Option<&str> {
+        match &self.start_line {
+            StartLine::Request { method, .. } => Some(method),
+            StartLine::Response { .. } => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        match self.start_line {
+            StartLine::Request { .. } => None,
+            StartLine::Response { status, .. } => Some(status),
+        }
+    }
+
+    /// Whether the connection changes to the protocol named in the
+    /// Upgrade header.
+    pub fn switches_protocols(&self) -> bool {
+        self.status() == Some(SWITCHING_PROTOCOLS)
+    }
+
+    /// The start line, e.g. `GET /index.html HTTP/1.1`.
+    pub fn info(&self) -> String {
+        match &self.start_line {
+            StartLine::Request { method, target } => {
+                format!("{} {} {}", method, target, self.version)
+            }
+            StartLine::Response { status, reason } => {
+                format!("{} {} {}", self.version, status, reason)
+                    .trim_end()
+                    .to_string()
+            }
+        }
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        match &self.start_line {
+            StartLine::Request { method, target } => {
+                fields.insert("http.request.method", method.as_str());
+                fields.insert("http.request.uri", target.as_str());
+                fields.insert("http.request.version", self.version.as_str());
+            }
+            StartLine::Response { status, reason } => {
+                fields.insert("http.response.code", *status);
+                fields.insert("http.response.phrase", reason.as_str());
+                fields.insert("http.response.version", self.version.as_str());
+            }
+        }
+        if let Some(length) = self
+            .header("content-length")
+            .and_then(|value| value.parse::<u64>().ok())
+        {
+            fields.insert("http.content_length", length);
+        }
+        for (header, field) in HEADER_FIELDS {
+            if let Some(value) = self.header(header) {
+                fields.insert(field, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_head() {
+        let request =
+            Head::parse(b"GET /chat HTTP/1.1\r\nHost: example.com\r\nUpgrade:  websocket \r\n")
+                .unwrap();
+        assert_eq!(request.method(), Some("GET"));
+        assert_eq!(request.header("host"), Some("example.com"));
+        assert_eq!(request.header("upgrade"), Some("websocket"));
+        assert_eq!(request.version, "HTTP/1.1");
+
+        let response = Head::parse(b"HTTP/1.1 101 Switching Protocols").unwrap();
+        assert!(response.switches_protocols());
+        assert_eq!(
+            response.start_line,
+            StartLine::Response {
+                status: 101,
+                reason: "Switching Protocols".to_string()
+            }
+        );
+
+        assert!(Head::parse(b"BREW /pot HTTP/1.1").is_none());
+        assert!(Head::parse(b"GET /").is_none());
+        assert!(Head::parse(b"HTTP/1.1 abc").is_none());
+        assert!(starts_message(b"POST /form HTTP/1.1"));
+        assert!(!starts_message(b"GETTING"));
+        assert_eq!(find(b"ab\r\n\r\ncd", HEADER_END), Some(2));
+    }
+
+    #[test]
+    fn test_head_fields() {
+        let mut fields = Fields::new();
+        let request = Head::parse(b"GET /a HTTP/1.1\r\nHost: corp.example\r\n").unwrap();
+        request.insert_fields(&mut fields);
+        let response = Head::parse(b"HTTP/1.0 404 Not Found\r\nContent-Length: 9").unwrap();
+        response.insert_fields(&mut fields);
+
+        assert_eq!(request.info(), "GET /a HTTP/1.1");
+        assert_eq!(response.info(), "HTTP/1.0 404 Not Found");
+        assert_eq!(fields.get("http.host"), Some(&"corp.example".into()));
+        assert_eq!(fields.get("http.request.uri"), Some(&"/a".into()));
+        assert_eq!(fields.get("http.response.code"), Some(&404u16.into()));
+        assert_eq!(fields.get("http.content_length"), Some(&9u64.into()));
+    }
+}
@@ -0,0 +1,589 @@
+use crate::fields::Fields;
+use crate::grpc::{self, GrpcMessage};
+use crate::hpack::{self, Header};
+use crate::protobuf::DescriptorPool;
+use crate::reassembly::StreamData;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// What a client sends first on an HTTP/2 connection with prior knowledge,
+/// like h2c inside a cluster.
+pub const CONNECTION_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+pub const DATA: u8 = 0x0;
+pub const HEADERS: u8 = 0x1;
+pub const PRIORITY: u8 = 0x2;
+pub const RST_STREAM: u8 = 0x3;
+pub const SETTINGS: u8 = 0x4;
+pub const PUSH_PROMISE: u8 = 0x5;
+pub const PING: u8 = 0x6;
+pub const GOAWAY: u8 = 0x7;
+pub const WINDOW_UPDATE: u8 = 0x8;
+pub const CONTINUATION: u8 = 0x9;
+
+const FLAG_END_STREAM: u8 = 0x01;
+const FLAG_END_HEADERS: u8 = 0x04;
+const FLAG_PADDED: u8 = 0x08;
+const FLAG_PRIORITY: u8 = 0x20;
+
+const FRAME_HEADER_LEN: usize = 9;
+
+// Upper bounds on tracked state so a long capture can't grow without limit
+const MAX_CONNECTIONS: usize = 65536;
+const MAX_STREAMS: usize = 1024;
+const MAX_BUFFERED: usize = 1 << 20;
+
+pub fn frame_type_name(frame_type: u8) -> &'static str {
+    match frame_type {
+        DATA => "DATA",
+        HEADERS => "HEADERS",
+        PRIORITY => "PRIORITY",
+        RST_STREAM => "RST_STREAM",
+        SETTINGS => "SETTINGS",
+        PUSH_PROMISE => "PUSH_PROMISE",
+        PING => "PING",
+        GOAWAY => "GOAWAY",
+        WINDOW_UPDATE => "WINDOW_UPDATE",
+        CONTINUATION => "CONTINUATION",
+        _ => "Unknown",
+    }
+}
+
+pub fn error_code_name(code: u32) -> &'static str {
+    match code {
+        0x0 => "NO_ERROR",
+        0x1 => "PROTOCOL_ERROR",
+        0x2 => "INTERNAL_ERROR",
+        0x3 => "FLOW_CONTROL_ERROR",
+        0x4 => "SETTINGS_TIMEOUT",
+        0x5 => "STREAM_CLOSED",
+        0x6 => "FRAME_SIZE_ERROR",
+        0x7 => "REFUSED_STREAM",
+        0x8 => "CANCEL",
+        0x9 => "COMPRESSION_ERROR",
+        0xa => "CONNECT_ERROR",
+        0xb => "ENHANCE_YOUR_CALM",
+        0xc => "INADEQUATE_SECURITY",
+        0xd => "HTTP_1_1_REQUIRED",
+        _ => "Unknown",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FramePayload {
+    /// gRPC messages completed by the frame.
+    Data(Vec<GrpcMessage>),
+    /// Decoded header block of HEADERS, PUSH_PROMISE and CONTINUATION
+    /// frames. Empty until the frame that ends the block.
+    Headers(Vec<Header>),
+    RstStream {
+        error_code: u32,
+    },
+    Settings(Vec<(u16, u32)>),
+    GoAway {
+        last_stream_id: u32,
+        error_code: u32,
+    },
+    WindowUpdate {
+        increment: u32,
+    },
+    Other,
+}
+
+/// An HTTP/2 frame (RFC 9113 section 4).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Http2Frame {
+    pub frame_type: u8,
+    pub flags: u8,
+    pub stream_id: u32,
+    pub length: usize,
+    pub payload: FramePayload,
+    /// Path of the gRPC call the stream carries.
+    pub grpc_method: Option<String>,
+}
+
+impl Http2Frame {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        match &self.payload {
+            FramePayload::Headers(headers) => headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str()),
+            _ => None,
+        }
+    }
+
+    /// The gRPC status of trailers.
+    pub fn grpc_status(&self) -> Option<u32> {
+        self.header("grpc-status")?.parse().ok()
+    }
+
+    pub fn info(&self) -> String {
+        let mut info = format!("{}[{}]", frame_type_name(self.frame_type), self.stream_id);
+        let detail = match &self.payload {
+            FramePayload::Data(messages) => messages
+                .iter()
+                .map(|m| format!("gRPC {}", m.info()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            FramePayload::Headers(_) => {
+                if let (Some(method), Some(path)) = (self.header(":method"), self.header(":path")) {
+                    format!("{} {}", method, path)
+                } else if let Some(status) = self.header(":status") {
+                    status.to_string()
+                } else if let Some(status) = self.grpc_status() {
+                    let mut detail =
+                        format!("grpc-status: {} ({})", status, grpc::status_name(status));
+                    if let Some(message) = self.header("grpc-message") {
+                        detail.push_str(&format!(" {}", message));
+                    }
+                    detail
+                } else {
+                    String::new()
+                }
+            }
+            FramePayload::RstStream { error_code } => error_code_name(*error_code).to_string(),
+            FramePayload::GoAway { error_code, .. } => error_code_name(*error_code).to_string(),
+            _ => String::new(),
+        };
+        if !detail.is_empty() {
+            info.push_str(&format!(": {}", detail));
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("http2.type", self.frame_type);
+        fields.insert("http2.flags", self.flags);
+        fields.insert("http2.streamid", self.stream_id);
+        fields.insert("http2.length", self.length);
+        match &self.payload {
+            FramePayload::Data(messages) => {
+                for message in messages {
+                    message.insert_fields(fields);
+                }
+            }
+            FramePayload::Headers(headers) => {
+                for (name, value) in headers {
+                    fields.insert("http2.header.name", name.as_str());
+                    fields.insert("http2.header.value", value.as_str());
+                    let field = match name.as_str() {
+                        ":method" => "http2.headers.method",
+                        ":path" => "http2.headers.path",
+                        ":authority" => "http2.headers.authority",
+                        ":status" => "http2.headers.status",
+                        "content-type" => "http2.headers.content_type",
+                        _ => continue,
+                    };
+                    fields.insert(field, value.as_str());
+                }
+                if let Some(status) = self.grpc_status() {
+                    fields.insert("grpc.status_code", status);
+                    if let Some(message) = self.header("grpc-message") {
+                        fields.insert("grpc.status_message", message);
+                    }
+                }
+            }
+            FramePayload::RstStream { error_code } => {
+                fields.insert("http2.rst_stream.error", *error_code);
+            }
+            FramePayload::Settings(settings) => {
+                for (id, value) in settings {
+                    fields.insert("http2.settings.id", *id);
+                    fields.insert("http2.settings.value", *value);
+                }
+            }
+            FramePayload::GoAway {
+                last_stream_id,
+                error_code,
+            } => {
+                fields.insert("http2.goaway.last_stream_id", *last_stream_id);
+                fields.insert("http2.goaway.error", *error_code);
+            }
+            FramePayload::WindowUpdate { increment } => {
+                fields.insert("http2.window_update.window_size_increment", *increment);
+            }
+            FramePayload::Other => {}
+        }
+        if let Some(method) = &self.grpc_method {
+            fields.insert("grpc.method", method.as_str());
+        }
+    }
+}
+
+/// One direction of a connection.
+#[derive(Debug, Default)]
+struct Direction {
+    buffer: Vec<u8>,
+    decoder: hpack::Decoder,
+    /// Header block waiting for CONTINUATION frames.
+    header_block: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Stream {
+    grpc_method: Option<String>,
+    /// Partial gRPC messages, by direction.
+    messages: [Vec<u8>; 2],
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug)]
+struct Connection {
+    client: Endpoint,
+    /// Client to server, then server to client.
+    directions: [Direction; 2],
+    streams: HashMap<u32, Stream>,
+    preface_seen: bool,
+}
+
+/// Splits reassembled TCP streams into HTTP/2 frames and follows the header
+/// compression and gRPC calls of every connection.
+#[derive(Debug, Default)]
+pub struct Http2Tracker {
+    connections: HashMap<(Endpoint, Endpoint), Connection>,
+    descriptors: Option<DescriptorPool>,
+}
+
+impl Http2Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes gRPC messages of the services in `descriptors`.
+    pub fn with_descriptors(mut self, descriptors: DescriptorPool) -> Self {
+        self.descriptors = Some(descriptors);
+        self
+    }
+
+    /// Adds stream data sent from `src` to `dst` and returns the frames it
+    /// completes. Connections are recognized by the client's preface.
+    pub fn process(
+        &mut self,
+        src: Endpoint,
+        dst: Endpoint,
+        stream: &StreamData,
+    ) -> Vec<Http2Frame> {
+        let key = if src <= dst { (src, dst) } else { (dst, src) };
+        if stream.gap {
+            // Header compression state is lost with the missing data
+            self.connections.remove(&key);
+        }
+        if stream.data.is_empty() {
+            return Vec::new();
+        }
+        if !self.connections.contains_key(&key) {
+            if !stream.data.starts_with(CONNECTION_PREFACE) {
+                return Vec::new();
+            }
+            if self.connections.len() >= MAX_CONNECTIONS {
+                self.connections.clear();
+            }
+            self.connections.insert(
+                key,
+                Connection {
+                    client: src,
+                    directions: Default::default(),
+                    streams: HashMap::new(),
+                    preface_seen: false,
+                },
+            );
+        }
+
+        let connection = self.connections.get_mut(&key).expect("inserted above");
+        let index = usize::from(src != connection.client);
+        if connection.directions[index].buffer.len() + stream.data.len() > MAX_BUFFERED {
+            self.connections.remove(&key);
+            return Vec::new();
+        }
+        let direction = &mut connection.directions[index];
+        direction.buffer.extend_from_slice(&stream.data);
+        if index == 0 && !connection.preface_seen {
+            if direction.buffer.len() < CONNECTION_PREFACE.len() {
+                return Vec::new();
+            }
+            direction.buffer.drain(..CONNECTION_PREFACE.len());
+            connection.preface_seen = true;
+        }
+
+        let mut frames = Vec::new();
+        let mut consumed = 0;
+        while let Some(header) = direction.buffer.get(consumed..consumed + FRAME_HEADER_LEN) {
+            let length =
+                usize::from(header[0]) << 16 | usize::from(header[1]) << 8 | usize::from(header[2]);
+            let start = consumed + FRAME_HEADER_LEN;
+            if direction.buffer.len() < start + length {
+                break;
+            }
+            let frame_type = header[3];
+            let flags = header[4];
+            let stream_id =
+                u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff;
+            let payload = direction.buffer[start..start + length].to_vec();
+            consumed = start + length;
+
+            let mut frame = Http2Frame {
+                frame_type,
+                flags,
+                stream_id,
+                length,
+                payload: FramePayload::Other,
+                grpc_method: None,
+            };
+            frame.payload = match frame_type {
+                DATA => {
+                    let data = unpadded(flags, &payload).unwrap_or_default();
+                    let messages = match connection.streams.get_mut(&stream_id) {
+                        Some(stream) if stream.grpc_method.is_some() => {
+                            stream.messages[index].extend_from_slice(data);
+                            GrpcMessage::take_all(&mut stream.messages[index])
+                        }
+                        _ => Vec::new(),
+                    };
+                    FramePayload::Data(messages)
+                }
+                HEADERS | PUSH_PROMISE | CONTINUATION => {
+                    let fragment = match frame_type {
+                        HEADERS => header_fragment(flags, &payload),
+                        // The promised stream ID comes before the block
+                        PUSH_PROMISE => unpadded(flags, &payload).and_then(|p| p.get(4..)),
+                        _ => Some(payload.as_slice()),
+                    };
+                    direction
+                        .header_block
+                        .extend_from_slice(fragment.unwrap_or_default());
+                    if flags & FLAG_END_HEADERS != 0 {
+                        let block = std::mem::take(&mut direction.header_block);
+                        FramePayload::Headers(direction.decoder.decode(&block).unwrap_or_default())
+                    } else {
+                        FramePayload::Headers(Vec::new())
+                    }
+                }
+                RST_STREAM if payload.len() == 4 => FramePayload::RstStream {
+                    error_code: u32::from_be_bytes([
+                        payload[0], payload[1], payload[2], payload[3],
+                    ]),
+                },
+                SETTINGS => FramePayload::Settings(
+                    payload
+                        .chunks_exact(6)
+                        .map(|s| {
+                            (
+                                u16::from_be_bytes([s[0], s[1]]),
+                                u32::from_be_bytes([s[2], s[3], s[4], s[5]]),
+                            )
+                        })
+                        .collect(),
+                ),
+                GOAWAY if payload.len() >= 8 => FramePayload::GoAway {
+                    last_stream_id: u32::from_be_bytes([
+                        payload[0], payload[1], payload[2], payload[3],
+                    ]) & 0x7fff_ffff,
+                    error_code: u32::from_be_bytes([
+                        payload[4], payload[5], payload[6], payload[7],
+                    ]),
+                },
+                WINDOW_UPDATE if payload.len() == 4 => FramePayload::WindowUpdate {
+                    increment: u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]])
+                        & 0x7fff_ffff,
+                },
+                _ => FramePayload::Other,
+            };
+
+            // Requests name the gRPC method of their stream
+            if let (Some(path), Some(content_type)) =
+                (frame.header(":path"), frame.header("content-type"))
+            {
+                if grpc::is_grpc(content_type) && connection.streams.len() < MAX_STREAMS {
+                    connection.streams.entry(stream_id).or_default().grpc_method =
+                        Some(path.to_string());
+                }
+            }
+            let stream = connection.streams.get(&stream_id);
+            frame.grpc_method = stream.and_then(|s| s.grpc_method.clone());
+            if let (Some(descriptors), Some(method)) = (&self.descriptors, &frame.grpc_method) {
+                if let FramePayload::Data(messages) = &mut frame.payload {
+                    decode_messages(descriptors, method, index == 0, messages);
+                }
+            }
+
+            // The server ending its side ends the call
+            let ends_stream = matches!(frame_type, DATA | HEADERS) && flags & FLAG_END_STREAM != 0;
+            if frame_type == RST_STREAM || (ends_stream && index == 1) {
+                connection.streams.remove(&stream_id);
+            }
+            frames.push(frame);
+        }
+        direction.buffer.drain(..consumed);
+        frames
+    }
+}
+
+fn decode_messages(
+    descriptors: &DescriptorPool,
+    method: &str,
+    request: bool,
+    messages: &mut [GrpcMessage],
+) {
+    let Some(descriptor) = descriptors.method(method) else {
+        return;
+    };
+    let message_type = if request {
+        &descriptor.input_type
+    } else {
+        &descriptor.output_type
+    };
+    for message in messages.iter_mut().filter(|m| !m.compressed) {
+        message.decoded = descriptors.decode_message(message_type, &message.data);
+        if message.decoded.is_some() {
+            message.message_type = Some(message_type.clone());
+        }
+    }
+}
+
+/// The frame payload without padding.
+fn unpadded(flags: u8, payload: &[u8]) -> Option<&[u8]> {
+    if flags & FLAG_PADDED == 0 {
+        return Some(payload);
+    }
+    let (&pad_len, rest) = payload.split_first()?;
+    rest.get(..rest.len().checked_sub(usize::from(pad_len))?)
+}
+
+/// The header block fragment of a HEADERS frame.
+fn header_fragment(flags: u8, payload: &[u8]) -> Option<&[u8]> {
+    let fragment = unpadded(flags, payload)?;
+    if flags & FLAG_PRIORITY != 0 {
+        // Stream dependency and weight
+        fragment.get(5..)
+    } else {
+        Some(fragment)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::protobuf::tests::{greeter_descriptor_set, hello_request};
+
+    pub(crate) fn frame(frame_type: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend_from_slice(&[frame_type, flags]);
+        frame.extend_from_slice(&stream_id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// Literal header field without indexing, with a literal name.
+    fn literal(name: &str, value: &str) -> Vec<u8> {
+        let mut bytes = vec![0, name.len() as u8];
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(value.len() as u8);
+        bytes.extend_from_slice(value.as_bytes());
+        bytes
+    }
+
+    fn grpc_message(message: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0];
+        bytes.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(message);
+        bytes
+    }
+
+    /// A gRPC SayHello request, with the connection preface.
+    pub(crate) fn grpc_request() -> Vec<u8> {
+        // :method POST and :scheme http from the static table
+        let mut block = vec![0x83, 0x86];
+        block.extend(literal(":path", "/helloworld.Greeter/SayHello"));
+        block.extend(literal("content-type", "application/grpc"));
+
+        let mut data = CONNECTION_PREFACE.to_vec();
+        data.extend(frame(SETTINGS, 0, 0, &[0, 3, 0, 0, 0, 100]));
+        data.extend(frame(HEADERS, FLAG_END_HEADERS, 1, &block));
+        data.extend(frame(
+            DATA,
+            FLAG_END_STREAM,
+            1,
+            &grpc_message(&hello_request()),
+        ));
+        data
+    }
+
+    #[test]
+    fn test_grpc_call() {
+        let mut tracker = Http2Tracker::new()
+            .with_descriptors(DescriptorPool::decode(&greeter_descriptor_set()).unwrap());
+        let client = ("10.0.0.1".parse().unwrap(), 40000);
+        let server = ("10.0.0.2".parse().unwrap(), 50051);
+
+        // The request arrives in two pieces, splitting the DATA frame
+        let request = grpc_request();
+        let (first, second) = request.split_at(request.len() - 4);
+        let frames = tracker.process(client, server, &StreamData::complete(first));
+        let infos: Vec<_> = frames.iter().map(|f| f.info()).collect();
+        assert_eq!(
+            infos,
+            vec![
+                "SETTINGS[0]",
+                "HEADERS[1]: POST /helloworld.Greeter/SayHello"
+            ]
+        );
+        let frames = tracker.process(client, server, &StreamData::complete(second));
+        assert_eq!(
+            frames[0].info(),
+            r#"DATA[1]: gRPC helloworld.HelloRequest {person.name: "world" person.mood: HAPPY times: 2}"#
+        );
+
+        // Trailers with an error status end the call
+        let mut trailers = literal("grpc-status", "14");
+        trailers.extend(literal("grpc-message", "backend down"));
+        let mut response = frame(HEADERS, FLAG_END_HEADERS, 1, &[0x88]);
+        response.extend(frame(
+            HEADERS,
+            FLAG_END_HEADERS | FLAG_END_STREAM,
+            1,
+            &trailers,
+        ));
+        response.extend(frame(RST_STREAM, 0, 3, &[0, 0, 0, 8]));
+        let frames = tracker.process(server, client, &StreamData::complete(&response));
+        assert_eq!(frames[0].info(), "HEADERS[1]: 200");
+        assert_eq!(
+            frames[0].grpc_method.as_deref(),
+            Some("/helloworld.Greeter/SayHello")
+        );
+        assert_eq!(
+            frames[1].info(),
+            "HEADERS[1]: grpc-status: 14 (UNAVAILABLE) backend down"
+        );
+        assert_eq!(frames[2].info(), "RST_STREAM[3]: CANCEL");
+    }
+
+    #[test]
+    fn test_continuation_frames() {
+        let mut tracker = Http2Tracker::new();
+        let client = ("10.0.0.1".parse().unwrap(), 40000);
+        let server = ("10.0.0.2".parse().unwrap(), 80);
+
+        let mut data = CONNECTION_PREFACE.to_vec();
+        data.extend(frame(HEADERS, 0, 1, &[0x82]));
+        data.extend(frame(CONTINUATION, FLAG_END_HEADERS, 1, &[0x84]));
+        let frames = tracker.process(client, server, &StreamData::complete(&data));
+        assert_eq!(frames[0].info(), "HEADERS[1]");
+        assert_eq!(frames[1].info(), "CONTINUATION[1]: GET /");
+    }
+
+    #[test]
+    fn test_ignores_other_protocols() {
+        let mut tracker = Http2Tracker::new();
+        let client = ("10.0.0.1".parse().unwrap(), 40000);
+        let server = ("10.0.0.2".parse().unwrap(), 80);
+        let frames = tracker.process(
+            client,
+            server,
+            &StreamData::complete(b"GET / HTTP/1.1\r\n\r\n"),
+        );
+        assert!(frames.is_empty());
+        assert!(tracker.connections.is_empty());
+    }
+}
@@ -0,0 +1,392 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// Upper bound on unanswered echo requests so a long capture can't grow
+// without limit
+const MAX_PENDING_ECHOES: usize = 65536;
+
+/// Name of an ICMP (or ICMPv6 if `v6`) message type, with the code spelled
+/// out where it matters, e.g. `Destination unreachable (port unreachable)`.
+pub fn describe(v6: bool, icmp_type: u8, code: u8) -> String {
+    let (name, code_name) = if v6 {
+        describe_v6(icmp_type, code)
+    } else {
+        describe_v4(icmp_type, code)
+    };
+    match (name, code_name) {
+        (Some(name), Some(code_name)) => format!("{} ({})", name, code_name),
+        (Some(name), None) => name.to_string(),
+        (None, _) => format!("Type: {}, Code: {}", icmp_type, code),
+    }
+}
+
+fn describe_v4(icmp_type: u8, code: u8) -> (Option<&'static str>, Option<&'static str>) {
+    match icmp_type {
+        0 => (Some("Echo (ping) reply"), None),
+        3 => (
+            Some("Destination unreachable"),
+            match code {
+                0 => Some("network unreachable"),
+                1 => Some("host unreachable"),
+                2 => Some("protocol unreachable"),
+                3 => Some("port unreachable"),
+                4 => Some("fragmentation needed"),
+                5 => Some("source route failed"),
+                6 => Some("destination network unknown"),
+                7 => Some("destination host unknown"),
+                9 | 10 => Some("administratively prohibited"),
+                13 => Some("communication administratively filtered"),
+                _ => None,
+            },
+        ),
+        4 => (Some("Source quench"), None),
+        5 => (
+            Some("Redirect"),
+            match code {
+                0 => Some("for network"),
+                1 => Some("for host"),
+                2 => Some("for TOS and network"),
+                3 => Some("for TOS and host"),
+                _ => None,
+            },
+        ),
+        8 => (Some("Echo (ping) request"), None),
+        9 => (Some("Router advertisement"), None),
+        10 => (Some("Router solicitation"), None),
+        11 => (
+            Some("Time exceeded"),
+            match code {
+                0 => Some("TTL exceeded in transit"),
+                1 => Some("fragment reassembly time exceeded"),
+                _ => None,
+            },
+        ),
+        12 => (Some("Parameter problem"), None),
+        13 => (Some("Timestamp request"), None),
+        14 => (Some("Timestamp reply"), None),
+        _ => (None, None),
+    }
+}
+
+fn describe_v6(icmp_type: u8, code: u8) -> (Option<&'static str>, Option<&'static str>) {
+    match icmp_type {
+        1 => (
+            Some("Destination unreachable"),
+            match code {
+                0 => Some("no route to destination"),
+                1 => Some("administratively prohibited"),
+                2 => Some("beyond scope of source address"),
+                3 => Some("address unreachable"),
+                4 => Some("port unreachable"),
+                5 => Some("source address failed policy"),
+                6 => Some("reject route"),
+                _ => None,
+            },
+        ),
+        2 => (Some("Packet too big"), None),
+        3 => (
+            Some("Time exceeded"),
+            match code {
+                0 => Some("hop limit exceeded in transit"),
+                1 => Some("fragment reassembly time exceeded"),
+                _ => None,
+            },
+        ),
+        4 => (
+            Some("Parameter problem"),
+            match code {
+                0 => Some("erroneous header field"),
+                1 => Some("unrecognized next header"),
+                2 => Some("unrecognized IPv6 option"),
+                _ => None,
+            },
+        ),
+        128 => (Some("Echo (ping) request"), None),
+        129 => (Some("Echo (ping) reply"), None),
+        130 => (Some("Multicast Listener Query"), None),
+        131 => (Some("Multicast Listener Report"), None),
+        132 => (Some("Multicast Listener Done"), None),
+        133 => (Some("Router Solicitation"), None),
+        134 => (Some("Router Advertisement"), None),
+        135 => (Some("Neighbor Solicitation"), None),
+        136 => (Some("Neighbor Advertisement"), None),
+        137 => (Some("Redirect"), None),
+        143 => (Some("Multicast Listener Report Message v2"), None),
+        _ => (None, None),
+    }
+}
+
+/// Field names of the ICMP and ICMPv6 dissectors.
+pub struct FieldNames {
+    pub identifier: &'static str,
+    pub sequence: &'static str,
+    pub response_time: &'static str,
+    pub mtu: &'static str,
+    pub quoted_src: &'static str,
+    pub quoted_dst: &'static str,
+    pub quoted_proto: &'static str,
+    pub quoted_srcport: &'static str,
+    pub quoted_dstport: &'static str,
+}
+
+const ICMP_FIELDS: FieldNames = FieldNames {
+    identifier: "icmp.ident",
+    sequence: "icmp.seq",
+    response_time: "icmp.resptime",
+    mtu: "icmp.mtu",
+    quoted_src: "icmp.quoted.src",
+    quoted_dst: "icmp.quoted.dst",
+    quoted_proto: "icmp.quoted.proto",
+    quoted_srcport: "icmp.quoted.srcport",
+    quoted_dstport: "icmp.quoted.dstport",
+};
+
+const ICMPV6_FIELDS: FieldNames = FieldNames {
+    identifier: "icmpv6.echo.identifier",
+    sequence: "icmpv6.echo.sequence_number",
+    response_time: "icmpv6.resptime",
+    mtu: "icmpv6.mtu",
+    quoted_src: "icmpv6.quoted.src",
+    quoted_dst: "icmpv6.quoted.dst",
+    quoted_proto: "icmpv6.quoted.proto",
+    quoted_srcport: "icmpv6.quoted.srcport",
+    quoted_dstport: "icmpv6.quoted.dstport",
+};
+
+pub fn field_names(v6: bool) -> &'static FieldNames {
+    if v6 {
+        &ICMPV6_FIELDS
+    } else {
+        &ICMP_FIELDS
+    }
+}
+
+/// The MTU announced by an ICMPv4 "fragmentation needed" or an ICMPv6
+/// "packet too big" message.
+pub fn mtu(v6: bool, message: &[u8]) -> Option<u32> {
+    let header = message.get(..8)?;
+    match (v6, header[0], header[1]) {
+        (false, 3, 4) => Some(u16::from_be_bytes([header[6], header[7]]).into()),
+        (true, 2, _) => Some(u32::from_be_bytes([
+            header[4], header[5], header[6], header[7],
+        ])),
+        _ => None,
+    }
+}
+
+/// Whether the message reports an error and quotes the offending packet.
+pub fn is_error(v6: bool, icmp_type: u8) -> bool {
+    if v6 {
+        matches!(icmp_type, 1..=4)
+    } else {
+        matches!(icmp_type, 3 | 4 | 5 | 11 | 12)
+    }
+}
+
+/// An echo request or reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Echo {
+    pub request: bool,
+    pub identifier: u16,
+    pub sequence: u16,
+}
+
+impl Echo {
+    /// Parses a whole ICMP message, starting at the type field.
+    pub fn parse(v6: bool, message: &[u8]) -> Option<Echo> {
+        let header = message.get(..8)?;
+        let request = match (v6, header[0]) {
+            (false, 8) | (true, 128) => true,
+            (false, 0) | (true, 129) => false,
+            _ => return None,
+        };
+        Some(Echo {
+            request,
+            identifier: u16::from_be_bytes([header[4], header[5]]),
+            sequence: u16::from_be_bytes([header[6], header[7]]),
+        })
+    }
+}
+
+/// The start of the packet quoted by an ICMP error message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotedPacket {
+    pub source: IpAddr,
+    pub destination: IpAddr,
+    pub protocol: u8,
+    /// Source and destination port for TCP and UDP.
+    pub ports: Option<(u16, u16)>,
+}
+
+impl QuotedPacket {
+    /// Parses the IP header and the first transport bytes following the
+    /// 8 byte header of an ICMP error message.
+    pub fn parse(message: &[u8]) -> Option<QuotedPacket> {
+        let packet = message.get(8..)?;
+        let (source, destination, protocol, transport): (IpAddr, IpAddr, u8, &[u8]) =
+            match packet.first()? >> 4 {
+                4 => {
+                    let header_len = usize::from(packet[0] & 0x0f) * 4;
+                    let header = packet.get(..header_len.max(20))?;
+                    let src: [u8; 4] = header[12..16].try_into().ok()?;
+                    let dst: [u8; 4] = header[16..20].try_into().ok()?;
+                    (
+                        Ipv4Addr::from(src).into(),
+                        Ipv4Addr::from(dst).into(),
+                        header[9],
+                        &packet[header.len()..],
+                    )
+                }
+                6 => {
+                    let header = packet.get(..40)?;
+                    let src: [u8; 16] = header[8..24].try_into().ok()?;
+                    let dst: [u8; 16] = header[24..40].try_into().ok()?;
+                    (
+                        Ipv6Addr::from(src).into(),
+                        Ipv6Addr::from(dst).into(),
+                        header[6],
+                        &packet[40..],
+                    )
+                }
+                _ => return None,
+            };
+
+        let ports = match (protocol, transport.get(..4)) {
+            (6 | 17, Some(t)) => Some((
+                u16::from_be_bytes([t[0], t[1]]),
+                u16::from_be_bytes([t[2], t[3]]),
+            )),
+            _ => None,
+        };
+
+        Some(QuotedPacket {
+            source,
+            destination,
+            protocol,
+            ports,
+        })
+    }
+
+    pub fn protocol_name(&self) -> String {
+        match self.protocol {
+            1 => "ICMP".to_string(),
+            6 => "TCP".to_string(),
+            17 => "UDP".to_string(),
+            58 => "ICMPv6".to_string(),
+            other => format!("IP protocol {}", other),
+        }
+    }
+}
+
+impl fmt::Display for QuotedPacket {
+    /// The flow of the quoted packet, e.g. `UDP 10.0.0.1:5353 -> 10.0.0.2:53`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ports {
+            Some((src_port, dst_port)) => write!(
+                f,
+                "{} {}:{} -> {}:{}",
+                self.protocol_name(),
+                self.source,
+                src_port,
+                self.destination,
+                dst_port
+            ),
+            None => write!(
+                f,
+                "{} {} -> {}",
+                self.protocol_name(),
+                self.source,
+                self.destination
+            ),
+        }
+    }
+}
+
+type EchoKey = (IpAddr, IpAddr, u16, u16);
+
+/// Pairs echo replies with their requests.
+#[derive(Debug, Default)]
+pub struct EchoTracker {
+    pending: HashMap<EchoKey, DateTime<Utc>>,
+}
+
+impl EchoTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a request, or returns the round trip time in seconds for a
+    /// reply to a request seen earlier.
+    pub fn process(
+        &mut self,
+        src: IpAddr,
+        dst: IpAddr,
+        echo: &Echo,
+        timestamp: DateTime<Utc>,
+    ) -> Option<f64> {
+        if echo.request {
+            if self.pending.len() >= MAX_PENDING_ECHOES {
+                self.pending.clear();
+            }
+            self.pending
+                .insert((src, dst, echo.identifier, echo.sequence), timestamp);
+            return None;
+        }
+
+        let sent = self
+            .pending
+            .remove(&(dst, src, echo.identifier, echo.sequence))?;
+        let elapsed = timestamp.signed_duration_since(sent);
+        elapsed.num_microseconds().map(|us| us as f64 / 1_000_000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_type_names() {
+        assert_eq!(
+            describe(false, 3, 3),
+            "Destination unreachable (port unreachable)"
+        );
+        assert_eq!(
+            describe(false, 11, 0),
+            "Time exceeded (TTL exceeded in transit)"
+        );
+        assert_eq!(describe(true, 2, 0), "Packet too big");
+        assert_eq!(describe(false, 200, 1), "Type: 200, Code: 1");
+    }
+
+    #[test]
+    fn test_quoted_udp_packet() {
+        let mut message = vec![3, 3, 0, 0, 0, 0, 0, 0];
+        message.extend_from_slice(&[0x45, 0, 0, 33, 0, 0, 0, 0, 64, 17, 0, 0]);
+        message.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        message.extend_from_slice(&[0x14, 0xe9, 0x00, 0x35, 0, 13, 0, 0]);
+
+        let quoted = QuotedPacket::parse(&message).unwrap();
+        assert_eq!(quoted.ports, Some((5353, 53)));
+        assert_eq!(quoted.to_string(), "UDP 10.0.0.1:5353 -> 10.0.0.2:53");
+    }
+
+    #[test]
+    fn test_echo_pairing() {
+        let mut tracker = EchoTracker::new();
+        let (a, b) = (IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2]));
+        let request = Echo::parse(false, &[8, 0, 0, 0, 0, 1, 0, 7]).unwrap();
+        let reply = Echo::parse(false, &[0, 0, 0, 0, 0, 1, 0, 7]).unwrap();
+        let sent = Utc::now();
+
+        assert_eq!(tracker.process(a, b, &request, sent), None);
+        let rtt = tracker.process(b, a, &reply, sent + Duration::milliseconds(25));
+        assert_eq!(rtt, Some(0.025));
+
+        // A second reply has no request left to pair with
+        assert_eq!(tracker.process(b, a, &reply, sent), None);
+    }
+}
@@ -0,0 +1,347 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionState, ConnectionTable, Exchange};
+use crate::reassembly::{MessageBuffer, StreamData};
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Request {
+        tag: String,
+        command: String,
+        arguments: String,
+    },
+    /// A response starting with `*`.
+    Untagged(String),
+    /// A `+` response asking for more client data.
+    Continuation(String),
+    /// The response completing the request with the same tag.
+    Tagged {
+        tag: String,
+        status: String,
+        text: String,
+    },
+    /// Data after a successful STARTTLS.
+    Encrypted(usize),
+}
+
+/// An IMAP request or response, including any literals it carries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImapMessage {
+    pub from_client: bool,
+    pub kind: Kind,
+    /// Bytes of the message, literals included.
+    pub length: usize,
+    /// The response accepting a STARTTLS command.
+    pub starts_tls: bool,
+}
+
+impl AppMessage for ImapMessage {
+    const TIME_FIELD: &'static str = "imap.response_time";
+
+    fn info(&self) -> String {
+        match &self.kind {
+            Kind::Request {
+                tag,
+                command,
+                arguments,
+            } => format!("Request: {} {} {}", tag, command, arguments)
+                .trim_end()
+                .to_string(),
+            Kind::Untagged(text) => format!("Response: * {}", text),
+            Kind::Continuation(text) => format!("Response: + {}", text),
+            Kind::Tagged { tag, status, text } => {
+                format!("Response: {} {} {}", tag, status, text)
+            }
+            Kind::Encrypted(bytes) => format!("TLS encrypted data, {} bytes", bytes),
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        match &self.kind {
+            Kind::Request {
+                tag,
+                command,
+                arguments,
+            } => {
+                fields.insert("imap.request.tag", tag.as_str());
+                fields.insert("imap.request.command", command.as_str());
+                fields.insert("imap.request", arguments.as_str());
+            }
+            Kind::Untagged(text) | Kind::Continuation(text) => {
+                fields.insert("imap.response", text.as_str());
+            }
+            Kind::Tagged { tag, status, text } => {
+                fields.insert("imap.response.tag", tag.as_str());
+                fields.insert("imap.response.status", status.as_str());
+                fields.insert("imap.response", text.as_str());
+            }
+            Kind::Encrypted(_) => fields.insert("imap.starttls", true),
+        }
+        if !matches!(self.kind, Kind::Encrypted(_)) {
+            fields.insert("imap.length", self.length);
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match &self.kind {
+            Kind::Tagged { status, text, .. } if status == "NO" || status == "BAD" => {
+                Some(format!("{} {}", status, text))
+            }
+            _ => None,
+        }
+    }
+
+    fn note(&self) -> Option<String> {
+        self.starts_tls
+            .then(|| "Connection switched to TLS by STARTTLS".to_string())
+    }
+}
+
+/// The size of a `{n}` or `{n+}` literal announced at the end of a line.
+fn literal_len(line: &[u8]) -> Option<usize> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let line = line.strip_suffix(b"}")?;
+    let start = line.iter().rposition(|&b| b == b'{')? + 1;
+    let digits = &line[start..];
+    let digits = digits.strip_suffix(b"+").unwrap_or(digits);
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Takes the next line from `buffer` together with the literals it
+/// announces and the lines continuing after them. Returns the first line
+/// and the length of the whole message.
+fn take_message(buffer: &mut MessageBuffer) -> Option<(String, usize)> {
+    let mut pos = 0;
+    let mut first = None;
+    loop {
+        let data = buffer.data();
+        let end = match data[pos..].iter().position(|&b| b == b'\n') {
+            Some(offset) => pos + offset,
+            None if pos == 0 => {
+                // Overlong lines are returned as they are
+                let line = buffer.take_line()?;
+                let length = line.len();
+                return Some((line, length));
+            }
+            None => return None,
+        };
+        let line = &data[pos..end];
+        if first.is_none() {
+            let text = line.strip_suffix(b"\r").unwrap_or(line);
+            first = Some(String::from_utf8_lossy(text).into_owned());
+        }
+        let Some(literal) = literal_len(line) else {
+            buffer.take(end + 1)?;
+            return first.map(|first| (first, end + 1));
+        };
+        if data.len() < end + 1 + literal {
+            // Large literals are cut short by the buffer
+            let message = buffer.take(end + 1 + literal)?;
+            return first.map(|first| (first, message.len()));
+        }
+        pos = end + 1 + literal;
+    }
+}
+
+fn parse_request(line: &str) -> Kind {
+    let mut parts = line.splitn(3, ' ');
+    let tag = parts.next().unwrap_or("").to_string();
+    let command = parts.next().unwrap_or("").to_ascii_uppercase();
+    let arguments = parts.next().unwrap_or("").to_string();
+    if command.is_empty() {
+        // Lines such as the DONE ending IDLE have no tag
+        return Kind::Request {
+            tag: String::new(),
+            command: tag.to_ascii_uppercase(),
+            arguments,
+        };
+    }
+    Kind::Request {
+        tag,
+        command,
+        arguments,
+    }
+}
+
+fn parse_response(line: &str) -> Kind {
+    if let Some(text) = line.strip_prefix('*') {
+        return Kind::Untagged(text.trim_start().to_string());
+    }
+    if let Some(text) = line.strip_prefix('+') {
+        return Kind::Continuation(text.trim_start().to_string());
+    }
+    let mut parts = line.splitn(3, ' ');
+    Kind::Tagged {
+        tag: parts.next().unwrap_or("").to_string(),
+        status: parts.next().unwrap_or("").to_ascii_uppercase(),
+        text: parts.next().unwrap_or("").to_string(),
+    }
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// The tag of a STARTTLS request waiting for its response.
+    starttls_tag: Option<String>,
+    encrypted: bool,
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+}
+
+impl Connection {
+    fn messages(
+        &mut self,
+        buffer: &mut MessageBuffer,
+        from_client: bool,
+        messages: &mut Vec<ImapMessage>,
+    ) {
+        while let Some((line, length)) = take_message(buffer) {
+            let kind = if from_client {
+                parse_request(&line)
+            } else {
+                parse_response(&line)
+            };
+            let mut starts_tls = false;
+            match &kind {
+                Kind::Request { tag, command, .. } if command == "STARTTLS" => {
+                    self.starttls_tag = Some(tag.clone());
+                }
+                Kind::Tagged { tag, status, .. } if self.starttls_tag.as_ref() == Some(tag) => {
+                    starts_tls = status == "OK";
+                    self.starttls_tag = None;
+                }
+                _ => {}
+            }
+            messages.push(ImapMessage {
+                from_client,
+                kind,
+                length,
+                starts_tls,
+            });
+            if starts_tls {
+                self.encrypted = true;
+                buffer.clear();
+                break;
+            }
+        }
+    }
+}
+
+/// Splits reassembled IMAP connections into requests and responses.
+#[derive(Debug, Default)]
+pub struct ImapTracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl ImapTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the requests or responses `stream` completes.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+    ) -> Exchange<ImapMessage> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (buffer, connection) = connection.split(from_client);
+        if connection.encrypted {
+            if !stream.data.is_empty() {
+                exchange.messages.push(ImapMessage {
+                    from_client,
+                    kind: Kind::Encrypted(stream.data.len()),
+                    length: stream.data.len(),
+                    starts_tls: false,
+                });
+            }
+            return exchange;
+        }
+
+        buffer.push(&stream.data);
+        connection.messages(buffer, from_client, &mut exchange.messages);
+        exchange
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    fn infos(exchange: &Exchange<ImapMessage>) -> Vec<String> {
+        exchange.messages.iter().map(|m| m.info()).collect()
+    }
+
+    #[test]
+    fn test_literal_framing() {
+        let mut tracker = ImapTracker::new();
+        let mut process = |from_client, data: &[u8]| {
+            tracker.process(CLIENT, SERVER, from_client, &StreamData::complete(data))
+        };
+
+        let exchange = process(true, b"a1 LOGIN {5}\r\nal\r\nc");
+        assert!(exchange.messages.is_empty());
+        let exchange = process(true, b"e secret\r\na2 SELECT INBOX\r\n");
+        assert_eq!(
+            infos(&exchange),
+            ["Request: a1 LOGIN {5}", "Request: a2 SELECT INBOX"]
+        );
+        assert_eq!(exchange.messages[0].length, 29);
+
+        let exchange = process(
+            false,
+            b"* 1 FETCH (BODY[] {7}\r\nSubject)\r\n* 3 EXISTS\r\na2 NO [NONEXISTENT] No mailbox\r\n",
+        );
+        assert_eq!(
+            infos(&exchange),
+            [
+                "Response: * 1 FETCH (BODY[] {7}",
+                "Response: * 3 EXISTS",
+                "Response: a2 NO [NONEXISTENT] No mailbox"
+            ]
+        );
+        assert_eq!(
+            exchange.messages[2].error().unwrap(),
+            "NO [NONEXISTENT] No mailbox"
+        );
+
+        let exchange = process(true, b"DONE\r\n");
+        assert_eq!(
+            exchange.messages[0].kind,
+            Kind::Request {
+                tag: String::new(),
+                command: "DONE".to_string(),
+                arguments: String::new()
+            }
+        );
+    }
+
+    #[test]
+    fn test_starttls() {
+        let mut tracker = ImapTracker::new();
+        tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(b"a1 STARTTLS\r\n"),
+        );
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(b"a1 OK Begin TLS\r\n"),
+        );
+        assert!(exchange.messages[0].note().is_some());
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&[0x16, 3, 1]));
+        assert_eq!(exchange.messages[0].kind, Kind::Encrypted(3));
+    }
+}
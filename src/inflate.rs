@@ -0,0 +1,321 @@
+// Decompressed bodies are cut off here, whatever their compressed size
+const MAX_OUTPUT_LEN: usize = 64 << 20;
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order code length code lengths are sent in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompresses a raw DEFLATE stream.
+pub fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(&mut reader, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                codes(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                codes(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return None,
+        }
+        if last {
+            return Some(output);
+        }
+    }
+}
+
+/// Decompresses a zlib stream, as HTTP's `deflate` encoding is meant to be.
+pub fn zlib_decompress(data: &[u8]) -> Option<Vec<u8>> {
+    let (&cmf, &flags) = (data.first()?, data.get(1)?);
+    let preset_dictionary = flags & 0x20 != 0;
+    if cmf & 0x0f != 8 || (u16::from(cmf) << 8 | u16::from(flags)) % 31 != 0 || preset_dictionary {
+        return None;
+    }
+    inflate(&data[2..])
+}
+
+/// Decompresses the first member of a gzip file.
+pub fn gunzip(data: &[u8]) -> Option<Vec<u8>> {
+    if data.get(..3)? != [0x1f, 0x8b, 8] {
+        return None;
+    }
+    let flags = data[3];
+    let mut offset = 10;
+    if flags & 0x04 != 0 {
+        let extra = data.get(offset..offset + 2)?;
+        offset += 2 + usize::from(u16::from_le_bytes([extra[0], extra[1]]));
+    }
+    // File name and comment are zero terminated
+    for flag in [0x08, 0x10] {
+        if flags & flag != 0 {
+            offset += data.get(offset..)?.iter().position(|&b| b == 0)? + 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        offset += 2;
+    }
+    inflate(data.get(offset..)?)
+}
+
+/// Reads the bits of a DEFLATE stream, least significant first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        while self.count < count {
+            let byte = *self.data.get(self.position)?;
+            self.position += 1;
+            self.buffer |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << count) - 1);
+        self.buffer >>= count;
+        self.count -= count;
+        Some(value)
+    }
+
+    /// Drops the bits left of the current byte.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position + len)?;
+        self.position += len;
+        Some(bytes)
+    }
+}
+
+/// A canonical Huffman code, by the number of codes of each length and the
+/// symbols in code order.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Option<Self> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        // Reject codes using more bit patterns than there are, incomplete
+        // codes are allowed for a single distance code
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+            if left < 0 {
+                return None;
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; usize::from(offsets[MAX_BITS + 1])];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                let offset = &mut offsets[usize::from(len)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+        counts[0] = 0;
+        Some(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Option<u16> {
+        // Codes of each length follow those of the shorter lengths
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(count);
+            if code < first + count {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+fn stored_block(reader: &mut BitReader, output: &mut Vec<u8>) -> Option<()> {
+    reader.align();
+    let header = reader.bytes(4)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if len != !complement || output.len() + usize::from(len) > MAX_OUTPUT_LEN {
+        return None;
+    }
+    output.extend_from_slice(reader.bytes(usize::from(len))?);
+    Some(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literals = Huffman::new(&lengths).expect("fixed literal code is complete");
+    let distances = Huffman::new(&[5; 30]).expect("fixed distance code is complete");
+    (literals, distances)
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Option<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return None;
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    // Literal and distance code lengths are sent as one run-length coded
+    // sequence
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (len, repeat) = match code_length_code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last()?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            18 => (0, 11 + reader.bits(7)?),
+            _ => return None,
+        };
+        for _ in 0..repeat {
+            lengths.push(len);
+        }
+    }
+    if lengths.len() > literal_count + distance_count || lengths[256] == 0 {
+        return None;
+    }
+
+    let literals = Huffman::new(&lengths[..literal_count])?;
+    let distances = Huffman::new(&lengths[literal_count..])?;
+    Some((literals, distances))
+}
+
+/// Decodes the literals and back references of a compressed block.
+fn codes(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Option<()> {
+    loop {
+        let symbol = usize::from(literals.decode(reader)?);
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Some(()),
+            _ => {
+                let index = symbol - 257;
+                let len = usize::from(*LENGTH_BASE.get(index)?)
+                    + reader.bits(u32::from(LENGTH_EXTRA[index]))? as usize;
+                let index = usize::from(distances.decode(reader)?);
+                let distance = usize::from(*DISTANCE_BASE.get(index)?)
+                    + reader.bits(u32::from(DISTANCE_EXTRA[index]))? as usize;
+                if distance > output.len() {
+                    return None;
+                }
+                // The copy may overlap what it produces
+                let start = output.len() - distance;
+                for i in 0..len {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+        if output.len() > MAX_OUTPUT_LEN {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_inflate_blocks() {
+        let stored = inflate(&hex("010300fcff616263")).unwrap();
+        assert_eq!(stored, b"abc");
+
+        let fixed = inflate(&hex("cb48cdc9c957c8402701")).unwrap();
+        assert_eq!(fixed, b"hello hello hello hello");
+
+        let dynamic = inflate(&hex(
+            "b5cbc70180201005d1567e05d4e2c10640490656b250bddb84e779b33a8d58fd764225ea01865e1cf5\
+             7e32a8e984c2f9927360272bb0fe8617c9ee1e508cba2f0ec637cd69ea80cbc74a895f9bc507",
+        ))
+        .unwrap();
+        let mut expected = b"The quick brown fox jumps over the lazy dog. ".repeat(3);
+        expected.extend_from_slice(b"Pack my box with five dozen liquor jugs.");
+        assert_eq!(dynamic, expected);
+
+        // Truncated streams and back references before the start fail
+        assert_eq!(inflate(&hex("cb48cdc9c957c840")), None);
+        assert_eq!(inflate(&[0x03, 0x02, 0x00]), None);
+    }
+
+    #[test]
+    fn test_wrappers() {
+        let gzip = hex("1f8b0800000000000203cb48cdc9c9d75148afca2ce00200861f82a40c000000");
+        assert_eq!(gunzip(&gzip).unwrap(), b"hello, gzip\n");
+        assert_eq!(zlib_decompress(&gzip), None);
+
+        let zlib = hex("789ccb48cdc9c9d751a8cac94c020018b20412");
+        assert_eq!(zlib_decompress(&zlib).unwrap(), b"hello, zlib");
+        assert_eq!(gunzip(&zlib), None);
+    }
+}
@@ -0,0 +1,486 @@
+use crate::fields::Fields;
+use crate::query::{self, AppMessage, ConnectionState, ConnectionTable, Exchange, QueryTiming};
+use crate::reassembly::{MessageBuffer, StreamData};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+// Produce requests with acks=0 are never answered
+const MAX_PENDING_REQUESTS: usize = 1024;
+const SIZE_LEN: usize = 4;
+// Requests and responses are far smaller; anything else is garbage
+const MAX_MESSAGE_SIZE: usize = 1 << 30;
+// Protects against absurd counts in malformed messages
+const MAX_TOPICS: usize = 1024;
+
+pub const PRODUCE: i16 = 0;
+pub const FETCH: i16 = 1;
+pub const METADATA: i16 = 3;
+
+pub fn api_key_name(api_key: i16) -> &'static str {
+    match api_key {
+        PRODUCE => "Produce",
+        FETCH => "Fetch",
+        2 => "ListOffsets",
+        METADATA => "Metadata",
+        4 => "LeaderAndIsr",
+        5 => "StopReplica",
+        6 => "UpdateMetadata",
+        7 => "ControlledShutdown",
+        8 => "OffsetCommit",
+        9 => "OffsetFetch",
+        10 => "FindCoordinator",
+        11 => "JoinGroup",
+        12 => "Heartbeat",
+        13 => "LeaveGroup",
+        14 => "SyncGroup",
+        15 => "DescribeGroups",
+        16 => "ListGroups",
+        17 => "SaslHandshake",
+        18 => "ApiVersions",
+        19 => "CreateTopics",
+        20 => "DeleteTopics",
+        21 => "DeleteRecords",
+        22 => "InitProducerId",
+        23 => "OffsetForLeaderEpoch",
+        24 => "AddPartitionsToTxn",
+        25 => "AddOffsetsToTxn",
+        26 => "EndTxn",
+        27 => "WriteTxnMarkers",
+        28 => "TxnOffsetCommit",
+        29 => "DescribeAcls",
+        30 => "CreateAcls",
+        31 => "DeleteAcls",
+        32 => "DescribeConfigs",
+        33 => "AlterConfigs",
+        36 => "SaslAuthenticate",
+        37 => "CreatePartitions",
+        42 => "DeleteGroups",
+        60 => "DescribeCluster",
+        _ => "Unknown",
+    }
+}
+
+/// Whether the request uses compact strings and tagged fields. Only known
+/// for the APIs whose topics are decoded.
+fn is_flexible(api_key: i16, api_version: i16) -> bool {
+    match api_key {
+        PRODUCE | METADATA => api_version >= 9,
+        FETCH => api_version >= 12,
+        _ => false,
+    }
+}
+
+/// A request or response of the Kafka wire protocol. Responses carry the
+/// API of the request with their correlation ID, when it was seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KafkaMessage {
+    pub request: bool,
+    pub length: usize,
+    pub correlation_id: i32,
+    pub api_key: Option<i16>,
+    pub api_version: Option<i16>,
+    pub client_id: Option<String>,
+    pub topics: Vec<String>,
+}
+
+impl KafkaMessage {
+    fn api(&self) -> String {
+        match (self.api_key, self.api_version) {
+            (Some(api_key), Some(api_version)) => {
+                format!("{} v{}", api_key_name(api_key), api_version)
+            }
+            _ => "Unknown".to_string(),
+        }
+    }
+}
+
+impl AppMessage for KafkaMessage {
+    const TIME_FIELD: &'static str = "kafka.response_time";
+
+    fn info(&self) -> String {
+        let direction = if self.request { "Request" } else { "Response" };
+        let mut info = format!("{} {}", self.api(), direction);
+        if !self.topics.is_empty() {
+            info.push_str(&format!(" [{}]", self.topics.join(", ")));
+        }
+        info
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("kafka.len", self.length);
+        fields.insert("kafka.correlation_id", self.correlation_id as u32);
+        if let Some(api_key) = self.api_key {
+            fields.insert("kafka.request_key", api_key as u32);
+        }
+        if let Some(api_version) = self.api_version {
+            fields.insert("kafka.request_version", api_version as u32);
+        }
+        if let Some(client_id) = &self.client_id {
+            fields.insert("kafka.client_id", client_id.as_str());
+        }
+        for topic in &self.topics {
+            fields.insert("kafka.topic_name", topic.as_str());
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        None
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    flexible: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(bytes)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        Some(i16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn uvarint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.bytes(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Length of a string, bytes or array; `None` inside for null.
+    fn len(&mut self, classic: Option<i64>) -> Option<Option<usize>> {
+        let len = match (self.flexible, classic) {
+            (true, _) => self.uvarint()? as i64 - 1,
+            (false, Some(len)) => len,
+            (false, None) => i64::from(self.i32()?),
+        };
+        Some(usize::try_from(len).ok())
+    }
+
+    fn string(&mut self) -> Option<Option<String>> {
+        let classic = match self.flexible {
+            true => None,
+            false => Some(i64::from(self.i16()?)),
+        };
+        let Some(len) = self.len(classic)? else {
+            return Some(None);
+        };
+        Some(Some(String::from_utf8_lossy(self.bytes(len)?).into_owned()))
+    }
+
+    fn skip_bytes(&mut self) -> Option<()> {
+        if let Some(len) = self.len(None)? {
+            self.bytes(len)?;
+        }
+        Some(())
+    }
+
+    fn array_len(&mut self) -> Option<usize> {
+        Some(self.len(None)?.unwrap_or(0).min(MAX_TOPICS))
+    }
+
+    fn tagged_fields(&mut self) -> Option<()> {
+        if self.flexible {
+            for _ in 0..self.uvarint()? {
+                self.uvarint()?;
+                let len = self.uvarint()?;
+                self.bytes(usize::try_from(len).ok()?)?;
+            }
+        }
+        Some(())
+    }
+}
+
+/// Names the topics of Produce, Fetch and Metadata requests.
+fn request_topics(api_key: i16, api_version: i16, reader: &mut Reader) -> Option<Vec<String>> {
+    let mut topics = Vec::new();
+    match api_key {
+        PRODUCE => {
+            if api_version >= 3 {
+                reader.string()?;
+            }
+            // Acks and timeout
+            reader.bytes(6)?;
+            for _ in 0..reader.array_len()? {
+                topics.extend(reader.string()?);
+                for _ in 0..reader.array_len()? {
+                    reader.i32()?;
+                    reader.skip_bytes()?;
+                    reader.tagged_fields()?;
+                }
+                reader.tagged_fields()?;
+            }
+        }
+        FETCH if api_version <= 12 => {
+            // Replica, max wait and min bytes, then fields added over time
+            let mut skip = 12;
+            skip += [(3, 4), (4, 1), (7, 8)]
+                .iter()
+                .filter(|(since, _)| api_version >= *since)
+                .map(|(_, len)| len)
+                .sum::<usize>();
+            reader.bytes(skip)?;
+            let mut partition_len = 4 + 8 + 4;
+            partition_len += [(9, 4), (12, 4), (5, 8)]
+                .iter()
+                .filter(|(since, _)| api_version >= *since)
+                .map(|(_, len)| len)
+                .sum::<usize>();
+            for _ in 0..reader.array_len()? {
+                topics.extend(reader.string()?);
+                for _ in 0..reader.array_len()? {
+                    reader.bytes(partition_len)?;
+                    reader.tagged_fields()?;
+                }
+                reader.tagged_fields()?;
+            }
+        }
+        METADATA => {
+            for _ in 0..reader.array_len()? {
+                if api_version >= 10 {
+                    // Topic ID
+                    reader.bytes(16)?;
+                }
+                topics.extend(reader.string()?);
+                reader.tagged_fields()?;
+            }
+        }
+        _ => {}
+    }
+    Some(topics)
+}
+
+fn parse_request(data: &[u8]) -> Option<KafkaMessage> {
+    let mut reader = Reader {
+        data,
+        flexible: false,
+    };
+    let api_key = reader.i16()?;
+    let api_version = reader.i16()?;
+    let correlation_id = reader.i32()?;
+    let client_id = reader.string()?;
+    reader.flexible = is_flexible(api_key, api_version);
+    let topics = reader
+        .tagged_fields()
+        .and_then(|_| request_topics(api_key, api_version, &mut reader))
+        .unwrap_or_default();
+    Some(KafkaMessage {
+        request: true,
+        length: data.len(),
+        correlation_id,
+        api_key: Some(api_key),
+        api_version: Some(api_version),
+        client_id,
+        topics,
+    })
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// API, request time and description of requests by correlation ID.
+    pending: HashMap<i32, (i16, i16, DateTime<Utc>, String)>,
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+}
+
+/// Splits reassembled Kafka connections into requests and responses and
+/// matches them by correlation ID.
+#[derive(Debug, Default)]
+pub struct KafkaTracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl KafkaTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the requests or responses `stream` completes, pairing
+    /// responses with their requests.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+        timestamp: DateTime<Utc>,
+    ) -> Exchange<KafkaMessage> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (buffer, connection) = connection.split(from_client);
+        buffer.push(&stream.data);
+        while let Some(size) = buffer.data().get(..SIZE_LEN) {
+            let size = u32::from_be_bytes(size.try_into().expect("four bytes")) as usize;
+            if size > MAX_MESSAGE_SIZE {
+                // Not at a message boundary, e.g. after missing data
+                buffer.clear();
+                break;
+            }
+            let Some(message) = buffer.take(SIZE_LEN + size) else {
+                break;
+            };
+            let data = &message[SIZE_LEN..];
+            if from_client {
+                let Some(request) = parse_request(data) else {
+                    continue;
+                };
+                if connection.pending.len() >= MAX_PENDING_REQUESTS {
+                    connection.pending.clear();
+                }
+                connection.pending.insert(
+                    request.correlation_id,
+                    (
+                        request.api_key.unwrap_or_default(),
+                        request.api_version.unwrap_or_default(),
+                        timestamp,
+                        request.info(),
+                    ),
+                );
+                exchange.messages.push(request);
+            } else {
+                let Some(correlation_id) = data.get(..4) else {
+                    continue;
+                };
+                let correlation_id =
+                    i32::from_be_bytes(correlation_id.try_into().expect("four bytes"));
+                let request = connection.pending.remove(&correlation_id);
+                if let Some((_, _, sent, query)) = &request {
+                    exchange
+                        .timings
+                        .extend(query::latency(*sent, timestamp).map(|latency| QueryTiming {
+                            query: query.clone(),
+                            latency,
+                        }));
+                }
+                exchange.messages.push(KafkaMessage {
+                    request: false,
+                    length: size,
+                    correlation_id,
+                    api_key: request.as_ref().map(|request| request.0),
+                    api_version: request.as_ref().map(|request| request.1),
+                    client_id: None,
+                    topics: Vec::new(),
+                });
+            }
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    fn string(text: &str) -> Vec<u8> {
+        let mut data = (text.len() as i16).to_be_bytes().to_vec();
+        data.extend_from_slice(text.as_bytes());
+        data
+    }
+
+    fn sized(body: &[u8]) -> Vec<u8> {
+        let mut message = (body.len() as u32).to_be_bytes().to_vec();
+        message.extend_from_slice(body);
+        message
+    }
+
+    fn header(api_key: i16, api_version: i16, correlation_id: i32) -> Vec<u8> {
+        let mut header = api_key.to_be_bytes().to_vec();
+        header.extend_from_slice(&api_version.to_be_bytes());
+        header.extend_from_slice(&correlation_id.to_be_bytes());
+        header.extend(string("producer-1"));
+        header
+    }
+
+    #[test]
+    fn test_produce_matched_by_correlation_id() {
+        let start = Utc::now();
+        let mut tracker = KafkaTracker::new();
+
+        // Produce v3 to two topics with one partition each
+        let mut produce = header(PRODUCE, 3, 7);
+        produce.extend_from_slice(&(-1i16).to_be_bytes());
+        produce.extend_from_slice(&[0, 1, 0, 0, 0x75, 0x30]);
+        produce.extend_from_slice(&2i32.to_be_bytes());
+        for topic in ["orders", "payments"] {
+            produce.extend(string(topic));
+            produce.extend_from_slice(&1i32.to_be_bytes());
+            produce.extend_from_slice(&0i32.to_be_bytes());
+            produce.extend(sized(b"records"));
+        }
+        let mut data = sized(&produce);
+        let mut metadata = header(METADATA, 1, 8);
+        metadata.extend_from_slice(&0i32.to_be_bytes());
+        data.extend(sized(&metadata));
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&data), start);
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Produce v3 Request [orders, payments]"
+        );
+        assert_eq!(exchange.messages[1].info(), "Metadata v1 Request");
+
+        let mut fields = Fields::new();
+        exchange.messages[0].insert_fields(&mut fields);
+        assert_eq!(fields.get_all("kafka.topic_name").count(), 2);
+
+        // Responses come back by correlation ID
+        let end = start + chrono::Duration::milliseconds(4);
+        let response = sized(&[0, 0, 0, 7, 0, 0, 0, 0]);
+        let exchange =
+            tracker.process(CLIENT, SERVER, false, &StreamData::complete(&response), end);
+        assert_eq!(exchange.messages[0].info(), "Produce v3 Response");
+        assert_eq!(
+            exchange.timings[0].query,
+            "Produce v3 Request [orders, payments]"
+        );
+        assert_eq!(exchange.timings[0].latency, 0.004);
+
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(&sized(&[0, 0, 0, 9])),
+            end,
+        );
+        assert_eq!(exchange.messages[0].info(), "Unknown Response");
+        assert!(exchange.timings.is_empty());
+    }
+
+    #[test]
+    fn test_flexible_metadata_topics() {
+        let mut tracker = KafkaTracker::new();
+        let mut metadata = header(METADATA, 9, 1);
+        // No header tags, then a compact array of one topic
+        metadata.extend_from_slice(&[0, 2, 7]);
+        metadata.extend_from_slice(b"events");
+        metadata.extend_from_slice(&[0, 1, 0, 0]);
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(&sized(&metadata)),
+            Utc::now(),
+        );
+        assert_eq!(exchange.messages[0].topics, ["events"]);
+    }
+}
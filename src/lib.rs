@@ -1,9 +1,65 @@
+pub mod amqp;
 pub mod analyzer;
+pub mod arp;
+pub mod bacnet;
+pub mod bgp;
+pub mod bindings;
+pub mod bpf;
 pub mod capture;
+pub mod cdp;
+pub mod checksum;
+pub mod defrag;
+pub mod dhcp;
+pub mod dhcpv6;
+pub mod dnp3;
+pub mod expert;
+pub mod export;
+pub mod fields;
 pub mod filters;
+pub mod ftp;
+pub mod grpc;
+pub mod hpack;
+pub mod hsrp;
+pub mod http1;
+pub mod http2;
+pub mod icmp;
+pub mod imap;
+pub mod inflate;
+pub mod kafka;
+pub mod link;
+pub mod lldp;
+pub mod modbus;
+pub mod mqtt;
+pub mod mysql;
+pub mod ndp;
+pub mod netflow;
+pub mod ntp;
+pub mod ospf;
+pub mod pgsql;
+pub mod pop3;
+pub mod ports;
+pub mod protobuf;
+pub mod query;
+pub mod quic;
+pub mod reassembly;
+pub mod redis;
+pub mod rtp;
+pub mod sip;
+pub mod smtp;
+pub mod snmp;
+pub mod ssh;
+pub mod stp;
+pub mod syslog;
+pub mod tcp_analysis;
+pub mod tls;
+pub mod tunnel;
 pub mod ui;
+pub mod vrrp;
+pub mod websocket;
+pub mod wlan;
 
 // Re-export commonly used types
+pub use analyzer::Analyzer;
 pub use capture::start_capture;
 pub use filters::parse_filter;
 pub use ui::PacketInfo;
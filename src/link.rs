@@ -0,0 +1,318 @@
+use crate::fields::Fields;
+
+pub mod ether_type {
+    pub const IPV4: u16 = 0x0800;
+    pub const ARP: u16 = 0x0806;
+    pub const TEB: u16 = 0x6558;
+    pub const RARP: u16 = 0x8035;
+    pub const VLAN: u16 = 0x8100;
+    pub const IPV6: u16 = 0x86dd;
+    pub const MPLS_UNICAST: u16 = 0x8847;
+    pub const MPLS_MULTICAST: u16 = 0x8848;
+    pub const QINQ: u16 = 0x88a8;
+    pub const LLDP: u16 = 0x88cc;
+    pub const QINQ_LEGACY: u16 = 0x9100;
+}
+
+const ETHERNET_HEADER_LEN: usize = 14;
+
+/// The link layer of captured frames, after the pcap link-layer header
+/// type of the interface or file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkType {
+    #[default]
+    Ethernet,
+    /// 802.11 frames without radio information.
+    Ieee80211,
+    /// 802.11 frames behind a radiotap header, as monitor-mode
+    /// interfaces deliver them.
+    Radiotap,
+}
+
+impl LinkType {
+    /// Link types that aren't supported are decoded as Ethernet.
+    pub fn from_dlt(dlt: i32) -> Self {
+        match dlt {
+            105 => LinkType::Ieee80211,
+            127 => LinkType::Radiotap,
+            _ => LinkType::Ethernet,
+        }
+    }
+}
+
+/// An 802.1Q or 802.1ad tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VlanTag {
+    /// Tag protocol identifier, 0x8100 for customer tags, 0x88a8 for
+    /// service (QinQ) tags.
+    pub tpid: u16,
+    pub priority: u8,
+    pub dei: bool,
+    pub id: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MplsLabel {
+    pub label: u32,
+    pub traffic_class: u8,
+    pub bottom_of_stack: bool,
+    pub ttl: u8,
+}
+
+/// Ethernet header with any VLAN tags and MPLS labels in front of the
+/// network layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkLayer {
+    pub source: [u8; 6],
+    pub destination: [u8; 6],
+    /// Type of the payload after all tags and labels. Values up to 1500
+    /// are an 802.3 length field, followed by an LLC header.
+    pub ether_type: u16,
+    pub vlans: Vec<VlanTag>,
+    pub mpls: Vec<MplsLabel>,
+    /// Offset of the payload within the frame.
+    pub payload_offset: usize,
+}
+
+impl LinkLayer {
+    pub fn is_llc(&self) -> bool {
+        self.ether_type <= 1500
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("eth.src", self.source);
+        fields.insert("eth.dst", self.destination);
+        fields.insert("eth.addr", self.source);
+        fields.insert("eth.addr", self.destination);
+        fields.insert("eth.type", self.ether_type);
+
+        for tag in &self.vlans {
+            fields.insert("vlan.id", tag.id);
+            fields.insert("vlan.priority", tag.priority);
+            fields.insert("vlan.dei", tag.dei);
+            fields.insert("vlan.tpid", tag.tpid);
+        }
+        for label in &self.mpls {
+            fields.insert("mpls.label", label.label);
+            fields.insert("mpls.exp", label.traffic_class);
+            fields.insert("mpls.bottom", label.bottom_of_stack);
+            fields.insert("mpls.ttl", label.ttl);
+        }
+    }
+}
+
+/// Decodes an Ethernet II frame including any number of 802.1Q/802.1ad
+/// tags and an MPLS label stack.
+pub fn decode_ethernet(frame: &[u8]) -> Option<LinkLayer> {
+    if frame.len() < ETHERNET_HEADER_LEN {
+        return None;
+    }
+
+    let mut link = LinkLayer {
+        destination: frame[0..6].try_into().ok()?,
+        source: frame[6..12].try_into().ok()?,
+        ether_type: u16::from_be_bytes([frame[12], frame[13]]),
+        vlans: Vec::new(),
+        mpls: Vec::new(),
+        payload_offset: ETHERNET_HEADER_LEN,
+    };
+
+    while matches!(
+        link.ether_type,
+        ether_type::VLAN | ether_type::QINQ | ether_type::QINQ_LEGACY
+    ) {
+        let tag = frame.get(link.payload_offset..link.payload_offset + 4)?;
+        let tci = u16::from_be_bytes([tag[0], tag[1]]);
+        link.vlans.push(VlanTag {
+            tpid: link.ether_type,
+            priority: (tci >> 13) as u8,
+            dei: tci & 0x1000 != 0,
+            id: tci & 0x0fff,
+        });
+        link.ether_type = u16::from_be_bytes([tag[2], tag[3]]);
+        link.payload_offset += 4;
+    }
+
+    if matches!(
+        link.ether_type,
+        ether_type::MPLS_UNICAST | ether_type::MPLS_MULTICAST
+    ) {
+        loop {
+            let entry = frame.get(link.payload_offset..link.payload_offset + 4)?;
+            let value = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            let label = MplsLabel {
+                label: value >> 12,
+                traffic_class: ((value >> 9) & 0x7) as u8,
+                bottom_of_stack: value & 0x100 != 0,
+                ttl: value as u8,
+            };
+            link.mpls.push(label);
+            link.payload_offset += 4;
+            if label.bottom_of_stack {
+                break;
+            }
+        }
+
+        // MPLS has no next protocol field, so guess from the IP version
+        // nibble. A leading zero nibble is a pseudowire control word
+        // followed by an Ethernet frame.
+        link.ether_type = match frame.get(link.payload_offset).map(|b| b >> 4) {
+            Some(4) => ether_type::IPV4,
+            Some(6) => ether_type::IPV6,
+            Some(0) => {
+                frame.get(link.payload_offset..link.payload_offset + 4)?;
+                link.payload_offset += 4;
+                ether_type::TEB
+            }
+            _ => link.ether_type,
+        };
+    }
+
+    Some(link)
+}
+
+/// An 802.2 LLC header, as used by 802.3 frames with a length field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Llc {
+    pub dsap: u8,
+    pub ssap: u8,
+    pub control: u16,
+    /// Organization code and protocol ID of a SNAP header.
+    pub snap: Option<(u32, u16)>,
+    /// Offset of the payload within the LLC PDU.
+    pub payload_offset: usize,
+}
+
+impl Llc {
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("llc.dsap", self.dsap);
+        fields.insert("llc.ssap", self.ssap);
+        fields.insert("llc.control", self.control);
+        if let Some((oui, pid)) = self.snap {
+            fields.insert("llc.oui", oui);
+            fields.insert("llc.pid", pid);
+        }
+    }
+}
+
+const LLC_SAP_SNAP: u8 = 0xaa;
+
+/// Decodes the LLC header (and SNAP extension) of an 802.3 frame payload.
+pub fn decode_llc(pdu: &[u8]) -> Option<Llc> {
+    let header = pdu.get(..3)?;
+    // Unnumbered frames have a one byte control field, the others two
+    let (control, mut offset) = if header[2] & 0x03 == 0x03 {
+        (u16::from(header[2]), 3)
+    } else {
+        (u16::from_be_bytes([header[2], *pdu.get(3)?]), 4)
+    };
+
+    let snap = if header[0] == LLC_SAP_SNAP && header[1] == LLC_SAP_SNAP {
+        let snap = pdu.get(offset..offset + 5)?;
+        offset += 5;
+        Some((
+            u32::from_be_bytes([0, snap[0], snap[1], snap[2]]),
+            u16::from_be_bytes([snap[3], snap[4]]),
+        ))
+    } else {
+        None
+    };
+
+    Some(Llc {
+        dsap: header[0],
+        ssap: header[1],
+        control,
+        snap,
+        payload_offset: offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ethernet(ether_type: u16) -> Vec<u8> {
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame
+    }
+
+    #[test]
+    fn test_plain_ethernet() {
+        let mut frame = ethernet(ether_type::IPV4);
+        frame.push(0x45);
+
+        let link = decode_ethernet(&frame).unwrap();
+        assert_eq!(link.source, [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        assert_eq!(link.ether_type, ether_type::IPV4);
+        assert_eq!(link.payload_offset, 14);
+        assert!(link.vlans.is_empty());
+    }
+
+    #[test]
+    fn test_qinq_double_tag() {
+        let mut frame = ethernet(ether_type::QINQ);
+        frame.extend_from_slice(&[0xa0, 0x64, 0x81, 0x00]); // PCP 5, VLAN 100
+        frame.extend_from_slice(&[0x10, 0x14, 0x86, 0xdd]); // DEI, VLAN 20
+
+        let link = decode_ethernet(&frame).unwrap();
+        assert_eq!(link.vlans.len(), 2);
+        assert_eq!(link.vlans[0].tpid, ether_type::QINQ);
+        assert_eq!(link.vlans[0].priority, 5);
+        assert_eq!(link.vlans[0].id, 100);
+        assert!(link.vlans[1].dei);
+        assert_eq!(link.vlans[1].id, 20);
+        assert_eq!(link.ether_type, ether_type::IPV6);
+        assert_eq!(link.payload_offset, 22);
+    }
+
+    #[test]
+    fn test_mpls_label_stack() {
+        let mut frame = ethernet(ether_type::MPLS_UNICAST);
+        frame.extend_from_slice(&[0x00, 0x01, 0x00, 0x40]); // label 16, ttl 64
+        frame.extend_from_slice(&[0x00, 0x01, 0x15, 0x3f]); // label 17, tc 2, BoS
+        frame.push(0x45);
+
+        let link = decode_ethernet(&frame).unwrap();
+        assert_eq!(link.mpls.len(), 2);
+        assert_eq!(link.mpls[0].label, 16);
+        assert_eq!(link.mpls[0].ttl, 64);
+        assert!(!link.mpls[0].bottom_of_stack);
+        assert_eq!(link.mpls[1].label, 17);
+        assert_eq!(link.mpls[1].traffic_class, 2);
+        assert!(link.mpls[1].bottom_of_stack);
+        assert_eq!(link.ether_type, ether_type::IPV4);
+        assert_eq!(link.payload_offset, 22);
+
+        let mut fields = Fields::new();
+        link.insert_fields(&mut fields);
+        assert_eq!(fields.get_all("mpls.label").count(), 2);
+    }
+
+    #[test]
+    fn test_truncated_tag() {
+        let mut frame = ethernet(ether_type::VLAN);
+        frame.extend_from_slice(&[0x00, 0x64]);
+        assert!(decode_ethernet(&frame).is_none());
+    }
+
+    #[test]
+    fn test_truncated_control_word() {
+        let mut frame = ethernet(ether_type::MPLS_UNICAST);
+        frame.extend_from_slice(&[0x2e, 0xac, 0x1f, 0x6b]); // BoS
+        frame.push(0x09);
+        assert!(decode_ethernet(&frame).is_none());
+    }
+
+    #[test]
+    fn test_llc_snap() {
+        let llc = decode_llc(&[0xaa, 0xaa, 0x03, 0x00, 0x00, 0x0c, 0x20, 0x00, 0x02]).unwrap();
+        assert_eq!(llc.snap, Some((0x00000c, 0x2000)));
+        assert_eq!(llc.payload_offset, 8);
+
+        let stp = decode_llc(&[0x42, 0x42, 0x03, 0x00]).unwrap();
+        assert_eq!(stp.snap, None);
+        assert_eq!(stp.payload_offset, 3);
+    }
+}
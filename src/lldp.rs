@@ -0,0 +1,185 @@
+use crate::fields::{format_mac, Fields};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+const TLV_END: u8 = 0;
+const TLV_CHASSIS_ID: u8 = 1;
+const TLV_PORT_ID: u8 = 2;
+const TLV_TTL: u8 = 3;
+const TLV_PORT_DESCRIPTION: u8 = 4;
+const TLV_SYSTEM_NAME: u8 = 5;
+const TLV_SYSTEM_DESCRIPTION: u8 = 6;
+const TLV_MANAGEMENT_ADDRESS: u8 = 8;
+
+// Chassis ID subtype 4 and port ID subtype 3 are MAC addresses, subtype 5
+// (chassis) and 4 (port) network addresses
+const CHASSIS_MAC: u8 = 4;
+const CHASSIS_NETWORK_ADDRESS: u8 = 5;
+const PORT_MAC: u8 = 3;
+const PORT_NETWORK_ADDRESS: u8 = 4;
+
+/// An LLDP data unit (IEEE 802.1AB).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LldpPacket {
+    pub chassis_id: String,
+    pub port_id: String,
+    pub ttl: u16,
+    pub port_description: Option<String>,
+    pub system_name: Option<String>,
+    pub system_description: Option<String>,
+    pub management_addresses: Vec<String>,
+}
+
+impl LldpPacket {
+    pub fn parse(payload: &[u8]) -> Option<LldpPacket> {
+        let mut packet = LldpPacket::default();
+        let mut seen_mandatory = 0;
+        let mut rest = payload;
+
+        // TLVs with a 7 bit type and a 9 bit length
+        while rest.len() >= 2 {
+            let header = u16::from_be_bytes([rest[0], rest[1]]);
+            let tlv_type = (header >> 9) as u8;
+            let len = usize::from(header & 0x01ff);
+            let value = rest.get(2..2 + len)?;
+            rest = &rest[2 + len..];
+
+            match tlv_type {
+                TLV_END => break,
+                TLV_CHASSIS_ID if !value.is_empty() => {
+                    packet.chassis_id =
+                        format_id(value[0], &value[1..], CHASSIS_MAC, CHASSIS_NETWORK_ADDRESS);
+                    seen_mandatory += 1;
+                }
+                TLV_PORT_ID if !value.is_empty() => {
+                    packet.port_id =
+                        format_id(value[0], &value[1..], PORT_MAC, PORT_NETWORK_ADDRESS);
+                    seen_mandatory += 1;
+                }
+                TLV_TTL if len == 2 => {
+                    packet.ttl = u16::from_be_bytes([value[0], value[1]]);
+                    seen_mandatory += 1;
+                }
+                TLV_PORT_DESCRIPTION => packet.port_description = Some(text(value)),
+                TLV_SYSTEM_NAME => packet.system_name = Some(text(value)),
+                TLV_SYSTEM_DESCRIPTION => packet.system_description = Some(text(value)),
+                TLV_MANAGEMENT_ADDRESS if len >= 2 => {
+                    // Address string length (including the family), then family
+                    let address_len = usize::from(value[0]);
+                    if let Some(address) = value.get(2..1 + address_len) {
+                        packet
+                            .management_addresses
+                            .push(format_address(value[1], address));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Chassis ID, port ID and TTL are mandatory
+        if seen_mandatory < 3 {
+            return None;
+        }
+        Some(packet)
+    }
+
+    pub fn info(&self) -> String {
+        let mut info = format!(
+            "Chassis Id = {}, Port Id = {}, TTL = {}",
+            self.chassis_id, self.port_id, self.ttl
+        );
+        if let Some(name) = &self.system_name {
+            info.push_str(&format!(", System Name = {}", name));
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("lldp.chassis.id", self.chassis_id.as_str());
+        fields.insert("lldp.port.id", self.port_id.as_str());
+        fields.insert("lldp.time_to_live", self.ttl);
+        if let Some(description) = &self.port_description {
+            fields.insert("lldp.port.desc", description.as_str());
+        }
+        if let Some(name) = &self.system_name {
+            fields.insert("lldp.tlv.system.name", name.as_str());
+        }
+        if let Some(description) = &self.system_description {
+            fields.insert("lldp.tlv.system.desc", description.as_str());
+        }
+        for address in &self.management_addresses {
+            fields.insert("lldp.mgn.addr", address.as_str());
+        }
+    }
+}
+
+fn format_id(subtype: u8, id: &[u8], mac_subtype: u8, address_subtype: u8) -> String {
+    match (subtype, id.len()) {
+        (s, 6) if s == mac_subtype => format_mac(id.try_into().unwrap_or(&[0; 6])),
+        (s, len) if s == address_subtype && len > 1 => format_address(id[0], &id[1..]),
+        _ => text(id),
+    }
+}
+
+/// Formats an address with an IANA address family number.
+fn format_address(family: u8, address: &[u8]) -> String {
+    match (family, address.len()) {
+        (1, 4) => Ipv4Addr::new(address[0], address[1], address[2], address[3]).to_string(),
+        (2, 16) => {
+            let bytes: [u8; 16] = address.try_into().unwrap_or([0; 16]);
+            Ipv6Addr::from(bytes).to_string()
+        }
+        (6, 6) => format_mac(address.try_into().unwrap_or(&[0; 6])),
+        _ => text(address),
+    }
+}
+
+fn text(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv(tlv_type: u8, value: &[u8]) -> Vec<u8> {
+        let header = (u16::from(tlv_type) << 9) | value.len() as u16;
+        let mut bytes = header.to_be_bytes().to_vec();
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    #[test]
+    fn test_lldp_tlvs() {
+        let mut payload = tlv(
+            TLV_CHASSIS_ID,
+            &[CHASSIS_MAC, 0, 0x11, 0x22, 0x33, 0x44, 0x55],
+        );
+        payload.extend(tlv(TLV_PORT_ID, b"\x05Gi0/1"));
+        payload.extend(tlv(TLV_TTL, &[0, 120]));
+        payload.extend(tlv(TLV_SYSTEM_NAME, b"switch1"));
+        payload.extend(tlv(
+            TLV_MANAGEMENT_ADDRESS,
+            &[5, 1, 10, 0, 0, 1, 2, 0, 0, 0, 1, 0],
+        ));
+        payload.extend(tlv(TLV_END, &[]));
+
+        let lldp = LldpPacket::parse(&payload).unwrap();
+        assert_eq!(lldp.chassis_id, "00:11:22:33:44:55");
+        assert_eq!(lldp.port_id, "Gi0/1");
+        assert_eq!(lldp.ttl, 120);
+        assert_eq!(lldp.system_name.as_deref(), Some("switch1"));
+        assert_eq!(lldp.management_addresses, vec!["10.0.0.1"]);
+        assert_eq!(
+            lldp.info(),
+            "Chassis Id = 00:11:22:33:44:55, Port Id = Gi0/1, TTL = 120, System Name = switch1"
+        );
+    }
+
+    #[test]
+    fn test_missing_mandatory_tlv() {
+        let payload = tlv(TLV_CHASSIS_ID, b"\x07local");
+        assert!(LldpPacket::parse(&payload).is_none());
+    }
+}
@@ -1,13 +1,24 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use pcap::Linktype;
 use std::error::Error;
+use std::net::IpAddr;
+use std::path::PathBuf;
 use tokio::sync::mpsc;
 
+use ferriscope::analyzer::Analyzer;
+use ferriscope::bpf;
 use ferriscope::capture;
+use ferriscope::export;
+use ferriscope::ports::PortOverride;
+use ferriscope::protobuf::DescriptorPool;
 use ferriscope::ui;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Network interface to capture from
     #[arg(short, long)]
     interface: Option<String>,
@@ -16,13 +27,61 @@ struct Args {
     #[arg(short, long)]
     filter: Option<String>,
 
+    /// Print the compiled capture filter like `tcpdump -d` and exit
+    #[arg(long, requires = "filter")]
+    dump_bpf: bool,
+
+    /// Link type to compile the capture filter for with --dump-bpf, by name
+    /// (EN10MB, LINUX_SLL, IEEE802_11_RADIO, ...) or number
+    #[arg(long, value_name = "TYPE", default_value = "EN10MB", value_parser = bpf::parse_linktype)]
+    linktype: Linktype,
+
     /// Output file for packet capture
     #[arg(short, long)]
     output: Option<String>,
 
+    /// Read packets from a capture file instead of capturing live
+    #[arg(short, long, value_name = "FILE", conflicts_with_all = ["interface", "output"])]
+    read: Option<String>,
+
     /// List available network interfaces
     #[arg(short = 'l', long)]
     list: bool,
+
+    /// Validate IP, TCP, UDP and ICMP checksums
+    #[arg(long)]
+    check_checksums: bool,
+
+    /// Legitimate DHCP server; answers from other servers are flagged
+    /// (may be given more than once)
+    #[arg(long = "dhcp-server", value_name = "IP")]
+    dhcp_servers: Vec<IpAddr>,
+
+    /// Protobuf descriptor set (protoc --include_imports
+    /// --descriptor_set_out) for decoding gRPC messages
+    #[arg(long, value_name = "FILE")]
+    proto_descriptors: Option<PathBuf>,
+
+    /// Decode TCP connections to PORT as PROTOCOL (postgresql, mysql, redis,
+    /// mqtt, amqp, kafka, smtp, imap, pop3, ftp, ssh, modbus, dnp3 or bgp)
+    /// in addition to its default port (may be given more than once)
+    #[arg(long = "decode-as", value_name = "PROTOCOL=PORT")]
+    decode_as: Vec<PortOverride>,
+
+    /// Directory to write exported HTTP and FTP objects to (objects for
+    /// export-objects); the interface only collects objects with one
+    #[arg(long, value_name = "DIR", global = true)]
+    export_dir: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write the files sent over HTTP and FTP in a capture file to the
+    /// export directory, with a manifest
+    ExportObjects {
+        /// Capture file to read
+        file: String,
+    },
 }
 
 #[tokio::main]
@@ -30,24 +89,69 @@ struct Args {
 async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let args = Args::parse();
 
+    if args.dump_bpf {
+        let filter = args.filter.unwrap_or_default();
+        match bpf::compile(&filter, args.linktype) {
+            Ok(program) => print!("{}", bpf::dump(&program)),
+            Err(e) => {
+                // Point at the mistake under the filter
+                eprintln!("{}\n{}^ {}", filter, " ".repeat(e.column - 1), e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    // Load files before the UI takes over the terminal
+    let descriptors = match &args.proto_descriptors {
+        Some(path) => Some(DescriptorPool::load(path)?),
+        None => None,
+    };
+
+    let mut analyzer = Analyzer::new()
+        .with_checksum_validation(args.check_checksums)
+        .with_trusted_dhcp_servers(args.dhcp_servers)
+        .with_port_overrides(args.decode_as)
+        .with_object_export(args.command.is_some() || args.export_dir.is_some());
+    if let Some(descriptors) = descriptors {
+        analyzer = analyzer.with_protobuf_descriptors(descriptors);
+    }
+
+    if let Some(Command::ExportObjects { file }) = args.command {
+        let dir = args.export_dir.unwrap_or_else(|| PathBuf::from("objects"));
+        let objects = capture::extract_objects(&file, args.filter, analyzer)?;
+        export::write_objects(&dir, &objects)?;
+        println!("Exported {} objects to {}", objects.len(), dir.display());
+        return Ok(());
+    }
+
     // Create channels
     let (packet_tx, packet_rx) = mpsc::channel::<ui::PacketInfo>(1000);
     let (_shutdown_tx, shutdown_rx) = mpsc::channel::<()>(1);
 
     // Initialize the UI with packet receiver
     let mut app = ui::App::new(packet_rx)?;
+    if let Some(dir) = args.export_dir {
+        app = app.with_export_dir(dir);
+    }
 
     // Start capture in background
     let _capture_handle = tokio::spawn(async move {
-        if let Err(e) = capture::start_capture(
-            args.interface,
-            args.filter,
-            args.output,
-            shutdown_rx,
-            packet_tx,
-        )
-        .await
-        {
+        let result = match args.read {
+            Some(path) => capture::read_capture_file(path, args.filter, analyzer, packet_tx).await,
+            None => {
+                capture::start_capture(
+                    args.interface,
+                    args.filter,
+                    args.output,
+                    analyzer,
+                    shutdown_rx,
+                    packet_tx,
+                )
+                .await
+            }
+        };
+        if let Err(e) = result {
             eprintln!("Capture error: {}", e);
         }
     });
@@ -0,0 +1,433 @@
+use crate::fields::Fields;
+use crate::query::{self, AppMessage, ConnectionState, ConnectionTable, Exchange, QueryTiming};
+use crate::reassembly::{MessageBuffer, StreamData};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+const MAX_PENDING_REQUESTS: usize = 1024;
+// Transaction ID, protocol ID and length
+const MBAP_LEN: usize = 6;
+// Unit ID and the longest PDU
+const MAX_LENGTH: usize = 254;
+// Register values listed in the info column
+const MAX_INFO_VALUES: usize = 8;
+
+const EXCEPTION: u8 = 0x80;
+
+pub fn function_name(function: u8) -> &'static str {
+    match function {
+        1 => "Read Coils",
+        2 => "Read Discrete Inputs",
+        3 => "Read Holding Registers",
+        4 => "Read Input Registers",
+        5 => "Write Single Coil",
+        6 => "Write Single Register",
+        7 => "Read Exception Status",
+        8 => "Diagnostics",
+        11 => "Get Comm Event Counter",
+        12 => "Get Comm Event Log",
+        15 => "Write Multiple Coils",
+        16 => "Write Multiple Registers",
+        17 => "Report Server ID",
+        20 => "Read File Record",
+        21 => "Write File Record",
+        22 => "Mask Write Register",
+        23 => "Read Write Multiple Registers",
+        24 => "Read FIFO Queue",
+        43 => "Encapsulated Interface Transport",
+        _ => "Unknown",
+    }
+}
+
+pub fn exception_name(code: u8) -> &'static str {
+    match code {
+        1 => "Illegal Function",
+        2 => "Illegal Data Address",
+        3 => "Illegal Data Value",
+        4 => "Server Device Failure",
+        5 => "Acknowledge",
+        6 => "Server Device Busy",
+        8 => "Memory Parity Error",
+        10 => "Gateway Path Unavailable",
+        11 => "Gateway Target Device Failed to Respond",
+        _ => "Unknown",
+    }
+}
+
+/// Whether the function changes coils, registers or files of the server.
+pub fn is_write(function: u8) -> bool {
+    matches!(function, 5 | 6 | 15 | 16 | 21 | 22 | 23)
+}
+
+/// What the addresses of a function refer to.
+fn reference_kind(function: u8) -> (&'static str, &'static str) {
+    match function {
+        1 | 5 | 15 => ("coil", "coils"),
+        2 => ("input", "inputs"),
+        _ => ("register", "registers"),
+    }
+}
+
+/// A Modbus/TCP request or response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModbusMessage {
+    pub request: bool,
+    pub transaction_id: u16,
+    pub unit_id: u8,
+    /// The function code without the exception bit.
+    pub function: u8,
+    pub exception: Option<u8>,
+    /// The first coil or register the message refers to. Responses that
+    /// don't repeat it take it from their request.
+    pub reference: Option<u16>,
+    pub count: Option<u16>,
+    /// Register values read or written, or the value of a single coil.
+    pub values: Vec<u16>,
+}
+
+impl ModbusMessage {
+    fn description(&self) -> String {
+        let mut description = format!("{}, unit {}", function_name(self.function), self.unit_id);
+        if let Some(reference) = self.reference {
+            let (one, many) = reference_kind(self.function);
+            match self.count.unwrap_or(1) {
+                0 | 1 => description.push_str(&format!(", {} {}", one, reference)),
+                count => description.push_str(&format!(
+                    ", {} {}-{}",
+                    many,
+                    reference,
+                    u32::from(reference) + u32::from(count) - 1
+                )),
+            }
+        }
+        match (self.function, self.values.as_slice()) {
+            (_, []) => {}
+            (5, [value]) => {
+                let state = if *value == 0xff00 { "ON" } else { "OFF" };
+                description.push_str(&format!(" = {}", state));
+            }
+            (6, [value]) => description.push_str(&format!(" = {}", value)),
+            (_, values) => {
+                let shown: Vec<_> = values
+                    .iter()
+                    .take(MAX_INFO_VALUES)
+                    .map(|value| value.to_string())
+                    .collect();
+                let more = if values.len() > MAX_INFO_VALUES {
+                    ", ..."
+                } else {
+                    ""
+                };
+                description.push_str(&format!(" = [{}{}]", shown.join(", "), more));
+            }
+        }
+        description
+    }
+}
+
+impl AppMessage for ModbusMessage {
+    const TIME_FIELD: &'static str = "modbus.response_time";
+
+    fn info(&self) -> String {
+        let direction = if self.request { "Query" } else { "Response" };
+        match self.exception {
+            Some(code) => format!(
+                "{}: {}, unit {}, exception {}",
+                direction,
+                function_name(self.function),
+                self.unit_id,
+                exception_name(code)
+            ),
+            None => format!("{}: {}", direction, self.description()),
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("mbtcp.trans_id", self.transaction_id);
+        fields.insert("mbtcp.unit_id", self.unit_id);
+        fields.insert("modbus.func_code", self.function);
+        if let Some(code) = self.exception {
+            fields.insert("modbus.exception_code", code);
+        }
+        if let Some(reference) = self.reference {
+            fields.insert("modbus.reference_num", reference);
+        }
+        if let Some(count) = self.count {
+            let name = match reference_kind(self.function) {
+                ("register", _) => "modbus.word_cnt",
+                _ => "modbus.bit_cnt",
+            };
+            fields.insert(name, count);
+        }
+        for value in &self.values {
+            fields.insert("modbus.regval_uint16", *value);
+        }
+        if self.request && is_write(self.function) {
+            fields.insert("modbus.write", true);
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        self.exception.map(|code| {
+            format!(
+                "{} ({})",
+                exception_name(code),
+                function_name(self.function)
+            )
+        })
+    }
+
+    fn warning(&self) -> Option<String> {
+        (self.request && is_write(self.function))
+            .then(|| format!("Modbus write: {}", self.description()))
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.data.get(..2)?;
+        self.data = &self.data[2..];
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Registers preceded by their byte count.
+    fn registers(&mut self) -> Option<Vec<u16>> {
+        let count = usize::from(self.u8()?) / 2;
+        (0..count).map(|_| self.u16()).collect()
+    }
+}
+
+/// Decodes the addresses and values of a PDU following its function code.
+fn parse_pdu(message: &mut ModbusMessage, data: &[u8]) -> Option<()> {
+    let mut reader = Reader { data };
+    match (message.request, message.function) {
+        (true, 1..=4 | 15) | (false, 15 | 16) => {
+            message.reference = Some(reader.u16()?);
+            message.count = Some(reader.u16()?);
+        }
+        (_, 5 | 6) => {
+            message.reference = Some(reader.u16()?);
+            message.count = Some(1);
+            message.values = vec![reader.u16()?];
+        }
+        (true, 16) => {
+            message.reference = Some(reader.u16()?);
+            message.count = Some(reader.u16()?);
+            message.values = reader.registers()?;
+        }
+        (_, 22) => {
+            message.reference = Some(reader.u16()?);
+            message.count = Some(1);
+            // AND and OR masks
+            message.values = vec![reader.u16()?, reader.u16()?];
+        }
+        (true, 23) => {
+            // The read range comes first, only the written one is kept
+            reader.u16()?;
+            reader.u16()?;
+            message.reference = Some(reader.u16()?);
+            message.count = Some(reader.u16()?);
+            message.values = reader.registers()?;
+        }
+        (false, 3 | 4 | 23) => {
+            message.values = reader.registers()?;
+            message.count = Some(message.values.len() as u16);
+        }
+        _ => {}
+    }
+    Some(())
+}
+
+/// Parses an ADU: the MBAP header, function code and its data.
+fn parse_adu(request: bool, adu: &[u8]) -> Option<ModbusMessage> {
+    let function = *adu.get(MBAP_LEN + 1)?;
+    let mut message = ModbusMessage {
+        request,
+        transaction_id: u16::from_be_bytes([adu[0], adu[1]]),
+        unit_id: adu[MBAP_LEN],
+        function: function & !EXCEPTION,
+        exception: None,
+        reference: None,
+        count: None,
+        values: Vec::new(),
+    };
+    let data = &adu[MBAP_LEN + 2..];
+    if !request && function & EXCEPTION != 0 {
+        message.exception = Some(*data.first()?);
+    } else {
+        // Truncated PDUs keep what was decoded
+        let _ = parse_pdu(&mut message, data);
+    }
+    Some(message)
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// Function, first address, request time and description of requests
+    /// by transaction ID.
+    pending: HashMap<u16, (u8, Option<u16>, DateTime<Utc>, String)>,
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+}
+
+/// Splits reassembled Modbus/TCP connections into requests and responses
+/// and matches them by transaction ID.
+#[derive(Debug, Default)]
+pub struct ModbusTracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl ModbusTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the requests or responses `stream` completes, pairing
+    /// responses with their requests.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+        timestamp: DateTime<Utc>,
+    ) -> Exchange<ModbusMessage> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (buffer, connection) = connection.split(from_client);
+        buffer.push(&stream.data);
+        while let Some(header) = buffer.data().get(..MBAP_LEN) {
+            let protocol_id = u16::from_be_bytes([header[2], header[3]]);
+            let length = usize::from(u16::from_be_bytes([header[4], header[5]]));
+            if protocol_id != 0 || !(2..=MAX_LENGTH).contains(&length) {
+                // Not at an ADU boundary, e.g. after missing data
+                buffer.clear();
+                break;
+            }
+            let Some(adu) = buffer.take(MBAP_LEN + length) else {
+                break;
+            };
+            let Some(mut message) = parse_adu(from_client, &adu) else {
+                continue;
+            };
+            if from_client {
+                if connection.pending.len() >= MAX_PENDING_REQUESTS {
+                    connection.pending.clear();
+                }
+                connection.pending.insert(
+                    message.transaction_id,
+                    (
+                        message.function,
+                        message.reference,
+                        timestamp,
+                        message.info(),
+                    ),
+                );
+            } else if let Some((function, reference, sent, query)) =
+                connection.pending.remove(&message.transaction_id)
+            {
+                if function == message.function && message.reference.is_none() {
+                    message.reference = reference;
+                }
+                exchange.timings.extend(
+                    query::latency(sent, timestamp).map(|latency| QueryTiming { query, latency }),
+                );
+            }
+            exchange.messages.push(message);
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    pub(crate) fn adu(transaction_id: u16, unit_id: u8, pdu: &[u8]) -> Vec<u8> {
+        let mut adu = transaction_id.to_be_bytes().to_vec();
+        adu.extend_from_slice(&[0, 0]);
+        adu.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+        adu.push(unit_id);
+        adu.extend_from_slice(pdu);
+        adu
+    }
+
+    #[test]
+    fn test_read_registers() {
+        let mut tracker = ModbusTracker::new();
+        let start = Utc::now();
+        let request = adu(1, 17, &[3, 0, 100, 0, 3]);
+        let exchange =
+            tracker.process(CLIENT, SERVER, true, &StreamData::complete(&request), start);
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Query: Read Holding Registers, unit 17, registers 100-102"
+        );
+        assert!(exchange.messages[0].warning().is_none());
+
+        let response = adu(1, 17, &[3, 6, 0, 1, 0, 2, 0x12, 0x34]);
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(&response),
+            start + chrono::Duration::milliseconds(4),
+        );
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Response: Read Holding Registers, unit 17, registers 100-102 = [1, 2, 4660]"
+        );
+        assert_eq!(exchange.timings[0].latency, 0.004);
+    }
+
+    #[test]
+    fn test_writes_and_exceptions() {
+        let mut tracker = ModbusTracker::new();
+        let now = Utc::now();
+        let mut data = adu(7, 1, &[5, 0, 8, 0xff, 0]);
+        data.extend(adu(8, 1, &[16, 0, 40, 0, 2, 4, 0, 10, 0, 20]));
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&data), now);
+        let warnings: Vec<_> = exchange
+            .messages
+            .iter()
+            .filter_map(|message| message.warning())
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "Modbus write: Write Single Coil, unit 1, coil 8 = ON",
+                "Modbus write: Write Multiple Registers, unit 1, registers 40-41 = [10, 20]"
+            ]
+        );
+
+        let response = adu(8, 1, &[16 | EXCEPTION, 2]);
+        let exchange =
+            tracker.process(CLIENT, SERVER, false, &StreamData::complete(&response), now);
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Response: Write Multiple Registers, unit 1, exception Illegal Data Address"
+        );
+        assert_eq!(
+            exchange.messages[0].error().unwrap(),
+            "Illegal Data Address (Write Multiple Registers)"
+        );
+    }
+}
@@ -0,0 +1,491 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionState, ConnectionTable, Exchange};
+use crate::reassembly::{MessageBuffer, StreamData};
+use std::net::IpAddr;
+
+// The remaining length takes at most four bytes
+const MAX_LENGTH_BYTES: usize = 4;
+const VERSION_5: u8 = 5;
+
+pub const CONNECT: u8 = 1;
+pub const CONNACK: u8 = 2;
+pub const PUBLISH: u8 = 3;
+pub const SUBSCRIBE: u8 = 8;
+pub const SUBACK: u8 = 9;
+pub const UNSUBSCRIBE: u8 = 10;
+
+pub fn packet_type_name(packet_type: u8) -> &'static str {
+    match packet_type {
+        CONNECT => "Connect Command",
+        CONNACK => "Connect Ack",
+        PUBLISH => "Publish Message",
+        4 => "Publish Ack",
+        5 => "Publish Received",
+        6 => "Publish Release",
+        7 => "Publish Complete",
+        SUBSCRIBE => "Subscribe Request",
+        SUBACK => "Subscribe Ack",
+        UNSUBSCRIBE => "Unsubscribe Request",
+        11 => "Unsubscribe Ack",
+        12 => "Ping Request",
+        13 => "Ping Response",
+        14 => "Disconnect Req",
+        15 => "Authentication Exchange",
+        _ => "Reserved",
+    }
+}
+
+/// Describes a CONNACK return code (3.1.1) or reason code (5.0).
+pub fn connect_return_name(code: u8) -> &'static str {
+    match code {
+        0 => "Connection Accepted",
+        1 => "Connection Refused: unacceptable protocol version",
+        2 => "Connection Refused: identifier rejected",
+        3 => "Connection Refused: server unavailable",
+        4 => "Connection Refused: bad user name or password",
+        5 => "Connection Refused: not authorized",
+        0x84 => "Unsupported Protocol Version",
+        0x85 => "Client Identifier not valid",
+        0x86 => "Bad User Name or Password",
+        0x87 => "Not authorized",
+        0x88 => "Server unavailable",
+        0x8a => "Banned",
+        _ => "Connection Refused",
+    }
+}
+
+/// The variable header and payload of the packet types that carry more
+/// than a packet identifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    Connect {
+        protocol_name: String,
+        version: u8,
+        keep_alive: u16,
+        client_id: String,
+        username: Option<String>,
+    },
+    ConnAck {
+        session_present: bool,
+        return_code: u8,
+    },
+    Publish {
+        topic: String,
+        payload: Vec<u8>,
+    },
+    /// Topic filters with their requested QoS.
+    Subscribe(Vec<(String, u8)>),
+    SubAck(Vec<u8>),
+    Unsubscribe(Vec<String>),
+    Other,
+}
+
+/// An MQTT control packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttPacket {
+    pub packet_type: u8,
+    pub flags: u8,
+    pub length: usize,
+    pub packet_id: Option<u16>,
+    pub body: Body,
+}
+
+impl MqttPacket {
+    /// QoS of a PUBLISH packet.
+    pub fn qos(&self) -> u8 {
+        (self.flags >> 1) & 0x03
+    }
+}
+
+impl AppMessage for MqttPacket {
+    const TIME_FIELD: &'static str = "mqtt.response_time";
+
+    fn info(&self) -> String {
+        let mut info = packet_type_name(self.packet_type).to_string();
+        if let Some(packet_id) = self.packet_id {
+            info.push_str(&format!(" (id={})", packet_id));
+        }
+        match &self.body {
+            Body::Connect { client_id, .. } => info.push_str(&format!(" [{}]", client_id)),
+            Body::ConnAck { return_code, .. } if *return_code != 0 => {
+                info.push_str(&format!(": {}", connect_return_name(*return_code)))
+            }
+            Body::Publish { topic, .. } => info.push_str(&format!(" [{}]", topic)),
+            Body::Subscribe(filters) => {
+                let topics: Vec<_> = filters.iter().map(|(topic, _)| topic.as_str()).collect();
+                info.push_str(&format!(" [{}]", topics.join(", ")));
+            }
+            Body::Unsubscribe(topics) => info.push_str(&format!(" [{}]", topics.join(", "))),
+            _ => {}
+        }
+        info
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("mqtt.msgtype", self.packet_type);
+        fields.insert("mqtt.len", self.length);
+        if let Some(packet_id) = self.packet_id {
+            fields.insert("mqtt.msgid", packet_id);
+        }
+        match &self.body {
+            Body::Connect {
+                protocol_name,
+                version,
+                keep_alive,
+                client_id,
+                username,
+            } => {
+                fields.insert("mqtt.protoname", protocol_name.as_str());
+                fields.insert("mqtt.ver", *version);
+                fields.insert("mqtt.kalive", *keep_alive);
+                fields.insert("mqtt.clientid", client_id.as_str());
+                if let Some(username) = username {
+                    fields.insert("mqtt.username", username.as_str());
+                }
+            }
+            Body::ConnAck {
+                session_present,
+                return_code,
+            } => {
+                fields.insert("mqtt.conack.flags.sp", *session_present);
+                fields.insert("mqtt.conack.val", *return_code);
+            }
+            Body::Publish { topic, payload } => {
+                fields.insert("mqtt.qos", self.qos());
+                fields.insert("mqtt.retain", self.flags & 0x01 != 0);
+                fields.insert("mqtt.dupflag", self.flags & 0x08 != 0);
+                fields.insert("mqtt.topic", topic.as_str());
+                fields.insert("mqtt.msg", payload.clone());
+            }
+            Body::Subscribe(filters) => {
+                for (topic, qos) in filters {
+                    fields.insert("mqtt.topic", topic.as_str());
+                    fields.insert("mqtt.sub.qos", *qos);
+                }
+            }
+            Body::SubAck(codes) => {
+                for code in codes {
+                    fields.insert("mqtt.suback.qos", *code);
+                }
+            }
+            Body::Unsubscribe(topics) => {
+                for topic in topics {
+                    fields.insert("mqtt.topic", topic.as_str());
+                }
+            }
+            Body::Other => {}
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match &self.body {
+            Body::ConnAck { return_code, .. } if *return_code != 0 => {
+                Some(connect_return_name(*return_code).to_string())
+            }
+            Body::SubAck(codes) if codes.iter().any(|code| *code >= 0x80) => {
+                Some("subscription refused".to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reads the variable length integer of the fixed header, returning it
+/// and the number of bytes it took.
+fn remaining_length(data: &[u8]) -> Option<Result<(usize, usize), ()>> {
+    let mut value = 0;
+    for (i, &byte) in data.iter().take(MAX_LENGTH_BYTES).enumerate() {
+        value |= usize::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(Ok((value, i + 1)));
+        }
+    }
+    match data.len() >= MAX_LENGTH_BYTES {
+        true => Some(Err(())),
+        false => None,
+    }
+}
+
+/// Reads fields of the variable header and payload.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u16()?;
+        Some(String::from_utf8_lossy(self.bytes(usize::from(len))?).into_owned())
+    }
+
+    /// Skips the properties of MQTT 5.0 packets.
+    fn properties(&mut self, version: u8) -> Option<()> {
+        if version == VERSION_5 {
+            let (len, consumed) = remaining_length(self.data)?.ok()?;
+            self.bytes(consumed + len)?;
+        }
+        Some(())
+    }
+}
+
+fn parse_body(packet_type: u8, flags: u8, version: u8, data: &[u8]) -> Option<(Option<u16>, Body)> {
+    let mut reader = Reader { data };
+    Some(match packet_type {
+        CONNECT => {
+            let protocol_name = reader.string()?;
+            let version = reader.u8()?;
+            let connect_flags = reader.u8()?;
+            let keep_alive = reader.u16()?;
+            reader.properties(version)?;
+            let client_id = reader.string()?;
+            if connect_flags & 0x04 != 0 {
+                // Will properties, topic and message
+                reader.properties(version)?;
+                reader.string()?;
+                let len = reader.u16()?;
+                reader.bytes(usize::from(len))?;
+            }
+            let username = match connect_flags & 0x80 {
+                0 => None,
+                _ => Some(reader.string()?),
+            };
+            (
+                None,
+                Body::Connect {
+                    protocol_name,
+                    version,
+                    keep_alive,
+                    client_id,
+                    username,
+                },
+            )
+        }
+        CONNACK => {
+            let session_present = reader.u8()? & 0x01 != 0;
+            (
+                None,
+                Body::ConnAck {
+                    session_present,
+                    return_code: reader.u8()?,
+                },
+            )
+        }
+        PUBLISH => {
+            let topic = reader.string()?;
+            let packet_id = match (flags >> 1) & 0x03 {
+                0 => None,
+                _ => Some(reader.u16()?),
+            };
+            reader.properties(version)?;
+            let payload = reader.data.to_vec();
+            (packet_id, Body::Publish { topic, payload })
+        }
+        SUBSCRIBE | UNSUBSCRIBE => {
+            let packet_id = reader.u16()?;
+            reader.properties(version)?;
+            let mut filters = Vec::new();
+            while !reader.data.is_empty() {
+                let topic = reader.string()?;
+                let qos = match packet_type {
+                    SUBSCRIBE => reader.u8()? & 0x03,
+                    _ => 0,
+                };
+                filters.push((topic, qos));
+            }
+            let body = match packet_type {
+                SUBSCRIBE => Body::Subscribe(filters),
+                _ => Body::Unsubscribe(filters.into_iter().map(|(topic, _)| topic).collect()),
+            };
+            (Some(packet_id), body)
+        }
+        SUBACK => {
+            let packet_id = reader.u16()?;
+            reader.properties(version)?;
+            (Some(packet_id), Body::SubAck(reader.data.to_vec()))
+        }
+        4..=7 | 11 => (Some(reader.u16()?), Body::Other),
+        _ => (None, Body::Other),
+    })
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// Protocol level from CONNECT, which decides whether packets carry
+    /// properties.
+    version: u8,
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+}
+
+impl Connection {
+    fn next_packet(&mut self, buffer: &mut MessageBuffer) -> Option<MqttPacket> {
+        let data = buffer.data();
+        let first = *data.first()?;
+        let (len, len_bytes) = match remaining_length(&data[1..])? {
+            Ok(length) => length,
+            Err(()) => {
+                // Not at a packet boundary, e.g. after missing data
+                buffer.clear();
+                return None;
+            }
+        };
+        let packet = buffer.take(1 + len_bytes + len)?;
+        let (packet_type, flags) = (first >> 4, first & 0x0f);
+        let data = &packet[1 + len_bytes..];
+        let (packet_id, body) =
+            parse_body(packet_type, flags, self.version, data).unwrap_or((None, Body::Other));
+        if let Body::Connect { version, .. } = body {
+            self.version = version;
+        }
+        Some(MqttPacket {
+            packet_type,
+            flags,
+            length: len,
+            packet_id,
+            body,
+        })
+    }
+}
+
+/// Splits reassembled MQTT connections into control packets.
+#[derive(Debug, Default)]
+pub struct MqttTracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl MqttTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the control packets `stream` completes.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+    ) -> Exchange<MqttPacket> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (buffer, connection) = connection.split(from_client);
+        buffer.push(&stream.data);
+        while let Some(packet) = connection.next_packet(buffer) {
+            exchange.messages.push(packet);
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    pub(crate) fn packet(first: u8, body: &[u8]) -> Vec<u8> {
+        let mut packet = vec![first];
+        let mut len = body.len();
+        loop {
+            let byte = (len & 0x7f) as u8;
+            len >>= 7;
+            packet.push(if len > 0 { byte | 0x80 } else { byte });
+            if len == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        packet
+    }
+
+    pub(crate) fn string(text: &str) -> Vec<u8> {
+        let mut data = (text.len() as u16).to_be_bytes().to_vec();
+        data.extend_from_slice(text.as_bytes());
+        data
+    }
+
+    #[test]
+    fn test_connect_and_publish_v5() {
+        let mut tracker = MqttTracker::new();
+
+        let mut connect = string("MQTT");
+        connect.extend_from_slice(&[5, 0x82, 0, 60, 0]);
+        connect.extend(string("sensor-1"));
+        connect.extend(string("alice"));
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(&packet(0x10, &connect)),
+        );
+        assert_eq!(exchange.messages[0].info(), "Connect Command [sensor-1]");
+
+        // QoS 1 with an empty property list
+        let mut publish = string("sensors/temp");
+        publish.extend_from_slice(&[0, 7, 0]);
+        publish.extend_from_slice(b"21.5");
+        let data = packet(0x32, &publish);
+        let (first, second) = data.split_at(6);
+        assert!(tracker
+            .process(CLIENT, SERVER, true, &StreamData::complete(first))
+            .messages
+            .is_empty());
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(second));
+        let message = &exchange.messages[0];
+        assert_eq!(message.info(), "Publish Message (id=7) [sensors/temp]");
+        assert_eq!(message.qos(), 1);
+
+        let mut fields = Fields::new();
+        message.insert_fields(&mut fields);
+        assert_eq!(fields.get("mqtt.topic"), Some(&"sensors/temp".into()));
+        assert_eq!(fields.get("mqtt.msg"), Some(&b"21.5".to_vec().into()));
+    }
+
+    #[test]
+    fn test_subscribe_and_refused_connection() {
+        let mut tracker = MqttTracker::new();
+
+        let mut subscribe = vec![0, 1];
+        subscribe.extend(string("a/#"));
+        subscribe.push(1);
+        subscribe.extend(string("b"));
+        subscribe.push(0);
+        let mut data = packet(0x82, &subscribe);
+        data.extend(packet(0xc0, &[]));
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&data));
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Subscribe Request (id=1) [a/#, b]"
+        );
+        assert_eq!(exchange.messages[1].info(), "Ping Request");
+
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(&packet(0x20, &[0, 5])),
+        );
+        assert_eq!(
+            exchange.messages[0].error().unwrap(),
+            "Connection Refused: not authorized"
+        );
+    }
+}
@@ -0,0 +1,789 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionState, ConnectionTable, Exchange, PendingQueries};
+use crate::reassembly::{MessageBuffer, StreamData};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+const MAX_STATEMENTS: usize = 1024;
+// Payload length and sequence number
+const PACKET_HEADER_LEN: usize = 4;
+const MAX_PAYLOAD_LEN: usize = 0xff_ffff;
+
+const CLIENT_CONNECT_WITH_DB: u32 = 0x0000_0008;
+const CLIENT_SSL: u32 = 0x0000_0800;
+const CLIENT_SECURE_CONNECTION: u32 = 0x0000_8000;
+const CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA: u32 = 0x0020_0000;
+// Login request fields before the user name
+const LOGIN_HEADER_LEN: usize = 32;
+
+const SERVER_MORE_RESULTS_EXISTS: u16 = 0x0008;
+
+pub const COM_QUIT: u8 = 0x01;
+pub const COM_INIT_DB: u8 = 0x02;
+pub const COM_QUERY: u8 = 0x03;
+pub const COM_FIELD_LIST: u8 = 0x04;
+pub const COM_STATISTICS: u8 = 0x09;
+pub const COM_STMT_PREPARE: u8 = 0x16;
+pub const COM_STMT_EXECUTE: u8 = 0x17;
+pub const COM_STMT_SEND_LONG_DATA: u8 = 0x18;
+pub const COM_STMT_CLOSE: u8 = 0x19;
+pub const COM_STMT_FETCH: u8 = 0x1c;
+
+pub fn command_name(command: u8) -> &'static str {
+    match command {
+        0x00 => "Sleep",
+        COM_QUIT => "Quit",
+        COM_INIT_DB => "Use Database",
+        COM_QUERY => "Query",
+        COM_FIELD_LIST => "Show Fields",
+        0x05 => "Create Database",
+        0x06 => "Drop Database",
+        0x07 => "Refresh",
+        0x08 => "Shutdown",
+        COM_STATISTICS => "Statistics",
+        0x0a => "Process Info",
+        0x0b => "Connect",
+        0x0c => "Kill",
+        0x0d => "Debug",
+        0x0e => "Ping",
+        0x11 => "Change User",
+        COM_STMT_PREPARE => "Prepare Statement",
+        COM_STMT_EXECUTE => "Execute Statement",
+        COM_STMT_SEND_LONG_DATA => "Send BLOB",
+        COM_STMT_CLOSE => "Close Statement",
+        0x1a => "Reset Statement",
+        0x1b => "Set Option",
+        COM_STMT_FETCH => "Fetch Data",
+        0x1f => "Reset Connection",
+        _ => "Unknown",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Greeting {
+        protocol: u8,
+        version: String,
+        connection_id: u32,
+    },
+    Login {
+        user: String,
+        database: Option<String>,
+    },
+    SslRequest,
+    AuthSwitch,
+    AuthData,
+    Command {
+        command: u8,
+        /// Query text, or the statement's query for executions.
+        argument: Option<String>,
+        statement_id: Option<u32>,
+    },
+    Ok {
+        affected_rows: u64,
+        last_insert_id: u64,
+    },
+    Error {
+        code: u16,
+        state: Option<String>,
+        message: String,
+    },
+    Eof,
+    ColumnCount(u64),
+    ColumnDefinition(Option<String>),
+    Row,
+    PrepareOk {
+        statement_id: u32,
+        columns: u16,
+        params: u16,
+    },
+    Other,
+}
+
+/// A packet of the MySQL client/server protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MysqlPacket {
+    pub from_client: bool,
+    pub length: usize,
+    pub number: u8,
+    pub kind: Kind,
+}
+
+impl AppMessage for MysqlPacket {
+    const TIME_FIELD: &'static str = "mysql.response_time";
+
+    fn info(&self) -> String {
+        match &self.kind {
+            Kind::Greeting { version, .. } => format!("Server Greeting: version={}", version),
+            Kind::Login { user, database } => match database {
+                Some(database) => format!("Login Request: user={} db={}", user, database),
+                None => format!("Login Request: user={}", user),
+            },
+            Kind::SslRequest => "SSL Request".to_string(),
+            Kind::AuthSwitch => "Auth Switch Request".to_string(),
+            Kind::AuthData => "Authentication Data".to_string(),
+            Kind::Command {
+                command, argument, ..
+            } => match argument {
+                Some(argument) => format!("Request {}: {}", command_name(*command), argument),
+                None => format!("Request {}", command_name(*command)),
+            },
+            Kind::Ok { affected_rows, .. } if *affected_rows > 0 => {
+                format!("Response OK, {} rows affected", affected_rows)
+            }
+            Kind::Ok { .. } => "Response OK".to_string(),
+            Kind::Error { code, message, .. } => format!("Response Error {}: {}", code, message),
+            Kind::Eof => "Response EOF".to_string(),
+            Kind::ColumnCount(count) => format!("Response: {} columns", count),
+            Kind::ColumnDefinition(_) => "Column definition".to_string(),
+            Kind::Row => "Row".to_string(),
+            Kind::PrepareOk { statement_id, .. } => {
+                format!("Response Prepare OK: statement={}", statement_id)
+            }
+            Kind::Other if self.from_client => "Request".to_string(),
+            Kind::Other => "Response".to_string(),
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("mysql.packet_length", self.length);
+        fields.insert("mysql.packet_number", self.number);
+        match &self.kind {
+            Kind::Greeting {
+                protocol,
+                version,
+                connection_id,
+            } => {
+                fields.insert("mysql.protocol", *protocol);
+                fields.insert("mysql.version", version.as_str());
+                fields.insert("mysql.thread_id", *connection_id);
+            }
+            Kind::Login { user, database } => {
+                fields.insert("mysql.user", user.as_str());
+                if let Some(database) = database {
+                    fields.insert("mysql.schema", database.as_str());
+                }
+            }
+            Kind::Command {
+                command,
+                argument,
+                statement_id,
+            } => {
+                fields.insert("mysql.command", *command);
+                match (*command, argument) {
+                    (COM_INIT_DB, Some(schema)) => fields.insert("mysql.schema", schema.as_str()),
+                    (_, Some(query)) => fields.insert("mysql.query", query.as_str()),
+                    _ => {}
+                }
+                if let Some(statement_id) = statement_id {
+                    fields.insert("mysql.stmt_id", *statement_id);
+                }
+            }
+            Kind::Ok {
+                affected_rows,
+                last_insert_id,
+            } => {
+                fields.insert("mysql.affected_rows", *affected_rows);
+                fields.insert("mysql.insert_id", *last_insert_id);
+            }
+            Kind::Error {
+                code,
+                state,
+                message,
+            } => {
+                fields.insert("mysql.error_code", *code);
+                if let Some(state) = state {
+                    fields.insert("mysql.sqlstate", state.as_str());
+                }
+                fields.insert("mysql.error.message", message.as_str());
+            }
+            Kind::ColumnCount(count) => fields.insert("mysql.num_fields", *count),
+            Kind::ColumnDefinition(Some(name)) => fields.insert("mysql.field.name", name.as_str()),
+            Kind::PrepareOk {
+                statement_id,
+                columns,
+                params,
+            } => {
+                fields.insert("mysql.stmt_id", *statement_id);
+                fields.insert("mysql.num_fields", *columns);
+                fields.insert("mysql.num_params", *params);
+            }
+            _ => {}
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match &self.kind {
+            Kind::Error { code, message, .. } => Some(format!("{} ({})", message, code)),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a length-encoded integer, returning it and the rest of `data`.
+fn lenenc_int(data: &[u8]) -> Option<(u64, &[u8])> {
+    let (&first, rest) = data.split_first()?;
+    let len = match first {
+        0xfc => 2,
+        0xfd => 3,
+        0xfe => 8,
+        0xfb | 0xff => return None,
+        _ => return Some((u64::from(first), rest)),
+    };
+    let bytes = rest.get(..len)?;
+    let value = bytes
+        .iter()
+        .rev()
+        .fold(0u64, |value, &byte| value << 8 | u64::from(byte));
+    Some((value, &rest[len..]))
+}
+
+fn lenenc_str(data: &[u8]) -> Option<(String, &[u8])> {
+    let (len, rest) = lenenc_int(data)?;
+    let len = usize::try_from(len).ok()?;
+    let bytes = rest.get(..len)?;
+    Some((String::from_utf8_lossy(bytes).into_owned(), &rest[len..]))
+}
+
+fn cstring(data: &[u8]) -> Option<(String, &[u8])> {
+    let end = data.iter().position(|&b| b == 0)?;
+    Some((
+        String::from_utf8_lossy(&data[..end]).into_owned(),
+        &data[end + 1..],
+    ))
+}
+
+fn u16_le(data: &[u8]) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(..2)?.try_into().ok()?))
+}
+
+fn u32_le(data: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(..4)?.try_into().ok()?))
+}
+
+fn parse_greeting(payload: &[u8]) -> Option<Kind> {
+    let (version, rest) = cstring(&payload[1..])?;
+    Some(Kind::Greeting {
+        protocol: payload[0],
+        version,
+        connection_id: u32_le(rest)?,
+    })
+}
+
+fn parse_login(payload: &[u8]) -> Option<Kind> {
+    let capabilities = u32_le(payload)?;
+    if capabilities & CLIENT_SSL != 0 && payload.len() == LOGIN_HEADER_LEN {
+        return Some(Kind::SslRequest);
+    }
+    let (user, rest) = cstring(payload.get(LOGIN_HEADER_LEN..)?)?;
+    let rest = if capabilities & CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA != 0 {
+        let (len, rest) = lenenc_int(rest)?;
+        rest.get(usize::try_from(len).ok()?..)?
+    } else if capabilities & CLIENT_SECURE_CONNECTION != 0 {
+        let (&len, rest) = rest.split_first()?;
+        rest.get(usize::from(len)..)?
+    } else {
+        cstring(rest)?.1
+    };
+    let database = match capabilities & CLIENT_CONNECT_WITH_DB {
+        0 => None,
+        _ => Some(cstring(rest)?.0),
+    };
+    Some(Kind::Login { user, database })
+}
+
+fn parse_error(payload: &[u8]) -> Option<Kind> {
+    let code = u16_le(&payload[1..])?;
+    let rest = payload.get(3..)?;
+    let (state, message) = match rest.first() {
+        Some(b'#') => (
+            Some(String::from_utf8_lossy(rest.get(1..6)?).into_owned()),
+            &rest[6..],
+        ),
+        _ => (None, rest),
+    };
+    Some(Kind::Error {
+        code,
+        state,
+        message: String::from_utf8_lossy(message).into_owned(),
+    })
+}
+
+/// The OK packet and its server status flags.
+fn parse_ok(payload: &[u8]) -> Option<(Kind, u16)> {
+    let (affected_rows, rest) = lenenc_int(&payload[1..])?;
+    let (last_insert_id, rest) = lenenc_int(rest)?;
+    let status = u16_le(rest).unwrap_or(0);
+    Some((
+        Kind::Ok {
+            affected_rows,
+            last_insert_id,
+        },
+        status,
+    ))
+}
+
+/// EOF packets and the OK packets replacing them both start with 0xfe,
+/// which otherwise only starts rows with a value of 16 MiB or more.
+fn is_eof(payload: &[u8]) -> bool {
+    payload.first() == Some(&0xfe) && payload.len() < MAX_PAYLOAD_LEN
+}
+
+fn eof_status(payload: &[u8]) -> u16 {
+    match payload.len() {
+        // Warnings, then status flags
+        5 => u16_le(&payload[3..]).unwrap_or(0),
+        _ => parse_ok(payload).map_or(0, |(_, status)| status),
+    }
+}
+
+/// What the server sends next in answer to a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Response {
+    Idle,
+    /// OK, ERR or the column count of a result set.
+    First,
+    Columns(u64),
+    /// The EOF after column definitions, or the first row without it.
+    ColumnsEnd,
+    Rows,
+    /// Parameter and column definitions of a prepared statement.
+    Definitions(u64),
+    /// A bare string, as answered to COM_STATISTICS.
+    Text,
+}
+
+#[derive(Debug)]
+struct Connection {
+    handshake: bool,
+    encrypted: bool,
+    response: Response,
+    command: Option<(u8, Option<String>)>,
+    pending: PendingQueries,
+    /// Query text of prepared statements, by statement id.
+    statements: HashMap<u32, String>,
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        Connection {
+            handshake: false,
+            encrypted: false,
+            response: Response::Idle,
+            command: None,
+            pending: PendingQueries::default(),
+            statements: HashMap::new(),
+        }
+    }
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+
+    fn gap(&mut self, _: &mut MessageBuffer, _: bool) {
+        // Responses can no longer be matched to their commands
+        self.pending.clear();
+        self.response = Response::Idle;
+    }
+}
+
+fn next_packet(buffer: &mut MessageBuffer) -> Option<(u8, Vec<u8>)> {
+    let header = buffer.data().get(..PACKET_HEADER_LEN)?;
+    let len = usize::from(header[0]) | usize::from(header[1]) << 8 | usize::from(header[2]) << 16;
+    let number = header[3];
+    let packet = buffer.take(PACKET_HEADER_LEN + len)?;
+    Some((number, packet[PACKET_HEADER_LEN..].to_vec()))
+}
+
+impl Connection {
+    fn client_packet(&mut self, number: u8, payload: &[u8], timestamp: DateTime<Utc>) -> Kind {
+        if self.handshake {
+            if number != 1 {
+                return Kind::AuthData;
+            }
+            let kind = parse_login(payload).unwrap_or(Kind::Other);
+            if kind == Kind::SslRequest {
+                self.encrypted = true;
+            }
+            return kind;
+        }
+        // Commands start a new sequence
+        let Some((&command, argument)) = payload.split_first().filter(|_| number == 0) else {
+            return Kind::Other;
+        };
+        let text = || Some(String::from_utf8_lossy(argument).into_owned());
+        let (argument, statement_id) = match command {
+            COM_QUERY | COM_INIT_DB | COM_STMT_PREPARE => (text(), None),
+            COM_FIELD_LIST => (cstring(argument).map(|(table, _)| table), None),
+            COM_STMT_EXECUTE | COM_STMT_CLOSE | COM_STMT_FETCH | COM_STMT_SEND_LONG_DATA => {
+                let statement_id = u32_le(argument);
+                let query = statement_id.and_then(|id| self.statements.get(&id).cloned());
+                if command == COM_STMT_CLOSE {
+                    statement_id.map(|id| self.statements.remove(&id));
+                }
+                (query, statement_id)
+            }
+            _ => (None, None),
+        };
+
+        self.response = match command {
+            COM_QUIT | COM_STMT_CLOSE | COM_STMT_SEND_LONG_DATA => Response::Idle,
+            COM_FIELD_LIST | COM_STMT_FETCH => Response::Rows,
+            COM_STATISTICS => Response::Text,
+            _ => Response::First,
+        };
+        if self.response != Response::Idle {
+            let query = argument
+                .clone()
+                .unwrap_or_else(|| command_name(command).to_string());
+            self.pending.push(query, timestamp);
+            self.command = Some((command, argument.clone()));
+        }
+        Kind::Command {
+            command,
+            argument,
+            statement_id,
+        }
+    }
+
+    /// Decodes a server packet and returns whether it ends the response.
+    fn server_packet(&mut self, number: u8, payload: &[u8]) -> (Kind, bool) {
+        if number == 0 && payload.first() == Some(&10) {
+            if let Some(greeting) = parse_greeting(payload) {
+                self.handshake = true;
+                return (greeting, false);
+            }
+        }
+        let Some(&first) = payload.first() else {
+            return (Kind::Other, false);
+        };
+        if self.handshake {
+            let kind = match first {
+                0x00 => {
+                    self.handshake = false;
+                    parse_ok(payload).map(|(ok, _)| ok)
+                }
+                0xff => parse_error(payload),
+                0xfe => Some(Kind::AuthSwitch),
+                _ => Some(Kind::AuthData),
+            };
+            return (kind.unwrap_or(Kind::Other), false);
+        }
+
+        let command = self.command.as_ref().map(|(command, _)| *command);
+        let (kind, response) = match self.response {
+            Response::Idle => (Kind::Other, Response::Idle),
+            _ if first == 0xff => (parse_error(payload).unwrap_or(Kind::Other), Response::Idle),
+            Response::First if first == 0x00 && command == Some(COM_STMT_PREPARE) => {
+                match (
+                    u32_le(&payload[1..]),
+                    u16_le(payload.get(5..).unwrap_or(&[])),
+                    u16_le(payload.get(7..).unwrap_or(&[])),
+                ) {
+                    (Some(statement_id), Some(columns), Some(params)) => {
+                        if let Some((_, Some(query))) = &self.command {
+                            if self.statements.len() >= MAX_STATEMENTS {
+                                self.statements.clear();
+                            }
+                            self.statements.insert(statement_id, query.clone());
+                        }
+                        let definitions = u64::from(columns) + u64::from(params);
+                        let response = match definitions {
+                            0 => Response::Idle,
+                            n => Response::Definitions(n),
+                        };
+                        (
+                            Kind::PrepareOk {
+                                statement_id,
+                                columns,
+                                params,
+                            },
+                            response,
+                        )
+                    }
+                    _ => (Kind::Other, Response::Idle),
+                }
+            }
+            Response::First if first == 0x00 => match parse_ok(payload) {
+                Some((ok, status)) if status & SERVER_MORE_RESULTS_EXISTS != 0 => {
+                    (ok, Response::First)
+                }
+                Some((ok, _)) => (ok, Response::Idle),
+                None => (Kind::Other, Response::Idle),
+            },
+            Response::First => match lenenc_int(payload) {
+                Some((count, _)) if count > 0 => {
+                    (Kind::ColumnCount(count), Response::Columns(count))
+                }
+                _ => (Kind::Other, Response::Idle),
+            },
+            Response::Columns(remaining) | Response::Definitions(remaining) => {
+                // Catalog, schema, table and original table come first
+                let name = (0..4)
+                    .try_fold(payload, |rest, _| lenenc_str(rest).map(|(_, rest)| rest))
+                    .and_then(|rest| lenenc_str(rest))
+                    .map(|(name, _)| name);
+                let response = match (self.response, remaining) {
+                    (Response::Columns(_), 1) => Response::ColumnsEnd,
+                    (Response::Columns(_), n) => Response::Columns(n - 1),
+                    (_, 1) => Response::ColumnsEnd,
+                    (_, n) => Response::Definitions(n - 1),
+                };
+                (Kind::ColumnDefinition(name), response)
+            }
+            Response::ColumnsEnd if is_eof(payload) => match command {
+                Some(COM_STMT_PREPARE) => (Kind::Eof, Response::Idle),
+                _ => (Kind::Eof, Response::Rows),
+            },
+            Response::ColumnsEnd | Response::Rows if !is_eof(payload) => {
+                (Kind::Row, Response::Rows)
+            }
+            Response::ColumnsEnd | Response::Rows => {
+                let more = eof_status(payload) & SERVER_MORE_RESULTS_EXISTS != 0;
+                let kind = match payload.len() {
+                    5 => Kind::Eof,
+                    _ => parse_ok(payload).map_or(Kind::Eof, |(ok, _)| ok),
+                };
+                (
+                    kind,
+                    if more {
+                        Response::First
+                    } else {
+                        Response::Idle
+                    },
+                )
+            }
+            Response::Text => (Kind::Other, Response::Idle),
+        };
+        let done = self.response != Response::Idle && response == Response::Idle;
+        self.response = response;
+        (kind, done)
+    }
+}
+
+/// Splits reassembled MySQL connections into packets and measures how
+/// long commands take.
+#[derive(Debug, Default)]
+pub struct MysqlTracker {
+    connections: ConnectionTable<Connection>,
+}
+
+type Endpoint = (IpAddr, u16);
+
+impl MysqlTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the packets `stream` completes and the commands they
+    /// answer.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+        timestamp: DateTime<Utc>,
+    ) -> Exchange<MysqlPacket> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (buffer, connection) = connection.split(from_client);
+        if connection.encrypted {
+            return exchange;
+        }
+
+        buffer.push(&stream.data);
+        while let Some((number, payload)) = next_packet(buffer) {
+            let kind = if from_client {
+                connection.client_packet(number, &payload, timestamp)
+            } else {
+                let (kind, done) = connection.server_packet(number, &payload);
+                if done {
+                    exchange
+                        .timings
+                        .extend(connection.pending.complete(timestamp));
+                }
+                kind
+            };
+            exchange.messages.push(MysqlPacket {
+                from_client,
+                length: payload.len(),
+                number,
+                kind,
+            });
+            if connection.encrypted {
+                // The rest is TLS
+                buffer.clear();
+                break;
+            }
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    fn packet(number: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = (payload.len() as u32).to_le_bytes()[..3].to_vec();
+        packet.push(number);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn column(name: &str) -> Vec<u8> {
+        let mut column = b"\x03def\x00\x00\x00".to_vec();
+        column.push(name.len() as u8);
+        column.extend_from_slice(name.as_bytes());
+        column.extend_from_slice(&[0; 13]);
+        column
+    }
+
+    #[test]
+    fn test_login_and_query() {
+        let start = Utc::now();
+        let mut tracker = MysqlTracker::new();
+
+        let greeting = packet(0, b"\x0a8.0.36\0\x07\0\0\0salt");
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(&greeting),
+            start,
+        );
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Server Greeting: version=8.0.36"
+        );
+
+        let mut login = (CLIENT_SECURE_CONNECTION | CLIENT_CONNECT_WITH_DB)
+            .to_le_bytes()
+            .to_vec();
+        login.extend_from_slice(&[0; 28]);
+        login.extend_from_slice(b"alice\0\x02ab" as &[u8]);
+        login.extend_from_slice(b"app\0");
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(&packet(1, &login)),
+            start,
+        );
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Login Request: user=alice db=app"
+        );
+        tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(&packet(2, b"\0\0\0\x02\0\0\0")),
+            start,
+        );
+
+        let query = packet(0, b"\x03SELECT id FROM users");
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&query), start);
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Request Query: SELECT id FROM users"
+        );
+
+        let mut result = packet(1, b"\x01");
+        result.extend(packet(2, &column("id")));
+        result.extend(packet(3, b"\xfe\0\0\x02\0"));
+        result.extend(packet(4, b"\x011"));
+        result.extend(packet(5, b"\x012"));
+        result.extend(packet(6, b"\xfe\0\0\x02\0"));
+        let end = start + chrono::Duration::milliseconds(2);
+        let exchange = tracker.process(CLIENT, SERVER, false, &StreamData::complete(&result), end);
+        let infos: Vec<_> = exchange.messages.iter().map(|m| m.info()).collect();
+        assert_eq!(
+            crate::query::summarize(infos),
+            "Response: 1 columns, Column definition, Response EOF, Row (x2), Response EOF"
+        );
+        assert_eq!(exchange.timings[0].query, "SELECT id FROM users");
+        assert_eq!(exchange.timings[0].latency, 0.002);
+
+        let mut fields = Fields::new();
+        exchange.messages[1].insert_fields(&mut fields);
+        assert_eq!(fields.get("mysql.field.name"), Some(&"id".into()));
+    }
+
+    #[test]
+    fn test_prepared_statement_error() {
+        let start = Utc::now();
+        let mut tracker = MysqlTracker::new();
+
+        let prepare = packet(0, b"\x16DELETE FROM t WHERE id = ?");
+        tracker.process(CLIENT, SERVER, true, &StreamData::complete(&prepare), start);
+        let mut prepared = packet(1, b"\0\x05\0\0\0\0\0\x01\0\0\0\0");
+        prepared.extend(packet(2, &column("?")));
+        prepared.extend(packet(3, b"\xfe\0\0\x02\0"));
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(&prepared),
+            start,
+        );
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Response Prepare OK: statement=5"
+        );
+        assert_eq!(exchange.timings.len(), 1);
+
+        let execute = packet(0, b"\x17\x05\0\0\0\0\x01\0\0\0");
+        let exchange =
+            tracker.process(CLIENT, SERVER, true, &StreamData::complete(&execute), start);
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Request Execute Statement: DELETE FROM t WHERE id = ?"
+        );
+        let error = packet(1, b"\xff\x7a\x04#42S02Table 'db.t' doesn't exist");
+        let exchange = tracker.process(CLIENT, SERVER, false, &StreamData::complete(&error), start);
+        assert_eq!(
+            exchange.messages[0].error().unwrap(),
+            "Table 'db.t' doesn't exist (1146)"
+        );
+        assert_eq!(exchange.timings[0].query, "DELETE FROM t WHERE id = ?");
+    }
+
+    #[test]
+    fn test_ssl_request_stops_decoding() {
+        let mut tracker = MysqlTracker::new();
+        tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(&packet(0, b"\x0a8.0\0\x01\0\0\0")),
+            Utc::now(),
+        );
+
+        let mut ssl = (CLIENT_SSL | CLIENT_SECURE_CONNECTION)
+            .to_le_bytes()
+            .to_vec();
+        ssl.extend_from_slice(&[0; 28]);
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(&packet(1, &ssl)),
+            Utc::now(),
+        );
+        assert_eq!(exchange.messages[0].kind, Kind::SslRequest);
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(&[0x16, 3, 3, 0, 5, 1, 2, 3, 4]),
+            Utc::now(),
+        );
+        assert!(exchange.messages.is_empty());
+    }
+}
@@ -0,0 +1,304 @@
+use crate::fields::{format_mac, Fields};
+use std::net::{IpAddr, Ipv6Addr};
+
+pub const ROUTER_SOLICITATION: u8 = 133;
+pub const ROUTER_ADVERTISEMENT: u8 = 134;
+pub const NEIGHBOR_SOLICITATION: u8 = 135;
+pub const NEIGHBOR_ADVERTISEMENT: u8 = 136;
+pub const REDIRECT: u8 = 137;
+
+const OPT_SOURCE_LINK_ADDR: u8 = 1;
+const OPT_TARGET_LINK_ADDR: u8 = 2;
+const OPT_PREFIX_INFO: u8 = 3;
+const OPT_MTU: u8 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NdpMessage {
+    RouterSolicitation,
+    RouterAdvertisement {
+        cur_hop_limit: u8,
+        managed: bool,
+        other: bool,
+        router_lifetime: u16,
+    },
+    NeighborSolicitation {
+        target: Ipv6Addr,
+    },
+    NeighborAdvertisement {
+        target: Ipv6Addr,
+        router: bool,
+        solicited: bool,
+        override_flag: bool,
+    },
+    Redirect {
+        target: Ipv6Addr,
+        destination: Ipv6Addr,
+    },
+}
+
+/// An IPv6 Neighbor Discovery message (RFC 4861) with its options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NdpPacket {
+    pub message: NdpMessage,
+    pub source_link_addr: Option<[u8; 6]>,
+    pub target_link_addr: Option<[u8; 6]>,
+    pub prefixes: Vec<(Ipv6Addr, u8)>,
+    pub mtu: Option<u32>,
+}
+
+impl NdpPacket {
+    /// Parses a whole ICMPv6 message, starting at the type field.
+    pub fn parse(icmp: &[u8]) -> Option<NdpPacket> {
+        let body = icmp.get(4..)?;
+        let address = |offset: usize| -> Option<Ipv6Addr> {
+            let bytes: [u8; 16] = body.get(offset..offset + 16)?.try_into().ok()?;
+            Some(Ipv6Addr::from(bytes))
+        };
+
+        let (message, options_offset) = match *icmp.first()? {
+            ROUTER_SOLICITATION => (NdpMessage::RouterSolicitation, 4),
+            ROUTER_ADVERTISEMENT => {
+                let fixed = body.get(..12)?;
+                let message = NdpMessage::RouterAdvertisement {
+                    cur_hop_limit: fixed[0],
+                    managed: fixed[1] & 0x80 != 0,
+                    other: fixed[1] & 0x40 != 0,
+                    router_lifetime: u16::from_be_bytes([fixed[2], fixed[3]]),
+                };
+                (message, 12)
+            }
+            NEIGHBOR_SOLICITATION => (
+                NdpMessage::NeighborSolicitation {
+                    target: address(4)?,
+                },
+                20,
+            ),
+            NEIGHBOR_ADVERTISEMENT => {
+                let target = address(4)?;
+                let flags = body[0];
+                let message = NdpMessage::NeighborAdvertisement {
+                    target,
+                    router: flags & 0x80 != 0,
+                    solicited: flags & 0x40 != 0,
+                    override_flag: flags & 0x20 != 0,
+                };
+                (message, 20)
+            }
+            REDIRECT => (
+                NdpMessage::Redirect {
+                    target: address(4)?,
+                    destination: address(20)?,
+                },
+                36,
+            ),
+            _ => return None,
+        };
+
+        let mut packet = NdpPacket {
+            message,
+            source_link_addr: None,
+            target_link_addr: None,
+            prefixes: Vec::new(),
+            mtu: None,
+        };
+
+        // Options are type, length in units of 8 bytes, and data
+        let mut options = body.get(options_offset..)?;
+        while options.len() >= 8 {
+            let len = usize::from(options[1]) * 8;
+            if len == 0 || len > options.len() {
+                break;
+            }
+            let option = &options[..len];
+            match option[0] {
+                OPT_SOURCE_LINK_ADDR => packet.source_link_addr = option[2..8].try_into().ok(),
+                OPT_TARGET_LINK_ADDR => packet.target_link_addr = option[2..8].try_into().ok(),
+                OPT_PREFIX_INFO if len == 32 => {
+                    let prefix: [u8; 16] = option[16..32].try_into().ok()?;
+                    packet.prefixes.push((Ipv6Addr::from(prefix), option[2]));
+                }
+                OPT_MTU => {
+                    packet.mtu = Some(u32::from_be_bytes([
+                        option[4], option[5], option[6], option[7],
+                    ]));
+                }
+                _ => {}
+            }
+            options = &options[len..];
+        }
+
+        Some(packet)
+    }
+
+    pub fn info(&self) -> String {
+        let from = |mac: Option<[u8; 6]>| match mac {
+            Some(mac) => format!(" from {}", format_mac(&mac)),
+            None => String::new(),
+        };
+        match &self.message {
+            NdpMessage::RouterSolicitation => {
+                format!("Router Solicitation{}", from(self.source_link_addr))
+            }
+            NdpMessage::RouterAdvertisement { .. } => {
+                format!("Router Advertisement{}", from(self.source_link_addr))
+            }
+            NdpMessage::NeighborSolicitation { target } => format!(
+                "Neighbor Solicitation for {}{}",
+                target,
+                from(self.source_link_addr)
+            ),
+            NdpMessage::NeighborAdvertisement {
+                target,
+                router,
+                solicited,
+                override_flag,
+            } => {
+                let flags: Vec<_> = [
+                    (*router, "rtr"),
+                    (*solicited, "sol"),
+                    (*override_flag, "ovr"),
+                ]
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, name)| *name)
+                .collect();
+                let mut info = format!("Neighbor Advertisement {}", target);
+                if !flags.is_empty() {
+                    info.push_str(&format!(" ({})", flags.join(", ")));
+                }
+                if let Some(mac) = self.target_link_addr {
+                    info.push_str(&format!(" is at {}", format_mac(&mac)));
+                }
+                info
+            }
+            NdpMessage::Redirect {
+                target,
+                destination,
+            } => format!("Redirect to {} for {}", target, destination),
+        }
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        match &self.message {
+            NdpMessage::RouterSolicitation => {}
+            NdpMessage::RouterAdvertisement {
+                cur_hop_limit,
+                managed,
+                other,
+                router_lifetime,
+            } => {
+                fields.insert("icmpv6.nd.ra.cur_hop_limit", *cur_hop_limit);
+                fields.insert("icmpv6.nd.ra.flag.m", *managed);
+                fields.insert("icmpv6.nd.ra.flag.o", *other);
+                fields.insert("icmpv6.nd.ra.router_lifetime", *router_lifetime);
+            }
+            NdpMessage::NeighborSolicitation { target } => {
+                fields.insert("icmpv6.nd.ns.target_address", *target);
+            }
+            NdpMessage::NeighborAdvertisement {
+                target,
+                router,
+                solicited,
+                override_flag,
+            } => {
+                fields.insert("icmpv6.nd.na.target_address", *target);
+                fields.insert("icmpv6.nd.na.flag.r", *router);
+                fields.insert("icmpv6.nd.na.flag.s", *solicited);
+                fields.insert("icmpv6.nd.na.flag.o", *override_flag);
+            }
+            NdpMessage::Redirect {
+                target,
+                destination,
+            } => {
+                fields.insert("icmpv6.nd.rd.target_address", *target);
+                fields.insert("icmpv6.nd.rd.destination_address", *destination);
+            }
+        }
+
+        if let Some(mac) = self.source_link_addr {
+            fields.insert("icmpv6.opt.src_linkaddr", mac);
+        }
+        if let Some(mac) = self.target_link_addr {
+            fields.insert("icmpv6.opt.target_linkaddr", mac);
+        }
+        for (prefix, len) in &self.prefixes {
+            fields.insert("icmpv6.opt.prefix", *prefix);
+            fields.insert("icmpv6.opt.prefix.length", *len);
+        }
+        if let Some(mtu) = self.mtu {
+            fields.insert("icmpv6.opt.mtu", mtu);
+        }
+    }
+
+    /// IP to MAC bindings announced by the message. `source` is the IPv6
+    /// source address of the packet.
+    pub fn bindings(&self, source: IpAddr) -> Vec<(IpAddr, [u8; 6])> {
+        let mut bindings = Vec::new();
+        if let Some(mac) = self.source_link_addr {
+            // Duplicate address detection probes come from ::
+            if !source.is_unspecified() {
+                bindings.push((source, mac));
+            }
+        }
+        if let (NdpMessage::NeighborAdvertisement { target, .. }, Some(mac)) =
+            (&self.message, self.target_link_addr)
+        {
+            bindings.push((IpAddr::V6(*target), mac));
+        }
+        bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+    #[test]
+    fn test_neighbor_solicitation() {
+        let target: Ipv6Addr = "fe80::2".parse().unwrap();
+        let mut icmp = vec![NEIGHBOR_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+        icmp.extend_from_slice(&target.octets());
+        icmp.extend_from_slice(&[OPT_SOURCE_LINK_ADDR, 1]);
+        icmp.extend_from_slice(&MAC);
+
+        let ndp = NdpPacket::parse(&icmp).unwrap();
+        assert_eq!(ndp.message, NdpMessage::NeighborSolicitation { target });
+        assert_eq!(ndp.source_link_addr, Some(MAC));
+        assert_eq!(
+            ndp.info(),
+            "Neighbor Solicitation for fe80::2 from 02:00:00:00:00:01"
+        );
+
+        let source: IpAddr = "fe80::1".parse().unwrap();
+        assert_eq!(ndp.bindings(source), vec![(source, MAC)]);
+        assert!(ndp.bindings(Ipv6Addr::UNSPECIFIED.into()).is_empty());
+    }
+
+    #[test]
+    fn test_router_advertisement_options() {
+        let mut icmp = vec![ROUTER_ADVERTISEMENT, 0, 0, 0];
+        icmp.extend_from_slice(&[64, 0x80, 0x07, 0x08, 0, 0, 0, 0, 0, 0, 0, 0]);
+        icmp.extend_from_slice(&[OPT_MTU, 1, 0, 0, 0, 0, 0x05, 0xdc]);
+        let mut prefix = vec![OPT_PREFIX_INFO, 4, 64, 0xc0];
+        prefix.resize(16, 0);
+        prefix.extend_from_slice(&"2001:db8::".parse::<Ipv6Addr>().unwrap().octets());
+        icmp.extend_from_slice(&prefix);
+
+        let ndp = NdpPacket::parse(&icmp).unwrap();
+        assert_eq!(ndp.mtu, Some(1500));
+        assert_eq!(ndp.prefixes, vec![("2001:db8::".parse().unwrap(), 64)]);
+        match ndp.message {
+            NdpMessage::RouterAdvertisement {
+                managed,
+                router_lifetime,
+                ..
+            } => {
+                assert!(managed);
+                assert_eq!(router_lifetime, 1800);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,567 @@
+use crate::fields::Fields;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Collector ports commonly used for NetFlow, and the IANA port of IPFIX.
+pub const NETFLOW_PORTS: [u16; 3] = [2055, 9995, 9996];
+pub const IPFIX_PORT: u16 = 4739;
+
+const MAX_TEMPLATES: usize = 65536;
+const MAX_EXPORTERS: usize = 65536;
+
+const V5_RECORD_LEN: usize = 48;
+const IPFIX_HEADER_LEN: usize = 16;
+const SET_HEADER_LEN: usize = 4;
+
+const V9_TEMPLATE_SET: u16 = 0;
+const V9_OPTIONS_TEMPLATE_SET: u16 = 1;
+const IPFIX_TEMPLATE_SET: u16 = 2;
+const IPFIX_OPTIONS_TEMPLATE_SET: u16 = 3;
+const FIRST_DATA_SET: u16 = 256;
+
+const ENTERPRISE_BIT: u16 = 0x8000;
+const VARIABLE_LENGTH: u16 = 65535;
+
+// Information elements making up a flow, the same in NetFlow v9
+const OCTET_DELTA_COUNT: u16 = 1;
+const PACKET_DELTA_COUNT: u16 = 2;
+const PROTOCOL: u16 = 4;
+const SOURCE_PORT: u16 = 7;
+const SOURCE_IPV4: u16 = 8;
+const DESTINATION_PORT: u16 = 11;
+const DESTINATION_IPV4: u16 = 12;
+const SOURCE_IPV6: u16 = 27;
+const DESTINATION_IPV6: u16 = 28;
+const OCTET_TOTAL_COUNT: u16 = 85;
+const PACKET_TOTAL_COUNT: u16 = 86;
+
+/// A flow record, with what its template or format includes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Flow {
+    pub src: Option<IpAddr>,
+    pub dst: Option<IpAddr>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    pub protocol: Option<u8>,
+    pub packets: Option<u64>,
+    pub octets: Option<u64>,
+}
+
+impl Flow {
+    fn set(&mut self, element: u16, value: &[u8]) {
+        let number =
+            || (value.len() <= 8).then(|| value.iter().fold(0u64, |n, &b| n << 8 | u64::from(b)));
+        match (element, value.len()) {
+            (SOURCE_IPV4, 4) => self.src = Some(IpAddr::V4(ipv4(value))),
+            (DESTINATION_IPV4, 4) => self.dst = Some(IpAddr::V4(ipv4(value))),
+            (SOURCE_IPV6, 16) => self.src = Some(IpAddr::V6(ipv6(value))),
+            (DESTINATION_IPV6, 16) => self.dst = Some(IpAddr::V6(ipv6(value))),
+            (SOURCE_PORT, _) => self.src_port = number().map(|n| n as u16),
+            (DESTINATION_PORT, _) => self.dst_port = number().map(|n| n as u16),
+            (PROTOCOL, _) => self.protocol = number().map(|n| n as u8),
+            (PACKET_DELTA_COUNT | PACKET_TOTAL_COUNT, _) => self.packets = number(),
+            (OCTET_DELTA_COUNT | OCTET_TOTAL_COUNT, _) => self.octets = number(),
+            _ => {}
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        if let Some(src) = self.src {
+            fields.insert("cflow.srcaddr", src);
+        }
+        if let Some(dst) = self.dst {
+            fields.insert("cflow.dstaddr", dst);
+        }
+        if let Some(port) = self.src_port {
+            fields.insert("cflow.srcport", port);
+        }
+        if let Some(port) = self.dst_port {
+            fields.insert("cflow.dstport", port);
+        }
+        if let Some(protocol) = self.protocol {
+            fields.insert("cflow.protocol", protocol);
+        }
+        if let Some(packets) = self.packets {
+            fields.insert("cflow.packets", packets);
+        }
+        if let Some(octets) = self.octets {
+            fields.insert("cflow.octets", octets);
+        }
+    }
+}
+
+fn ipv4(bytes: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])
+}
+
+fn ipv6(bytes: &[u8]) -> Ipv6Addr {
+    Ipv6Addr::from(<[u8; 16]>::try_from(bytes).unwrap())
+}
+
+/// The fields of a template: element ID and length, with enterprise
+/// elements set apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Template {
+    fields: Vec<(u16, u16, bool)>,
+    /// Records of an options template describe the exporter, not flows.
+    options: bool,
+}
+
+/// A NetFlow v5 or v9 packet or an IPFIX message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowPacket {
+    /// 10 for IPFIX.
+    pub version: u16,
+    pub sequence: u32,
+    /// Source ID of NetFlow v9, observation domain of IPFIX, or engine
+    /// type and ID of NetFlow v5.
+    pub domain: u32,
+    /// IDs of the templates the packet defines.
+    pub templates: Vec<u16>,
+    pub flows: Vec<Flow>,
+    pub options_records: usize,
+    /// Data sets whose template hasn't been seen.
+    pub unknown_templates: Vec<u16>,
+    /// The sequence number expected from the exporter, when the packet
+    /// doesn't carry it.
+    pub sequence_gap: Option<u32>,
+}
+
+impl FlowPacket {
+    fn empty(version: u16, sequence: u32, domain: u32) -> Self {
+        FlowPacket {
+            version,
+            sequence,
+            domain,
+            templates: Vec::new(),
+            flows: Vec::new(),
+            options_records: 0,
+            unknown_templates: Vec::new(),
+            sequence_gap: None,
+        }
+    }
+
+    pub fn protocol(&self) -> &'static str {
+        if self.version == 10 {
+            "IPFIX"
+        } else {
+            "NetFlow"
+        }
+    }
+
+    pub fn info(&self) -> String {
+        let mut parts = vec![match self.version {
+            10 => "IPFIX".to_string(),
+            version => format!("NetFlow v{}", version),
+        }];
+        let count =
+            |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+        if !self.templates.is_empty() {
+            parts.push(count(self.templates.len(), "template"));
+        }
+        if !self.flows.is_empty() {
+            parts.push(count(self.flows.len(), "flow"));
+        }
+        if self.options_records > 0 {
+            parts.push(count(self.options_records, "options record"));
+        }
+        if !self.unknown_templates.is_empty() {
+            parts.push(count(
+                self.unknown_templates.len(),
+                "set with unknown template",
+            ));
+        }
+        parts.join(", ")
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("cflow.version", self.version);
+        fields.insert("cflow.sequence", self.sequence);
+        match self.version {
+            5 => {}
+            9 => fields.insert("cflow.source_id", self.domain),
+            _ => fields.insert("cflow.od_id", self.domain),
+        }
+        for template in &self.templates {
+            fields.insert("cflow.template_id", *template);
+        }
+        for flow in &self.flows {
+            flow.insert_fields(fields);
+        }
+    }
+
+    /// Warning about records the collector can't have received.
+    pub fn error(&self) -> Option<String> {
+        self.sequence_gap.map(|expected| {
+            format!(
+                "sequence number {} where {} was expected",
+                self.sequence, expected
+            )
+        })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(bytes)
+    }
+}
+
+/// Keys templates and sequence numbers by exporter, version and domain.
+type Domain = (IpAddr, u16, u32);
+
+/// Decodes NetFlow and IPFIX export packets, keeping the templates of
+/// each exporter to decode the data sets that follow them.
+#[derive(Debug, Default)]
+pub struct FlowCollector {
+    templates: HashMap<(Domain, u16), Template>,
+    /// The next sequence number expected from each exporter.
+    sequences: HashMap<Domain, u32>,
+}
+
+impl FlowCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn process(&mut self, exporter: IpAddr, payload: &[u8]) -> Option<FlowPacket> {
+        let mut reader = Reader { data: payload };
+        let version = reader.u16()?;
+        let (mut packet, next_sequence) = match version {
+            5 => Self::parse_v5(reader)?,
+            9 => self.parse_v9(exporter, reader)?,
+            10 => self.parse_ipfix(exporter, payload)?,
+            _ => return None,
+        };
+
+        let key = (exporter, version, packet.domain);
+        if let Some(&expected) = self.sequences.get(&key) {
+            if expected != packet.sequence {
+                packet.sequence_gap = Some(expected);
+            }
+        }
+        if self.sequences.len() >= MAX_EXPORTERS && !self.sequences.contains_key(&key) {
+            self.sequences.clear();
+        }
+        self.sequences.insert(key, next_sequence);
+        Some(packet)
+    }
+
+    /// Fixed-format records; the sequence counts flows.
+    fn parse_v5(mut reader: Reader) -> Option<(FlowPacket, u32)> {
+        let count = reader.u16()?;
+        // Uptime, seconds and nanoseconds
+        reader.bytes(12)?;
+        let sequence = reader.u32()?;
+        let engine_type = reader.u8()?;
+        let engine_id = reader.u8()?;
+        reader.u16()?;
+        let mut flows = Vec::new();
+        for _ in 0..count {
+            let Some(record) = reader.bytes(V5_RECORD_LEN) else {
+                break;
+            };
+            flows.push(Flow {
+                src: Some(IpAddr::V4(ipv4(&record[0..4]))),
+                dst: Some(IpAddr::V4(ipv4(&record[4..8]))),
+                src_port: Some(u16::from_be_bytes([record[32], record[33]])),
+                dst_port: Some(u16::from_be_bytes([record[34], record[35]])),
+                protocol: Some(record[38]),
+                packets: Some(u64::from(u32::from_be_bytes(
+                    record[16..20].try_into().unwrap(),
+                ))),
+                octets: Some(u64::from(u32::from_be_bytes(
+                    record[20..24].try_into().unwrap(),
+                ))),
+            });
+        }
+        let domain = u32::from(engine_type) << 8 | u32::from(engine_id);
+        let packet = FlowPacket {
+            flows,
+            ..FlowPacket::empty(5, sequence, domain)
+        };
+        Some((packet, sequence.wrapping_add(u32::from(count))))
+    }
+
+    /// Flowsets after the header; the sequence counts packets.
+    fn parse_v9(&mut self, exporter: IpAddr, mut reader: Reader) -> Option<(FlowPacket, u32)> {
+        // Record count, uptime and seconds
+        reader.bytes(10)?;
+        let sequence = reader.u32()?;
+        let domain = reader.u32()?;
+        let mut packet = FlowPacket::empty(9, sequence, domain);
+        self.parse_sets(exporter, &mut packet, reader.data);
+        Some((packet, sequence.wrapping_add(1)))
+    }
+
+    /// Sets up to the message length; the sequence counts data records.
+    fn parse_ipfix(&mut self, exporter: IpAddr, payload: &[u8]) -> Option<(FlowPacket, u32)> {
+        let mut reader = Reader {
+            data: payload.get(2..)?,
+        };
+        let length = usize::from(reader.u16()?);
+        let message = payload.get(IPFIX_HEADER_LEN..length)?;
+        // Export time
+        reader.u32()?;
+        let sequence = reader.u32()?;
+        let domain = reader.u32()?;
+        let mut packet = FlowPacket::empty(10, sequence, domain);
+        self.parse_sets(exporter, &mut packet, message);
+        let records = packet.flows.len() + packet.options_records;
+        Some((packet, sequence.wrapping_add(records as u32)))
+    }
+
+    fn parse_sets(&mut self, exporter: IpAddr, packet: &mut FlowPacket, data: &[u8]) {
+        let domain = (exporter, packet.version, packet.domain);
+        let mut reader = Reader { data };
+        while let (Some(id), Some(length)) = (reader.u16(), reader.u16()) {
+            let Some(body) = usize::from(length)
+                .checked_sub(SET_HEADER_LEN)
+                .and_then(|len| reader.bytes(len))
+            else {
+                break;
+            };
+            match id {
+                V9_TEMPLATE_SET | V9_OPTIONS_TEMPLATE_SET if packet.version == 9 => {
+                    self.templates_v9(domain, packet, id == V9_OPTIONS_TEMPLATE_SET, body);
+                }
+                IPFIX_TEMPLATE_SET | IPFIX_OPTIONS_TEMPLATE_SET if packet.version == 10 => {
+                    self.templates_ipfix(domain, packet, id == IPFIX_OPTIONS_TEMPLATE_SET, body);
+                }
+                FIRST_DATA_SET.. => match self.templates.get(&(domain, id)) {
+                    Some(template) => decode_records(template, packet, body),
+                    None => packet.unknown_templates.push(id),
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn define(&mut self, domain: Domain, id: u16, template: Template) {
+        if self.templates.len() >= MAX_TEMPLATES && !self.templates.contains_key(&(domain, id)) {
+            self.templates.clear();
+        }
+        self.templates.insert((domain, id), template);
+    }
+
+    fn templates_v9(
+        &mut self,
+        domain: Domain,
+        packet: &mut FlowPacket,
+        options: bool,
+        body: &[u8],
+    ) {
+        let mut reader = Reader { data: body };
+        // Sets are padded to four bytes
+        while reader.data.len() >= SET_HEADER_LEN {
+            let (Some(id), Some(first)) = (reader.u16(), reader.u16()) else {
+                break;
+            };
+            // Options templates give the scope and option lengths in bytes
+            let count = if options {
+                let Some(second) = reader.u16() else {
+                    break;
+                };
+                (usize::from(first) + usize::from(second)) / 4
+            } else {
+                usize::from(first)
+            };
+            let Some(fields) = (0..count)
+                .map(|_| Some((reader.u16()?, reader.u16()?, false)))
+                .collect::<Option<Vec<_>>>()
+            else {
+                break;
+            };
+            if id < FIRST_DATA_SET {
+                break;
+            }
+            packet.templates.push(id);
+            self.define(domain, id, Template { fields, options });
+        }
+    }
+
+    fn templates_ipfix(
+        &mut self,
+        domain: Domain,
+        packet: &mut FlowPacket,
+        options: bool,
+        body: &[u8],
+    ) {
+        let mut reader = Reader { data: body };
+        while reader.data.len() >= SET_HEADER_LEN {
+            let (Some(id), Some(count)) = (reader.u16(), reader.u16()) else {
+                break;
+            };
+            if options && count > 0 && reader.u16().is_none() {
+                break;
+            }
+            let Some(fields) = (0..count)
+                .map(|_| {
+                    let element = reader.u16()?;
+                    let length = reader.u16()?;
+                    let enterprise = element & ENTERPRISE_BIT != 0;
+                    if enterprise {
+                        reader.u32()?;
+                    }
+                    Some((element & !ENTERPRISE_BIT, length, enterprise))
+                })
+                .collect::<Option<Vec<_>>>()
+            else {
+                break;
+            };
+            if id < FIRST_DATA_SET {
+                break;
+            }
+            if fields.is_empty() {
+                // A template withdrawal
+                self.templates.remove(&(domain, id));
+                continue;
+            }
+            packet.templates.push(id);
+            self.define(domain, id, Template { fields, options });
+        }
+    }
+}
+
+/// Decodes the records of a data set up to its padding.
+fn decode_records(template: &Template, packet: &mut FlowPacket, body: &[u8]) {
+    let mut reader = Reader { data: body };
+    let min_len: usize = template
+        .fields
+        .iter()
+        .map(|&(_, length, _)| {
+            if length == VARIABLE_LENGTH {
+                1
+            } else {
+                usize::from(length)
+            }
+        })
+        .sum();
+    if min_len == 0 {
+        return;
+    }
+    while reader.data.len() >= min_len {
+        let mut flow = Flow::default();
+        for &(element, length, enterprise) in &template.fields {
+            let length = if length == VARIABLE_LENGTH {
+                match reader.u8() {
+                    Some(255) => reader.u16().map(usize::from),
+                    length => length.map(usize::from),
+                }
+            } else {
+                Some(usize::from(length))
+            };
+            let Some(value) = length.and_then(|length| reader.bytes(length)) else {
+                return;
+            };
+            if !enterprise {
+                flow.set(element, value);
+            }
+        }
+        if template.options {
+            packet.options_records += 1;
+        } else {
+            packet.flows.push(flow);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORTER: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 254));
+
+    fn v9_header(sequence: u32) -> Vec<u8> {
+        let mut data = vec![0, 9, 0, 2];
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&sequence.to_be_bytes());
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data
+    }
+
+    fn set(id: u16, body: &[u8]) -> Vec<u8> {
+        let mut data = id.to_be_bytes().to_vec();
+        data.extend_from_slice(&(body.len() as u16 + 4).to_be_bytes());
+        data.extend_from_slice(body);
+        data
+    }
+
+    // Template 256: source and destination address, protocol and packets
+    const TEMPLATE: [u8; 20] = [1, 0, 0, 4, 0, 8, 0, 4, 0, 12, 0, 4, 0, 4, 0, 1, 0, 2, 0, 4];
+    const RECORD: [u8; 13] = [10, 0, 0, 1, 10, 0, 0, 2, 6, 0, 0, 0, 42];
+
+    #[test]
+    fn test_v9_template_and_data() {
+        let mut collector = FlowCollector::new();
+
+        // Data before its template can't be decoded
+        let mut data = v9_header(1);
+        data.extend(set(256, &RECORD));
+        let packet = collector.process(EXPORTER, &data).unwrap();
+        assert_eq!(packet.info(), "NetFlow v9, 1 set with unknown template");
+
+        let mut data = v9_header(2);
+        data.extend(set(V9_TEMPLATE_SET, &TEMPLATE));
+        let mut records = RECORD.to_vec();
+        records.extend_from_slice(&RECORD);
+        records.extend_from_slice(&[0, 0]);
+        data.extend(set(256, &records));
+        let packet = collector.process(EXPORTER, &data).unwrap();
+        assert_eq!(packet.info(), "NetFlow v9, 1 template, 2 flows");
+        assert_eq!(packet.flows[0].src, Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(packet.flows[0].packets, Some(42));
+        assert!(packet.error().is_none());
+
+        // Two export packets were lost
+        let packet = collector.process(EXPORTER, &v9_header(5)).unwrap();
+        assert_eq!(
+            packet.error().unwrap(),
+            "sequence number 5 where 3 was expected"
+        );
+    }
+
+    #[test]
+    fn test_ipfix_variable_length() {
+        let mut collector = FlowCollector::new();
+        // Template 300: protocol, then an enterprise element of variable length
+        let template = [
+            1, 44, 0, 2, 0, 4, 0, 1, 0x80, 1, 0xff, 0xff, 0, 0, 0x7e, 0x6b,
+        ];
+        let mut sets = set(IPFIX_TEMPLATE_SET, &template);
+        sets.extend(set(300, &[17, 3, b'a', b'b', b'c', 6, 0]));
+        let mut data = vec![0, 10];
+        data.extend_from_slice(&((IPFIX_HEADER_LEN + sets.len()) as u16).to_be_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&100u32.to_be_bytes());
+        data.extend_from_slice(&7u32.to_be_bytes());
+        data.extend(sets);
+
+        let packet = collector.process(EXPORTER, &data).unwrap();
+        assert_eq!(packet.info(), "IPFIX, 1 template, 2 flows");
+        assert_eq!(packet.flows[1].protocol, Some(6));
+        assert_eq!(collector.sequences[&(EXPORTER, 10, 7)], 102);
+    }
+}
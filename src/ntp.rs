@@ -0,0 +1,216 @@
+use crate::fields::Fields;
+use std::net::Ipv4Addr;
+
+pub const NTP_PORT: u16 = 123;
+
+const HEADER_LEN: usize = 48;
+// Seconds from the NTP epoch in 1900 to the Unix epoch
+const UNIX_OFFSET: u64 = 2_208_988_800;
+
+const SERVER: u8 = 4;
+const CONTROL: u8 = 6;
+const UNSYNCHRONIZED: u8 = 3;
+
+fn mode_name(mode: u8) -> &'static str {
+    match mode {
+        1 => "symmetric active",
+        2 => "symmetric passive",
+        3 => "client",
+        SERVER => "server",
+        5 => "broadcast",
+        CONTROL => "control",
+        7 => "private",
+        _ => "reserved",
+    }
+}
+
+/// Converts a 64-bit NTP timestamp to seconds since the Unix epoch.
+fn unix_seconds(timestamp: u64) -> f64 {
+    let seconds = (timestamp >> 32) as f64 - UNIX_OFFSET as f64;
+    seconds + (timestamp & 0xffff_ffff) as f64 / 4_294_967_296.0
+}
+
+/// A 32-bit NTP short format value in seconds.
+fn short_seconds(value: u32) -> f64 {
+    f64::from(value) / 65536.0
+}
+
+/// An NTP packet. Only the flags of control and private packets are
+/// decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NtpPacket {
+    pub leap: u8,
+    pub version: u8,
+    pub mode: u8,
+    pub stratum: u8,
+    /// Poll interval and precision as powers of two.
+    pub poll: i8,
+    pub precision: i8,
+    /// Seconds.
+    pub root_delay: f64,
+    pub root_dispersion: f64,
+    pub reference_id: [u8; 4],
+    pub reference: u64,
+    pub origin: u64,
+    pub receive: u64,
+    pub transmit: u64,
+}
+
+impl NtpPacket {
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let flags = *payload.first()?;
+        let mut packet = NtpPacket {
+            leap: flags >> 6,
+            version: (flags >> 3) & 0x07,
+            mode: flags & 0x07,
+            stratum: 0,
+            poll: 0,
+            precision: 0,
+            root_delay: 0.0,
+            root_dispersion: 0.0,
+            reference_id: [0; 4],
+            reference: 0,
+            origin: 0,
+            receive: 0,
+            transmit: 0,
+        };
+        if !(1..=4).contains(&packet.version) {
+            return None;
+        }
+        if packet.mode >= CONTROL {
+            return Some(packet);
+        }
+        let header = payload.get(..HEADER_LEN)?;
+        let u32_at = |i: usize| u32::from_be_bytes(header[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_be_bytes(header[i..i + 8].try_into().unwrap());
+        packet.stratum = header[1];
+        packet.poll = header[2] as i8;
+        packet.precision = header[3] as i8;
+        packet.root_delay = short_seconds(u32_at(4));
+        packet.root_dispersion = short_seconds(u32_at(8));
+        packet.reference_id = header[12..16].try_into().unwrap();
+        packet.reference = u64_at(16);
+        packet.origin = u64_at(24);
+        packet.receive = u64_at(32);
+        packet.transmit = u64_at(40);
+        Some(packet)
+    }
+
+    /// The reference ID: a source such as GPS for primary servers, the
+    /// code of a kiss-o'-death, or the address of the upstream server.
+    pub fn reference_id(&self) -> String {
+        if self.stratum <= 1 {
+            let code = self.reference_id.iter().take_while(|&&b| b != 0);
+            code.map(|&b| char::from(b)).collect()
+        } else {
+            Ipv4Addr::from(self.reference_id).to_string()
+        }
+    }
+
+    /// The clock offset and round-trip delay in seconds of a server
+    /// response received at `arrival`, seconds since the Unix epoch.
+    /// Capture time stands in for the client's receive time, so the
+    /// offset is the client's only when captured on or near it.
+    pub fn offset_and_delay(&self, arrival: f64) -> Option<(f64, f64)> {
+        if self.mode != SERVER || self.origin == 0 || self.receive == 0 || self.transmit == 0 {
+            return None;
+        }
+        let (t1, t2, t3) = (
+            unix_seconds(self.origin),
+            unix_seconds(self.receive),
+            unix_seconds(self.transmit),
+        );
+        let offset = ((t2 - t1) + (t3 - arrival)) / 2.0;
+        let delay = (arrival - t1) - (t3 - t2);
+        Some((offset, delay))
+    }
+
+    /// The code of a kiss-o'-death packet, a server asking its client to
+    /// back off or go away.
+    pub fn kiss_code(&self) -> Option<String> {
+        (self.mode == SERVER && self.stratum == 0).then(|| self.reference_id())
+    }
+
+    pub fn info(&self) -> String {
+        let mut info = format!("NTP Version {}, {}", self.version, mode_name(self.mode));
+        if self.mode == SERVER && self.stratum != 0 {
+            info.push_str(&format!(", stratum {}", self.stratum));
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("ntp.flags.li", self.leap);
+        fields.insert("ntp.flags.vn", self.version);
+        fields.insert("ntp.flags.mode", self.mode);
+        if self.mode >= CONTROL {
+            return;
+        }
+        fields.insert("ntp.stratum", self.stratum);
+        fields.insert("ntp.ppoll", self.poll.max(0) as u8);
+        fields.insert("ntp.rootdelay", self.root_delay);
+        fields.insert("ntp.rootdispersion", self.root_dispersion);
+        fields.insert("ntp.refid", self.reference_id());
+    }
+
+    /// A server that doesn't know the time itself.
+    pub fn unsynchronized(&self) -> bool {
+        self.mode == SERVER && self.leap == UNSYNCHRONIZED && self.stratum != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(unix: f64) -> u64 {
+        let seconds = unix.floor();
+        let fraction = ((unix - seconds) * 4_294_967_296.0) as u64;
+        (seconds as u64 + UNIX_OFFSET) << 32 | fraction
+    }
+
+    fn packet(flags: u8, stratum: u8, reference_id: [u8; 4], times: [f64; 3]) -> Vec<u8> {
+        let mut data = vec![flags, stratum, 6, 0xe9];
+        data.extend_from_slice(&0x0000_0800u32.to_be_bytes());
+        data.extend_from_slice(&0x0000_0400u32.to_be_bytes());
+        data.extend_from_slice(&reference_id);
+        data.extend_from_slice(&timestamp(times[2] - 60.0).to_be_bytes());
+        for time in times {
+            let value = if time == 0.0 { 0 } else { timestamp(time) };
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_server_response_offset() {
+        // The client's clock is 0.5 s behind, with 20 ms of network delay
+        let request_sent = 1_700_000_000.0;
+        let data = packet(
+            0x24,
+            2,
+            [192, 0, 2, 1],
+            [request_sent, request_sent + 0.51, request_sent + 0.511],
+        );
+        let ntp = NtpPacket::parse(&data).unwrap();
+        assert_eq!(ntp.info(), "NTP Version 4, server, stratum 2");
+        assert_eq!(ntp.reference_id(), "192.0.2.1");
+        assert_eq!(ntp.root_delay, 0.03125);
+
+        let (offset, delay) = ntp.offset_and_delay(request_sent + 0.021).unwrap();
+        assert!((offset - 0.5).abs() < 1e-6, "offset {}", offset);
+        assert!((delay - 0.020).abs() < 1e-6, "delay {}", delay);
+        assert!(ntp.kiss_code().is_none());
+    }
+
+    #[test]
+    fn test_client_and_kiss_of_death() {
+        let client = NtpPacket::parse(&packet(0xe3, 0, [0; 4], [0.0, 0.0, 1e9])).unwrap();
+        assert_eq!(client.info(), "NTP Version 4, client");
+        assert!(client.offset_and_delay(1e9).is_none());
+
+        let kiss = NtpPacket::parse(&packet(0xe4, 0, *b"RATE", [1e9, 1e9, 1e9])).unwrap();
+        assert_eq!(kiss.kiss_code().as_deref(), Some("RATE"));
+        assert!(!kiss.unsynchronized());
+    }
+}
@@ -0,0 +1,387 @@
+use crate::fields::Fields;
+use std::net::Ipv4Addr;
+
+pub const IP_PROTOCOL: u8 = 89;
+
+const V2_HEADER_LEN: usize = 24;
+const V3_HEADER_LEN: usize = 16;
+const LSA_HEADER_LEN: usize = 20;
+const LS_REQUEST_LEN: usize = 12;
+// The age of an LSA being flushed from the domain
+const MAX_AGE: u16 = 3600;
+const DO_NOT_AGE: u16 = 0x8000;
+
+const HELLO: u8 = 1;
+const DB_DESCRIPTION: u8 = 2;
+const LS_REQUEST: u8 = 3;
+const LS_UPDATE: u8 = 4;
+const LS_ACKNOWLEDGE: u8 = 5;
+
+fn packet_type_name(packet_type: u8) -> &'static str {
+    match packet_type {
+        HELLO => "Hello Packet",
+        DB_DESCRIPTION => "DB Description",
+        LS_REQUEST => "LS Request",
+        LS_UPDATE => "LS Update",
+        LS_ACKNOWLEDGE => "LS Acknowledge",
+        _ => "Unknown",
+    }
+}
+
+fn lsa_type_name(version: u8, lsa_type: u16) -> &'static str {
+    match (version, lsa_type) {
+        (2, 1) => "Router-LSA",
+        (2, 2) => "Network-LSA",
+        (2, 3) => "Summary-LSA (IP network)",
+        (2, 4) => "Summary-LSA (ASBR)",
+        (2, 5) => "AS-External-LSA",
+        (2, 7) => "NSSA-LSA",
+        (2, 9..=11) => "Opaque-LSA",
+        (3, 0x2001) => "Router-LSA",
+        (3, 0x2002) => "Network-LSA",
+        (3, 0x2003) => "Inter-Area-Prefix-LSA",
+        (3, 0x2004) => "Inter-Area-Router-LSA",
+        (3, 0x4005) => "AS-External-LSA",
+        (3, 0x2007) => "NSSA-LSA",
+        (3, 0x0008) => "Link-LSA",
+        (3, 0x2009) => "Intra-Area-Prefix-LSA",
+        _ => "Unknown-LSA",
+    }
+}
+
+/// The header identifying an LSA and its instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LsaHeader {
+    pub age: u16,
+    pub lsa_type: u16,
+    pub id: Ipv4Addr,
+    pub advertising_router: Ipv4Addr,
+    pub sequence: u32,
+    pub length: u16,
+}
+
+impl LsaHeader {
+    fn parse(version: u8, data: &[u8]) -> Option<Self> {
+        let data = data.get(..LSA_HEADER_LEN)?;
+        let u32_at = |i: usize| u32::from_be_bytes(data[i..i + 4].try_into().unwrap());
+        Some(LsaHeader {
+            age: u16::from_be_bytes([data[0], data[1]]),
+            // OSPFv2 has an options byte before a one-byte type
+            lsa_type: match version {
+                2 => u16::from(data[3]),
+                _ => u16::from_be_bytes([data[2], data[3]]),
+            },
+            id: Ipv4Addr::from(u32_at(4)),
+            advertising_router: Ipv4Addr::from(u32_at(8)),
+            sequence: u32_at(12),
+            length: u16::from_be_bytes([data[18], data[19]]),
+        })
+    }
+
+    fn is_flushed(&self) -> bool {
+        self.age & !DO_NOT_AGE >= MAX_AGE
+    }
+}
+
+/// The parameters of a Hello that neighbors must agree on, and the
+/// neighbors the sender has heard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hello {
+    /// The network mask of OSPFv2; OSPFv3 hellos carry an interface ID.
+    pub network_mask: Option<Ipv4Addr>,
+    pub hello_interval: u16,
+    pub dead_interval: u32,
+    pub priority: u8,
+    pub designated_router: Ipv4Addr,
+    pub backup_designated_router: Ipv4Addr,
+    pub neighbors: Vec<Ipv4Addr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    Hello(Hello),
+    DbDescription {
+        mtu: u16,
+        flags: u8,
+        sequence: u32,
+        lsas: Vec<LsaHeader>,
+    },
+    LsRequest(usize),
+    /// LS Update and LS Acknowledge.
+    Lsas(Vec<LsaHeader>),
+    Unknown,
+}
+
+/// An OSPFv2 or OSPFv3 packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OspfPacket {
+    pub version: u8,
+    pub packet_type: u8,
+    pub router_id: Ipv4Addr,
+    pub area_id: Ipv4Addr,
+    pub body: Body,
+}
+
+fn ipv4_at(data: &[u8], i: usize) -> Option<Ipv4Addr> {
+    let bytes: [u8; 4] = data.get(i..i + 4)?.try_into().ok()?;
+    Some(Ipv4Addr::from(bytes))
+}
+
+fn ipv4_list(data: &[u8]) -> Vec<Ipv4Addr> {
+    data.chunks_exact(4)
+        .map(|chunk| Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3]))
+        .collect()
+}
+
+fn lsa_headers(version: u8, data: &[u8]) -> Vec<LsaHeader> {
+    data.chunks_exact(LSA_HEADER_LEN)
+        .filter_map(|chunk| LsaHeader::parse(version, chunk))
+        .collect()
+}
+
+impl OspfPacket {
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let version = *payload.first()?;
+        let header_len = match version {
+            2 => V2_HEADER_LEN,
+            3 => V3_HEADER_LEN,
+            _ => return None,
+        };
+        let length = usize::from(u16::from_be_bytes([*payload.get(2)?, *payload.get(3)?]));
+        let packet = payload.get(..length)?;
+        let body = packet.get(header_len..)?;
+        let packet_type = packet[1];
+
+        let body = match (packet_type, version) {
+            (HELLO, 2) => Body::Hello(Hello {
+                network_mask: ipv4_at(body, 0),
+                hello_interval: u16::from_be_bytes([*body.get(4)?, *body.get(5)?]),
+                priority: *body.get(7)?,
+                dead_interval: u32::from_be_bytes(body.get(8..12)?.try_into().ok()?),
+                designated_router: ipv4_at(body, 12)?,
+                backup_designated_router: ipv4_at(body, 16)?,
+                neighbors: ipv4_list(body.get(20..)?),
+            }),
+            (HELLO, _) => Body::Hello(Hello {
+                network_mask: None,
+                priority: *body.get(4)?,
+                hello_interval: u16::from_be_bytes([*body.get(8)?, *body.get(9)?]),
+                dead_interval: u32::from(u16::from_be_bytes([*body.get(10)?, *body.get(11)?])),
+                designated_router: ipv4_at(body, 12)?,
+                backup_designated_router: ipv4_at(body, 16)?,
+                neighbors: ipv4_list(body.get(20..)?),
+            }),
+            (DB_DESCRIPTION, 2) => Body::DbDescription {
+                mtu: u16::from_be_bytes([*body.first()?, *body.get(1)?]),
+                flags: *body.get(3)?,
+                sequence: u32::from_be_bytes(body.get(4..8)?.try_into().ok()?),
+                lsas: lsa_headers(version, body.get(8..)?),
+            },
+            (DB_DESCRIPTION, _) => Body::DbDescription {
+                mtu: u16::from_be_bytes([*body.get(4)?, *body.get(5)?]),
+                flags: *body.get(7)?,
+                sequence: u32::from_be_bytes(body.get(8..12)?.try_into().ok()?),
+                lsas: lsa_headers(version, body.get(12..)?),
+            },
+            (LS_REQUEST, _) => Body::LsRequest(body.len() / LS_REQUEST_LEN),
+            (LS_UPDATE, _) => {
+                let count = u32::from_be_bytes(body.get(..4)?.try_into().ok()?);
+                let mut lsas = Vec::new();
+                let mut rest = &body[4..];
+                for _ in 0..count {
+                    let Some(lsa) = LsaHeader::parse(version, rest) else {
+                        break;
+                    };
+                    let Some(next) = rest.get(usize::from(lsa.length).max(LSA_HEADER_LEN)..) else {
+                        break;
+                    };
+                    rest = next;
+                    lsas.push(lsa);
+                }
+                Body::Lsas(lsas)
+            }
+            (LS_ACKNOWLEDGE, _) => Body::Lsas(lsa_headers(version, body)),
+            _ => Body::Unknown,
+        };
+
+        Some(OspfPacket {
+            version,
+            packet_type,
+            router_id: ipv4_at(packet, 4)?,
+            area_id: ipv4_at(packet, 8)?,
+            body,
+        })
+    }
+
+    pub fn protocol(&self) -> &'static str {
+        if self.version == 3 {
+            "OSPFv3"
+        } else {
+            "OSPF"
+        }
+    }
+
+    pub fn info(&self) -> String {
+        let mut info = packet_type_name(self.packet_type).to_string();
+        match &self.body {
+            Body::Hello(hello) => info.push_str(&format!(
+                ", router {}, area {}, {} neighbor{}",
+                self.router_id,
+                self.area_id,
+                hello.neighbors.len(),
+                if hello.neighbors.len() == 1 { "" } else { "s" }
+            )),
+            Body::DbDescription {
+                flags,
+                sequence,
+                lsas,
+                ..
+            } => {
+                let bits: Vec<_> = [(0x04, "I"), (0x02, "M"), (0x01, "MS")]
+                    .iter()
+                    .filter(|(bit, _)| flags & bit != 0)
+                    .map(|(_, name)| *name)
+                    .collect();
+                info.push_str(&format!(", seq {}", sequence));
+                if !bits.is_empty() {
+                    info.push_str(&format!(" [{}]", bits.join(", ")));
+                }
+                if !lsas.is_empty() {
+                    info.push_str(&format!(", {} LSA headers", lsas.len()));
+                }
+            }
+            Body::LsRequest(count) => info.push_str(&format!(", {} requested", count)),
+            Body::Lsas(lsas) => {
+                let names: Vec<_> = lsas
+                    .iter()
+                    .map(|lsa| format!("{} {}", lsa_type_name(self.version, lsa.lsa_type), lsa.id))
+                    .collect();
+                info.push_str(&format!(", {}", names.join(", ")));
+            }
+            Body::Unknown => {}
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("ospf.version", self.version);
+        fields.insert("ospf.msg", self.packet_type);
+        fields.insert("ospf.srcrouter", self.router_id);
+        fields.insert("ospf.area_id", self.area_id);
+        match &self.body {
+            Body::Hello(hello) => {
+                if let Some(mask) = hello.network_mask {
+                    fields.insert("ospf.hello.network_mask", mask);
+                }
+                fields.insert("ospf.hello.hello_interval", hello.hello_interval);
+                fields.insert("ospf.hello.router_dead_interval", hello.dead_interval);
+                fields.insert("ospf.hello.router_priority", hello.priority);
+                fields.insert("ospf.hello.designated_router", hello.designated_router);
+                fields.insert(
+                    "ospf.hello.backup_designated_router",
+                    hello.backup_designated_router,
+                );
+                for neighbor in &hello.neighbors {
+                    fields.insert("ospf.hello.active_neighbor", *neighbor);
+                }
+            }
+            Body::DbDescription {
+                mtu,
+                sequence,
+                lsas,
+                ..
+            } => {
+                fields.insert("ospf.db.interface_mtu", *mtu);
+                fields.insert("ospf.db.dd_sequence", *sequence);
+                self.insert_lsa_fields(lsas, fields);
+            }
+            Body::Lsas(lsas) => self.insert_lsa_fields(lsas, fields),
+            Body::LsRequest(_) | Body::Unknown => {}
+        }
+    }
+
+    fn insert_lsa_fields(&self, lsas: &[LsaHeader], fields: &mut Fields) {
+        for lsa in lsas {
+            fields.insert("ospf.lsa", lsa_type_name(self.version, lsa.lsa_type));
+            fields.insert("ospf.lsa.id", lsa.id);
+            fields.insert("ospf.advrouter", lsa.advertising_router);
+            fields.insert("ospf.lsa.seqnum", lsa.sequence);
+            fields.insert("ospf.lsa.age", lsa.age & !DO_NOT_AGE);
+        }
+    }
+
+    /// LSAs an update flushes from the domain by setting them to MaxAge.
+    pub fn flushed(&self) -> Vec<&LsaHeader> {
+        match (&self.body, self.packet_type) {
+            (Body::Lsas(lsas), LS_UPDATE) => lsas.iter().filter(|lsa| lsa.is_flushed()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn flush_note(&self) -> Option<String> {
+        let flushed: Vec<_> = self
+            .flushed()
+            .iter()
+            .map(|lsa| {
+                format!(
+                    "{} {} from {}",
+                    lsa_type_name(self.version, lsa.lsa_type),
+                    lsa.id,
+                    lsa.advertising_router
+                )
+            })
+            .collect();
+        (!flushed.is_empty()).then(|| format!("LSA flushed (MaxAge): {}", flushed.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(version: u8, packet_type: u8, body: &[u8]) -> Vec<u8> {
+        let header_len = if version == 2 {
+            V2_HEADER_LEN
+        } else {
+            V3_HEADER_LEN
+        };
+        let mut data = vec![version, packet_type];
+        data.extend_from_slice(&((header_len + body.len()) as u16).to_be_bytes());
+        data.extend_from_slice(&[1, 1, 1, 1, 0, 0, 0, 0]);
+        data.resize(header_len, 0);
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn test_v2_hello() {
+        let mut body = vec![255, 255, 255, 0, 0, 10, 0x02, 1, 0, 0, 0, 40];
+        body.extend_from_slice(&[10, 0, 0, 1, 0, 0, 0, 0, 2, 2, 2, 2]);
+        let packet = OspfPacket::parse(&header(2, HELLO, &body)).unwrap();
+        assert_eq!(
+            packet.info(),
+            "Hello Packet, router 1.1.1.1, area 0.0.0.0, 1 neighbor"
+        );
+        let mut fields = Fields::new();
+        packet.insert_fields(&mut fields);
+        assert_eq!(
+            fields.get("ospf.hello.router_dead_interval"),
+            Some(&40u32.into())
+        );
+    }
+
+    #[test]
+    fn test_v3_update_flushing_lsa() {
+        let mut lsa = vec![0x0e, 0x10, 0x20, 0x01, 0, 0, 0, 0, 1, 1, 1, 1];
+        lsa.extend_from_slice(&[0x80, 0, 0, 2, 0, 0, 0, 24, 0, 0, 0, 0]);
+        let mut body = vec![0, 0, 0, 1];
+        body.extend_from_slice(&lsa);
+        let packet = OspfPacket::parse(&header(3, LS_UPDATE, &body)).unwrap();
+        assert_eq!(packet.protocol(), "OSPFv3");
+        assert_eq!(packet.info(), "LS Update, Router-LSA 0.0.0.0");
+        assert_eq!(
+            packet.flush_note().unwrap(),
+            "LSA flushed (MaxAge): Router-LSA 0.0.0.0 from 1.1.1.1"
+        );
+    }
+}
@@ -0,0 +1,602 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionState, ConnectionTable, Exchange, PendingQueries};
+use crate::reassembly::{MessageBuffer, StreamData};
+use chrono::{DateTime, Utc};
+use std::net::IpAddr;
+
+// Startup packets are small; anything larger is not a startup packet
+const MAX_STARTUP_LEN: usize = 10000;
+
+const PROTOCOL_VERSION_3: u32 = 3;
+const CANCEL_REQUEST_CODE: u32 = 80877102;
+const SSL_REQUEST_CODE: u32 = 80877103;
+const GSSENC_REQUEST_CODE: u32 = 80877104;
+
+pub fn frontend_message_name(type_code: u8) -> &'static str {
+    match type_code {
+        b'Q' => "Simple query",
+        b'P' => "Parse",
+        b'B' => "Bind",
+        b'E' => "Execute",
+        b'D' => "Describe",
+        b'C' => "Close",
+        b'S' => "Sync",
+        b'H' => "Flush",
+        b'X' => "Termination",
+        b'p' => "Password message",
+        b'F' => "Function call",
+        b'd' => "Copy data",
+        b'c' => "Copy completion",
+        b'f' => "Copy failure",
+        _ => "Unknown",
+    }
+}
+
+pub fn backend_message_name(type_code: u8) -> &'static str {
+    match type_code {
+        b'R' => "Authentication request",
+        b'S' => "Parameter status",
+        b'K' => "Backend key data",
+        b'Z' => "Ready for query",
+        b'T' => "Row description",
+        b'D' => "Data row",
+        b'C' => "Command completion",
+        b'E' => "Error",
+        b'N' => "Notice",
+        b'1' => "Parse completion",
+        b'2' => "Bind completion",
+        b'3' => "Close completion",
+        b'n' => "No data",
+        b'I' => "Empty query",
+        b's' => "Portal suspended",
+        b't' => "Parameter description",
+        b'A' => "Notification response",
+        b'V' => "Function call response",
+        b'G' => "Copy in response",
+        b'H' => "Copy out response",
+        b'W' => "Copy both response",
+        b'd' => "Copy data",
+        b'c' => "Copy completion",
+        b'v' => "Negotiate protocol version",
+        _ => "Unknown",
+    }
+}
+
+/// The decoded part of a message; most messages are only named.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    Startup {
+        parameters: Vec<(String, String)>,
+    },
+    SslRequest,
+    GssEncRequest,
+    CancelRequest,
+    /// The server's one byte answer to an encryption request.
+    EncryptionResponse {
+        accepted: bool,
+    },
+    Query(String),
+    Parse {
+        statement: String,
+        query: String,
+    },
+    Authentication(u32),
+    ParameterStatus {
+        name: String,
+        value: String,
+    },
+    ReadyForQuery(u8),
+    RowDescription(Vec<String>),
+    CommandComplete(String),
+    /// ErrorResponse and NoticeResponse fields by their type code.
+    Response(Vec<(u8, String)>),
+    Other,
+}
+
+/// A message of the PostgreSQL frontend/backend protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgsqlMessage {
+    /// Sent by the client.
+    pub frontend: bool,
+    /// `None` for the startup messages, which have no type byte.
+    pub type_code: Option<u8>,
+    pub length: usize,
+    pub body: Body,
+}
+
+impl PgsqlMessage {
+    pub fn name(&self) -> &'static str {
+        match (&self.body, self.type_code) {
+            (Body::Startup { .. }, _) => "Startup message",
+            (Body::SslRequest, _) => "SSL request",
+            (Body::GssEncRequest, _) => "GSSAPI encryption request",
+            (Body::CancelRequest, _) => "Cancel request",
+            (Body::EncryptionResponse { .. }, _) => "Encryption response",
+            (_, Some(code)) if self.frontend => frontend_message_name(code),
+            (_, Some(code)) => backend_message_name(code),
+            (_, None) => "Unknown",
+        }
+    }
+
+    fn response_field(&self, code: u8) -> Option<&str> {
+        match &self.body {
+            Body::Response(fields) => fields
+                .iter()
+                .find(|(field, _)| *field == code)
+                .map(|(_, value)| value.as_str()),
+            _ => None,
+        }
+    }
+}
+
+impl AppMessage for PgsqlMessage {
+    const TIME_FIELD: &'static str = "pgsql.response_time";
+
+    fn info(&self) -> String {
+        let name = self.name();
+        match &self.body {
+            Body::Startup { parameters } => {
+                let shown: Vec<_> = parameters
+                    .iter()
+                    .filter(|(name, _)| name == "user" || name == "database")
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                match shown.is_empty() {
+                    true => name.to_string(),
+                    false => format!("{}: {}", name, shown.join(" ")),
+                }
+            }
+            Body::EncryptionResponse { accepted } => {
+                format!(
+                    "{}: {}",
+                    name,
+                    if *accepted { "accepted" } else { "refused" }
+                )
+            }
+            Body::Query(query) | Body::Parse { query, .. } => format!("{}: {}", name, query),
+            Body::ParameterStatus {
+                name: parameter,
+                value,
+            } => {
+                format!("{}: {}={}", name, parameter, value)
+            }
+            Body::RowDescription(columns) => format!("{}: {}", name, columns.join(", ")),
+            Body::CommandComplete(tag) => format!("{}: {}", name, tag),
+            Body::Response(_) => format!(
+                "{}: {} {} {}",
+                name,
+                self.response_field(b'S').unwrap_or("?"),
+                self.response_field(b'C').unwrap_or("?"),
+                self.response_field(b'M').unwrap_or("")
+            ),
+            _ => name.to_string(),
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("pgsql.type", self.name());
+        fields.insert("pgsql.length", self.length);
+        match &self.body {
+            Body::Startup { parameters } => {
+                for (name, value) in parameters {
+                    fields.insert("pgsql.parameter_name", name.as_str());
+                    fields.insert("pgsql.parameter_value", value.as_str());
+                }
+            }
+            Body::Query(query) => fields.insert("pgsql.query", query.as_str()),
+            Body::Parse { statement, query } => {
+                fields.insert("pgsql.statement", statement.as_str());
+                fields.insert("pgsql.query", query.as_str());
+            }
+            Body::Authentication(auth_type) => fields.insert("pgsql.authtype", *auth_type),
+            Body::ParameterStatus { name, value } => {
+                fields.insert("pgsql.parameter_name", name.as_str());
+                fields.insert("pgsql.parameter_value", value.as_str());
+            }
+            Body::ReadyForQuery(status) => {
+                fields.insert("pgsql.status", char::from(*status).to_string())
+            }
+            Body::RowDescription(columns) => {
+                for column in columns {
+                    fields.insert("pgsql.col.name", column.as_str());
+                }
+            }
+            Body::CommandComplete(tag) => fields.insert("pgsql.tag", tag.as_str()),
+            Body::Response(_) => {
+                for (code, name) in [
+                    (b'S', "pgsql.severity"),
+                    (b'C', "pgsql.code"),
+                    (b'M', "pgsql.message"),
+                    (b'D', "pgsql.detail"),
+                ] {
+                    if let Some(value) = self.response_field(code) {
+                        fields.insert(name, value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        if self.frontend || self.type_code != Some(b'E') {
+            return None;
+        }
+        Some(format!(
+            "{} ({})",
+            self.response_field(b'M').unwrap_or("unknown error"),
+            self.response_field(b'C').unwrap_or("?")
+        ))
+    }
+}
+
+/// Splits a NUL-terminated string off the front of `data`.
+fn cstring(data: &[u8]) -> Option<(String, &[u8])> {
+    let end = data.iter().position(|&b| b == 0)?;
+    Some((
+        String::from_utf8_lossy(&data[..end]).into_owned(),
+        &data[end + 1..],
+    ))
+}
+
+fn parse_startup(code: u32, mut data: &[u8]) -> Body {
+    match code {
+        CANCEL_REQUEST_CODE => Body::CancelRequest,
+        SSL_REQUEST_CODE => Body::SslRequest,
+        GSSENC_REQUEST_CODE => Body::GssEncRequest,
+        _ if code >> 16 == PROTOCOL_VERSION_3 => {
+            let mut parameters = Vec::new();
+            while let Some((name, rest)) = cstring(data).filter(|(name, _)| !name.is_empty()) {
+                let Some((value, rest)) = cstring(rest) else {
+                    break;
+                };
+                parameters.push((name, value));
+                data = rest;
+            }
+            Body::Startup { parameters }
+        }
+        _ => Body::Other,
+    }
+}
+
+fn parse_body(frontend: bool, type_code: u8, data: &[u8]) -> Option<Body> {
+    Some(match (frontend, type_code) {
+        (true, b'Q') => Body::Query(cstring(data)?.0),
+        (true, b'P') => {
+            let (statement, rest) = cstring(data)?;
+            Body::Parse {
+                statement,
+                query: cstring(rest)?.0,
+            }
+        }
+        (false, b'R') => Body::Authentication(u32::from_be_bytes(data.get(..4)?.try_into().ok()?)),
+        (false, b'S') => {
+            let (name, rest) = cstring(data)?;
+            Body::ParameterStatus {
+                name,
+                value: cstring(rest)?.0,
+            }
+        }
+        (false, b'Z') => Body::ReadyForQuery(*data.first()?),
+        (false, b'T') => {
+            let count = u16::from_be_bytes(data.get(..2)?.try_into().ok()?);
+            let mut rest = &data[2..];
+            let mut columns = Vec::new();
+            for _ in 0..count {
+                let (name, after) = cstring(rest)?;
+                columns.push(name);
+                // Table and type details of the column
+                rest = after.get(18..)?;
+            }
+            Body::RowDescription(columns)
+        }
+        (false, b'C') => Body::CommandComplete(cstring(data)?.0),
+        (false, b'E') | (false, b'N') => {
+            let mut fields = Vec::new();
+            let mut rest = data;
+            while let Some((&code, after)) = rest.split_first().filter(|(&code, _)| code != 0) {
+                let (value, after) = cstring(after)?;
+                fields.push((code, value));
+                rest = after;
+            }
+            Body::Response(fields)
+        }
+        _ => Body::Other,
+    })
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Connection {
+    startup_done: bool,
+    awaiting_encryption_response: bool,
+    encrypted: bool,
+    pending: PendingQueries,
+    /// Query and start of an extended query batch waiting for its Sync.
+    batch: Option<(String, DateTime<Utc>)>,
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+
+    fn gap(&mut self, _: &mut MessageBuffer, _: bool) {
+        // Responses can no longer be matched to their queries
+        self.pending.clear();
+        self.batch = None;
+    }
+}
+
+impl Connection {
+    fn next_message(&mut self, buffer: &mut MessageBuffer, frontend: bool) -> Option<PgsqlMessage> {
+        let data = buffer.data();
+        let first = *data.first()?;
+
+        // Startup messages have no type byte but start with a length
+        // below 16 MiB, while type bytes are never zero
+        if frontend && !self.startup_done && first == 0 {
+            let len = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+            let code = u32::from_be_bytes(data.get(4..8)?.try_into().ok()?);
+            if !(8..=MAX_STARTUP_LEN).contains(&len) {
+                buffer.clear();
+                return None;
+            }
+            let message = buffer.take(len)?;
+            return Some(PgsqlMessage {
+                frontend,
+                type_code: None,
+                length: len,
+                body: parse_startup(code, &message[8..]),
+            });
+        }
+
+        if !frontend && self.awaiting_encryption_response {
+            self.awaiting_encryption_response = false;
+            if matches!(first, b'S' | b'G' | b'N') {
+                buffer.take(1);
+                return Some(PgsqlMessage {
+                    frontend,
+                    type_code: None,
+                    length: 1,
+                    body: Body::EncryptionResponse {
+                        accepted: first != b'N',
+                    },
+                });
+            }
+        }
+
+        let len = u32::from_be_bytes(data.get(1..5)?.try_into().ok()?) as usize;
+        if len < 4 || !first.is_ascii_alphanumeric() {
+            // Not at a message boundary, e.g. after missing data
+            buffer.clear();
+            return None;
+        }
+        let message = buffer.take(1 + len)?;
+        Some(PgsqlMessage {
+            frontend,
+            type_code: Some(first),
+            length: len,
+            body: parse_body(frontend, first, &message[5..]).unwrap_or(Body::Other),
+        })
+    }
+
+    /// Tracks requests and their completion by ReadyForQuery.
+    fn update(
+        &mut self,
+        message: &PgsqlMessage,
+        timestamp: DateTime<Utc>,
+    ) -> Option<crate::query::QueryTiming> {
+        if message.frontend && message.type_code.is_some() {
+            self.startup_done = true;
+        }
+        match (&message.body, message.frontend, message.type_code) {
+            (Body::Startup { .. }, _, _) => self.startup_done = true,
+            (Body::SslRequest | Body::GssEncRequest, _, _) => {
+                self.awaiting_encryption_response = true
+            }
+            (Body::EncryptionResponse { accepted }, _, _) => self.encrypted = *accepted,
+            (Body::Query(query), _, _) => self.pending.push(query.clone(), timestamp),
+            (Body::Parse { query, .. }, _, _) => match &mut self.batch {
+                Some((batch_query, _)) if batch_query.is_empty() => *batch_query = query.clone(),
+                Some(_) => {}
+                None => self.batch = Some((query.clone(), timestamp)),
+            },
+            (_, true, Some(b'B' | b'E' | b'D' | b'C')) => {
+                self.batch.get_or_insert_with(|| (String::new(), timestamp));
+            }
+            (_, true, Some(b'S')) => {
+                let (query, start) = self.batch.take().unwrap_or((String::new(), timestamp));
+                self.pending.push(query, start);
+            }
+            (Body::ReadyForQuery(_), false, _) => return self.pending.complete(timestamp),
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Splits reassembled PostgreSQL connections into messages and measures
+/// how long queries take.
+#[derive(Debug, Default)]
+pub struct PgsqlTracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl PgsqlTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the messages `stream` completes and the queries they
+    /// answer.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+        timestamp: DateTime<Utc>,
+    ) -> Exchange<PgsqlMessage> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (buffer, connection) = connection.split(from_client);
+        if connection.encrypted {
+            return exchange;
+        }
+
+        buffer.push(&stream.data);
+        while let Some(message) = connection.next_message(buffer, from_client) {
+            if let Some(timing) = connection.update(&message, timestamp) {
+                exchange.timings.push(timing);
+            }
+            exchange.messages.push(message);
+            if connection.encrypted {
+                // The rest is TLS or GSSAPI
+                buffer.clear();
+                break;
+            }
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    fn message(type_code: u8, body: &[u8]) -> Vec<u8> {
+        let mut message = vec![type_code];
+        message.extend_from_slice(&(body.len() as u32 + 4).to_be_bytes());
+        message.extend_from_slice(body);
+        message
+    }
+
+    fn startup(code: u32, body: &[u8]) -> Vec<u8> {
+        let mut message = (body.len() as u32 + 8).to_be_bytes().to_vec();
+        message.extend_from_slice(&code.to_be_bytes());
+        message.extend_from_slice(body);
+        message
+    }
+
+    #[test]
+    fn test_startup_and_simple_query() {
+        let start = Utc::now();
+        let mut tracker = PgsqlTracker::new();
+
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(&startup(196608, b"user\0alice\0database\0app\0\0")),
+            start,
+        );
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Startup message: user=alice database=app"
+        );
+
+        let mut answer = message(b'R', &0u32.to_be_bytes());
+        answer.extend(message(b'Z', b"I"));
+        let exchange =
+            tracker.process(CLIENT, SERVER, false, &StreamData::complete(&answer), start);
+        assert_eq!(exchange.messages.len(), 2);
+        assert!(exchange.timings.is_empty());
+
+        let query = message(b'Q', b"SELECT id FROM users\0");
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&query), start);
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Simple query: SELECT id FROM users"
+        );
+
+        let mut columns = b"\0\x01id\0".to_vec();
+        columns.extend_from_slice(&[0; 18]);
+        let mut result = message(b'T', &columns);
+        result.extend(message(b'D', b"\0\x01\0\0\0\x011"));
+        result.extend(message(b'D', b"\0\x01\0\0\0\x012"));
+        result.extend(message(b'C', b"SELECT 2\0"));
+        result.extend(message(b'Z', b"I"));
+        // The response arrives in two segments
+        let (first, second) = result.split_at(30);
+        let exchange = tracker.process(CLIENT, SERVER, false, &StreamData::complete(first), start);
+        assert_eq!(exchange.messages.len(), 1);
+        let end = start + chrono::Duration::milliseconds(3);
+        let exchange = tracker.process(CLIENT, SERVER, false, &StreamData::complete(second), end);
+        let infos: Vec<_> = exchange.messages.iter().map(|m| m.info()).collect();
+        assert_eq!(
+            crate::query::summarize(infos),
+            "Data row (x2), Command completion: SELECT 2, Ready for query"
+        );
+        assert_eq!(exchange.timings[0].query, "SELECT id FROM users");
+        assert_eq!(exchange.timings[0].latency, 0.003);
+    }
+
+    #[test]
+    fn test_extended_query_error() {
+        let start = Utc::now();
+        let mut tracker = PgsqlTracker::new();
+
+        let mut batch = message(b'P', b"\0SELECT * FROM missing\0\0\0");
+        batch.extend(message(b'B', b"\0\0\0\0\0\0\0\0"));
+        batch.extend(message(b'E', b"\0\0\0\0\0"));
+        batch.extend(message(b'S', b""));
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&batch), start);
+        assert_eq!(exchange.messages.len(), 4);
+
+        let mut error = message(
+            b'E',
+            b"SERROR\0C42P01\0Mrelation \"missing\" does not exist\0\0",
+        );
+        error.extend(message(b'Z', b"I"));
+        let end = start + chrono::Duration::milliseconds(1);
+        let exchange = tracker.process(CLIENT, SERVER, false, &StreamData::complete(&error), end);
+        assert_eq!(
+            exchange.messages[0].info(),
+            "Error: ERROR 42P01 relation \"missing\" does not exist"
+        );
+        assert_eq!(
+            exchange.messages[0].error().unwrap(),
+            "relation \"missing\" does not exist (42P01)"
+        );
+        assert_eq!(exchange.timings[0].query, "SELECT * FROM missing");
+
+        let mut fields = Fields::new();
+        exchange.messages[0].insert_fields(&mut fields);
+        assert_eq!(fields.get("pgsql.code"), Some(&"42P01".into()));
+    }
+
+    #[test]
+    fn test_ssl_request_stops_decoding() {
+        let start = Utc::now();
+        let mut tracker = PgsqlTracker::new();
+
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(&startup(SSL_REQUEST_CODE, b"")),
+            start,
+        );
+        assert_eq!(exchange.messages[0].body, Body::SslRequest);
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(b"S\x16\x03\x01"),
+            start,
+        );
+        assert_eq!(exchange.messages.len(), 1);
+        assert_eq!(exchange.messages[0].info(), "Encryption response: accepted");
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(&[0x16, 3, 1, 0, 5]),
+            start,
+        );
+        assert!(exchange.messages.is_empty());
+    }
+}
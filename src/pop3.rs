@@ -0,0 +1,308 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionState, ConnectionTable, Exchange};
+use crate::reassembly::{MessageBuffer, StreamData};
+use std::collections::VecDeque;
+use std::net::IpAddr;
+
+const MAX_PENDING_COMMANDS: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Command {
+        command: String,
+        parameter: String,
+    },
+    Response {
+        ok: bool,
+        description: String,
+    },
+    /// Lines of a multi-line response, such as a retrieved message.
+    Data {
+        bytes: usize,
+        end: bool,
+    },
+    /// Data after a successful STLS.
+    Encrypted(usize),
+}
+
+/// A POP3 command, response or multi-line response data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pop3Message {
+    pub from_client: bool,
+    pub kind: Kind,
+    /// The response accepting an STLS command.
+    pub starts_tls: bool,
+}
+
+impl AppMessage for Pop3Message {
+    const TIME_FIELD: &'static str = "pop.response_time";
+
+    fn info(&self) -> String {
+        let side = if self.from_client { "C" } else { "S" };
+        match &self.kind {
+            Kind::Command { command, parameter } if parameter.is_empty() => {
+                format!("{}: {}", side, command)
+            }
+            Kind::Command { command, parameter } => {
+                format!("{}: {} {}", side, command, parameter)
+            }
+            Kind::Response { ok, description } => {
+                let indicator = if *ok { "+OK" } else { "-ERR" };
+                format!("{}: {} {}", side, indicator, description)
+                    .trim_end()
+                    .to_string()
+            }
+            Kind::Data { bytes, end: false } => format!("{}: DATA fragment, {} bytes", side, bytes),
+            Kind::Data { bytes, end: true } => {
+                format!("{}: DATA fragment, {} bytes (end of data)", side, bytes)
+            }
+            Kind::Encrypted(bytes) => format!("{}: TLS encrypted data, {} bytes", side, bytes),
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        match &self.kind {
+            Kind::Command { command, parameter } => {
+                fields.insert("pop.request.command", command.as_str());
+                if !parameter.is_empty() {
+                    fields.insert("pop.request.parameter", parameter.as_str());
+                }
+            }
+            Kind::Response { ok, description } => {
+                fields.insert("pop.response.indicator", if *ok { "+OK" } else { "-ERR" });
+                fields.insert("pop.response.description", description.as_str());
+            }
+            Kind::Data { bytes, .. } => fields.insert("pop.data.length", *bytes),
+            Kind::Encrypted(_) => fields.insert("pop.stls", true),
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match &self.kind {
+            Kind::Response {
+                ok: false,
+                description,
+            } => Some(description.clone()),
+            _ => None,
+        }
+    }
+
+    fn note(&self) -> Option<String> {
+        self.starts_tls
+            .then(|| "Connection switched to TLS by STLS".to_string())
+    }
+}
+
+/// Whether a successful response to the command has more lines.
+fn is_multi_line(command: &str, parameter: &str) -> bool {
+    match command {
+        "CAPA" | "RETR" | "TOP" => true,
+        "LIST" | "UIDL" => parameter.is_empty(),
+        _ => false,
+    }
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// Commands waiting for their response, by whether they're STLS and
+    /// whether a successful response has more lines.
+    pending: VecDeque<(bool, bool)>,
+    /// The server is sending the lines of a multi-line response.
+    data_mode: bool,
+    encrypted: bool,
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+
+    fn gap(&mut self, _: &mut MessageBuffer, _: bool) {
+        // Responses can no longer be matched to their commands
+        self.pending.clear();
+        self.data_mode = false;
+    }
+}
+
+impl Connection {
+    fn client_messages(&mut self, buffer: &mut MessageBuffer, messages: &mut Vec<Pop3Message>) {
+        while let Some(line) = buffer.take_line() {
+            let (command, parameter) = line.split_once(' ').unwrap_or((&line, ""));
+            let command = command.to_ascii_uppercase();
+            if self.pending.len() >= MAX_PENDING_COMMANDS {
+                self.pending.pop_front();
+            }
+            self.pending
+                .push_back((command == "STLS", is_multi_line(&command, parameter)));
+            messages.push(Pop3Message {
+                from_client: true,
+                kind: Kind::Command {
+                    command,
+                    parameter: parameter.to_string(),
+                },
+                starts_tls: false,
+            });
+        }
+    }
+
+    fn server_messages(&mut self, buffer: &mut MessageBuffer, messages: &mut Vec<Pop3Message>) {
+        let mut data = 0;
+        while let Some(line) = buffer.take_line() {
+            if self.data_mode {
+                data += line.len() + 2;
+                if line == "." {
+                    self.data_mode = false;
+                    messages.push(Pop3Message {
+                        from_client: false,
+                        kind: Kind::Data {
+                            bytes: data,
+                            end: true,
+                        },
+                        starts_tls: false,
+                    });
+                    data = 0;
+                }
+                continue;
+            }
+
+            let ok = line.starts_with("+OK");
+            let description = line
+                .split_once(' ')
+                .map_or("", |(_, description)| description)
+                .to_string();
+            // The greeting answers no command
+            let (stls, multi_line) = self.pending.pop_front().unwrap_or((false, false));
+            self.data_mode = ok && multi_line;
+            let starts_tls = ok && stls;
+            messages.push(Pop3Message {
+                from_client: false,
+                kind: Kind::Response { ok, description },
+                starts_tls,
+            });
+            if starts_tls {
+                self.encrypted = true;
+                buffer.clear();
+                return;
+            }
+        }
+        if data > 0 {
+            messages.push(Pop3Message {
+                from_client: false,
+                kind: Kind::Data {
+                    bytes: data,
+                    end: false,
+                },
+                starts_tls: false,
+            });
+        }
+    }
+}
+
+/// Splits reassembled POP3 connections into commands and responses.
+#[derive(Debug, Default)]
+pub struct Pop3Tracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl Pop3Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the commands, responses or response lines `stream`
+    /// completes.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+    ) -> Exchange<Pop3Message> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (buffer, connection) = connection.split(from_client);
+        if connection.encrypted {
+            if !stream.data.is_empty() {
+                exchange.messages.push(Pop3Message {
+                    from_client,
+                    kind: Kind::Encrypted(stream.data.len()),
+                    starts_tls: false,
+                });
+            }
+            return exchange;
+        }
+
+        buffer.push(&stream.data);
+        if from_client {
+            connection.client_messages(buffer, &mut exchange.messages);
+        } else {
+            connection.server_messages(buffer, &mut exchange.messages);
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    #[test]
+    fn test_retrieve_message() {
+        let mut tracker = Pop3Tracker::new();
+        let mut process = |from_client, data: &[u8]| {
+            let exchange =
+                tracker.process(CLIENT, SERVER, from_client, &StreamData::complete(data));
+            exchange
+                .messages
+                .iter()
+                .map(|m| m.info())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(process(false, b"+OK POP3 ready\r\n"), ["S: +OK POP3 ready"]);
+        assert_eq!(
+            process(true, b"USER alice\r\nPASS secret\r\nRETR 1\r\n"),
+            ["C: USER alice", "C: PASS secret", "C: RETR 1"]
+        );
+        assert_eq!(
+            process(
+                false,
+                b"+OK\r\n-ERR invalid password\r\n-ERR not logged in\r\n"
+            ),
+            [
+                "S: +OK",
+                "S: -ERR invalid password",
+                "S: -ERR not logged in"
+            ]
+        );
+
+        process(true, b"PASS right\r\nRETR 1\r\n");
+        assert_eq!(
+            process(false, b"+OK\r\n+OK 20 octets\r\nSubject: x\r\n"),
+            ["S: +OK", "S: +OK 20 octets", "S: DATA fragment, 12 bytes"]
+        );
+        // A dot-stuffed line doesn't end the data
+        assert_eq!(
+            process(false, b"..\r\n.\r\n"),
+            ["S: DATA fragment, 7 bytes (end of data)"]
+        );
+    }
+
+    #[test]
+    fn test_stls() {
+        let mut tracker = Pop3Tracker::new();
+        tracker.process(CLIENT, SERVER, true, &StreamData::complete(b"STLS\r\n"));
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(b"+OK Begin TLS\r\n"),
+        );
+        assert!(exchange.messages[0].note().is_some());
+        let exchange = tracker.process(CLIENT, SERVER, true, &StreamData::complete(&[0x16, 3, 1]));
+        assert_eq!(exchange.messages[0].kind, Kind::Encrypted(3));
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Application protocols recognized by their server's TCP port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppProtocol {
+    PostgreSql,
+    MySql,
+    Redis,
+    Mqtt,
+    Amqp,
+    Kafka,
+    Smtp,
+    Imap,
+    Pop3,
+    Ftp,
+    Ssh,
+    Modbus,
+    Dnp3,
+    Bgp,
+}
+
+impl AppProtocol {
+    pub const ALL: [AppProtocol; 14] = [
+        AppProtocol::PostgreSql,
+        AppProtocol::MySql,
+        AppProtocol::Redis,
+        AppProtocol::Mqtt,
+        AppProtocol::Amqp,
+        AppProtocol::Kafka,
+        AppProtocol::Smtp,
+        AppProtocol::Imap,
+        AppProtocol::Pop3,
+        AppProtocol::Ftp,
+        AppProtocol::Ssh,
+        AppProtocol::Modbus,
+        AppProtocol::Dnp3,
+        AppProtocol::Bgp,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppProtocol::PostgreSql => "postgresql",
+            AppProtocol::MySql => "mysql",
+            AppProtocol::Redis => "redis",
+            AppProtocol::Mqtt => "mqtt",
+            AppProtocol::Amqp => "amqp",
+            AppProtocol::Kafka => "kafka",
+            AppProtocol::Smtp => "smtp",
+            AppProtocol::Imap => "imap",
+            AppProtocol::Pop3 => "pop3",
+            AppProtocol::Ftp => "ftp",
+            AppProtocol::Ssh => "ssh",
+            AppProtocol::Modbus => "modbus",
+            AppProtocol::Dnp3 => "dnp3",
+            AppProtocol::Bgp => "bgp",
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            AppProtocol::PostgreSql => 5432,
+            AppProtocol::MySql => 3306,
+            AppProtocol::Redis => 6379,
+            AppProtocol::Mqtt => 1883,
+            AppProtocol::Amqp => 5672,
+            AppProtocol::Kafka => 9092,
+            AppProtocol::Smtp => 25,
+            AppProtocol::Imap => 143,
+            AppProtocol::Pop3 => 110,
+            AppProtocol::Ftp => 21,
+            AppProtocol::Ssh => 22,
+            AppProtocol::Modbus => 502,
+            AppProtocol::Dnp3 => 20000,
+            AppProtocol::Bgp => 179,
+        }
+    }
+}
+
+impl FromStr for AppProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        AppProtocol::ALL
+            .into_iter()
+            .find(|protocol| protocol.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = AppProtocol::ALL.iter().map(|p| p.name()).collect();
+                format!(
+                    "unknown protocol '{}', expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for AppProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A `PROTOCOL=PORT` assignment from the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortOverride {
+    pub protocol: AppProtocol,
+    pub port: u16,
+}
+
+impl FromStr for PortOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (protocol, port) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected PROTOCOL=PORT, got '{}'", s))?;
+        Ok(PortOverride {
+            protocol: protocol.parse()?,
+            port: port
+                .parse()
+                .map_err(|_| format!("invalid port '{}'", port))?,
+        })
+    }
+}
+
+/// Which application protocol the server port of a connection speaks.
+#[derive(Debug, Clone)]
+pub struct PortMap {
+    ports: HashMap<u16, AppProtocol>,
+}
+
+impl Default for PortMap {
+    fn default() -> Self {
+        PortMap {
+            ports: AppProtocol::ALL
+                .iter()
+                .map(|protocol| (protocol.default_port(), *protocol))
+                .collect(),
+        }
+    }
+}
+
+impl PortMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes `port` as `protocol`, replacing what it was assigned before.
+    pub fn set(&mut self, port: u16, protocol: AppProtocol) {
+        self.ports.insert(port, protocol);
+    }
+
+    /// The protocol of a segment between the two ports, and whether the
+    /// segment was sent by the client.
+    pub fn lookup(&self, src_port: u16, dst_port: u16) -> Option<(AppProtocol, bool)> {
+        match self.ports.get(&dst_port) {
+            Some(protocol) => Some((*protocol, true)),
+            None => self.ports.get(&src_port).map(|protocol| (*protocol, false)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_overrides() {
+        let mut ports = PortMap::new();
+        assert_eq!(
+            ports.lookup(40000, 5432),
+            Some((AppProtocol::PostgreSql, true))
+        );
+        assert_eq!(ports.lookup(6379, 40000), Some((AppProtocol::Redis, false)));
+
+        let port_override: PortOverride = "MySQL=13306".parse().unwrap();
+        ports.set(port_override.port, port_override.protocol);
+        assert_eq!(ports.lookup(40000, 13306), Some((AppProtocol::MySql, true)));
+        assert!("mongodb=27017".parse::<PortOverride>().is_err());
+        assert!("redis".parse::<PortOverride>().is_err());
+    }
+}
@@ -0,0 +1,509 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+// Field types of FieldDescriptorProto
+const TYPE_DOUBLE: u32 = 1;
+const TYPE_FLOAT: u32 = 2;
+const TYPE_INT64: u32 = 3;
+const TYPE_UINT64: u32 = 4;
+const TYPE_INT32: u32 = 5;
+const TYPE_FIXED64: u32 = 6;
+const TYPE_FIXED32: u32 = 7;
+const TYPE_BOOL: u32 = 8;
+const TYPE_STRING: u32 = 9;
+const TYPE_GROUP: u32 = 10;
+const TYPE_MESSAGE: u32 = 11;
+const TYPE_BYTES: u32 = 12;
+const TYPE_UINT32: u32 = 13;
+const TYPE_ENUM: u32 = 14;
+const TYPE_SFIXED32: u32 = 15;
+const TYPE_SFIXED64: u32 = 16;
+const TYPE_SINT32: u32 = 17;
+const TYPE_SINT64: u32 = 18;
+
+const LABEL_REPEATED: u64 = 3;
+
+// Messages nested deeper than this are shown as bytes
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDescriptor {
+    pub name: String,
+    pub number: u64,
+    pub field_type: u32,
+    /// Fully qualified message or enum type, without the leading dot.
+    pub type_name: Option<String>,
+    pub repeated: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageDescriptor {
+    pub name: String,
+    pub fields: Vec<FieldDescriptor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodDescriptor {
+    pub input_type: String,
+    pub output_type: String,
+}
+
+/// A decoded field, named by its path from the outermost message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedField {
+    pub name: String,
+    pub value: String,
+}
+
+/// Message, enum and service definitions from a descriptor set, as written
+/// by `protoc --include_imports --descriptor_set_out`.
+#[derive(Debug, Clone, Default)]
+pub struct DescriptorPool {
+    messages: HashMap<String, MessageDescriptor>,
+    enums: HashMap<String, HashMap<i64, String>>,
+    /// Methods by gRPC path, `/package.Service/Method`.
+    methods: HashMap<String, MethodDescriptor>,
+}
+
+impl DescriptorPool {
+    pub fn load(path: &Path) -> Result<DescriptorPool, Box<dyn Error + Send + Sync>> {
+        let bytes = std::fs::read(path)?;
+        DescriptorPool::decode(&bytes)
+            .ok_or_else(|| format!("{} is not a protobuf descriptor set", path.display()).into())
+    }
+
+    /// Decodes a serialized FileDescriptorSet.
+    pub fn decode(bytes: &[u8]) -> Option<DescriptorPool> {
+        let mut pool = DescriptorPool::default();
+        for field in fields(bytes)? {
+            if let (1, Value::Len(file)) = field {
+                pool.add_file(file)?;
+            }
+        }
+        Some(pool)
+    }
+
+    fn add_file(&mut self, file: &[u8]) -> Option<()> {
+        let fields = fields(file)?;
+        let package = fields
+            .iter()
+            .find_map(|field| match field {
+                (2, Value::Len(package)) => Some(String::from_utf8_lossy(package).into_owned()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        for field in fields {
+            match field {
+                (4, Value::Len(message)) => self.add_message(&package, message)?,
+                (5, Value::Len(enumeration)) => self.add_enum(&package, enumeration)?,
+                (6, Value::Len(service)) => self.add_service(&package, service)?,
+                _ => {}
+            }
+        }
+        Some(())
+    }
+
+    fn add_message(&mut self, scope: &str, message: &[u8]) -> Option<()> {
+        let fields = fields(message)?;
+        let name = qualify(scope, &string_field(&fields, 1).unwrap_or_default());
+        let mut descriptor = MessageDescriptor {
+            name: name.clone(),
+            fields: Vec::new(),
+        };
+        for field in &fields {
+            match field {
+                (2, Value::Len(field)) => descriptor.fields.push(parse_field(field)?),
+                (3, Value::Len(nested)) => self.add_message(&name, nested)?,
+                (4, Value::Len(enumeration)) => self.add_enum(&name, enumeration)?,
+                _ => {}
+            }
+        }
+        self.messages.insert(name, descriptor);
+        Some(())
+    }
+
+    fn add_enum(&mut self, scope: &str, enumeration: &[u8]) -> Option<()> {
+        let fields = fields(enumeration)?;
+        let name = qualify(scope, &string_field(&fields, 1).unwrap_or_default());
+        let mut values = HashMap::new();
+        for field in &fields {
+            if let (2, Value::Len(value)) = field {
+                let value = self::fields(value)?;
+                if let (Some(name), Some(number)) =
+                    (string_field(&value, 1), varint_field(&value, 2))
+                {
+                    values.insert(number as i32 as i64, name);
+                }
+            }
+        }
+        self.enums.insert(name, values);
+        Some(())
+    }
+
+    fn add_service(&mut self, package: &str, service: &[u8]) -> Option<()> {
+        let fields = fields(service)?;
+        let name = qualify(package, &string_field(&fields, 1).unwrap_or_default());
+        for field in &fields {
+            if let (2, Value::Len(method)) = field {
+                let method = self::fields(method)?;
+                let type_name = |number| {
+                    string_field(&method, number).map(|t| t.trim_start_matches('.').to_string())
+                };
+                if let (Some(method_name), Some(input_type), Some(output_type)) =
+                    (string_field(&method, 1), type_name(2), type_name(3))
+                {
+                    self.methods.insert(
+                        format!("/{}/{}", name, method_name),
+                        MethodDescriptor {
+                            input_type,
+                            output_type,
+                        },
+                    );
+                }
+            }
+        }
+        Some(())
+    }
+
+    pub fn method(&self, path: &str) -> Option<&MethodDescriptor> {
+        self.methods.get(path)
+    }
+
+    pub fn message(&self, name: &str) -> Option<&MessageDescriptor> {
+        self.messages.get(name)
+    }
+
+    /// Decodes a message of type `message_type`. Fields missing from the
+    /// descriptor are named by their number.
+    pub fn decode_message(&self, message_type: &str, data: &[u8]) -> Option<Vec<DecodedField>> {
+        let mut decoded = Vec::new();
+        self.decode_into(message_type, data, "", 0, &mut decoded)?;
+        Some(decoded)
+    }
+
+    fn decode_into(
+        &self,
+        message_type: &str,
+        data: &[u8],
+        prefix: &str,
+        depth: usize,
+        decoded: &mut Vec<DecodedField>,
+    ) -> Option<()> {
+        let message = self.messages.get(message_type)?;
+        for (number, value) in fields(data)? {
+            let field = message.fields.iter().find(|f| f.number == number);
+            let name = match field {
+                Some(field) => format!("{}{}", prefix, field.name),
+                None => format!("{}{}", prefix, number),
+            };
+            let Some(field) = field else {
+                decoded.push(DecodedField {
+                    name,
+                    value: value.raw(),
+                });
+                continue;
+            };
+
+            match (field.field_type, value) {
+                (TYPE_MESSAGE, Value::Len(nested)) if depth < MAX_DEPTH => {
+                    let nested_type = field.type_name.as_deref().unwrap_or_default();
+                    let nested_prefix = format!("{}.", name);
+                    if self
+                        .decode_into(nested_type, nested, &nested_prefix, depth + 1, decoded)
+                        .is_none()
+                    {
+                        decoded.push(DecodedField {
+                            name,
+                            value: Value::Len(nested).raw(),
+                        });
+                    }
+                }
+                (TYPE_STRING, Value::Len(text)) => decoded.push(DecodedField {
+                    name,
+                    value: format!("{:?}", String::from_utf8_lossy(text)),
+                }),
+                // Repeated scalars are packed into one length-delimited field
+                (field_type, Value::Len(packed)) if field.repeated && is_scalar(field_type) => {
+                    for value in packed_values(field_type, packed)? {
+                        decoded.push(DecodedField {
+                            name: name.clone(),
+                            value: self.scalar(field, value),
+                        });
+                    }
+                }
+                (_, value) => decoded.push(DecodedField {
+                    name,
+                    value: self.scalar(field, value),
+                }),
+            }
+        }
+        Some(())
+    }
+
+    fn scalar(&self, field: &FieldDescriptor, value: Value) -> String {
+        match (field.field_type, value) {
+            (TYPE_DOUBLE, Value::Fixed64(v)) => f64::from_bits(v).to_string(),
+            (TYPE_FLOAT, Value::Fixed32(v)) => f32::from_bits(v).to_string(),
+            (TYPE_INT64 | TYPE_SFIXED64, Value::Varint(v) | Value::Fixed64(v)) => {
+                (v as i64).to_string()
+            }
+            (TYPE_INT32, Value::Varint(v)) => (v as i32).to_string(),
+            (TYPE_SFIXED32, Value::Fixed32(v)) => (v as i32).to_string(),
+            (TYPE_UINT64 | TYPE_UINT32 | TYPE_FIXED64, Value::Varint(v) | Value::Fixed64(v)) => {
+                v.to_string()
+            }
+            (TYPE_FIXED32, Value::Fixed32(v)) => v.to_string(),
+            (TYPE_BOOL, Value::Varint(v)) => (v != 0).to_string(),
+            (TYPE_SINT32 | TYPE_SINT64, Value::Varint(v)) => {
+                ((v >> 1) as i64 ^ -((v & 1) as i64)).to_string()
+            }
+            (TYPE_ENUM, Value::Varint(v)) => {
+                let number = v as i32 as i64;
+                field
+                    .type_name
+                    .as_ref()
+                    .and_then(|name| self.enums.get(name)?.get(&number))
+                    .cloned()
+                    .unwrap_or_else(|| number.to_string())
+            }
+            (_, value) => value.raw(),
+        }
+    }
+}
+
+/// Renders decoded fields like the protobuf text format, on one line.
+pub fn format_fields(fields: &[DecodedField]) -> String {
+    fields
+        .iter()
+        .map(|field| format!("{}: {}", field.name, field.value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    Len(&'a [u8]),
+}
+
+impl Value<'_> {
+    /// The value without knowing its type.
+    fn raw(&self) -> String {
+        match self {
+            Value::Varint(v) | Value::Fixed64(v) => v.to_string(),
+            Value::Fixed32(v) => v.to_string(),
+            Value::Len(bytes) => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+}
+
+/// Splits a message into its fields. Groups are not supported.
+fn fields(mut data: &[u8]) -> Option<Vec<(u64, Value<'_>)>> {
+    let mut fields = Vec::new();
+    while !data.is_empty() {
+        let (key, rest) = varint(data)?;
+        let (value, rest) = match (key & 0x07) as u8 {
+            WIRE_VARINT => {
+                let (value, rest) = varint(rest)?;
+                (Value::Varint(value), rest)
+            }
+            WIRE_FIXED64 => {
+                let bytes: [u8; 8] = rest.get(..8)?.try_into().ok()?;
+                (Value::Fixed64(u64::from_le_bytes(bytes)), &rest[8..])
+            }
+            WIRE_LEN => {
+                let (len, rest) = varint(rest)?;
+                let len = usize::try_from(len).ok()?;
+                (Value::Len(rest.get(..len)?), &rest[len..])
+            }
+            WIRE_FIXED32 => {
+                let bytes: [u8; 4] = rest.get(..4)?.try_into().ok()?;
+                (Value::Fixed32(u32::from_le_bytes(bytes)), &rest[4..])
+            }
+            _ => return None,
+        };
+        fields.push((key >> 3, value));
+        data = rest;
+    }
+    Some(fields)
+}
+
+fn varint(data: &[u8]) -> Option<(u64, &[u8])> {
+    let mut value = 0u64;
+    for (i, byte) in data.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, &data[i + 1..]));
+        }
+    }
+    None
+}
+
+fn is_scalar(field_type: u32) -> bool {
+    !matches!(
+        field_type,
+        TYPE_STRING | TYPE_GROUP | TYPE_BYTES | TYPE_MESSAGE
+    )
+}
+
+fn packed_values(field_type: u32, mut data: &[u8]) -> Option<Vec<Value<'_>>> {
+    let mut values = Vec::new();
+    while !data.is_empty() {
+        let value = match field_type {
+            TYPE_DOUBLE | TYPE_FIXED64 | TYPE_SFIXED64 => {
+                let bytes: [u8; 8] = data.get(..8)?.try_into().ok()?;
+                data = &data[8..];
+                Value::Fixed64(u64::from_le_bytes(bytes))
+            }
+            TYPE_FLOAT | TYPE_FIXED32 | TYPE_SFIXED32 => {
+                let bytes: [u8; 4] = data.get(..4)?.try_into().ok()?;
+                data = &data[4..];
+                Value::Fixed32(u32::from_le_bytes(bytes))
+            }
+            _ => {
+                let (value, rest) = varint(data)?;
+                data = rest;
+                Value::Varint(value)
+            }
+        };
+        values.push(value);
+    }
+    Some(values)
+}
+
+fn parse_field(field: &[u8]) -> Option<FieldDescriptor> {
+    let fields = fields(field)?;
+    Some(FieldDescriptor {
+        name: string_field(&fields, 1)?,
+        number: varint_field(&fields, 3)?,
+        field_type: varint_field(&fields, 5)? as u32,
+        type_name: string_field(&fields, 6).map(|t| t.trim_start_matches('.').to_string()),
+        repeated: varint_field(&fields, 4) == Some(LABEL_REPEATED),
+    })
+}
+
+fn string_field(fields: &[(u64, Value)], number: u64) -> Option<String> {
+    fields.iter().find_map(|field| match field {
+        (n, Value::Len(text)) if *n == number => Some(String::from_utf8_lossy(text).into_owned()),
+        _ => None,
+    })
+}
+
+fn varint_field(fields: &[(u64, Value)], number: u64) -> Option<u64> {
+    fields.iter().find_map(|field| match field {
+        (n, Value::Varint(value)) if *n == number => Some(*value),
+        _ => None,
+    })
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn encode_len(number: u64, value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![(number << 3) as u8 | WIRE_LEN];
+        let mut len = value.len();
+        while len >= 0x80 {
+            bytes.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+        bytes.push(len as u8);
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    pub(crate) fn encode_varint(number: u64, value: u8) -> Vec<u8> {
+        vec![(number << 3) as u8 | WIRE_VARINT, value]
+    }
+
+    fn field(name: &str, number: u8, field_type: u8, type_name: Option<&str>) -> Vec<u8> {
+        let mut field = encode_len(1, name.as_bytes());
+        field.extend(encode_varint(3, number));
+        field.extend(encode_varint(5, field_type));
+        if let Some(type_name) = type_name {
+            field.extend(encode_len(6, type_name.as_bytes()));
+        }
+        encode_len(2, &field)
+    }
+
+    /// A descriptor set for a greeter service with nested messages and an
+    /// enum.
+    pub(crate) fn greeter_descriptor_set() -> Vec<u8> {
+        let mut person = encode_len(1, b"Person");
+        person.extend(field("name", 1, TYPE_STRING as u8, None));
+        person.extend(field("mood", 2, TYPE_ENUM as u8, Some(".helloworld.Mood")));
+
+        let mut request = encode_len(1, b"HelloRequest");
+        request.extend(field(
+            "person",
+            1,
+            TYPE_MESSAGE as u8,
+            Some(".helloworld.Person"),
+        ));
+        request.extend(field("times", 2, TYPE_INT32 as u8, None));
+
+        let mut happy = encode_len(1, b"HAPPY");
+        happy.extend(encode_varint(2, 1));
+        let mut mood = encode_len(1, b"Mood");
+        mood.extend(encode_len(2, &happy));
+
+        let mut method = encode_len(1, b"SayHello");
+        method.extend(encode_len(2, b".helloworld.HelloRequest"));
+        method.extend(encode_len(3, b".helloworld.Person"));
+        let mut service = encode_len(1, b"Greeter");
+        service.extend(encode_len(2, &method));
+
+        let mut file = encode_len(1, b"helloworld.proto");
+        file.extend(encode_len(2, b"helloworld"));
+        file.extend(encode_len(4, &person));
+        file.extend(encode_len(4, &request));
+        file.extend(encode_len(5, &mood));
+        file.extend(encode_len(6, &service));
+        encode_len(1, &file)
+    }
+
+    /// A HelloRequest for a happy "world", twice.
+    pub(crate) fn hello_request() -> Vec<u8> {
+        let mut person = encode_len(1, b"world");
+        person.extend(encode_varint(2, 1));
+        let mut request = encode_len(1, &person);
+        request.extend(encode_varint(2, 2));
+        request
+    }
+
+    #[test]
+    fn test_decode_with_descriptor_set() {
+        let pool = DescriptorPool::decode(&greeter_descriptor_set()).unwrap();
+        let method = pool.method("/helloworld.Greeter/SayHello").unwrap();
+        assert_eq!(method.input_type, "helloworld.HelloRequest");
+
+        let mut request = hello_request();
+        request.extend(encode_varint(9, 7));
+        let decoded = pool.decode_message(&method.input_type, &request).unwrap();
+        assert_eq!(
+            format_fields(&decoded),
+            r#"person.name: "world" person.mood: HAPPY times: 2 9: 7"#
+        );
+    }
+
+    #[test]
+    fn test_invalid_descriptor_set() {
+        assert!(DescriptorPool::decode(&[0x0a, 0x05, 0x01]).is_none());
+    }
+}
@@ -0,0 +1,293 @@
+use crate::fields::Fields;
+use crate::reassembly::{MessageBuffer, StreamData};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::net::IpAddr;
+
+// Clients that never get answers shouldn't queue without limit
+const MAX_PENDING_QUERIES: usize = 1024;
+const MAX_CONNECTIONS: usize = 65536;
+
+type Endpoint = (IpAddr, u16);
+
+/// A request and the time it took the server to answer it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTiming {
+    pub query: String,
+    /// Seconds from the request to the end of the response.
+    pub latency: f64,
+}
+
+/// Requests of one connection waiting for their responses, answered in
+/// order.
+#[derive(Debug, Default)]
+pub struct PendingQueries {
+    queue: VecDeque<(String, DateTime<Utc>)>,
+}
+
+impl PendingQueries {
+    pub fn push(&mut self, query: String, timestamp: DateTime<Utc>) {
+        if self.queue.len() >= MAX_PENDING_QUERIES {
+            self.queue.pop_front();
+        }
+        self.queue.push_back((query, timestamp));
+    }
+
+    /// Completes the oldest request with a response at `timestamp`.
+    pub fn complete(&mut self, timestamp: DateTime<Utc>) -> Option<QueryTiming> {
+        let (query, sent) = self.queue.pop_front()?;
+        Some(QueryTiming {
+            query,
+            latency: latency(sent, timestamp)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+/// Seconds between a request and its response.
+pub fn latency(sent: DateTime<Utc>, received: DateTime<Utc>) -> Option<f64> {
+    Some((received - sent).num_microseconds()? as f64 / 1e6)
+}
+
+/// A message of a request/response protocol such as a database's.
+pub trait AppMessage {
+    /// Field holding the response time of the requests a packet completes.
+    const TIME_FIELD: &'static str;
+
+    fn info(&self) -> String;
+    fn insert_fields(&self, fields: &mut Fields);
+    /// The server's error message, if this message reports one.
+    fn error(&self) -> Option<String>;
+
+    /// Something worth noting about the connection, such as the switch
+    /// to TLS.
+    fn note(&self) -> Option<String> {
+        None
+    }
+
+    /// A request worth a warning even when it succeeds, such as a write
+    /// to a controller.
+    fn warning(&self) -> Option<String> {
+        None
+    }
+}
+
+/// The messages decoded from a segment and the requests they completed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exchange<M> {
+    pub messages: Vec<M>,
+    pub timings: Vec<QueryTiming>,
+}
+
+impl<M> Default for Exchange<M> {
+    fn default() -> Self {
+        Exchange {
+            messages: Vec::new(),
+            timings: Vec::new(),
+        }
+    }
+}
+
+/// What a protocol remembers about a connection tracked in a
+/// [`ConnectionTable`].
+pub trait ConnectionState: Default {
+    /// What is kept for each direction, at least the buffer of its
+    /// incomplete message.
+    type Direction: Debug + Default + AsMut<MessageBuffer>;
+
+    /// Forgets what depended on data lost in one direction, after the
+    /// direction's buffer was emptied.
+    fn gap(&mut self, _direction: &mut Self::Direction, _from_client: bool) {}
+}
+
+impl ConnectionState for () {
+    type Direction = MessageBuffer;
+}
+
+#[derive(Debug, Default)]
+pub struct Connection<C: ConnectionState> {
+    pub state: C,
+    /// Client to server, then server to client.
+    directions: [C::Direction; 2],
+}
+
+impl<C: ConnectionState> Connection<C> {
+    pub fn direction(&mut self, from_client: bool) -> &mut C::Direction {
+        &mut self.directions[usize::from(!from_client)]
+    }
+
+    /// The direction data is sent in, next to the state it updates.
+    pub fn split(&mut self, from_client: bool) -> (&mut C::Direction, &mut C) {
+        (
+            &mut self.directions[usize::from(!from_client)],
+            &mut self.state,
+        )
+    }
+}
+
+/// The connections of a protocol carried over TCP, by client and server.
+#[derive(Debug)]
+pub struct ConnectionTable<C: ConnectionState> {
+    connections: HashMap<(Endpoint, Endpoint), Connection<C>>,
+}
+
+impl<C: ConnectionState> Default for ConnectionTable<C> {
+    fn default() -> Self {
+        ConnectionTable {
+            connections: HashMap::new(),
+        }
+    }
+}
+
+impl<C: ConnectionState> ConnectionTable<C> {
+    /// The connection from `client` to `server` that `stream` continues,
+    /// unless it brings neither data nor a gap. After a gap, the direction
+    /// it was sent in starts over.
+    pub fn update(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+    ) -> Option<&mut Connection<C>> {
+        if stream.data.is_empty() && !stream.gap {
+            return None;
+        }
+        if self.connections.len() >= MAX_CONNECTIONS
+            && !self.connections.contains_key(&(client, server))
+        {
+            self.connections.clear();
+        }
+        let connection = self.connections.entry((client, server)).or_default();
+        if stream.gap {
+            let (direction, state) = connection.split(from_client);
+            direction.as_mut().clear();
+            state.gap(direction, from_client);
+        }
+        Some(connection)
+    }
+
+    pub fn get(&self, client: Endpoint, server: Endpoint) -> Option<&Connection<C>> {
+        self.connections.get(&(client, server))
+    }
+
+    pub fn contains(&self, client: Endpoint, server: Endpoint) -> bool {
+        self.connections.contains_key(&(client, server))
+    }
+}
+
+/// Joins message names for the info column, counting runs of the same
+/// message like the rows of a result set.
+pub fn summarize<I: IntoIterator<Item = String>>(names: I) -> String {
+    let mut runs: Vec<(String, usize)> = Vec::new();
+    for name in names {
+        match runs.last_mut() {
+            Some((last, count)) if *last == name => *count += 1,
+            _ => runs.push((name, 1)),
+        }
+    }
+    runs.into_iter()
+        .map(|(name, count)| match count {
+            1 => name,
+            n => format!("{} (x{})", name, n),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries_complete_in_order() {
+        let start = Utc::now();
+        let mut pending = PendingQueries::default();
+        pending.push("SELECT 1".to_string(), start);
+        pending.push(
+            "SELECT 2".to_string(),
+            start + chrono::Duration::milliseconds(5),
+        );
+
+        let timing = pending
+            .complete(start + chrono::Duration::milliseconds(20))
+            .unwrap();
+        assert_eq!(timing.query, "SELECT 1");
+        assert_eq!(timing.latency, 0.02);
+        assert_eq!(
+            pending
+                .complete(start + chrono::Duration::milliseconds(20))
+                .unwrap()
+                .latency,
+            0.015
+        );
+        assert!(pending.complete(start).is_none());
+    }
+
+    #[derive(Debug, Default)]
+    struct Gaps(Vec<bool>);
+
+    impl ConnectionState for Gaps {
+        type Direction = MessageBuffer;
+
+        fn gap(&mut self, _: &mut MessageBuffer, from_client: bool) {
+            self.0.push(from_client);
+        }
+    }
+
+    #[test]
+    fn test_connection_table() {
+        let client = (IpAddr::from([10, 0, 0, 1]), 40000);
+        let server = (IpAddr::from([10, 0, 0, 2]), 80);
+        let data = |data: &[u8], gap| StreamData {
+            data: data.to_vec(),
+            gap,
+        };
+        let mut table = ConnectionTable::<Gaps>::default();
+        assert!(table
+            .update(client, server, true, &data(b"", false))
+            .is_none());
+        assert!(!table.contains(client, server));
+
+        let connection = table.update(client, server, false, &data(b"ab", false));
+        connection.unwrap().direction(false).push(b"ab");
+        let connection = table.update(client, server, true, &data(b"cd", false));
+        connection.unwrap().direction(true).push(b"cd");
+
+        let connection = table
+            .update(client, server, false, &data(b"", true))
+            .unwrap();
+        assert_eq!(connection.state.0, vec![false]);
+        assert!(connection.direction(false).data().is_empty());
+        assert_eq!(connection.direction(true).data(), b"cd");
+
+        // A full table starts over
+        for port in 0..=u16::MAX {
+            let other = (IpAddr::from([10, 0, 0, 3]), port);
+            table.update(other, server, true, &data(b"x", false));
+        }
+        assert!(!table.contains(client, server));
+    }
+
+    #[test]
+    fn test_summarize_runs() {
+        let names = [
+            "Row description",
+            "Data row",
+            "Data row",
+            "Command completion",
+        ];
+        assert_eq!(
+            summarize(names.iter().map(|n| n.to_string())),
+            "Row description, Data row (x2), Command completion"
+        );
+    }
+}
@@ -0,0 +1,925 @@
+use crate::fields::Fields;
+use crate::tls::{self, ClientHello};
+use aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+use aes::Aes128;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes128Gcm, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+
+pub const QUIC_PORT: u16 = 443;
+
+pub const VERSION_1: u32 = 0x0000_0001;
+pub const VERSION_2: u32 = 0x6b33_43cf;
+
+const MAX_CID_LEN: usize = 20;
+
+// Upper bound on tracked connection IDs and address pairs so a long capture
+// can't grow without limit
+const MAX_CONNECTIONS: usize = 65536;
+// A ClientHello spanning more than this is not worth buffering
+const MAX_CRYPTO_LEN: usize = 65536;
+
+const FRAME_PADDING: u64 = 0x00;
+const FRAME_PING: u64 = 0x01;
+const FRAME_ACK: u64 = 0x02;
+const FRAME_ACK_ECN: u64 = 0x03;
+const FRAME_CRYPTO: u64 = 0x06;
+const FRAME_CONNECTION_CLOSE: u64 = 0x1c;
+const FRAME_CONNECTION_CLOSE_APP: u64 = 0x1d;
+
+/// Salts and labels for deriving Initial keys (RFC 9001 section 5.2 and
+/// RFC 9369 section 3.3).
+struct InitialParams {
+    salt: [u8; 20],
+    key_label: &'static str,
+    iv_label: &'static str,
+    hp_label: &'static str,
+}
+
+const V1_PARAMS: InitialParams = InitialParams {
+    salt: [
+        0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c,
+        0xad, 0xcc, 0xbb, 0x7f, 0x0a,
+    ],
+    key_label: "quic key",
+    iv_label: "quic iv",
+    hp_label: "quic hp",
+};
+
+const V2_PARAMS: InitialParams = InitialParams {
+    salt: [
+        0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d,
+        0xcb, 0xf9, 0xbd, 0x2e, 0xd9,
+    ],
+    key_label: "quicv2 key",
+    iv_label: "quicv2 iv",
+    hp_label: "quicv2 hp",
+};
+
+// Drafts 29 to 32 were widely deployed before version 1
+const DRAFT_29_PARAMS: InitialParams = InitialParams {
+    salt: [
+        0xaf, 0xbf, 0xec, 0x28, 0x99, 0x93, 0xd2, 0x4c, 0x9e, 0x97, 0x86, 0xf1, 0x9c, 0x61, 0x11,
+        0xe0, 0x43, 0x90, 0xa8, 0x99,
+    ],
+    key_label: "quic key",
+    iv_label: "quic iv",
+    hp_label: "quic hp",
+};
+
+fn initial_params(version: u32) -> Option<&'static InitialParams> {
+    match version {
+        VERSION_1 => Some(&V1_PARAMS),
+        VERSION_2 => Some(&V2_PARAMS),
+        0xff00_001d..=0xff00_0020 => Some(&DRAFT_29_PARAMS),
+        _ => None,
+    }
+}
+
+pub fn version_name(version: u32) -> String {
+    match version {
+        VERSION_1 => "1".to_string(),
+        VERSION_2 => "2".to_string(),
+        v if v >> 8 == 0xff_0000 => format!("draft-{}", v & 0xff),
+        // Versions reserved to exercise version negotiation (RFC 9000
+        // section 15)
+        v if v & 0x0f0f_0f0f == 0x0a0a_0a0a => "Forcing Version Negotiation".to_string(),
+        v => format!("0x{:08x}", v),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketType {
+    Initial,
+    ZeroRtt,
+    Handshake,
+    Retry,
+    VersionNegotiation,
+    /// Short header packet.
+    OneRtt,
+}
+
+impl PacketType {
+    fn from_long_bits(version: u32, bits: u8) -> PacketType {
+        // Version 2 shuffled the long header packet types
+        let bits = if version == VERSION_2 {
+            bits.wrapping_sub(1) & 0x03
+        } else {
+            bits
+        };
+        match bits {
+            0 => PacketType::Initial,
+            1 => PacketType::ZeroRtt,
+            2 => PacketType::Handshake,
+            _ => PacketType::Retry,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PacketType::Initial => "Initial",
+            PacketType::ZeroRtt => "0-RTT",
+            PacketType::Handshake => "Handshake",
+            PacketType::Retry => "Retry",
+            PacketType::VersionNegotiation => "Version Negotiation",
+            PacketType::OneRtt => "Protected Payload",
+        }
+    }
+}
+
+/// A frame of a decrypted Initial packet. Only the frames allowed in
+/// Initial packets are decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Padding(usize),
+    Ping,
+    Ack { largest: u64 },
+    Crypto { offset: u64, data: Vec<u8> },
+    ConnectionClose { error_code: u64, reason: String },
+}
+
+impl Frame {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Frame::Padding(_) => "PADDING",
+            Frame::Ping => "PING",
+            Frame::Ack { .. } => "ACK",
+            Frame::Crypto { .. } => "CRYPTO",
+            Frame::ConnectionClose { .. } => "CONNECTION_CLOSE",
+        }
+    }
+
+    fn frame_type(&self) -> u64 {
+        match self {
+            Frame::Padding(_) => FRAME_PADDING,
+            Frame::Ping => FRAME_PING,
+            Frame::Ack { .. } => FRAME_ACK,
+            Frame::Crypto { .. } => FRAME_CRYPTO,
+            Frame::ConnectionClose { .. } => FRAME_CONNECTION_CLOSE,
+        }
+    }
+}
+
+/// One QUIC packet (RFC 9000 section 17). A UDP datagram can carry several.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuicPacket {
+    pub packet_type: PacketType,
+    /// None for short header packets.
+    pub version: Option<u32>,
+    /// Empty when a short header's connection ID length is unknown.
+    pub dcid: Vec<u8>,
+    pub scid: Vec<u8>,
+    pub token_length: Option<u64>,
+    pub length: Option<u64>,
+    pub supported_versions: Vec<u32>,
+    pub spin_bit: Option<bool>,
+    /// Set for Initial packets sent by the client, which can be decrypted.
+    pub packet_number: Option<u64>,
+    pub frames: Vec<Frame>,
+}
+
+impl QuicPacket {
+    fn new(packet_type: PacketType, version: Option<u32>, dcid: &[u8]) -> QuicPacket {
+        QuicPacket {
+            packet_type,
+            version,
+            dcid: dcid.to_vec(),
+            scid: Vec::new(),
+            token_length: None,
+            length: None,
+            supported_versions: Vec::new(),
+            spin_bit: None,
+            packet_number: None,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Parses a long header packet, returning it with its length in the
+    /// datagram.
+    fn parse_long(packet: &[u8]) -> Option<(QuicPacket, usize)> {
+        let mut reader = Reader::new(packet);
+        let first = reader.u8()?;
+        let version = reader.u32()?;
+        let dcid_len = usize::from(reader.u8()?);
+        let dcid = reader.bytes(dcid_len)?;
+        let scid_len = usize::from(reader.u8()?);
+        let scid = reader.bytes(scid_len)?;
+
+        if version == 0 {
+            let mut quic = QuicPacket::new(PacketType::VersionNegotiation, Some(0), dcid);
+            quic.scid = scid.to_vec();
+            quic.supported_versions = reader
+                .rest()
+                .chunks_exact(4)
+                .map(|v| u32::from_be_bytes([v[0], v[1], v[2], v[3]]))
+                .collect();
+            return Some((quic, packet.len()));
+        }
+        if dcid_len > MAX_CID_LEN || scid_len > MAX_CID_LEN {
+            return None;
+        }
+
+        let packet_type = PacketType::from_long_bits(version, (first >> 4) & 0x03);
+        let mut quic = QuicPacket::new(packet_type, Some(version), dcid);
+        quic.scid = scid.to_vec();
+        match packet_type {
+            // Retry packets carry a token and an integrity tag up to the end
+            PacketType::Retry => return Some((quic, packet.len())),
+            PacketType::Initial => {
+                let token_length = reader.varint()?;
+                reader.bytes(usize::try_from(token_length).ok()?)?;
+                quic.token_length = Some(token_length);
+            }
+            _ => {}
+        }
+        let length = reader.varint()?;
+        quic.length = Some(length);
+        let pn_offset = reader.position();
+        let end = pn_offset.checked_add(usize::try_from(length).ok()?)?;
+        let packet = packet.get(..end)?;
+
+        if packet_type == PacketType::Initial {
+            if let Some((packet_number, payload)) =
+                decrypt_initial(version, dcid, packet, pn_offset)
+            {
+                quic.packet_number = Some(packet_number);
+                quic.frames = parse_frames(&payload);
+            }
+        }
+        Some((quic, end))
+    }
+
+    fn parse_short(packet: &[u8], dcid_len: Option<usize>) -> QuicPacket {
+        let dcid = dcid_len
+            .and_then(|len| packet.get(1..1 + len))
+            .unwrap_or_default();
+        let mut quic = QuicPacket::new(PacketType::OneRtt, None, dcid);
+        quic.spin_bit = Some(packet[0] & 0x20 != 0);
+        quic
+    }
+
+    pub fn is_long_header(&self) -> bool {
+        self.version.is_some()
+    }
+
+    pub fn info(&self) -> String {
+        let mut info = self.packet_type.name().to_string();
+        if !self.dcid.is_empty() {
+            info.push_str(&format!(", DCID={}", hex(&self.dcid)));
+        }
+        if !self.scid.is_empty() {
+            info.push_str(&format!(", SCID={}", hex(&self.scid)));
+        }
+        if let Some(packet_number) = self.packet_number {
+            info.push_str(&format!(", PKN: {}", packet_number));
+        }
+        let mut previous = None;
+        for frame in &self.frames {
+            // Runs of the same frame, like ACKs or PADDING, are named once
+            if previous != Some(frame.name()) {
+                info.push_str(&format!(", {}", frame.name()));
+                previous = Some(frame.name());
+            }
+        }
+        if !self.supported_versions.is_empty() {
+            let versions: Vec<_> = self
+                .supported_versions
+                .iter()
+                .map(|v| version_name(*v))
+                .collect();
+            info.push_str(&format!(", Supported Versions: {}", versions.join(", ")));
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("quic.header_form", u8::from(self.is_long_header()));
+        if let Some(version) = self.version {
+            fields.insert("quic.version", version);
+            fields.insert("quic.dcil", self.dcid.len());
+        }
+        if !self.dcid.is_empty() {
+            fields.insert("quic.dcid", self.dcid.clone());
+        }
+        if self.is_long_header() {
+            fields.insert("quic.scil", self.scid.len());
+            if !self.scid.is_empty() {
+                fields.insert("quic.scid", self.scid.clone());
+            }
+        }
+        if let Some(spin_bit) = self.spin_bit {
+            fields.insert("quic.spin_bit", spin_bit);
+        }
+        for version in &self.supported_versions {
+            fields.insert("quic.supported_version", *version);
+        }
+        if let Some(token_length) = self.token_length {
+            fields.insert("quic.token_length", token_length);
+        }
+        if let Some(length) = self.length {
+            fields.insert("quic.length", length);
+        }
+        if let Some(packet_number) = self.packet_number {
+            fields.insert("quic.packet_number", packet_number);
+        }
+        for frame in &self.frames {
+            fields.insert("quic.frame_type", frame.frame_type());
+            match frame {
+                Frame::Ack { largest } => fields.insert("quic.ack.largest_acknowledged", *largest),
+                Frame::Crypto { offset, data } => {
+                    fields.insert("quic.crypto.offset", *offset);
+                    fields.insert("quic.crypto.length", data.len());
+                }
+                Frame::ConnectionClose { error_code, reason } => {
+                    fields.insert("quic.cc.error_code", *error_code);
+                    if !reason.is_empty() {
+                        fields.insert("quic.cc.reason_phrase", reason.as_str());
+                    }
+                }
+                Frame::Padding(_) | Frame::Ping => {}
+            }
+        }
+    }
+}
+
+/// Parses the coalesced packets of a UDP datagram. `short_dcid_len` looks up
+/// the connection ID length of a short header packet, which the header
+/// doesn't encode; it's given the packet without its first byte.
+pub fn parse_datagram(
+    datagram: &[u8],
+    short_dcid_len: impl Fn(&[u8]) -> Option<usize>,
+) -> Vec<QuicPacket> {
+    let mut packets = Vec::new();
+    let mut rest = datagram;
+    while let Some(&first) = rest.first() {
+        if first & 0x80 != 0 {
+            match QuicPacket::parse_long(rest) {
+                Some((packet, len)) => {
+                    packets.push(packet);
+                    rest = &rest[len..];
+                }
+                None => break,
+            }
+        } else {
+            // A short header packet fills the rest of the datagram. Without
+            // the fixed bit, this is padding or not QUIC at all.
+            if first & 0x40 != 0 {
+                packets.push(QuicPacket::parse_short(rest, short_dcid_len(&rest[1..])));
+            }
+            break;
+        }
+    }
+    packets
+}
+
+fn parse_frames(payload: &[u8]) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut reader = Reader::new(payload);
+    while let Some(frame_type) = reader.varint() {
+        let frame = match frame_type {
+            FRAME_PADDING => {
+                let mut len = 1;
+                while reader.rest().first() == Some(&0) {
+                    reader.bytes(1);
+                    len += 1;
+                }
+                Frame::Padding(len)
+            }
+            FRAME_PING => Frame::Ping,
+            FRAME_ACK | FRAME_ACK_ECN => {
+                match parse_ack(&mut reader, frame_type == FRAME_ACK_ECN) {
+                    Some(largest) => Frame::Ack { largest },
+                    None => break,
+                }
+            }
+            FRAME_CRYPTO => {
+                let data = reader.varint().and_then(|offset| {
+                    let len = usize::try_from(reader.varint()?).ok()?;
+                    Some((offset, reader.bytes(len)?.to_vec()))
+                });
+                match data {
+                    Some((offset, data)) => Frame::Crypto { offset, data },
+                    None => break,
+                }
+            }
+            FRAME_CONNECTION_CLOSE | FRAME_CONNECTION_CLOSE_APP => {
+                let close = reader.varint().and_then(|error_code| {
+                    if frame_type == FRAME_CONNECTION_CLOSE {
+                        // Type of the frame that triggered the error
+                        reader.varint()?;
+                    }
+                    let len = usize::try_from(reader.varint()?).ok()?;
+                    let reason = String::from_utf8_lossy(reader.bytes(len)?).into_owned();
+                    Some(Frame::ConnectionClose { error_code, reason })
+                });
+                match close {
+                    Some(close) => close,
+                    None => break,
+                }
+            }
+            // Other frames aren't allowed in Initial packets
+            _ => break,
+        };
+        frames.push(frame);
+    }
+    frames
+}
+
+/// Returns the largest acknowledged packet number of an ACK frame.
+fn parse_ack(reader: &mut Reader, ecn: bool) -> Option<u64> {
+    let largest = reader.varint()?;
+    let _delay = reader.varint()?;
+    let range_count = reader.varint()?;
+    let _first_range = reader.varint()?;
+    for _ in 0..range_count {
+        let _gap = reader.varint()?;
+        let _range = reader.varint()?;
+    }
+    if ecn {
+        for _ in 0..3 {
+            reader.varint()?;
+        }
+    }
+    Some(largest)
+}
+
+/// Packet protection keys for one direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InitialKeys {
+    key: [u8; 16],
+    iv: [u8; 12],
+    hp: [u8; 16],
+}
+
+/// Derives the client's Initial keys from the Destination Connection ID of
+/// the client's first Initial packet (RFC 9001 section 5.2).
+fn client_initial_keys(version: u32, dcid: &[u8]) -> Option<InitialKeys> {
+    let params = initial_params(version)?;
+    let (initial_secret, _) = Hkdf::<Sha256>::extract(Some(&params.salt), dcid);
+    let mut client_secret = [0; 32];
+    expand_label(&initial_secret, "client in", &mut client_secret)?;
+
+    let mut keys = InitialKeys {
+        key: [0; 16],
+        iv: [0; 12],
+        hp: [0; 16],
+    };
+    expand_label(&client_secret, params.key_label, &mut keys.key)?;
+    expand_label(&client_secret, params.iv_label, &mut keys.iv)?;
+    expand_label(&client_secret, params.hp_label, &mut keys.hp)?;
+    Some(keys)
+}
+
+/// HKDF-Expand-Label from TLS 1.3 with an empty context.
+fn expand_label(secret: &[u8], label: &str, out: &mut [u8]) -> Option<()> {
+    let hkdf = Hkdf::<Sha256>::from_prk(secret).ok()?;
+    let label = format!("tls13 {}", label);
+    let mut info = (out.len() as u16).to_be_bytes().to_vec();
+    info.push(label.len() as u8);
+    info.extend_from_slice(label.as_bytes());
+    info.push(0);
+    hkdf.expand(&info, out).ok()
+}
+
+fn header_protection_mask(hp: &[u8; 16], sample: &[u8]) -> [u8; 5] {
+    let cipher = Aes128::new(GenericArray::from_slice(hp));
+    let mut block = GenericArray::clone_from_slice(&sample[..16]);
+    cipher.encrypt_block(&mut block);
+    let mut mask = [0; 5];
+    mask.copy_from_slice(&block[..5]);
+    mask
+}
+
+/// Removes header protection from an Initial packet and decrypts it with
+/// the client's keys. Returns the packet number and the plaintext payload,
+/// or None for server packets and anything that fails authentication.
+fn decrypt_initial(
+    version: u32,
+    dcid: &[u8],
+    packet: &[u8],
+    pn_offset: usize,
+) -> Option<(u64, Vec<u8>)> {
+    let keys = client_initial_keys(version, dcid)?;
+    // The sample starts 4 bytes into the packet number field, whatever its
+    // actual length
+    let sample = packet.get(pn_offset + 4..pn_offset + 20)?;
+    let mask = header_protection_mask(&keys.hp, sample);
+
+    let mut header = packet[..pn_offset + 4].to_vec();
+    header[0] ^= mask[0] & 0x0f;
+    let pn_len = usize::from(header[0] & 0x03) + 1;
+    header.truncate(pn_offset + pn_len);
+    let mut packet_number = 0u64;
+    for (i, byte) in header[pn_offset..].iter_mut().enumerate() {
+        *byte ^= mask[1 + i];
+        packet_number = packet_number << 8 | u64::from(*byte);
+    }
+
+    let mut nonce = keys.iv;
+    for (n, p) in nonce
+        .iter_mut()
+        .rev()
+        .zip(packet_number.to_be_bytes().iter().rev())
+    {
+        *n ^= p;
+    }
+    let cipher = Aes128Gcm::new(GenericArray::from_slice(&keys.key));
+    let payload = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &packet[pn_offset + pn_len..],
+                aad: &header,
+            },
+        )
+        .ok()?;
+    Some((packet_number, payload))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, position: 0 }
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Variable-length integer with a 2 bit length prefix.
+    fn varint(&mut self) -> Option<u64> {
+        let first = *self.rest().first()?;
+        let len = 1 << (first >> 6);
+        let bytes = self.bytes(len)?;
+        Some(bytes[1..].iter().fold(u64::from(first & 0x3f), |value, b| {
+            value << 8 | u64::from(*b)
+        }))
+    }
+}
+
+/// What the tracker knows about the connection of a datagram.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuicFlow {
+    /// Connection number, counting from 0 in order of appearance.
+    pub connection: usize,
+    /// The client's ClientHello, if this datagram completed it.
+    pub client_hello: Option<ClientHello>,
+}
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// CRYPTO frame data of the client's Initial packets, by offset.
+    crypto: Vec<(u64, Vec<u8>)>,
+    hello_seen: bool,
+}
+
+impl Connection {
+    /// Adds CRYPTO data and returns the ClientHello once it's complete.
+    fn add_crypto(&mut self, offset: u64, data: &[u8]) -> Option<ClientHello> {
+        if self.hello_seen {
+            return None;
+        }
+        let buffered: usize = self.crypto.iter().map(|(_, d)| d.len()).sum();
+        if buffered + data.len() > MAX_CRYPTO_LEN {
+            self.crypto.clear();
+            return None;
+        }
+        self.crypto.push((offset, data.to_vec()));
+        self.crypto.sort_by_key(|(offset, _)| *offset);
+
+        // Reassemble the contiguous data from offset 0
+        let mut stream = Vec::new();
+        for (offset, data) in &self.crypto {
+            let offset = usize::try_from(*offset).ok()?;
+            if offset > stream.len() {
+                break;
+            }
+            if offset + data.len() > stream.len() {
+                stream.extend_from_slice(&data[stream.len() - offset..]);
+            }
+        }
+        if stream.len() < tls::message_len(&stream)? {
+            return None;
+        }
+        self.hello_seen = true;
+        self.crypto.clear();
+        ClientHello::parse(&stream)
+    }
+}
+
+type Endpoints = ((IpAddr, u16), (IpAddr, u16));
+
+/// Groups QUIC packets into connections by connection ID, falling back to
+/// the address pair when no known connection ID is present.
+#[derive(Debug, Default)]
+pub struct QuicTracker {
+    cids: HashMap<Vec<u8>, usize>,
+    cid_lengths: BTreeSet<usize>,
+    endpoints: HashMap<Endpoints, usize>,
+    connections: HashMap<usize, Connection>,
+    next_connection: usize,
+}
+
+impl QuicTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Length of the known connection ID a short header packet starts with.
+    pub fn dcid_len(&self, packet: &[u8]) -> Option<usize> {
+        // Prefer the longest match, a short ID could be a prefix of another
+        self.cid_lengths
+            .iter()
+            .rev()
+            .find(|len| {
+                packet
+                    .get(..**len)
+                    .is_some_and(|cid| self.cids.contains_key(cid))
+            })
+            .copied()
+    }
+
+    pub fn process(
+        &mut self,
+        src: (IpAddr, u16),
+        dst: (IpAddr, u16),
+        packets: &[QuicPacket],
+    ) -> QuicFlow {
+        if self.cids.len() >= MAX_CONNECTIONS || self.endpoints.len() >= MAX_CONNECTIONS {
+            self.cids.clear();
+            self.cid_lengths.clear();
+            self.endpoints.clear();
+            self.connections.clear();
+        }
+
+        let endpoints = if src <= dst { (src, dst) } else { (dst, src) };
+        let known = packets
+            .iter()
+            .flat_map(|p| [&p.dcid, &p.scid])
+            .filter(|cid| !cid.is_empty())
+            .find_map(|cid| self.cids.get(cid.as_slice()).copied());
+        // An Initial with unknown connection IDs starts a new connection,
+        // even when the client reuses its address and port
+        let starts_connection = packets
+            .first()
+            .is_some_and(|p| p.packet_type == PacketType::Initial);
+        let connection = match known {
+            Some(connection) => connection,
+            None => match self.endpoints.get(&endpoints) {
+                Some(connection) if !starts_connection => *connection,
+                _ => {
+                    self.next_connection += 1;
+                    self.next_connection - 1
+                }
+            },
+        };
+
+        self.endpoints.insert(endpoints, connection);
+        for packet in packets {
+            for cid in [&packet.dcid, &packet.scid] {
+                if !cid.is_empty() {
+                    self.cids.insert(cid.clone(), connection);
+                    self.cid_lengths.insert(cid.len());
+                }
+            }
+        }
+
+        let state = self.connections.entry(connection).or_default();
+        let mut client_hello = None;
+        for packet in packets {
+            for frame in &packet.frames {
+                if let Frame::Crypto { offset, data } = frame {
+                    client_hello = client_hello.or(state.add_crypto(*offset, data));
+                }
+            }
+        }
+
+        QuicFlow {
+            connection,
+            client_hello,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::tls::tests::client_hello;
+
+    pub(crate) const DCID: [u8; 8] = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn varint(value: u64) -> Vec<u8> {
+        // Always the 2 byte form, which covers everything in these tests
+        (0x4000 | value as u16).to_be_bytes().to_vec()
+    }
+
+    /// A protected client Initial carrying `crypto` at `offset`, padded to
+    /// the 1200 bytes clients must send.
+    pub(crate) fn client_initial(dcid: &[u8], scid: &[u8], offset: u64, crypto: &[u8]) -> Vec<u8> {
+        let keys = client_initial_keys(VERSION_1, dcid).unwrap();
+        let packet_number = 2u64;
+
+        let mut payload = vec![FRAME_CRYPTO as u8];
+        payload.extend(varint(offset));
+        payload.extend(varint(crypto.len() as u64));
+        payload.extend_from_slice(crypto);
+
+        let mut header = vec![0xc3];
+        header.extend_from_slice(&VERSION_1.to_be_bytes());
+        header.push(dcid.len() as u8);
+        header.extend_from_slice(dcid);
+        header.push(scid.len() as u8);
+        header.extend_from_slice(scid);
+        header.push(0); // no token
+        let length_offset = header.len();
+        // Packet number and tag are 4 and 16 bytes
+        let length = 1200 - length_offset - 2;
+        payload.resize(length - 4 - 16, 0);
+        header.extend(varint(length as u64));
+        let pn_offset = header.len();
+        header.extend_from_slice(&(packet_number as u32).to_be_bytes());
+
+        let mut nonce = keys.iv;
+        for (n, p) in nonce
+            .iter_mut()
+            .rev()
+            .zip(packet_number.to_be_bytes().iter().rev())
+        {
+            *n ^= p;
+        }
+        let cipher = Aes128Gcm::new(GenericArray::from_slice(&keys.key));
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &payload,
+                    aad: &header,
+                },
+            )
+            .unwrap();
+
+        let mut packet = header;
+        packet.extend(ciphertext);
+        let mask = header_protection_mask(&keys.hp, &packet[pn_offset + 4..pn_offset + 20]);
+        packet[0] ^= mask[0] & 0x0f;
+        for i in 0..4 {
+            packet[pn_offset + i] ^= mask[1 + i];
+        }
+        packet
+    }
+
+    #[test]
+    fn test_initial_keys() {
+        // RFC 9001 appendix A.1
+        let keys = client_initial_keys(VERSION_1, &DCID).unwrap();
+        assert_eq!(keys.key.to_vec(), unhex("1f369613dd76d5467730efcbe3b1a22d"));
+        assert_eq!(keys.iv.to_vec(), unhex("fa044b2f42a3fd3b46fb255c"));
+        assert_eq!(keys.hp.to_vec(), unhex("9f50449e04a0e810283a1e9933adedd2"));
+
+        // RFC 9001 appendix A.2
+        let sample = unhex("d1b1c98dd7689fb8ec11d242b123dc9b");
+        assert_eq!(
+            header_protection_mask(&keys.hp, &sample).to_vec(),
+            unhex("437b9aec36")
+        );
+    }
+
+    #[test]
+    fn test_decrypt_client_initial() {
+        let hello = client_hello("example.com", &["h3"]);
+        let datagram = client_initial(&DCID, &[0xaa, 0xbb], 0, &hello);
+
+        let packets = parse_datagram(&datagram, |_| None);
+        assert_eq!(packets.len(), 1);
+        let packet = &packets[0];
+        assert_eq!(packet.packet_type, PacketType::Initial);
+        assert_eq!(packet.packet_number, Some(2));
+        assert_eq!(
+            packet.info(),
+            "Initial, DCID=8394c8f03e515708, SCID=aabb, PKN: 2, CRYPTO, PADDING"
+        );
+
+        let mut tracker = QuicTracker::new();
+        let client = ("10.0.0.1".parse().unwrap(), 50000);
+        let server = ("10.0.0.2".parse().unwrap(), QUIC_PORT);
+        let flow = tracker.process(client, server, &packets);
+        let hello = flow.client_hello.unwrap();
+        assert_eq!(hello.server_name.as_deref(), Some("example.com"));
+        assert_eq!(hello.alpn, vec!["h3"]);
+    }
+
+    #[test]
+    fn test_client_hello_across_datagrams() {
+        let hello = client_hello("example.com", &["h3"]);
+        let (first, second) = hello.split_at(20);
+        let mut tracker = QuicTracker::new();
+        let client = ("10.0.0.1".parse().unwrap(), 50000);
+        let server = ("10.0.0.2".parse().unwrap(), QUIC_PORT);
+
+        let datagram = client_initial(&DCID, &[0xaa], 20, second);
+        let flow = tracker.process(client, server, &parse_datagram(&datagram, |_| None));
+        assert!(flow.client_hello.is_none());
+
+        let datagram = client_initial(&DCID, &[0xaa], 0, first);
+        let flow = tracker.process(client, server, &parse_datagram(&datagram, |_| None));
+        assert_eq!(
+            flow.client_hello.unwrap().server_name.as_deref(),
+            Some("example.com")
+        );
+    }
+
+    #[test]
+    fn test_connection_grouping() {
+        let mut tracker = QuicTracker::new();
+        let client = ("10.0.0.1".parse().unwrap(), 50000);
+        let server = ("10.0.0.2".parse().unwrap(), QUIC_PORT);
+        let client_cid = [0xaa, 0xbb];
+        let server_cid = [0x11, 0x22, 0x33, 0x44];
+
+        let hello = client_hello("example.com", &[]);
+        let initial = client_initial(&DCID, &client_cid, 0, &hello);
+        let flow = tracker.process(client, server, &parse_datagram(&initial, |_| None));
+        assert_eq!(flow.connection, 0);
+
+        // Server Handshake packet from the server's connection ID
+        let mut handshake = vec![0xe0];
+        handshake.extend_from_slice(&VERSION_1.to_be_bytes());
+        handshake.push(2);
+        handshake.extend_from_slice(&client_cid);
+        handshake.push(4);
+        handshake.extend_from_slice(&server_cid);
+        handshake.extend(varint(20));
+        handshake.extend_from_slice(&[0; 20]);
+        let packets = parse_datagram(&handshake, |_| None);
+        assert_eq!(packets[0].packet_type, PacketType::Handshake);
+        assert_eq!(tracker.process(server, client, &packets).connection, 0);
+
+        // The client migrated to another address, the short header packet
+        // still belongs to the connection
+        let mut short = vec![0x40];
+        short.extend_from_slice(&server_cid);
+        short.extend_from_slice(&[0; 30]);
+        let packets = parse_datagram(&short, |p| tracker.dcid_len(p));
+        assert_eq!(packets[0].dcid, server_cid);
+        let migrated = ("10.0.0.3".parse().unwrap(), 50001);
+        assert_eq!(tracker.process(migrated, server, &packets).connection, 0);
+
+        // A new Initial from the same address is another connection
+        let initial = client_initial(&[1; 8], &[0xcc], 0, &hello);
+        let flow = tracker.process(client, server, &parse_datagram(&initial, |_| None));
+        assert_eq!(flow.connection, 1);
+    }
+
+    #[test]
+    fn test_version_negotiation() {
+        let mut packet = vec![0x80, 0, 0, 0, 0, 1, 0xaa, 1, 0xbb];
+        packet.extend_from_slice(&VERSION_1.to_be_bytes());
+        packet.extend_from_slice(&VERSION_2.to_be_bytes());
+
+        let packets = parse_datagram(&packet, |_| None);
+        assert_eq!(packets[0].packet_type, PacketType::VersionNegotiation);
+        assert_eq!(
+            packets[0].info(),
+            "Version Negotiation, DCID=aa, SCID=bb, Supported Versions: 1, 2"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use crate::tcp_analysis::TcpSegment;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+// Upper bounds on buffered state so a long capture can't grow without limit
+const MAX_STREAMS: usize = 65536;
+const MAX_PENDING_SEGMENTS: usize = 256;
+const MAX_MESSAGE_LEN: usize = 1 << 20;
+// Text protocols limit lines to about 1000 bytes
+const MAX_LINE_LEN: usize = 1 << 16;
+
+type Endpoint = (IpAddr, u16);
+
+/// Bytes of a TCP stream that became contiguous with a segment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamData {
+    pub data: Vec<u8>,
+    /// Data was lost before `data`, so it doesn't continue what was
+    /// delivered earlier.
+    pub gap: bool,
+}
+
+impl StreamData {
+    /// Data following on from what was delivered before, or a datagram.
+    pub fn complete(data: &[u8]) -> Self {
+        StreamData {
+            data: data.to_vec(),
+            gap: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Stream {
+    next_seq: u32,
+    /// Segments received ahead of `next_seq`.
+    pending: Vec<(u32, Vec<u8>)>,
+}
+
+impl Stream {
+    fn new(next_seq: u32) -> Self {
+        Stream {
+            next_seq,
+            pending: Vec::new(),
+        }
+    }
+
+    fn deliver(&mut self, seq: u32, payload: &[u8]) -> StreamData {
+        let mut delivered = StreamData::default();
+        if !payload.is_empty() {
+            self.pending.push((seq, payload.to_vec()));
+        }
+        if self.pending.len() > MAX_PENDING_SEGMENTS {
+            // The missing data isn't coming, continue after the hole
+            let earliest = self
+                .pending
+                .iter()
+                .map(|(seq, _)| *seq)
+                .min_by_key(|seq| seq.wrapping_sub(self.next_seq))
+                .unwrap_or(self.next_seq);
+            self.next_seq = earliest;
+            delivered.gap = true;
+        }
+
+        while let Some(index) = self
+            .pending
+            .iter()
+            .position(|(seq, _)| seq_le(*seq, self.next_seq))
+        {
+            let (seq, data) = self.pending.swap_remove(index);
+            // Skip what was delivered already, retransmissions are dropped
+            let skip = self.next_seq.wrapping_sub(seq) as usize;
+            if skip < data.len() {
+                delivered.data.extend_from_slice(&data[skip..]);
+                self.next_seq = self.next_seq.wrapping_add((data.len() - skip) as u32);
+            }
+        }
+        delivered
+    }
+}
+
+/// Reassembles the byte streams of TCP connections from segments arriving
+/// out of order or more than once.
+#[derive(Default)]
+pub struct TcpReassembler {
+    streams: HashMap<(Endpoint, Endpoint), Stream>,
+}
+
+impl TcpReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a segment sent from `src` to `dst` and returns the stream data
+    /// it completes.
+    pub fn process(
+        &mut self,
+        src: Endpoint,
+        dst: Endpoint,
+        segment: &TcpSegment,
+        payload: &[u8],
+    ) -> StreamData {
+        if segment.rst {
+            self.streams.remove(&(src, dst));
+            return StreamData::default();
+        }
+        if self.streams.len() >= MAX_STREAMS {
+            self.streams.clear();
+        }
+
+        // The stream starts after the SYN, or with the first segment of a
+        // connection that was already open when the capture started
+        let stream = self
+            .streams
+            .entry((src, dst))
+            .or_insert_with(|| Stream::new(segment.seq));
+        if segment.syn {
+            *stream = Stream::new(segment.seq.wrapping_add(1));
+        }
+        let seq = segment.seq.wrapping_add(u32::from(segment.syn));
+        stream.deliver(seq, payload)
+    }
+}
+
+/// Buffers one direction of a stream until whole messages of an
+/// application protocol are available.
+#[derive(Debug, Default)]
+pub struct MessageBuffer {
+    data: Vec<u8>,
+    /// Rest of a message too large to buffer, dropped as it arrives.
+    skip: usize,
+}
+
+impl MessageBuffer {
+    pub fn push(&mut self, data: &[u8]) {
+        let skipped = self.skip.min(data.len());
+        self.skip -= skipped;
+        self.data.extend_from_slice(&data[skipped..]);
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Removes a message of `len` bytes from the front, once it's complete.
+    /// Messages too large to buffer are cut short instead and the rest of
+    /// them is skipped.
+    pub fn take(&mut self, len: usize) -> Option<Vec<u8>> {
+        if self.data.len() >= len {
+            return Some(self.data.drain(..len).collect());
+        }
+        if len > MAX_MESSAGE_LEN && self.data.len() >= MAX_MESSAGE_LEN {
+            self.skip = len - self.data.len();
+            return Some(std::mem::take(&mut self.data));
+        }
+        None
+    }
+
+    /// Removes a line ending in LF from the front, returning it without
+    /// the line ending. Overlong lines are returned as they are.
+    pub fn take_line(&mut self) -> Option<String> {
+        let len = match self.data.iter().position(|&b| b == b'\n') {
+            Some(end) => end + 1,
+            None if self.data.len() > MAX_LINE_LEN => self.data.len(),
+            None => return None,
+        };
+        let line: Vec<u8> = self.data.drain(..len).collect();
+        let line = String::from_utf8_lossy(&line);
+        Some(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.skip = 0;
+    }
+}
+
+impl AsMut<MessageBuffer> for MessageBuffer {
+    fn as_mut(&mut self) -> &mut MessageBuffer {
+        self
+    }
+}
+
+/// `a <= b` in sequence space.
+fn seq_le(a: u32, b: u32) -> bool {
+    b.wrapping_sub(a) < 1 << 31
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::net::Ipv4Addr;
+
+    pub(crate) const CLIENT: Endpoint = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 40000);
+    pub(crate) const SERVER: Endpoint = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 80);
+
+    fn segment(seq: u32, syn: bool, payload_len: usize) -> TcpSegment {
+        TcpSegment {
+            seq,
+            ack: 0,
+            syn,
+            ack_flag: !syn,
+            fin: false,
+            rst: false,
+            window: 65535,
+            window_scale: None,
+            payload_len,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_out_of_order_and_retransmitted_segments() {
+        let mut reassembler = TcpReassembler::new();
+        let client = ("10.0.0.1".parse().unwrap(), 40000);
+        let server = ("10.0.0.2".parse().unwrap(), 80);
+        let mut send = |seq: u32, payload: &[u8]| {
+            reassembler
+                .process(client, server, &segment(seq, false, payload.len()), payload)
+                .data
+        };
+
+        assert_eq!(send(1000, b"hello "), b"hello ");
+        // The third segment arrives before the second
+        assert!(send(1011, b"!").is_empty());
+        assert_eq!(send(1006, b"world"), b"world!");
+        // A retransmission overlapping new data only delivers the new part
+        assert_eq!(send(1009, b"ld!?"), b"?");
+        assert!(send(1000, b"hello").is_empty());
+    }
+
+    #[test]
+    fn test_stream_starts_after_syn() {
+        let mut reassembler = TcpReassembler::new();
+        let client = ("10.0.0.1".parse().unwrap(), 40000);
+        let server = ("10.0.0.2".parse().unwrap(), 80);
+        reassembler.process(client, server, &segment(999, true, 0), &[]);
+
+        // The first data segment went missing
+        let data = reassembler.process(client, server, &segment(1005, false, 3), b"abc");
+        assert!(data.data.is_empty());
+        let data = reassembler.process(client, server, &segment(1000, false, 5), b"01234");
+        assert_eq!(data.data, b"01234abc");
+    }
+
+    #[test]
+    fn test_message_buffer_skips_large_messages() {
+        let mut buffer = MessageBuffer::default();
+        buffer.push(b"abc");
+        assert!(buffer.take(4).is_none());
+        assert_eq!(buffer.take(2).unwrap(), b"ab");
+        assert_eq!(buffer.take(1).unwrap(), b"c");
+
+        buffer.push(&vec![0; MAX_MESSAGE_LEN]);
+        let message = buffer.take(MAX_MESSAGE_LEN + 10).unwrap();
+        assert_eq!(message.len(), MAX_MESSAGE_LEN);
+        // The end of the large message is dropped
+        buffer.push(b"0123456789next");
+        assert_eq!(buffer.data(), b"next");
+
+        buffer.push(b" line\r\nrest");
+        assert_eq!(buffer.take_line().unwrap(), "next line");
+        assert!(buffer.take_line().is_none());
+    }
+}
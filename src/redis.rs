@@ -0,0 +1,415 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionState, ConnectionTable, Exchange, PendingQueries};
+use crate::reassembly::{MessageBuffer, StreamData};
+use chrono::{DateTime, Utc};
+use std::net::IpAddr;
+
+// Values still incomplete past this size are dropped
+const MAX_BUFFERED: usize = 1 << 20;
+const MAX_DEPTH: usize = 16;
+// Longer arguments are cut short in the info column
+const MAX_ARGUMENT_LEN: usize = 32;
+
+/// A RESP2 or RESP3 value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Array(Vec<Value>),
+    /// Out of band data such as pub/sub messages in RESP3.
+    Push(Vec<Value>),
+    Null,
+}
+
+impl Value {
+    fn text(&self) -> Option<String> {
+        match self {
+            Value::Simple(text) => Some(text.clone()),
+            Value::Bulk(data) => Some(String::from_utf8_lossy(data).into_owned()),
+            Value::Integer(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Formats the value like `redis-cli`, shortening long strings.
+    pub fn format(&self) -> String {
+        match self {
+            Value::Simple(text) => text.clone(),
+            Value::Error(message) => format!("(error) {}", message),
+            Value::Integer(value) => format!("(integer) {}", value),
+            Value::Bulk(data) => format!("\"{}\"", shorten(&String::from_utf8_lossy(data))),
+            Value::Array(values) if values.is_empty() => "(empty array)".to_string(),
+            Value::Array(values) => format!("array of {}", values.len()),
+            Value::Push(values) => format!("push of {}", values.len()),
+            Value::Null => "(nil)".to_string(),
+        }
+    }
+}
+
+fn shorten(text: &str) -> String {
+    match text.char_indices().nth(MAX_ARGUMENT_LEN) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Parsed {
+    Value(Value, usize),
+    Incomplete,
+    Invalid,
+}
+
+fn line(data: &[u8]) -> Option<(&[u8], usize)> {
+    let end = data.windows(2).position(|w| w == b"\r\n")?;
+    Some((&data[..end], end + 2))
+}
+
+fn parse_number<T: std::str::FromStr>(text: &[u8]) -> Option<T> {
+    std::str::from_utf8(text).ok()?.parse().ok()
+}
+
+/// Parses the value at the start of `data`.
+fn parse_value(data: &[u8], depth: usize) -> Parsed {
+    if depth > MAX_DEPTH {
+        return Parsed::Invalid;
+    }
+    let Some((header, header_len)) = data.get(1..).and_then(line) else {
+        return Parsed::Incomplete;
+    };
+    let header_len = header_len + 1;
+    let text = || String::from_utf8_lossy(header).into_owned();
+    let value = match data[0] {
+        b'+' | b',' | b'(' => Value::Simple(text()),
+        b'-' => Value::Error(text()),
+        b':' => match parse_number(header) {
+            Some(value) => Value::Integer(value),
+            None => return Parsed::Invalid,
+        },
+        b'#' => Value::Simple(if header == b"t" { "true" } else { "false" }.to_string()),
+        b'_' => Value::Null,
+        b'$' | b'!' | b'=' => {
+            let len = match parse_number::<i64>(header) {
+                Some(-1) => return Parsed::Value(Value::Null, header_len),
+                Some(len) => match usize::try_from(len) {
+                    Ok(len) => len,
+                    Err(_) => return Parsed::Invalid,
+                },
+                None => return Parsed::Invalid,
+            };
+            let Some(body) = data.get(header_len..header_len + len + 2) else {
+                return Parsed::Incomplete;
+            };
+            if !body.ends_with(b"\r\n") {
+                return Parsed::Invalid;
+            }
+            let body = body[..len].to_vec();
+            let value = match data[0] {
+                b'!' => Value::Error(String::from_utf8_lossy(&body).into_owned()),
+                _ => Value::Bulk(body),
+            };
+            return Parsed::Value(value, header_len + len + 2);
+        }
+        b'*' | b'~' | b'>' | b'%' | b'|' => {
+            let count = match parse_number::<i64>(header) {
+                Some(-1) => return Parsed::Value(Value::Null, header_len),
+                Some(count) if count >= 0 => count as usize,
+                _ => return Parsed::Invalid,
+            };
+            // Maps and attributes hold key/value pairs
+            let count = match data[0] {
+                b'%' | b'|' => count.saturating_mul(2),
+                _ => count,
+            };
+            let mut values = Vec::new();
+            let mut offset = header_len;
+            for _ in 0..count {
+                match parse_value(&data[offset..], depth + 1) {
+                    Parsed::Value(value, len) => {
+                        values.push(value);
+                        offset += len;
+                    }
+                    other => return other,
+                }
+            }
+            return match data[0] {
+                // Attributes describe the value that follows them
+                b'|' => match parse_value(&data[offset..], depth + 1) {
+                    Parsed::Value(value, len) => Parsed::Value(value, offset + len),
+                    other => other,
+                },
+                b'>' => Parsed::Value(Value::Push(values), offset),
+                _ => Parsed::Value(Value::Array(values), offset),
+            };
+        }
+        _ => return Parsed::Invalid,
+    };
+    Parsed::Value(value, header_len)
+}
+
+/// A Redis command or reply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedisMessage {
+    pub from_client: bool,
+    pub length: usize,
+    pub value: Value,
+}
+
+impl RedisMessage {
+    /// The command and its arguments, for client messages.
+    pub fn arguments(&self) -> Option<Vec<String>> {
+        match &self.value {
+            Value::Array(values) if self.from_client && !values.is_empty() => {
+                values.iter().map(Value::text).collect()
+            }
+            _ => None,
+        }
+    }
+
+    pub fn command(&self) -> Option<String> {
+        Some(self.arguments()?.first()?.to_ascii_uppercase())
+    }
+}
+
+impl AppMessage for RedisMessage {
+    const TIME_FIELD: &'static str = "redis.response_time";
+
+    fn info(&self) -> String {
+        match self.arguments() {
+            Some(mut arguments) => {
+                arguments[0] = arguments[0].to_ascii_uppercase();
+                let shown: Vec<_> = arguments.iter().map(|a| shorten(a)).collect();
+                format!("Request: {}", shown.join(" "))
+            }
+            None if self.from_client => "Request".to_string(),
+            None => format!("Response: {}", self.value.format()),
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("redis.length", self.length);
+        if let Some(arguments) = self.arguments() {
+            fields.insert("redis.command", arguments[0].to_ascii_uppercase());
+            fields.insert("redis.query", arguments.join(" "));
+        } else if !self.from_client {
+            fields.insert("redis.response", self.value.format());
+        }
+        if let Some(message) = self.error() {
+            fields.insert("redis.error", message);
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match &self.value {
+            Value::Error(message) if !self.from_client => Some(message.clone()),
+            _ => None,
+        }
+    }
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Connection {
+    pending: PendingQueries,
+    /// Replies no longer answer commands one to one, as after SUBSCRIBE.
+    unmatched: bool,
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+
+    fn gap(&mut self, _: &mut MessageBuffer, _: bool) {
+        // Replies can no longer be matched to their commands
+        self.pending.clear();
+    }
+}
+
+impl Connection {
+    fn next_message(
+        &mut self,
+        buffer: &mut MessageBuffer,
+        from_client: bool,
+    ) -> Option<RedisMessage> {
+        let data = buffer.data();
+        let first = *data.first()?;
+
+        // Commands typed in telnet style are sent as plain lines
+        if from_client && first != b'*' {
+            let (line, len) = line(data)?;
+            let arguments: Vec<_> = String::from_utf8_lossy(line)
+                .split_whitespace()
+                .map(|argument| Value::Bulk(argument.as_bytes().to_vec()))
+                .collect();
+            buffer.take(len);
+            return Some(RedisMessage {
+                from_client,
+                length: len,
+                value: Value::Array(arguments),
+            });
+        }
+
+        match parse_value(data, 0) {
+            Parsed::Value(value, len) => {
+                buffer.take(len);
+                Some(RedisMessage {
+                    from_client,
+                    length: len,
+                    value,
+                })
+            }
+            Parsed::Incomplete if data.len() <= MAX_BUFFERED => None,
+            _ => {
+                // Not at a value boundary, e.g. after missing data
+                buffer.clear();
+                self.pending.clear();
+                None
+            }
+        }
+    }
+}
+
+/// Splits reassembled Redis connections into commands and replies and
+/// pairs them to measure how long commands take.
+#[derive(Debug, Default)]
+pub struct RedisTracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl RedisTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the commands or replies `stream` completes and times the
+    /// commands that get replies.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+        timestamp: DateTime<Utc>,
+    ) -> Exchange<RedisMessage> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (buffer, connection) = connection.split(from_client);
+        buffer.push(&stream.data);
+        while let Some(message) = connection.next_message(buffer, from_client) {
+            if from_client {
+                let command = message.command().unwrap_or_default();
+                if matches!(
+                    command.as_str(),
+                    "SUBSCRIBE" | "PSUBSCRIBE" | "SSUBSCRIBE" | "MONITOR"
+                ) {
+                    connection.unmatched = true;
+                    connection.pending.clear();
+                }
+                if !connection.unmatched {
+                    let query = message.arguments().unwrap_or_default().join(" ");
+                    connection.pending.push(query, timestamp);
+                }
+            } else if !matches!(message.value, Value::Push(_)) {
+                exchange
+                    .timings
+                    .extend(connection.pending.complete(timestamp));
+            }
+            exchange.messages.push(message);
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(
+            parse_value(b"*2\r\n$3\r\nGET\r\n:-5\r\n", 0),
+            Parsed::Value(
+                Value::Array(vec![Value::Bulk(b"GET".to_vec()), Value::Integer(-5)]),
+                18
+            )
+        );
+        assert_eq!(parse_value(b"$5\r\nhel", 0), Parsed::Incomplete);
+        assert_eq!(parse_value(b"$-1\r\n", 0), Parsed::Value(Value::Null, 5));
+        assert_eq!(
+            parse_value(b"%1\r\n+a\r\n#t\r\n", 0),
+            Parsed::Value(
+                Value::Array(vec![
+                    Value::Simple("a".to_string()),
+                    Value::Simple("true".to_string())
+                ]),
+                12
+            )
+        );
+        let nested = "*1\r\n".repeat(MAX_DEPTH + 2);
+        assert_eq!(parse_value(nested.as_bytes(), 0), Parsed::Invalid);
+    }
+
+    #[test]
+    fn test_pipelined_commands() {
+        let start = Utc::now();
+        let mut tracker = RedisTracker::new();
+
+        let commands = b"*3\r\n$3\r\nset\r\n$3\r\nfoo\r\n$3\r\nbar\r\nGET foo\r\n";
+        let exchange =
+            tracker.process(CLIENT, SERVER, true, &StreamData::complete(commands), start);
+        let infos: Vec<_> = exchange.messages.iter().map(|m| m.info()).collect();
+        assert_eq!(infos, ["Request: SET foo bar", "Request: GET foo"]);
+
+        let end = start + chrono::Duration::milliseconds(1);
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(b"+OK\r\n$3\r\nba"),
+            end,
+        );
+        assert_eq!(exchange.messages[0].info(), "Response: OK");
+        assert_eq!(exchange.timings[0].query, "set foo bar");
+        assert_eq!(exchange.timings[0].latency, 0.001);
+
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(b"r\r\n-WRONGTYPE Operation\r\n"),
+            end,
+        );
+        assert_eq!(exchange.messages[0].info(), "Response: \"bar\"");
+        assert_eq!(exchange.timings[0].query, "GET foo");
+        assert_eq!(exchange.messages[1].error().unwrap(), "WRONGTYPE Operation");
+        // No command is left to answer
+        assert!(exchange.timings.len() == 1);
+    }
+
+    #[test]
+    fn test_subscribe_disables_matching() {
+        let mut tracker = RedisTracker::new();
+        tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(b"SUBSCRIBE news\r\n"),
+            Utc::now(),
+        );
+        let reply = b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n";
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(reply),
+            Utc::now(),
+        );
+        assert_eq!(exchange.messages[0].info(), "Response: array of 3");
+        assert!(exchange.timings.is_empty());
+    }
+}
@@ -0,0 +1,402 @@
+use crate::fields::Fields;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+const MAX_STREAMS: usize = 65536;
+// Sequence numbers further ahead than this are taken as late packets
+const MAX_DROPOUT: u16 = 3000;
+
+/// The encoding name and clock rate of a static RTP payload type.
+pub fn static_payload_type(payload_type: u8) -> Option<(&'static str, u32)> {
+    let (name, clock_rate) = match payload_type {
+        0 => ("PCMU", 8000),
+        3 => ("GSM", 8000),
+        4 => ("G723", 8000),
+        8 => ("PCMA", 8000),
+        9 => ("G722", 8000),
+        13 => ("CN", 8000),
+        18 => ("G729", 8000),
+        26 => ("JPEG", 90000),
+        31 => ("H261", 90000),
+        34 => ("H263", 90000),
+        _ => return None,
+    };
+    Some((name, clock_rate))
+}
+
+/// The fixed header of an RTP packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtpHeader {
+    pub marker: bool,
+    pub payload_type: u8,
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    pub payload_len: usize,
+}
+
+impl RtpHeader {
+    pub fn parse(data: &[u8]) -> Option<RtpHeader> {
+        if data.len() < 12 || data[0] >> 6 != 2 {
+            return None;
+        }
+        let csrc_count = usize::from(data[0] & 0x0f);
+        let mut header_len = 12 + 4 * csrc_count;
+        if data[0] & 0x10 != 0 {
+            let extension = data.get(header_len..header_len + 4)?;
+            header_len += 4 + 4 * usize::from(u16::from_be_bytes([extension[2], extension[3]]));
+        }
+        let mut payload_len = data.len().checked_sub(header_len)?;
+        if data[0] & 0x20 != 0 {
+            payload_len = payload_len.checked_sub(usize::from(*data.last()?))?;
+        }
+        Some(RtpHeader {
+            marker: data[1] & 0x80 != 0,
+            payload_type: data[1] & 0x7f,
+            sequence: u16::from_be_bytes([data[2], data[3]]),
+            timestamp: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            ssrc: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            payload_len,
+        })
+    }
+
+    pub fn info(&self, encoding: Option<&str>) -> String {
+        let payload_type = match encoding {
+            Some(encoding) => format!("{} ({})", encoding, self.payload_type),
+            None => self.payload_type.to_string(),
+        };
+        let mut info = format!(
+            "PT={}, SSRC=0x{:08X}, Seq={}, Time={}",
+            payload_type, self.ssrc, self.sequence, self.timestamp
+        );
+        if self.marker {
+            info.push_str(", Mark");
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("rtp.p_type", self.payload_type);
+        fields.insert("rtp.marker", self.marker);
+        fields.insert("rtp.seq", self.sequence);
+        fields.insert("rtp.timestamp", self.timestamp);
+        fields.insert("rtp.ssrc", self.ssrc);
+        fields.insert("rtp.payload.length", self.payload_len);
+    }
+}
+
+/// One packet of a compound RTCP packet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RtcpPacket {
+    pub packet_type: u8,
+    pub ssrc: Option<u32>,
+    /// Reception reports as the SSRC reported on, fraction lost (0-1),
+    /// cumulative packets lost and interarrival jitter in timestamp units.
+    pub reports: Vec<(u32, f64, i32, u32)>,
+}
+
+impl RtcpPacket {
+    /// Splits a compound RTCP packet into its packets.
+    pub fn parse_compound(data: &[u8]) -> Option<Vec<RtcpPacket>> {
+        let mut packets = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            if rest.len() < 4 || rest[0] >> 6 != 2 {
+                return None;
+            }
+            let packet_type = rest[1];
+            if !(200..=206).contains(&packet_type) {
+                return None;
+            }
+            let len = 4 * (usize::from(u16::from_be_bytes([rest[2], rest[3]])) + 1);
+            let packet = rest.get(..len)?;
+            rest = &rest[len..];
+
+            let ssrc = packet
+                .get(4..8)
+                .map(|ssrc| u32::from_be_bytes([ssrc[0], ssrc[1], ssrc[2], ssrc[3]]));
+            // Report blocks follow the sender info of sender reports
+            let blocks_start = match packet_type {
+                200 => 28,
+                201 => 8,
+                _ => packet.len(),
+            };
+            let count = usize::from(packet[0] & 0x1f);
+            let reports = packet
+                .get(blocks_start..)
+                .unwrap_or_default()
+                .chunks_exact(24)
+                .take(count)
+                .map(|block| {
+                    let source = u32::from_be_bytes([block[0], block[1], block[2], block[3]]);
+                    let fraction = f64::from(block[4]) / 256.0;
+                    // 24-bit signed count
+                    let lost = i32::from_be_bytes([block[5], block[6], block[7], 0]) >> 8;
+                    let jitter = u32::from_be_bytes([block[12], block[13], block[14], block[15]]);
+                    (source, fraction, lost, jitter)
+                })
+                .collect();
+            packets.push(RtcpPacket {
+                packet_type,
+                ssrc,
+                reports,
+            });
+        }
+        (!packets.is_empty()).then_some(packets)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.packet_type {
+            200 => "Sender Report",
+            201 => "Receiver Report",
+            202 => "Source description",
+            203 => "Goodbye",
+            204 => "Application specific",
+            205 => "Generic RTP Feedback",
+            _ => "Payload-specific Feedback",
+        }
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("rtcp.pt", self.packet_type);
+        if let Some(ssrc) = self.ssrc {
+            fields.insert("rtcp.senderssrc", ssrc);
+        }
+        for (source, fraction, lost, jitter) in &self.reports {
+            fields.insert("rtcp.ssrc.identifier", *source);
+            fields.insert("rtcp.ssrc.fraction", *fraction);
+            fields.insert("rtcp.ssrc.cum_nr", i64::from(*lost).max(0) as u64);
+            fields.insert("rtcp.ssrc.jitter", *jitter);
+        }
+    }
+}
+
+/// Quality of an RTP stream up to and including a packet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamStats {
+    pub packets: u64,
+    pub expected: u64,
+    pub lost: u64,
+    /// Packets that weren't the one expected next: gaps, duplicates and
+    /// late packets.
+    pub sequence_errors: u64,
+    pub jitter_ms: f64,
+    pub max_jitter_ms: f64,
+    /// Estimated mean opinion score, from 1 (bad) to 4.5 (excellent).
+    pub mos: f64,
+    /// The sequence number expected and the one received, when this packet
+    /// skipped some.
+    pub gap: Option<(u16, u16)>,
+}
+
+impl StreamStats {
+    pub fn lost_percent(&self) -> f64 {
+        if self.expected == 0 {
+            return 0.0;
+        }
+        self.lost as f64 * 100.0 / self.expected as f64
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("rtp.analysis.packets", self.packets);
+        fields.insert("rtp.analysis.expected", self.expected);
+        fields.insert("rtp.analysis.lost", self.lost);
+        fields.insert("rtp.analysis.sequence_errors", self.sequence_errors);
+        fields.insert("rtp.analysis.jitter", self.jitter_ms);
+        fields.insert("rtp.analysis.max_jitter", self.max_jitter_ms);
+        fields.insert("rtp.analysis.mos", self.mos);
+    }
+}
+
+/// Estimates the mean opinion score with a simplified E-model. Only jitter
+/// and loss are seen on the wire, so the delay is taken from the jitter.
+pub fn estimate_mos(jitter_ms: f64, lost_percent: f64) -> f64 {
+    let latency = jitter_ms * 2.0 + 10.0;
+    let mut r = if latency < 160.0 {
+        93.2 - latency / 40.0
+    } else {
+        93.2 - (latency - 120.0) / 10.0
+    };
+    r -= lost_percent * 2.5;
+    if r <= 0.0 {
+        return 1.0;
+    }
+    let r = r.min(100.0);
+    (1.0 + 0.035 * r + 0.000007 * r * (r - 60.0) * (100.0 - r)).clamp(1.0, 4.5)
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug)]
+struct Stream {
+    base_sequence: u16,
+    max_sequence: u16,
+    cycles: u64,
+    packets: u64,
+    sequence_errors: u64,
+    /// The previous relative transit time in timestamp units.
+    transit: Option<f64>,
+    jitter: f64,
+    max_jitter_ms: f64,
+}
+
+/// Computes jitter, loss and sequence errors of RTP streams as defined by
+/// RFC 3550.
+#[derive(Debug, Default)]
+pub struct RtpAnalyzer {
+    streams: HashMap<(Endpoint, Endpoint, u32), Stream>,
+}
+
+impl RtpAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a packet from `src` to `dst` that arrived at `arrival` seconds,
+    /// whose timestamps count at `clock_rate` Hz.
+    pub fn process(
+        &mut self,
+        src: Endpoint,
+        dst: Endpoint,
+        header: &RtpHeader,
+        arrival: f64,
+        clock_rate: u32,
+    ) -> StreamStats {
+        if self.streams.len() >= MAX_STREAMS && !self.streams.contains_key(&(src, dst, header.ssrc))
+        {
+            self.streams.clear();
+        }
+        let stream = self
+            .streams
+            .entry((src, dst, header.ssrc))
+            .or_insert(Stream {
+                base_sequence: header.sequence,
+                max_sequence: header.sequence.wrapping_sub(1),
+                cycles: 0,
+                packets: 0,
+                sequence_errors: 0,
+                transit: None,
+                jitter: 0.0,
+                max_jitter_ms: 0.0,
+            });
+
+        let mut gap = None;
+        let delta = header.sequence.wrapping_sub(stream.max_sequence);
+        if delta == 0 || delta > MAX_DROPOUT {
+            // Duplicate or late
+            stream.sequence_errors += 1;
+        } else {
+            if delta != 1 {
+                stream.sequence_errors += 1;
+                gap = Some((stream.max_sequence.wrapping_add(1), header.sequence));
+            }
+            if header.sequence < stream.max_sequence {
+                stream.cycles += 1 << 16;
+            }
+            stream.max_sequence = header.sequence;
+        }
+        stream.packets += 1;
+
+        let clock_rate = f64::from(clock_rate.max(1));
+        let transit = arrival * clock_rate - f64::from(header.timestamp);
+        if let Some(previous) = stream.transit {
+            let d = (transit - previous).abs();
+            stream.jitter += (d - stream.jitter) / 16.0;
+        }
+        stream.transit = Some(transit);
+        let jitter_ms = stream.jitter * 1000.0 / clock_rate;
+        stream.max_jitter_ms = stream.max_jitter_ms.max(jitter_ms);
+
+        let expected =
+            stream.cycles + u64::from(stream.max_sequence) - u64::from(stream.base_sequence) + 1;
+        let lost = expected.saturating_sub(stream.packets);
+        let mut stats = StreamStats {
+            packets: stream.packets,
+            expected,
+            lost,
+            sequence_errors: stream.sequence_errors,
+            jitter_ms,
+            max_jitter_ms: stream.max_jitter_ms,
+            mos: 0.0,
+            gap,
+        };
+        stats.mos = estimate_mos(jitter_ms, stats.lost_percent());
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtp(sequence: u16, timestamp: u32) -> Vec<u8> {
+        let mut data = vec![0x80, 0x00];
+        data.extend_from_slice(&sequence.to_be_bytes());
+        data.extend_from_slice(&timestamp.to_be_bytes());
+        data.extend_from_slice(&0x1234_5678u32.to_be_bytes());
+        data.extend_from_slice(&[0xff; 160]);
+        data
+    }
+
+    const SRC: Endpoint = (IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1)), 40000);
+    const DST: Endpoint = (IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 2)), 50000);
+
+    #[test]
+    fn test_parse_header() {
+        let header = RtpHeader::parse(&rtp(7, 1120)).unwrap();
+        assert_eq!(header.sequence, 7);
+        assert_eq!(header.payload_len, 160);
+        assert_eq!(
+            header.info(Some("PCMU")),
+            "PT=PCMU (0), SSRC=0x12345678, Seq=7, Time=1120"
+        );
+        assert!(RtpHeader::parse(&[0x40; 20]).is_none());
+    }
+
+    #[test]
+    fn test_loss_and_jitter() {
+        let mut analyzer = RtpAnalyzer::new();
+        // Timestamps follow the position in the stream, arrivals may not
+        let mut process = |sequence: u16, position: u32, arrival: f64| {
+            let header = RtpHeader::parse(&rtp(sequence, position * 160)).unwrap();
+            analyzer.process(SRC, DST, &header, arrival, 8000)
+        };
+
+        // Packets every 20 ms without jitter, across the sequence wrap
+        for (i, sequence) in (65530..=65535).chain(0..4).enumerate() {
+            let stats = process(sequence, i as u32, i as f64 * 0.02);
+            assert_eq!(stats.lost, 0);
+            assert!(stats.jitter_ms < 1e-6);
+        }
+        let stats = process(6, 12, 12.0 * 0.02);
+        assert_eq!(stats.gap, Some((4, 6)));
+        assert_eq!((stats.expected, stats.packets, stats.lost), (13, 11, 2));
+        // The late packet fills part of the gap
+        let stats = process(4, 10, 13.0 * 0.02);
+        assert_eq!((stats.lost, stats.sequence_errors), (1, 2));
+        assert!(stats.jitter_ms > 0.0);
+        assert!(stats.mos < 4.5);
+    }
+
+    #[test]
+    fn test_estimate_mos() {
+        assert!(estimate_mos(0.0, 0.0) > 4.3);
+        assert!(estimate_mos(5.0, 10.0) < 3.5);
+        assert_eq!(estimate_mos(0.0, 50.0), 1.0);
+    }
+
+    #[test]
+    fn test_parse_receiver_report() {
+        let mut data = vec![0x81, 201, 0, 7];
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&0x1234_5678u32.to_be_bytes());
+        data.extend_from_slice(&[64, 0, 0, 5]);
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&40u32.to_be_bytes());
+        data.extend_from_slice(&[0; 8]);
+        let packets = RtcpPacket::parse_compound(&data).unwrap();
+        assert_eq!(packets[0].name(), "Receiver Report");
+        assert_eq!(packets[0].reports, [(0x1234_5678, 0.25, 5, 40)]);
+        assert!(RtcpPacket::parse_compound(&rtp(1, 1)).is_none());
+    }
+}
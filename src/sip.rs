@@ -0,0 +1,713 @@
+use crate::fields::{FieldValue, Fields};
+use crate::rtp;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+
+pub const SIP_PORT: u16 = 5060;
+
+const MAX_CALLS: usize = 65536;
+const MAX_MEDIA_ENDPOINTS: usize = 65536;
+
+/// A media description of an SDP body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Media {
+    /// `audio`, `video`, ...
+    pub kind: String,
+    /// The media-level connection address, or else the session-level one.
+    pub address: Option<IpAddr>,
+    pub port: u16,
+    /// Payload types with their encoding name and clock rate, where known.
+    pub formats: Vec<(u8, Option<(String, u32)>)>,
+}
+
+/// The parts of a session description that locate its media streams.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sdp {
+    pub address: Option<IpAddr>,
+    pub media: Vec<Media>,
+}
+
+/// Parses `IN IP4 192.0.2.1` connection data, ignoring any TTL.
+fn parse_connection(value: &str) -> Option<IpAddr> {
+    let address = value.split_whitespace().nth(2)?;
+    address.split('/').next()?.parse().ok()
+}
+
+impl Sdp {
+    pub fn parse(body: &str) -> Sdp {
+        let mut sdp = Sdp::default();
+        let mut rtpmaps: Vec<HashMap<u8, (String, u32)>> = Vec::new();
+        for line in body.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match key {
+                "c" => match sdp.media.last_mut() {
+                    Some(media) => media.address = parse_connection(value),
+                    None => sdp.address = parse_connection(value),
+                },
+                "m" => {
+                    let mut parts = value.split_whitespace();
+                    let kind = parts.next().unwrap_or_default().to_string();
+                    let port = parts
+                        .next()
+                        .and_then(|port| port.split('/').next()?.parse().ok())
+                        .unwrap_or(0);
+                    // Transport
+                    parts.next();
+                    let formats = parts
+                        .filter_map(|format| format.parse().ok())
+                        .map(|payload_type| (payload_type, None))
+                        .collect();
+                    sdp.media.push(Media {
+                        kind,
+                        address: sdp.address,
+                        port,
+                        formats,
+                    });
+                    rtpmaps.push(HashMap::new());
+                }
+                "a" => {
+                    let (Some(rtpmap), Some(map)) =
+                        (value.strip_prefix("rtpmap:"), rtpmaps.last_mut())
+                    else {
+                        continue;
+                    };
+                    let Some((payload_type, encoding)) = rtpmap.split_once(' ') else {
+                        continue;
+                    };
+                    let mut encoding = encoding.split('/');
+                    let name = encoding.next().unwrap_or_default().to_string();
+                    let clock_rate = encoding.next().and_then(|rate| rate.parse().ok());
+                    if let (Ok(payload_type), Some(clock_rate)) = (payload_type.parse(), clock_rate)
+                    {
+                        map.insert(payload_type, (name, clock_rate));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (media, mut rtpmap) in sdp.media.iter_mut().zip(rtpmaps) {
+            for (payload_type, encoding) in &mut media.formats {
+                *encoding = rtpmap.remove(payload_type).or_else(|| {
+                    rtp::static_payload_type(*payload_type)
+                        .map(|(name, clock_rate)| (name.to_string(), clock_rate))
+                });
+            }
+        }
+        sdp
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        if let Some(address) = self.address {
+            fields.insert("sdp.connection_info.address", address);
+        }
+        for media in &self.media {
+            fields.insert("sdp.media.media", media.kind.as_str());
+            fields.insert("sdp.media.port", media.port);
+            for (_, encoding) in &media.formats {
+                if let Some((name, clock_rate)) = encoding {
+                    fields.insert("sdp.media.format", format!("{}/{}", name, clock_rate));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartLine {
+    Request { method: String, uri: String },
+    Response { code: u16, reason: String },
+}
+
+/// A SIP request or response with the headers that identify its dialog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SipMessage {
+    pub start: StartLine,
+    pub call_id: Option<String>,
+    /// The URIs of the From and To headers.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub cseq: Option<(u32, String)>,
+    pub user_agent: Option<String>,
+    pub sdp: Option<Sdp>,
+}
+
+/// Expands the compact form of a header name.
+fn header_name(name: &str) -> String {
+    let name = name.trim().to_ascii_lowercase();
+    let full = match name.as_str() {
+        "i" => "call-id",
+        "f" => "from",
+        "t" => "to",
+        "c" => "content-type",
+        "l" => "content-length",
+        _ => return name,
+    };
+    full.to_string()
+}
+
+/// The URI of a From or To header, without display name or parameters.
+fn header_uri(value: &str) -> String {
+    match value.split_once('<') {
+        Some((_, rest)) => rest.split('>').next().unwrap_or(rest).to_string(),
+        None => value.split(';').next().unwrap_or(value).trim().to_string(),
+    }
+}
+
+impl SipMessage {
+    pub fn parse(payload: &[u8]) -> Option<SipMessage> {
+        let (head, body) = match payload.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(end) => (&payload[..end], &payload[end + 4..]),
+            None => (payload, &[][..]),
+        };
+        let head = std::str::from_utf8(head).ok()?;
+        let mut lines = head.lines();
+        let first = lines.next()?;
+        let start = if let Some(rest) = first.strip_prefix("SIP/2.0 ") {
+            let (code, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+            StartLine::Response {
+                code: code.parse().ok()?,
+                reason: reason.to_string(),
+            }
+        } else {
+            let mut parts = first.split(' ');
+            let (method, uri) = (parts.next()?, parts.next()?);
+            if parts.next()? != "SIP/2.0" || !method.bytes().all(|b| b.is_ascii_uppercase()) {
+                return None;
+            }
+            StartLine::Request {
+                method: method.to_string(),
+                uri: uri.to_string(),
+            }
+        };
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in lines {
+            if line.starts_with([' ', '\t']) {
+                // Folded continuation of the previous header
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                headers.push((header_name(name), value.trim().to_string()));
+            }
+        }
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(header, _)| header == name)
+                .map(|(_, value)| value.as_str())
+        };
+
+        let body = match header("content-length").and_then(|len| len.parse().ok()) {
+            Some(len) => body.get(..len).unwrap_or(body),
+            None => body,
+        };
+        let sdp = header("content-type")
+            .filter(|content_type| {
+                content_type
+                    .to_ascii_lowercase()
+                    .contains("application/sdp")
+            })
+            .map(|_| Sdp::parse(&String::from_utf8_lossy(body)));
+        let cseq = header("cseq").and_then(|cseq| {
+            let (number, method) = cseq.split_once(' ')?;
+            Some((number.parse().ok()?, method.trim().to_string()))
+        });
+        Some(SipMessage {
+            start,
+            call_id: header("call-id").map(str::to_string),
+            from: header("from").map(header_uri),
+            to: header("to").map(header_uri),
+            cseq,
+            user_agent: header("user-agent").map(str::to_string),
+            sdp,
+        })
+    }
+
+    pub fn info(&self) -> String {
+        let mut info = match &self.start {
+            StartLine::Request { method, uri } => format!("Request: {} {}", method, uri),
+            StartLine::Response { code, reason } => format!("Status: {} {}", code, reason),
+        };
+        if self.sdp.is_some() {
+            info.push_str(", with session description");
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        match &self.start {
+            StartLine::Request { method, uri } => {
+                fields.insert("sip.method", method.as_str());
+                fields.insert("sip.r_uri", uri.as_str());
+            }
+            StartLine::Response { code, reason } => {
+                fields.insert("sip.status_code", *code);
+                fields.insert("sip.reason", reason.as_str());
+            }
+        }
+        if let Some(call_id) = &self.call_id {
+            fields.insert("sip.call_id", call_id.as_str());
+        }
+        if let Some(from) = &self.from {
+            fields.insert("sip.from.addr", from.as_str());
+        }
+        if let Some(to) = &self.to {
+            fields.insert("sip.to.addr", to.as_str());
+        }
+        if let Some((number, method)) = &self.cseq {
+            fields.insert("sip.cseq.seq", *number);
+            fields.insert("sip.cseq.method", method.as_str());
+        }
+        if let Some(user_agent) = &self.user_agent {
+            fields.insert("sip.user_agent", user_agent.as_str());
+        }
+        if let Some(sdp) = &self.sdp {
+            sdp.insert_fields(fields);
+        }
+    }
+
+    /// Describes error responses, except authentication challenges that
+    /// are answered by a new request.
+    pub fn error(&self) -> Option<String> {
+        match &self.start {
+            StartLine::Response { code, reason } if *code >= 400 && ![401, 407].contains(code) => {
+                Some(format!("SIP {} {}", code, reason))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Where an INVITE dialog stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallState {
+    Calling,
+    Ringing,
+    InCall,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+impl CallState {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            CallState::Completed | CallState::Cancelled | CallState::Failed
+        )
+    }
+}
+
+impl fmt::Display for CallState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CallState::Calling => "Calling",
+            CallState::Ringing => "Ringing",
+            CallState::InCall => "In call",
+            CallState::Completed => "Completed",
+            CallState::Cancelled => "Cancelled",
+            CallState::Failed => "Failed",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A media endpoint announced in the SDP of a call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaEndpoint {
+    pub call_id: String,
+    /// The endpoint receives RTCP, one port above its RTP.
+    pub rtcp: bool,
+    encodings: HashMap<u8, (String, u32)>,
+}
+
+impl MediaEndpoint {
+    /// The encoding name and clock rate of a payload type.
+    pub fn encoding(&self, payload_type: u8) -> Option<(&str, u32)> {
+        match self.encodings.get(&payload_type) {
+            Some((name, clock_rate)) => Some((name, *clock_rate)),
+            None => rtp::static_payload_type(payload_type),
+        }
+    }
+}
+
+type Endpoint = (IpAddr, u16);
+
+/// Follows INVITE dialogs by Call-ID and remembers the RTP and RTCP
+/// endpoints their session descriptions announce.
+#[derive(Debug, Default)]
+pub struct SipTracker {
+    calls: HashMap<String, CallState>,
+    media: HashMap<Endpoint, MediaEndpoint>,
+}
+
+impl SipTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the call `message` belongs to and returns its state, or
+    /// `None` for messages outside INVITE dialogs.
+    pub fn process(&mut self, message: &SipMessage) -> Option<CallState> {
+        let call_id = message.call_id.as_ref()?;
+        let invite =
+            matches!(&message.start, StartLine::Request { method, .. } if method == "INVITE");
+        if invite && !self.calls.contains_key(call_id) {
+            if self.calls.len() >= MAX_CALLS {
+                self.calls.clear();
+            }
+            self.calls.insert(call_id.clone(), CallState::Calling);
+        }
+        let state = self.calls.get_mut(call_id)?;
+
+        let cseq_method = message.cseq.as_ref().map(|(_, method)| method.as_str());
+        *state = match (&message.start, cseq_method) {
+            (StartLine::Request { method, .. }, _) if method == "BYE" => CallState::Completed,
+            (StartLine::Request { method, .. }, _) if method == "INVITE" && state.is_finished() => {
+                CallState::Calling
+            }
+            (
+                StartLine::Response {
+                    code: 200..=299, ..
+                },
+                Some("BYE"),
+            ) => CallState::Completed,
+            (StartLine::Response { code, .. }, Some("INVITE")) => match code {
+                180 | 183 if *state == CallState::Calling => CallState::Ringing,
+                200..=299 if !state.is_finished() => CallState::InCall,
+                // Authentication challenges are answered by a new INVITE
+                401 | 407 => *state,
+                487 => CallState::Cancelled,
+                // Failed re-INVITEs leave the call up
+                300.. if *state != CallState::InCall => CallState::Failed,
+                _ => *state,
+            },
+            _ => *state,
+        };
+        let state = *state;
+
+        if let Some(sdp) = &message.sdp {
+            self.add_media(call_id, sdp);
+        }
+        Some(state)
+    }
+
+    fn add_media(&mut self, call_id: &str, sdp: &Sdp) {
+        for media in &sdp.media {
+            let Some(address) = media.address.filter(|_| media.port != 0) else {
+                continue;
+            };
+            if self.media.len() >= MAX_MEDIA_ENDPOINTS {
+                self.media.clear();
+            }
+            let encodings: HashMap<_, _> = media
+                .formats
+                .iter()
+                .filter_map(|(payload_type, encoding)| Some((*payload_type, encoding.clone()?)))
+                .collect();
+            let endpoint = MediaEndpoint {
+                call_id: call_id.to_string(),
+                rtcp: false,
+                encodings,
+            };
+            let rtcp = MediaEndpoint {
+                rtcp: true,
+                ..endpoint.clone()
+            };
+            self.media.insert((address, media.port), endpoint);
+            self.media
+                .insert((address, media.port.wrapping_add(1)), rtcp);
+        }
+    }
+
+    /// The media endpoint of a call at `endpoint`, if any.
+    pub fn media(&self, endpoint: Endpoint) -> Option<&MediaEndpoint> {
+        self.media.get(&endpoint)
+    }
+}
+
+/// The quality of one RTP stream of a call, as of its latest packet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamQuality {
+    pub packets: u64,
+    pub expected: u64,
+    pub lost: u64,
+    pub max_jitter_ms: f64,
+    pub mos: f64,
+}
+
+/// A call seen in the capture, with the quality of its media.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub call_id: String,
+    pub from: String,
+    pub to: String,
+    pub state: String,
+    pub started: DateTime<Utc>,
+    pub answered: Option<DateTime<Utc>>,
+    pub ended: Option<DateTime<Utc>>,
+    /// Streams by SSRC.
+    pub streams: HashMap<u32, StreamQuality>,
+}
+
+impl Call {
+    /// Time since the call was answered, up to its end or `now`.
+    pub fn duration(&self, now: DateTime<Utc>) -> Option<chrono::Duration> {
+        Some(self.ended.unwrap_or(now) - self.answered?)
+    }
+
+    pub fn max_jitter_ms(&self) -> Option<f64> {
+        self.streams
+            .values()
+            .map(|stream| stream.max_jitter_ms)
+            .reduce(f64::max)
+    }
+
+    pub fn lost_percent(&self) -> Option<f64> {
+        let expected: u64 = self.streams.values().map(|stream| stream.expected).sum();
+        let lost: u64 = self.streams.values().map(|stream| stream.lost).sum();
+        (expected > 0).then(|| lost as f64 * 100.0 / expected as f64)
+    }
+
+    /// The MOS of the worst stream.
+    pub fn mos(&self) -> Option<f64> {
+        self.streams
+            .values()
+            .map(|stream| stream.mos)
+            .reduce(f64::min)
+    }
+}
+
+/// Calls in the order they started, rebuilt from the fields of SIP and RTP
+/// packets.
+#[derive(Debug, Default)]
+pub struct CallTable {
+    calls: Vec<Call>,
+    index: HashMap<String, usize>,
+}
+
+impl CallTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, fields: &Fields, timestamp: DateTime<Utc>) {
+        let string = |name| fields.get(name).map(|value| value.to_string());
+        let uint = |name| match fields.get(name) {
+            Some(FieldValue::UInt(value)) => *value,
+            _ => 0,
+        };
+        let float = |name| match fields.get(name) {
+            Some(FieldValue::Float(value)) => *value,
+            _ => 0.0,
+        };
+
+        if let (Some(call_id), Some(state)) = (string("sip.call_id"), string("sip.call.state")) {
+            let index = *self.index.entry(call_id.clone()).or_insert_with(|| {
+                self.calls.push(Call {
+                    call_id,
+                    from: string("sip.from.addr").unwrap_or_default(),
+                    to: string("sip.to.addr").unwrap_or_default(),
+                    state: String::new(),
+                    started: timestamp,
+                    answered: None,
+                    ended: None,
+                    streams: HashMap::new(),
+                });
+                self.calls.len() - 1
+            });
+            let call = &mut self.calls[index];
+            if state == CallState::InCall.to_string() && call.answered.is_none() {
+                call.answered = Some(timestamp);
+            }
+            let finished = [
+                CallState::Completed,
+                CallState::Cancelled,
+                CallState::Failed,
+            ]
+            .iter()
+            .any(|finished| state == finished.to_string());
+            if finished && call.ended.is_none() {
+                call.ended = Some(timestamp);
+            }
+            call.state = state;
+        }
+
+        let Some(call_id) = string("rtp.call_id") else {
+            return;
+        };
+        let (Some(&index), Some(FieldValue::UInt(ssrc))) =
+            (self.index.get(&call_id), fields.get("rtp.ssrc"))
+        else {
+            return;
+        };
+        self.calls[index].streams.insert(
+            *ssrc as u32,
+            StreamQuality {
+                packets: uint("rtp.analysis.packets"),
+                expected: uint("rtp.analysis.expected"),
+                lost: uint("rtp.analysis.lost"),
+                max_jitter_ms: float("rtp.analysis.max_jitter"),
+                mos: float("rtp.analysis.mos"),
+            },
+        );
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Call> {
+        self.calls.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const INVITE: &str = "INVITE sip:bob@example.com SIP/2.0\r\n\
+        Via: SIP/2.0/UDP 10.0.0.1:5060;branch=z9hG4bK776asdhds\r\n\
+        From: Alice <sip:alice@example.com>;tag=1928301774\r\n\
+        To: Bob <sip:bob@example.com>\r\n\
+        i: a84b4c76e66710@pc33.example.com\r\n\
+        CSeq: 314159 INVITE\r\n\
+        Content-Type: application/sdp\r\n\
+        Content-Length: 147\r\n\
+        \r\n\
+        v=0\r\n\
+        o=alice 2890844526 2890844526 IN IP4 10.0.0.1\r\n\
+        s=-\r\n\
+        c=IN IP4 10.0.0.1\r\n\
+        t=0 0\r\n\
+        m=audio 49170 RTP/AVP 0 101\r\n\
+        a=rtpmap:101 telephone-event/8000\r\n";
+
+    fn response(code: &str, cseq: &str) -> Vec<u8> {
+        format!(
+            "SIP/2.0 {}\r\n\
+            From: Alice <sip:alice@example.com>;tag=1928301774\r\n\
+            To: Bob <sip:bob@example.com>;tag=a6c85cf\r\n\
+            Call-ID: a84b4c76e66710@pc33.example.com\r\n\
+            CSeq: {}\r\n\
+            Content-Length: 0\r\n\r\n",
+            code, cseq
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_parse_invite_with_sdp() {
+        let invite = SipMessage::parse(INVITE.as_bytes()).unwrap();
+        assert_eq!(
+            invite.info(),
+            "Request: INVITE sip:bob@example.com, with session description"
+        );
+        assert_eq!(
+            invite.call_id.as_deref(),
+            Some("a84b4c76e66710@pc33.example.com")
+        );
+        assert_eq!(invite.from.as_deref(), Some("sip:alice@example.com"));
+        assert_eq!(invite.cseq, Some((314159, "INVITE".to_string())));
+
+        let sdp = invite.sdp.unwrap();
+        assert_eq!(sdp.media.len(), 1);
+        let media = &sdp.media[0];
+        assert_eq!(media.address, Some(IpAddr::from([10, 0, 0, 1])));
+        assert_eq!(media.port, 49170);
+        assert_eq!(
+            media.formats,
+            [
+                (0, Some(("PCMU".to_string(), 8000))),
+                (101, Some(("telephone-event".to_string(), 8000)))
+            ]
+        );
+
+        assert!(SipMessage::parse(b"GET / HTTP/1.1\r\n\r\n").is_none());
+    }
+
+    #[test]
+    fn test_call_states_and_media() {
+        let mut tracker = SipTracker::new();
+        let invite = SipMessage::parse(INVITE.as_bytes()).unwrap();
+        assert_eq!(tracker.process(&invite), Some(CallState::Calling));
+        let media = tracker.media((IpAddr::from([10, 0, 0, 1]), 49170)).unwrap();
+        assert!(!media.rtcp);
+        assert_eq!(media.encoding(0), Some(("PCMU", 8000)));
+        assert!(
+            tracker
+                .media((IpAddr::from([10, 0, 0, 1]), 49171))
+                .unwrap()
+                .rtcp
+        );
+
+        let mut process = |message: &[u8]| tracker.process(&SipMessage::parse(message).unwrap());
+        let trying = response("100 Trying", "314159 INVITE");
+        assert_eq!(process(&trying), Some(CallState::Calling));
+        let ringing = response("180 Ringing", "314159 INVITE");
+        assert_eq!(process(&ringing), Some(CallState::Ringing));
+        let ok = response("200 OK", "314159 INVITE");
+        assert_eq!(process(&ok), Some(CallState::InCall));
+        // A rejected re-INVITE leaves the call up
+        let rejected = response("488 Not Acceptable Here", "314160 INVITE");
+        assert_eq!(process(&rejected), Some(CallState::InCall));
+        let bye = INVITE.replacen("INVITE sip", "BYE sip", 1);
+        assert_eq!(process(bye.as_bytes()), Some(CallState::Completed));
+        let ok = response("200 OK", "314161 BYE");
+        assert_eq!(process(&ok), Some(CallState::Completed));
+
+        let register = INVITE.replacen("INVITE sip", "REGISTER sip", 1);
+        let register = register.replace("a84b4c76", "other");
+        assert_eq!(process(register.as_bytes()), None);
+    }
+
+    #[test]
+    fn test_call_table() {
+        let start = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut table = CallTable::new();
+        let mut fields = Fields::new();
+        fields.insert("sip.call_id", "call-1");
+        fields.insert("sip.from.addr", "sip:alice@example.com");
+        fields.insert("sip.to.addr", "sip:bob@example.com");
+        fields.insert("sip.call.state", "Calling");
+        table.update(&fields, start);
+
+        let mut answer = Fields::new();
+        answer.insert("sip.call_id", "call-1");
+        answer.insert("sip.call.state", "In call");
+        table.update(&answer, start + chrono::Duration::seconds(2));
+
+        for (ssrc, lost, mos) in [(1u32, 0u64, 4.4), (2, 10, 3.1)] {
+            let mut rtp = Fields::new();
+            rtp.insert("rtp.call_id", "call-1");
+            rtp.insert("rtp.ssrc", ssrc);
+            rtp.insert("rtp.analysis.expected", 100u64);
+            rtp.insert("rtp.analysis.lost", lost);
+            rtp.insert("rtp.analysis.max_jitter", 12.5);
+            rtp.insert("rtp.analysis.mos", mos);
+            table.update(&rtp, start + chrono::Duration::seconds(3));
+        }
+
+        let call = table.iter().next().unwrap();
+        assert_eq!(call.state, "In call");
+        assert_eq!(call.from, "sip:alice@example.com");
+        assert_eq!(
+            call.duration(start + chrono::Duration::seconds(12)),
+            Some(chrono::Duration::seconds(10))
+        );
+        assert_eq!(call.lost_percent(), Some(5.0));
+        assert_eq!(call.mos(), Some(3.1));
+        assert_eq!(call.max_jitter_ms(), Some(12.5));
+    }
+}
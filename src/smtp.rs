@@ -0,0 +1,309 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionState, ConnectionTable, Exchange};
+use crate::reassembly::{MessageBuffer, StreamData};
+use std::net::IpAddr;
+
+// Continuation lines of a reply kept for the info column
+const MAX_REPLY_LINES: usize = 64;
+const SERVICE_READY: u16 = 220;
+const START_MAIL_INPUT: u16 = 354;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Command {
+        command: String,
+        parameter: String,
+    },
+    /// A reply with all its continuation lines.
+    Reply {
+        code: u16,
+        lines: Vec<String>,
+    },
+    /// Message content sent after DATA.
+    Data {
+        bytes: usize,
+        end: bool,
+    },
+    /// Data after a successful STARTTLS.
+    Encrypted(usize),
+}
+
+/// An SMTP command, reply or piece of message content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmtpMessage {
+    pub from_client: bool,
+    pub kind: Kind,
+    /// The reply accepting a STARTTLS command.
+    pub starts_tls: bool,
+}
+
+impl AppMessage for SmtpMessage {
+    const TIME_FIELD: &'static str = "smtp.response_time";
+
+    fn info(&self) -> String {
+        let side = if self.from_client { "C" } else { "S" };
+        match &self.kind {
+            Kind::Command { command, parameter } if parameter.is_empty() => {
+                format!("{}: {}", side, command)
+            }
+            Kind::Command { command, parameter } => {
+                format!("{}: {} {}", side, command, parameter)
+            }
+            Kind::Reply { code, lines } => format!("{}: {} {}", side, code, lines.join(" | ")),
+            Kind::Data { bytes, end: false } => format!("{}: DATA fragment, {} bytes", side, bytes),
+            Kind::Data { bytes, end: true } => {
+                format!("{}: DATA fragment, {} bytes (end of message)", side, bytes)
+            }
+            Kind::Encrypted(bytes) => format!("{}: TLS encrypted data, {} bytes", side, bytes),
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        match &self.kind {
+            Kind::Command { command, parameter } => {
+                fields.insert("smtp.req.command", command.as_str());
+                if !parameter.is_empty() {
+                    fields.insert("smtp.req.parameter", parameter.as_str());
+                }
+            }
+            Kind::Reply { code, lines } => {
+                fields.insert("smtp.response.code", *code);
+                for line in lines {
+                    fields.insert("smtp.rsp.parameter", line.as_str());
+                }
+            }
+            Kind::Data { bytes, .. } => fields.insert("smtp.data.length", *bytes),
+            Kind::Encrypted(_) => fields.insert("smtp.starttls", true),
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match &self.kind {
+            Kind::Reply { code, lines } if *code >= 400 => {
+                Some(format!("{} {}", code, lines.join(" ")))
+            }
+            _ => None,
+        }
+    }
+
+    fn note(&self) -> Option<String> {
+        self.starts_tls
+            .then(|| "Connection switched to TLS by STARTTLS".to_string())
+    }
+}
+
+/// Splits a reply line into its code, whether more lines follow, and text.
+fn parse_reply_line(line: &str) -> (u16, bool, String) {
+    let code = line
+        .get(..3)
+        .and_then(|code| code.parse().ok())
+        .unwrap_or(0);
+    let more = line.as_bytes().get(3) == Some(&b'-');
+    let text = line.get(4..).unwrap_or("").to_string();
+    (code, more, text)
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// Lines of a reply waiting for its last line.
+    reply: Vec<String>,
+    /// The client is sending message content.
+    data_mode: bool,
+    starttls_sent: bool,
+    encrypted: bool,
+}
+
+impl ConnectionState for Connection {
+    type Direction = MessageBuffer;
+
+    fn gap(&mut self, _: &mut MessageBuffer, _: bool) {
+        self.reply.clear();
+    }
+}
+
+impl Connection {
+    fn client_messages(&mut self, buffer: &mut MessageBuffer, messages: &mut Vec<SmtpMessage>) {
+        if self.data_mode {
+            let mut bytes = 0;
+            let mut end = false;
+            while let Some(line) = buffer.take_line() {
+                bytes += line.len() + 2;
+                if line == "." {
+                    end = true;
+                    self.data_mode = false;
+                    break;
+                }
+            }
+            if bytes > 0 {
+                messages.push(SmtpMessage {
+                    from_client: true,
+                    kind: Kind::Data { bytes, end },
+                    starts_tls: false,
+                });
+            }
+            if self.data_mode {
+                return;
+            }
+        }
+        while let Some(line) = buffer.take_line() {
+            let (command, parameter) = line.split_once(' ').unwrap_or((&line, ""));
+            let command = command.to_ascii_uppercase();
+            if command == "STARTTLS" {
+                self.starttls_sent = true;
+            }
+            messages.push(SmtpMessage {
+                from_client: true,
+                kind: Kind::Command {
+                    command,
+                    parameter: parameter.to_string(),
+                },
+                starts_tls: false,
+            });
+        }
+    }
+
+    fn server_messages(&mut self, buffer: &mut MessageBuffer, messages: &mut Vec<SmtpMessage>) {
+        while let Some(line) = buffer.take_line() {
+            let (code, more, text) = parse_reply_line(&line);
+            if self.reply.len() < MAX_REPLY_LINES {
+                self.reply.push(text);
+            }
+            if more {
+                continue;
+            }
+            let starts_tls = self.starttls_sent && code == SERVICE_READY;
+            self.starttls_sent = false;
+            if code == START_MAIL_INPUT {
+                self.data_mode = true;
+            }
+            messages.push(SmtpMessage {
+                from_client: false,
+                kind: Kind::Reply {
+                    code,
+                    lines: std::mem::take(&mut self.reply),
+                },
+                starts_tls,
+            });
+            if starts_tls {
+                self.encrypted = true;
+                buffer.clear();
+                break;
+            }
+        }
+    }
+}
+
+/// Splits reassembled SMTP connections into commands, replies and message
+/// content.
+#[derive(Debug, Default)]
+pub struct SmtpTracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl SmtpTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the commands, replies or message content `stream`
+    /// completes.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+    ) -> Exchange<SmtpMessage> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (buffer, connection) = connection.split(from_client);
+        if connection.encrypted {
+            if !stream.data.is_empty() {
+                exchange.messages.push(SmtpMessage {
+                    from_client,
+                    kind: Kind::Encrypted(stream.data.len()),
+                    starts_tls: false,
+                });
+            }
+            return exchange;
+        }
+
+        buffer.push(&stream.data);
+        if from_client {
+            connection.client_messages(buffer, &mut exchange.messages);
+        } else {
+            connection.server_messages(buffer, &mut exchange.messages);
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    fn infos(exchange: &Exchange<SmtpMessage>) -> Vec<String> {
+        exchange.messages.iter().map(|m| m.info()).collect()
+    }
+
+    #[test]
+    fn test_mail_transaction() {
+        let mut tracker = SmtpTracker::new();
+        let mut process = |from_client, data: &[u8]| {
+            tracker.process(CLIENT, SERVER, from_client, &StreamData::complete(data))
+        };
+
+        let exchange = process(false, b"250-mail.example\r\n250-PIPE");
+        assert!(exchange.messages.is_empty());
+        let exchange = process(false, b"LINING\r\n250 SIZE 1000\r\n");
+        assert_eq!(
+            infos(&exchange),
+            ["S: 250 mail.example | PIPELINING | SIZE 1000"]
+        );
+
+        let exchange = process(true, b"MAIL FROM:<a@example.org>\r\nDATA\r\n");
+        assert_eq!(
+            infos(&exchange),
+            ["C: MAIL FROM:<a@example.org>", "C: DATA"]
+        );
+        process(false, b"250 OK\r\n354 Go ahead\r\n");
+
+        let exchange = process(true, b"Subject: hi\r\n\r\nbody\r\n.\r\nQUIT\r\n");
+        assert_eq!(
+            infos(&exchange),
+            ["C: DATA fragment, 24 bytes (end of message)", "C: QUIT"]
+        );
+
+        let exchange = process(false, b"550 5.1.1 User unknown\r\n");
+        assert_eq!(
+            exchange.messages[0].error().unwrap(),
+            "550 5.1.1 User unknown"
+        );
+    }
+
+    #[test]
+    fn test_starttls() {
+        let mut tracker = SmtpTracker::new();
+        tracker.process(CLIENT, SERVER, true, &StreamData::complete(b"STARTTLS\r\n"));
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            false,
+            &StreamData::complete(b"220 Ready to start TLS\r\n"),
+        );
+        assert!(exchange.messages[0].note().is_some());
+
+        let exchange = tracker.process(
+            CLIENT,
+            SERVER,
+            true,
+            &StreamData::complete(&[0x16, 3, 1, 0, 1, 1]),
+        );
+        assert_eq!(infos(&exchange), ["C: TLS encrypted data, 6 bytes"]);
+    }
+}
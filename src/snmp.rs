@@ -0,0 +1,496 @@
+use crate::fields::Fields;
+use std::net::Ipv4Addr;
+
+pub const SNMP_PORT: u16 = 161;
+pub const SNMP_TRAP_PORT: u16 = 162;
+
+// Universal BER tags
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+
+// Application tags of SNMP values
+const IP_ADDRESS: u8 = 0x40;
+const COUNTER32: u8 = 0x41;
+const GAUGE32: u8 = 0x42;
+const TIME_TICKS: u8 = 0x43;
+const COUNTER64: u8 = 0x46;
+
+const TRAP: u8 = 0xa4;
+const GET_BULK_REQUEST: u8 = 0xa5;
+
+const VERSION_3: i64 = 3;
+// msgFlags bit of an encrypted scoped PDU
+const PRIV_FLAG: u8 = 0x02;
+
+fn pdu_type_name(pdu_type: u8) -> &'static str {
+    match pdu_type {
+        0xa0 => "get-request",
+        0xa1 => "get-next-request",
+        0xa2 => "get-response",
+        0xa3 => "set-request",
+        TRAP => "trap",
+        GET_BULK_REQUEST => "getBulkRequest",
+        0xa6 => "informRequest",
+        0xa7 => "snmpV2-trap",
+        0xa8 => "report",
+        _ => "unknown",
+    }
+}
+
+fn error_status_name(status: i64) -> &'static str {
+    match status {
+        0 => "noError",
+        1 => "tooBig",
+        2 => "noSuchName",
+        3 => "badValue",
+        4 => "readOnly",
+        5 => "genErr",
+        6 => "noAccess",
+        7 => "wrongType",
+        8 => "wrongLength",
+        9 => "wrongEncoding",
+        10 => "wrongValue",
+        11 => "noCreation",
+        12 => "inconsistentValue",
+        13 => "resourceUnavailable",
+        14 => "commitFailed",
+        15 => "undoFailed",
+        16 => "authorizationError",
+        17 => "notWritable",
+        18 => "inconsistentName",
+        _ => "unknown",
+    }
+}
+
+fn version_name(version: i64) -> &'static str {
+    match version {
+        0 => "v1",
+        1 => "v2c",
+        VERSION_3 => "v3",
+        _ => "unknown",
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// The tag and contents of the next TLV.
+    fn tlv(&mut self) -> Option<(u8, &'a [u8])> {
+        let (&tag, rest) = self.data.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+        let (len, rest) = if first & 0x80 == 0 {
+            (usize::from(first), rest)
+        } else {
+            let count = usize::from(first & 0x7f);
+            let bytes = rest.get(..count).filter(|_| (1..=4).contains(&count))?;
+            let len = bytes
+                .iter()
+                .fold(0usize, |len, &b| len << 8 | usize::from(b));
+            (len, &rest[count..])
+        };
+        let contents = rest.get(..len)?;
+        self.data = &rest[len..];
+        Some((tag, contents))
+    }
+
+    fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.tlv()? {
+            (t, contents) if t == tag => Some(contents),
+            _ => None,
+        }
+    }
+
+    fn integer(&mut self) -> Option<i64> {
+        integer(self.expect(INTEGER)?)
+    }
+}
+
+fn integer(bytes: &[u8]) -> Option<i64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+    let sign = if bytes[0] & 0x80 != 0 { -1 } else { 0 };
+    Some(
+        bytes
+            .iter()
+            .fold(sign, |value, &b| value << 8 | i64::from(b)),
+    )
+}
+
+fn unsigned(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() > 9 {
+        return None;
+    }
+    Some(bytes.iter().fold(0, |value, &b| value << 8 | u64::from(b)))
+}
+
+pub fn format_oid(bytes: &[u8]) -> Option<String> {
+    let mut arcs = Vec::new();
+    let mut arc: u64 = 0;
+    for &byte in bytes {
+        arc = arc.checked_mul(128)? | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            if arcs.is_empty() {
+                // The first two arcs share a subidentifier
+                let first = (arc / 40).min(2);
+                arcs.push(first);
+                arcs.push(arc - first * 40);
+            } else {
+                arcs.push(arc);
+            }
+            arc = 0;
+        }
+    }
+    if arcs.is_empty() || bytes.last()? & 0x80 != 0 {
+        return None;
+    }
+    let arcs: Vec<_> = arcs.iter().map(|arc| arc.to_string()).collect();
+    Some(arcs.join("."))
+}
+
+fn format_octets(bytes: &[u8]) -> String {
+    if !bytes.is_empty()
+        && bytes
+            .iter()
+            .all(|&b| b.is_ascii_graphic() || b == b' ' || b == b'\r' || b == b'\n')
+    {
+        String::from_utf8_lossy(bytes).into_owned()
+    } else {
+        let octets: Vec<_> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        octets.join(":")
+    }
+}
+
+/// The value of a variable binding as text, or None for the NULL of a
+/// request.
+fn format_value(tag: u8, bytes: &[u8]) -> Option<String> {
+    Some(match tag {
+        NULL => return None,
+        INTEGER => integer(bytes)?.to_string(),
+        OCTET_STRING => format_octets(bytes),
+        OBJECT_IDENTIFIER => format_oid(bytes)?,
+        IP_ADDRESS => Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).to_string(),
+        COUNTER32 | GAUGE32 | COUNTER64 => unsigned(bytes)?.to_string(),
+        TIME_TICKS => format!("{} ticks", unsigned(bytes)?),
+        0x80 => "noSuchObject".to_string(),
+        0x81 => "noSuchInstance".to_string(),
+        0x82 => "endOfMibView".to_string(),
+        _ => format_octets(bytes),
+    })
+}
+
+/// The enterprise and trap codes of an SNMPv1 trap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trap {
+    pub enterprise: String,
+    pub agent: Option<Ipv4Addr>,
+    pub generic: i64,
+    pub specific: i64,
+}
+
+/// A PDU and its variable bindings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pdu {
+    pub pdu_type: u8,
+    pub request_id: i64,
+    /// Non-repeaters of a GetBulkRequest.
+    pub error_status: i64,
+    /// Max-repetitions of a GetBulkRequest.
+    pub error_index: i64,
+    pub trap: Option<Trap>,
+    /// Object identifiers and their values.
+    pub varbinds: Vec<(String, Option<String>)>,
+}
+
+impl Pdu {
+    fn parse(pdu_type: u8, contents: &[u8]) -> Option<Self> {
+        let mut reader = Reader { data: contents };
+        let mut pdu = Pdu {
+            pdu_type,
+            request_id: 0,
+            error_status: 0,
+            error_index: 0,
+            trap: None,
+            varbinds: Vec::new(),
+        };
+        if pdu_type == TRAP {
+            let enterprise = format_oid(reader.expect(OBJECT_IDENTIFIER)?)?;
+            let agent = <[u8; 4]>::try_from(reader.expect(IP_ADDRESS)?)
+                .ok()
+                .map(Ipv4Addr::from);
+            let generic = reader.integer()?;
+            let specific = reader.integer()?;
+            reader.expect(TIME_TICKS)?;
+            pdu.trap = Some(Trap {
+                enterprise,
+                agent,
+                generic,
+                specific,
+            });
+        } else {
+            pdu.request_id = reader.integer()?;
+            pdu.error_status = reader.integer()?;
+            pdu.error_index = reader.integer()?;
+        }
+
+        let mut bindings = Reader {
+            data: reader.expect(SEQUENCE)?,
+        };
+        while let Some(binding) = bindings.expect(SEQUENCE) {
+            let mut binding = Reader { data: binding };
+            let oid = format_oid(binding.expect(OBJECT_IDENTIFIER)?)?;
+            let (tag, value) = binding.tlv()?;
+            pdu.varbinds.push((oid, format_value(tag, value)));
+        }
+        Some(pdu)
+    }
+
+    fn is_error(&self) -> bool {
+        self.pdu_type != GET_BULK_REQUEST && self.trap.is_none() && self.error_status != 0
+    }
+}
+
+/// The header of an SNMPv3 message and its user-based security
+/// parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct V3Header {
+    pub msg_id: i64,
+    pub flags: u8,
+    pub security_model: i64,
+    pub engine_id: Vec<u8>,
+    pub user_name: String,
+}
+
+/// An SNMP message. The PDU of an encrypted SNMPv3 message is None.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnmpMessage {
+    pub version: i64,
+    pub community: Option<String>,
+    pub v3: Option<V3Header>,
+    pub pdu: Option<Pdu>,
+}
+
+impl SnmpMessage {
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let mut message = Reader {
+            data: Reader { data: payload }.expect(SEQUENCE)?,
+        };
+        let version = message.integer()?;
+        if version != VERSION_3 {
+            if !matches!(version, 0 | 1) {
+                return None;
+            }
+            let community = String::from_utf8_lossy(message.expect(OCTET_STRING)?).into_owned();
+            let (pdu_type, contents) = message.tlv()?;
+            return Some(SnmpMessage {
+                version,
+                community: Some(community),
+                v3: None,
+                pdu: Some(Pdu::parse(pdu_type, contents)?),
+            });
+        }
+
+        let mut global = Reader {
+            data: message.expect(SEQUENCE)?,
+        };
+        let msg_id = global.integer()?;
+        // Maximum message size
+        global.integer()?;
+        let flags = *global.expect(OCTET_STRING)?.first()?;
+        let security_model = global.integer()?;
+
+        let mut security = Reader {
+            data: message.expect(OCTET_STRING)?,
+        };
+        let (engine_id, user_name) = match security.expect(SEQUENCE) {
+            Some(usm) => {
+                let mut usm = Reader { data: usm };
+                let engine_id = usm.expect(OCTET_STRING)?.to_vec();
+                // Engine boots and time
+                usm.integer()?;
+                usm.integer()?;
+                let user_name = String::from_utf8_lossy(usm.expect(OCTET_STRING)?).into_owned();
+                (engine_id, user_name)
+            }
+            None => (Vec::new(), String::new()),
+        };
+
+        let pdu = if flags & PRIV_FLAG != 0 {
+            None
+        } else {
+            let mut scoped = Reader {
+                data: message.expect(SEQUENCE)?,
+            };
+            // Context engine ID and name
+            scoped.expect(OCTET_STRING)?;
+            scoped.expect(OCTET_STRING)?;
+            let (pdu_type, contents) = scoped.tlv()?;
+            Some(Pdu::parse(pdu_type, contents)?)
+        };
+        Some(SnmpMessage {
+            version,
+            community: None,
+            v3: Some(V3Header {
+                msg_id,
+                flags,
+                security_model,
+                engine_id,
+                user_name,
+            }),
+            pdu,
+        })
+    }
+
+    pub fn info(&self) -> String {
+        let Some(pdu) = &self.pdu else {
+            return "encryptedPDU".to_string();
+        };
+        let mut info = pdu_type_name(pdu.pdu_type).to_string();
+        if let Some(trap) = &pdu.trap {
+            info.push_str(&format!(
+                " {} generic={} specific={}",
+                trap.enterprise, trap.generic, trap.specific
+            ));
+        }
+        for (oid, _) in &pdu.varbinds {
+            info.push(' ');
+            info.push_str(oid);
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("snmp.version", version_name(self.version));
+        if let Some(community) = &self.community {
+            fields.insert("snmp.community", community.as_str());
+        }
+        if let Some(v3) = &self.v3 {
+            if let Ok(msg_id) = u64::try_from(v3.msg_id) {
+                fields.insert("snmp.msgID", msg_id);
+            }
+            fields.insert("snmp.msgFlags", v3.flags);
+            if let Ok(model) = u64::try_from(v3.security_model) {
+                fields.insert("snmp.msgSecurityModel", model);
+            }
+            if !v3.engine_id.is_empty() {
+                fields.insert("snmp.msgAuthoritativeEngineID", v3.engine_id.clone());
+            }
+            if !v3.user_name.is_empty() {
+                fields.insert("snmp.msgUserName", v3.user_name.as_str());
+            }
+        }
+        let Some(pdu) = &self.pdu else {
+            return;
+        };
+        fields.insert("snmp.data", pdu_type_name(pdu.pdu_type));
+        if let Some(trap) = &pdu.trap {
+            fields.insert("snmp.enterprise", trap.enterprise.as_str());
+            if let Some(agent) = trap.agent {
+                fields.insert("snmp.agent_addr", agent);
+            }
+        } else if let Ok(request_id) = u64::try_from(pdu.request_id) {
+            fields.insert("snmp.request_id", request_id);
+        }
+        if pdu.is_error() {
+            fields.insert("snmp.error_status", error_status_name(pdu.error_status));
+        }
+        for (oid, value) in &pdu.varbinds {
+            fields.insert("snmp.name", oid.as_str());
+            if let Some(value) = value {
+                fields.insert("snmp.value", value.as_str());
+            }
+        }
+    }
+
+    /// The error status of a response, with the binding it refers to.
+    pub fn error(&self) -> Option<String> {
+        let pdu = self.pdu.as_ref().filter(|pdu| pdu.is_error())?;
+        let binding = usize::try_from(pdu.error_index)
+            .ok()
+            .and_then(|index| pdu.varbinds.get(index.checked_sub(1)?));
+        Some(match binding {
+            Some((oid, _)) => format!("{} ({})", error_status_name(pdu.error_status), oid),
+            None => error_status_name(pdu.error_status).to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut data = vec![tag, contents.len() as u8];
+        data.extend_from_slice(contents);
+        data
+    }
+
+    fn v2c(pdu_type: u8, error_status: u8, value: &[u8]) -> Vec<u8> {
+        // sysDescr.0
+        let mut binding = tlv(OBJECT_IDENTIFIER, &[0x2b, 6, 1, 2, 1, 1, 1, 0]);
+        binding.extend_from_slice(value);
+        let mut pdu = tlv(INTEGER, &[0x30, 0x39]);
+        pdu.extend(tlv(INTEGER, &[error_status]));
+        pdu.extend(tlv(INTEGER, &[u8::from(error_status != 0)]));
+        pdu.extend(tlv(SEQUENCE, &tlv(SEQUENCE, &binding)));
+        let mut message = tlv(INTEGER, &[1]);
+        message.extend(tlv(OCTET_STRING, b"public"));
+        message.extend(tlv(pdu_type, &pdu));
+        tlv(SEQUENCE, &message)
+    }
+
+    #[test]
+    fn test_get_and_response() {
+        let request = SnmpMessage::parse(&v2c(0xa0, 0, &tlv(NULL, &[]))).unwrap();
+        assert_eq!(request.community.as_deref(), Some("public"));
+        assert_eq!(request.info(), "get-request 1.3.6.1.2.1.1.1.0");
+        assert!(request.error().is_none());
+
+        let response = SnmpMessage::parse(&v2c(0xa2, 0, &tlv(OCTET_STRING, b"Linux"))).unwrap();
+        let mut fields = Fields::new();
+        response.insert_fields(&mut fields);
+        assert_eq!(fields.get("snmp.value"), Some(&"Linux".into()));
+        assert_eq!(fields.get("snmp.request_id"), Some(&12345u64.into()));
+
+        let failed = SnmpMessage::parse(&v2c(0xa2, 2, &tlv(NULL, &[]))).unwrap();
+        assert_eq!(failed.error().unwrap(), "noSuchName (1.3.6.1.2.1.1.1.0)");
+    }
+
+    #[test]
+    fn test_v1_trap_and_encrypted_v3() {
+        let mut pdu = tlv(OBJECT_IDENTIFIER, &[0x2b, 6, 1, 4, 1, 0x82, 0x37]);
+        pdu.extend(tlv(IP_ADDRESS, &[10, 0, 0, 1]));
+        pdu.extend(tlv(INTEGER, &[2]));
+        pdu.extend(tlv(INTEGER, &[0]));
+        pdu.extend(tlv(TIME_TICKS, &[1, 0]));
+        pdu.extend(tlv(SEQUENCE, &[]));
+        let mut message = tlv(INTEGER, &[0]);
+        message.extend(tlv(OCTET_STRING, b"public"));
+        message.extend(tlv(TRAP, &pdu));
+        let trap = SnmpMessage::parse(&tlv(SEQUENCE, &message)).unwrap();
+        assert_eq!(trap.info(), "trap 1.3.6.1.4.1.311 generic=2 specific=0");
+
+        let mut global = tlv(INTEGER, &[7]);
+        global.extend(tlv(INTEGER, &[0x05, 0xdc]));
+        global.extend(tlv(OCTET_STRING, &[0x07]));
+        global.extend(tlv(INTEGER, &[3]));
+        let mut usm = tlv(OCTET_STRING, &[0x80, 0, 0x1f, 0x88]);
+        usm.extend(tlv(INTEGER, &[1]));
+        usm.extend(tlv(INTEGER, &[0x10]));
+        usm.extend(tlv(OCTET_STRING, b"monitor"));
+        usm.extend(tlv(OCTET_STRING, &[0; 12]));
+        usm.extend(tlv(OCTET_STRING, &[0; 8]));
+        let mut message = tlv(INTEGER, &[3]);
+        message.extend(tlv(SEQUENCE, &global));
+        message.extend(tlv(OCTET_STRING, &tlv(SEQUENCE, &usm)));
+        message.extend(tlv(OCTET_STRING, &[0xaa; 16]));
+        let encrypted = SnmpMessage::parse(&tlv(SEQUENCE, &message)).unwrap();
+        assert_eq!(encrypted.info(), "encryptedPDU");
+        assert_eq!(encrypted.v3.unwrap().user_name, "monitor");
+    }
+}
@@ -0,0 +1,634 @@
+use crate::fields::Fields;
+use crate::query::{AppMessage, ConnectionState, ConnectionTable, Exchange};
+use crate::reassembly::{MessageBuffer, StreamData};
+use md5::{Digest, Md5};
+use std::net::IpAddr;
+
+// Larger lengths mean the stream isn't cleartext SSH
+const MAX_PACKET_LEN: usize = 35000;
+
+const DISCONNECT: u8 = 1;
+const KEXINIT: u8 = 20;
+const NEWKEYS: u8 = 21;
+
+/// The name of a transport or key exchange message.
+pub fn message_name(code: u8) -> &'static str {
+    match code {
+        1 => "Disconnect",
+        2 => "Ignore",
+        3 => "Unimplemented",
+        4 => "Debug",
+        5 => "Service Request",
+        6 => "Service Accept",
+        7 => "Extension Info",
+        20 => "Key Exchange Init",
+        21 => "New Keys",
+        30 => "Diffie-Hellman Key Exchange Init",
+        31 => "Diffie-Hellman Key Exchange Reply",
+        32 => "Diffie-Hellman Group Exchange Init",
+        33 => "Diffie-Hellman Group Exchange Reply",
+        34 => "Diffie-Hellman Group Exchange Request",
+        _ => "Unknown",
+    }
+}
+
+/// The algorithm lists of a KEXINIT message. Directional lists are client
+/// to server, then server to client.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KexInit {
+    pub kex_algorithms: String,
+    pub server_host_key_algorithms: String,
+    pub encryption_algorithms: [String; 2],
+    pub mac_algorithms: [String; 2],
+    pub compression_algorithms: [String; 2],
+}
+
+impl KexInit {
+    /// The HASSH fingerprint of a client's KEXINIT, or the hasshServer of a
+    /// server's, with the algorithms it hashes.
+    pub fn hassh(&self, from_client: bool) -> (String, String) {
+        let direction = usize::from(!from_client);
+        let algorithms = format!(
+            "{};{};{};{}",
+            self.kex_algorithms,
+            self.encryption_algorithms[direction],
+            self.mac_algorithms[direction],
+            self.compression_algorithms[direction]
+        );
+        let hash = Md5::digest(algorithms.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        (algorithms, hash)
+    }
+}
+
+/// The algorithms both sides agreed on, `None` where they have none in
+/// common.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Negotiated {
+    pub kex: Option<String>,
+    pub host_key: Option<String>,
+    pub encryption: [Option<String>; 2],
+    pub mac: [Option<String>; 2],
+    pub compression: [Option<String>; 2],
+}
+
+/// Picks the first algorithm of the client's list the server supports.
+fn choose(client: &str, server: &str) -> Option<String> {
+    client
+        .split(',')
+        .find(|algorithm| server.split(',').any(|other| other == *algorithm))
+        .map(str::to_string)
+}
+
+fn negotiate(client: &KexInit, server: &KexInit) -> Negotiated {
+    let both = |lists: fn(&KexInit) -> &[String; 2]| {
+        [0, 1].map(|direction| choose(&lists(client)[direction], &lists(server)[direction]))
+    };
+    Negotiated {
+        kex: choose(&client.kex_algorithms, &server.kex_algorithms),
+        host_key: choose(
+            &client.server_host_key_algorithms,
+            &server.server_host_key_algorithms,
+        ),
+        encryption: both(|kexinit| &kexinit.encryption_algorithms),
+        mac: both(|kexinit| &kexinit.mac_algorithms),
+        compression: both(|kexinit| &kexinit.compression_algorithms),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// The identification string, such as `SSH-2.0-OpenSSH_9.6`.
+    Banner(String),
+    /// A KEXINIT, with the algorithms negotiated once both sides sent one.
+    KexInit {
+        kexinit: Box<KexInit>,
+        negotiated: Option<Box<Negotiated>>,
+    },
+    Disconnect {
+        reason: u32,
+        description: String,
+    },
+    /// Any other cleartext message.
+    Packet(u8),
+    /// Data after NEWKEYS.
+    Encrypted(usize),
+}
+
+/// An SSH identification string or transport message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshMessage {
+    pub from_client: bool,
+    pub kind: Kind,
+}
+
+impl AppMessage for SshMessage {
+    const TIME_FIELD: &'static str = "ssh.response_time";
+
+    fn info(&self) -> String {
+        let side = if self.from_client { "Client" } else { "Server" };
+        match &self.kind {
+            Kind::Banner(banner) => format!("{}: Protocol ({})", side, banner),
+            Kind::KexInit {
+                negotiated: Some(negotiated),
+                ..
+            } => {
+                let unknown = || "none".to_string();
+                format!(
+                    "{}: Key Exchange Init (kex {}, cipher {})",
+                    side,
+                    negotiated.kex.clone().unwrap_or_else(unknown),
+                    negotiated.encryption[0].clone().unwrap_or_else(unknown)
+                )
+            }
+            Kind::KexInit { .. } => format!("{}: Key Exchange Init", side),
+            Kind::Disconnect { .. } => format!("{}: Disconnect", side),
+            Kind::Packet(code) => format!("{}: {}", side, message_name(*code)),
+            Kind::Encrypted(len) => format!("{}: Encrypted packet (len={})", side, len),
+        }
+    }
+
+    fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert(
+            "ssh.direction",
+            if self.from_client { "client" } else { "server" },
+        );
+        match &self.kind {
+            Kind::Banner(banner) => {
+                fields.insert("ssh.protocol", banner.as_str());
+                let mut parts = banner.splitn(3, '-').skip(1);
+                if let Some(version) = parts.next() {
+                    fields.insert("ssh.protoversion", version);
+                }
+                if let Some(rest) = parts.next() {
+                    let (software, comments) = rest.split_once(' ').unwrap_or((rest, ""));
+                    fields.insert("ssh.softwareversion", software);
+                    if !comments.is_empty() {
+                        fields.insert("ssh.comments", comments);
+                    }
+                }
+            }
+            Kind::KexInit {
+                kexinit,
+                negotiated,
+            } => {
+                fields.insert("ssh.message_code", KEXINIT);
+                fields.insert("ssh.kex_algorithms", kexinit.kex_algorithms.as_str());
+                fields.insert(
+                    "ssh.server_host_key_algorithms",
+                    kexinit.server_host_key_algorithms.as_str(),
+                );
+                for (names, lists) in [
+                    (
+                        [
+                            "ssh.encryption_algorithms_client_to_server",
+                            "ssh.encryption_algorithms_server_to_client",
+                        ],
+                        &kexinit.encryption_algorithms,
+                    ),
+                    (
+                        [
+                            "ssh.mac_algorithms_client_to_server",
+                            "ssh.mac_algorithms_server_to_client",
+                        ],
+                        &kexinit.mac_algorithms,
+                    ),
+                    (
+                        [
+                            "ssh.compression_algorithms_client_to_server",
+                            "ssh.compression_algorithms_server_to_client",
+                        ],
+                        &kexinit.compression_algorithms,
+                    ),
+                ] {
+                    for (name, list) in names.into_iter().zip(lists) {
+                        fields.insert(name, list.as_str());
+                    }
+                }
+                let (algorithms, hash) = kexinit.hassh(self.from_client);
+                if self.from_client {
+                    fields.insert("ssh.kex.hassh_algorithms", algorithms);
+                    fields.insert("ssh.kex.hassh", hash);
+                } else {
+                    fields.insert("ssh.kex.hasshserver_algorithms", algorithms);
+                    fields.insert("ssh.kex.hasshserver", hash);
+                }
+                if let Some(negotiated) = negotiated {
+                    insert_negotiated(fields, negotiated);
+                }
+            }
+            Kind::Disconnect {
+                reason,
+                description,
+            } => {
+                fields.insert("ssh.message_code", DISCONNECT);
+                fields.insert("ssh.disconnect.reason", *reason);
+                fields.insert("ssh.disconnect.description", description.as_str());
+            }
+            Kind::Packet(code) => fields.insert("ssh.message_code", *code),
+            Kind::Encrypted(len) => fields.insert("ssh.encrypted_packet.length", *len),
+        }
+    }
+
+    fn error(&self) -> Option<String> {
+        match &self.kind {
+            Kind::Disconnect {
+                reason,
+                description,
+            } => Some(format!("disconnected ({}): {}", reason, description)),
+            Kind::KexInit {
+                negotiated: Some(negotiated),
+                ..
+            } => {
+                let missing: Vec<_> = [
+                    ("key exchange", negotiated.kex.is_none()),
+                    ("host key", negotiated.host_key.is_none()),
+                    (
+                        "encryption",
+                        negotiated.encryption.iter().any(Option::is_none),
+                    ),
+                    (
+                        "compression",
+                        negotiated.compression.iter().any(Option::is_none),
+                    ),
+                ]
+                .into_iter()
+                .filter_map(|(kind, missing)| missing.then_some(kind))
+                .collect();
+                (!missing.is_empty())
+                    .then(|| format!("no common {} algorithm", missing.join(" or ")))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn insert_negotiated(fields: &mut Fields, negotiated: &Negotiated) {
+    let names: [(&'static str, &Option<String>); 8] = [
+        ("ssh.negotiated.kex", &negotiated.kex),
+        ("ssh.negotiated.host_key", &negotiated.host_key),
+        (
+            "ssh.negotiated.encryption_client_to_server",
+            &negotiated.encryption[0],
+        ),
+        (
+            "ssh.negotiated.encryption_server_to_client",
+            &negotiated.encryption[1],
+        ),
+        ("ssh.negotiated.mac_client_to_server", &negotiated.mac[0]),
+        ("ssh.negotiated.mac_server_to_client", &negotiated.mac[1]),
+        (
+            "ssh.negotiated.compression_client_to_server",
+            &negotiated.compression[0],
+        ),
+        (
+            "ssh.negotiated.compression_server_to_client",
+            &negotiated.compression[1],
+        ),
+    ];
+    for (name, algorithm) in names {
+        if let Some(algorithm) = algorithm {
+            fields.insert(name, algorithm.as_str());
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        Some(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+}
+
+fn parse_kexinit(payload: &[u8]) -> Option<KexInit> {
+    let mut reader = Reader { data: payload };
+    // Cookie
+    reader.bytes(16)?;
+    Some(KexInit {
+        kex_algorithms: reader.string()?,
+        server_host_key_algorithms: reader.string()?,
+        encryption_algorithms: [reader.string()?, reader.string()?],
+        mac_algorithms: [reader.string()?, reader.string()?],
+        compression_algorithms: [reader.string()?, reader.string()?],
+    })
+}
+
+fn parse_message(code: u8, payload: &[u8]) -> Kind {
+    match code {
+        KEXINIT => match parse_kexinit(payload) {
+            Some(kexinit) => Kind::KexInit {
+                kexinit: Box::new(kexinit),
+                negotiated: None,
+            },
+            None => Kind::Packet(code),
+        },
+        DISCONNECT => {
+            let mut reader = Reader { data: payload };
+            match (reader.u32(), reader.string()) {
+                (Some(reason), Some(description)) => Kind::Disconnect {
+                    reason,
+                    description,
+                },
+                _ => Kind::Packet(code),
+            }
+        }
+        _ => Kind::Packet(code),
+    }
+}
+
+/// Whether stream data starts with an SSH identification string.
+pub fn is_banner(data: &[u8]) -> bool {
+    data.starts_with(b"SSH-2.0-") || data.starts_with(b"SSH-1.99-")
+}
+
+type Endpoint = (IpAddr, u16);
+
+#[derive(Debug, Default)]
+struct Direction {
+    buffer: MessageBuffer,
+    banner_seen: bool,
+    /// NEWKEYS was sent, so everything after it is encrypted.
+    encrypted: bool,
+}
+
+impl AsMut<MessageBuffer> for Direction {
+    fn as_mut(&mut self) -> &mut MessageBuffer {
+        &mut self.buffer
+    }
+}
+
+impl Direction {
+    fn messages(&mut self, from_client: bool, messages: &mut Vec<SshMessage>) {
+        while !self.banner_seen {
+            // Servers may send other lines before their banner
+            let Some(line) = self.buffer.take_line() else {
+                return;
+            };
+            if line.starts_with("SSH-") {
+                self.banner_seen = true;
+                messages.push(SshMessage {
+                    from_client,
+                    kind: Kind::Banner(line),
+                });
+            }
+        }
+        while !self.encrypted {
+            let data = self.buffer.data();
+            let Some(header) = data.get(..5) else {
+                return;
+            };
+            let packet_len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+            let packet_len = packet_len as usize;
+            let padding_len = usize::from(header[4]);
+            if packet_len > MAX_PACKET_LEN || padding_len + 1 > packet_len {
+                // Lost sync; don't guess at packet boundaries
+                self.encrypted = true;
+                break;
+            }
+            let Some(packet) = self.buffer.take(4 + packet_len) else {
+                return;
+            };
+            let payload = &packet[5..4 + packet_len - padding_len];
+            let Some((&code, body)) = payload.split_first() else {
+                continue;
+            };
+            if code == NEWKEYS {
+                self.encrypted = true;
+            }
+            messages.push(SshMessage {
+                from_client,
+                kind: parse_message(code, body),
+            });
+        }
+        let remaining = self.buffer.data().len();
+        if remaining > 0 {
+            messages.push(SshMessage {
+                from_client,
+                kind: Kind::Encrypted(remaining),
+            });
+            self.buffer.clear();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// The KEXINIT of each side, until both are known.
+    kexinits: [Option<KexInit>; 2],
+}
+
+impl ConnectionState for Connection {
+    type Direction = Direction;
+
+    fn gap(&mut self, direction: &mut Direction, _: bool) {
+        // Packet boundaries are lost
+        direction.encrypted = true;
+    }
+}
+
+/// Splits reassembled SSH connections into identification strings and the
+/// cleartext messages of the key exchange.
+#[derive(Debug, Default)]
+pub struct SshTracker {
+    connections: ConnectionTable<Connection>,
+}
+
+impl SshTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes stream data between `src` and `dst` if it belongs to a
+    /// connection that started with an SSH identification string, whatever
+    /// its port. The side with the lower port is taken as the server.
+    pub fn detect(
+        &mut self,
+        src: Endpoint,
+        dst: Endpoint,
+        stream: &StreamData,
+    ) -> Option<Exchange<SshMessage>> {
+        if self.connections.contains(src, dst) {
+            return Some(self.process(src, dst, true, stream));
+        }
+        if self.connections.contains(dst, src) {
+            return Some(self.process(dst, src, false, stream));
+        }
+        if !is_banner(&stream.data) {
+            return None;
+        }
+        Some(if src.1 > dst.1 {
+            self.process(src, dst, true, stream)
+        } else {
+            self.process(dst, src, false, stream)
+        })
+    }
+
+    /// Decodes the identification strings and key exchange messages
+    /// `stream` completes.
+    pub fn process(
+        &mut self,
+        client: Endpoint,
+        server: Endpoint,
+        from_client: bool,
+        stream: &StreamData,
+    ) -> Exchange<SshMessage> {
+        let mut exchange = Exchange::default();
+        let Some(connection) = self.connections.update(client, server, from_client, stream) else {
+            return exchange;
+        };
+        let (direction, connection) = connection.split(from_client);
+        direction.buffer.push(&stream.data);
+        direction.messages(from_client, &mut exchange.messages);
+
+        for message in &mut exchange.messages {
+            let Kind::KexInit {
+                kexinit,
+                negotiated,
+            } = &mut message.kind
+            else {
+                continue;
+            };
+            connection.kexinits[usize::from(!from_client)] = Some((**kexinit).clone());
+            if let [Some(client_kexinit), Some(server_kexinit)] = &connection.kexinits {
+                *negotiated = Some(Box::new(negotiate(client_kexinit, server_kexinit)));
+            }
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    fn string(text: &str) -> Vec<u8> {
+        let mut data = (text.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(text.as_bytes());
+        data
+    }
+
+    fn packet(payload: &[u8]) -> Vec<u8> {
+        let padding = 8 - (payload.len() + 5) % 8 + 4;
+        let mut data = ((payload.len() + padding + 1) as u32)
+            .to_be_bytes()
+            .to_vec();
+        data.push(padding as u8);
+        data.extend_from_slice(payload);
+        data.resize(data.len() + padding, 0);
+        data
+    }
+
+    fn kexinit(kex: &str, cipher: &str) -> Vec<u8> {
+        let mut payload = vec![KEXINIT];
+        payload.extend_from_slice(&[0; 16]);
+        for list in [
+            kex,
+            "ssh-ed25519",
+            cipher,
+            cipher,
+            "hmac-sha2-256",
+            "hmac-sha2-256",
+            "none",
+            "none",
+            "",
+            "",
+        ] {
+            payload.extend(string(list));
+        }
+        payload.extend_from_slice(&[0, 0, 0, 0, 0]);
+        packet(&payload)
+    }
+
+    #[test]
+    fn test_key_exchange() {
+        let mut tracker = SshTracker::new();
+        let mut data = b"SSH-2.0-OpenSSH_9.6 Ubuntu-3\r\n".to_vec();
+        data.extend(kexinit(
+            "curve25519-sha256,diffie-hellman-group14-sha256",
+            "aes256-gcm@openssh.com,aes128-ctr",
+        ));
+        let exchange = tracker
+            .detect(SERVER, CLIENT, &StreamData::complete(&data))
+            .unwrap();
+        let infos: Vec<_> = exchange.messages.iter().map(|m| m.info()).collect();
+        assert_eq!(
+            infos,
+            [
+                "Server: Protocol (SSH-2.0-OpenSSH_9.6 Ubuntu-3)",
+                "Server: Key Exchange Init"
+            ]
+        );
+        let mut fields = Fields::new();
+        exchange.messages[1].insert_fields(&mut fields);
+        assert!(fields.contains("ssh.kex.hasshserver"));
+
+        let mut data = b"SSH-2.0-PuTTY_Release_0.80\r\n".to_vec();
+        data.extend(kexinit("diffie-hellman-group14-sha256", "aes128-ctr"));
+        data.extend(packet(&[NEWKEYS]));
+        data.extend_from_slice(&[0xde; 36]);
+        let exchange = tracker
+            .detect(CLIENT, SERVER, &StreamData::complete(&data))
+            .unwrap();
+        let infos: Vec<_> = exchange.messages.iter().map(|m| m.info()).collect();
+        assert_eq!(
+            infos,
+            [
+                "Client: Protocol (SSH-2.0-PuTTY_Release_0.80)",
+                "Client: Key Exchange Init (kex diffie-hellman-group14-sha256, cipher aes128-ctr)",
+                "Client: New Keys",
+                "Client: Encrypted packet (len=36)"
+            ]
+        );
+
+        let (algorithms, hash) = match &exchange.messages[1].kind {
+            Kind::KexInit { kexinit, .. } => kexinit.hassh(true),
+            kind => panic!("unexpected {:?}", kind),
+        };
+        assert_eq!(
+            algorithms,
+            "diffie-hellman-group14-sha256;aes128-ctr;hmac-sha2-256;none"
+        );
+        assert_eq!(hash, "66d445f1033fbb0144406fffe0e61fee");
+        assert!(exchange.messages[1].error().is_none());
+    }
+
+    #[test]
+    fn test_detection_and_failed_negotiation() {
+        let mut tracker = SshTracker::new();
+        assert!(tracker
+            .detect(CLIENT, SERVER, &StreamData::complete(b"GET / HTTP/1.1\r\n"))
+            .is_none());
+
+        let mut data = b"SSH-2.0-OpenSSH_9.6\r\n".to_vec();
+        data.extend(kexinit("curve25519-sha256", "aes128-ctr"));
+        tracker
+            .detect(SERVER, CLIENT, &StreamData::complete(&data))
+            .unwrap();
+        let mut data = b"SSH-2.0-libssh_0.9\r\n".to_vec();
+        data.extend(kexinit("diffie-hellman-group1-sha1", "3des-cbc"));
+        let exchange = tracker
+            .detect(CLIENT, SERVER, &StreamData::complete(&data))
+            .unwrap();
+        assert_eq!(
+            exchange.messages[1].error().unwrap(),
+            "no common key exchange or encryption algorithm"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use crate::fields::{format_mac, Fields};
+
+/// LLC SAP of the Spanning Tree Protocol.
+pub const LLC_SAP_STP: u8 = 0x42;
+
+const BPDU_CONFIG: u8 = 0x00;
+const BPDU_TCN: u8 = 0x80;
+const BPDU_RST: u8 = 0x02;
+
+/// Bridge priority (including the VLAN in the system ID extension) and MAC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BridgeId {
+    pub priority: u16,
+    pub mac: [u8; 6],
+}
+
+impl BridgeId {
+    fn parse(bytes: &[u8]) -> Option<BridgeId> {
+        Some(BridgeId {
+            priority: u16::from_be_bytes([bytes[0], bytes[1]]),
+            mac: bytes.get(2..8)?.try_into().ok()?,
+        })
+    }
+}
+
+impl std::fmt::Display for BridgeId {
+    /// Priority, system ID extension and MAC, e.g. `32768/100/00:11:22:33:44:55`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}",
+            self.priority & 0xf000,
+            self.priority & 0x0fff,
+            format_mac(&self.mac)
+        )
+    }
+}
+
+/// Fields of a configuration or RST BPDU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BpduConfig {
+    pub flags: u8,
+    pub root: BridgeId,
+    pub root_path_cost: u32,
+    pub bridge: BridgeId,
+    pub port_id: u16,
+}
+
+/// A Spanning Tree BPDU (802.1D STP, 802.1w RSTP or 802.1s MSTP).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bpdu {
+    pub version: u8,
+    pub bpdu_type: u8,
+    /// None for topology change notifications, which carry no more fields.
+    pub config: Option<BpduConfig>,
+}
+
+impl Bpdu {
+    pub fn parse(payload: &[u8]) -> Option<Bpdu> {
+        let header = payload.get(..4)?;
+        // Protocol identifier is always 0
+        if header[0..2] != [0, 0] {
+            return None;
+        }
+        let (version, bpdu_type) = (header[2], header[3]);
+
+        let config = match bpdu_type {
+            BPDU_TCN => None,
+            BPDU_CONFIG | BPDU_RST => {
+                let body = payload.get(4..35)?;
+                Some(BpduConfig {
+                    flags: body[0],
+                    root: BridgeId::parse(&body[1..9])?,
+                    root_path_cost: u32::from_be_bytes([body[9], body[10], body[11], body[12]]),
+                    bridge: BridgeId::parse(&body[13..21])?,
+                    port_id: u16::from_be_bytes([body[21], body[22]]),
+                })
+            }
+            _ => return None,
+        };
+
+        Some(Bpdu {
+            version,
+            bpdu_type,
+            config,
+        })
+    }
+
+    pub fn protocol_name(&self) -> &'static str {
+        match self.version {
+            0 => "STP",
+            2 => "RSTP",
+            _ => "MSTP",
+        }
+    }
+
+    pub fn info(&self) -> String {
+        match &self.config {
+            None => "Topology Change Notification".to_string(),
+            Some(config) => format!(
+                "{}. Root = {}  Cost = {}  Port = 0x{:04x}",
+                if self.bpdu_type == BPDU_RST {
+                    match self.version {
+                        2 => "RST",
+                        _ => "MST",
+                    }
+                } else {
+                    "Conf"
+                },
+                config.root,
+                config.root_path_cost,
+                config.port_id
+            ),
+        }
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("stp.version", self.version);
+        fields.insert("stp.type", self.bpdu_type);
+        if let Some(config) = &self.config {
+            fields.insert("stp.flags.tc", config.flags & 0x01 != 0);
+            fields.insert("stp.root.prio", config.root.priority);
+            fields.insert("stp.root.hw", config.root.mac);
+            fields.insert("stp.root.cost", config.root_path_cost);
+            fields.insert("stp.bridge.prio", config.bridge.priority);
+            fields.insert("stp.bridge.hw", config.bridge.mac);
+            fields.insert("stp.port", config.port_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configuration_bpdu() {
+        let mut payload = vec![0, 0, 0, BPDU_CONFIG, 0x01];
+        payload.extend_from_slice(&[0x80, 0x64, 0, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        payload.extend_from_slice(&4u32.to_be_bytes());
+        payload.extend_from_slice(&[0x80, 0x64, 0, 0x11, 0x22, 0x33, 0x44, 0x66]);
+        payload.extend_from_slice(&[0x80, 0x01]);
+        payload.extend_from_slice(&[0; 8]); // ages and timers
+
+        let bpdu = Bpdu::parse(&payload).unwrap();
+        assert_eq!(bpdu.protocol_name(), "STP");
+        assert_eq!(
+            bpdu.info(),
+            "Conf. Root = 32768/100/00:11:22:33:44:55  Cost = 4  Port = 0x8001"
+        );
+
+        let mut fields = Fields::new();
+        bpdu.insert_fields(&mut fields);
+        assert_eq!(fields.get("stp.flags.tc"), Some(&true.into()));
+    }
+
+    #[test]
+    fn test_topology_change_notification() {
+        let bpdu = Bpdu::parse(&[0, 0, 0, BPDU_TCN]).unwrap();
+        assert_eq!(bpdu.config, None);
+        assert_eq!(bpdu.info(), "Topology Change Notification");
+    }
+}
@@ -0,0 +1,252 @@
+use crate::fields::Fields;
+
+pub const SYSLOG_PORT: u16 = 514;
+
+// Severities from error up raise a warning
+const ERROR: u8 = 3;
+
+const FACILITIES: [&str; 24] = [
+    "KERN", "USER", "MAIL", "DAEMON", "AUTH", "SYSLOG", "LPR", "NEWS", "UUCP", "CRON", "AUTHPRIV",
+    "FTP", "NTP", "AUDIT", "ALERT", "CLOCK", "LOCAL0", "LOCAL1", "LOCAL2", "LOCAL3", "LOCAL4",
+    "LOCAL5", "LOCAL6", "LOCAL7",
+];
+
+const SEVERITIES: [&str; 8] = [
+    "EMERG", "ALERT", "CRIT", "ERR", "WARNING", "NOTICE", "INFO", "DEBUG",
+];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A syslog message in the BSD format of RFC 3164 or the format of
+/// RFC 5424. Parts a message leaves out are empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogMessage {
+    pub facility: u8,
+    pub severity: u8,
+    /// 1 for RFC 5424 messages, None for BSD ones.
+    pub version: Option<u8>,
+    pub timestamp: String,
+    pub hostname: String,
+    pub app_name: String,
+    pub proc_id: String,
+    pub msg_id: String,
+    pub structured_data: String,
+    pub message: String,
+}
+
+/// The NILVALUE of RFC 5424 as an empty string.
+fn value(field: &str) -> String {
+    if field == "-" {
+        String::new()
+    } else {
+        field.to_string()
+    }
+}
+
+/// Splits the structured data of an RFC 5424 message from the rest.
+fn split_structured_data(rest: &str) -> (&str, &str) {
+    if let Some(rest) = rest.strip_prefix("- ") {
+        return ("", rest);
+    }
+    if rest == "-" {
+        return ("", "");
+    }
+    // Elements are bracketed, with escaped brackets in their values
+    let mut end = 0;
+    let mut escaped = false;
+    let mut depth = 0;
+    for (i, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                end = i + 1;
+            }
+            ' ' if depth == 0 => break,
+            _ => {}
+        }
+    }
+    let (data, message) = rest.split_at(end);
+    (data, message.strip_prefix(' ').unwrap_or(message))
+}
+
+impl SyslogMessage {
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let text = String::from_utf8_lossy(payload);
+        let text = text.trim_end_matches(['\r', '\n', '\0']);
+        let rest = text.strip_prefix('<')?;
+        let (priority, rest) = rest.split_once('>')?;
+        if priority.is_empty() || priority.len() > 3 {
+            return None;
+        }
+        let priority: u8 = priority.parse().ok()?;
+        if usize::from(priority >> 3) >= FACILITIES.len() {
+            return None;
+        }
+        let mut message = SyslogMessage {
+            facility: priority >> 3,
+            severity: priority & 0x07,
+            version: None,
+            timestamp: String::new(),
+            hostname: String::new(),
+            app_name: String::new(),
+            proc_id: String::new(),
+            msg_id: String::new(),
+            structured_data: String::new(),
+            message: String::new(),
+        };
+
+        if let Some(rest) = rest.strip_prefix("1 ") {
+            let mut parts = rest.splitn(6, ' ');
+            message.version = Some(1);
+            message.timestamp = value(parts.next()?);
+            message.hostname = value(parts.next().unwrap_or("-"));
+            message.app_name = value(parts.next().unwrap_or("-"));
+            message.proc_id = value(parts.next().unwrap_or("-"));
+            message.msg_id = value(parts.next().unwrap_or("-"));
+            let (structured_data, text) = split_structured_data(parts.next().unwrap_or("-"));
+            message.structured_data = structured_data.to_string();
+            // A byte order mark may precede UTF-8 text
+            message.message = text.trim_start_matches('\u{feff}').to_string();
+        } else {
+            message.parse_bsd(rest);
+        }
+        Some(message)
+    }
+
+    /// Decodes the timestamp, hostname and tag of a BSD message. Relays
+    /// may have stripped them, leaving only the text.
+    fn parse_bsd(&mut self, rest: &str) {
+        let has_timestamp = rest.len() >= 16
+            && MONTHS.contains(&rest.get(..3).unwrap_or(""))
+            && rest.as_bytes()[15] == b' ';
+        let mut rest = rest;
+        if has_timestamp {
+            self.timestamp = rest[..15].to_string();
+            rest = &rest[16..];
+            if let Some((hostname, after)) = rest.split_once(' ') {
+                if !hostname.ends_with(':') && !hostname.contains('[') {
+                    self.hostname = hostname.to_string();
+                    rest = after;
+                }
+            }
+        }
+        // The tag is the program name and optional process ID
+        let tag_end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "-_./[]".contains(c)))
+            .unwrap_or(rest.len());
+        if tag_end > 0 && rest[tag_end..].starts_with(": ") {
+            let tag = &rest[..tag_end];
+            match tag.split_once('[') {
+                Some((name, pid)) => {
+                    self.app_name = name.to_string();
+                    self.proc_id = pid.trim_end_matches(']').to_string();
+                }
+                None => self.app_name = tag.to_string(),
+            }
+            rest = &rest[tag_end + 2..];
+        }
+        self.message = rest.to_string();
+    }
+
+    pub fn facility_name(&self) -> &'static str {
+        FACILITIES[usize::from(self.facility)]
+    }
+
+    pub fn severity_name(&self) -> &'static str {
+        SEVERITIES[usize::from(self.severity)]
+    }
+
+    pub fn info(&self) -> String {
+        let mut info = format!("{}.{}: ", self.facility_name(), self.severity_name());
+        if !self.hostname.is_empty() {
+            info.push_str(&self.hostname);
+            info.push(' ');
+        }
+        if !self.app_name.is_empty() {
+            info.push_str(&self.app_name);
+            if !self.proc_id.is_empty() {
+                info.push_str(&format!("[{}]", self.proc_id));
+            }
+            info.push_str(": ");
+        }
+        info.push_str(&self.message);
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("syslog.facility", self.facility);
+        fields.insert("syslog.level", self.severity);
+        if let Some(version) = self.version {
+            fields.insert("syslog.version", version);
+        }
+        let parts = [
+            ("syslog.timestamp", &self.timestamp),
+            ("syslog.hostname", &self.hostname),
+            ("syslog.appname", &self.app_name),
+            ("syslog.procid", &self.proc_id),
+            ("syslog.msgid", &self.msg_id),
+            ("syslog.sd", &self.structured_data),
+            ("syslog.msg", &self.message),
+        ];
+        for (name, part) in parts {
+            if !part.is_empty() {
+                fields.insert(name, part.as_str());
+            }
+        }
+    }
+
+    /// Messages of severity error or worse.
+    pub fn is_error(&self) -> bool {
+        self.severity <= ERROR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bsd_message() {
+        let message =
+            SyslogMessage::parse(b"<34>Oct 11 22:14:15 mymachine su[230]: 'su root' failed\n")
+                .unwrap();
+        assert_eq!(message.facility_name(), "AUTH");
+        assert_eq!(message.severity_name(), "CRIT");
+        assert_eq!(message.hostname, "mymachine");
+        assert_eq!(message.proc_id, "230");
+        assert_eq!(
+            message.info(),
+            "AUTH.CRIT: mymachine su[230]: 'su root' failed"
+        );
+        assert!(message.is_error());
+
+        // Without a header, as some devices send
+        let message = SyslogMessage::parse(b"<13>link down on port 3").unwrap();
+        assert_eq!(message.info(), "USER.NOTICE: link down on port 3");
+    }
+
+    #[test]
+    fn test_rfc5424_message() {
+        let message = SyslogMessage::parse(
+            b"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 \
+              [exampleSDID@32473 iut=\"3\" eventSource=\"App\"] An application event",
+        )
+        .unwrap();
+        assert_eq!(message.version, Some(1));
+        assert_eq!(message.facility_name(), "LOCAL4");
+        assert_eq!(message.severity_name(), "NOTICE");
+        assert_eq!(message.msg_id, "ID47");
+        assert_eq!(message.proc_id, "");
+        assert_eq!(
+            message.structured_data,
+            "[exampleSDID@32473 iut=\"3\" eventSource=\"App\"]"
+        );
+        assert_eq!(message.message, "An application event");
+        assert!(!message.is_error());
+    }
+}
@@ -0,0 +1,453 @@
+use crate::expert::Severity;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+
+// Upper bounds on tracked state so a long capture can't grow without limit
+const MAX_CONVERSATIONS: usize = 65536;
+const MAX_UNACKED_SEGMENTS: usize = 1024;
+const MAX_GAPS: usize = 64;
+
+/// The fields of a TCP segment the tracker looks at.
+#[derive(Debug, Clone)]
+pub struct TcpSegment {
+    pub seq: u32,
+    pub ack: u32,
+    pub syn: bool,
+    pub ack_flag: bool,
+    pub fin: bool,
+    pub rst: bool,
+    pub window: u16,
+    /// Window scale option, only present on SYN segments.
+    pub window_scale: Option<u8>,
+    pub payload_len: usize,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl TcpSegment {
+    pub fn from_header(
+        tcp: &etherparse::TcpHeaderSlice,
+        payload_len: usize,
+        timestamp: DateTime<Utc>,
+    ) -> Self {
+        let window_scale = tcp.options_iterator().find_map(|opt| match opt {
+            Ok(etherparse::TcpOptionElement::WindowScale(shift)) => Some(shift),
+            _ => None,
+        });
+
+        Self {
+            seq: tcp.sequence_number(),
+            ack: tcp.acknowledgment_number(),
+            syn: tcp.syn(),
+            ack_flag: tcp.ack(),
+            fin: tcp.fin(),
+            rst: tcp.rst(),
+            window: tcp.window_size(),
+            window_scale,
+            payload_len,
+            timestamp,
+        }
+    }
+
+    /// Length of the segment in sequence space (SYN and FIN count as one byte).
+    fn seq_len(&self) -> u32 {
+        self.payload_len as u32 + u32::from(self.syn) + u32::from(self.fin)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpAnalysisFlag {
+    Retransmission,
+    FastRetransmission,
+    OutOfOrder,
+    LostSegment,
+    DuplicateAck,
+    ZeroWindow,
+    WindowFull,
+    KeepAlive,
+    Reset,
+}
+
+impl TcpAnalysisFlag {
+    pub fn field_name(&self) -> &'static str {
+        match self {
+            TcpAnalysisFlag::Retransmission => "tcp.analysis.retransmission",
+            TcpAnalysisFlag::FastRetransmission => "tcp.analysis.fast_retransmission",
+            TcpAnalysisFlag::OutOfOrder => "tcp.analysis.out_of_order",
+            TcpAnalysisFlag::LostSegment => "tcp.analysis.lost_segment",
+            TcpAnalysisFlag::DuplicateAck => "tcp.analysis.duplicate_ack",
+            TcpAnalysisFlag::ZeroWindow => "tcp.analysis.zero_window",
+            TcpAnalysisFlag::WindowFull => "tcp.analysis.window_full",
+            TcpAnalysisFlag::KeepAlive => "tcp.analysis.keep_alive",
+            TcpAnalysisFlag::Reset => "tcp.analysis.reset",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TcpAnalysisFlag::Retransmission => "TCP Retransmission",
+            TcpAnalysisFlag::FastRetransmission => "TCP Fast Retransmission",
+            TcpAnalysisFlag::OutOfOrder => "TCP Out-Of-Order",
+            TcpAnalysisFlag::LostSegment => "TCP Previous segment not captured",
+            TcpAnalysisFlag::DuplicateAck => "TCP Dup ACK",
+            TcpAnalysisFlag::ZeroWindow => "TCP ZeroWindow",
+            TcpAnalysisFlag::WindowFull => "TCP Window Full",
+            TcpAnalysisFlag::KeepAlive => "TCP Keep-Alive",
+            TcpAnalysisFlag::Reset => "TCP Reset",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            TcpAnalysisFlag::KeepAlive => Severity::Note,
+            TcpAnalysisFlag::DuplicateAck => Severity::Note,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+/// Result of tracking a single segment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TcpAnalysis {
+    pub flags: Vec<TcpAnalysisFlag>,
+    /// Number of duplicate ACKs seen so far, set on duplicate ACKs only.
+    pub duplicate_ack_num: Option<u32>,
+    /// Time between the acknowledged segment and this ACK, in seconds.
+    pub ack_rtt: Option<f64>,
+}
+
+impl TcpAnalysis {
+    pub fn has(&self, flag: TcpAnalysisFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+}
+
+struct UnackedSegment {
+    end_seq: u32,
+    sent: DateTime<Utc>,
+    retransmitted: bool,
+}
+
+#[derive(Default)]
+struct Direction {
+    seen: bool,
+    next_seq: u32,
+    ack_seen: bool,
+    last_ack: u32,
+    last_window: u32,
+    window_scale: Option<u8>,
+    dup_ack_count: u32,
+    /// Sequence ranges skipped by this sender, filled later by out-of-order segments.
+    gaps: Vec<(u32, u32)>,
+    unacked: VecDeque<UnackedSegment>,
+}
+
+#[derive(Default)]
+struct Conversation {
+    directions: [Direction; 2],
+}
+
+impl Conversation {
+    /// Returns (sender, receiver) state for the given direction index.
+    fn split(&mut self, index: usize) -> (&mut Direction, &mut Direction) {
+        let (a, b) = self.directions.split_at_mut(1);
+        if index == 0 {
+            (&mut a[0], &mut b[0])
+        } else {
+            (&mut b[0], &mut a[0])
+        }
+    }
+}
+
+type Endpoint = (IpAddr, u16);
+
+/// Tracks sequence state of TCP conversations to detect retransmissions,
+/// duplicate ACKs, window problems and to estimate round trip times.
+#[derive(Default)]
+pub struct TcpTracker {
+    conversations: HashMap<(Endpoint, Endpoint), Conversation>,
+}
+
+impl TcpTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn process(&mut self, src: Endpoint, dst: Endpoint, segment: &TcpSegment) -> TcpAnalysis {
+        if self.conversations.len() >= MAX_CONVERSATIONS {
+            self.conversations.clear();
+        }
+
+        let (key, index) = if src <= dst {
+            ((src, dst), 0)
+        } else {
+            ((dst, src), 1)
+        };
+        let conversation = self.conversations.entry(key).or_default();
+        let (fwd, rev) = conversation.split(index);
+
+        let mut analysis = TcpAnalysis::default();
+        let seq_len = segment.seq_len();
+        let end_seq = segment.seq.wrapping_add(seq_len);
+        let control = segment.syn || segment.fin || segment.rst;
+
+        if segment.syn {
+            fwd.window_scale = segment.window_scale;
+        }
+        let window = scaled_window(segment, fwd, rev);
+
+        if segment.rst {
+            analysis.flags.push(TcpAnalysisFlag::Reset);
+        }
+
+        if segment.window == 0 && !control {
+            analysis.flags.push(TcpAnalysisFlag::ZeroWindow);
+        }
+
+        let mut new_data = seq_len > 0;
+        if fwd.seen {
+            if segment.payload_len <= 1 && !control && segment.seq == fwd.next_seq.wrapping_sub(1) {
+                analysis.flags.push(TcpAnalysisFlag::KeepAlive);
+                new_data = false;
+            } else if seq_len > 0 && seq_lt(segment.seq, fwd.next_seq) {
+                let gap = fwd.gaps.iter().position(|&(start, end)| {
+                    seq_le(start, segment.seq) && seq_lt(segment.seq, end)
+                });
+                let fast = rev.dup_ack_count >= 2 && rev.last_ack == segment.seq;
+
+                if gap.is_some() && !fast {
+                    analysis.flags.push(TcpAnalysisFlag::OutOfOrder);
+                } else {
+                    if fast {
+                        analysis.flags.push(TcpAnalysisFlag::FastRetransmission);
+                    } else {
+                        analysis.flags.push(TcpAnalysisFlag::Retransmission);
+                    }
+                    // Karn's algorithm: retransmitted data can't be timed
+                    for pending in fwd.unacked.iter_mut() {
+                        if seq_le(pending.end_seq, end_seq) {
+                            pending.retransmitted = true;
+                        }
+                    }
+                    new_data = false;
+                }
+                if let Some(pos) = gap {
+                    fill_gap(&mut fwd.gaps, pos, segment.seq, end_seq);
+                }
+            } else if seq_lt(fwd.next_seq, segment.seq) && !segment.rst {
+                analysis.flags.push(TcpAnalysisFlag::LostSegment);
+                if fwd.gaps.len() < MAX_GAPS {
+                    fwd.gaps.push((fwd.next_seq, segment.seq));
+                }
+            }
+        }
+
+        if segment.payload_len > 0
+            && rev.ack_seen
+            && end_seq.wrapping_sub(u32::from(segment.fin))
+                == rev.last_ack.wrapping_add(rev.last_window)
+        {
+            analysis.flags.push(TcpAnalysisFlag::WindowFull);
+        }
+
+        if segment.ack_flag {
+            if seq_len == 0
+                && !control
+                && fwd.ack_seen
+                && segment.ack == fwd.last_ack
+                && window == fwd.last_window
+                && !analysis.has(TcpAnalysisFlag::KeepAlive)
+            {
+                fwd.dup_ack_count += 1;
+                analysis.flags.push(TcpAnalysisFlag::DuplicateAck);
+                analysis.duplicate_ack_num = Some(fwd.dup_ack_count);
+            } else if !fwd.ack_seen || segment.ack != fwd.last_ack {
+                fwd.dup_ack_count = 0;
+            }
+
+            while let Some(pending) = rev.unacked.front() {
+                if !seq_le(pending.end_seq, segment.ack) {
+                    break;
+                }
+                if pending.end_seq == segment.ack && !pending.retransmitted {
+                    let elapsed = segment.timestamp - pending.sent;
+                    analysis.ack_rtt = Some(elapsed.num_microseconds().unwrap_or(0) as f64 / 1e6);
+                }
+                rev.unacked.pop_front();
+            }
+
+            fwd.ack_seen = true;
+            fwd.last_ack = segment.ack;
+            fwd.last_window = window;
+        }
+
+        if seq_len > 0 && (!fwd.seen || seq_lt(fwd.next_seq, end_seq)) {
+            fwd.next_seq = end_seq;
+        } else if !fwd.seen {
+            fwd.next_seq = segment.seq;
+        }
+        if new_data && fwd.unacked.len() < MAX_UNACKED_SEGMENTS {
+            fwd.unacked.push_back(UnackedSegment {
+                end_seq,
+                sent: segment.timestamp,
+                retransmitted: false,
+            });
+        }
+        fwd.seen = true;
+
+        analysis
+    }
+}
+
+/// Window scaling only applies once both sides announced it on their SYN.
+fn scaled_window(segment: &TcpSegment, fwd: &Direction, rev: &Direction) -> u32 {
+    match (segment.syn, fwd.window_scale, rev.window_scale) {
+        (false, Some(shift), Some(_)) => u32::from(segment.window) << shift.min(14),
+        _ => u32::from(segment.window),
+    }
+}
+
+fn fill_gap(gaps: &mut Vec<(u32, u32)>, pos: usize, seq: u32, end_seq: u32) {
+    let (start, end) = gaps.remove(pos);
+    if seq_lt(start, seq) {
+        gaps.push((start, seq));
+    }
+    if seq_lt(end_seq, end) {
+        gaps.push((end_seq, end));
+    }
+}
+
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    a == b || seq_lt(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::net::Ipv4Addr;
+
+    const CLIENT: Endpoint = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 40000);
+    const SERVER: Endpoint = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 80);
+
+    fn segment(seq: u32, ack: u32, payload_len: usize, ms: i64) -> TcpSegment {
+        TcpSegment {
+            seq,
+            ack,
+            syn: false,
+            ack_flag: true,
+            fin: false,
+            rst: false,
+            window: 1000,
+            window_scale: None,
+            payload_len,
+            timestamp: DateTime::<Utc>::UNIX_EPOCH + Duration::milliseconds(ms),
+        }
+    }
+
+    fn handshake(tracker: &mut TcpTracker) {
+        let mut syn = segment(100, 0, 0, 0);
+        syn.syn = true;
+        syn.ack_flag = false;
+        tracker.process(CLIENT, SERVER, &syn);
+
+        let mut syn_ack = segment(500, 101, 0, 20);
+        syn_ack.syn = true;
+        let analysis = tracker.process(SERVER, CLIENT, &syn_ack);
+        assert_eq!(analysis.ack_rtt, Some(0.02));
+
+        tracker.process(CLIENT, SERVER, &segment(101, 501, 0, 21));
+    }
+
+    #[test]
+    fn test_retransmission() {
+        let mut tracker = TcpTracker::new();
+        handshake(&mut tracker);
+
+        let first = tracker.process(CLIENT, SERVER, &segment(101, 501, 100, 30));
+        assert!(first.flags.is_empty());
+
+        let again = tracker.process(CLIENT, SERVER, &segment(101, 501, 100, 330));
+        assert!(again.has(TcpAnalysisFlag::Retransmission));
+
+        // The ACK matches a retransmitted segment, so no RTT sample
+        let ack = tracker.process(SERVER, CLIENT, &segment(501, 201, 0, 340));
+        assert_eq!(ack.ack_rtt, None);
+    }
+
+    #[test]
+    fn test_duplicate_ack_and_fast_retransmission() {
+        let mut tracker = TcpTracker::new();
+        handshake(&mut tracker);
+
+        tracker.process(CLIENT, SERVER, &segment(101, 501, 100, 30));
+        let lost = tracker.process(CLIENT, SERVER, &segment(301, 501, 100, 31));
+        assert!(lost.has(TcpAnalysisFlag::LostSegment));
+
+        let ack = tracker.process(SERVER, CLIENT, &segment(501, 201, 0, 50));
+        assert_eq!(ack.ack_rtt, Some(0.02));
+        let dup1 = tracker.process(SERVER, CLIENT, &segment(501, 201, 0, 51));
+        let dup2 = tracker.process(SERVER, CLIENT, &segment(501, 201, 0, 52));
+        assert!(dup1.has(TcpAnalysisFlag::DuplicateAck));
+        assert_eq!(dup2.duplicate_ack_num, Some(2));
+
+        let fast = tracker.process(CLIENT, SERVER, &segment(201, 501, 100, 53));
+        assert!(fast.has(TcpAnalysisFlag::FastRetransmission));
+        assert!(!fast.has(TcpAnalysisFlag::OutOfOrder));
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut tracker = TcpTracker::new();
+        handshake(&mut tracker);
+
+        tracker.process(CLIENT, SERVER, &segment(201, 501, 100, 30));
+        let late = tracker.process(CLIENT, SERVER, &segment(101, 501, 100, 31));
+        assert!(late.has(TcpAnalysisFlag::OutOfOrder));
+        assert!(!late.has(TcpAnalysisFlag::Retransmission));
+    }
+
+    #[test]
+    fn test_zero_window_keep_alive_and_reset() {
+        let mut tracker = TcpTracker::new();
+        handshake(&mut tracker);
+
+        let mut zero = segment(501, 101, 0, 30);
+        zero.window = 0;
+        assert!(tracker
+            .process(SERVER, CLIENT, &zero)
+            .has(TcpAnalysisFlag::ZeroWindow));
+
+        let keep_alive = tracker.process(CLIENT, SERVER, &segment(100, 501, 0, 40));
+        assert!(keep_alive.has(TcpAnalysisFlag::KeepAlive));
+        assert!(!keep_alive.has(TcpAnalysisFlag::Retransmission));
+
+        let mut rst = segment(101, 501, 0, 50);
+        rst.rst = true;
+        assert!(tracker
+            .process(CLIENT, SERVER, &rst)
+            .has(TcpAnalysisFlag::Reset));
+    }
+
+    #[test]
+    fn test_window_full() {
+        let mut tracker = TcpTracker::new();
+        handshake(&mut tracker);
+
+        // Server advertised 1000 bytes at ack 101
+        tracker.process(SERVER, CLIENT, &segment(501, 101, 0, 25));
+        let full = tracker.process(CLIENT, SERVER, &segment(101, 501, 1000, 30));
+        assert!(full.has(TcpAnalysisFlag::WindowFull));
+    }
+
+    #[test]
+    fn test_sequence_wraparound() {
+        assert!(seq_lt(u32::MAX - 10, 5));
+        assert!(!seq_lt(5, u32::MAX - 10));
+        assert!(seq_le(7, 7));
+    }
+}
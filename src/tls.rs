@@ -0,0 +1,163 @@
+use crate::fields::Fields;
+
+pub const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+
+const EXT_SERVER_NAME: u16 = 0;
+const EXT_ALPN: u16 = 16;
+const SERVER_NAME_HOST: u8 = 0;
+
+/// The parts of a TLS ClientHello handshake message we report.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientHello {
+    pub server_name: Option<String>,
+    pub alpn: Vec<String>,
+}
+
+impl ClientHello {
+    /// Parses a handshake message, starting at the handshake type.
+    pub fn parse(handshake: &[u8]) -> Option<ClientHello> {
+        if *handshake.first()? != HANDSHAKE_CLIENT_HELLO {
+            return None;
+        }
+        let body = handshake.get(4..message_len(handshake)?)?;
+
+        // Legacy version and random, then the session ID, cipher suites and
+        // compression methods
+        let mut rest = body.get(34..)?;
+        rest = skip_vector(rest, 1)?;
+        rest = skip_vector(rest, 2)?;
+        rest = skip_vector(rest, 1)?;
+
+        let mut hello = ClientHello::default();
+        // ClientHellos without extensions are valid
+        let extensions = match vector(rest, 2) {
+            Some((extensions, _)) => extensions,
+            None => return Some(hello),
+        };
+        let mut extensions = extensions;
+        while extensions.len() >= 4 {
+            let ext_type = u16::from_be_bytes([extensions[0], extensions[1]]);
+            let (value, next) = vector(&extensions[2..], 2)?;
+            extensions = next;
+            match ext_type {
+                EXT_SERVER_NAME => hello.server_name = server_name(value),
+                EXT_ALPN => {
+                    let (mut protocols, _) = vector(value, 2)?;
+                    while let Some((protocol, next)) = vector(protocols, 1) {
+                        hello
+                            .alpn
+                            .push(String::from_utf8_lossy(protocol).into_owned());
+                        protocols = next;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(hello)
+    }
+
+    pub fn info(&self) -> String {
+        let mut info = "Client Hello".to_string();
+        if let Some(name) = &self.server_name {
+            info.push_str(&format!(" (SNI={})", name));
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("tls.handshake.type", HANDSHAKE_CLIENT_HELLO);
+        if let Some(name) = &self.server_name {
+            fields.insert("tls.handshake.extensions_server_name", name.as_str());
+        }
+        for protocol in &self.alpn {
+            fields.insert("tls.handshake.extensions_alpn_str", protocol.as_str());
+        }
+    }
+}
+
+/// Length of a handshake message including its 4 byte header.
+pub fn message_len(handshake: &[u8]) -> Option<usize> {
+    let header = handshake.get(..4)?;
+    Some(4 + (usize::from(header[1]) << 16 | usize::from(header[2]) << 8 | usize::from(header[3])))
+}
+
+/// Splits off a vector with a `len_size` byte length prefix.
+fn vector(data: &[u8], len_size: usize) -> Option<(&[u8], &[u8])> {
+    let len = data
+        .get(..len_size)?
+        .iter()
+        .fold(0usize, |len, b| len << 8 | usize::from(*b));
+    let value = data.get(len_size..len_size + len)?;
+    Some((value, &data[len_size + len..]))
+}
+
+fn skip_vector(data: &[u8], len_size: usize) -> Option<&[u8]> {
+    vector(data, len_size).map(|(_, rest)| rest)
+}
+
+/// The host name in a server_name extension (RFC 6066).
+fn server_name(value: &[u8]) -> Option<String> {
+    let (mut names, _) = vector(value, 2)?;
+    while let Some((&name_type, rest)) = names.split_first() {
+        let (name, next) = vector(rest, 2)?;
+        if name_type == SERVER_NAME_HOST {
+            return Some(String::from_utf8_lossy(name).into_owned());
+        }
+        names = next;
+    }
+    None
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn with_len(len_size: usize, value: &[u8]) -> Vec<u8> {
+        let mut bytes = value.len().to_be_bytes()[8 - len_size..].to_vec();
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    /// A ClientHello with the given SNI and ALPN protocols.
+    pub(crate) fn client_hello(server_name: &str, alpn: &[&str]) -> Vec<u8> {
+        let mut sni = vec![SERVER_NAME_HOST];
+        sni.extend(with_len(2, server_name.as_bytes()));
+        let protocols: Vec<u8> = alpn
+            .iter()
+            .flat_map(|p| with_len(1, p.as_bytes()))
+            .collect();
+
+        let mut extensions = EXT_SERVER_NAME.to_be_bytes().to_vec();
+        extensions.extend(with_len(2, &with_len(2, &sni)));
+        extensions.extend_from_slice(&EXT_ALPN.to_be_bytes());
+        extensions.extend(with_len(2, &with_len(2, &protocols)));
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0; 32]);
+        body.extend(with_len(1, &[]));
+        body.extend(with_len(2, &[0x13, 0x01]));
+        body.extend(with_len(1, &[0]));
+        body.extend(with_len(2, &extensions));
+
+        let mut handshake = vec![HANDSHAKE_CLIENT_HELLO];
+        handshake.extend(with_len(3, &body));
+        handshake
+    }
+
+    #[test]
+    fn test_client_hello_extensions() {
+        let handshake = client_hello("example.com", &["h3", "h3-29"]);
+        assert_eq!(message_len(&handshake), Some(handshake.len()));
+
+        let hello = ClientHello::parse(&handshake).unwrap();
+        assert_eq!(hello.server_name.as_deref(), Some("example.com"));
+        assert_eq!(hello.alpn, vec!["h3", "h3-29"]);
+        assert_eq!(hello.info(), "Client Hello (SNI=example.com)");
+    }
+
+    #[test]
+    fn test_truncated_client_hello() {
+        let handshake = client_hello("example.com", &["h3"]);
+        assert!(ClientHello::parse(&handshake[..handshake.len() - 1]).is_none());
+    }
+}
@@ -0,0 +1,414 @@
+use crate::fields::Fields;
+use crate::link::ether_type;
+
+pub const VXLAN_PORT: u16 = 4789;
+pub const GENEVE_PORT: u16 = 6081;
+pub const WIREGUARD_PORT: u16 = 51820;
+
+const IP_PROTO_IPIP: u8 = 4;
+const IP_PROTO_IPV6: u8 = 41;
+const IP_PROTO_GRE: u8 = 47;
+
+/// Packet carried by a tunnel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inner<'a> {
+    Ethernet(&'a [u8]),
+    Ip(&'a [u8]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireGuardMessage {
+    HandshakeInitiation { sender: u32 },
+    HandshakeResponse { sender: u32, receiver: u32 },
+    CookieReply { receiver: u32 },
+    TransportData { receiver: u32, counter: u64 },
+}
+
+impl WireGuardMessage {
+    pub fn message_type(&self) -> u8 {
+        match self {
+            WireGuardMessage::HandshakeInitiation { .. } => 1,
+            WireGuardMessage::HandshakeResponse { .. } => 2,
+            WireGuardMessage::CookieReply { .. } => 3,
+            WireGuardMessage::TransportData { .. } => 4,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WireGuardMessage::HandshakeInitiation { .. } => "Handshake Initiation",
+            WireGuardMessage::HandshakeResponse { .. } => "Handshake Response",
+            WireGuardMessage::CookieReply { .. } => "Cookie Reply",
+            WireGuardMessage::TransportData { .. } => "Transport Data",
+        }
+    }
+}
+
+/// Header of an encapsulation protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tunnel {
+    Vxlan {
+        vni: u32,
+    },
+    Geneve {
+        vni: u32,
+        protocol: u16,
+        options_len: usize,
+        oam: bool,
+        critical: bool,
+    },
+    Gre {
+        protocol: u16,
+        key: Option<u32>,
+        sequence: Option<u32>,
+    },
+    /// IPv4 or IPv6 directly inside IPv4 or IPv6 (IP protocol 4 or 41).
+    IpInIp {
+        protocol: u8,
+    },
+    WireGuard(WireGuardMessage),
+}
+
+impl Tunnel {
+    pub fn protocol_name(&self) -> &'static str {
+        match self {
+            Tunnel::Vxlan { .. } => "VXLAN",
+            Tunnel::Geneve { .. } => "GENEVE",
+            Tunnel::Gre { .. } => "GRE",
+            Tunnel::IpInIp { .. } => "IP-in-IP",
+            Tunnel::WireGuard(_) => "WireGuard",
+        }
+    }
+
+    /// Info column text, used when the inner packet cannot be decoded.
+    pub fn info(&self) -> String {
+        match self {
+            Tunnel::Vxlan { vni } | Tunnel::Geneve { vni, .. } => format!("VNI {}", vni),
+            Tunnel::Gre { protocol, key, .. } => match key {
+                Some(key) => format!("Protocol 0x{:04x}, Key {}", protocol, key),
+                None => format!("Protocol 0x{:04x}", protocol),
+            },
+            Tunnel::IpInIp { protocol } => format!("IP protocol {}", protocol),
+            Tunnel::WireGuard(message) => match message {
+                WireGuardMessage::HandshakeInitiation { sender } => {
+                    format!("{}, sender=0x{:08x}", message.name(), sender)
+                }
+                WireGuardMessage::HandshakeResponse { sender, receiver } => format!(
+                    "{}, sender=0x{:08x}, receiver=0x{:08x}",
+                    message.name(),
+                    sender,
+                    receiver
+                ),
+                WireGuardMessage::CookieReply { receiver } => {
+                    format!("{}, receiver=0x{:08x}", message.name(), receiver)
+                }
+                WireGuardMessage::TransportData { receiver, counter } => format!(
+                    "{}, receiver=0x{:08x}, counter={}",
+                    message.name(),
+                    receiver,
+                    counter
+                ),
+            },
+        }
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        match *self {
+            Tunnel::Vxlan { vni } => fields.insert("vxlan.vni", vni),
+            Tunnel::Geneve {
+                vni,
+                protocol,
+                options_len,
+                oam,
+                critical,
+            } => {
+                fields.insert("geneve.vni", vni);
+                fields.insert("geneve.proto_type", protocol);
+                fields.insert("geneve.options_len", options_len);
+                fields.insert("geneve.flags.oam", oam);
+                fields.insert("geneve.flags.critical", critical);
+            }
+            Tunnel::Gre {
+                protocol,
+                key,
+                sequence,
+            } => {
+                fields.insert("gre.proto", protocol);
+                if let Some(key) = key {
+                    fields.insert("gre.key", key);
+                }
+                if let Some(sequence) = sequence {
+                    fields.insert("gre.sequence_number", sequence);
+                }
+            }
+            Tunnel::IpInIp { protocol } => fields.insert("ipip.proto", protocol),
+            Tunnel::WireGuard(message) => {
+                fields.insert("wg.type", message.message_type());
+                match message {
+                    WireGuardMessage::HandshakeInitiation { sender } => {
+                        fields.insert("wg.sender", sender);
+                    }
+                    WireGuardMessage::HandshakeResponse { sender, receiver } => {
+                        fields.insert("wg.sender", sender);
+                        fields.insert("wg.receiver", receiver);
+                    }
+                    WireGuardMessage::CookieReply { receiver } => {
+                        fields.insert("wg.receiver", receiver);
+                    }
+                    WireGuardMessage::TransportData { receiver, counter } => {
+                        fields.insert("wg.receiver", receiver);
+                        fields.insert("wg.counter", counter);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A decoded tunnel header and the packet it carries, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decapsulated<'a> {
+    pub tunnel: Tunnel,
+    pub inner: Option<Inner<'a>>,
+}
+
+/// Decodes a UDP payload sent to one of the well-known tunnel ports.
+pub fn from_udp(src_port: u16, dst_port: u16, payload: &[u8]) -> Option<Decapsulated<'_>> {
+    match dst_port {
+        VXLAN_PORT => return decode_vxlan(payload),
+        GENEVE_PORT => return decode_geneve(payload),
+        _ => {}
+    }
+    if src_port == WIREGUARD_PORT || dst_port == WIREGUARD_PORT {
+        return decode_wireguard(payload);
+    }
+    None
+}
+
+/// Decodes the payload of an IP packet carrying GRE or IP-in-IP.
+pub fn from_ip_protocol(protocol: u8, payload: &[u8]) -> Option<Decapsulated<'_>> {
+    match protocol {
+        IP_PROTO_GRE => decode_gre(payload),
+        IP_PROTO_IPIP | IP_PROTO_IPV6 => Some(Decapsulated {
+            tunnel: Tunnel::IpInIp { protocol },
+            inner: Some(Inner::Ip(payload)),
+        }),
+        _ => None,
+    }
+}
+
+/// Maps an EtherType naming the payload of GRE or GENEVE to the inner packet.
+fn inner_by_ether_type(protocol: u16, payload: &[u8]) -> Option<Inner<'_>> {
+    match protocol {
+        ether_type::TEB => Some(Inner::Ethernet(payload)),
+        ether_type::IPV4 | ether_type::IPV6 => Some(Inner::Ip(payload)),
+        _ => None,
+    }
+}
+
+fn decode_vxlan(payload: &[u8]) -> Option<Decapsulated<'_>> {
+    let header = payload.get(..8)?;
+    // The I flag marks a valid VNI
+    if header[0] & 0x08 == 0 {
+        return None;
+    }
+    Some(Decapsulated {
+        tunnel: Tunnel::Vxlan {
+            vni: u32::from_be_bytes([0, header[4], header[5], header[6]]),
+        },
+        inner: Some(Inner::Ethernet(&payload[8..])),
+    })
+}
+
+fn decode_geneve(payload: &[u8]) -> Option<Decapsulated<'_>> {
+    let header = payload.get(..8)?;
+    if header[0] >> 6 != 0 {
+        return None;
+    }
+    let options_len = usize::from(header[0] & 0x3f) * 4;
+    let protocol = u16::from_be_bytes([header[2], header[3]]);
+    let inner = payload.get(8 + options_len..)?;
+    Some(Decapsulated {
+        tunnel: Tunnel::Geneve {
+            vni: u32::from_be_bytes([0, header[4], header[5], header[6]]),
+            protocol,
+            options_len,
+            oam: header[1] & 0x80 != 0,
+            critical: header[1] & 0x40 != 0,
+        },
+        inner: inner_by_ether_type(protocol, inner),
+    })
+}
+
+fn decode_gre(payload: &[u8]) -> Option<Decapsulated<'_>> {
+    let header = payload.get(..4)?;
+    let flags = u16::from_be_bytes([header[0], header[1]]);
+    // Only version 0; version 1 is the PPTP variant
+    if flags & 0x0007 != 0 {
+        return None;
+    }
+    let protocol = u16::from_be_bytes([header[2], header[3]]);
+
+    let read_u32 = |offset: usize| {
+        payload
+            .get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let mut offset = 4;
+    if flags & 0x8000 != 0 {
+        // Checksum and reserved field
+        offset += 4;
+    }
+    let key = if flags & 0x2000 != 0 {
+        offset += 4;
+        Some(read_u32(offset - 4)?)
+    } else {
+        None
+    };
+    let sequence = if flags & 0x1000 != 0 {
+        offset += 4;
+        Some(read_u32(offset - 4)?)
+    } else {
+        None
+    };
+
+    Some(Decapsulated {
+        tunnel: Tunnel::Gre {
+            protocol,
+            key,
+            sequence,
+        },
+        inner: inner_by_ether_type(protocol, payload.get(offset..)?),
+    })
+}
+
+fn decode_wireguard(payload: &[u8]) -> Option<Decapsulated<'_>> {
+    // Message type followed by three reserved zero bytes
+    let header = payload.get(..4)?;
+    if header[1..] != [0, 0, 0] {
+        return None;
+    }
+    let read_u32 = |offset: usize| {
+        u32::from_le_bytes([
+            payload[offset],
+            payload[offset + 1],
+            payload[offset + 2],
+            payload[offset + 3],
+        ])
+    };
+
+    let message = match (header[0], payload.len()) {
+        (1, 148) => WireGuardMessage::HandshakeInitiation {
+            sender: read_u32(4),
+        },
+        (2, 92) => WireGuardMessage::HandshakeResponse {
+            sender: read_u32(4),
+            receiver: read_u32(8),
+        },
+        (3, 64) => WireGuardMessage::CookieReply {
+            receiver: read_u32(4),
+        },
+        // Counter, then the encrypted packet padded to 16 bytes plus a tag
+        (4, len) if len >= 32 && len % 16 == 0 => WireGuardMessage::TransportData {
+            receiver: read_u32(4),
+            counter: u64::from(read_u32(8)) | u64::from(read_u32(12)) << 32,
+        },
+        _ => return None,
+    };
+
+    Some(Decapsulated {
+        tunnel: Tunnel::WireGuard(message),
+        inner: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vxlan_header() {
+        let mut payload = vec![0x08, 0, 0, 0, 0x00, 0x13, 0x88, 0];
+        payload.extend_from_slice(&[0xff; 14]);
+
+        let decap = from_udp(50000, VXLAN_PORT, &payload).unwrap();
+        assert_eq!(decap.tunnel, Tunnel::Vxlan { vni: 5000 });
+        assert_eq!(decap.inner, Some(Inner::Ethernet(&payload[8..])));
+
+        // Without the I flag the packet is not VXLAN
+        payload[0] = 0;
+        assert!(from_udp(50000, VXLAN_PORT, &payload).is_none());
+    }
+
+    #[test]
+    fn test_geneve_skips_options() {
+        let mut payload = vec![0x01, 0x40, 0x08, 0x00, 0x00, 0x00, 0x2a, 0];
+        payload.extend_from_slice(&[0xaa; 4]); // one 4 byte option
+        payload.push(0x45);
+
+        let decap = from_udp(50000, GENEVE_PORT, &payload).unwrap();
+        match decap.tunnel {
+            Tunnel::Geneve {
+                vni,
+                options_len,
+                critical,
+                ..
+            } => {
+                assert_eq!(vni, 42);
+                assert_eq!(options_len, 4);
+                assert!(critical);
+            }
+            other => panic!("unexpected tunnel {:?}", other),
+        }
+        assert_eq!(decap.inner, Some(Inner::Ip(&[0x45][..])));
+    }
+
+    #[test]
+    fn test_gre_with_key_and_sequence() {
+        let mut payload = vec![0x30, 0x00, 0x65, 0x58];
+        payload.extend_from_slice(&7u32.to_be_bytes());
+        payload.extend_from_slice(&9u32.to_be_bytes());
+        payload.extend_from_slice(&[0xff; 14]);
+
+        let decap = from_ip_protocol(IP_PROTO_GRE, &payload).unwrap();
+        assert_eq!(
+            decap.tunnel,
+            Tunnel::Gre {
+                protocol: ether_type::TEB,
+                key: Some(7),
+                sequence: Some(9),
+            }
+        );
+        assert_eq!(decap.inner, Some(Inner::Ethernet(&payload[12..])));
+    }
+
+    #[test]
+    fn test_wireguard_messages() {
+        let mut initiation = vec![1, 0, 0, 0];
+        initiation.extend_from_slice(&0x1234u32.to_le_bytes());
+        initiation.resize(148, 0);
+        let decap = from_udp(WIREGUARD_PORT, 40000, &initiation).unwrap();
+        assert_eq!(
+            decap.tunnel,
+            Tunnel::WireGuard(WireGuardMessage::HandshakeInitiation { sender: 0x1234 })
+        );
+        assert!(decap.inner.is_none());
+
+        let mut data = vec![4, 0, 0, 0];
+        data.extend_from_slice(&5u32.to_le_bytes());
+        data.extend_from_slice(&300u64.to_le_bytes());
+        data.resize(48, 0);
+        let decap = from_udp(40000, WIREGUARD_PORT, &data).unwrap();
+        assert_eq!(
+            decap.tunnel,
+            Tunnel::WireGuard(WireGuardMessage::TransportData {
+                receiver: 5,
+                counter: 300
+            })
+        );
+
+        // A handshake initiation has a fixed size
+        initiation.push(0);
+        assert!(from_udp(WIREGUARD_PORT, 40000, &initiation).is_none());
+    }
+}
@@ -1,27 +1,78 @@
+use crate::dhcp::{Lease, LeaseTable};
+use crate::expert::{self, ExpertInfo, Severity};
+use crate::export::{self, ExportedObject};
+use crate::fields::format_mac;
+use crate::fields::{FieldValue, Fields};
+use crate::filters::{self, Highlight, PacketFilter};
+use crate::sip::CallTable;
+use crate::wlan::BssTable;
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Position},
     style::{Color, Style},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table, Wrap},
     Terminal,
 };
 use std::error::Error;
 use std::io::stdout;
+use std::net::IpAddr;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
+// Packets tested against a new display filter between two screen updates
+const SCAN_CHUNK: usize = 20_000;
+const MAX_FILTER_HISTORY: usize = 100;
+
 pub struct App {
     terminal: Terminal<CrosstermBackend<std::io::Stdout>>,
     packets: Vec<PacketInfo>,
+    /// Index of the selected packet in `packets`.
     selected: Option<usize>,
+    /// First row of the packet list on screen.
+    offset: usize,
     packet_rx: mpsc::Receiver<PacketInfo>,
     running: Arc<AtomicBool>,
+    view: View,
+    leases: LeaseTable,
+    calls: CallTable,
+    networks: BssTable,
+    /// Files sent over HTTP and FTP so far, collected only with an export
+    /// directory.
+    objects: Vec<ExportedObject>,
+    export_dir: Option<PathBuf>,
+    /// Outcome of the last action, shown in the packet list title.
+    status: Option<String>,
+    /// The packets passing the applied display filter.
+    packet_view: PacketView,
+    /// The text of the applied display filter.
+    filter_text: String,
+    filter_input: FilterInput,
+    /// Keys go to the filter input rather than the packet list.
+    editing_filter: bool,
+    /// The connection shown by the stream view.
+    stream: Option<FollowedStream>,
+}
+
+/// What the upper pane shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Packets,
+    /// DHCP leases seen in the capture.
+    Leases,
+    /// SIP calls with the quality of their RTP streams.
+    Calls,
+    /// Wireless networks by BSSID, with their stations and handshakes.
+    Networks,
+    /// The WebSocket messages of the followed connection.
+    Stream,
 }
 
 #[derive(Clone)]
@@ -33,6 +84,10 @@ pub struct PacketInfo {
     pub length: usize,
     pub info: String,
     pub raw_data: Vec<u8>,
+    pub fields: Fields,
+    pub expert: Vec<ExpertInfo>,
+    /// Files this packet completed, when object export is enabled.
+    pub objects: Vec<ExportedObject>,
 }
 
 impl App {
@@ -57,28 +112,80 @@ impl App {
             terminal,
             packets: Vec::new(),
             selected: None,
+            offset: 0,
             packet_rx,
             running,
+            view: View::Packets,
+            leases: LeaseTable::new(),
+            calls: CallTable::new(),
+            networks: BssTable::new(),
+            objects: Vec::new(),
+            export_dir: None,
+            status: None,
+            packet_view: PacketView::default(),
+            filter_text: String::new(),
+            filter_input: FilterInput::default(),
+            editing_filter: false,
+            stream: None,
         })
     }
 
+    /// Lets `e` export the objects of the capture to `dir`.
+    pub fn with_export_dir(mut self, dir: PathBuf) -> Self {
+        self.export_dir = Some(dir);
+        self
+    }
+
     pub async fn run(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
         while self.running.load(Ordering::SeqCst) {
             // Check for new packets
-            while let Ok(packet) = self.packet_rx.try_recv() {
+            while let Ok(mut packet) = self.packet_rx.try_recv() {
+                for lease in Lease::from_fields(&packet.fields, packet.timestamp) {
+                    self.leases.update(lease);
+                }
+                self.calls.update(&packet.fields, packet.timestamp);
+                self.networks.update(&packet.fields);
+                self.objects.append(&mut packet.objects);
                 self.packets.push(packet);
             }
+            self.packet_view.scan(&self.packets);
+            self.packet_view
+                .update_selection(&mut self.selected, self.packets.len());
+            if let Some(stream) = &mut self.stream {
+                stream.scan(&self.packets);
+            }
 
             // Draw UI
             self.draw()?;
 
-            // Handle input with timeout
-            if event::poll(std::time::Duration::from_millis(100))? {
+            // Handle input with timeout, unless filtering isn't done yet
+            let timeout = if self.packet_view.is_complete(self.packets.len()) {
+                std::time::Duration::from_millis(100)
+            } else {
+                std::time::Duration::ZERO
+            };
+            if event::poll(timeout)? {
                 if let Event::Key(key) = event::read()? {
+                    if key.code == KeyCode::Char('r')
+                        && key.modifiers.contains(KeyModifiers::CONTROL)
+                    {
+                        self.reset_filter();
+                        continue;
+                    }
+                    if self.editing_filter {
+                        self.edit_filter(key);
+                        continue;
+                    }
                     match key.code {
                         KeyCode::Char('q') => break,
+                        KeyCode::Char('/') | KeyCode::Char('f') => self.editing_filter = true,
                         KeyCode::Up => self.select_previous(),
                         KeyCode::Down => self.select_next(),
+                        KeyCode::Char('l') => self.toggle_view(View::Leases),
+                        KeyCode::Char('v') => self.toggle_view(View::Calls),
+                        KeyCode::Char('w') => self.toggle_view(View::Networks),
+                        KeyCode::Char('e') => self.export_objects(),
+                        KeyCode::Char('t') => self.follow_stream(),
                         _ => {}
                     }
                 }
@@ -101,26 +208,56 @@ impl App {
         self.terminal.draw(|frame| {
             let size = frame.area();
 
+            // The filter bar shows while a filter is typed or applied
+            let show_filter = self.editing_filter || self.packet_view.filter.is_some();
+            let outer = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(if show_filter {
+                    [Constraint::Min(0), Constraint::Length(3)]
+                } else {
+                    [Constraint::Min(0), Constraint::Length(0)]
+                })
+                .split(size);
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
-                .split(size);
+                .split(outer[0]);
 
-            // Packet list
-            let items: Vec<ListItem> = self
-                .packets
-                .iter()
-                .enumerate()
-                .map(|(i, p)| {
+            // Packet list, only building the rows on screen
+            let total = self.packets.len();
+            let rows = self.packet_view.len(total);
+            let height = usize::from(chunks[0].height.saturating_sub(2)).max(1);
+            if let Some(selected) = self.selected {
+                let row = self.packet_view.position(selected);
+                if row < self.offset {
+                    self.offset = row;
+                } else if row >= self.offset + height {
+                    self.offset = row + 1 - height;
+                }
+            }
+            self.offset = self.offset.min(rows.saturating_sub(1));
+            let items: Vec<ListItem> = (self.offset..rows.min(self.offset + height))
+                .filter_map(|row| self.packet_view.packet(row, total))
+                .map(|i| {
+                    let p = &self.packets[i];
                     let style = if Some(i) == self.selected {
                         Style::default().fg(Color::Yellow)
                     } else {
-                        Style::default()
+                        row_style(p)
+                    };
+
+                    // Mark packets that failed checksum validation
+                    let marker = if p.fields.contains("bad_checksum") {
+                        "⚠ "
+                    } else {
+                        ""
                     };
 
                     ListItem::new(format!(
-                        "{} {} {} -> {} [{}] {}",
+                        "{}{} {}{} {} -> {} [{}] {}",
+                        marker,
                         p.timestamp.format("%H:%M:%S%.3f"),
+                        encapsulation_column(p),
                         p.protocol,
                         p.source,
                         p.destination,
@@ -131,31 +268,66 @@ impl App {
                 })
                 .collect();
 
+            let mut notes = Vec::new();
+            if self.packet_view.filter.is_some() {
+                notes.push(format!("{} of {} shown", rows, total));
+                if !self.packet_view.is_complete(total) {
+                    notes.push(format!(
+                        "filtering {}%",
+                        self.packet_view.scanned * 100 / total.max(1)
+                    ));
+                }
+            }
+            notes.extend(self.status.clone());
+            let title = if notes.is_empty() {
+                "Network Packets".to_string()
+            } else {
+                format!("Network Packets ({})", notes.join(", "))
+            };
             let list = List::new(items)
-                .block(
-                    Block::default()
-                        .title("Network Packets")
-                        .borders(Borders::ALL),
-                )
+                .block(Block::default().title(title).borders(Borders::ALL))
                 .highlight_style(Style::default().fg(Color::Yellow));
 
-            frame.render_widget(list, chunks[0]);
+            match self.view {
+                View::Packets => frame.render_widget(list, chunks[0]),
+                View::Leases => frame.render_widget(lease_table(&self.leases), chunks[0]),
+                View::Calls => {
+                    let now = self
+                        .packets
+                        .last()
+                        .map_or_else(chrono::Utc::now, |p| p.timestamp);
+                    frame.render_widget(call_table(&self.calls, now), chunks[0]);
+                }
+                View::Networks => frame.render_widget(network_table(&self.networks), chunks[0]),
+                View::Stream => {
+                    if let Some(stream) = &self.stream {
+                        frame.render_widget(stream_list(stream, height), chunks[0]);
+                    }
+                }
+            }
 
             // Packet details
             if let Some(selected) = self.selected {
                 if let Some(packet) = self.packets.get(selected) {
-                    let details = vec![
+                    let mut details = vec![
                         format!("Timestamp: {}", packet.timestamp),
                         format!("Protocol: {}", packet.protocol),
                         format!("Source: {}", packet.source),
                         format!("Destination: {}", packet.destination),
                         format!("Length: {} bytes", packet.length),
                         format!("Info: {}", packet.info),
-                        String::new(),
-                        "Raw Data (hex):".to_string(),
-                        format_hex_dump(&packet.raw_data),
-                    ]
-                    .join("\n");
+                    ];
+                    for item in &packet.expert {
+                        details.push(format!("Expert ({:?}): {}", item.severity, item.message));
+                    }
+                    if !packet.fields.is_empty() {
+                        details.push(String::new());
+                        details.extend(protocol_tree(packet));
+                    }
+                    details.push(String::new());
+                    details.push("Raw Data (hex):".to_string());
+                    details.push(format_hex_dump(&packet.raw_data));
+                    let details = details.join("\n");
 
                     let details_widget = Paragraph::new(details)
                         .block(
@@ -163,30 +335,461 @@ impl App {
                                 .title("Packet Details")
                                 .borders(Borders::ALL),
                         )
-                        .wrap(Wrap { trim: true });
+                        .wrap(Wrap { trim: false });
 
                     frame.render_widget(details_widget, chunks[1]);
                 }
             }
+
+            if show_filter {
+                let text = if self.editing_filter {
+                    &self.filter_input.text
+                } else {
+                    &self.filter_text
+                };
+                frame.render_widget(filter_bar(text, self.editing_filter), outer[1]);
+                if self.editing_filter {
+                    let area = outer[1];
+                    let x = area.x + 1 + self.filter_input.cursor as u16;
+                    frame.set_cursor_position(Position::new(
+                        x.min(area.right().saturating_sub(2)),
+                        area.y + 1,
+                    ));
+                }
+            }
         })?;
 
         Ok(())
     }
 
     fn select_next(&mut self) {
+        let total = self.packets.len();
         self.selected = match self.selected {
-            Some(i) if i < self.packets.len() - 1 => Some(i + 1),
-            None if !self.packets.is_empty() => Some(0),
-            _ => self.selected,
+            // A selected packet the filter hides is followed by the row at
+            // its position
+            Some(i) => {
+                let row = self.packet_view.position(i);
+                let next = if self.packet_view.packet(row, total) == Some(i) {
+                    row + 1
+                } else {
+                    row
+                };
+                self.packet_view.packet(next, total).or(Some(i))
+            }
+            None => self.packet_view.packet(0, total),
+        };
+    }
+
+    /// Switches the upper pane to `view`, or back to the packet list.
+    fn toggle_view(&mut self, view: View) {
+        self.view = if self.view == view {
+            View::Packets
+        } else {
+            view
         };
     }
 
+    /// Shows the WebSocket messages of the selected packet's connection, or
+    /// goes back to the packet list.
+    fn follow_stream(&mut self) {
+        if self.view == View::Stream {
+            self.view = View::Packets;
+            return;
+        }
+        let connection = self
+            .selected
+            .and_then(|i| self.packets.get(i))
+            .and_then(connection);
+        match connection {
+            Some(connection) => {
+                let mut stream = FollowedStream::new(connection);
+                stream.scan(&self.packets);
+                self.stream = Some(stream);
+                self.view = View::Stream;
+            }
+            None => self.status = Some("select a TCP packet to follow its stream".to_string()),
+        }
+    }
+
+    /// Writes the objects seen so far to the export directory.
+    fn export_objects(&mut self) {
+        let Some(export_dir) = &self.export_dir else {
+            self.status = Some("start with --export-dir to export objects".to_string());
+            return;
+        };
+        let dir = export_dir.display();
+        self.status = Some(match export::write_objects(export_dir, &self.objects) {
+            Ok(()) => format!("exported {} objects to {}", self.objects.len(), dir),
+            Err(e) => format!("export to {} failed: {}", dir, e),
+        });
+    }
+
     fn select_previous(&mut self) {
+        let total = self.packets.len();
         self.selected = match self.selected {
-            Some(i) if i > 0 => Some(i - 1),
-            _ => self.selected,
+            Some(i) => match self.packet_view.position(i) {
+                0 => Some(i),
+                row => self.packet_view.packet(row - 1, total),
+            },
+            None => None,
         };
     }
+
+    /// Handles a key typed into the filter input.
+    fn edit_filter(&mut self, key: KeyEvent) {
+        let input = &mut self.filter_input;
+        match key.code {
+            KeyCode::Enter => {
+                // Invalid filters stay in the input, their error shown
+                let Ok(filter) = PacketFilter::parse(&input.text) else {
+                    return;
+                };
+                let text = input.text.trim().to_string();
+                input.remember(&text);
+                let filter = (!text.is_empty()).then_some(filter);
+                self.packet_view.set_filter(filter, self.selected);
+                self.filter_text = text;
+                self.offset = 0;
+                self.editing_filter = false;
+            }
+            KeyCode::Esc => {
+                input.set_text(&self.filter_text);
+                self.editing_filter = false;
+            }
+            KeyCode::Up => input.previous(),
+            KeyCode::Down => input.next(),
+            KeyCode::Left => input.cursor = input.cursor.saturating_sub(1),
+            KeyCode::Right => input.cursor = (input.cursor + 1).min(input.text.chars().count()),
+            KeyCode::Home => input.cursor = 0,
+            KeyCode::End => input.cursor = input.text.chars().count(),
+            KeyCode::Backspace => input.backspace(),
+            KeyCode::Delete => input.delete(),
+            KeyCode::Char(c) => input.insert(c),
+            _ => {}
+        }
+    }
+
+    /// Shows every packet again.
+    fn reset_filter(&mut self) {
+        self.packet_view.set_filter(None, self.selected);
+        self.filter_text.clear();
+        self.filter_input.set_text("");
+        self.editing_filter = false;
+    }
+}
+
+/// The packets passing a display filter. Packets are tested a chunk at a
+/// time as the interface updates, so filtering a large capture doesn't
+/// stall it.
+#[derive(Default)]
+struct PacketView {
+    filter: Option<PacketFilter>,
+    /// Indices of the matching packets among those scanned.
+    rows: Vec<usize>,
+    scanned: usize,
+    /// The packet selected when the filter changed, until the scan shows
+    /// where the selection moves.
+    reselect: Option<usize>,
+}
+
+impl PacketView {
+    /// Starts over with `filter`, or shows every packet without one. The
+    /// selection moves from `selected` to the first packet shown at or
+    /// after it.
+    fn set_filter(&mut self, filter: Option<PacketFilter>, selected: Option<usize>) {
+        self.filter = filter;
+        self.rows.clear();
+        self.scanned = 0;
+        self.reselect = selected;
+    }
+
+    /// Tests the next chunk of packets not tested yet.
+    fn scan(&mut self, packets: &[PacketInfo]) {
+        let Some(filter) = &self.filter else {
+            return;
+        };
+        let end = packets.len().min(self.scanned + SCAN_CHUNK);
+        for (i, packet) in packets.iter().enumerate().take(end).skip(self.scanned) {
+            if filter.matches(packet) {
+                self.rows.push(i);
+            }
+        }
+        self.scanned = end;
+    }
+
+    /// Moves `selected` once the scan has found the packet it goes to, or
+    /// tested every packet without finding one.
+    fn update_selection(&mut self, selected: &mut Option<usize>, total: usize) {
+        let Some(index) = self.reselect else {
+            return;
+        };
+        let next = self.packet(self.position(index), total);
+        if next.is_some() || self.is_complete(total) {
+            *selected = next;
+            self.reselect = None;
+        }
+    }
+
+    fn is_complete(&self, total: usize) -> bool {
+        self.filter.is_none() || self.scanned == total
+    }
+
+    /// The number of rows shown out of `total` packets.
+    fn len(&self, total: usize) -> usize {
+        match self.filter {
+            Some(_) => self.rows.len(),
+            None => total,
+        }
+    }
+
+    /// The index of the packet shown in `row`.
+    fn packet(&self, row: usize, total: usize) -> Option<usize> {
+        match self.filter {
+            Some(_) => self.rows.get(row).copied(),
+            None => (row < total).then_some(row),
+        }
+    }
+
+    /// The row of packet `index`, or of the first shown packet after it.
+    fn position(&self, index: usize) -> usize {
+        match self.filter {
+            Some(_) => self.rows.partition_point(|&row| row < index),
+            None => index,
+        }
+    }
+}
+
+type Endpoint = (IpAddr, u16);
+
+/// The endpoints of the TCP connection a packet belongs to, lowest first.
+/// Tunnels add their outer headers' fields first, so the last ones belong
+/// to the packet carrying the TCP segment.
+fn connection(packet: &PacketInfo) -> Option<(Endpoint, Endpoint)> {
+    let ip = |name| match packet.fields.get_all(name).last()? {
+        FieldValue::Ip(ip) => Some(*ip),
+        _ => None,
+    };
+    let port = |name| match packet.fields.get_all(name).last()? {
+        FieldValue::UInt(port) => u16::try_from(*port).ok(),
+        _ => None,
+    };
+    let src = (ip("ip.src")?, port("tcp.srcport")?);
+    let dst = (ip("ip.dst")?, port("tcp.dstport")?);
+    Some(if src <= dst { (src, dst) } else { (dst, src) })
+}
+
+/// The WebSocket messages of one connection, in the order they were sent.
+/// Like the packet view, it picks up packets as they are captured.
+#[derive(Debug)]
+struct FollowedStream {
+    connection: (Endpoint, Endpoint),
+    /// Set by the upgrade request.
+    client: Option<Endpoint>,
+    /// Text messages, and the sizes of binary ones, with whether the
+    /// client sent them.
+    messages: Vec<(bool, String)>,
+    scanned: usize,
+}
+
+impl FollowedStream {
+    fn new(connection: (Endpoint, Endpoint)) -> Self {
+        FollowedStream {
+            connection,
+            client: None,
+            messages: Vec::new(),
+            scanned: 0,
+        }
+    }
+
+    fn scan(&mut self, packets: &[PacketInfo]) {
+        for packet in &packets[self.scanned..] {
+            if connection(packet) != Some(self.connection) {
+                continue;
+            }
+            let source = match (
+                packet.fields.get("ip.src"),
+                packet.fields.get("tcp.srcport"),
+            ) {
+                (Some(FieldValue::Ip(ip)), Some(FieldValue::UInt(port))) => (*ip, *port as u16),
+                _ => continue,
+            };
+            if packet.fields.contains("http.upgrade") {
+                self.client = Some(source);
+            }
+            // Without the upgrade request, clients are told by their masks
+            let from_client = match self.client {
+                Some(client) => client == source,
+                None => packet.fields.get("websocket.mask") == Some(&FieldValue::Bool(true)),
+            };
+            for text in packet.fields.get_all("websocket.payload.text") {
+                self.messages.push((from_client, text.to_string()));
+            }
+            for binary in packet.fields.get_all("websocket.payload.binary") {
+                if let FieldValue::Bytes(data) = binary {
+                    self.messages
+                        .push((from_client, format!("<{} bytes binary>", data.len())));
+                }
+            }
+        }
+        self.scanned = packets.len();
+    }
+}
+
+/// The latest messages of a followed stream that fit in `height` rows,
+/// client messages in red and server messages in blue as in Wireshark.
+fn stream_list(stream: &FollowedStream, height: usize) -> List<'static> {
+    let ((a, a_port), (b, b_port)) = stream.connection;
+    let skip = stream.messages.len().saturating_sub(height);
+    let items: Vec<ListItem> = stream.messages[skip..]
+        .iter()
+        .map(|(from_client, text)| {
+            let (arrow, color) = if *from_client {
+                ("→", Color::Red)
+            } else {
+                ("←", Color::Blue)
+            };
+            ListItem::new(format!("{} {}", arrow, text)).style(Style::default().fg(color))
+        })
+        .collect();
+    let title = format!(
+        "WebSocket Stream {}:{} ⇄ {}:{} ({} messages)",
+        a,
+        a_port,
+        b,
+        b_port,
+        stream.messages.len()
+    );
+    List::new(items).block(Block::default().title(title).borders(Borders::ALL))
+}
+
+/// The display filter being typed, with the filters applied before.
+#[derive(Debug, Default)]
+struct FilterInput {
+    text: String,
+    /// Cursor position in characters.
+    cursor: usize,
+    history: Vec<String>,
+    /// The history entry shown while browsing, and the text typed before.
+    browsing: Option<(usize, String)>,
+}
+
+impl FilterInput {
+    fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.chars().count();
+        self.browsing = None;
+    }
+
+    /// Byte offset of the cursor in `text`.
+    fn byte_offset(&self) -> usize {
+        self.text
+            .char_indices()
+            .nth(self.cursor)
+            .map_or(self.text.len(), |(offset, _)| offset)
+    }
+
+    fn insert(&mut self, c: char) {
+        let offset = self.byte_offset();
+        self.text.insert(offset, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let offset = self.byte_offset();
+            self.text.remove(offset);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.text.chars().count() {
+            let offset = self.byte_offset();
+            self.text.remove(offset);
+        }
+    }
+
+    /// Adds an applied filter to the history, unless it was the last one.
+    fn remember(&mut self, text: &str) {
+        self.browsing = None;
+        if text.is_empty() || self.history.last().is_some_and(|last| last == text) {
+            return;
+        }
+        if self.history.len() >= MAX_FILTER_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push(text.to_string());
+    }
+
+    /// Shows the filter applied before the one shown.
+    fn previous(&mut self) {
+        let entry = match &self.browsing {
+            Some((0, _)) => return,
+            Some((entry, _)) => entry - 1,
+            None if self.history.is_empty() => return,
+            None => self.history.len() - 1,
+        };
+        let typed = match self.browsing.take() {
+            Some((_, typed)) => typed,
+            None => self.text.clone(),
+        };
+        self.set_text(&self.history[entry].clone());
+        self.browsing = Some((entry, typed));
+    }
+
+    /// Shows the filter applied after the one shown, and finally the text
+    /// typed before browsing.
+    fn next(&mut self) {
+        let Some((entry, typed)) = self.browsing.take() else {
+            return;
+        };
+        if entry + 1 < self.history.len() {
+            self.set_text(&self.history[entry + 1].clone());
+            self.browsing = Some((entry + 1, typed));
+        } else {
+            self.set_text(&typed);
+        }
+    }
+}
+
+/// The filter input line, highlighted and framed after whether it parses.
+fn filter_bar(text: &str, editing: bool) -> Paragraph<'static> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut spans = Vec::new();
+    let mut end = 0;
+    for (start, len, highlight) in filters::highlight(text) {
+        spans.push(Span::raw(chars[end..start].iter().collect::<String>()));
+        let color = match highlight {
+            Highlight::Field => Color::Cyan,
+            Highlight::Operator => Color::Yellow,
+            Highlight::Keyword => Color::Magenta,
+            Highlight::Value => Color::Green,
+            Highlight::Punctuation => Color::White,
+            Highlight::Invalid => Color::Red,
+        };
+        let part: String = chars[start..start + len].iter().collect();
+        spans.push(Span::styled(part, Style::default().fg(color)));
+        end = start + len;
+    }
+    spans.push(Span::raw(chars[end..].iter().collect::<String>()));
+
+    let (title, color) = match PacketFilter::parse(text) {
+        Err(error) => (format!("Display Filter: {}", error), Color::Red),
+        Ok(_) if editing => (
+            "Display Filter (Enter to apply, Esc to cancel)".to_string(),
+            Color::Green,
+        ),
+        Ok(_) => (
+            "Display Filter (/ to edit, Ctrl+R to reset)".to_string(),
+            Color::Green,
+        ),
+    };
+    Paragraph::new(Line::from(spans)).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(color)),
+    )
 }
 
 impl Drop for App {
@@ -196,6 +799,225 @@ impl Drop for App {
     }
 }
 
+/// Fields grouped by protocol layer, outermost first. Tunneled packets list
+/// the outer and inner layers separately, e.g. `ip`, `udp`, `vxlan`, `eth`,
+/// `ip`, `tcp`.
+fn protocol_tree(packet: &PacketInfo) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = None;
+    for (name, value) in packet.fields.iter() {
+        let layer = name.split('.').next().unwrap_or(name);
+        if current != Some(layer) {
+            lines.push(format!("▾ {}", layer));
+            current = Some(layer);
+        }
+        lines.push(format!("    {}: {}", name, value));
+    }
+    lines
+}
+
+/// VLAN IDs and MPLS labels of a packet, e.g. `[VLAN 100.20] `, or nothing
+/// for untagged packets.
+fn encapsulation_column(packet: &PacketInfo) -> String {
+    let join = |name: &'static str, sep: &str| {
+        packet
+            .fields
+            .get_all(name)
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(sep)
+    };
+
+    let mut parts = Vec::new();
+    let vlans = join("vlan.id", ".");
+    if !vlans.is_empty() {
+        parts.push(format!("VLAN {}", vlans));
+    }
+    let labels = join("mpls.label", "/");
+    if !labels.is_empty() {
+        parts.push(format!("MPLS {}", labels));
+    }
+
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("[{}] ", parts.join(" "))
+    }
+}
+
+fn lease_table(leases: &LeaseTable) -> Table<'static> {
+    let header = Row::new(vec![
+        "Client", "Address", "Server", "Hostname", "Lease", "Granted",
+    ])
+    .style(Style::default().fg(Color::Yellow));
+    let rows: Vec<Row> = leases
+        .iter()
+        .map(|lease| {
+            Row::new(vec![
+                lease.client.clone(),
+                lease.address.to_string(),
+                lease.server.to_string(),
+                lease.hostname.clone().unwrap_or_default(),
+                lease
+                    .lease_time
+                    .map(|time| format!("{}s", time))
+                    .unwrap_or_default(),
+                lease.granted.format("%H:%M:%S").to_string(),
+            ])
+        })
+        .collect();
+
+    Table::new(
+        rows,
+        [
+            Constraint::Length(20),
+            Constraint::Length(26),
+            Constraint::Length(26),
+            Constraint::Min(12),
+            Constraint::Length(8),
+            Constraint::Length(9),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title(format!(
+                "DHCP Leases ({}) - press l to go back",
+                leases.len()
+            ))
+            .borders(Borders::ALL),
+    )
+}
+
+fn call_table(calls: &CallTable, now: chrono::DateTime<chrono::Utc>) -> Table<'static> {
+    let header = Row::new(vec![
+        "Start", "From", "To", "State", "Duration", "Streams", "Jitter", "Lost", "MOS",
+    ])
+    .style(Style::default().fg(Color::Yellow));
+    let rows: Vec<Row> = calls
+        .iter()
+        .map(|call| {
+            let row = Row::new(vec![
+                call.started.format("%H:%M:%S").to_string(),
+                call.from.clone(),
+                call.to.clone(),
+                call.state.clone(),
+                call.duration(now)
+                    .map(|duration| format!("{}s", duration.num_seconds()))
+                    .unwrap_or_default(),
+                call.streams.len().to_string(),
+                call.max_jitter_ms()
+                    .map(|jitter| format!("{:.1} ms", jitter))
+                    .unwrap_or_default(),
+                call.lost_percent()
+                    .map(|lost| format!("{:.1}%", lost))
+                    .unwrap_or_default(),
+                call.mos()
+                    .map(|mos| format!("{:.2}", mos))
+                    .unwrap_or_default(),
+            ]);
+            // Below 3.6 most users are dissatisfied
+            match call.mos() {
+                Some(mos) if mos < 3.1 => row.style(Style::default().fg(Color::Red)),
+                Some(mos) if mos < 3.6 => row.style(Style::default().fg(Color::Yellow)),
+                _ => row,
+            }
+        })
+        .collect();
+
+    Table::new(
+        rows,
+        [
+            Constraint::Length(9),
+            Constraint::Min(16),
+            Constraint::Min(16),
+            Constraint::Length(10),
+            Constraint::Length(9),
+            Constraint::Length(8),
+            Constraint::Length(10),
+            Constraint::Length(7),
+            Constraint::Length(5),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title(format!("SIP Calls ({}) - press v to go back", calls.len()))
+            .borders(Borders::ALL),
+    )
+}
+
+fn network_table(networks: &BssTable) -> Table<'static> {
+    let header = Row::new(vec![
+        "BSSID",
+        "SSID",
+        "Channel",
+        "Signal",
+        "Stations",
+        "Frames",
+        "Handshakes",
+    ])
+    .style(Style::default().fg(Color::Yellow));
+    let rows: Vec<Row> = networks
+        .iter()
+        .map(|bss| {
+            Row::new(vec![
+                format_mac(&bss.bssid),
+                bss.ssid.clone(),
+                bss.channel
+                    .map(|channel| channel.to_string())
+                    .unwrap_or_default(),
+                bss.signal
+                    .map(|signal| format!("{} dBm", signal))
+                    .unwrap_or_default(),
+                bss.stations.len().to_string(),
+                bss.frames.to_string(),
+                if bss.handshakes() == 0 {
+                    String::new()
+                } else {
+                    format!(
+                        "{}/{} complete",
+                        bss.complete_handshakes(),
+                        bss.handshakes()
+                    )
+                },
+            ])
+        })
+        .collect();
+
+    Table::new(
+        rows,
+        [
+            Constraint::Length(18),
+            Constraint::Min(16),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(9),
+            Constraint::Length(8),
+            Constraint::Length(14),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title(format!(
+                "Wireless Networks ({}) - press w to go back",
+                networks.len()
+            ))
+            .borders(Borders::ALL),
+    )
+}
+
+/// Colors a packet row after the most severe expert item it carries.
+fn row_style(packet: &PacketInfo) -> Style {
+    match expert::max_severity(&packet.expert) {
+        Some(Severity::Error) => Style::default().fg(Color::White).bg(Color::Red),
+        Some(Severity::Warning) => Style::default().fg(Color::Red),
+        Some(Severity::Note) => Style::default().fg(Color::Cyan),
+        _ => Style::default(),
+    }
+}
+
 fn format_hex_dump(data: &[u8]) -> String {
     let mut output = String::new();
     for (i, chunk) in data.chunks(16).enumerate() {
@@ -228,3 +1050,164 @@ fn format_hex_dump(data: &[u8]) -> String {
     }
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(protocol: &str) -> PacketInfo {
+        PacketInfo {
+            raw_data: Vec::new(),
+            protocol: protocol.to_string(),
+            source: String::new(),
+            destination: String::new(),
+            info: String::new(),
+            length: 0,
+            timestamp: chrono::Utc::now(),
+            fields: Fields::new(),
+            expert: Vec::new(),
+            objects: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_packet_view_scans_in_chunks() {
+        let packets: Vec<PacketInfo> = (0..SCAN_CHUNK + 10)
+            .map(|i| packet(if i % 2 == 0 { "DNS" } else { "TCP" }))
+            .collect();
+        let mut view = PacketView::default();
+        assert_eq!(view.len(packets.len()), packets.len());
+
+        view.set_filter(Some(PacketFilter::parse("dns").unwrap()), None);
+        view.scan(&packets);
+        assert!(!view.is_complete(packets.len()));
+        view.scan(&packets);
+        assert!(view.is_complete(packets.len()));
+        assert_eq!(view.len(packets.len()), packets.len() / 2);
+        assert_eq!(view.packet(1, packets.len()), Some(2));
+        assert_eq!(view.position(2), 1);
+        // A hidden packet sits at the row of the next one shown
+        assert_eq!(view.position(3), 2);
+
+        view.set_filter(None, None);
+        assert_eq!(view.packet(3, packets.len()), Some(3));
+    }
+
+    #[test]
+    fn test_selection_moves_to_shown_packet() {
+        let mut packets: Vec<PacketInfo> = (0..SCAN_CHUNK + 10).map(|_| packet("TCP")).collect();
+        packets[SCAN_CHUNK + 5] = packet("DNS");
+        let total = packets.len();
+        let mut view = PacketView::default();
+
+        // The selected TCP packet is hidden; the next DNS packet is beyond
+        // the first chunk
+        let mut selected = Some(3);
+        view.set_filter(Some(PacketFilter::parse("dns").unwrap()), selected);
+        view.scan(&packets);
+        view.update_selection(&mut selected, total);
+        assert_eq!(selected, Some(3));
+        view.scan(&packets);
+        view.update_selection(&mut selected, total);
+        assert_eq!(selected, Some(SCAN_CHUNK + 5));
+
+        // Nothing shown after the selection
+        view.set_filter(Some(PacketFilter::parse("dns").unwrap()), Some(total - 1));
+        view.scan(&packets);
+        view.scan(&packets);
+        view.update_selection(&mut selected, total);
+        assert_eq!(selected, None);
+
+        let mut selected = Some(7);
+        view.set_filter(None, selected);
+        view.update_selection(&mut selected, total);
+        assert_eq!(selected, Some(7));
+    }
+
+    fn websocket(src: [u8; 4], sport: u16, dst: [u8; 4], dport: u16) -> PacketInfo {
+        let mut packet = packet("WebSocket");
+        packet.fields.insert("ip.src", IpAddr::from(src));
+        packet.fields.insert("ip.dst", IpAddr::from(dst));
+        packet.fields.insert("tcp.srcport", sport);
+        packet.fields.insert("tcp.dstport", dport);
+        packet
+    }
+
+    #[test]
+    fn test_followed_stream_lists_messages() {
+        let client = [10, 0, 0, 1];
+        let server = [10, 0, 0, 2];
+        let mut upgrade = websocket(client, 40000, server, 80);
+        upgrade.fields.insert("http.upgrade", "websocket");
+        let mut hello = websocket(client, 40000, server, 80);
+        hello.fields.insert("websocket.payload.text", "hello");
+        let mut other = websocket(client, 40001, server, 80);
+        other.fields.insert("websocket.payload.text", "elsewhere");
+        let mut reply = websocket(server, 80, client, 40000);
+        reply
+            .fields
+            .insert("websocket.payload.binary", vec![1, 2, 3]);
+        let mut packets = vec![upgrade, hello, other];
+
+        let mut stream = FollowedStream::new(connection(&packets[1]).unwrap());
+        stream.scan(&packets);
+        packets.push(reply);
+        stream.scan(&packets);
+
+        assert_eq!(
+            stream.messages,
+            vec![
+                (true, "hello".to_string()),
+                (false, "<3 bytes binary>".to_string())
+            ]
+        );
+        assert!(connection(&packet("ARP")).is_none());
+    }
+
+    #[test]
+    fn test_tunneled_connection() {
+        let mut tunneled = packet("WebSocket");
+        tunneled
+            .fields
+            .insert("ip.src", IpAddr::from([192, 168, 0, 1]));
+        tunneled
+            .fields
+            .insert("ip.dst", IpAddr::from([192, 168, 0, 2]));
+        let inner = websocket([10, 0, 0, 1], 40000, [10, 0, 0, 2], 80);
+        for (name, value) in inner.fields.iter() {
+            tunneled.fields.insert(name, value.clone());
+        }
+
+        assert_eq!(connection(&tunneled), connection(&inner));
+    }
+
+    #[test]
+    fn test_filter_input_editing_and_history() {
+        let mut input = FilterInput::default();
+        for c in "dn".chars() {
+            input.insert(c);
+        }
+        input.cursor = 0;
+        input.insert('é');
+        input.delete();
+        assert_eq!(input.text, "én");
+        input.backspace();
+        assert_eq!(input.text, "n");
+
+        input.remember("tcp");
+        input.remember("dns");
+        input.remember("dns");
+        assert_eq!(input.history, vec!["tcp", "dns"]);
+
+        input.set_text("ud");
+        input.previous();
+        assert_eq!(input.text, "dns");
+        input.previous();
+        input.previous();
+        assert_eq!(input.text, "tcp");
+        input.next();
+        input.next();
+        assert_eq!(input.text, "ud");
+        assert_eq!(input.cursor, 2);
+    }
+}
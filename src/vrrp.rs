@@ -0,0 +1,139 @@
+use crate::fields::Fields;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub const IP_PROTOCOL: u8 = 112;
+
+const HEADER_LEN: usize = 8;
+const ADVERTISEMENT: u8 = 1;
+// A master giving up the virtual router advertises priority zero
+const RELEASE_PRIORITY: u8 = 0;
+
+fn auth_type_name(auth_type: u8) -> &'static str {
+    match auth_type {
+        0 => "No Authentication",
+        1 => "Simple Text Password",
+        2 => "IP Authentication Header",
+        _ => "Unknown",
+    }
+}
+
+/// A VRRP advertisement from the master of a virtual router.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VrrpPacket {
+    pub version: u8,
+    pub packet_type: u8,
+    pub virtual_router_id: u8,
+    pub priority: u8,
+    /// VRRPv2 only.
+    pub auth_type: Option<u8>,
+    /// Seconds for VRRPv2, centiseconds for VRRPv3.
+    pub advertisement_interval: u16,
+    pub addresses: Vec<IpAddr>,
+}
+
+impl VrrpPacket {
+    /// Parses an advertisement carried over IPv6 when `ipv6` is set,
+    /// which only VRRPv3 supports.
+    pub fn parse(payload: &[u8], ipv6: bool) -> Option<Self> {
+        let header = payload.get(..HEADER_LEN)?;
+        let version = header[0] >> 4;
+        let count = usize::from(header[3]);
+        let (auth_type, advertisement_interval) = match version {
+            2 if !ipv6 => (Some(header[4]), u16::from(header[5])),
+            3 => (None, u16::from_be_bytes([header[4], header[5]]) & 0x0fff),
+            _ => return None,
+        };
+        let address_len = if ipv6 { 16 } else { 4 };
+        let addresses = payload
+            .get(HEADER_LEN..HEADER_LEN + count * address_len)?
+            .chunks_exact(address_len)
+            .map(|chunk| match <[u8; 16]>::try_from(chunk) {
+                Ok(bytes) => IpAddr::V6(Ipv6Addr::from(bytes)),
+                Err(_) => IpAddr::V4(Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3])),
+            })
+            .collect();
+        Some(VrrpPacket {
+            version,
+            packet_type: header[0] & 0x0f,
+            virtual_router_id: header[1],
+            priority: header[2],
+            auth_type,
+            advertisement_interval,
+            addresses,
+        })
+    }
+
+    pub fn info(&self) -> String {
+        let kind = if self.packet_type == ADVERTISEMENT {
+            "Announcement"
+        } else {
+            "Unknown"
+        };
+        let addresses: Vec<_> = self.addresses.iter().map(|a| a.to_string()).collect();
+        format!(
+            "{} (v{}), VRID {}, priority {}, {}",
+            kind,
+            self.version,
+            self.virtual_router_id,
+            self.priority,
+            addresses.join(" ")
+        )
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("vrrp.version", self.version);
+        fields.insert("vrrp.type", self.packet_type);
+        fields.insert("vrrp.virt_rtr_id", self.virtual_router_id);
+        fields.insert("vrrp.prio", self.priority);
+        fields.insert("vrrp.adver_int", self.advertisement_interval);
+        if let Some(auth_type) = self.auth_type {
+            fields.insert("vrrp.auth_type", auth_type_name(auth_type));
+        }
+        for address in &self.addresses {
+            fields.insert("vrrp.ip_addr", *address);
+        }
+    }
+
+    /// The master is leaving and a backup should take over right away.
+    pub fn releasing(&self) -> bool {
+        self.priority == RELEASE_PRIORITY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v2_advertisement() {
+        let mut data = vec![0x21, 1, 100, 1, 0, 1, 0, 0, 192, 168, 1, 254];
+        // Authentication data
+        data.extend_from_slice(&[0; 8]);
+        let vrrp = VrrpPacket::parse(&data, false).unwrap();
+        assert_eq!(
+            vrrp.info(),
+            "Announcement (v2), VRID 1, priority 100, 192.168.1.254"
+        );
+        assert!(!vrrp.releasing());
+        let mut fields = Fields::new();
+        vrrp.insert_fields(&mut fields);
+        assert_eq!(
+            fields.get("vrrp.auth_type"),
+            Some(&"No Authentication".into())
+        );
+    }
+
+    #[test]
+    fn test_v3_ipv6_release() {
+        let mut data = vec![0x31, 7, 0, 1, 0, 100, 0, 0];
+        data.extend_from_slice(&"fe80::1".parse::<Ipv6Addr>().unwrap().octets());
+        let vrrp = VrrpPacket::parse(&data, true).unwrap();
+        assert_eq!(vrrp.advertisement_interval, 100);
+        assert_eq!(
+            vrrp.info(),
+            "Announcement (v3), VRID 7, priority 0, fe80::1"
+        );
+        assert!(vrrp.releasing());
+        assert!(VrrpPacket::parse(&[0x21, 1, 100, 0, 0, 1, 0, 0], true).is_none());
+    }
+}
@@ -0,0 +1,635 @@
+use crate::fields::Fields;
+use crate::http1::{find, Head, StartLine, HEADER_END, SWITCHING_PROTOCOLS};
+use crate::reassembly::StreamData;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+pub const CONTINUATION: u8 = 0x0;
+pub const TEXT: u8 = 0x1;
+pub const BINARY: u8 = 0x2;
+pub const CLOSE: u8 = 0x8;
+pub const PING: u8 = 0x9;
+pub const PONG: u8 = 0xa;
+
+// The payload of the first data frame of a message compressed by an
+// extension such as permessage-deflate
+const RSV1: u8 = 0x4;
+// Characters of a text message shown in the info column
+const TEXT_PREVIEW: usize = 100;
+
+// Upper bounds on tracked state so a long capture can't grow without limit
+const MAX_CONNECTIONS: usize = 65536;
+const MAX_BUFFERED: usize = 1 << 20;
+
+pub fn opcode_name(opcode: u8) -> &'static str {
+    match opcode {
+        CONTINUATION => "Continuation",
+        TEXT => "Text",
+        BINARY => "Binary",
+        CLOSE => "Connection Close",
+        PING => "Ping",
+        PONG => "Pong",
+        _ => "Reserved",
+    }
+}
+
+pub fn close_code_name(code: u16) -> &'static str {
+    match code {
+        1000 => "Normal Closure",
+        1001 => "Going Away",
+        1002 => "Protocol Error",
+        1003 => "Unsupported Data",
+        1005 => "No Status Received",
+        1006 => "Abnormal Closure",
+        1007 => "Invalid Payload Data",
+        1008 => "Policy Violation",
+        1009 => "Message Too Big",
+        1010 => "Mandatory Extension",
+        1011 => "Internal Error",
+        1012 => "Service Restart",
+        1013 => "Try Again Later",
+        1014 => "Bad Gateway",
+        1015 => "TLS Handshake",
+        3000..=3999 => "Registered",
+        4000..=4999 => "Private Use",
+        _ => "Unknown",
+    }
+}
+
+/// The HTTP/1.1 request asking to switch a connection to WebSocket, or
+/// the server's answer to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Handshake {
+    /// Set for requests.
+    pub path: Option<String>,
+    pub status: Option<u16>,
+    pub reason: String,
+    /// The start line and header fields, unless the response is malformed.
+    pub head: Option<Head>,
+    pub key: Option<String>,
+    pub accept: Option<String>,
+    pub protocol: Option<String>,
+    pub extensions: Option<String>,
+}
+
+impl Handshake {
+    /// Parses a complete request, if it is a WebSocket upgrade.
+    pub fn parse_request(data: &[u8]) -> Option<Self> {
+        if !data.starts_with(b"GET ") {
+            return None;
+        }
+        let head = Head::parse(&data[..find(data, HEADER_END)?])?;
+        let upgrade = head.header("upgrade")?;
+        upgrade
+            .eq_ignore_ascii_case("websocket")
+            .then(|| Handshake::from_head(&head))
+    }
+
+    /// Parses the status line and headers of a response.
+    pub fn parse_response(head: &[u8]) -> Self {
+        Head::parse(head)
+            .map(|head| Handshake::from_head(&head))
+            .unwrap_or_default()
+    }
+
+    /// Keeps the start line and WebSocket headers.
+    fn from_head(head: &Head) -> Self {
+        let header = |name| head.header(name).map(str::to_string);
+        let (path, status, reason) = match &head.start_line {
+            StartLine::Request { target, .. } => (Some(target.clone()), None, String::new()),
+            StartLine::Response { status, reason } => (None, Some(*status), reason.clone()),
+        };
+        Handshake {
+            path,
+            status,
+            reason,
+            head: Some(head.clone()),
+            key: header("sec-websocket-key"),
+            accept: header("sec-websocket-accept"),
+            protocol: header("sec-websocket-protocol"),
+            extensions: header("sec-websocket-extensions"),
+        }
+    }
+
+    pub fn accepted(&self) -> bool {
+        self.status == Some(SWITCHING_PROTOCOLS)
+    }
+
+    pub fn info(&self) -> String {
+        let mut info = match (&self.path, self.status) {
+            (Some(path), _) => format!("Upgrade request GET {}", path),
+            (None, Some(SWITCHING_PROTOCOLS)) => "Switching Protocols (101)".to_string(),
+            (None, Some(status)) => format!("Upgrade refused: {} {}", status, self.reason),
+            (None, None) => "Malformed upgrade response".to_string(),
+        };
+        if let Some(protocol) = &self.protocol {
+            info.push_str(&format!(", protocol {}", protocol));
+        }
+        info
+    }
+
+    /// Adds the WebSocket headers; the rest of the head has fields of its
+    /// own.
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        let headers = [
+            ("http.sec_websocket_key", &self.key),
+            ("http.sec_websocket_accept", &self.accept),
+            ("http.sec_websocket_protocol", &self.protocol),
+            ("http.sec_websocket_extensions", &self.extensions),
+        ];
+        for (name, value) in headers {
+            if let Some(value) = value {
+                fields.insert(name, value.as_str());
+            }
+        }
+    }
+}
+
+/// A text or binary message, reassembled from its fragments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub opcode: u8,
+    pub data: Vec<u8>,
+    pub compressed: bool,
+}
+
+impl Message {
+    /// The text of a text message, if it isn't compressed.
+    pub fn text(&self) -> Option<String> {
+        (self.opcode == TEXT && !self.compressed)
+            .then(|| String::from_utf8_lossy(&self.data).into_owned())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketFrame {
+    pub fin: bool,
+    pub rsv: u8,
+    pub opcode: u8,
+    pub from_client: bool,
+    pub mask: Option<[u8; 4]>,
+    /// Unmasked.
+    pub payload: Vec<u8>,
+    /// The message this frame completes.
+    pub message: Option<Message>,
+}
+
+impl WebSocketFrame {
+    /// Parses the frame at the start of `data` and returns it with its
+    /// length, None while it's incomplete, or an error for a frame too long
+    /// to buffer.
+    fn parse(data: &[u8], from_client: bool) -> Result<Option<(Self, usize)>, ()> {
+        let [first, second, ..] = *data else {
+            return Ok(None);
+        };
+        let mut offset = 2;
+        let length = match second & 0x7f {
+            126 => {
+                let Some(bytes) = data.get(2..4) else {
+                    return Ok(None);
+                };
+                offset = 4;
+                u64::from(u16::from_be_bytes([bytes[0], bytes[1]]))
+            }
+            127 => {
+                let Some(bytes) = data.get(2..10) else {
+                    return Ok(None);
+                };
+                offset = 10;
+                u64::from_be_bytes(bytes.try_into().unwrap())
+            }
+            length => u64::from(length),
+        };
+        let length = match usize::try_from(length) {
+            Ok(length) if length <= MAX_BUFFERED => length,
+            _ => return Err(()),
+        };
+        let mask = if second & 0x80 != 0 {
+            let Some(key) = data.get(offset..offset + 4) else {
+                return Ok(None);
+            };
+            offset += 4;
+            Some(<[u8; 4]>::try_from(key).unwrap())
+        } else {
+            None
+        };
+        let Some(payload) = data.get(offset..offset + length) else {
+            return Ok(None);
+        };
+        let payload = match mask {
+            Some(key) => payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ key[i % 4])
+                .collect(),
+            None => payload.to_vec(),
+        };
+        let frame = WebSocketFrame {
+            fin: first & 0x80 != 0,
+            rsv: (first >> 4) & 0x07,
+            opcode: first & 0x0f,
+            from_client,
+            mask,
+            payload,
+            message: None,
+        };
+        Ok(Some((frame, offset + length)))
+    }
+
+    pub fn is_control(&self) -> bool {
+        self.opcode >= CLOSE
+    }
+
+    /// The status code and reason of a close frame.
+    pub fn close(&self) -> Option<(u16, String)> {
+        if self.opcode != CLOSE || self.payload.len() < 2 {
+            return None;
+        }
+        let code = u16::from_be_bytes([self.payload[0], self.payload[1]]);
+        Some((
+            code,
+            String::from_utf8_lossy(&self.payload[2..]).into_owned(),
+        ))
+    }
+
+    pub fn info(&self) -> String {
+        let mut flags = Vec::new();
+        if self.fin {
+            flags.push("FIN");
+        }
+        if self.mask.is_some() {
+            flags.push("MASKED");
+        }
+        let mut info = opcode_name(self.opcode).to_string();
+        if !flags.is_empty() {
+            info.push_str(&format!(" [{}]", flags.join(", ")));
+        }
+
+        if let Some((code, reason)) = self.close() {
+            info.push_str(&format!(": {} {}", code, close_code_name(code)));
+            if !reason.is_empty() {
+                info.push_str(&format!(", {}", reason));
+            }
+        } else if let Some(message) = &self.message {
+            match message.text() {
+                Some(text) => {
+                    let preview: String = text
+                        .chars()
+                        .take(TEXT_PREVIEW)
+                        .map(|c| if c.is_control() { ' ' } else { c })
+                        .collect();
+                    let ellipsis = if text.chars().count() > TEXT_PREVIEW {
+                        "…"
+                    } else {
+                        ""
+                    };
+                    info.push_str(&format!(": {}{}", preview, ellipsis));
+                }
+                None if message.compressed => {
+                    info.push_str(&format!(": {} bytes compressed", message.data.len()))
+                }
+                None => info.push_str(&format!(": {} bytes", message.data.len())),
+            }
+        } else if !self.is_control() {
+            info.push_str(" (fragment)");
+        }
+        info
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("websocket.fin", self.fin);
+        fields.insert("websocket.rsv", self.rsv);
+        fields.insert("websocket.opcode", self.opcode);
+        fields.insert("websocket.mask", self.mask.is_some());
+        if let Some(mask) = self.mask {
+            fields.insert("websocket.masking_key", mask.to_vec());
+        }
+        fields.insert("websocket.payload_length", self.payload.len());
+        if let Some((code, reason)) = self.close() {
+            fields.insert("websocket.payload.close.status_code", code);
+            if !reason.is_empty() {
+                fields.insert("websocket.payload.close.reason", reason);
+            }
+        }
+        if let Some(message) = &self.message {
+            match message.text() {
+                Some(text) => fields.insert("websocket.payload.text", text),
+                None => fields.insert("websocket.payload.binary", message.data.clone()),
+            }
+        }
+    }
+
+    /// Frames breaking the rules of RFC 6455, and connections closed for
+    /// a reason other than going away.
+    pub fn warning(&self) -> Option<String> {
+        if self.from_client && self.mask.is_none() {
+            return Some("WebSocket frame from client is not masked".to_string());
+        }
+        if !self.from_client && self.mask.is_some() {
+            return Some("WebSocket frame from server is masked".to_string());
+        }
+        if opcode_name(self.opcode) == "Reserved" {
+            return Some(format!(
+                "WebSocket frame with reserved opcode {}",
+                self.opcode
+            ));
+        }
+        if self.is_control() && !self.fin {
+            return Some("WebSocket control frame is fragmented".to_string());
+        }
+        match self.close() {
+            Some((code, _)) if !matches!(code, 1000 | 1001) => Some(format!(
+                "WebSocket closed with {} {}",
+                code,
+                close_code_name(code)
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketMessage {
+    Handshake(Handshake),
+    Frame(WebSocketFrame),
+}
+
+#[derive(Debug, Default)]
+struct Direction {
+    buffer: Vec<u8>,
+    /// Opcode, compression and data of a message still missing fragments.
+    fragment: Option<Message>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    client: (IpAddr, u16),
+    /// Set once the server switched protocols.
+    open: bool,
+    /// Client to server, then server to client.
+    directions: [Direction; 2],
+}
+
+type Endpoint = (IpAddr, u16);
+
+/// Follows HTTP/1.1 connections upgraded to WebSocket and splits their
+/// streams into frames.
+#[derive(Debug, Default)]
+pub struct WebSocketTracker {
+    connections: HashMap<(Endpoint, Endpoint), Connection>,
+}
+
+impl WebSocketTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds stream data sent from `src` to `dst` and returns the handshake
+    /// messages and frames it completes, or None if the connection isn't a
+    /// WebSocket one. Connections are recognized by the upgrade request.
+    pub fn process(
+        &mut self,
+        src: Endpoint,
+        dst: Endpoint,
+        stream: &StreamData,
+    ) -> Option<Vec<WebSocketMessage>> {
+        let key = if src <= dst { (src, dst) } else { (dst, src) };
+        if stream.gap {
+            // Frame boundaries are lost with the missing data
+            self.connections.remove(&key);
+        }
+        if !self.connections.contains_key(&key) {
+            let handshake = Handshake::parse_request(&stream.data)?;
+            if self.connections.len() >= MAX_CONNECTIONS {
+                self.connections.clear();
+            }
+            self.connections.insert(
+                key,
+                Connection {
+                    client: src,
+                    open: false,
+                    directions: Default::default(),
+                },
+            );
+            return Some(vec![WebSocketMessage::Handshake(handshake)]);
+        }
+
+        let connection = self.connections.get_mut(&key).expect("checked above");
+        let from_client = src == connection.client;
+        let direction = &mut connection.directions[usize::from(!from_client)];
+        if direction.buffer.len() + stream.data.len() > MAX_BUFFERED {
+            self.connections.remove(&key);
+            return Some(Vec::new());
+        }
+        direction.buffer.extend_from_slice(&stream.data);
+
+        let mut messages = Vec::new();
+        if !connection.open {
+            // Frames from the client wait for the server to switch
+            if from_client {
+                return Some(messages);
+            }
+            let Some(end) = find(&direction.buffer, HEADER_END) else {
+                return Some(messages);
+            };
+            let head: Vec<u8> = direction.buffer.drain(..end + HEADER_END.len()).collect();
+            let handshake = Handshake::parse_response(&head);
+            let accepted = handshake.accepted();
+            messages.push(WebSocketMessage::Handshake(handshake));
+            if !accepted {
+                self.connections.remove(&key);
+                return Some(messages);
+            }
+            connection.open = true;
+        }
+
+        let mut consumed = 0;
+        loop {
+            let mut frame = match WebSocketFrame::parse(&direction.buffer[consumed..], from_client)
+            {
+                Ok(Some((frame, length))) => {
+                    consumed += length;
+                    frame
+                }
+                Ok(None) => break,
+                Err(()) => {
+                    self.connections.remove(&key);
+                    return Some(messages);
+                }
+            };
+            direction.assemble(&mut frame);
+            messages.push(WebSocketMessage::Frame(frame));
+        }
+        direction.buffer.drain(..consumed);
+        Some(messages)
+    }
+}
+
+impl Direction {
+    /// Adds a data frame to the message it belongs to, and hands the
+    /// message to the frame completing it.
+    fn assemble(&mut self, frame: &mut WebSocketFrame) {
+        match frame.opcode {
+            TEXT | BINARY => {
+                self.fragment = Some(Message {
+                    opcode: frame.opcode,
+                    data: frame.payload.clone(),
+                    compressed: frame.rsv & RSV1 != 0,
+                });
+            }
+            CONTINUATION => {
+                let Some(fragment) = self.fragment.as_mut() else {
+                    return;
+                };
+                if fragment.data.len() + frame.payload.len() > MAX_BUFFERED {
+                    self.fragment = None;
+                    return;
+                }
+                fragment.data.extend_from_slice(&frame.payload);
+            }
+            _ => return,
+        }
+        if frame.fin {
+            frame.message = self.fragment.take();
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::reassembly::tests::{CLIENT, SERVER};
+
+    pub(crate) const UPGRADE: &str = "GET /chat HTTP/1.1\r\nHost: example.com\r\n\
+        Upgrade: websocket\r\nConnection: Upgrade\r\n\
+        Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+        Sec-WebSocket-Protocol: chat\r\nSec-WebSocket-Version: 13\r\n\r\n";
+    pub(crate) const SWITCH: &str = "HTTP/1.1 101 Switching Protocols\r\n\
+        Upgrade: websocket\r\nConnection: Upgrade\r\n\
+        Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\
+        Sec-WebSocket-Protocol: chat\r\n\r\n";
+
+    /// A frame, masked with a fixed key when `mask` is set.
+    pub(crate) fn frame(first: u8, payload: &[u8], mask: bool) -> Vec<u8> {
+        let mut data = vec![first];
+        let mask_bit = if mask { 0x80 } else { 0 };
+        match payload.len() {
+            length @ 0..=125 => data.push(mask_bit | length as u8),
+            length @ 126..=0xffff => {
+                data.push(mask_bit | 126);
+                data.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                data.push(mask_bit | 127);
+                data.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+        if mask {
+            let key = [0x37, 0xfa, 0x21, 0x3d];
+            data.extend_from_slice(&key);
+            data.extend(payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+        } else {
+            data.extend_from_slice(payload);
+        }
+        data
+    }
+
+    fn frames_of(messages: Vec<WebSocketMessage>) -> Vec<WebSocketFrame> {
+        messages
+            .into_iter()
+            .filter_map(|message| match message {
+                WebSocketMessage::Frame(frame) => Some(frame),
+                WebSocketMessage::Handshake(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_handshake_and_masked_text() {
+        let mut tracker = WebSocketTracker::new();
+        let request = tracker
+            .process(CLIENT, SERVER, &StreamData::complete(UPGRADE.as_bytes()))
+            .unwrap();
+        let WebSocketMessage::Handshake(request) = &request[0] else {
+            panic!("expected the upgrade request");
+        };
+        assert_eq!(request.info(), "Upgrade request GET /chat, protocol chat");
+
+        // The server's first frame arrives with the response
+        let mut response = SWITCH.as_bytes().to_vec();
+        response.extend(frame(0x81, b"welcome", false));
+        let messages = tracker
+            .process(SERVER, CLIENT, &StreamData::complete(&response))
+            .unwrap();
+        assert_eq!(messages.len(), 2);
+        let WebSocketMessage::Handshake(switch) = &messages[0] else {
+            panic!("expected the response");
+        };
+        assert_eq!(switch.info(), "Switching Protocols (101), protocol chat");
+
+        let data = frame(0x81, b"Hello", true);
+        let frames = frames_of(
+            tracker
+                .process(CLIENT, SERVER, &StreamData::complete(&data[..4]))
+                .unwrap(),
+        );
+        assert!(frames.is_empty());
+        let frames = frames_of(
+            tracker
+                .process(CLIENT, SERVER, &StreamData::complete(&data[4..]))
+                .unwrap(),
+        );
+        assert_eq!(frames[0].info(), "Text [FIN, MASKED]: Hello");
+        assert!(frames[0].warning().is_none());
+
+        // Requests without an upgrade aren't WebSocket connections
+        let plain = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let other = (CLIENT.0, 40001);
+        assert!(tracker
+            .process(other, SERVER, &StreamData::complete(plain))
+            .is_none());
+    }
+
+    #[test]
+    fn test_fragments_and_close() {
+        let mut tracker = WebSocketTracker::new();
+        tracker.process(CLIENT, SERVER, &StreamData::complete(UPGRADE.as_bytes()));
+        tracker.process(SERVER, CLIENT, &StreamData::complete(SWITCH.as_bytes()));
+
+        let mut data = frame(0x01, b"Hel", false);
+        data.extend(frame(0x89, b"", false));
+        data.extend(frame(0x80, b"lo!", false));
+        data.extend(frame(0x88, b"\x03\xf3overloaded", false));
+        let frames = frames_of(
+            tracker
+                .process(SERVER, CLIENT, &StreamData::complete(&data))
+                .unwrap(),
+        );
+        let infos: Vec<_> = frames.iter().map(|frame| frame.info()).collect();
+        assert_eq!(
+            infos,
+            [
+                "Text (fragment)",
+                "Ping [FIN]",
+                "Continuation [FIN]: Hello!",
+                "Connection Close [FIN]: 1011 Internal Error, overloaded",
+            ]
+        );
+        assert_eq!(
+            frames[3].warning().unwrap(),
+            "WebSocket closed with 1011 Internal Error"
+        );
+
+        let unmasked = frame(0x82, &[1, 2, 3], false);
+        let frames = frames_of(
+            tracker
+                .process(CLIENT, SERVER, &StreamData::complete(&unmasked))
+                .unwrap(),
+        );
+        assert_eq!(frames[0].info(), "Binary [FIN]: 3 bytes");
+        assert_eq!(
+            frames[0].warning().unwrap(),
+            "WebSocket frame from client is not masked"
+        );
+    }
+}
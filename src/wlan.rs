@@ -0,0 +1,919 @@
+use crate::fields::{FieldValue, Fields};
+use std::collections::HashMap;
+
+const RADIOTAP_HEADER_LEN: usize = 8;
+// Another presence bitmap follows
+const RADIOTAP_EXT: u32 = 1 << 31;
+// Alignment and size of the radiotap fields up to the timestamp, in the
+// order of their presence bits
+const RADIOTAP_FIELDS: [(usize, usize); 23] = [
+    (8, 8),
+    (1, 1),
+    (1, 1),
+    (2, 4),
+    (1, 2),
+    (1, 1),
+    (1, 1),
+    (2, 2),
+    (2, 2),
+    (2, 2),
+    (1, 1),
+    (1, 1),
+    (1, 1),
+    (1, 1),
+    (2, 2),
+    (2, 2),
+    (1, 1),
+    (1, 1),
+    (4, 8),
+    (1, 3),
+    (4, 8),
+    (2, 12),
+    (8, 12),
+];
+const TSFT: usize = 0;
+const FLAGS: usize = 1;
+const RATE: usize = 2;
+const CHANNEL: usize = 3;
+const ANTENNA_SIGNAL: usize = 5;
+const ANTENNA_NOISE: usize = 6;
+const ANTENNA: usize = 11;
+const MCS: usize = 19;
+// The frame ends with its frame check sequence
+const FLAG_FCS: u8 = 0x10;
+const FCS_LEN: usize = 4;
+
+const MANAGEMENT: u8 = 0;
+const CONTROL: u8 = 1;
+const DATA: u8 = 2;
+
+const TO_DS: u8 = 0x01;
+const FROM_DS: u8 = 0x02;
+const RETRY: u8 = 0x08;
+const PROTECTED: u8 = 0x40;
+const ORDER: u8 = 0x80;
+
+const ASSOCIATION_REQUEST: u8 = 0;
+const ASSOCIATION_RESPONSE: u8 = 1;
+const REASSOCIATION_REQUEST: u8 = 2;
+const REASSOCIATION_RESPONSE: u8 = 3;
+const PROBE_REQUEST: u8 = 4;
+const PROBE_RESPONSE: u8 = 5;
+const BEACON: u8 = 8;
+const DISASSOCIATION: u8 = 10;
+const AUTHENTICATION: u8 = 11;
+const DEAUTHENTICATION: u8 = 12;
+
+const CTS: u8 = 12;
+const ACK: u8 = 13;
+// Data subtypes with a QoS control field
+const QOS: u8 = 0x08;
+
+const SSID: u8 = 0;
+const DS_PARAMETER: u8 = 3;
+const RSN: u8 = 48;
+const VENDOR_SPECIFIC: u8 = 221;
+const WPA_OUI: [u8; 4] = [0x00, 0x50, 0xf2, 1];
+const RSN_OUI: [u8; 3] = [0x00, 0x0f, 0xac];
+const AKM_SAE: u8 = 8;
+const CAPABILITY_PRIVACY: u16 = 0x0010;
+
+const LLC_SNAP: [u8; 6] = [0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00];
+
+pub const EAPOL_ETHER_TYPE: u16 = 0x888e;
+const EAPOL_KEY: u8 = 3;
+// Offset of the key data length in an EAPOL-Key frame
+const KEY_DATA_LENGTH: usize = 97;
+const KEY_TYPE_PAIRWISE: u16 = 0x0008;
+const KEY_ACK: u16 = 0x0080;
+const KEY_MIC: u16 = 0x0100;
+const KEY_SECURE: u16 = 0x0200;
+
+/// Radio information a monitor-mode interface prepends to each frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Radiotap {
+    /// Length of the radiotap header in front of the 802.11 frame.
+    pub length: usize,
+    pub tsft: Option<u64>,
+    pub flags: u8,
+    /// Mbit/s.
+    pub rate: Option<f64>,
+    /// MHz.
+    pub frequency: Option<u16>,
+    /// dBm.
+    pub signal: Option<i8>,
+    pub noise: Option<i8>,
+    pub antenna: Option<u8>,
+    pub mcs: Option<u8>,
+}
+
+impl Radiotap {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < RADIOTAP_HEADER_LEN || data[0] != 0 {
+            return None;
+        }
+        let length = usize::from(u16::from_le_bytes([data[2], data[3]]));
+        let header = data.get(..length)?;
+        let present = u32::from_le_bytes(header[4..8].try_into().unwrap());
+
+        // Fields follow all presence bitmaps and are naturally aligned
+        let mut offset = RADIOTAP_HEADER_LEN;
+        let mut word = present;
+        while word & RADIOTAP_EXT != 0 {
+            word = u32::from_le_bytes(header.get(offset..offset + 4)?.try_into().ok()?);
+            offset += 4;
+        }
+
+        let mut radiotap = Radiotap {
+            length,
+            ..Default::default()
+        };
+        for (bit, &(align, size)) in RADIOTAP_FIELDS.iter().enumerate() {
+            if present & (1 << bit) == 0 {
+                continue;
+            }
+            offset = offset.next_multiple_of(align);
+            let Some(field) = header.get(offset..offset + size) else {
+                break;
+            };
+            match bit {
+                TSFT => radiotap.tsft = Some(u64::from_le_bytes(field.try_into().unwrap())),
+                FLAGS => radiotap.flags = field[0],
+                RATE => radiotap.rate = Some(f64::from(field[0]) / 2.0),
+                CHANNEL => radiotap.frequency = Some(u16::from_le_bytes([field[0], field[1]])),
+                ANTENNA_SIGNAL => radiotap.signal = Some(field[0] as i8),
+                ANTENNA_NOISE => radiotap.noise = Some(field[0] as i8),
+                ANTENNA => radiotap.antenna = Some(field[0]),
+                // The index is only valid if its known bit is set
+                MCS if field[0] & 0x02 != 0 => radiotap.mcs = Some(field[2]),
+                _ => {}
+            }
+            offset += size;
+        }
+        Some(radiotap)
+    }
+
+    /// Length of the frame check sequence at the end of the frame.
+    pub fn trailer_len(&self) -> usize {
+        if self.flags & FLAG_FCS != 0 {
+            FCS_LEN
+        } else {
+            0
+        }
+    }
+
+    pub fn channel(&self) -> Option<u8> {
+        self.frequency.and_then(channel_number)
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("radiotap.length", self.length);
+        fields.insert("radiotap.flags", self.flags);
+        if let Some(tsft) = self.tsft {
+            fields.insert("radiotap.mactime", tsft);
+        }
+        if let Some(rate) = self.rate {
+            fields.insert("radiotap.datarate", rate);
+        }
+        if let Some(frequency) = self.frequency {
+            fields.insert("radiotap.channel.freq", frequency);
+        }
+        if let Some(channel) = self.channel() {
+            fields.insert("wlan_radio.channel", channel);
+        }
+        if let Some(signal) = self.signal {
+            fields.insert("radiotap.dbm_antsignal", f64::from(signal));
+        }
+        if let Some(noise) = self.noise {
+            fields.insert("radiotap.dbm_antnoise", f64::from(noise));
+        }
+        if let Some(antenna) = self.antenna {
+            fields.insert("radiotap.antenna", antenna);
+        }
+        if let Some(mcs) = self.mcs {
+            fields.insert("radiotap.mcs.index", mcs);
+        }
+    }
+
+    /// Signal, channel and rate for the info column.
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(signal) = self.signal {
+            parts.push(format!("{} dBm", signal));
+        }
+        if let Some(channel) = self.channel() {
+            parts.push(format!("ch {}", channel));
+        }
+        match (self.rate, self.mcs) {
+            (Some(rate), _) => parts.push(format!("{} Mb/s", rate)),
+            (None, Some(mcs)) => parts.push(format!("MCS {}", mcs)),
+            _ => {}
+        }
+        parts.join(", ")
+    }
+}
+
+/// The channel number of a 2.4, 5 or 6 GHz frequency in MHz.
+pub fn channel_number(frequency: u16) -> Option<u8> {
+    let channel = match frequency {
+        2484 => 14,
+        2412..=2472 => (frequency - 2407) / 5,
+        5955..=7115 => (frequency - 5950) / 5,
+        5000..=5900 => (frequency - 5000) / 5,
+        _ => return None,
+    };
+    u8::try_from(channel).ok()
+}
+
+fn is_group(address: &[u8; 6]) -> bool {
+    address[0] & 0x01 != 0
+}
+
+fn frame_name(frame_type: u8, subtype: u8) -> &'static str {
+    match (frame_type, subtype) {
+        (MANAGEMENT, ASSOCIATION_REQUEST) => "Association Request",
+        (MANAGEMENT, ASSOCIATION_RESPONSE) => "Association Response",
+        (MANAGEMENT, REASSOCIATION_REQUEST) => "Reassociation Request",
+        (MANAGEMENT, REASSOCIATION_RESPONSE) => "Reassociation Response",
+        (MANAGEMENT, PROBE_REQUEST) => "Probe Request",
+        (MANAGEMENT, PROBE_RESPONSE) => "Probe Response",
+        (MANAGEMENT, 6) => "Timing Advertisement",
+        (MANAGEMENT, BEACON) => "Beacon frame",
+        (MANAGEMENT, 9) => "ATIM",
+        (MANAGEMENT, DISASSOCIATION) => "Disassociate",
+        (MANAGEMENT, AUTHENTICATION) => "Authentication",
+        (MANAGEMENT, DEAUTHENTICATION) => "Deauthentication",
+        (MANAGEMENT, 13) => "Action",
+        (MANAGEMENT, 14) => "Action No Ack",
+        (CONTROL, 7) => "Control Wrapper",
+        (CONTROL, 8) => "802.11 Block Ack Req",
+        (CONTROL, 9) => "802.11 Block Ack",
+        (CONTROL, 10) => "Power-Save poll",
+        (CONTROL, 11) => "Request-to-send",
+        (CONTROL, CTS) => "Clear-to-send",
+        (CONTROL, ACK) => "Acknowledgement",
+        (CONTROL, 14) => "CF-End",
+        (CONTROL, 15) => "CF-End + CF-Ack",
+        (DATA, 4) => "Null function (No data)",
+        (DATA, 8) => "QoS Data",
+        (DATA, 12) => "QoS Null function (No data)",
+        (DATA, _) => "Data",
+        _ => "Unrecognized (Reserved frame)",
+    }
+}
+
+fn reason_name(reason: u16) -> &'static str {
+    match reason {
+        1 => "Unspecified reason",
+        2 => "Previous authentication no longer valid",
+        3 => "Deauthenticated because sending STA is leaving",
+        4 => "Disassociated due to inactivity",
+        5 => "AP is unable to handle all currently associated STAs",
+        6 => "Class 2 frame received from nonauthenticated STA",
+        7 => "Class 3 frame received from nonassociated STA",
+        8 => "Disassociated because sending STA is leaving",
+        14 => "Message integrity code (MIC) failure",
+        15 => "4-Way Handshake timeout",
+        16 => "Group Key Handshake timeout",
+        23 => "IEEE 802.1X authentication failed",
+        _ => "Reserved",
+    }
+}
+
+/// An 802.11 MAC frame. Control frames carry one or two addresses,
+/// management and data frames three or four.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WlanFrame<'a> {
+    pub frame_type: u8,
+    pub subtype: u8,
+    pub flags: u8,
+    pub duration: u16,
+    pub receiver: [u8; 6],
+    pub transmitter: Option<[u8; 6]>,
+    pub address3: Option<[u8; 6]>,
+    pub address4: Option<[u8; 6]>,
+    pub sequence: Option<u16>,
+    pub body: &'a [u8],
+}
+
+impl<'a> WlanFrame<'a> {
+    pub fn parse(frame: &'a [u8]) -> Option<Self> {
+        let control = frame.get(..2)?;
+        if control[0] & 0x03 != 0 {
+            return None;
+        }
+        let frame_type = (control[0] >> 2) & 0x03;
+        let subtype = control[0] >> 4;
+        let flags = control[1];
+        let address = |i: usize| -> Option<[u8; 6]> { frame.get(i..i + 6)?.try_into().ok() };
+
+        let mut wlan = WlanFrame {
+            frame_type,
+            subtype,
+            flags,
+            duration: u16::from_le_bytes([*frame.get(2)?, *frame.get(3)?]),
+            receiver: address(4)?,
+            transmitter: None,
+            address3: None,
+            address4: None,
+            sequence: None,
+            body: &[],
+        };
+        let header_len = match frame_type {
+            CONTROL if subtype == CTS || subtype == ACK => 10,
+            CONTROL => {
+                wlan.transmitter = Some(address(10)?);
+                16
+            }
+            MANAGEMENT | DATA => {
+                wlan.transmitter = Some(address(10)?);
+                wlan.address3 = Some(address(16)?);
+                wlan.sequence = Some(u16::from_le_bytes([*frame.get(22)?, *frame.get(23)?]) >> 4);
+                let mut len = 24;
+                let qos = frame_type == DATA && subtype & QOS != 0;
+                if frame_type == DATA && flags & (TO_DS | FROM_DS) == TO_DS | FROM_DS {
+                    wlan.address4 = Some(address(24)?);
+                    len += 6;
+                }
+                if qos {
+                    len += 2;
+                }
+                // The HT control field of the order bit
+                if flags & ORDER != 0 && (qos || frame_type == MANAGEMENT) {
+                    len += 4;
+                }
+                len
+            }
+            _ => return None,
+        };
+        wlan.body = frame.get(header_len..)?;
+        Some(wlan)
+    }
+
+    pub fn name(&self) -> &'static str {
+        frame_name(self.frame_type, self.subtype)
+    }
+
+    pub fn is_protected(&self) -> bool {
+        self.flags & PROTECTED != 0
+    }
+
+    pub fn is_management(&self) -> bool {
+        self.frame_type == MANAGEMENT
+    }
+
+    pub fn is_data(&self) -> bool {
+        self.frame_type == DATA
+    }
+
+    fn distribution(&self) -> (bool, bool) {
+        (self.flags & TO_DS != 0, self.flags & FROM_DS != 0)
+    }
+
+    /// The access point's address. Frames between access points have
+    /// none.
+    pub fn bssid(&self) -> Option<[u8; 6]> {
+        match (self.frame_type, self.distribution()) {
+            (MANAGEMENT, _) | (DATA, (false, false)) => self.address3,
+            (DATA, (true, false)) => Some(self.receiver),
+            (DATA, (false, true)) => self.transmitter,
+            _ => None,
+        }
+    }
+
+    pub fn source(&self) -> Option<[u8; 6]> {
+        match (self.frame_type, self.distribution()) {
+            (DATA, (false, true)) => self.address3,
+            (DATA, (true, true)) => self.address4,
+            _ => self.transmitter,
+        }
+    }
+
+    pub fn destination(&self) -> [u8; 6] {
+        match (self.frame_type, self.distribution()) {
+            (DATA, (true, _)) => self.address3.unwrap_or(self.receiver),
+            _ => self.receiver,
+        }
+    }
+
+    /// The client station a frame to or from an access point belongs to.
+    pub fn station(&self) -> Option<[u8; 6]> {
+        let bssid = self.bssid()?;
+        let station = if self.transmitter == Some(bssid) {
+            self.receiver
+        } else {
+            self.transmitter?
+        };
+        (station != bssid && !is_group(&station)).then_some(station)
+    }
+
+    /// The EtherType and payload of an unprotected data frame with an
+    /// LLC/SNAP header.
+    pub fn llc_payload(&self) -> Option<(u16, &'a [u8])> {
+        if !self.is_data() || self.is_protected() || self.body.get(..6)? != LLC_SNAP {
+            return None;
+        }
+        let ether_type = u16::from_be_bytes([*self.body.get(6)?, *self.body.get(7)?]);
+        Some((ether_type, &self.body[8..]))
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("wlan.fc.type", self.frame_type);
+        fields.insert("wlan.fc.subtype", self.subtype);
+        fields.insert("wlan.fc.type_subtype", self.frame_type << 4 | self.subtype);
+        fields.insert("wlan.fc.tods", self.flags & TO_DS != 0);
+        fields.insert("wlan.fc.fromds", self.flags & FROM_DS != 0);
+        fields.insert("wlan.fc.retry", self.flags & RETRY != 0);
+        fields.insert("wlan.fc.protected", self.is_protected());
+        fields.insert("wlan.duration", self.duration);
+        fields.insert("wlan.ra", self.receiver);
+        fields.insert("wlan.da", self.destination());
+        if let Some(transmitter) = self.transmitter {
+            fields.insert("wlan.ta", transmitter);
+        }
+        if let Some(source) = self.source() {
+            fields.insert("wlan.sa", source);
+        }
+        if let Some(bssid) = self.bssid() {
+            fields.insert("wlan.bssid", bssid);
+        }
+        if let Some(station) = self.station() {
+            fields.insert("wlan.staa", station);
+        }
+        let addresses = [
+            Some(self.receiver),
+            self.transmitter,
+            self.address3,
+            self.address4,
+        ];
+        for address in addresses.into_iter().flatten() {
+            fields.insert("wlan.addr", address);
+        }
+        if let Some(sequence) = self.sequence {
+            fields.insert("wlan.seq", sequence);
+        }
+    }
+}
+
+/// The fixed parameters and information elements of a management frame
+/// that matter for telling networks apart.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Management {
+    pub ssid: Option<String>,
+    pub channel: Option<u8>,
+    /// Open, WEP, WPA, WPA2 or WPA3 for beacons and probe responses.
+    pub security: Option<&'static str>,
+    pub beacon_interval: Option<u16>,
+    pub reason: Option<u16>,
+    pub status: Option<u16>,
+    pub auth_sequence: Option<u16>,
+}
+
+impl Management {
+    pub fn parse(subtype: u8, body: &[u8]) -> Self {
+        let u16_at = |i: usize| {
+            body.get(i..i + 2)
+                .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        };
+        let mut management = Management::default();
+        let elements_offset = match subtype {
+            BEACON | PROBE_RESPONSE => {
+                management.beacon_interval = u16_at(8);
+                12
+            }
+            ASSOCIATION_REQUEST => 4,
+            REASSOCIATION_REQUEST => 10,
+            ASSOCIATION_RESPONSE | REASSOCIATION_RESPONSE => {
+                management.status = u16_at(2);
+                6
+            }
+            AUTHENTICATION => {
+                management.auth_sequence = u16_at(2);
+                management.status = u16_at(4);
+                6
+            }
+            DISASSOCIATION | DEAUTHENTICATION => {
+                management.reason = u16_at(0);
+                return management;
+            }
+            PROBE_REQUEST => 0,
+            _ => return management,
+        };
+
+        let mut rsn = None;
+        let mut wpa = false;
+        let mut elements = body.get(elements_offset..).unwrap_or_default();
+        while let [id, len, rest @ ..] = elements {
+            let Some(value) = rest.get(..usize::from(*len)) else {
+                break;
+            };
+            match *id {
+                SSID => management.ssid = Some(String::from_utf8_lossy(value).into_owned()),
+                DS_PARAMETER => management.channel = value.first().copied(),
+                RSN => rsn = Some(value),
+                VENDOR_SPECIFIC if value.starts_with(&WPA_OUI) => wpa = true,
+                _ => {}
+            }
+            elements = &rest[value.len()..];
+        }
+
+        if let BEACON | PROBE_RESPONSE = subtype {
+            let privacy = u16_at(10).is_some_and(|capability| capability & CAPABILITY_PRIVACY != 0);
+            management.security = Some(match rsn {
+                Some(rsn) if uses_sae(rsn) => "WPA3",
+                Some(_) => "WPA2",
+                None if wpa => "WPA",
+                None if privacy => "WEP",
+                None => "Open",
+            });
+        }
+        management
+    }
+
+    pub fn info(&self) -> String {
+        let mut parts = Vec::new();
+        match self.ssid.as_deref() {
+            Some("") => parts.push("wildcard SSID".to_string()),
+            Some(ssid) => parts.push(format!("SSID \"{}\"", ssid)),
+            None => {}
+        }
+        if let Some(channel) = self.channel {
+            parts.push(format!("channel {}", channel));
+        }
+        if let Some(security) = self.security {
+            parts.push(security.to_string());
+        }
+        if let Some(sequence) = self.auth_sequence {
+            parts.push(format!("seq {}", sequence));
+        }
+        if let Some(status) = self.status.filter(|&status| status != 0) {
+            parts.push(format!("status {}", status));
+        }
+        if let Some(reason) = self.reason {
+            parts.push(format!("reason {} ({})", reason, reason_name(reason)));
+        }
+        parts.join(", ")
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        if let Some(ssid) = &self.ssid {
+            fields.insert("wlan.ssid", ssid.as_str());
+        }
+        if let Some(channel) = self.channel {
+            fields.insert("wlan.ds.current_channel", channel);
+        }
+        if let Some(interval) = self.beacon_interval {
+            fields.insert("wlan.fixed.beacon", interval);
+        }
+        if let Some(reason) = self.reason {
+            fields.insert("wlan.fixed.reason_code", reason);
+        }
+        if let Some(status) = self.status {
+            fields.insert("wlan.fixed.status_code", status);
+        }
+        if let Some(sequence) = self.auth_sequence {
+            fields.insert("wlan.fixed.auth_seq", sequence);
+        }
+    }
+}
+
+/// Whether an RSN element offers SAE authentication.
+fn uses_sae(rsn: &[u8]) -> bool {
+    // Version and group cipher, then the pairwise cipher suites
+    let Some(count) = rsn.get(6..8) else {
+        return false;
+    };
+    let akm_offset = 8 + 4 * usize::from(u16::from_le_bytes([count[0], count[1]]));
+    let Some(count) = rsn.get(akm_offset..akm_offset + 2) else {
+        return false;
+    };
+    let count = usize::from(u16::from_le_bytes([count[0], count[1]]));
+    rsn.get(akm_offset + 2..)
+        .unwrap_or_default()
+        .chunks_exact(4)
+        .take(count)
+        .any(|suite| suite[..3] == RSN_OUI && suite[3] == AKM_SAE)
+}
+
+pub fn eapol_type_name(packet_type: u8) -> &'static str {
+    match packet_type {
+        0 => "EAP Packet",
+        1 => "Start",
+        2 => "Logoff",
+        EAPOL_KEY => "Key",
+        4 => "Encapsulated ASF Alert",
+        _ => "Unknown",
+    }
+}
+
+/// An EAPOL-Key frame of the 4-way or group key handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EapolKey {
+    pub key_info: u16,
+    pub replay_counter: u64,
+    /// 1 to 4 in the 4-way handshake, 1 or 2 in the group key handshake.
+    pub message: u8,
+}
+
+impl EapolKey {
+    pub fn parse(eapol: &[u8]) -> Option<Self> {
+        if *eapol.get(1)? != EAPOL_KEY {
+            return None;
+        }
+        let key_info = u16::from_be_bytes([*eapol.get(5)?, *eapol.get(6)?]);
+        let replay_counter = u64::from_be_bytes(eapol.get(9..17)?.try_into().ok()?);
+        let key_data_len = u16::from_be_bytes(
+            eapol
+                .get(KEY_DATA_LENGTH..KEY_DATA_LENGTH + 2)?
+                .try_into()
+                .ok()?,
+        );
+        let flag = |bit: u16| key_info & bit != 0;
+        let message = match (flag(KEY_TYPE_PAIRWISE), flag(KEY_ACK), flag(KEY_MIC)) {
+            (true, true, false) => 1,
+            (true, true, true) => 3,
+            // Message 4 of WPA1 doesn't set the secure bit but has no key data
+            (true, false, _) if !flag(KEY_SECURE) && key_data_len > 0 => 2,
+            (true, false, _) => 4,
+            (false, true, _) => 1,
+            (false, false, _) => 2,
+        };
+        Some(EapolKey {
+            key_info,
+            replay_counter,
+            message,
+        })
+    }
+
+    pub fn is_pairwise(&self) -> bool {
+        self.key_info & KEY_TYPE_PAIRWISE != 0
+    }
+
+    pub fn info(&self) -> String {
+        if self.is_pairwise() {
+            format!("Key (Message {} of 4)", self.message)
+        } else {
+            format!("Key (Group Message {} of 2)", self.message)
+        }
+    }
+
+    pub fn insert_fields(&self, fields: &mut Fields) {
+        fields.insert("wlan_rsna_eapol.keydes.msgnr", self.message);
+        fields.insert(
+            "wlan_rsna_eapol.keydes.key_info.key_type",
+            self.is_pairwise(),
+        );
+        fields.insert("wlan_rsna_eapol.keydes.key_info", self.key_info);
+        fields.insert("eapol.keydes.replay_counter", self.replay_counter);
+    }
+}
+
+/// A wireless network and the stations seen talking to it.
+#[derive(Debug, Clone)]
+pub struct Bss {
+    pub bssid: [u8; 6],
+    pub ssid: String,
+    pub channel: Option<u8>,
+    /// Signal of the last frame from the access point, in dBm.
+    pub signal: Option<f64>,
+    pub frames: u64,
+    pub stations: Vec<[u8; 6]>,
+    /// Messages of the latest 4-way handshake of each station, as bits.
+    handshakes: HashMap<[u8; 6], u8>,
+}
+
+impl Bss {
+    /// Stations whose latest 4-way handshake was captured in full.
+    pub fn complete_handshakes(&self) -> usize {
+        self.handshakes
+            .values()
+            .filter(|&&seen| seen == 0x0f)
+            .count()
+    }
+
+    /// Stations seen exchanging handshake messages.
+    pub fn handshakes(&self) -> usize {
+        self.handshakes.len()
+    }
+}
+
+/// Networks in the order they were first seen, rebuilt from the fields of
+/// 802.11 frames.
+#[derive(Debug, Default)]
+pub struct BssTable {
+    networks: Vec<Bss>,
+    index: HashMap<[u8; 6], usize>,
+}
+
+impl BssTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, fields: &Fields) {
+        let mac = |name| match fields.get(name) {
+            Some(FieldValue::Mac(mac)) => Some(*mac),
+            _ => None,
+        };
+        let uint = |name| match fields.get(name) {
+            Some(FieldValue::UInt(value)) => u8::try_from(*value).ok(),
+            _ => None,
+        };
+        let Some(bssid) = mac("wlan.bssid").filter(|bssid| !is_group(bssid)) else {
+            return;
+        };
+        let index = *self.index.entry(bssid).or_insert_with(|| {
+            self.networks.push(Bss {
+                bssid,
+                ssid: String::new(),
+                channel: None,
+                signal: None,
+                frames: 0,
+                stations: Vec::new(),
+                handshakes: HashMap::new(),
+            });
+            self.networks.len() - 1
+        });
+        let bss = &mut self.networks[index];
+        bss.frames += 1;
+
+        if let Some(FieldValue::Str(ssid)) = fields.get("wlan.ssid") {
+            if !ssid.is_empty() {
+                bss.ssid = ssid.clone();
+            }
+        }
+        // The channel announced by the access point beats the one tuned to
+        if let Some(channel) = uint("wlan.ds.current_channel") {
+            bss.channel = Some(channel);
+        } else if bss.channel.is_none() {
+            bss.channel = uint("wlan_radio.channel");
+        }
+        if mac("wlan.ta") == Some(bssid) {
+            if let Some(FieldValue::Float(signal)) = fields.get("radiotap.dbm_antsignal") {
+                bss.signal = Some(*signal);
+            }
+        }
+
+        let Some(station) = mac("wlan.staa") else {
+            return;
+        };
+        if !bss.stations.contains(&station) {
+            bss.stations.push(station);
+        }
+        let pairwise = matches!(
+            fields.get("wlan_rsna_eapol.keydes.key_info.key_type"),
+            Some(FieldValue::Bool(true))
+        );
+        if let (true, Some(message @ 1..=4)) = (pairwise, uint("wlan_rsna_eapol.keydes.msgnr")) {
+            let seen = bss.handshakes.entry(station).or_default();
+            // A first message starts a new handshake
+            if message == 1 {
+                *seen = 0;
+            }
+            *seen |= 1 << (message - 1);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Bss> {
+        self.networks.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.networks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const AP: [u8; 6] = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+    pub(crate) const STATION: [u8; 6] = [0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb];
+
+    /// A radiotap header with flags, rate, channel and signal.
+    pub(crate) fn radiotap(frequency: u16, signal: i8) -> Vec<u8> {
+        let mut data = vec![0, 0, 15, 0, 0x2e, 0, 0, 0, FLAG_FCS, 12];
+        data.extend_from_slice(&frequency.to_le_bytes());
+        data.extend_from_slice(&0x00a0u16.to_le_bytes());
+        data.push(signal as u8);
+        data
+    }
+
+    pub(crate) fn beacon(ssid: &str) -> Vec<u8> {
+        let broadcast = [0xff; 6];
+        let mut frame = vec![0x80, 0x00, 0, 0];
+        frame.extend_from_slice(&broadcast);
+        frame.extend_from_slice(&AP);
+        frame.extend_from_slice(&AP);
+        frame.extend_from_slice(&[0x10, 0x00]);
+        frame.extend_from_slice(&[0; 8]);
+        frame.extend_from_slice(&100u16.to_le_bytes());
+        frame.extend_from_slice(&0x0411u16.to_le_bytes());
+        frame.extend_from_slice(&[SSID, ssid.len() as u8]);
+        frame.extend_from_slice(ssid.as_bytes());
+        frame.extend_from_slice(&[DS_PARAMETER, 1, 6]);
+        frame.extend_from_slice(&[RSN, 20, 1, 0, 0x00, 0x0f, 0xac, 4, 1, 0]);
+        frame.extend_from_slice(&[0x00, 0x0f, 0xac, 4, 1, 0, 0x00, 0x0f, 0xac, 2, 0, 0]);
+        frame
+    }
+
+    /// An EAPOL-Key frame of the 4-way handshake between AP and STATION.
+    pub(crate) fn eapol_key(message: u8) -> Vec<u8> {
+        let key_info: u16 = match message {
+            1 => 0x008a,
+            2 => 0x010a,
+            3 => 0x13ca,
+            _ => 0x030a,
+        };
+        let from_ap = message % 2 == 1;
+        let mut frame = vec![0x08, if from_ap { FROM_DS } else { TO_DS }, 0, 0];
+        let (receiver, transmitter) = if from_ap {
+            (STATION, AP)
+        } else {
+            (AP, STATION)
+        };
+        frame.extend_from_slice(&receiver);
+        frame.extend_from_slice(&transmitter);
+        frame.extend_from_slice(&if from_ap { AP } else { STATION });
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&LLC_SNAP);
+        frame.extend_from_slice(&EAPOL_ETHER_TYPE.to_be_bytes());
+        let mut eapol = vec![2, EAPOL_KEY, 0, 95, 2];
+        eapol.extend_from_slice(&key_info.to_be_bytes());
+        eapol.extend_from_slice(&[0, 16]);
+        eapol.extend_from_slice(&u64::from(message).to_be_bytes());
+        eapol.resize(KEY_DATA_LENGTH, 0);
+        let key_data: &[u8] = if message == 2 { &[0xdd; 22] } else { &[] };
+        eapol.extend_from_slice(&(key_data.len() as u16).to_be_bytes());
+        eapol.extend_from_slice(key_data);
+        frame.extend(eapol);
+        frame
+    }
+
+    #[test]
+    fn test_radiotap() {
+        let radiotap = Radiotap::parse(&radiotap(2437, -42)).unwrap();
+        assert_eq!(radiotap.length, 15);
+        assert_eq!(radiotap.rate, Some(6.0));
+        assert_eq!(radiotap.channel(), Some(6));
+        assert_eq!(radiotap.signal, Some(-42));
+        assert_eq!(radiotap.trailer_len(), FCS_LEN);
+        assert_eq!(radiotap.summary(), "-42 dBm, ch 6, 6 Mb/s");
+        assert_eq!(channel_number(5180), Some(36));
+        assert_eq!(channel_number(5955), Some(1));
+    }
+
+    #[test]
+    fn test_beacon() {
+        let data = beacon("HomeNet");
+        let frame = WlanFrame::parse(&data).unwrap();
+        assert_eq!(frame.name(), "Beacon frame");
+        assert_eq!(frame.bssid(), Some(AP));
+        assert_eq!(frame.station(), None);
+        let management = Management::parse(frame.subtype, frame.body);
+        assert_eq!(management.info(), "SSID \"HomeNet\", channel 6, WPA2");
+        assert_eq!(management.beacon_interval, Some(100));
+    }
+
+    #[test]
+    fn test_four_way_handshake() {
+        let mut table = BssTable::new();
+        for message in 1..=4 {
+            let data = eapol_key(message);
+            let frame = WlanFrame::parse(&data).unwrap();
+            assert_eq!(frame.bssid(), Some(AP));
+            assert_eq!(frame.station(), Some(STATION));
+            let (ether_type, eapol) = frame.llc_payload().unwrap();
+            assert_eq!(ether_type, EAPOL_ETHER_TYPE);
+            let key = EapolKey::parse(eapol).unwrap();
+            assert_eq!(key.info(), format!("Key (Message {} of 4)", message));
+
+            let mut fields = Fields::new();
+            frame.insert_fields(&mut fields);
+            key.insert_fields(&mut fields);
+            table.update(&fields);
+        }
+        let bss = table.iter().next().unwrap();
+        assert_eq!(bss.stations, vec![STATION]);
+        assert_eq!(bss.complete_handshakes(), 1);
+    }
+
+    #[test]
+    fn test_deauthentication() {
+        let mut data = vec![0xc0, 0x00, 0, 0];
+        data.extend_from_slice(&STATION);
+        data.extend_from_slice(&AP);
+        data.extend_from_slice(&AP);
+        data.extend_from_slice(&[0, 0, 7, 0]);
+        let frame = WlanFrame::parse(&data).unwrap();
+        assert_eq!(frame.station(), Some(STATION));
+        assert_eq!(
+            Management::parse(frame.subtype, frame.body).info(),
+            "reason 7 (Class 3 frame received from nonassociated STA)"
+        );
+    }
+}